@@ -0,0 +1,23 @@
+//! Shared setup for the criterion benches.
+
+/// Payload sizes in bytes, overridable with `BENCH_PAYLOAD_SIZES=64,4096,...`.
+pub fn payload_sizes() -> Vec<usize> {
+    std::env::var("BENCH_PAYLOAD_SIZES")
+        .ok()
+        .map(|v| v.split(',').filter_map(|s| s.trim().parse().ok()).collect::<Vec<usize>>())
+        .filter(|sizes| !sizes.is_empty())
+        .unwrap_or_else(|| vec![64, 1024, 16 * 1024])
+}
+
+/// Deterministic, incompressible-looking payload.
+pub fn payload(size: usize) -> Vec<u8> {
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    (0..size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
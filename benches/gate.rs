@@ -0,0 +1,72 @@
+//! Gate and policy VM paths.
+//!
+//! `cargo bench --bench gate`; set `BENCH_PAYLOAD_SIZES` to change the payload sizes.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rfsn_core::ledger::entry::{self, EntryKind};
+use rfsn_core::ledger::merkle::leaf_hash;
+use rfsn_core::vm::bytecode::{encode, ALLOW, INSTR_LEN, POP, PUSH};
+use rfsn_core::vm::interp::decide;
+use rfsn_core::wcet::watchdog::{ExecutionBudget, Watchdog};
+
+mod common;
+use common::{payload, payload_sizes};
+
+/// Frames a proposal the way it reaches the ledger: typed-entry header plus leaf hash.
+fn gate_framing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gate_framing");
+    for size in payload_sizes() {
+        let proposal = payload(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &proposal, |b, proposal| {
+            b.iter(|| {
+                let framed = entry::encode(EntryKind::Repair, black_box(proposal));
+                leaf_hash(&framed)
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Straight-line policy of about `size` bytes: push/pop pairs ending in `ALLOW`, so
+/// every instruction executes and the cost scales with the size.
+fn straight_line_policy(size: usize) -> Vec<u8> {
+    let instrs = (size / INSTR_LEN).max(1);
+    (0..instrs)
+        .flat_map(|i| match i {
+            _ if i + 1 == instrs => encode(ALLOW, 0),
+            _ if i % 2 == 0 => encode(PUSH, i as u32),
+            _ => encode(POP, 0),
+        })
+        .collect()
+}
+
+fn policy_evaluation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("policy_evaluation");
+    for size in payload_sizes() {
+        let policy = straight_line_policy(size);
+        let budget = ExecutionBudget::steps((policy.len() / INSTR_LEN) as u64);
+        // Checked once, untimed: a policy that denied early or skipped instructions
+        // would make the numbers measure something else. Runs under `cargo test --benches`.
+        let mut check = Watchdog::new("bench", budget);
+        assert!(decide(&policy, &[], &mut check).expect("within budget").is_allow());
+        assert_eq!(check.remaining_steps(), 0, "every instruction executes");
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("steps", size), &policy, |b, policy| {
+            b.iter(|| {
+                let mut watchdog = Watchdog::new("bench", budget);
+                decide(black_box(policy), &[], &mut watchdog).expect("within budget")
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("steps+cycles", size), &policy, |b, policy| {
+            b.iter(|| {
+                let mut watchdog = Watchdog::new("bench", budget.with_cycles(u64::MAX));
+                decide(black_box(policy), &[], &mut watchdog).expect("within budget")
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, gate_framing, policy_evaluation);
+criterion_main!(benches);
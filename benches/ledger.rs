@@ -0,0 +1,82 @@
+//! Ledger append and Merkle checkpointing.
+//!
+//! `cargo bench --bench ledger`; set `BENCH_PAYLOAD_SIZES` to change the entry sizes.
+
+use std::path::PathBuf;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rfsn_core::ledger::merkle::{leaf_hash, Checkpoint, MerkleFrontier};
+use rfsn_core::ledger::storage::{DeterministicStore, StoreConfig, SyncPolicy};
+
+mod common;
+use common::{payload, payload_sizes};
+
+/// Fresh directory under the system temp dir; removed when dropped.
+pub struct ScratchDir(pub PathBuf);
+
+impl ScratchDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("rfsn-bench-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("creatable bench directory");
+        Self(dir)
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Tree sizes for the checkpoint benches.
+const TREE_SIZES: [u64; 3] = [1 << 10, 1 << 14, 1 << 18];
+
+fn ledger_append(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ledger_append");
+    for (label, sync_policy) in [("on-commit", SyncPolicy::OnCommit), ("every-entry", SyncPolicy::EveryEntry)] {
+        for size in payload_sizes() {
+            let scratch = ScratchDir::new(&format!("append-{}-{}", label, size));
+            // A checkpoint interval larger than any run keeps checkpoint writes out of this group.
+            let config = StoreConfig::new(&scratch.0).checkpoint_interval(u64::MAX).sync_policy(sync_policy);
+            let mut store = DeterministicStore::open(config).expect("bench store");
+            let entry = payload(size);
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(BenchmarkId::new(label, size), &entry, |b, entry| {
+                b.iter(|| store.append_entry(black_box(entry)).expect("append"))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn merkle_checkpoint(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merkle_checkpoint");
+    for size in TREE_SIZES {
+        let mut tree = MerkleFrontier::new();
+        for i in 0..size {
+            tree.push(leaf_hash(&i.to_le_bytes()));
+        }
+        group.bench_with_input(BenchmarkId::new("push", size), &tree, |b, tree| {
+            b.iter_batched(|| tree.clone(), |mut tree| tree.push(leaf_hash(b"next")), BatchSize::SmallInput)
+        });
+        group.bench_with_input(BenchmarkId::new("encode", size), &tree, |b, tree| {
+            b.iter(|| Checkpoint::of(black_box(tree), size).encode())
+        });
+    }
+
+    // End to end: every append writes and fsyncs `merkle.chk`.
+    for size in payload_sizes() {
+        let scratch = ScratchDir::new(&format!("checkpoint-{}", size));
+        let mut store = DeterministicStore::open(StoreConfig::new(&scratch.0).checkpoint_interval(1)).expect("bench store");
+        let entry = payload(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("append+checkpoint", size), &entry, |b, entry| {
+            b.iter(|| store.append_entry(black_box(entry)).expect("append"))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, ledger_append, merkle_checkpoint);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+//! `rfsn-audit`: verifies the receipt chain of a ledger and prints a timeline report.
+//!
+//! ```text
+//! rfsn-audit <ledger-dir> <witnesses.json> [--nested]
+//! ```
+//!
+//! The ledger is opened read-only. `witnesses.json` is a `WitnessSet`; only the pinned
+//! keys are needed for JSON witnesses, while RFC 3161 and Ethereum witnesses need
+//! their verification key and RPC endpoint respectively. Exits non-zero if any
+//! problem is found.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use rfsn_core::ledger::audit::audit_ledger;
+use rfsn_core::ledger::notarize::WitnessSet;
+use rfsn_core::ledger::notary_http::HttpClientConfig;
+use rfsn_core::ledger::storage::{DeterministicStore, DirLayout, OpenMode, StoreConfig};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let nested = args.iter().any(|a| a == "--nested");
+    let positional: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    let [ledger_dir, witnesses] = positional.as_slice() else {
+        eprintln!("usage: rfsn-audit <ledger-dir> <witnesses.json> [--nested]");
+        return ExitCode::from(2);
+    };
+
+    let mut config = StoreConfig::new(&PathBuf::from(ledger_dir));
+    if nested {
+        config = config.layout(DirLayout::Nested);
+    }
+    let result = (|| -> Result<bool, Box<dyn std::error::Error>> {
+        let store = DeterministicStore::open_with(config, OpenMode::ReadOnly)?;
+        let client = WitnessSet::load(&PathBuf::from(witnesses))?.into_client(&HttpClientConfig::default())?;
+        let report = audit_ledger(&store, &client)?;
+        println!("{}", report);
+        Ok(report.is_clean())
+    })();
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("❌ Audit failed: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
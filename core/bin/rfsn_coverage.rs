@@ -0,0 +1,64 @@
+//! `rfsn-coverage`: reports the rules of the active policy that have never fired.
+//!
+//! ```text
+//! rfsn-coverage <ledger-dir> [--all] [--nested]
+//! ```
+//!
+//! The ledger is opened read-only. The decisions recorded under the policy version in
+//! effect, or with `--all` under each version in the ledger, are replayed over the
+//! version's bytecode (see `vm::coverage`), and the rules never fired and branch
+//! outcomes never taken are printed. Exits non-zero if a deny or escalate rule has
+//! never fired.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use rfsn_core::gate::policy::PolicyVersion;
+use rfsn_core::ledger::storage::{DeterministicStore, DirLayout, OpenMode, StoreConfig};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let nested = args.iter().any(|a| a == "--nested");
+    let all = args.iter().any(|a| a == "--all");
+    let positional: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    let [ledger_dir] = positional.as_slice() else {
+        eprintln!("usage: rfsn-coverage <ledger-dir> [--all] [--nested]");
+        return ExitCode::from(2);
+    };
+
+    let mut config = StoreConfig::new(&PathBuf::from(ledger_dir));
+    if nested {
+        config = config.layout(DirLayout::Nested);
+    }
+    let result = (|| -> Result<bool, Box<dyn std::error::Error>> {
+        let store = DeterministicStore::open_with(config, OpenMode::ReadOnly)?;
+        let mut versions = PolicyVersion::history(&store)?;
+        if !all {
+            versions = versions.split_off(versions.len().saturating_sub(1));
+        }
+        if versions.is_empty() {
+            return Err("no policy version is recorded".into());
+        }
+        let mut covered = true;
+        for version in versions {
+            println!("policy {} ({}), effective at tick {}", version.name, version.policy_hash, version.effective_tick);
+            match version.coverage(&store)? {
+                Some(coverage) => {
+                    print!("{}", coverage);
+                    covered &= coverage.untested_refusals().next().is_none();
+                }
+                None => println!("  runtime {} does not trace; no coverage", version.runtime.as_deref().unwrap_or("?")),
+            }
+        }
+        Ok(covered)
+    })();
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("❌ Coverage failed: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Structured capability identifiers.
+//!
+//! A capability names an action on a resource, written `resource:action`, where the
+//! resource is a `/`-separated path: `sys:read`, `actuator/arm/joint2:move`. A grant
+//! may use `*` as the last resource segment to cover a whole subtree, and `*` as the
+//! action to cover every action; a required capability is always concrete.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub const WILDCARD: &str = "*";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CapabilityId {
+    pub resource: Vec<String>,
+    pub action: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CapabilityError {
+    /// Not of the form `resource:action` with non-empty parts.
+    Malformed(String),
+    /// `*` anywhere but the last resource segment or the action.
+    MisplacedWildcard(String),
+}
+
+impl fmt::Display for CapabilityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CapabilityError::Malformed(s) => write!(f, "malformed capability {:?}; expected resource:action", s),
+            CapabilityError::MisplacedWildcard(s) => write!(f, "capability {:?} has a wildcard before the last resource segment", s),
+        }
+    }
+}
+
+impl std::error::Error for CapabilityError {}
+
+impl CapabilityId {
+    pub fn new(resource: &str, action: &str) -> Result<Self, CapabilityError> {
+        format!("{}:{}", resource, action).parse()
+    }
+
+    pub fn is_concrete(&self) -> bool {
+        self.action != WILDCARD && self.resource.last().is_none_or(|s| s != WILDCARD)
+    }
+
+    /// Whether a grant of `self` authorizes everything `other` does. Wildcards in
+    /// `other` are only covered by the same or a broader wildcard in `self`, so this is
+    /// also the subset test for attenuating a grant.
+    pub fn covers(&self, other: &CapabilityId) -> bool {
+        let action = self.action == WILDCARD || self.action == other.action;
+        let resource = match self.resource.split_last() {
+            Some((last, prefix)) if last == WILDCARD => {
+                other.resource.len() > prefix.len() && other.resource.starts_with(prefix)
+            }
+            _ => self.resource == other.resource,
+        };
+        action && resource
+    }
+}
+
+impl FromStr for CapabilityId {
+    type Err = CapabilityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || CapabilityError::Malformed(s.to_string());
+        let (resource, action) = s.rsplit_once(':').ok_or_else(malformed)?;
+        let resource: Vec<String> = resource.split('/').map(str::to_string).collect();
+        if action.is_empty() || action.contains('/') || resource.iter().any(String::is_empty) {
+            return Err(malformed());
+        }
+        if resource[..resource.len() - 1].iter().any(|segment| segment == WILDCARD) {
+            return Err(CapabilityError::MisplacedWildcard(s.to_string()));
+        }
+        Ok(Self { resource, action: action.to_string() })
+    }
+}
+
+impl fmt::Display for CapabilityId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.resource.join("/"), self.action)
+    }
+}
+
+// Serialized in the `resource:action` form, so ledger entries stay readable.
+impl Serialize for CapabilityId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for CapabilityId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cap(s: &str) -> CapabilityId {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_prints() {
+        assert_eq!(cap("actuator/arm/joint2:move").to_string(), "actuator/arm/joint2:move");
+        assert_eq!(cap("sys:read").resource, vec!["sys"]);
+        assert!(matches!("sys".parse::<CapabilityId>(), Err(CapabilityError::Malformed(_))));
+        assert!(matches!("a//b:read".parse::<CapabilityId>(), Err(CapabilityError::Malformed(_))));
+        assert!(matches!("*/b:read".parse::<CapabilityId>(), Err(CapabilityError::MisplacedWildcard(_))));
+    }
+
+    #[test]
+    fn wildcards_cover_subtrees_and_actions() {
+        assert!(cap("actuator/*:move").covers(&cap("actuator/arm/joint2:move")));
+        assert!(!cap("actuator/*:move").covers(&cap("actuator:move")));
+        assert!(!cap("actuator/*:move").covers(&cap("actuator/arm:stop")));
+        assert!(cap("sys:*").covers(&cap("sys:read")));
+        assert!(!cap("sys:read").covers(&cap("sys:*")));
+        assert!(cap("actuator/*:*").covers(&cap("actuator/arm/*:move")));
+    }
+}
@@ -0,0 +1,528 @@
+//! Capability grants and the checks made against them.
+//!
+//! A grant binds a capability to a principal for a window of ticks (on the node's
+//! shared `TickSource`). The Gate asks the `CapabilityStore` whether the principal
+//! behind a proposal holds the capability it requires. Every grant and every check
+//! is appended to the ledger as an `EntryKind::Capability` entry before it takes
+//! effect or is answered, and `CapabilityStore::from_ledger` rebuilds the grants from
+//! those entries, so the authority behind any decision can be audited afterwards.
+//!
+//! A holder can delegate an attenuated grant: a strictly narrower capability, to
+//! another principal, expiring no later than its own. Revocations form a list that
+//! every check consults; revoking a grant also revokes everything delegated from
+//! it, and revocations are ledger entries too, so one cannot be quietly undone.
+//!
+//! Emergency grants are meant to be narrow in time: `valid_for` bounds a grant to a
+//! window of ticks, and a `single_use` grant is spent by the first successful
+//! actuation made under it (`record_actuation`). Every check records the bounds of
+//! the grant that answered it, so the decision entry shows how long the authority
+//! behind it was meant to last.
+//!
+//! A grant with a `Quota` allows at most `max` actuations in any `window` ticks. The
+//! successful actuations are the `Consumed` entries `record_actuation` appends, so
+//! the count is rebuilt from the ledger like everything else, and a quota limits the
+//! actuations under every grant delegated from it too. Quotas turn a blanket grant
+//! into a bounded allowance, e.g. at most 5 diagnostic runs an hour.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use super::id::CapabilityId;
+use crate::ledger::entry::{self, EntryKind};
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grant {
+    pub grant_id: String,
+    pub principal: String,
+    pub capability: CapabilityId,
+    /// Who issued the grant, e.g. an operator identity.
+    pub issuer: String,
+    /// First tick at which the grant is valid.
+    pub issued_at: u64,
+    /// Ticks at or after this are outside the grant.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Grant this one was delegated from; `None` for grants issued directly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegated_from: Option<String>,
+    /// Spent by the first successful actuation under it.
+    #[serde(default)]
+    pub single_use: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<Quota>,
+}
+
+/// At most `max` actuations in any `window` ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quota {
+    pub max: u32,
+    pub window: u64,
+}
+
+impl Quota {
+    /// Actuations left at `tick`, given the ticks of past ones.
+    fn remaining(&self, used: &[u64], tick: u64) -> u32 {
+        let in_window = used.iter().filter(|&&at| at <= tick && at.saturating_add(self.window) > tick).count();
+        self.max.saturating_sub(u32::try_from(in_window).unwrap_or(u32::MAX))
+    }
+}
+
+impl Grant {
+    pub fn new(grant_id: &str, principal: &str, capability: CapabilityId, issuer: &str, issued_at: u64) -> Self {
+        Self {
+            grant_id: grant_id.to_string(),
+            principal: principal.to_string(),
+            capability,
+            issuer: issuer.to_string(),
+            issued_at,
+            expires_at: None,
+            delegated_from: None,
+            single_use: false,
+            quota: None,
+        }
+    }
+
+    pub fn expires_at(mut self, tick: u64) -> Self {
+        self.expires_at = Some(tick);
+        self
+    }
+
+    /// Valid for `ticks` ticks from issuance.
+    pub fn valid_for(self, ticks: u64) -> Self {
+        let expiry = self.issued_at.saturating_add(ticks);
+        self.expires_at(expiry)
+    }
+
+    pub fn single_use(mut self) -> Self {
+        self.single_use = true;
+        self
+    }
+
+    /// At most `max` actuations in any `window` ticks.
+    pub fn quota(mut self, max: u32, window: u64) -> Self {
+        self.quota = Some(Quota { max, window });
+        self
+    }
+
+    pub fn valid_at(&self, tick: u64) -> bool {
+        tick >= self.issued_at && self.expires_at.is_none_or(|expiry| tick < expiry)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "kebab-case")]
+pub enum CheckOutcome {
+    Granted { grant_id: String },
+    /// The principal holds no grant covering the capability.
+    NoGrant,
+    /// Covering grants exist, but none is valid at the check's tick.
+    Expired { grant_id: String },
+    /// The covering grant, or one it was delegated from, is revoked.
+    Revoked { grant_id: String },
+    /// The covering grant, or one it was delegated from, was single-use and is spent.
+    Consumed { grant_id: String },
+    /// The quota of the covering grant, or of one it was delegated from, is used up
+    /// for the current window.
+    Exhausted { grant_id: String },
+}
+
+/// One authorization question and its answer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapabilityCheck {
+    pub principal: String,
+    pub capability: CapabilityId,
+    pub at: u64,
+    pub outcome: CheckOutcome,
+    /// Effective expiry of the granting chain, when granted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Whether the granting chain is spent by the actuation this check authorizes.
+    #[serde(default)]
+    pub single_use: bool,
+    /// Actuations left under the granting chain's quotas, when granted under one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remaining: Option<u32>,
+}
+
+impl CapabilityCheck {
+    pub fn is_granted(&self) -> bool {
+        matches!(self.outcome, CheckOutcome::Granted { .. })
+    }
+}
+
+/// Body of an `EntryKind::Capability` entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum CapabilityEvent {
+    Granted { grant: Grant },
+    Checked { check: CapabilityCheck },
+    /// `grant_id` and every grant delegated from it are void from `revoked_at` on.
+    Revoked { grant_id: String, revoked_at: u64, reason: String },
+    /// An actuation under `grant_id` succeeded at `at`; spends it and every single-use
+    /// grant it was delegated from, and counts against every quota on the way.
+    Consumed { grant_id: String, at: u64 },
+}
+
+impl CapabilityEvent {
+    /// Appends the grant, revocation or check as an `EntryKind::Capability` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::Capability, self)
+    }
+}
+
+#[derive(Debug)]
+pub enum DelegationError {
+    UnknownGrant { grant_id: String },
+    /// The delegating principal does not hold the parent grant.
+    NotHolder { grant_id: String, principal: String },
+    /// The parent grant is expired or revoked at the delegation tick.
+    Ineffective { grant_id: String },
+    /// The delegated capability is not strictly narrower than the parent's, would
+    /// outlive it, or is reusable where the parent is single-use.
+    NotAttenuated { grant_id: String, requested: CapabilityId },
+    DuplicateGrant { grant_id: String },
+    Ledger(LedgerError),
+}
+
+impl fmt::Display for DelegationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DelegationError::UnknownGrant { grant_id } => write!(f, "no grant {}", grant_id),
+            DelegationError::NotHolder { grant_id, principal } => write!(f, "{} does not hold grant {}", principal, grant_id),
+            DelegationError::Ineffective { grant_id } => write!(f, "grant {} is expired or revoked", grant_id),
+            DelegationError::NotAttenuated { grant_id, requested } => {
+                write!(f, "{} is not a strict attenuation of grant {}", requested, grant_id)
+            }
+            DelegationError::DuplicateGrant { grant_id } => write!(f, "grant {} already exists", grant_id),
+            DelegationError::Ledger(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for DelegationError {}
+
+impl From<LedgerError> for DelegationError {
+    fn from(e: LedgerError) -> Self {
+        DelegationError::Ledger(e)
+    }
+}
+
+enum GrantStatus {
+    Valid,
+    Expired,
+    Revoked,
+    Consumed,
+    Exhausted,
+}
+
+/// Grants in effect, by grant id.
+#[derive(Debug, Clone, Default)]
+pub struct CapabilityStore {
+    grants: BTreeMap<String, Grant>,
+    /// Revocation list: grant id to the tick it was revoked at.
+    revoked: BTreeMap<String, u64>,
+    /// Spent single-use grants: grant id to the tick of the actuation.
+    consumed: BTreeMap<String, u64>,
+    /// Ticks of the actuations counted against each grant with a quota.
+    used: BTreeMap<String, Vec<u64>>,
+}
+
+impl CapabilityStore {
+    pub fn grant(&self, grant_id: &str) -> Option<&Grant> {
+        self.grants.get(grant_id)
+    }
+
+    pub fn grants_of<'a>(&'a self, principal: &'a str) -> impl Iterator<Item = &'a Grant> + 'a {
+        self.grants.values().filter(move |g| g.principal == principal)
+    }
+
+    /// Applies a recorded event; checks do not change the store.
+    pub fn apply(&mut self, event: &CapabilityEvent) {
+        match event {
+            CapabilityEvent::Granted { grant } => {
+                self.grants.insert(grant.grant_id.clone(), grant.clone());
+            }
+            CapabilityEvent::Checked { .. } => {}
+            CapabilityEvent::Revoked { grant_id, revoked_at, .. } => {
+                let at = self.revoked.entry(grant_id.clone()).or_insert(*revoked_at);
+                *at = (*at).min(*revoked_at);
+            }
+            CapabilityEvent::Consumed { grant_id, at } => {
+                let spent: Vec<String> = self.chain(grant_id).filter(|g| g.single_use).map(|g| g.grant_id.clone()).collect();
+                for grant_id in spent {
+                    self.consumed.entry(grant_id).or_insert(*at);
+                }
+                let counted: Vec<String> = self.chain(grant_id).filter(|g| g.quota.is_some()).map(|g| g.grant_id.clone()).collect();
+                for grant_id in counted {
+                    self.used.entry(grant_id).or_default().push(*at);
+                }
+            }
+        }
+    }
+
+    /// `grant_id` and the grants it was delegated from, nearest first. Bounded by the
+    /// number of grants, in case a replayed ledger holds a cycle.
+    fn chain<'a>(&'a self, grant_id: &str) -> impl Iterator<Item = &'a Grant> + 'a {
+        let first = self.grants.get(grant_id);
+        std::iter::successors(first, |g| g.delegated_from.as_ref().and_then(|parent| self.grants.get(parent))).take(self.grants.len())
+    }
+
+    pub fn revoked_at(&self, grant_id: &str) -> Option<u64> {
+        self.revoked.get(grant_id).copied()
+    }
+
+    /// Status of `grant` at `tick`, following its delegation chain up to the root.
+    fn status(&self, grant: &Grant, tick: u64) -> GrantStatus {
+        for g in self.chain(&grant.grant_id) {
+            if self.revoked_at(&g.grant_id).is_some_and(|at| tick >= at) {
+                return GrantStatus::Revoked;
+            }
+            if self.consumed.get(&g.grant_id).is_some_and(|&at| tick >= at) {
+                return GrantStatus::Consumed;
+            }
+            if !g.valid_at(tick) {
+                return GrantStatus::Expired;
+            }
+            if self.remaining(g, tick) == Some(0) {
+                return GrantStatus::Exhausted;
+            }
+            if g.delegated_from.is_none() {
+                return GrantStatus::Valid;
+            }
+        }
+        // A parent is missing, or the chain loops: no root authority.
+        GrantStatus::Revoked
+    }
+
+    /// Actuations `grant`'s own quota leaves at `tick`, if it has one.
+    fn remaining(&self, grant: &Grant, tick: u64) -> Option<u32> {
+        let quota = grant.quota?;
+        Some(quota.remaining(self.used.get(&grant.grant_id).map_or(&[], Vec::as_slice), tick))
+    }
+
+    /// Records `grant` in the ledger, then puts it into effect.
+    pub fn issue(&mut self, ledger: &mut DeterministicStore, grant: Grant) -> LedgerResult<()> {
+        let event = CapabilityEvent::Granted { grant };
+        event.append_to(ledger)?;
+        self.apply(&event);
+        Ok(())
+    }
+
+    /// Delegates an attenuated copy of `parent_id`, held by `delegator`, to `principal`
+    /// as `grant_id`. `capability` must be strictly narrower than the parent's, and the
+    /// delegation expires no later than the parent. Recorded like any other grant.
+    pub fn delegate(
+        &mut self,
+        ledger: &mut DeterministicStore,
+        parent_id: &str,
+        delegator: &str,
+        delegation: Grant,
+    ) -> Result<Grant, DelegationError> {
+        let parent = self.grants.get(parent_id).ok_or_else(|| DelegationError::UnknownGrant { grant_id: parent_id.to_string() })?;
+        if parent.principal != delegator {
+            return Err(DelegationError::NotHolder { grant_id: parent_id.to_string(), principal: delegator.to_string() });
+        }
+        if !matches!(self.status(parent, delegation.issued_at), GrantStatus::Valid) {
+            return Err(DelegationError::Ineffective { grant_id: parent_id.to_string() });
+        }
+        let narrower = parent.capability.covers(&delegation.capability) && !delegation.capability.covers(&parent.capability);
+        let outlives = match (parent.expires_at, delegation.expires_at) {
+            (Some(_), None) => true,
+            (Some(parent), Some(child)) => child > parent,
+            (None, _) => false,
+        };
+        let reusable = parent.single_use && !delegation.single_use;
+        if !narrower || outlives || reusable {
+            return Err(DelegationError::NotAttenuated { grant_id: parent_id.to_string(), requested: delegation.capability });
+        }
+        if self.grants.contains_key(&delegation.grant_id) {
+            return Err(DelegationError::DuplicateGrant { grant_id: delegation.grant_id });
+        }
+        let grant = Grant { delegated_from: Some(parent_id.to_string()), issuer: delegator.to_string(), ..delegation };
+        self.issue(ledger, grant.clone())?;
+        Ok(grant)
+    }
+
+    /// Records the revocation of `grant_id` (and its delegations) from `revoked_at` on.
+    pub fn revoke(&mut self, ledger: &mut DeterministicStore, grant_id: &str, revoked_at: u64, reason: &str) -> LedgerResult<()> {
+        let event = CapabilityEvent::Revoked { grant_id: grant_id.to_string(), revoked_at, reason: reason.to_string() };
+        event.append_to(ledger)?;
+        self.apply(&event);
+        Ok(())
+    }
+
+    /// Records a successful actuation under `grant_id`, spending it if it (or a grant
+    /// it was delegated from) is single-use and counting it against their quotas.
+    /// Returns whether anything was spent or counted; call it as soon as the actuation
+    /// is known to have succeeded.
+    pub fn record_actuation(&mut self, ledger: &mut DeterministicStore, grant_id: &str, tick: u64) -> LedgerResult<bool> {
+        if !self.chain(grant_id).any(|g| g.single_use || g.quota.is_some()) {
+            return Ok(false);
+        }
+        let event = CapabilityEvent::Consumed { grant_id: grant_id.to_string(), at: tick };
+        event.append_to(ledger)?;
+        self.apply(&event);
+        Ok(true)
+    }
+
+    /// Answers whether `principal` holds `required` at `tick`, without recording it.
+    pub fn evaluate(&self, principal: &str, required: &CapabilityId, tick: u64) -> CheckOutcome {
+        let mut denied = None;
+        for grant in self.grants_of(principal).filter(|g| g.capability.covers(required)) {
+            let grant_id = grant.grant_id.clone();
+            match self.status(grant, tick) {
+                GrantStatus::Valid => return CheckOutcome::Granted { grant_id },
+                GrantStatus::Revoked => denied = Some(CheckOutcome::Revoked { grant_id }),
+                GrantStatus::Consumed => {
+                    denied.get_or_insert(CheckOutcome::Consumed { grant_id });
+                }
+                GrantStatus::Exhausted => {
+                    denied.get_or_insert(CheckOutcome::Exhausted { grant_id });
+                }
+                GrantStatus::Expired => {
+                    denied.get_or_insert(CheckOutcome::Expired { grant_id });
+                }
+            }
+        }
+        denied.unwrap_or(CheckOutcome::NoGrant)
+    }
+
+    /// The Gate's check: evaluates and records the answer. A ledger error is returned
+    /// instead of the answer, so no authorization goes unrecorded.
+    pub fn check(&self, ledger: &mut DeterministicStore, principal: &str, required: &CapabilityId, tick: u64) -> LedgerResult<CapabilityCheck> {
+        let check = self.answer(principal, required, tick);
+        CapabilityEvent::Checked { check: check.clone() }.append_to(ledger)?;
+        Ok(check)
+    }
+
+    /// The check `check` would record, with the bounds of the granting chain.
+    pub fn answer(&self, principal: &str, required: &CapabilityId, tick: u64) -> CapabilityCheck {
+        let outcome = self.evaluate(principal, required, tick);
+        let (expires_at, single_use, remaining) = match &outcome {
+            CheckOutcome::Granted { grant_id } => (
+                self.chain(grant_id).filter_map(|g| g.expires_at).min(),
+                self.chain(grant_id).any(|g| g.single_use),
+                self.chain(grant_id).filter_map(|g| self.remaining(g, tick)).min(),
+            ),
+            _ => (None, false, None),
+        };
+        CapabilityCheck {
+            principal: principal.to_string(),
+            capability: required.clone(),
+            at: tick,
+            outcome,
+            expires_at,
+            single_use,
+            remaining,
+        }
+    }
+
+    /// Rebuilds the store by replaying the ledger's `Capability` entries in order.
+    pub fn from_ledger(ledger: &DeterministicStore) -> LedgerResult<Self> {
+        let mut store = Self::default();
+        let mut unreadable = None;
+        ledger.for_each_entry(|index, payload| {
+            let Some((EntryKind::Capability, body)) = entry::decode(payload) else { return };
+            match serde_json::from_slice::<CapabilityEvent>(body) {
+                Ok(event) => store.apply(&event),
+                Err(_) => unreadable = unreadable.or(Some(index)),
+            }
+        })?;
+        if let Some(index) = unreadable {
+            return Err(LedgerError::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unreadable capability entry at index {}", index),
+            )));
+        }
+        Ok(store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn granted(grant: Grant) -> CapabilityEvent {
+        CapabilityEvent::Granted { grant }
+    }
+
+    #[test]
+    fn revoking_a_grant_revokes_its_delegations() {
+        let mut store = CapabilityStore::default();
+        let root = Grant::new("g1", "planner", "actuator/*:move".parse().unwrap(), "operator", 0).expires_at(100);
+        let delegated = Grant { delegated_from: Some("g1".into()), ..Grant::new("g2", "arm", "actuator/arm:move".parse().unwrap(), "planner", 10) };
+        store.apply(&granted(root));
+        store.apply(&granted(delegated));
+        let required = "actuator/arm:move".parse().unwrap();
+
+        assert_eq!(store.evaluate("arm", &required, 20), CheckOutcome::Granted { grant_id: "g2".into() });
+        assert_eq!(store.evaluate("arm", &required, 100), CheckOutcome::Expired { grant_id: "g2".into() });
+        assert_eq!(store.evaluate("nobody", &required, 20), CheckOutcome::NoGrant);
+
+        store.apply(&CapabilityEvent::Revoked { grant_id: "g1".into(), revoked_at: 30, reason: "compromised".into() });
+        assert_eq!(store.evaluate("arm", &required, 29), CheckOutcome::Granted { grant_id: "g2".into() });
+        assert_eq!(store.evaluate("arm", &required, 30), CheckOutcome::Revoked { grant_id: "g2".into() });
+        // Re-issuing the grant does not lift the revocation.
+        store.apply(&granted(Grant::new("g1", "planner", "actuator/*:move".parse().unwrap(), "operator", 0)));
+        assert_eq!(store.evaluate("planner", &required, 40), CheckOutcome::Revoked { grant_id: "g1".into() });
+    }
+
+    #[test]
+    fn single_use_grants_are_spent_by_one_actuation() {
+        let mut store = CapabilityStore::default();
+        let emergency = Grant::new("e1", "operator", "actuator/*:stop".parse().unwrap(), "safety", 50).valid_for(10).single_use();
+        assert_eq!(emergency.expires_at, Some(60));
+        store.apply(&granted(emergency));
+        let required = "actuator/arm:stop".parse().unwrap();
+
+        assert_eq!(store.evaluate("operator", &required, 55), CheckOutcome::Granted { grant_id: "e1".into() });
+        store.apply(&CapabilityEvent::Consumed { grant_id: "e1".into(), at: 55 });
+        assert_eq!(store.evaluate("operator", &required, 54), CheckOutcome::Granted { grant_id: "e1".into() });
+        assert_eq!(store.evaluate("operator", &required, 56), CheckOutcome::Consumed { grant_id: "e1".into() });
+        assert_eq!(store.evaluate("operator", &required, 60), CheckOutcome::Consumed { grant_id: "e1".into() });
+    }
+
+    #[test]
+    fn quotas_bound_actuations_per_window() {
+        let mut store = CapabilityStore::default();
+        store.apply(&granted(Grant::new("q1", "planner", "sys:*".parse().unwrap(), "operator", 0).quota(2, 100)));
+        let delegated = Grant { delegated_from: Some("q1".into()), ..Grant::new("q2", "probe", "sys:read".parse().unwrap(), "planner", 0) };
+        store.apply(&granted(delegated));
+        let required = "sys:read".parse().unwrap();
+
+        assert_eq!(store.answer("planner", &required, 5).remaining, Some(2));
+        store.apply(&CapabilityEvent::Consumed { grant_id: "q1".into(), at: 10 });
+        // The delegate's actuations count against the quota it was delegated under.
+        store.apply(&CapabilityEvent::Consumed { grant_id: "q2".into(), at: 20 });
+        assert_eq!(store.evaluate("planner", &required, 20), CheckOutcome::Exhausted { grant_id: "q1".into() });
+        assert_eq!(store.evaluate("probe", &required, 109), CheckOutcome::Exhausted { grant_id: "q2".into() });
+        // The first use leaves the window at tick 110.
+        let check = store.answer("probe", &required, 110);
+        assert_eq!((check.outcome, check.remaining), (CheckOutcome::Granted { grant_id: "q2".into() }, Some(1)));
+    }
+
+    #[test]
+    fn application_entries_cannot_forge_a_replayed_grant() {
+        let dir = std::env::temp_dir().join(format!("rfsn-capability-forged-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut ledger = DeterministicStore::new(&dir).unwrap();
+        let mut store = CapabilityStore::default();
+        store.issue(&mut ledger, Grant::new("g1", "planner", "sys:read".parse().unwrap(), "operator", 0)).unwrap();
+
+        let forged = granted(Grant::new("g2", "intruder", "actuator/*:move".parse().unwrap(), "operator", 0));
+        let payload = entry::encode(EntryKind::Capability, &serde_json::to_vec(&forged).unwrap());
+        assert!(matches!(ledger.append_entry(&payload), Err(LedgerError::ReservedPrefix)));
+        ledger.append_entry(b"application bytes").unwrap();
+
+        let replayed = CapabilityStore::from_ledger(&ledger).unwrap();
+        assert!(replayed.grant("g1").is_some());
+        assert!(replayed.grant("g2").is_none());
+        assert_eq!(replayed.evaluate("intruder", &"actuator/arm:move".parse().unwrap(), 1), CheckOutcome::NoGrant);
+        drop(ledger);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,149 @@
+//! Anomalies the predictive loop reports, and what the Gate made of them.
+//!
+//! When the loop sees a prediction error large enough to act on, it names the
+//! anomaly with an id that is stable for its source, `<source>#<sequence>`, and puts
+//! that id on every proposal it makes because of it. `Gate::record_anomaly` appends
+//! the anomaly to the ledger as an `EntryKind::Anomaly` entry and counts it toward
+//! the risk score; each decision on such a proposal records the id in
+//! `Decision::anomaly`; and the execution results of allowed runs point back at their
+//! decision. `trail` follows those links, so an auditor can go from an anomaly to
+//! every proposal it caused, how each was decided and whether it ran, from the
+//! ledger alone.
+//!
+//! A source also checkpoints its model, periodically and as it stood when it raised
+//! an anomaly, with `Gate::record_model_checkpoint`: the state, in the source's own
+//! canonical encoding, goes into the ledger as an `EntryKind::ModelCheckpoint` entry
+//! under its hash. The source restores from its `latest` checkpoint after a restart,
+//! and the trail of an anomaly holds the checkpoint its source recorded last before
+//! it, the model that raised it.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use super::decision::Decision;
+use super::sandbox::ExecutionResult;
+use crate::ledger::entry::{self, EntryKind};
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+
+/// Body of an `EntryKind::Anomaly` entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Anomaly {
+    pub anomaly_id: String,
+    /// The principal that observed it, e.g. `subsystem::PREDICTIVE_LOOP`.
+    pub source: String,
+    pub description: String,
+    /// Gate tick it was recorded at.
+    pub tick: u64,
+}
+
+impl Anomaly {
+    /// Records the anomaly as an `EntryKind::Anomaly` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::Anomaly, self)
+    }
+}
+
+/// The id of `source`'s `sequence`th anomaly.
+pub fn anomaly_id(source: &str, sequence: u64) -> String {
+    format!("{}#{}", source, sequence)
+}
+
+/// Body of an `EntryKind::ModelCheckpoint` entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelCheckpoint {
+    pub source: String,
+    /// Gate tick it was recorded at.
+    pub tick: u64,
+    /// Hex blake3 of the state.
+    pub model_hash: String,
+    /// Hex of the state.
+    pub state: String,
+}
+
+impl ModelCheckpoint {
+    /// Records the checkpoint as an `EntryKind::ModelCheckpoint` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::ModelCheckpoint, self)
+    }
+
+    pub fn new(source: &str, tick: u64, state: &[u8]) -> Self {
+        Self { source: source.to_string(), tick, model_hash: blake3::hash(state).to_hex().to_string(), state: hex::encode(state) }
+    }
+
+    /// The state, if it decodes and matches its hash.
+    pub fn state(&self) -> Option<Vec<u8>> {
+        hex::decode(&self.state).ok().filter(|state| blake3::hash(state).to_hex().as_str() == self.model_hash)
+    }
+
+    /// The checkpoint `source` recorded last in `ledger`.
+    pub fn latest(ledger: &DeterministicStore, source: &str) -> LedgerResult<Option<Self>> {
+        let mut latest = None;
+        let mut unreadable = None;
+        ledger.for_each_entry(|at, payload| {
+            let Some((EntryKind::ModelCheckpoint, body)) = entry::decode(payload) else { return };
+            match serde_json::from_slice::<ModelCheckpoint>(body) {
+                Ok(checkpoint) if checkpoint.source == source => latest = Some(checkpoint),
+                Ok(_) => {}
+                Err(_) => unreadable = unreadable.or(Some(at)),
+            }
+        })?;
+        match unreadable {
+            Some(at) => Err(LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("unreadable model checkpoint entry at index {}", at)))),
+            None => Ok(latest),
+        }
+    }
+}
+
+/// Everything the ledger holds about one anomaly.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnomalyTrail {
+    /// `None` if the anomaly was never recorded, though proposals name it.
+    pub anomaly: Option<Anomaly>,
+    /// The model that raised it: its source's last checkpoint before it.
+    pub checkpoint: Option<ModelCheckpoint>,
+    /// Decisions on proposals made because of it, in ledger order.
+    pub decisions: Vec<Decision>,
+    /// Runs those decisions allowed.
+    pub executions: Vec<ExecutionResult>,
+}
+
+/// The trail of `anomaly_id` in `ledger`.
+pub fn trail(ledger: &DeterministicStore, anomaly_id: &str) -> LedgerResult<AnomalyTrail> {
+    let mut trail = AnomalyTrail::default();
+    // Each source's last checkpoint so far.
+    let mut checkpoints: BTreeMap<String, ModelCheckpoint> = BTreeMap::new();
+    let mut unreadable = None;
+    ledger.for_each_entry(|at, payload| {
+        let read = match entry::decode(payload) {
+            Some((EntryKind::ModelCheckpoint, body)) => serde_json::from_slice::<ModelCheckpoint>(body).map(|checkpoint| {
+                checkpoints.insert(checkpoint.source.clone(), checkpoint);
+            }),
+            Some((EntryKind::Anomaly, body)) => serde_json::from_slice::<Anomaly>(body).map(|anomaly| {
+                if anomaly.anomaly_id == anomaly_id {
+                    trail.checkpoint = checkpoints.get(&anomaly.source).cloned();
+                    trail.anomaly = Some(anomaly);
+                }
+            }),
+            Some((EntryKind::Decision, body)) => serde_json::from_slice::<Decision>(body).map(|decision| {
+                if decision.anomaly.as_deref() == Some(anomaly_id) {
+                    trail.decisions.push(decision);
+                }
+            }),
+            Some((EntryKind::Execution, body)) => serde_json::from_slice::<ExecutionResult>(body).map(|result| {
+                if trail.decisions.iter().any(|decision| decision.is_allowed() && decision.context_hash == result.decision) {
+                    trail.executions.push(result);
+                }
+            }),
+            _ => Ok(()),
+        };
+        if read.is_err() {
+            unreadable = unreadable.or(Some(at));
+        }
+    })?;
+    match unreadable {
+        Some(at) => Err(LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("unreadable entry at index {}", at)))),
+        None => Ok(trail),
+    }
+}
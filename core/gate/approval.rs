@@ -0,0 +1,231 @@
+//! m-of-n human approval of escalated proposals.
+//!
+//! A policy that ends in `ESCALATE` neither allows nor denies: the Gate parks the
+//! proposal under an approval id (a hash of the proposer and of the context the
+//! policy evaluated; see `approval_id`) and collects `ApprovalToken`s, Ed25519 signatures by designated approvers over that
+//! id. Once `required` distinct approvers have signed, the proposal is released
+//! through the remaining stages. Parking, each accepted approval and the release are
+//! appended to the ledger as `EntryKind::Approval` entries.
+//!
+//! The parking entry also holds the proposal, the capability it was checked against
+//! and, for native policies, a `vm::snapshot` of the evaluation that escalated, so a
+//! Gate reopened over the ledger parks again whatever was pending, with the approvals
+//! it had collected. A proposal whose snapshot does not match the decision that
+//! parked it, in policy, context or verdict, is not restored, and its proposer
+//! resubmits. Restored approvals are verified again against the `ApprovalPolicy` the
+//! reopened Gate is given, so an approver removed from it no longer counts.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use super::decision::{Decision, DenyReason};
+use super::proposal::ProposedAction;
+use crate::capability::id::CapabilityId;
+use crate::ledger::entry::{self, EntryKind};
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+use crate::vm::interp::Verdict;
+use crate::vm::snapshot::Snapshot;
+
+/// One approver's sign-off on a parked proposal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApprovalToken {
+    pub approval_id: String,
+    pub approver: String,
+    /// Ed25519 signature by the approver's key over `ApprovalToken::signing_payload`.
+    #[serde(with = "hex::serde")]
+    pub signature: Vec<u8>,
+}
+
+impl ApprovalToken {
+    pub fn signing_payload(approval_id: &str, approver: &str) -> Vec<u8> {
+        let mut out = b"RFSN-APPROVAL\0".to_vec();
+        for field in [approval_id.as_bytes(), approver.as_bytes()] {
+            out.extend_from_slice(&(field.len() as u64).to_le_bytes());
+            out.extend_from_slice(field);
+        }
+        out
+    }
+
+    pub fn sign(approval_id: &str, approver: &str, key: &SigningKey) -> Self {
+        let signature = key.sign(&Self::signing_payload(approval_id, approver)).to_bytes().to_vec();
+        Self { approval_id: approval_id.to_string(), approver: approver.to_string(), signature }
+    }
+}
+
+/// The id `principal`'s proposal is parked under, given the `context_hash` of the
+/// decision that parked it. The context does not name the proposer, so two
+/// principals' identical proposals at one tick would otherwise share an id.
+pub fn approval_id(principal: &str, context_hash: &str) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"RFSN-APPROVAL-ID\0");
+    for field in [principal.as_bytes(), context_hash.as_bytes()] {
+        hasher.update(&(field.len() as u64).to_le_bytes());
+        hasher.update(field);
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Who may approve, and how many must.
+#[derive(Debug, Clone)]
+pub struct ApprovalPolicy {
+    pub required: usize,
+    pub approvers: BTreeMap<String, VerifyingKey>,
+}
+
+impl ApprovalPolicy {
+    pub fn new(required: usize) -> Self {
+        Self { required, approvers: BTreeMap::new() }
+    }
+
+    pub fn approver(mut self, name: &str, key: VerifyingKey) -> Self {
+        self.approvers.insert(name.to_string(), key);
+        self
+    }
+
+    /// Checks that `token` is a valid signature by a designated approver.
+    pub fn verify(&self, token: &ApprovalToken) -> Result<(), ApprovalError> {
+        let key = self.approvers.get(&token.approver).ok_or_else(|| ApprovalError::UnknownApprover { approver: token.approver.clone() })?;
+        let signature = ed25519_dalek::Signature::from_slice(&token.signature).map_err(|_| ApprovalError::BadSignature { approver: token.approver.clone() })?;
+        key.verify_strict(&ApprovalToken::signing_payload(&token.approval_id, &token.approver), &signature)
+            .map_err(|_| ApprovalError::BadSignature { approver: token.approver.clone() })
+    }
+}
+
+/// Body of an `EntryKind::Approval` entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum ApprovalEvent {
+    Parked {
+        approval_id: String,
+        principal: String,
+        proposal_hash: String,
+        reason: u16,
+        at: u64,
+        /// Hex canonical proposal.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        proposal: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        capability: Option<CapabilityId>,
+        /// Hex `Snapshot::to_bytes` of the evaluation that escalated.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        snapshot: Option<String>,
+    },
+    Approved { token: ApprovalToken, at: u64 },
+    /// `approvers` signed off; the decision entry that follows says what became of it.
+    Released { approval_id: String, approvers: Vec<String>, at: u64 },
+}
+
+impl ApprovalEvent {
+    /// Appends the step as an `EntryKind::Approval` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::Approval, self)
+    }
+}
+
+#[derive(Debug)]
+pub enum ApprovalError {
+    /// Nothing is parked under the id, or it was already released.
+    UnknownApproval { approval_id: String },
+    UnknownApprover { approver: String },
+    BadSignature { approver: String },
+    AlreadyApproved { approver: String },
+    /// The Gate has no `ApprovalPolicy`.
+    NoApprovers,
+    Ledger(LedgerError),
+}
+
+impl fmt::Display for ApprovalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApprovalError::UnknownApproval { approval_id } => write!(f, "no proposal is awaiting approval {}", approval_id),
+            ApprovalError::UnknownApprover { approver } => write!(f, "{} is not a designated approver", approver),
+            ApprovalError::BadSignature { approver } => write!(f, "approval by {} has an invalid signature", approver),
+            ApprovalError::AlreadyApproved { approver } => write!(f, "{} has already approved", approver),
+            ApprovalError::NoApprovers => write!(f, "no approvers are configured"),
+            ApprovalError::Ledger(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ApprovalError {}
+
+impl From<LedgerError> for ApprovalError {
+    fn from(e: LedgerError) -> Self {
+        ApprovalError::Ledger(e)
+    }
+}
+
+/// A parked proposal and the approvals collected so far.
+#[derive(Debug, Clone)]
+pub struct PendingApproval {
+    pub principal: String,
+    pub proposal: ProposedAction,
+    /// The capability it was checked against when parked.
+    pub capability: CapabilityId,
+    /// The decision that parked it.
+    pub decision: Decision,
+    /// Tokens collected so far, by approver.
+    pub approvers: BTreeMap<String, ApprovalToken>,
+}
+
+impl PendingApproval {
+    /// Drops the approvals `policy` does not accept: by approvers no longer in it, or
+    /// whose signature does not verify under the key it holds for them.
+    pub fn retain_verified(&mut self, policy: &ApprovalPolicy) {
+        self.approvers.retain(|_, token| policy.verify(token).is_ok());
+    }
+}
+
+/// Whether `snapshot`, hex, is of the evaluation that made `decision` escalate with
+/// `reason`.
+fn escalated_in(snapshot: &str, decision: &Decision, reason: u16) -> bool {
+    let Some(snapshot) = hex::decode(snapshot).ok().and_then(|bytes| Snapshot::from_bytes(&bytes).ok()) else { return false };
+    snapshot.outcome == Some(Verdict::Escalate { reason })
+        && hex::encode(snapshot.context_hash) == decision.context_hash
+        && blake3::Hash::from(snapshot.policy_hash).to_hex().as_str() == decision.policy_hash
+}
+
+/// The proposals parked in `ledger` and not yet released, by approval id, with the
+/// approvals each has collected.
+pub fn parked(ledger: &DeterministicStore) -> LedgerResult<BTreeMap<String, PendingApproval>> {
+    let mut pending = BTreeMap::new();
+    let mut last_decision: Option<Decision> = None;
+    let mut unreadable = None;
+    ledger.for_each_entry(|index, payload| match entry::decode(payload) {
+        Some((EntryKind::Decision, body)) => match serde_json::from_slice(body) {
+            Ok(decision) => last_decision = Some(decision),
+            Err(_) => unreadable = unreadable.or(Some(index)),
+        },
+        Some((EntryKind::Approval, body)) => match serde_json::from_slice(body) {
+            Ok(ApprovalEvent::Parked { approval_id, principal, reason, proposal: Some(proposal), capability: Some(capability), snapshot, .. }) => {
+                // `admit` records the parking decision right before parking it.
+                let Some(decision) = last_decision.take() else { return };
+                let parked_by = matches!(&decision.denied, Some(DenyReason::PendingApproval { approval_id: id, .. }) if *id == approval_id);
+                let proposal = hex::decode(&proposal).ok().and_then(|bytes| ProposedAction::from_canonical(&bytes).ok());
+                let (true, Some(proposal)) = (parked_by, proposal) else { return };
+                if snapshot.is_none_or(|snapshot| escalated_in(&snapshot, &decision, reason)) {
+                    pending.insert(approval_id, PendingApproval { principal, proposal, capability, decision, approvers: BTreeMap::new() });
+                }
+            }
+            Ok(ApprovalEvent::Approved { token, .. }) => {
+                if let Some(parked) = pending.get_mut(&token.approval_id) {
+                    parked.approvers.insert(token.approver.clone(), token);
+                }
+            }
+            Ok(ApprovalEvent::Released { approval_id, .. }) => {
+                pending.remove(&approval_id);
+            }
+            Ok(ApprovalEvent::Parked { .. }) => {}
+            Err(_) => unreadable = unreadable.or(Some(index)),
+        },
+        _ => {}
+    })?;
+    match unreadable {
+        Some(index) => Err(LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("unreadable approval or decision entry at index {}", index)))),
+        None => Ok(pending),
+    }
+}
@@ -0,0 +1,248 @@
+//! Break-glass overrides of denials.
+//!
+//! A designated principal can force-allow a proposal the Gate denied, but never
+//! quietly. `Gate::break_glass` appends the signed override to the ledger as an
+//! `EntryKind::Override` entry, then has the ledger head covering it notarized by the
+//! witness quorum. Only once the receipts are in (and recorded) does it return an
+//! allowing decision. If the quorum is not reached, the override is queued instead and
+//! needs the Gate's m-of-n approvers (see `approval`) to sign it off with
+//! `Gate::approve_override`. Either way nothing can actuate until the override is
+//! witnessed or approved, and once it is, it cannot be taken back out of the ledger.
+//!
+//! The token names the denial by id only; the Gate reads the decision it overrides
+//! from the ledger, so a caller cannot present a denial the Gate never made. A token
+//! is honoured once: an override that was notarized or queued is spent. A Gate
+//! reopened over the ledger knows the spent ids, and queues again whatever was
+//! waiting for approvers, with the approvals it had collected.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io;
+
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use super::approval::{ApprovalError, ApprovalToken};
+use super::decision::{Decision, DenyReason};
+use crate::ledger::entry::{self, EntryKind};
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+
+/// A principal's signed demand to allow a denied decision.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverrideToken {
+    /// `context_hash` of the denied decision; also the override's id.
+    pub decision_id: String,
+    pub principal: String,
+    /// Why the glass was broken, for whoever audits the ledger.
+    pub justification: String,
+    /// Ed25519 signature by the principal's key over `OverrideToken::signing_payload`.
+    #[serde(with = "hex::serde")]
+    pub signature: Vec<u8>,
+}
+
+impl OverrideToken {
+    pub fn signing_payload(decision_id: &str, principal: &str, justification: &str) -> Vec<u8> {
+        let mut out = b"RFSN-BREAK-GLASS\0".to_vec();
+        for field in [decision_id.as_bytes(), principal.as_bytes(), justification.as_bytes()] {
+            out.extend_from_slice(&(field.len() as u64).to_le_bytes());
+            out.extend_from_slice(field);
+        }
+        out
+    }
+
+    pub fn sign(decision_id: &str, principal: &str, justification: &str, key: &SigningKey) -> Self {
+        let signature = key.sign(&Self::signing_payload(decision_id, principal, justification)).to_bytes().to_vec();
+        Self { decision_id: decision_id.to_string(), principal: principal.to_string(), justification: justification.to_string(), signature }
+    }
+}
+
+/// Who may break the glass.
+#[derive(Debug, Clone, Default)]
+pub struct BreakGlassPolicy {
+    pub principals: BTreeMap<String, VerifyingKey>,
+}
+
+impl BreakGlassPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn principal(mut self, name: &str, key: VerifyingKey) -> Self {
+        self.principals.insert(name.to_string(), key);
+        self
+    }
+
+    /// Checks that `token` is a valid signature by a designated principal.
+    pub fn verify(&self, token: &OverrideToken) -> Result<(), BreakGlassError> {
+        let key = self.principals.get(&token.principal).ok_or_else(|| BreakGlassError::UnknownPrincipal { principal: token.principal.clone() })?;
+        let signature = ed25519_dalek::Signature::from_slice(&token.signature).map_err(|_| BreakGlassError::BadSignature { principal: token.principal.clone() })?;
+        key.verify_strict(&OverrideToken::signing_payload(&token.decision_id, &token.principal, &token.justification), &signature)
+            .map_err(|_| BreakGlassError::BadSignature { principal: token.principal.clone() })
+    }
+}
+
+/// Body of an `EntryKind::Override` entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum OverrideEvent {
+    Requested { token: OverrideToken, proposal_hash: String, denied: DenyReason, at: u64 },
+    /// The witness quorum anchored the ledger head at `index`, which covers the request.
+    Notarized { override_id: String, index: u64, at: u64 },
+    /// The quorum was not reached; the override waits for approvers.
+    Queued { override_id: String, at: u64 },
+    Approved { token: ApprovalToken, at: u64 },
+    /// The decision entry that follows allows the proposal.
+    Released { override_id: String, approvers: Vec<String>, at: u64 },
+}
+
+impl OverrideEvent {
+    /// Appends the step as an `EntryKind::Override` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::Override, self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverrideOutcome {
+    /// Notarized; the decision allows the proposal.
+    Allowed(Box<Decision>),
+    /// Waiting for approvals under `override_id`.
+    Queued { override_id: String },
+}
+
+#[derive(Debug)]
+pub enum BreakGlassError {
+    /// The Gate has no `BreakGlassPolicy`.
+    Disabled,
+    /// The decision already allows the proposal.
+    NotDenied,
+    /// The ledger records no decision under the token's id.
+    UnknownDecision { decision_id: String },
+    /// The override was already notarized or queued.
+    AlreadyUsed { override_id: String },
+    UnknownPrincipal { principal: String },
+    BadSignature { principal: String },
+    /// Notarization failed and the Gate has no approvers to queue the override for.
+    NotNotarized { receipts: usize, threshold: usize },
+    /// Nothing is queued under the id, or it was already released.
+    UnknownOverride { override_id: String },
+    Approval(ApprovalError),
+    Ledger(LedgerError),
+}
+
+impl fmt::Display for BreakGlassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakGlassError::Disabled => write!(f, "break-glass overrides are not enabled"),
+            BreakGlassError::NotDenied => write!(f, "the decision is not a denial"),
+            BreakGlassError::UnknownDecision { decision_id } => write!(f, "no decision is recorded under {}", decision_id),
+            BreakGlassError::AlreadyUsed { override_id } => write!(f, "override {} was already used", override_id),
+            BreakGlassError::UnknownPrincipal { principal } => write!(f, "{} may not break the glass", principal),
+            BreakGlassError::BadSignature { principal } => write!(f, "override by {} has an invalid signature", principal),
+            BreakGlassError::NotNotarized { receipts, threshold } => {
+                write!(f, "override got {} of {} witness receipts and there are no approvers to queue it for", receipts, threshold)
+            }
+            BreakGlassError::UnknownOverride { override_id } => write!(f, "no override is queued under {}", override_id),
+            BreakGlassError::Approval(e) => write!(f, "{}", e),
+            BreakGlassError::Ledger(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BreakGlassError {}
+
+impl From<LedgerError> for BreakGlassError {
+    fn from(e: LedgerError) -> Self {
+        BreakGlassError::Ledger(e)
+    }
+}
+
+impl From<ApprovalError> for BreakGlassError {
+    fn from(e: ApprovalError) -> Self {
+        BreakGlassError::Approval(e)
+    }
+}
+
+/// A queued override and the approvals collected so far.
+#[derive(Debug, Clone)]
+pub struct PendingOverride {
+    pub token: OverrideToken,
+    /// The denial being overridden.
+    pub decision: Decision,
+    pub approvers: BTreeSet<String>,
+}
+
+/// The decision last recorded under `decision_id`, a context hash, among the first
+/// `before` entries of `ledger`.
+pub fn recorded_decision(ledger: &DeterministicStore, decision_id: &str, before: u64) -> LedgerResult<Option<Decision>> {
+    let mut found = None;
+    let mut unreadable = None;
+    ledger.for_each_entry(|index, payload| {
+        let Some((EntryKind::Decision, body)) = entry::decode(payload).filter(|_| index < before) else { return };
+        match serde_json::from_slice::<Decision>(body) {
+            Ok(decision) if decision.context_hash == decision_id => found = Some(decision),
+            Ok(_) => {}
+            Err(_) => unreadable = unreadable.or(Some(index)),
+        }
+    })?;
+    match unreadable {
+        Some(index) => Err(LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("unreadable decision entry at index {}", index)))),
+        None => Ok(found),
+    }
+}
+
+/// The overrides recorded in a ledger.
+#[derive(Debug, Clone, Default)]
+pub struct RecordedOverrides {
+    /// Ids of the overrides notarized or queued, which are not honoured again.
+    pub used: BTreeSet<String>,
+    /// Overrides queued and not yet released, by override id.
+    pub queued: BTreeMap<String, PendingOverride>,
+}
+
+impl RecordedOverrides {
+    pub fn from_ledger(ledger: &DeterministicStore) -> LedgerResult<Self> {
+        let mut used = BTreeSet::new();
+        // Each request by override id, with the index of its entry.
+        let mut requested = BTreeMap::new();
+        let mut queued = BTreeMap::new();
+        let mut unreadable = None;
+        ledger.for_each_entry(|index, payload| {
+            let Some((EntryKind::Override, body)) = entry::decode(payload) else { return };
+            match serde_json::from_slice(body) {
+                Ok(OverrideEvent::Requested { token, .. }) => {
+                    requested.insert(token.decision_id.clone(), (index, token));
+                }
+                Ok(OverrideEvent::Notarized { override_id, .. }) => {
+                    used.insert(override_id);
+                }
+                Ok(OverrideEvent::Queued { override_id, .. }) => {
+                    if let Some(request) = requested.get(&override_id) {
+                        queued.insert(override_id.clone(), (request.clone(), BTreeSet::new()));
+                    }
+                    used.insert(override_id);
+                }
+                Ok(OverrideEvent::Approved { token, .. }) => {
+                    if let Some((_, approvers)) = queued.get_mut(&token.approval_id) {
+                        approvers.insert(token.approver);
+                    }
+                }
+                Ok(OverrideEvent::Released { override_id, .. }) => {
+                    queued.remove(&override_id);
+                }
+                Err(_) => unreadable = unreadable.or(Some(index)),
+            }
+        })?;
+        if let Some(index) = unreadable {
+            return Err(LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("unreadable override entry at index {}", index))));
+        }
+        let mut recorded = Self { used, queued: BTreeMap::new() };
+        for (override_id, ((index, token), approvers)) in queued {
+            // `break_glass` only queues a denial it found in the ledger before the request.
+            if let Some(decision) = recorded_decision(ledger, &override_id, index)?.filter(|decision| decision.denied.is_some()) {
+                recorded.queued.insert(override_id, PendingOverride { token, decision, approvers });
+            }
+        }
+        Ok(recorded)
+    }
+}
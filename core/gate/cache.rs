@@ -0,0 +1,160 @@
+//! Caching of allowing decisions.
+//!
+//! A Gate built `with_decision_cache` remembers, for tools its registry marks
+//! read-only, the policy verdict and capability check of each proposal it allowed,
+//! keyed by policy hash, principal, proposal hash and context. A proposal that
+//! repeats all four skips the VM and the grant walk. The context key covers risk and
+//! every provided variable, and the tick only for a policy that may read it: the
+//! tick advances with every recorded decision, so keying every policy on it would
+//! never hit. A hit is served only while the cached check's grant chain is still
+//! unexpired at the decision's tick, so it is exactly the decision a fresh evaluation
+//! would have reached; the capability check is still recorded, at the current tick.
+//! The rate limits still apply.
+//!
+//! Only allows are cached, and never ones under a single-use or quota-limited grant,
+//! whose check depends on actuations and the quota window. The Gate empties
+//! the cache whenever the inputs the key does not cover change: a policy or tool
+//! registry is loaded, or a grant is issued, revoked or spent. Since those are ledger
+//! events, every node replaying the same ledger invalidates at the same point.
+//! Eviction is first in, first out.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use super::context::{Context, VAR_TICK};
+use super::decision::Decision;
+use super::policy::LoadedPolicy;
+use crate::capability::store::CapabilityCheck;
+use crate::vm::bytecode::{fetch, LOAD32, LOAD8};
+use crate::vm::interp::Trace;
+use crate::vm::runtime::NATIVE;
+use crate::wcet::watchdog::ExecutionUsage;
+
+/// `(policy_hash, principal, proposal_hash, context key)`; see `context_key`.
+type CacheKey = (String, String, String, String);
+
+/// What a cached allow restores into a decision.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedAllow {
+    pub usage: ExecutionUsage,
+    pub policy_trace: Option<Trace>,
+    pub capability: CapabilityCheck,
+}
+
+#[derive(Debug, Clone)]
+pub struct DecisionCache {
+    capacity: usize,
+    entries: BTreeMap<CacheKey, CachedAllow>,
+    /// Keys in insertion order, for eviction.
+    order: VecDeque<CacheKey>,
+    hits: u64,
+    misses: u64,
+    /// Policy hash, and whether that policy may read the tick.
+    reads_tick: Option<(String, bool)>,
+}
+
+/// Whether native bytecode `payload` loads any byte of the tick slot.
+fn loads_tick(payload: &[u8]) -> bool {
+    (0..).map_while(|pc| fetch(payload, pc)).any(|(opcode, operand)| matches!(opcode, LOAD8 | LOAD32) && operand < VAR_TICK + 4)
+}
+
+/// Whether `policy` may read the tick: a native policy or module that loads it, or
+/// any policy in a runtime that cannot be linted.
+pub fn reads_tick(policy: &LoadedPolicy) -> bool {
+    if policy.modules.is_empty() {
+        return policy.runtime.name() != NATIVE || loads_tick(&policy.payload);
+    }
+    policy.modules.iter().any(|module| module.runtime.is_some() || loads_tick(&module.payload))
+}
+
+/// Hex blake3 of `context`, with the tick slot zeroed unless `with_tick`.
+fn context_key(context: &Context, with_tick: bool) -> String {
+    if with_tick {
+        return context.hash();
+    }
+    let mut bytes = context.bytes().to_vec();
+    let tick = VAR_TICK as usize;
+    bytes[tick..tick + 4].fill(0);
+    blake3::hash(&bytes).to_hex().to_string()
+}
+
+impl DecisionCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, entries: BTreeMap::new(), order: VecDeque::new(), hits: 0, misses: 0, reads_tick: None }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    fn key(&mut self, policy: &LoadedPolicy, principal: &str, context: &Context, decision: &Decision) -> CacheKey {
+        let with_tick = match &self.reads_tick {
+            Some((hash, reads)) if hash == policy.hash() => *reads,
+            _ => {
+                let reads = reads_tick(policy);
+                self.reads_tick = Some((policy.hash().to_string(), reads));
+                reads
+            }
+        };
+        (decision.policy_hash.clone(), principal.to_string(), decision.proposal_hash.clone(), context_key(context, with_tick))
+    }
+
+    /// The cached allow for `principal`'s prepared `decision`, evaluated by `policy`
+    /// against `context`, if any. Its capability check is restamped at the decision's
+    /// tick; an entry whose grant chain has since expired is dropped.
+    pub fn get(&mut self, policy: &LoadedPolicy, principal: &str, context: &Context, decision: &Decision) -> Option<CachedAllow> {
+        let key = self.key(policy, principal, context, decision);
+        let expired = self.entries.get(&key).is_some_and(|cached| cached.capability.expires_at.is_some_and(|expiry| decision.tick >= expiry));
+        if expired {
+            self.entries.remove(&key);
+            self.order.retain(|k| *k != key);
+        }
+        let cached = self.entries.get(&key).cloned().map(|mut cached| {
+            cached.capability.at = decision.tick;
+            cached
+        });
+        match cached {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        cached
+    }
+
+    /// Remembers `decision` once the policy allowed it and `principal`'s capability
+    /// check passed; ignores anything else.
+    pub fn insert(&mut self, policy: &LoadedPolicy, principal: &str, context: &Context, decision: &Decision) {
+        let cacheable = |check: &&CapabilityCheck| check.is_granted() && !check.single_use && check.remaining.is_none();
+        let Some(capability) = decision.capability.as_ref().filter(cacheable) else { return };
+        if self.capacity == 0 {
+            return;
+        }
+        let key = self.key(policy, principal, context, decision);
+        if self.entries.contains_key(&key) {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        let cached = CachedAllow { usage: decision.usage, policy_trace: decision.policy_trace.clone(), capability: capability.clone() };
+        self.entries.insert(key.clone(), cached);
+        self.order.push_back(key);
+    }
+
+    pub fn invalidate(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
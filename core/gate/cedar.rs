@@ -0,0 +1,500 @@
+//! Importing Cedar policies.
+//!
+//! `translate` compiles a Cedar policy set into native bytecode, so authorization rules
+//! an organization already keeps in Cedar can be loaded without rewriting them. Only
+//! the part of Cedar the native context can express is accepted; anything else is
+//! refused with `ImportError::Unsupported` naming the construct, never approximated.
+//!
+//! The accepted subset:
+//!
+//! - `permit` and `forbid` policies with an unconstrained scope,
+//!   `(principal, action, resource)`. Who may use which tool is the capability
+//!   store's job, and the VM cannot match entities.
+//! - Any number of `when { .. }` and `unless { .. }` clauses over `true`, `false`,
+//!   integer literals up to `MAX_OPERAND`, the context variables below, `==`, `!=`,
+//!   `<`, `<=`, `>`, `>=`, `&&`, `||`, `!` and parentheses.
+//! - Annotations, of which only `@reason("N")` means anything: the code a `forbid`
+//!   denies with instead of `FORBID_REASON`.
+//!
+//! The variables are `context.tick`, `context.args`, `context.proposal_len` and
+//! `context.risk`, which the Gate sets, and `context.ledger_head`, `context.cluster`
+//! and `context.anomalies`, which read 0 unless the Gate has the matching
+//! `ContextProvider`.
+//!
+//! Cedar's semantics carry over: a matching `forbid` denies whatever permits it, and
+//! a proposal no `permit` matches is denied with `NO_PERMIT_REASON`. The program
+//! tests every `forbid` in order, then every `permit`, and the result is verified like
+//! any other native policy.
+
+use std::fmt;
+
+use super::context::{VAR_ANOMALIES, VAR_ARGS, VAR_CLUSTER, VAR_LEDGER_HEAD, VAR_PROPOSAL_LEN, VAR_RISK, VAR_TICK};
+use super::policy::LoadedPolicy;
+use crate::vm::bytecode::{encode, ALLOW, AND, DENY, EQ, GT, JZ, LOAD32, LT, MAX_OPERAND, NOT, OR, PUSH};
+use crate::vm::interp::REASON_MALFORMED;
+use crate::vm::verify::VerifyError;
+
+/// Denial code of a matching `forbid` without a `@reason`.
+pub const FORBID_REASON: u16 = 1;
+/// Denial code when no `permit` matches.
+pub const NO_PERMIT_REASON: u16 = 2;
+
+/// Parentheses and operators nest at most this deep.
+const MAX_NESTING: usize = 64;
+
+const VARS: [(&str, u32); 7] = [
+    ("tick", VAR_TICK),
+    ("args", VAR_ARGS),
+    ("proposal_len", VAR_PROPOSAL_LEN),
+    ("risk", VAR_RISK),
+    ("ledger_head", VAR_LEDGER_HEAD),
+    ("cluster", VAR_CLUSTER),
+    ("anomalies", VAR_ANOMALIES),
+];
+
+#[derive(Debug)]
+pub enum ImportError {
+    /// Not valid Cedar, or ill-typed, e.g. `context.risk && true`.
+    Syntax { line: usize, message: String },
+    /// Valid Cedar that has no native translation.
+    Unsupported { line: usize, construct: String },
+    Verify(VerifyError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            ImportError::Unsupported { line, construct } => write!(f, "line {}: {} cannot be translated", line, construct),
+            ImportError::Verify(e) => write!(f, "translated policy does not verify: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<VerifyError> for ImportError {
+    fn from(e: VerifyError) -> Self {
+        ImportError::Verify(e)
+    }
+}
+
+/// Translates and loads a Cedar policy set as the native policy `name`.
+pub fn import(name: &str, source: &str) -> Result<LoadedPolicy, ImportError> {
+    Ok(LoadedPolicy::load(name, translate(source)?)?)
+}
+
+/// The native bytecode of a Cedar policy set.
+pub fn translate(source: &str) -> Result<Vec<u8>, ImportError> {
+    let mut parser = Parser { tokens: tokenize(source)?, at: 0, depth: 0 };
+    let mut policies = Vec::new();
+    while parser.peek().is_some() {
+        policies.push(parser.policy()?);
+    }
+    // Forbids first, so no permit can pre-empt one.
+    policies.sort_by_key(|policy| policy.permit);
+    let mut program = Vec::new();
+    for policy in &policies {
+        policy.condition.emit(&mut program);
+        program.push((JZ, program.len() as u32 + 2));
+        program.push(if policy.permit { (ALLOW, 0) } else { (DENY, policy.reason as u32) });
+    }
+    program.push((DENY, NO_PERMIT_REASON as u32));
+    Ok(program.iter().flat_map(|&(op, arg)| encode(op, arg)).collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Int(u64),
+    Str(String),
+    /// Operators and punctuation.
+    Sym(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(s) => write!(f, "`{}`", s),
+            Token::Int(n) => write!(f, "`{}`", n),
+            Token::Str(s) => write!(f, "{:?}", s),
+            Token::Sym(s) => write!(f, "`{}`", s),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 22] = ["==", "!=", "<=", ">=", "&&", "||", "::", "<", ">", "!", "(", ")", "{", "}", "[", "]", ",", ";", ".", "@", "+", "-"];
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ImportError> {
+    let mut tokens = Vec::new();
+    for (number, text) in source.lines().enumerate() {
+        let line = number + 1;
+        let mut rest = text;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() || rest.starts_with("//") {
+                break;
+            }
+            let c = rest.chars().next().unwrap_or_default();
+            let (token, len) = if c.is_ascii_alphabetic() || c == '_' {
+                let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                (Token::Ident(rest[..len].to_string()), len)
+            } else if c.is_ascii_digit() {
+                let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                let value = rest[..len].parse().map_err(|_| ImportError::Unsupported { line, construct: format!("integer {}", &rest[..len]) })?;
+                (Token::Int(value), len)
+            } else if c == '"' {
+                // Escapes are kept verbatim; only annotation values are read.
+                let end = rest[1..].find('"').ok_or_else(|| ImportError::Syntax { line, message: "unterminated string".into() })?;
+                (Token::Str(rest[1..end + 1].to_string()), end + 2)
+            } else if let Some(symbol) = SYMBOLS.iter().find(|&&s| rest.starts_with(s)) {
+                (Token::Sym(symbol), symbol.len())
+            } else if c == '*' || c == '?' {
+                return Err(ImportError::Unsupported { line, construct: format!("`{}`", c) });
+            } else {
+                return Err(ImportError::Syntax { line, message: format!("unexpected character {:?}", c) });
+            };
+            tokens.push((line, token));
+            rest = &rest[len..];
+        }
+    }
+    Ok(tokens)
+}
+
+struct Policy {
+    permit: bool,
+    reason: u16,
+    condition: Expr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Bool,
+    Long,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Bool(bool),
+    Long(u32),
+    Var(u32),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Comparison, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn ty(&self) -> Type {
+        match self {
+            Expr::Long(_) | Expr::Var(_) => Type::Long,
+            _ => Type::Bool,
+        }
+    }
+
+    /// Leaves 1 or 0 on the stack for booleans, the value for longs. Nothing has side
+    /// effects or can fail, so `&&` and `||` need not short-circuit.
+    fn emit(&self, out: &mut Vec<(u8, u32)>) {
+        match self {
+            Expr::Bool(value) => out.push((PUSH, *value as u32)),
+            Expr::Long(value) => out.push((PUSH, *value)),
+            Expr::Var(var) => out.push((LOAD32, *var)),
+            Expr::Not(inner) => {
+                inner.emit(out);
+                out.push((NOT, 0));
+            }
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.emit(out);
+                b.emit(out);
+                out.push((if matches!(self, Expr::And(..)) { AND } else { OR }, 0));
+            }
+            Expr::Compare(comparison, a, b) => {
+                a.emit(out);
+                b.emit(out);
+                let (op, negate) = match comparison {
+                    Comparison::Eq => (EQ, false),
+                    Comparison::Ne => (EQ, true),
+                    Comparison::Lt => (LT, false),
+                    Comparison::Ge => (LT, true),
+                    Comparison::Gt => (GT, false),
+                    Comparison::Le => (GT, true),
+                };
+                out.push((op, 0));
+                if negate {
+                    out.push((NOT, 0));
+                }
+            }
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    at: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at).map(|(_, token)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.at).or(self.tokens.last()).map_or(1, |&(line, _)| line)
+    }
+
+    fn next(&mut self) -> Result<Token, ImportError> {
+        let token = self.peek().cloned().ok_or_else(|| self.syntax("unexpected end of input"))?;
+        self.at += 1;
+        Ok(token)
+    }
+
+    fn syntax(&self, message: &str) -> ImportError {
+        ImportError::Syntax { line: self.line(), message: message.to_string() }
+    }
+
+    fn unsupported(&self, construct: &str) -> ImportError {
+        ImportError::Unsupported { line: self.line(), construct: construct.to_string() }
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Sym(s)) if *s == symbol);
+        self.at += found as usize;
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ImportError> {
+        match self.eat(symbol) {
+            true => Ok(()),
+            false => Err(self.syntax(&format!("expected `{}`", symbol))),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, ImportError> {
+        match self.next()? {
+            Token::Ident(name) => Ok(name),
+            token => Err(ImportError::Syntax { line: self.tokens[self.at - 1].0, message: format!("expected a name, found {}", token) }),
+        }
+    }
+
+    fn policy(&mut self) -> Result<Policy, ImportError> {
+        let mut reason = FORBID_REASON;
+        while self.eat("@") {
+            let key = self.ident()?;
+            self.expect("(")?;
+            let Token::Str(value) = self.next()? else { return Err(self.syntax("annotation values are strings")) };
+            if key == "reason" {
+                reason = value.parse().ok().filter(|&code| code < REASON_MALFORMED).ok_or_else(|| self.syntax("@reason must be a code below 0xfff0"))?;
+            }
+            self.expect(")")?;
+        }
+        let permit = match self.ident()?.as_str() {
+            "permit" => true,
+            "forbid" => false,
+            _ => return Err(self.syntax("expected `permit` or `forbid`")),
+        };
+        self.expect("(")?;
+        for (i, var) in ["principal", "action", "resource"].into_iter().enumerate() {
+            if self.ident()? != var {
+                return Err(self.syntax(&format!("expected `{}`", var)));
+            }
+            if !self.eat(if i == 2 { ")" } else { "," }) {
+                return Err(self.unsupported(&format!("a constraint on {} in the policy scope", var)));
+            }
+        }
+        let mut condition = Expr::Bool(true);
+        while let Some(Token::Ident(keyword)) = self.peek() {
+            let unless = match keyword.as_str() {
+                "when" => false,
+                "unless" => true,
+                _ => break,
+            };
+            self.at += 1;
+            self.expect("{")?;
+            let clause = self.boolean()?;
+            self.expect("}")?;
+            let clause = if unless { Expr::Not(Box::new(clause)) } else { clause };
+            condition = match condition {
+                Expr::Bool(true) => clause,
+                condition => Expr::And(Box::new(condition), Box::new(clause)),
+            };
+        }
+        self.expect(";")?;
+        Ok(Policy { permit, reason, condition })
+    }
+
+    fn boolean(&mut self) -> Result<Expr, ImportError> {
+        let expr = self.or()?;
+        match expr.ty() {
+            Type::Bool => Ok(expr),
+            Type::Long => Err(self.syntax("expected a boolean")),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, ImportError> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(self.typed(expr, Type::Bool)?), Box::new(self.and().and_then(|e| self.typed(e, Type::Bool))?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ImportError> {
+        let mut expr = self.relation()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(self.typed(expr, Type::Bool)?), Box::new(self.relation().and_then(|e| self.typed(e, Type::Bool))?));
+        }
+        Ok(expr)
+    }
+
+    fn typed(&self, expr: Expr, ty: Type) -> Result<Expr, ImportError> {
+        match expr.ty() == ty {
+            true => Ok(expr),
+            false => Err(self.syntax(&format!("expected {}", if ty == Type::Bool { "a boolean" } else { "an integer" }))),
+        }
+    }
+
+    fn relation(&mut self) -> Result<Expr, ImportError> {
+        let left = self.unary()?;
+        let comparison = match self.peek() {
+            Some(Token::Sym("==")) => Comparison::Eq,
+            Some(Token::Sym("!=")) => Comparison::Ne,
+            Some(Token::Sym("<")) => Comparison::Lt,
+            Some(Token::Sym("<=")) => Comparison::Le,
+            Some(Token::Sym(">")) => Comparison::Gt,
+            Some(Token::Sym(">=")) => Comparison::Ge,
+            Some(Token::Sym(op @ ("+" | "-"))) => return Err(self.unsupported(&format!("arithmetic `{}`", op))),
+            Some(Token::Ident(op)) if ["in", "has", "like", "is"].contains(&op.as_str()) => return Err(self.unsupported(&format!("`{}`", op))),
+            _ => return Ok(left),
+        };
+        self.at += 1;
+        let right = self.unary()?;
+        let ordered = !matches!(comparison, Comparison::Eq | Comparison::Ne);
+        if left.ty() != right.ty() || (ordered && left.ty() != Type::Long) {
+            return Err(self.syntax("comparison of mismatched or unordered types"));
+        }
+        Ok(Expr::Compare(comparison, Box::new(left), Box::new(right)))
+    }
+
+    fn unary(&mut self) -> Result<Expr, ImportError> {
+        if self.depth == MAX_NESTING {
+            return Err(self.unsupported("nesting this deep"));
+        }
+        self.depth += 1;
+        let expr = if self.eat("!") {
+            let inner = self.unary()?;
+            Ok(Expr::Not(Box::new(self.typed(inner, Type::Bool)?)))
+        } else {
+            self.primary()
+        };
+        self.depth -= 1;
+        expr
+    }
+
+    fn primary(&mut self) -> Result<Expr, ImportError> {
+        let line = self.line();
+        let expr = match self.next()? {
+            Token::Int(value) if value <= MAX_OPERAND as u64 => Expr::Long(value as u32),
+            Token::Int(value) => return Err(self.unsupported(&format!("integer {} (above {})", value, MAX_OPERAND))),
+            Token::Sym("(") => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                expr
+            }
+            Token::Sym("-") => return Err(self.unsupported("negation")),
+            Token::Sym("[") => return Err(self.unsupported("a set")),
+            Token::Sym("{") => return Err(self.unsupported("a record")),
+            Token::Str(_) => return Err(self.unsupported("a string")),
+            Token::Ident(name) => match name.as_str() {
+                "true" | "false" => Expr::Bool(name == "true"),
+                "context" => {
+                    self.expect(".")?;
+                    let field = self.ident()?;
+                    let var = VARS.iter().find(|(name, _)| *name == field).ok_or_else(|| self.unsupported(&format!("context.{}", field)))?;
+                    Expr::Var(var.1)
+                }
+                "principal" | "action" | "resource" => return Err(self.unsupported(&format!("`{}` in a condition", name))),
+                "if" => return Err(self.unsupported("`if`")),
+                _ => return Err(self.unsupported(&format!("`{}`", name))),
+            },
+            token => return Err(ImportError::Syntax { line, message: format!("unexpected {}", token) }),
+        };
+        match self.peek() {
+            Some(Token::Sym(".")) => Err(self.unsupported("a method or attribute access")),
+            Some(Token::Sym("(")) => Err(self.unsupported("a function call")),
+            Some(Token::Sym("::")) => Err(self.unsupported("an entity reference")),
+            _ => Ok(expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::gate::context::Context;
+    use crate::gate::proposal::ProposedAction;
+    use crate::vm::interp::Verdict;
+    use crate::wcet::watchdog::Watchdog;
+
+    const POLICIES: &str = r#"
+        // Nothing actuates while the cluster is degraded.
+        @id("degraded")
+        @reason("7")
+        forbid (principal, action, resource) when { context.cluster != 0 };
+
+        permit (principal, action, resource)
+        when { context.risk <= 300 && (context.args < 4 || context.risk == 0) }
+        unless { context.anomalies > 2 };
+    "#;
+
+    fn decide(policy: &LoadedPolicy, vars: &[(u32, u32)]) -> Verdict {
+        let proposal = ProposedAction {
+            tool_name: "sys_diagnostic".into(),
+            capability_required: "sys:read".into(),
+            risk_hint: "low".into(),
+            args: HashMap::new(),
+            tenant: None,
+            nonce: None,
+            anomaly: None,
+        };
+        let mut context = Context::new(&proposal, 0);
+        for &(var, value) in vars {
+            context.set(var, value);
+        }
+        let mut watchdog = Watchdog::new(&policy.name, policy.proof.execution_budget());
+        policy.runtime.decide(&policy.payload, context.bytes(), &mut watchdog).unwrap()
+    }
+
+    #[test]
+    fn translates_the_subset_and_rejects_the_rest() {
+        let policy = import("cedar", POLICIES).unwrap();
+        assert_eq!(decide(&policy, &[(VAR_RISK, 120)]), Verdict::Allow);
+        assert_eq!(decide(&policy, &[(VAR_RISK, 120), (VAR_CLUSTER, 1)]), Verdict::Deny { reason: 7 });
+        assert_eq!(decide(&policy, &[(VAR_RISK, 301)]), Verdict::Deny { reason: NO_PERMIT_REASON });
+        assert_eq!(decide(&policy, &[(VAR_RISK, 120), (VAR_ANOMALIES, 3)]), Verdict::Deny { reason: NO_PERMIT_REASON });
+
+        let rejected = [
+            r#"permit (principal == User::"alice", action, resource);"#,
+            r#"permit (principal, action, resource) when { resource.owner == principal };"#,
+            r#"permit (principal, action, resource) when { context.region == 1 };"#,
+            r#"permit (principal, action, resource) when { context.risk + 1 > 2 };"#,
+            r#"permit (principal, action, resource) when { context.risk < 99999999 };"#,
+        ];
+        for source in rejected {
+            assert!(matches!(translate(source), Err(ImportError::Unsupported { line: 1, .. })), "{}", source);
+        }
+        assert!(matches!(translate("permit (principal, action, resource) when { context.risk };"), Err(ImportError::Syntax { .. })));
+    }
+}
@@ -0,0 +1,205 @@
+//! Policies composed from modules.
+//!
+//! A `Composition` is a declarative tree of combinators over policy modules, each
+//! named with its hash. `compose` checks the tree against the modules it is given and
+//! loads it as one policy whose payload is the tree's JSON, so the composition is what
+//! `policy_hash` covers and what the `PolicyVersion` entry records, along with every
+//! module's bytes.
+//!
+//! A module abstains by denying with `REASON_NO_DECISION`, the code the interpreter
+//! gives a run that reaches no decision. The combinators are:
+//!
+//! - `all-of`: allows only if every child allows; the first child that does not
+//!   decides otherwise.
+//! - `any-of`: allows if any child allows; else the first escalation, else the first
+//!   denial.
+//! - `deny-overrides`: the first denial wins, then the first escalation, then any
+//!   allow. Abstentions are ignored.
+//! - `first-match`: the first child that does not abstain decides.
+//!
+//! Children are evaluated in order and only as far as needed, all under the one
+//! watchdog, whose budget is the sum of the modules'. A composition that abstains as a
+//! whole denies with `REASON_NO_DECISION`, as a module would.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::policy::{LoadedPolicy, PolicyModule};
+use crate::vm::interp::{Verdict, REASON_NO_DECISION};
+use crate::vm::runtime::PolicyRuntime;
+use crate::vm::verify::{BoundednessProof, VerifyError};
+use crate::wcet::watchdog::{BudgetExceeded, Watchdog};
+
+pub const COMPOSED: &str = "composed";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "combinator", rename_all = "kebab-case")]
+pub enum Composition {
+    Module { name: String, hash: String },
+    AllOf { of: Vec<Composition> },
+    AnyOf { of: Vec<Composition> },
+    DenyOverrides { of: Vec<Composition> },
+    FirstMatch { of: Vec<Composition> },
+}
+
+impl Composition {
+    pub fn module(policy: &LoadedPolicy) -> Self {
+        Composition::Module { name: policy.name.clone(), hash: policy.hash().to_string() }
+    }
+
+    fn check(&self, modules: &BTreeMap<String, LoadedPolicy>) -> Result<(), VerifyError> {
+        match self {
+            Composition::Module { name, hash } => match modules.get(name) {
+                Some(module) if module.hash() == hash => Ok(()),
+                Some(_) => Err(VerifyError::Module(format!("module {} does not have hash {}", name, hash))),
+                None => Err(VerifyError::Module(format!("no module {}", name))),
+            },
+            Composition::AllOf { of } | Composition::AnyOf { of } | Composition::DenyOverrides { of } | Composition::FirstMatch { of } => {
+                if of.is_empty() {
+                    return Err(VerifyError::Module("empty combinator".into()));
+                }
+                of.iter().try_for_each(|child| child.check(modules))
+            }
+        }
+    }
+}
+
+/// Loads `composition` over `modules` as one policy. Every module the tree names must
+/// be given, with the hash the tree names; modules may not be compositions
+/// themselves, since trees nest directly.
+pub fn compose(name: &str, composition: Composition, modules: Vec<LoadedPolicy>) -> Result<LoadedPolicy, VerifyError> {
+    if let Some(nested) = modules.iter().find(|module| module.runtime.name() == COMPOSED) {
+        return Err(VerifyError::Module(format!("module {} is itself composed", nested.name)));
+    }
+    let modules: BTreeMap<String, LoadedPolicy> = modules.into_iter().map(|module| (module.name.clone(), module)).collect();
+    composition.check(&modules)?;
+    let payload = serde_json::to_vec(&composition).map_err(|e| VerifyError::Module(e.to_string()))?;
+    let recorded = modules.values().map(PolicyModule::of).collect();
+    let runtime = ComposedRuntime { composition, modules };
+    let policy = LoadedPolicy::load_with(Arc::new(runtime), name, payload)?;
+    Ok(LoadedPolicy { modules: recorded, ..policy })
+}
+
+/// Evaluates one composition; see `compose`.
+#[derive(Debug)]
+pub struct ComposedRuntime {
+    composition: Composition,
+    modules: BTreeMap<String, LoadedPolicy>,
+}
+
+impl ComposedRuntime {
+    /// `None` if the node abstains.
+    #[allow(clippy::result_large_err)]
+    fn evaluate(&self, node: &Composition, context: &[u8], watchdog: &mut Watchdog<'_>) -> Result<Option<Verdict>, BudgetExceeded> {
+        let (mut allowed, mut escalated, mut denied) = (None, None, None);
+        match node {
+            Composition::Module { name, .. } => {
+                let module = &self.modules[name];
+                return match module.runtime.decide(&module.payload, context, watchdog)? {
+                    Verdict::Deny { reason: REASON_NO_DECISION } => Ok(None),
+                    verdict => Ok(Some(verdict)),
+                };
+            }
+            Composition::AllOf { of } => {
+                for child in of {
+                    match self.evaluate(child, context, watchdog)? {
+                        Some(Verdict::Allow) => {}
+                        other => return Ok(other),
+                    }
+                }
+                return Ok(Some(Verdict::Allow));
+            }
+            Composition::FirstMatch { of } => {
+                for child in of {
+                    if let Some(verdict) = self.evaluate(child, context, watchdog)? {
+                        return Ok(Some(verdict));
+                    }
+                }
+                return Ok(None);
+            }
+            Composition::AnyOf { of } | Composition::DenyOverrides { of } => {
+                let deny_overrides = matches!(node, Composition::DenyOverrides { .. });
+                for child in of {
+                    match self.evaluate(child, context, watchdog)? {
+                        Some(Verdict::Allow) if !deny_overrides => return Ok(Some(Verdict::Allow)),
+                        Some(verdict @ Verdict::Deny { .. }) if deny_overrides => return Ok(Some(verdict)),
+                        Some(verdict @ Verdict::Allow) => allowed = allowed.or(Some(verdict)),
+                        Some(verdict @ Verdict::Escalate { .. }) => escalated = escalated.or(Some(verdict)),
+                        Some(verdict @ Verdict::Deny { .. }) => denied = denied.or(Some(verdict)),
+                        None => {}
+                    }
+                }
+            }
+        }
+        Ok(escalated.or(denied).or(allowed))
+    }
+}
+
+impl PolicyRuntime for ComposedRuntime {
+    fn name(&self) -> &'static str {
+        COMPOSED
+    }
+
+    /// Accepts only this runtime's own composition; the proof sums the modules'.
+    fn verify(&self, payload: &[u8]) -> Result<BoundednessProof, VerifyError> {
+        let expected = serde_json::to_vec(&self.composition).map_err(|e| VerifyError::Module(e.to_string()))?;
+        if payload != expected.as_slice() {
+            return Err(VerifyError::Module("payload is not this runtime's composition".into()));
+        }
+        let proofs = self.modules.values().map(|module| &module.proof);
+        Ok(BoundednessProof {
+            policy_hash: blake3::hash(payload).to_hex().to_string(),
+            instructions: proofs.clone().map(|proof| proof.instructions).sum(),
+            max_steps: proofs.clone().map(|proof| proof.max_steps).sum(),
+            max_stack_depth: proofs.map(|proof| proof.max_stack_depth).max().unwrap_or(0),
+            secrets: Vec::new(),
+        })
+    }
+
+    fn decide(&self, _payload: &[u8], context: &[u8], watchdog: &mut Watchdog<'_>) -> Result<Verdict, BudgetExceeded> {
+        Ok(self.evaluate(&self.composition, context, watchdog)?.unwrap_or(Verdict::Deny { reason: REASON_NO_DECISION }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::bytecode::*;
+
+    fn module(name: &str, program: &[(u8, u32)]) -> LoadedPolicy {
+        LoadedPolicy::load(name, program.iter().flat_map(|&(op, arg)| encode(op, arg)).collect()).unwrap()
+    }
+
+    fn decide(policy: &LoadedPolicy) -> Verdict {
+        let mut watchdog = Watchdog::new(&policy.name, policy.proof.execution_budget());
+        policy.runtime.decide(&policy.payload, &[], &mut watchdog).unwrap()
+    }
+
+    #[test]
+    fn combinators_treat_abstentions_as_documented() {
+        let allow = module("allow", &[(ALLOW, 0)]);
+        let deny = module("deny", &[(DENY, 3)]);
+        let escalate = module("escalate", &[(ESCALATE, 4)]);
+        let abstain = module("abstain", &[(PUSH, 1), (POP, 0), (DENY, REASON_NO_DECISION as u32)]);
+        let modules = || vec![allow.clone(), deny.clone(), escalate.clone(), abstain.clone()];
+        let leaf = |policy: &LoadedPolicy| Composition::module(policy);
+        let composed = |composition| compose("composed", composition, modules()).unwrap();
+
+        let all_of = composed(Composition::AllOf { of: vec![leaf(&allow), leaf(&abstain), leaf(&deny)] });
+        assert_eq!(decide(&all_of), Verdict::Deny { reason: REASON_NO_DECISION });
+        let any_of = composed(Composition::AnyOf { of: vec![leaf(&deny), leaf(&escalate), leaf(&allow)] });
+        assert_eq!(decide(&any_of), Verdict::Allow);
+        let overrides = composed(Composition::DenyOverrides { of: vec![leaf(&abstain), leaf(&allow), leaf(&escalate)] });
+        assert_eq!(decide(&overrides), Verdict::Escalate { reason: 4 });
+        let first = composed(Composition::FirstMatch { of: vec![leaf(&abstain), leaf(&deny), leaf(&allow)] });
+        assert_eq!(decide(&first), Verdict::Deny { reason: 3 });
+        assert_eq!(first.proof.max_steps, 6);
+        assert_eq!(first.modules.len(), 4);
+
+        let stale = Composition::Module { name: "allow".into(), hash: deny.hash().to_string() };
+        assert!(matches!(compose("stale", stale, modules()), Err(VerifyError::Module(_))));
+        assert!(matches!(compose("nested", leaf(&first), vec![first.clone()]), Err(VerifyError::Module(_))));
+    }
+}
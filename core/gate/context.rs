@@ -0,0 +1,157 @@
+//! The evaluation context a policy runs against.
+//!
+//! A context is a fixed header of `CONTEXT_SLOTS` little-endian u32 variables
+//! followed by the proposal's canonical bytes. Policies read a variable with
+//! `LOAD32 <VAR_*>` and the proposal from `PROPOSAL_OFFSET` on. Every input is
+//! deterministic, so a context (and its hash) is the same on every node that
+//! evaluates the proposal at the same tick.
+//!
+//! The Gate sets the tick, argument count, proposal length and risk itself; the other
+//! slots are filled by the `ContextProvider`s registered with it. A provider only
+//! sees `ProviderInput`, the Gate's replicated state at the decision's tick, so it
+//! cannot bring a host clock or anything else node-local into a decision by accident.
+//! A provider that does so on purpose declares it with `nondeterminism`, and the Gate
+//! refuses policies that read its slot unless the slot is explicitly allowed; see
+//! `vm::lint`.
+
+use std::fmt;
+use std::ops::Range;
+
+use super::proposal::ProposedAction;
+use super::risk::{ClusterState, RiskScorer};
+use crate::ledger::storage::DeterministicStore;
+use crate::vm::lint::Nondeterminism;
+
+pub const CONTEXT_SLOTS: usize = 16;
+
+/// Byte offset of the canonical proposal.
+pub const PROPOSAL_OFFSET: u32 = (CONTEXT_SLOTS * 4) as u32;
+
+/// Low 32 bits of the evaluation tick.
+pub const VAR_TICK: u32 = 0;
+/// Number of proposal arguments.
+pub const VAR_ARGS: u32 = 4;
+/// Length of the canonical proposal in bytes.
+pub const VAR_PROPOSAL_LEN: u32 = 8;
+/// Risk score from 0 to `risk::MAX_RISK`, set by the Gate.
+pub const VAR_RISK: u32 = 12;
+/// First four bytes of the ledger's Merkle root, little-endian; see `LedgerHead`.
+pub const VAR_LEDGER_HEAD: u32 = 16;
+/// 0 healthy, 1 degraded, 2 frozen; see `ClusterStatus`.
+pub const VAR_CLUSTER: u32 = 20;
+/// See `RecentAnomalies`.
+pub const VAR_ANOMALIES: u32 = 24;
+
+/// Slots the Gate sets itself; providers may not claim them.
+pub const GATE_VARS: [u32; 4] = [VAR_TICK, VAR_ARGS, VAR_PROPOSAL_LEN, VAR_RISK];
+
+/// Context bytes of the variable at `var`, e.g. to declare it secret.
+pub fn var_bytes(var: u32) -> Range<u32> {
+    var..var + 4
+}
+
+/// What a provider may read.
+#[derive(Clone, Copy)]
+pub struct ProviderInput<'a> {
+    pub tick: u64,
+    pub ledger: &'a DeterministicStore,
+    pub risk: &'a RiskScorer,
+}
+
+/// Supplies one context variable from the Gate's deterministic state.
+pub trait ContextProvider: fmt::Debug + Send + Sync {
+    /// Header slot the value goes in; not one of `GATE_VARS`.
+    fn var(&self) -> u32;
+
+    fn value(&self, input: &ProviderInput<'_>) -> u32;
+
+    /// What node-local source the value draws on, if any.
+    fn nondeterminism(&self) -> Option<Nondeterminism> {
+        None
+    }
+}
+
+/// The ledger head at `VAR_LEDGER_HEAD`, so a policy can pin a decision to a ledger
+/// state.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LedgerHead;
+
+impl ContextProvider for LedgerHead {
+    fn var(&self) -> u32 {
+        VAR_LEDGER_HEAD
+    }
+
+    fn value(&self, input: &ProviderInput<'_>) -> u32 {
+        let root = input.ledger.tree().root();
+        u32::from_le_bytes([root[0], root[1], root[2], root[3]])
+    }
+}
+
+/// The cluster state the risk scorer was fed, at `VAR_CLUSTER`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClusterStatus;
+
+impl ContextProvider for ClusterStatus {
+    fn var(&self) -> u32 {
+        VAR_CLUSTER
+    }
+
+    fn value(&self, input: &ProviderInput<'_>) -> u32 {
+        match input.risk.cluster_state() {
+            ClusterState::Healthy => 0,
+            ClusterState::Degraded => 1,
+            ClusterState::Frozen => 2,
+        }
+    }
+}
+
+/// Anomalies in the risk scorer's window ending at the decision's tick, at
+/// `VAR_ANOMALIES`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecentAnomalies;
+
+impl ContextProvider for RecentAnomalies {
+    fn var(&self) -> u32 {
+        VAR_ANOMALIES
+    }
+
+    fn value(&self, input: &ProviderInput<'_>) -> u32 {
+        input.risk.recent_anomalies(input.tick)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Context {
+    bytes: Vec<u8>,
+}
+
+impl Context {
+    pub fn new(proposal: &ProposedAction, tick: u64) -> Self {
+        let canonical = proposal.canonical();
+        let mut context = Self { bytes: vec![0; PROPOSAL_OFFSET as usize] };
+        context.bytes.extend_from_slice(&canonical);
+        context.set(VAR_TICK, tick as u32);
+        context.set(VAR_ARGS, proposal.args.len() as u32);
+        context.set(VAR_PROPOSAL_LEN, canonical.len() as u32);
+        context
+    }
+
+    /// Sets the variable at byte offset `var`.
+    ///
+    /// # Panics
+    /// If `var` is not a slot offset in the header.
+    pub fn set(&mut self, var: u32, value: u32) {
+        assert!(var.is_multiple_of(4) && var < PROPOSAL_OFFSET, "context variable {} is not a header slot", var);
+        let at = var as usize;
+        self.bytes[at..at + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Hex blake3 of the context bytes.
+    pub fn hash(&self) -> String {
+        blake3::hash(&self.bytes).to_hex().to_string()
+    }
+}
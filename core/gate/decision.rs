@@ -0,0 +1,237 @@
+//! What the Gate answers for one proposal.
+//!
+//! A decision carries the path its policy took (`trace`), and `explain` renders the
+//! stage that denied and the conditions behind it, so a rejection can be understood
+//! without reading the policy's bytecode. `outcome` reduces it to a stable reason
+//! code for automation (see `reason`).
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::fallback::Fallback;
+use super::proposal::ProposedAction;
+use super::reason::DecisionOutcome;
+use super::replay::ProposerNonce;
+use super::tools::ArgViolation;
+use crate::capability::store::{CapabilityCheck, CheckOutcome};
+use crate::ledger::entry::EntryKind;
+use crate::ledger::storage::{DeterministicStore, LedgerResult};
+use crate::vm::interp::{Trace, Verdict, REASON_MALFORMED};
+use crate::wcet::watchdog::{ExceededLimit, ExecutionBudget, ExecutionUsage};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum DenyReason {
+    /// `capability_required` does not parse as a capability.
+    MalformedCapability { capability: String },
+    /// The proposal's nonce is not above `last`, the proposer's latest; see `replay`.
+    Replayed { nonce: u64, last: u64 },
+    /// The Gate requires nonces and the proposal has none.
+    MissingNonce,
+    /// The Gate is frozen and the tool actuates; see `freeze`.
+    Frozen { freeze_id: String },
+    /// The proposal's tool or arguments do not match the tool registry.
+    InvalidArgs { violation: ArgViolation },
+    /// An internal subsystem proposed a tool that is not read-only; see `subsystem`.
+    SubsystemActuation { tool: String },
+    /// The Gate requires sessions and the proposal was not made in an open one.
+    Unauthenticated,
+    /// The policy denied with `code`; codes from `0xfff0` up are VM faults.
+    Policy { code: u16 },
+    /// The policy was cut off by its watchdog.
+    Budget { limit: ExceededLimit },
+    /// The principal does not hold the required capability.
+    Capability { outcome: CheckOutcome },
+    /// The rate limit `key`, a tool or capability bucket, has no tokens left.
+    RateLimited { key: String },
+    /// The policy escalated with `code`; the proposal is parked until enough
+    /// approvers sign `approval_id`.
+    PendingApproval { approval_id: String, code: u16 },
+    /// The same principal's identical proposal is already parked under `approval_id`;
+    /// it is approved once, not parked twice.
+    AlreadyPending { approval_id: String },
+}
+
+impl fmt::Display for DenyReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DenyReason::MalformedCapability { capability } => write!(f, "capability {:?} does not parse", capability),
+            DenyReason::Replayed { nonce, last } => write!(f, "nonce {} is not above the last one used, {}", nonce, last),
+            DenyReason::MissingNonce => write!(f, "the proposal carries no nonce"),
+            DenyReason::Frozen { freeze_id } => write!(f, "the gate is frozen by {}", freeze_id),
+            DenyReason::InvalidArgs { violation } => write!(f, "{}", violation),
+            DenyReason::SubsystemActuation { tool } => write!(f, "subsystems may not actuate, and {} is not read-only", tool),
+            DenyReason::Unauthenticated => write!(f, "the proposal was not made in an authenticated session"),
+            DenyReason::Policy { code } if *code >= REASON_MALFORMED => write!(f, "policy faulted with code {:#06x}", code),
+            DenyReason::Policy { code } => write!(f, "policy denied with code {}", code),
+            DenyReason::Budget { limit } => write!(f, "policy exceeded its {:?} budget", limit),
+            DenyReason::Capability { outcome } => match outcome {
+                CheckOutcome::Granted { grant_id } => write!(f, "grant {} was not enough", grant_id),
+                CheckOutcome::NoGrant => write!(f, "no grant covers the required capability"),
+                CheckOutcome::Expired { grant_id } => write!(f, "grant {} is not valid at this tick", grant_id),
+                CheckOutcome::Revoked { grant_id } => write!(f, "grant {} is revoked", grant_id),
+                CheckOutcome::Consumed { grant_id } => write!(f, "grant {} is spent", grant_id),
+                CheckOutcome::Exhausted { grant_id } => write!(f, "grant {} has no actuations left in this window", grant_id),
+            },
+            DenyReason::RateLimited { key } => write!(f, "rate limit {} is exhausted", key),
+            DenyReason::PendingApproval { approval_id, code } => {
+                write!(f, "policy escalated with code {}; awaiting approval of {}", code, approval_id)
+            }
+            DenyReason::AlreadyPending { approval_id } => write!(f, "an identical proposal is already awaiting approval of {}", approval_id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Decision {
+    /// Who proposed: the session's principal on a Gate that requires sessions, else
+    /// the principal the caller named. Empty for a proposal made outside a session.
+    #[serde(default)]
+    pub principal: String,
+    /// The session the proposal was made in; see `session`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    /// Hex blake3 of the canonical proposal.
+    pub proposal_hash: String,
+    /// The proposal's tool, so limits can be rebuilt from decisions.
+    #[serde(default)]
+    pub tool: String,
+    /// The anomaly the proposal was made because of; see `anomaly`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anomaly: Option<String>,
+    /// Hex blake3 of the context the policy ran against.
+    pub context_hash: String,
+    pub policy: String,
+    pub policy_hash: String,
+    pub tick: u64,
+    /// Risk score the policy saw.
+    #[serde(default)]
+    pub risk: u32,
+    /// `None` if the proposal is allowed.
+    pub denied: Option<DenyReason>,
+    /// What the policy evaluation used of its budget.
+    pub usage: ExecutionUsage,
+    /// The budget the policy ran under, if it ran; see `metering`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<ExecutionBudget>,
+    /// The namespace fallback that decided, if the policy reached no decision; see
+    /// `fallback`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<Fallback>,
+    /// The capability check, if the proposal got that far.
+    pub capability: Option<CapabilityCheck>,
+    /// Approvers who released a parked proposal.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub approvers: Vec<String>,
+    /// Principal whose break-glass override allowed a denied proposal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overridden_by: Option<String>,
+    /// Hex canonical form of a safer proposal the Gate would allow instead of a
+    /// denied one; see `tools::SaferAlternative`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counter: Option<String>,
+    /// The proposer's nonce, once the decision has used it up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<ProposerNonce>,
+    /// The path the policy took, if it ran; empty for runtimes that cannot trace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_trace: Option<Trace>,
+}
+
+impl Decision {
+    /// Records the decision as an `EntryKind::Decision` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::Decision, self)
+    }
+
+    pub fn is_allowed(&self) -> bool {
+        self.denied.is_none()
+    }
+
+    /// Allow, deny or escalate, with a code from the reason registry.
+    pub fn outcome(&self) -> DecisionOutcome {
+        DecisionOutcome::of(self)
+    }
+
+    /// The counter-proposal offered with a denial, ready to submit.
+    pub fn counter_proposal(&self) -> Option<ProposedAction> {
+        let bytes = hex::decode(self.counter.as_ref()?).ok()?;
+        ProposedAction::from_canonical(&bytes).ok()
+    }
+
+    /// Grant the proposal is allowed under.
+    pub fn grant_id(&self) -> Option<&str> {
+        match self.capability.as_ref().map(|c| &c.outcome) {
+            Some(CheckOutcome::Granted { grant_id }) if self.is_allowed() => Some(grant_id),
+            _ => None,
+        }
+    }
+
+    /// The conditions the policy evaluated and the instruction that decided, if the
+    /// policy ran.
+    pub fn trace(&self) -> Option<&Trace> {
+        self.policy_trace.as_ref()
+    }
+
+    /// One line on why the proposal was allowed or denied, e.g. `denied: policy denied
+    /// with code 2 at pc 8, after conditions pc 1 held, pc 5 failed`.
+    pub fn explain(&self) -> String {
+        let mut line = match (&self.denied, &self.overridden_by) {
+            (None, Some(principal)) => format!("allowed by a break-glass override of {}", principal),
+            (None, None) => "allowed".to_string(),
+            (Some(reason), _) => format!("denied: {}", reason),
+        };
+        if let Some(trace) = self.trace() {
+            if let Some(pc) = trace.decided_at {
+                line.push_str(&format!(" at pc {}", pc));
+            }
+            if !trace.conditions.is_empty() {
+                let conditions: Vec<String> =
+                    trace.conditions.iter().map(|c| format!("pc {} {}", c.pc, if c.held { "held" } else { "failed" })).collect();
+                line.push_str(&format!(", after conditions {}", conditions.join(", ")));
+            }
+        }
+        line
+    }
+}
+
+/// A dry run: the decision `admit` would make at the same tick, with none of its
+/// effects.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Simulation {
+    pub decision: Decision,
+    /// The policy's own verdict, if it ran to completion. Shows which rule the policy
+    /// matched (its deny reason) even when a later stage decided.
+    pub policy_verdict: Option<Verdict>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deny_reasons_roundtrip_through_json() {
+        let reasons = [
+            DenyReason::MalformedCapability { capability: "sys".into() },
+            DenyReason::Replayed { nonce: 3, last: 5 },
+            DenyReason::MissingNonce,
+            DenyReason::Frozen { freeze_id: "f1".into() },
+            DenyReason::InvalidArgs { violation: ArgViolation::OutOfRange { arg: "speed".into(), min: Some(0), max: None } },
+            DenyReason::SubsystemActuation { tool: "arm_move".into() },
+            DenyReason::Unauthenticated,
+            DenyReason::Policy { code: 7 },
+            DenyReason::Budget { limit: ExceededLimit::Steps },
+            DenyReason::Capability { outcome: CheckOutcome::Revoked { grant_id: "g1".into() } },
+            DenyReason::RateLimited { key: "tool:arm_move".into() },
+            DenyReason::PendingApproval { approval_id: "a1".into(), code: 9 },
+            DenyReason::AlreadyPending { approval_id: "a1".into() },
+        ];
+        for reason in reasons {
+            let json = serde_json::to_string(&reason).unwrap();
+            assert_eq!(serde_json::from_str::<DenyReason>(&json).unwrap(), reason, "{}", json);
+        }
+        let parked = serde_json::to_value(DenyReason::PendingApproval { approval_id: "a1".into(), code: 9 }).unwrap();
+        assert_eq!(parked, serde_json::json!({ "reason": "pending-approval", "approval_id": "a1", "code": 9 }));
+    }
+}
@@ -0,0 +1,99 @@
+//! What the Gate decides when no policy does.
+//!
+//! A policy, or every module of a composition, may reach no decision for a proposal:
+//! it then denies with `REASON_NO_DECISION` (see `compose`). What that means is
+//! configured per namespace, the first resource segment of the capability the
+//! proposal requires (`actuator` for `actuator/arm/joint2:move`). A safety-critical
+//! namespace stays `DefaultDeny`, the Gate's behaviour without a config; a low-risk
+//! namespace such as telemetry can be `DefaultAllow`. Either way the decision records
+//! the `Fallback` that made it, so every proposal no policy matched can be found in
+//! the ledger. A default-allowed proposal still needs its capability and passes the
+//! rate limits.
+//!
+//! Each config the Gate loads is appended to the ledger, with its hash, as an
+//! `EntryKind::NamespaceConfig` entry.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::capability::id::CapabilityId;
+use crate::ledger::entry::{self, EntryKind};
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fallback {
+    #[default]
+    DefaultDeny,
+    DefaultAllow,
+}
+
+/// Fallbacks by namespace.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamespaceConfig {
+    #[serde(default)]
+    pub namespaces: BTreeMap<String, Fallback>,
+    /// For namespaces not listed.
+    #[serde(default)]
+    pub default: Fallback,
+}
+
+impl NamespaceConfig {
+    pub fn namespace(mut self, name: &str, fallback: Fallback) -> Self {
+        self.namespaces.insert(name.to_string(), fallback);
+        self
+    }
+
+    /// The namespace of a required capability.
+    pub fn namespace_of(required: &CapabilityId) -> &str {
+        required.resource.first().map_or("", String::as_str)
+    }
+
+    pub fn fallback(&self, required: &CapabilityId) -> Fallback {
+        self.namespaces.get(Self::namespace_of(required)).copied().unwrap_or(self.default)
+    }
+
+    /// Hex blake3 of the config's JSON, which is canonical: maps are ordered.
+    pub fn hash(&self) -> String {
+        blake3::hash(&serde_json::to_vec(self).expect("namespace configs serialize")).to_hex().to_string()
+    }
+}
+
+/// Body of an `EntryKind::NamespaceConfig` entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamespaceVersion {
+    pub config_hash: String,
+    /// First tick decided under this config.
+    pub effective_tick: u64,
+    pub config: NamespaceConfig,
+}
+
+impl NamespaceVersion {
+    /// Records the config as an `EntryKind::NamespaceConfig` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::NamespaceConfig, self)
+    }
+
+    pub fn of(config: &NamespaceConfig, effective_tick: u64) -> Self {
+        Self { config_hash: config.hash(), effective_tick, config: config.clone() }
+    }
+
+    /// The config last recorded in `ledger`.
+    pub fn latest(ledger: &DeterministicStore) -> LedgerResult<Option<Self>> {
+        let mut latest = None;
+        let mut unreadable = None;
+        ledger.for_each_entry(|index, payload| {
+            let Some((EntryKind::NamespaceConfig, body)) = entry::decode(payload) else { return };
+            match serde_json::from_slice::<NamespaceVersion>(body) {
+                Ok(version) => latest = Some(version),
+                Err(_) => unreadable = unreadable.or(Some(index)),
+            }
+        })?;
+        match unreadable {
+            Some(index) => Err(LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("unreadable namespace config entry at index {}", index)))),
+            None => Ok(latest),
+        }
+    }
+}
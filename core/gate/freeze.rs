@@ -0,0 +1,187 @@
+//! Emergency freeze of the Gate.
+//!
+//! `Gate::freeze` is the kill switch: from the moment it returns, every proposal for
+//! an actuating tool (any tool the registry does not mark read-only, or every tool
+//! without a registry) is denied before its policy runs. The freeze is appended to the
+//! ledger as an `EntryKind::Freeze` entry, so a Gate reopened over the ledger is still
+//! frozen, and the cluster learns of it through the sequencer, whose nodes apply the
+//! same event with `Gate::apply_freeze`.
+//!
+//! Lifting a freeze takes an `UnfreezeToken`: an Ed25519 signature by one of the
+//! `FreezeAuthority`'s principals over the freeze's id, recorded with its own entry.
+//! A token only lifts the freeze it names, so it cannot be replayed against a later
+//! one. This is separate from `ledger::freeze::AnchoringFreeze`, which stops
+//! high-risk work when notarization falls behind.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::ledger::entry::{self, EntryKind};
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+
+/// A freeze in force.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Freeze {
+    /// Hex blake3 over the freeze's fields; the same on every node that applies it.
+    pub freeze_id: String,
+    pub reason: String,
+    /// Who pulled the switch.
+    pub principal: String,
+    pub at: u64,
+}
+
+impl Freeze {
+    pub fn new(reason: &str, principal: &str, at: u64) -> Self {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"RFSN-FREEZE\0");
+        for field in [reason.as_bytes(), principal.as_bytes()] {
+            hasher.update(&(field.len() as u64).to_le_bytes());
+            hasher.update(field);
+        }
+        hasher.update(&at.to_le_bytes());
+        let freeze_id = hasher.finalize().to_hex().to_string();
+        Self { freeze_id, reason: reason.to_string(), principal: principal.to_string(), at }
+    }
+}
+
+/// Authorization to lift one freeze.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnfreezeToken {
+    pub freeze_id: String,
+    pub principal: String,
+    /// Ed25519 signature by the principal's key over `UnfreezeToken::signing_payload`.
+    #[serde(with = "hex::serde")]
+    pub signature: Vec<u8>,
+}
+
+impl UnfreezeToken {
+    pub fn signing_payload(freeze_id: &str, principal: &str) -> Vec<u8> {
+        let mut out = b"RFSN-UNFREEZE\0".to_vec();
+        for field in [freeze_id.as_bytes(), principal.as_bytes()] {
+            out.extend_from_slice(&(field.len() as u64).to_le_bytes());
+            out.extend_from_slice(field);
+        }
+        out
+    }
+
+    pub fn sign(freeze_id: &str, principal: &str, key: &SigningKey) -> Self {
+        let signature = key.sign(&Self::signing_payload(freeze_id, principal)).to_bytes().to_vec();
+        Self { freeze_id: freeze_id.to_string(), principal: principal.to_string(), signature }
+    }
+}
+
+/// Who may lift a freeze.
+#[derive(Debug, Clone, Default)]
+pub struct FreezeAuthority {
+    pub principals: BTreeMap<String, VerifyingKey>,
+}
+
+impl FreezeAuthority {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn principal(mut self, name: &str, key: VerifyingKey) -> Self {
+        self.principals.insert(name.to_string(), key);
+        self
+    }
+
+    /// Checks that `token` is a valid signature by one of the authority's principals.
+    pub fn verify(&self, token: &UnfreezeToken) -> Result<(), FreezeError> {
+        let key = self.principals.get(&token.principal).ok_or_else(|| FreezeError::UnknownPrincipal { principal: token.principal.clone() })?;
+        let signature = ed25519_dalek::Signature::from_slice(&token.signature).map_err(|_| FreezeError::BadSignature { principal: token.principal.clone() })?;
+        key.verify_strict(&UnfreezeToken::signing_payload(&token.freeze_id, &token.principal), &signature)
+            .map_err(|_| FreezeError::BadSignature { principal: token.principal.clone() })
+    }
+}
+
+/// Body of an `EntryKind::Freeze` entry, and what the sequencer relays.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum FreezeEvent {
+    Frozen { freeze: Freeze },
+    Unfrozen { token: UnfreezeToken, at: u64 },
+}
+
+impl FreezeEvent {
+    /// Appends the freeze or its lifting as an `EntryKind::Freeze` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::Freeze, self)
+    }
+}
+
+#[derive(Debug)]
+pub enum FreezeError {
+    NotFrozen,
+    /// The token names a freeze other than the one in force.
+    WrongFreeze { freeze_id: String },
+    UnknownPrincipal { principal: String },
+    BadSignature { principal: String },
+    /// The Gate has no `FreezeAuthority`, so nothing can lift a freeze.
+    NoAuthority,
+    Ledger(LedgerError),
+}
+
+impl fmt::Display for FreezeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FreezeError::NotFrozen => write!(f, "the gate is not frozen"),
+            FreezeError::WrongFreeze { freeze_id } => write!(f, "freeze {} is not the one in force", freeze_id),
+            FreezeError::UnknownPrincipal { principal } => write!(f, "{} may not lift a freeze", principal),
+            FreezeError::BadSignature { principal } => write!(f, "unfreeze by {} has an invalid signature", principal),
+            FreezeError::NoAuthority => write!(f, "no freeze authority is configured"),
+            FreezeError::Ledger(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for FreezeError {}
+
+impl From<LedgerError> for FreezeError {
+    fn from(e: LedgerError) -> Self {
+        FreezeError::Ledger(e)
+    }
+}
+
+/// The freeze in force after replaying `ledger`, if any.
+pub fn current(ledger: &DeterministicStore) -> LedgerResult<Option<Freeze>> {
+    let mut current = None;
+    let mut unreadable = None;
+    ledger.for_each_entry(|index, payload| {
+        let Some((EntryKind::Freeze, body)) = entry::decode(payload) else { return };
+        match serde_json::from_slice::<FreezeEvent>(body) {
+            Ok(FreezeEvent::Frozen { freeze }) => current = Some(freeze),
+            Ok(FreezeEvent::Unfrozen { token, .. }) => current = current.take().filter(|f: &Freeze| f.freeze_id != token.freeze_id),
+            Err(_) => unreadable = unreadable.or(Some(index)),
+        }
+    })?;
+    match unreadable {
+        Some(index) => Err(LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("unreadable freeze entry at index {}", index)))),
+        None => Ok(current),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_lift_only_the_freeze_they_name() {
+        let key = SigningKey::from_bytes(&[4; 32]);
+        let authority = FreezeAuthority::new().principal("oncall", key.verifying_key());
+        let freeze = Freeze::new("runaway arm", "operator", 10);
+        assert_eq!(freeze, Freeze::new("runaway arm", "operator", 10));
+        assert_ne!(freeze.freeze_id, Freeze::new("runaway arm", "operator", 11).freeze_id);
+
+        let token = UnfreezeToken::sign(&freeze.freeze_id, "oncall", &key);
+        assert!(authority.verify(&token).is_ok());
+        let forged = UnfreezeToken { freeze_id: Freeze::new("other", "operator", 12).freeze_id, ..token.clone() };
+        assert!(matches!(authority.verify(&forged), Err(FreezeError::BadSignature { .. })));
+        let stranger = UnfreezeToken::sign(&freeze.freeze_id, "intern", &key);
+        assert!(matches!(authority.verify(&stranger), Err(FreezeError::UnknownPrincipal { .. })));
+    }
+}
@@ -0,0 +1,146 @@
+//! Outbound hooks on Gate events.
+//!
+//! Hooks registered with `Gate::with_hook` are told of every decision the Gate
+//! records and of every freeze and unfreeze, right after the ledger has it, so
+//! ticketing, chat and monitoring systems can react as it happens. A hook only
+//! observes: it cannot change the outcome, and nothing it does reaches the Gate. The
+//! ledger remains the authoritative record and delivery is best-effort.
+//!
+//! Any `Fn(&GateEvent)` is a hook, for in-process callbacks. `WebhookHook` POSTs each
+//! event as JSON from a thread of its own, so a slow or unreachable endpoint never
+//! holds up a decision; events that arrive while its queue is full are dropped and
+//! counted in its `WebhookStats`.
+
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::Arc;
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+
+use super::decision::{Decision, DenyReason};
+use super::freeze::FreezeEvent;
+use crate::ledger::notary_http::HttpClientConfig;
+
+/// What a hook can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    Allow,
+    Deny,
+    Escalate,
+    Freeze,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 4] = [EventKind::Allow, EventKind::Deny, EventKind::Escalate, EventKind::Freeze];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum GateEvent {
+    Allow { decision: Box<Decision> },
+    Deny { decision: Box<Decision> },
+    /// The policy escalated and the proposal is parked for approvers.
+    Escalate { decision: Box<Decision> },
+    /// A freeze or unfreeze, as recorded.
+    Freeze { change: FreezeEvent },
+}
+
+impl GateEvent {
+    /// The event for a recorded decision.
+    pub fn of(decision: &Decision) -> Self {
+        let decision = Box::new(decision.clone());
+        match &decision.denied {
+            None => GateEvent::Allow { decision },
+            Some(DenyReason::PendingApproval { .. }) => GateEvent::Escalate { decision },
+            Some(_) => GateEvent::Deny { decision },
+        }
+    }
+
+    pub fn kind(&self) -> EventKind {
+        match self {
+            GateEvent::Allow { .. } => EventKind::Allow,
+            GateEvent::Deny { .. } => EventKind::Deny,
+            GateEvent::Escalate { .. } => EventKind::Escalate,
+            GateEvent::Freeze { .. } => EventKind::Freeze,
+        }
+    }
+}
+
+/// Told of the events it subscribed to, on the thread that caused them, so it
+/// should return quickly.
+pub trait GateHook: Send + Sync {
+    fn on_event(&self, event: &GateEvent);
+}
+
+impl<F: Fn(&GateEvent) + Send + Sync> GateHook for F {
+    fn on_event(&self, event: &GateEvent) {
+        self(event)
+    }
+}
+
+/// Delivery counts of a `WebhookHook`.
+#[derive(Debug, Default)]
+pub struct WebhookStats {
+    delivered: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl WebhookStats {
+    /// Events the endpoint accepted with a 2xx.
+    pub fn delivered(&self) -> u64 {
+        self.delivered.load(Ordering::Relaxed)
+    }
+
+    /// Events the endpoint refused or could not be reached for.
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Events dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// POSTs events as JSON to a URL. Each event is tried once; the delivery thread
+/// stops when the hook is dropped.
+pub struct WebhookHook {
+    queue: SyncSender<GateEvent>,
+    stats: Arc<WebhookStats>,
+}
+
+impl WebhookHook {
+    /// Starts delivering to `url` with a client built from `config`. At most `queue`
+    /// events wait for delivery.
+    pub fn new(url: &str, config: &HttpClientConfig, queue: usize) -> Result<Self, Box<dyn Error>> {
+        let client = config.build_blocking()?;
+        let (sender, receiver) = mpsc::sync_channel::<GateEvent>(queue);
+        let stats = Arc::new(WebhookStats::default());
+        let (url, counts) = (url.to_string(), stats.clone());
+        thread::Builder::new().name("rfsn-webhook".into()).spawn(move || {
+            for event in receiver {
+                let accepted = client.post(&url).json(&event).send().is_ok_and(|res| res.status().is_success());
+                let count = if accepted { &counts.delivered } else { &counts.failed };
+                count.fetch_add(1, Ordering::Relaxed);
+            }
+        })?;
+        Ok(Self { queue: sender, stats })
+    }
+
+    /// The hook's counts; keep a handle before registering it with the Gate.
+    pub fn stats(&self) -> Arc<WebhookStats> {
+        self.stats.clone()
+    }
+}
+
+impl GateHook for WebhookHook {
+    fn on_event(&self, event: &GateEvent) {
+        if self.queue.try_send(event.clone()).is_err() {
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
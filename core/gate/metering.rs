@@ -0,0 +1,112 @@
+//! Step metering over decisions.
+//!
+//! Every decision whose policy ran records the steps it took (`Decision::usage`) and
+//! the budget it ran under (`Decision::budget`). A `StepMeter` folds those per policy
+//! version: how many evaluations there were, the most steps any took and how many
+//! were cut off. Meters of several nodes `merge`, so a fleet can see a policy drifting
+//! toward its WCET budget, as the contexts it sees grow, well before evaluations start
+//! to be aborted. Steps are deterministic, so every node meters the same decision
+//! alike.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use super::decision::{Decision, DenyReason};
+use crate::ledger::entry::{self, EntryKind};
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+use crate::wcet::watchdog::ExceededLimit;
+
+/// Step use of one policy version.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StepUse {
+    pub policy: String,
+    pub evaluations: u64,
+    pub total_steps: u64,
+    pub max_steps: u64,
+    /// The step budget the evaluations ran under.
+    pub budget_steps: u64,
+    /// Evaluations cut off at the step budget.
+    pub aborted: u64,
+}
+
+impl StepUse {
+    /// `max_steps` as a percentage of `budget_steps`.
+    pub fn peak_pct(&self) -> f64 {
+        if self.budget_steps == 0 {
+            return 0.0;
+        }
+        self.max_steps as f64 * 100.0 / self.budget_steps as f64
+    }
+
+    pub fn mean_steps(&self) -> f64 {
+        if self.evaluations == 0 {
+            return 0.0;
+        }
+        self.total_steps as f64 / self.evaluations as f64
+    }
+
+    fn merge(&mut self, other: &StepUse) {
+        self.evaluations += other.evaluations;
+        self.total_steps += other.total_steps;
+        self.max_steps = self.max_steps.max(other.max_steps);
+        self.budget_steps = self.budget_steps.max(other.budget_steps);
+        self.aborted += other.aborted;
+    }
+}
+
+/// Step use by policy hash.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StepMeter {
+    pub policies: BTreeMap<String, StepUse>,
+}
+
+impl StepMeter {
+    /// Meters the decisions in `ledger`.
+    pub fn from_ledger(ledger: &DeterministicStore) -> LedgerResult<Self> {
+        let mut meter = Self::default();
+        let mut unreadable = None;
+        ledger.for_each_entry(|at, payload| {
+            let Some((EntryKind::Decision, body)) = entry::decode(payload) else { return };
+            match serde_json::from_slice::<Decision>(body) {
+                Ok(decision) => meter.observe(&decision),
+                Err(_) => unreadable = unreadable.or(Some(at)),
+            }
+        })?;
+        match unreadable {
+            Some(at) => Err(LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("unreadable decision entry at index {}", at)))),
+            None => Ok(meter),
+        }
+    }
+
+    /// Counts `decision` if its policy ran.
+    pub fn observe(&mut self, decision: &Decision) {
+        let Some(budget) = decision.budget else { return };
+        let entry = self.policies.entry(decision.policy_hash.clone()).or_default();
+        entry.policy.clone_from(&decision.policy);
+        entry.merge(&StepUse {
+            policy: String::new(),
+            evaluations: 1,
+            total_steps: decision.usage.steps,
+            max_steps: decision.usage.steps,
+            budget_steps: budget.max_steps,
+            aborted: matches!(decision.denied, Some(DenyReason::Budget { limit: ExceededLimit::Steps })) as u64,
+        });
+    }
+
+    /// Folds in another node's meter.
+    pub fn merge(&mut self, other: &StepMeter) {
+        for (policy_hash, used) in &other.policies {
+            let entry = self.policies.entry(policy_hash.clone()).or_default();
+            entry.policy.clone_from(&used.policy);
+            entry.merge(used);
+        }
+    }
+
+    /// Policy versions whose peak use has reached `pct` percent of their budget, the
+    /// closest first.
+    pub fn near_budget(&self, pct: f64) -> Vec<(&str, &StepUse)> {
+        let mut near: Vec<_> = self.policies.iter().filter(|(_, used)| used.peak_pct() >= pct).map(|(hash, used)| (hash.as_str(), used)).collect();
+        near.sort_by(|a, b| b.1.peak_pct().total_cmp(&a.1.peak_pct()));
+        near
+    }
+}
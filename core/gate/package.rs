@@ -0,0 +1,241 @@
+//! Signed policy packages.
+//!
+//! Policy is the root of control over what agents may do, so in a deployment with a
+//! `TrustRoot` a policy is only loaded from a package signed by one of its keys: a
+//! file write alone cannot swap it. A package is the bytecode plus an Ed25519
+//! signature over its name and payload. The Gate verifies every policy it is given
+//! against the trust root and appends the result, accepted or rejected, to the
+//! ledger as an `EntryKind::PolicyPackage` entry.
+//!
+//! For two-person integrity a trust root can require more than one signer for the
+//! policies of a namespace, the part of a policy's name before its first `/`. Further
+//! signers add a `Cosignature` over the same package; each must be a distinct trusted
+//! signer. The Gate parks a policy short of its signers as pending, recording the
+//! partial verification, until `Gate::cosign_policy` completes it.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use super::policy::LoadedPolicy;
+use crate::ledger::entry::EntryKind;
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+use crate::vm::lint::NondeterministicRead;
+use crate::vm::verify::VerifyError;
+
+const PACKAGE_FORMAT_VERSION: u32 = 1;
+
+/// A policy as distributed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyPackage {
+    pub version: u32,
+    pub name: String,
+    pub signer: String,
+    #[serde(with = "hex::serde")]
+    pub payload: Vec<u8>,
+    /// Ed25519 signature by the signer's key over `PolicyPackage::signing_payload`.
+    #[serde(with = "hex::serde")]
+    pub signature: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cosignatures: Vec<Cosignature>,
+}
+
+/// A further signer's signature over a package, as for the first signer's.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cosignature {
+    pub signer: String,
+    #[serde(with = "hex::serde")]
+    pub signature: Vec<u8>,
+}
+
+impl Cosignature {
+    pub fn sign(name: &str, payload: &[u8], signer: &str, key: &SigningKey) -> Self {
+        let signature = key.sign(&PolicyPackage::signing_payload(PACKAGE_FORMAT_VERSION, name, signer, payload)).to_bytes().to_vec();
+        Self { signer: signer.to_string(), signature }
+    }
+}
+
+/// The namespace of the policy named `name`: what precedes its first `/`, if anything.
+pub fn namespace(name: &str) -> &str {
+    name.split_once('/').map_or("", |(namespace, _)| namespace)
+}
+
+impl PolicyPackage {
+    pub fn signing_payload(version: u32, name: &str, signer: &str, payload: &[u8]) -> Vec<u8> {
+        let mut out = b"RFSN-POLICY-PACKAGE\0".to_vec();
+        out.extend_from_slice(&version.to_le_bytes());
+        for field in [name.as_bytes(), signer.as_bytes(), payload] {
+            out.extend_from_slice(&(field.len() as u64).to_le_bytes());
+            out.extend_from_slice(field);
+        }
+        out
+    }
+
+    pub fn sign(name: &str, payload: Vec<u8>, signer: &str, key: &SigningKey) -> Self {
+        let signature = key.sign(&Self::signing_payload(PACKAGE_FORMAT_VERSION, name, signer, &payload)).to_bytes().to_vec();
+        Self { version: PACKAGE_FORMAT_VERSION, name: name.to_string(), signer: signer.to_string(), payload, signature, cosignatures: Vec::new() }
+    }
+
+    pub fn cosign(mut self, signer: &str, key: &SigningKey) -> Self {
+        self.cosignatures.push(Cosignature::sign(&self.name, &self.payload, signer, key));
+        self
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum PackageError {
+    /// The policy carries no package signature.
+    Unsigned { name: String },
+    UnknownSigner { signer: String },
+    BadSignature { signer: String },
+    UnsupportedVersion(u32),
+    /// Signed, but the bytecode does not verify.
+    Unverified(VerifyError),
+    /// The Gate has no trust root to check a package against.
+    NoTrustRoot,
+    /// Validly signed, but by fewer distinct signers than its namespace requires.
+    AwaitingSignatures { name: String, policy_hash: String, signers: Vec<String>, required: usize },
+    /// No policy with this hash is pending.
+    NotPending { policy_hash: String },
+    /// The policy reads context a provider fills from a node-local source.
+    Nondeterministic { name: String, reads: Vec<NondeterministicRead> },
+    Ledger(LedgerError),
+}
+
+impl fmt::Display for PackageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageError::Unsigned { name } => write!(f, "policy {} is not from a signed package", name),
+            PackageError::UnknownSigner { signer } => write!(f, "{} is not a trusted policy signer", signer),
+            PackageError::BadSignature { signer } => write!(f, "policy package signature by {} is invalid", signer),
+            PackageError::UnsupportedVersion(v) => write!(f, "unsupported policy package version {}", v),
+            PackageError::Unverified(e) => write!(f, "policy bytecode rejected: {}", e),
+            PackageError::NoTrustRoot => write!(f, "no policy trust root is configured"),
+            PackageError::AwaitingSignatures { name, signers, required, .. } => {
+                write!(f, "policy {} is signed by {} of the {} signers it requires", name, signers.len(), required)
+            }
+            PackageError::NotPending { policy_hash } => write!(f, "no pending policy {}", policy_hash),
+            PackageError::Nondeterministic { name, reads } => {
+                let reads: Vec<String> = reads.iter().map(ToString::to_string).collect();
+                write!(f, "policy {} is not deterministic: {}", name, reads.join("; "))
+            }
+            PackageError::Ledger(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PackageError {}
+
+impl From<LedgerError> for PackageError {
+    fn from(e: LedgerError) -> Self {
+        PackageError::Ledger(e)
+    }
+}
+
+/// The keys allowed to sign policies.
+#[derive(Debug, Clone, Default)]
+pub struct TrustRoot {
+    pub signers: BTreeMap<String, VerifyingKey>,
+    /// Distinct signers required by namespace; one where unset.
+    pub required: BTreeMap<String, usize>,
+}
+
+impl TrustRoot {
+    pub fn signer(mut self, name: &str, key: VerifyingKey) -> Self {
+        self.signers.insert(name.to_string(), key);
+        self
+    }
+
+    /// Requires `count` distinct signers for policies in `namespace`.
+    pub fn require_signers(mut self, namespace: &str, count: usize) -> Self {
+        self.required.insert(namespace.to_string(), count);
+        self
+    }
+
+    pub fn required_signers(&self, name: &str) -> usize {
+        self.required.get(namespace(name)).copied().unwrap_or(1)
+    }
+
+    fn check_signature(&self, version: u32, name: &str, signer: &str, payload: &[u8], signature: &[u8]) -> Result<(), PackageError> {
+        if version != PACKAGE_FORMAT_VERSION {
+            return Err(PackageError::UnsupportedVersion(version));
+        }
+        let key = self.signers.get(signer).ok_or_else(|| PackageError::UnknownSigner { signer: signer.to_string() })?;
+        let bad = || PackageError::BadSignature { signer: signer.to_string() };
+        let signature = ed25519_dalek::Signature::from_slice(signature).map_err(|_| bad())?;
+        key.verify_strict(&PolicyPackage::signing_payload(version, name, signer, payload), &signature).map_err(|_| bad())
+    }
+
+    /// Verifies `package`'s signatures, then its bytecode. Whether it has all the
+    /// signers it needs is left to `check`.
+    pub fn open(&self, package: &PolicyPackage) -> Result<LoadedPolicy, PackageError> {
+        self.check_signature(package.version, &package.name, &package.signer, &package.payload, &package.signature)?;
+        for cosignature in &package.cosignatures {
+            self.check_signature(package.version, &package.name, &cosignature.signer, &package.payload, &cosignature.signature)?;
+        }
+        let policy = LoadedPolicy::load(&package.name, package.payload.clone()).map_err(PackageError::Unverified)?;
+        Ok(LoadedPolicy { signature: Some(package.signature.clone()), cosignatures: package.cosignatures.clone(), ..policy.signed_by(&package.signer) })
+    }
+
+    /// Checks that `policy` came from a package signed under this root, by as many
+    /// distinct signers as its namespace requires.
+    pub fn check(&self, policy: &LoadedPolicy) -> Result<(), PackageError> {
+        let (Some(signer), Some(signature)) = (&policy.signer, &policy.signature) else {
+            return Err(PackageError::Unsigned { name: policy.name.clone() });
+        };
+        self.check_signature(PACKAGE_FORMAT_VERSION, &policy.name, signer, &policy.payload, signature)?;
+        let mut signers = vec![signer.clone()];
+        for cosignature in &policy.cosignatures {
+            self.check_signature(PACKAGE_FORMAT_VERSION, &policy.name, &cosignature.signer, &policy.payload, &cosignature.signature)?;
+            if !signers.contains(&cosignature.signer) {
+                signers.push(cosignature.signer.clone());
+            }
+        }
+        let required = self.required_signers(&policy.name);
+        if signers.len() < required {
+            return Err(PackageError::AwaitingSignatures { name: policy.name.clone(), policy_hash: policy.hash().to_string(), signers, required });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "kebab-case")]
+pub enum VerificationResult {
+    Accepted,
+    /// Validly signed so far by `signers`, short of the `required` count.
+    Pending { signers: Vec<String>, required: usize },
+    Rejected { reason: String },
+}
+
+/// Body of an `EntryKind::PolicyPackage` entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageVerification {
+    pub name: String,
+    /// Hex blake3 of the payload offered.
+    pub policy_hash: String,
+    pub signer: Option<String>,
+    pub at: u64,
+    pub result: VerificationResult,
+}
+
+impl PackageVerification {
+    /// Records the check as an `EntryKind::PolicyPackage` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::PolicyPackage, self)
+    }
+}
@@ -0,0 +1,1769 @@
+//! The Gate: the single admission point for proposed actions.
+//!
+//! `Gate::admit` takes a `ProposedAction` from an authenticated principal through a
+//! fixed pipeline: canonicalize and hash the proposal, score its risk and build its
+//! context, validate its arguments against the tool registry if there is one, run the
+//! loaded policy in its runtime under the budget its boundedness proof allows, check
+//! the principal's capability, then the rate limits. The first stage that refuses
+//! decides the denial, and later stages are not consulted. Every decision is appended
+//! to the ledger as an `EntryKind::Decision` entry before `admit` returns it, so the
+//! ledger is the authoritative record of what was allowed and why. The Gate never
+//! actuates: the caller does that for an allowed decision and then reports it with
+//! `record_actuation`, so single-use grants are spent and quotas counted.
+//!
+//! A policy that escalates parks the proposal instead, once the capability check has
+//! passed; `Gate::approve` collects the approvals (see `approval`) and releases it
+//! through the capability check and rate limits again once there are enough.
+//!
+//! `Gate::simulate` runs the same stages as a dry run for operators trying out a
+//! proposal or a policy change: nothing is appended to the ledger, no rate limit is
+//! taken and no grant is spent.
+//!
+//! A Gate opened with `Gate::open_trusted` only runs policies from packages signed
+//! under its trust root (see `package`). A policy that still needs signers in its
+//! namespace is parked until `Gate::cosign_policy` supplies them.
+//!
+//! A proposal whose nonce its proposer has already used is denied before anything
+//! else runs (see `replay`).
+//!
+//! A Gate built `with_sessions` only decides proposals made with `admit_session` in a
+//! session opened from an authenticated credential, and records the session's
+//! principal and id with each decision (see `session`). Anything else is denied as
+//! unauthenticated.
+//!
+//! `Gate::freeze` is the kill switch: while frozen, actuating proposals are denied
+//! before anything else runs, until an authorized `unfreeze` (see `freeze`).
+//!
+//! Internal subsystems propose as `subsystem/<name>` principals and are held to
+//! read-only tools (see `subsystem`).
+//!
+//! A denial can be overridden with `Gate::break_glass`, only once the override is
+//! notarized or approved (see `breakglass`).
+//!
+//! Context variables beyond the Gate's own come from the `ContextProvider`s registered
+//! with `with_provider`, evaluated in registration order when the context is built.
+//! `load_policy` refuses a policy that reads a slot whose provider declares a
+//! node-local source, unless the slot was allowed with `allow_nondeterministic`
+//! (see `vm::lint`); `nondeterministic_reads` runs the same check on any policy.
+//!
+//! Repeated proposals for read-only tools can skip the policy run with
+//! `with_decision_cache` (see `cache`).
+//!
+//! External agents submit proposals and receive decisions over the framing in `wire`.
+//!
+//! Hooks registered with `with_hook` are told of each decision and freeze once it is
+//! recorded (see `hooks`).
+//!
+//! A candidate policy loaded with `load_shadow_policy` is run beside the active one
+//! and compared with it, without being enforced (see `shadow`).
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::anomaly::{Anomaly, ModelCheckpoint};
+use super::approval::{self, ApprovalError, ApprovalEvent, ApprovalPolicy, ApprovalToken, PendingApproval};
+use super::breakglass::{self, BreakGlassError, BreakGlassPolicy, OverrideEvent, OverrideOutcome, OverrideToken, PendingOverride, RecordedOverrides};
+use super::cache::DecisionCache;
+use super::context::{var_bytes, Context, ContextProvider, ProviderInput, GATE_VARS, PROPOSAL_OFFSET, VAR_RISK};
+use super::decision::{Decision, DenyReason, Simulation};
+use super::fallback::{Fallback, NamespaceConfig, NamespaceVersion};
+use super::freeze::{self, Freeze, FreezeAuthority, FreezeError, FreezeEvent, UnfreezeToken};
+use super::hooks::{EventKind, GateEvent, GateHook};
+use super::package::{Cosignature, PackageError, PackageVerification, PolicyPackage, TrustRoot, VerificationResult};
+use super::policy::{LoadedPolicy, PolicyVersion};
+use super::proposal::ProposedAction;
+use super::rate::RateLimiter;
+use super::replay::{NonceIndex, ProposerNonce};
+use super::risk::RiskScorer;
+use super::sandbox::ExecutionResult;
+use super::session::{Authenticator, Credential, Session, SessionError, Sessions};
+use super::shadow::{Shadow, ShadowReport};
+use super::subsystem::{self, SubsystemError};
+use super::suite::Outcome;
+use super::tools::{ToolRegistry, ToolsVersion};
+use crate::capability::id::CapabilityId;
+use crate::capability::store::{CapabilityEvent, CapabilityStore, Grant};
+use crate::ledger::entry::ReceiptEntry;
+use crate::ledger::notarize::{AnchorRequest, NotaryClient};
+use crate::ledger::storage::{DeterministicStore, LedgerResult};
+use crate::vm::interp::{Trace, Verdict, REASON_MALFORMED, REASON_NO_DECISION};
+use crate::vm::lint::{lint, Nondeterminism, NondeterministicRead};
+use crate::vm::runtime::NATIVE;
+use crate::vm::snapshot::{Machine, Snapshot};
+use crate::wcet::watchdog::{BudgetExceeded, ExecutionUsage, Watchdog};
+
+pub struct Gate {
+    ledger: DeterministicStore,
+    capabilities: CapabilityStore,
+    policy: LoadedPolicy,
+    rate_limiter: Option<Box<dyn RateLimiter>>,
+    risk: RiskScorer,
+    approvals: Option<ApprovalPolicy>,
+    /// Parked proposals by approval id.
+    pending: BTreeMap<String, PendingApproval>,
+    trust_root: Option<TrustRoot>,
+    /// Policies awaiting cosigners, by policy hash.
+    pending_policies: BTreeMap<String, LoadedPolicy>,
+    tools: Option<ToolRegistry>,
+    namespaces: NamespaceConfig,
+    providers: Vec<Box<dyn ContextProvider>>,
+    /// Provider slots policies may read though their values are node-local.
+    nondeterministic_allowed: BTreeSet<u32>,
+    frozen: Option<Freeze>,
+    freeze_authority: Option<FreezeAuthority>,
+    break_glass: Option<BreakGlassPolicy>,
+    /// Overrides waiting for approvers, by override id.
+    overrides: BTreeMap<String, PendingOverride>,
+    /// Ids of the overrides already notarized or queued.
+    used_overrides: BTreeSet<String>,
+    cache: Option<DecisionCache>,
+    nonces: NonceIndex,
+    require_nonces: bool,
+    /// Each hook with the kinds of event it subscribed to.
+    hooks: Vec<(BTreeSet<EventKind>, Box<dyn GateHook>)>,
+    shadow: Option<Shadow>,
+    sessions: Option<Sessions>,
+    counter_proposals: bool,
+}
+
+/// The ledger's tick source, or its entry count without one, as for checkpoints.
+fn ledger_tick(ledger: &DeterministicStore) -> u64 {
+    ledger.tick_source().map_or(ledger.entry_count(), |ticks| ticks.now())
+}
+
+/// Checks `policy` against `root` and records the result.
+fn verify_package(ledger: &mut DeterministicStore, root: &TrustRoot, policy: &LoadedPolicy) -> Result<(), PackageError> {
+    let checked = root.check(policy);
+    let result = match &checked {
+        Ok(()) => VerificationResult::Accepted,
+        Err(PackageError::AwaitingSignatures { signers, required, .. }) => VerificationResult::Pending { signers: signers.clone(), required: *required },
+        Err(e) => VerificationResult::Rejected { reason: e.to_string() },
+    };
+    let verification = PackageVerification {
+        name: policy.name.clone(),
+        policy_hash: policy.hash().to_string(),
+        signer: policy.signer.clone(),
+        at: ledger_tick(ledger),
+        result,
+    };
+    verification.append_to(ledger)?;
+    checked
+}
+
+impl Gate {
+    /// Opens a Gate over `ledger`, rebuilding the capability store, the parked
+    /// proposals and the queued overrides from it. `policy` is recorded as a new version unless it is the one
+    /// last recorded.
+    pub fn open(mut ledger: DeterministicStore, policy: LoadedPolicy) -> LedgerResult<Self> {
+        let capabilities = CapabilityStore::from_ledger(&ledger)?;
+        let frozen = freeze::current(&ledger)?;
+        let nonces = NonceIndex::from_ledger(&ledger)?;
+        let pending = approval::parked(&ledger)?;
+        let overrides = RecordedOverrides::from_ledger(&ledger)?;
+        let current = PolicyVersion::history(&ledger)?.pop();
+        if current.is_none_or(|v| v.policy_hash != policy.hash() || v.name != policy.name) {
+            let tick = ledger_tick(&ledger);
+            PolicyVersion::of(&policy, tick).append_to(&mut ledger)?;
+        }
+        Ok(Self {
+            ledger,
+            capabilities,
+            policy,
+            rate_limiter: None,
+            risk: RiskScorer::default(),
+            approvals: None,
+            pending,
+            trust_root: None,
+            pending_policies: BTreeMap::new(),
+            tools: None,
+            namespaces: NamespaceConfig::default(),
+            providers: Vec::new(),
+            nondeterministic_allowed: BTreeSet::new(),
+            frozen,
+            freeze_authority: None,
+            break_glass: None,
+            overrides: overrides.queued,
+            used_overrides: overrides.used,
+            cache: None,
+            nonces,
+            require_nonces: false,
+            hooks: Vec::new(),
+            shadow: None,
+            sessions: None,
+            counter_proposals: false,
+        })
+    }
+
+    /// Opens a Gate that only runs policies from packages signed under `root`,
+    /// starting with `policy`.
+    pub fn open_trusted(mut ledger: DeterministicStore, policy: LoadedPolicy, root: TrustRoot) -> Result<Self, PackageError> {
+        verify_package(&mut ledger, &root, &policy)?;
+        let mut gate = Self::open(ledger, policy)?;
+        gate.trust_root = Some(root);
+        Ok(gate)
+    }
+
+    pub fn with_rate_limiter(mut self, limiter: impl RateLimiter + 'static) -> Self {
+        self.rate_limiter = Some(Box::new(limiter));
+        self
+    }
+
+    pub fn with_risk_scorer(mut self, scorer: RiskScorer) -> Self {
+        self.risk = scorer;
+        self
+    }
+
+    /// Adds a provider; a later one for the same slot overrides it.
+    ///
+    /// # Panics
+    /// If the provider's slot is not a header slot or is one of `GATE_VARS`.
+    pub fn with_provider(mut self, provider: impl ContextProvider + 'static) -> Self {
+        let var = provider.var();
+        assert!(var.is_multiple_of(4) && var < PROPOSAL_OFFSET && !GATE_VARS.contains(&var), "context variable {} is not a provider slot", var);
+        self.providers.push(Box::new(provider));
+        self
+    }
+
+    /// Lets policies read `var` even if its provider declares a node-local source, for
+    /// deployments that accept decisions differing between nodes.
+    pub fn allow_nondeterministic(mut self, var: u32) -> Self {
+        self.nondeterministic_allowed.insert(var);
+        self
+    }
+
+    /// Requires every proposal to be made in a session `authenticator` opened.
+    pub fn with_sessions(mut self, authenticator: Authenticator) -> Self {
+        self.sessions = Some(Sessions::new(authenticator));
+        self
+    }
+
+    /// Without approvers, a policy's escalation is a denial. Approvals restored from
+    /// the ledger that `approvals` does not accept are dropped.
+    pub fn with_approvals(mut self, approvals: ApprovalPolicy) -> Self {
+        for pending in self.pending.values_mut() {
+            pending.retain_verified(&approvals);
+        }
+        self.approvals = Some(approvals);
+        self
+    }
+
+    /// Who may lift a freeze; without one, a freeze is permanent.
+    pub fn with_freeze_authority(mut self, authority: FreezeAuthority) -> Self {
+        self.freeze_authority = Some(authority);
+        self
+    }
+
+    /// Who may override denials; without a policy, `break_glass` always fails.
+    pub fn with_break_glass(mut self, policy: BreakGlassPolicy) -> Self {
+        self.break_glass = Some(policy);
+        self
+    }
+
+    /// Denies proposals without a nonce; see `replay`.
+    pub fn with_required_nonces(mut self) -> Self {
+        self.require_nonces = true;
+        self
+    }
+
+    /// Offers a counter-proposal with each policy denial of a tool that has a
+    /// `tools::SaferAlternative`, if a dry run allows it. It carries the next nonce
+    /// after the denied proposal's, if that had one.
+    pub fn with_counter_proposals(mut self) -> Self {
+        self.counter_proposals = true;
+        self
+    }
+
+    /// Caches up to `capacity` allows of read-only tools; see `cache`.
+    pub fn with_decision_cache(mut self, capacity: usize) -> Self {
+        self.cache = Some(DecisionCache::new(capacity));
+        self
+    }
+
+    pub fn decision_cache(&self) -> Option<&DecisionCache> {
+        self.cache.as_ref()
+    }
+
+    /// Tells `hook` of every event of the given kinds, in registration order.
+    pub fn with_hook(mut self, kinds: &[EventKind], hook: impl GateHook + 'static) -> Self {
+        self.hooks.push((kinds.iter().copied().collect(), Box::new(hook)));
+        self
+    }
+
+    fn notify(&self, event: GateEvent) {
+        for (kinds, hook) in &self.hooks {
+            if kinds.contains(&event.kind()) {
+                hook.on_event(&event);
+            }
+        }
+    }
+
+    fn record_decision(&mut self, decision: &Decision) -> LedgerResult<()> {
+        decision.append_to(&mut self.ledger)?;
+        if !self.hooks.is_empty() {
+            self.notify(GateEvent::of(decision));
+        }
+        Ok(())
+    }
+
+    fn record_freeze(&mut self, event: &FreezeEvent) -> LedgerResult<()> {
+        event.append_to(&mut self.ledger)?;
+        self.notify(GateEvent::Freeze { change: event.clone() });
+        Ok(())
+    }
+
+    /// Empties the decision cache, when an input its keys do not cover changes.
+    fn invalidate_cache(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            cache.invalidate();
+        }
+    }
+
+    /// The scorer, to feed it anomalies and cluster state.
+    pub fn risk_mut(&mut self) -> &mut RiskScorer {
+        &mut self.risk
+    }
+
+    pub fn ledger(&self) -> &DeterministicStore {
+        &self.ledger
+    }
+
+    pub fn capabilities(&self) -> &CapabilityStore {
+        &self.capabilities
+    }
+
+    pub fn policy(&self) -> &LoadedPolicy {
+        &self.policy
+    }
+
+    /// Records `policy` as the new version and makes it the active one; decisions
+    /// from the next on run it. Proposals already parked keep the decision, and the
+    /// policy hash, that parked them. A Gate with a trust root first verifies the
+    /// policy's package signatures and refuses it if that fails, or parks it if its
+    /// namespace requires more signers than have signed.
+    pub fn load_policy(&mut self, policy: LoadedPolicy) -> Result<PolicyVersion, PackageError> {
+        if let Some(root) = &self.trust_root {
+            match verify_package(&mut self.ledger, root, &policy) {
+                Err(e @ PackageError::AwaitingSignatures { .. }) => {
+                    self.pending_policies.insert(policy.hash().to_string(), policy);
+                    return Err(e);
+                }
+                checked => checked?,
+            }
+        }
+        self.deterministic(&policy)?;
+        let version = PolicyVersion::of(&policy, self.tick());
+        version.append_to(&mut self.ledger)?;
+        self.policy = policy;
+        self.invalidate_cache();
+        Ok(version)
+    }
+
+    /// Records `tools` unless it is the registry last recorded, and makes it the
+    /// active one. From then on proposals for tools not in it, or with arguments their
+    /// schema does not accept, are denied before the policy runs, and the capability
+    /// and base risk of a proposal are its tool's.
+    pub fn load_tools(&mut self, tools: ToolRegistry) -> LedgerResult<ToolsVersion> {
+        let version = match ToolsVersion::latest(&self.ledger)? {
+            Some(current) if current.registry_hash == tools.hash() => current,
+            _ => {
+                let version = ToolsVersion::of(&tools, self.tick());
+                version.append_to(&mut self.ledger)?;
+                version
+            }
+        };
+        self.tools = Some(tools);
+        self.invalidate_cache();
+        Ok(version)
+    }
+
+    pub fn tools(&self) -> Option<&ToolRegistry> {
+        self.tools.as_ref()
+    }
+
+    /// Records `config` unless it is the config last recorded, and makes it the
+    /// active one: from then on a proposal no policy decides is allowed or denied as
+    /// its namespace falls back.
+    pub fn load_namespaces(&mut self, config: NamespaceConfig) -> LedgerResult<NamespaceVersion> {
+        let version = match NamespaceVersion::latest(&self.ledger)? {
+            Some(current) if current.config_hash == config.hash() => current,
+            _ => {
+                let version = NamespaceVersion::of(&config, self.tick());
+                version.append_to(&mut self.ledger)?;
+                version
+            }
+        };
+        self.namespaces = config;
+        self.invalidate_cache();
+        Ok(version)
+    }
+
+    pub fn namespaces(&self) -> &NamespaceConfig {
+        &self.namespaces
+    }
+
+    /// Runs `candidate` in shadow beside the active policy from the next decision on,
+    /// keeping up to `capacity` divergences, and returns the report of the candidate it
+    /// replaces, if any. A Gate with a trust root refuses a candidate it would not load.
+    pub fn load_shadow_policy(&mut self, candidate: LoadedPolicy, capacity: usize) -> Result<Option<ShadowReport>, PackageError> {
+        if let Some(root) = &self.trust_root {
+            root.check(&candidate)?;
+        }
+        self.deterministic(&candidate)?;
+        Ok(self.shadow.replace(Shadow::new(candidate, capacity)).map(Shadow::into_report))
+    }
+
+    /// Every read `policy` makes of a provider slot with a node-local source that has
+    /// not been allowed. A policy in a runtime that cannot be linted reads them all.
+    pub fn nondeterministic_reads(&self, policy: &LoadedPolicy) -> Vec<NondeterministicRead> {
+        // A later provider for a slot overrides an earlier one.
+        let sources: BTreeMap<u32, Option<Nondeterminism>> = self.providers.iter().map(|p| (p.var(), p.nondeterminism())).collect();
+        let ranges: Vec<_> = sources
+            .into_iter()
+            .filter(|(var, _)| !self.nondeterministic_allowed.contains(var))
+            .filter_map(|(var, source)| Some((var_bytes(var), source?)))
+            .collect();
+        let modules: Vec<(Option<&str>, bool, &[u8])> = if policy.modules.is_empty() {
+            vec![(None, policy.runtime.name() == NATIVE, &policy.payload)]
+        } else {
+            policy.modules.iter().map(|m| (Some(m.name.as_str()), m.runtime.is_none(), m.payload.as_slice())).collect()
+        };
+        let mut reads = Vec::new();
+        for (module, native, payload) in modules {
+            let found = if native {
+                lint(payload, &ranges)
+            } else {
+                ranges.iter().map(|(bytes, source)| NondeterministicRead { module: None, pc: None, bytes: bytes.clone(), source: *source }).collect()
+            };
+            reads.extend(found.into_iter().map(|read| NondeterministicRead { module: module.map(str::to_string), ..read }));
+        }
+        reads
+    }
+
+    fn deterministic(&self, policy: &LoadedPolicy) -> Result<(), PackageError> {
+        let reads = self.nondeterministic_reads(policy);
+        if reads.is_empty() {
+            return Ok(());
+        }
+        Err(PackageError::Nondeterministic { name: policy.name.clone(), reads })
+    }
+
+    pub fn shadow_report(&self) -> Option<&ShadowReport> {
+        self.shadow.as_ref().map(Shadow::report)
+    }
+
+    /// Stops the shadow evaluation and returns its report.
+    pub fn end_shadow(&mut self) -> Option<ShadowReport> {
+        self.shadow.take().map(Shadow::into_report)
+    }
+
+    /// Adds `cosignature` to the pending policy `policy_hash` and loads it if that
+    /// completes its signers; `None` if it is still pending. A cosignature that does
+    /// not verify is refused, and the policy stays pending as it was.
+    pub fn cosign_policy(&mut self, policy_hash: &str, cosignature: Cosignature) -> Result<Option<PolicyVersion>, PackageError> {
+        let pending = self.pending_policies.remove(policy_hash).ok_or_else(|| PackageError::NotPending { policy_hash: policy_hash.to_string() })?;
+        let mut policy = pending.clone();
+        policy.cosignatures.push(cosignature);
+        match self.load_policy(policy) {
+            Ok(version) => Ok(Some(version)),
+            Err(PackageError::AwaitingSignatures { .. }) => Ok(None),
+            Err(e) => {
+                self.pending_policies.insert(policy_hash.to_string(), pending);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn pending_policies(&self) -> &BTreeMap<String, LoadedPolicy> {
+        &self.pending_policies
+    }
+
+    /// Verifies `package` against the trust root and loads it. A package whose
+    /// bytecode does not verify is refused, and the refusal recorded, too.
+    pub fn load_package(&mut self, package: &PolicyPackage) -> Result<PolicyVersion, PackageError> {
+        let root = self.trust_root.as_ref().ok_or(PackageError::NoTrustRoot)?;
+        match root.open(package) {
+            Ok(policy) => self.load_policy(policy),
+            Err(e) => {
+                let verification = PackageVerification {
+                    name: package.name.clone(),
+                    policy_hash: blake3::hash(&package.payload).to_hex().to_string(),
+                    signer: Some(package.signer.clone()),
+                    at: self.tick(),
+                    result: VerificationResult::Rejected { reason: e.to_string() },
+                };
+                verification.append_to(&mut self.ledger)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// The ledger's tick source, or its entry count without one, as for checkpoints.
+    pub fn tick(&self) -> u64 {
+        ledger_tick(&self.ledger)
+    }
+
+    pub fn issue(&mut self, grant: Grant) -> LedgerResult<()> {
+        self.invalidate_cache();
+        self.capabilities.issue(&mut self.ledger, grant)
+    }
+
+    /// Revokes `grant_id` from the current tick on.
+    pub fn revoke(&mut self, grant_id: &str, reason: &str) -> LedgerResult<()> {
+        let tick = self.tick();
+        self.invalidate_cache();
+        self.capabilities.revoke(&mut self.ledger, grant_id, tick, reason)
+    }
+
+    /// Delegates `grant`, an attenuation of `parent_id` held by `delegator`, to the
+    /// subsystem it names, once the tool registry shows it covers only read-only
+    /// tools. Recorded like any delegation; a grant to a principal that is not a
+    /// subsystem, or one covering an actuating tool, is refused before it is.
+    pub fn delegate_to_subsystem(&mut self, parent_id: &str, delegator: &str, grant: Grant) -> Result<Grant, SubsystemError> {
+        if !subsystem::is_subsystem(&grant.principal) {
+            return Err(SubsystemError::NotASubsystem { principal: grant.principal });
+        }
+        let tools = self.tools.as_ref().ok_or(SubsystemError::NoToolRegistry)?;
+        let actuating = subsystem::actuating_tools(tools, &grant.capability);
+        if !actuating.is_empty() {
+            return Err(SubsystemError::Actuating { capability: grant.capability, tools: actuating });
+        }
+        let grant = self.capabilities.delegate(&mut self.ledger, parent_id, delegator, grant)?;
+        self.invalidate_cache();
+        Ok(grant)
+    }
+
+    /// Decides on `proposal`, submitted by `principal`, and records the decision.
+    /// Capability checks and budget violations are recorded as they happen; a ledger
+    /// error is returned instead of the decision, so no decision goes unrecorded. A
+    /// Gate that requires sessions denies it; see `admit_session`.
+    pub fn admit(&mut self, principal: &str, proposal: &ProposedAction) -> LedgerResult<Decision> {
+        if self.sessions.is_some() {
+            return self.unauthenticated(None, proposal);
+        }
+        self.admit_as(principal, None, proposal)
+    }
+
+    /// Authenticates `credential` and opens the session it grants.
+    pub fn open_session(&mut self, credential: &Credential) -> Result<Session, SessionError> {
+        let tick = self.tick();
+        let sessions = self.sessions.as_mut().ok_or(SessionError::NotRequired)?;
+        let session = sessions.authenticator.authenticate(credential, tick)?;
+        sessions.open.insert(session.session_id.clone(), session.clone());
+        Ok(session)
+    }
+
+    /// Closes `session_id`; whether it was open.
+    pub fn close_session(&mut self, session_id: &str) -> bool {
+        self.sessions.as_mut().is_some_and(|sessions| sessions.open.remove(session_id).is_some())
+    }
+
+    /// `admit` for the principal of the open session `session_id`.
+    pub fn admit_session(&mut self, session_id: &str, proposal: &ProposedAction) -> LedgerResult<Decision> {
+        let tick = self.tick();
+        match self.sessions.as_ref().and_then(|sessions| sessions.resolve(session_id, tick)) {
+            Some(session) => {
+                let principal = session.principal.clone();
+                self.admit_as(&principal, Some(session_id), proposal)
+            }
+            None => self.unauthenticated(Some(session_id), proposal),
+        }
+    }
+
+    /// Records the denial of a proposal made outside an open session.
+    fn unauthenticated(&mut self, session: Option<&str>, proposal: &ProposedAction) -> LedgerResult<Decision> {
+        let (_, mut decision) = self.prepare("", proposal);
+        decision.session = session.map(str::to_string);
+        decision.denied = Some(DenyReason::Unauthenticated);
+        self.record_decision(&decision)?;
+        Ok(decision)
+    }
+
+    fn admit_as(&mut self, principal: &str, session: Option<&str>, proposal: &ProposedAction) -> LedgerResult<Decision> {
+        let (context, mut decision) = self.prepare(principal, proposal);
+        decision.session = session.map(str::to_string);
+        decision.denied = match self.replayed(principal, proposal) {
+            Some(replayed) => Some(replayed),
+            None => {
+                // Used up whatever the outcome, so a denied proposal cannot be retried.
+                if let Some(nonce) = proposal.nonce {
+                    self.nonces.record(principal, nonce);
+                    decision.nonce = Some(ProposerNonce { principal: principal.to_string(), nonce });
+                }
+                self.evaluate(principal, proposal, &context, &mut decision)?
+            }
+        };
+        decision.counter = self.counter_proposal(principal, proposal, &decision).map(|counter| hex::encode(counter.canonical()));
+        self.record_decision(&decision)?;
+        if let Some(DenyReason::PendingApproval { approval_id, code }) = &decision.denied {
+            let capability = self.required_capability(proposal).expect("parked proposals have a valid capability");
+            let parked = ApprovalEvent::Parked {
+                approval_id: approval_id.clone(),
+                principal: principal.to_string(),
+                proposal_hash: decision.proposal_hash.clone(),
+                reason: *code,
+                at: decision.tick,
+                proposal: Some(hex::encode(proposal.canonical())),
+                capability: Some(capability.clone()),
+                snapshot: self.escalation_snapshot(&context).map(|snapshot| hex::encode(snapshot.to_bytes())),
+            };
+            parked.append_to(&mut self.ledger)?;
+            let pending = PendingApproval {
+                principal: principal.to_string(),
+                proposal: proposal.clone(),
+                capability,
+                decision: decision.clone(),
+                approvers: Default::default(),
+            };
+            self.pending.insert(approval_id.clone(), pending);
+        }
+        Ok(decision)
+    }
+
+    /// What to offer instead of `proposal`, if `decision` is a policy denial, not a
+    /// fault, and the safer alternative would be allowed.
+    fn counter_proposal(&self, principal: &str, proposal: &ProposedAction, decision: &Decision) -> Option<ProposedAction> {
+        let Some(DenyReason::Policy { code }) = decision.denied else { return None };
+        if !self.counter_proposals || code >= REASON_MALFORMED {
+            return None;
+        }
+        let mut counter = self.tools.as_ref()?.counter_proposal(proposal)?;
+        counter.nonce = proposal.nonce.map(|nonce| nonce + 1);
+        self.simulate(principal, &counter).decision.is_allowed().then_some(counter)
+    }
+
+    /// The final state of the active policy's run against `context`, if it is native
+    /// bytecode; rerun rather than kept from `evaluate`, as only escalations need it.
+    fn escalation_snapshot(&self, context: &Context) -> Option<Snapshot> {
+        if self.policy.runtime.name() != NATIVE || !self.policy.modules.is_empty() {
+            return None;
+        }
+        let mut machine = Machine::new(&self.policy.payload, context.bytes());
+        let mut watchdog = Watchdog::new(&self.policy.name, self.policy.proof.execution_budget());
+        machine.run(&mut watchdog, None).ok()?;
+        Some(machine.snapshot())
+    }
+
+    /// Denies every actuating proposal from now on, and returns the event to relay to
+    /// the cluster. A Gate already frozen stays under the freeze in force.
+    pub fn freeze(&mut self, reason: &str, principal: &str) -> LedgerResult<FreezeEvent> {
+        if let Some(freeze) = &self.frozen {
+            return Ok(FreezeEvent::Frozen { freeze: freeze.clone() });
+        }
+        let freeze = Freeze::new(reason, principal, self.tick());
+        let event = FreezeEvent::Frozen { freeze: freeze.clone() };
+        self.record_freeze(&event)?;
+        self.frozen = Some(freeze);
+        Ok(event)
+    }
+
+    /// Lifts the freeze `token` names, and returns the event to relay to the cluster.
+    pub fn unfreeze(&mut self, token: &UnfreezeToken) -> Result<FreezeEvent, FreezeError> {
+        let event = FreezeEvent::Unfrozen { token: token.clone(), at: self.tick() };
+        match self.apply_freeze(&event)? {
+            true => Ok(event),
+            false => Err(FreezeError::NotFrozen),
+        }
+    }
+
+    /// Applies a freeze event relayed from the cluster; returns whether it changed
+    /// anything. Events already applied are ignored, so relays may repeat.
+    pub fn apply_freeze(&mut self, event: &FreezeEvent) -> Result<bool, FreezeError> {
+        match event {
+            FreezeEvent::Frozen { freeze } if self.frozen.as_ref().is_some_and(|f| f.freeze_id == freeze.freeze_id) => return Ok(false),
+            FreezeEvent::Frozen { freeze } => {
+                self.record_freeze(event)?;
+                self.frozen = Some(freeze.clone());
+            }
+            FreezeEvent::Unfrozen { token, .. } => {
+                let Some(frozen) = &self.frozen else { return Ok(false) };
+                if frozen.freeze_id != token.freeze_id {
+                    return Err(FreezeError::WrongFreeze { freeze_id: token.freeze_id.clone() });
+                }
+                self.freeze_authority.as_ref().ok_or(FreezeError::NoAuthority)?.verify(token)?;
+                self.record_freeze(event)?;
+                self.frozen = None;
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn frozen(&self) -> Option<&Freeze> {
+        self.frozen.as_ref()
+    }
+
+    /// The freeze that stops `tool`, unless the registry marks it read-only.
+    pub fn freeze_for(&self, tool: &str) -> Option<&Freeze> {
+        let read_only = self.tools.as_ref().and_then(|tools| tools.tools.get(tool)).is_some_and(|spec| spec.read_only);
+        self.frozen.as_ref().filter(|_| !read_only)
+    }
+
+    /// Why `proposal` is a replay by `principal`, if it is one.
+    fn replayed(&self, principal: &str, proposal: &ProposedAction) -> Option<DenyReason> {
+        match proposal.nonce {
+            None => self.require_nonces.then_some(DenyReason::MissingNonce),
+            Some(nonce) if self.nonces.is_fresh(principal, nonce) => None,
+            Some(nonce) => Some(DenyReason::Replayed { nonce, last: self.nonces.last(principal).unwrap_or_default() }),
+        }
+    }
+
+    fn frozen_out(&self, tool: &str) -> Option<DenyReason> {
+        self.freeze_for(tool).map(|freeze| DenyReason::Frozen { freeze_id: freeze.freeze_id.clone() })
+    }
+
+    /// Denies a subsystem's proposal unless the registry marks its tool read-only.
+    fn subsystem_actuation(&self, principal: &str, tool: &str) -> Option<DenyReason> {
+        let read_only = self.tools.as_ref().and_then(|tools| tools.tools.get(tool)).is_some_and(|spec| spec.read_only);
+        (subsystem::is_subsystem(principal) && !read_only).then(|| DenyReason::SubsystemActuation { tool: tool.to_string() })
+    }
+
+    /// Proposals awaiting approval, by approval id.
+    pub fn pending(&self) -> &BTreeMap<String, PendingApproval> {
+        &self.pending
+    }
+
+    /// Records `token` towards the proposal parked under its approval id. Once enough
+    /// approvers have signed, the proposal is released: its capability is checked
+    /// again at the current tick and rate limits are taken, and the resulting decision
+    /// is recorded and returned. Until then, returns `None`.
+    pub fn approve(&mut self, token: &ApprovalToken) -> Result<Option<Decision>, ApprovalError> {
+        let policy = self.approvals.as_ref().ok_or(ApprovalError::NoApprovers)?;
+        let required = policy.required;
+        let pending = self.pending.get(&token.approval_id).ok_or_else(|| ApprovalError::UnknownApproval { approval_id: token.approval_id.clone() })?;
+        policy.verify(token)?;
+        if pending.approvers.contains_key(&token.approver) {
+            return Err(ApprovalError::AlreadyApproved { approver: token.approver.clone() });
+        }
+        let tick = self.tick();
+        ApprovalEvent::Approved { token: token.clone(), at: tick }.append_to(&mut self.ledger)?;
+        let pending = self.pending.get_mut(&token.approval_id).expect("checked above");
+        pending.approvers.insert(token.approver.clone(), token.clone());
+        if pending.approvers.len() < required {
+            return Ok(None);
+        }
+
+        let pending = self.pending.remove(&token.approval_id).expect("checked above");
+        let approvers: Vec<String> = pending.approvers.into_keys().collect();
+        let tick = self.tick();
+        let released = ApprovalEvent::Released { approval_id: token.approval_id.clone(), approvers: approvers.clone(), at: tick };
+        released.append_to(&mut self.ledger)?;
+        let mut decision = Decision { tick, approvers, capability: None, ..pending.decision };
+        decision.denied = match self.frozen_out(&pending.proposal.tool_name) {
+            Some(frozen) => Some(frozen),
+            None => match self.check_capability(&pending.principal, &pending.capability, &mut decision)? {
+                Some(denied) => Some(denied),
+                None => self.take_rate_limit(&pending.proposal.tool_name, &pending.capability, tick),
+            },
+        };
+        self.record_decision(&decision)?;
+        Ok(Some(decision))
+    }
+
+    /// Overrides the denial recorded under `token`'s decision id on its authority. The
+    /// override is recorded, then the ledger head covering it is notarized through
+    /// `notary`; if the quorum anchors it, the receipts and an allowing decision are
+    /// recorded and the decision returned. Otherwise the override is queued for the
+    /// approvers, or refused if there are none. A token already notarized or queued is
+    /// refused.
+    pub fn break_glass(&mut self, token: &OverrideToken, notary: &NotaryClient) -> Result<OverrideOutcome, BreakGlassError> {
+        let policy = self.break_glass.as_ref().ok_or(BreakGlassError::Disabled)?;
+        policy.verify(token)?;
+        let override_id = token.decision_id.clone();
+        if self.used_overrides.contains(&override_id) {
+            return Err(BreakGlassError::AlreadyUsed { override_id });
+        }
+        let denied = breakglass::recorded_decision(&self.ledger, &override_id, self.ledger.entry_count())?
+            .ok_or_else(|| BreakGlassError::UnknownDecision { decision_id: override_id.clone() })?;
+        let Some(reason) = &denied.denied else { return Err(BreakGlassError::NotDenied) };
+        let tick = self.tick();
+        let requested = OverrideEvent::Requested { token: token.clone(), proposal_hash: denied.proposal_hash.clone(), denied: reason.clone(), at: tick };
+        requested.append_to(&mut self.ledger)?;
+
+        let tree = self.ledger.tree();
+        let request = AnchorRequest { ledger_head_hash: hex::encode(tree.root()), index: tree.size(), timestamp_ticks: tick };
+        let status = notary.collect_receipts(&request);
+        if status.anchored() {
+            ReceiptEntry::from_status(&status).append_to(&mut self.ledger)?;
+            let tick = self.tick();
+            OverrideEvent::Notarized { override_id: override_id.clone(), index: request.index, at: tick }.append_to(&mut self.ledger)?;
+            self.used_overrides.insert(override_id);
+            let decision = Decision { tick, denied: None, overridden_by: Some(token.principal.clone()), counter: None, ..denied };
+            self.record_decision(&decision)?;
+            return Ok(OverrideOutcome::Allowed(Box::new(decision)));
+        }
+        if self.approvals.is_none() {
+            return Err(BreakGlassError::NotNotarized { receipts: status.receipts.len(), threshold: status.threshold });
+        }
+        OverrideEvent::Queued { override_id: override_id.clone(), at: self.tick() }.append_to(&mut self.ledger)?;
+        self.used_overrides.insert(override_id.clone());
+        let pending = PendingOverride { token: token.clone(), decision: denied, approvers: Default::default() };
+        self.overrides.insert(override_id.clone(), pending);
+        Ok(OverrideOutcome::Queued { override_id })
+    }
+
+    /// Overrides awaiting approval, by override id.
+    pub fn queued_overrides(&self) -> &BTreeMap<String, PendingOverride> {
+        &self.overrides
+    }
+
+    /// Records `token`, whose approval id is an override id, towards a queued
+    /// override. Once enough approvers have signed, an allowing decision is recorded
+    /// and returned; until then, returns `None`.
+    pub fn approve_override(&mut self, token: &ApprovalToken) -> Result<Option<Decision>, BreakGlassError> {
+        let policy = self.approvals.as_ref().ok_or(ApprovalError::NoApprovers)?;
+        let required = policy.required;
+        let pending = self.overrides.get(&token.approval_id).ok_or_else(|| BreakGlassError::UnknownOverride { override_id: token.approval_id.clone() })?;
+        policy.verify(token)?;
+        if pending.approvers.contains(&token.approver) {
+            return Err(ApprovalError::AlreadyApproved { approver: token.approver.clone() }.into());
+        }
+        OverrideEvent::Approved { token: token.clone(), at: self.tick() }.append_to(&mut self.ledger)?;
+        let pending = self.overrides.get_mut(&token.approval_id).expect("checked above");
+        pending.approvers.insert(token.approver.clone());
+        if pending.approvers.len() < required {
+            return Ok(None);
+        }
+
+        let pending = self.overrides.remove(&token.approval_id).expect("checked above");
+        let approvers: Vec<String> = pending.approvers.into_iter().collect();
+        let tick = self.tick();
+        let released = OverrideEvent::Released { override_id: token.approval_id.clone(), approvers: approvers.clone(), at: tick };
+        released.append_to(&mut self.ledger)?;
+        let decision = Decision { tick, denied: None, approvers, overridden_by: Some(pending.token.principal), counter: None, ..pending.decision };
+        self.record_decision(&decision)?;
+        Ok(Some(decision))
+    }
+
+    /// Decides on `proposal` as `admit` would at the current tick, without recording
+    /// anything or taking any limit.
+    pub fn simulate(&self, principal: &str, proposal: &ProposedAction) -> Simulation {
+        let (context, decision) = self.prepare(principal, proposal);
+        let mut simulation = Simulation { decision, policy_verdict: None };
+        simulation.decision.denied = self.dry_run(principal, proposal, &context, &mut simulation);
+        simulation
+    }
+
+    /// The proposal's context at the current tick, and an allowing decision to fill in.
+    fn prepare(&self, principal: &str, proposal: &ProposedAction) -> (Context, Decision) {
+        let tick = self.tick();
+        let risk = match self.tools.as_ref().and_then(|tools| tools.tools.get(&proposal.tool_name)) {
+            Some(spec) => self.risk.score_from(spec.risk.weight(), proposal, tick),
+            None => self.risk.score(proposal, tick),
+        };
+        let mut context = Context::new(proposal, tick);
+        context.set(VAR_RISK, risk);
+        let input = ProviderInput { tick, ledger: &self.ledger, risk: &self.risk };
+        for provider in &self.providers {
+            context.set(provider.var(), provider.value(&input));
+        }
+        let decision = Decision {
+            principal: principal.to_string(),
+            session: None,
+            proposal_hash: proposal.hash(),
+            tool: proposal.tool_name.clone(),
+            anomaly: proposal.anomaly.clone(),
+            context_hash: context.hash(),
+            policy: self.policy.name.clone(),
+            policy_hash: self.policy.hash().to_string(),
+            tick,
+            risk,
+            denied: None,
+            usage: ExecutionUsage::default(),
+            budget: None,
+            fallback: None,
+            capability: None,
+            approvers: Vec::new(),
+            overridden_by: None,
+            counter: None,
+            nonce: None,
+            policy_trace: None,
+        };
+        (context, decision)
+    }
+
+    /// Runs the loaded policy against `context` under its proven budget.
+    // `BudgetExceeded` is returned unboxed, as by `Watchdog::step`.
+    #[allow(clippy::result_large_err)]
+    fn run_policy(&self, context: &Context, decision: &mut Decision) -> Result<Verdict, BudgetExceeded> {
+        // Sized before the watchdog starts, so tracing charges nothing to the policy.
+        let mut trace = Trace::with_capacity(self.policy.proof.instructions);
+        let budget = self.policy.proof.execution_budget();
+        let mut watchdog = Watchdog::new(&self.policy.name, budget).at_tick(decision.tick);
+        let verdict = self.policy.runtime.decide_traced(&self.policy.payload, context.bytes(), &mut watchdog, &mut trace);
+        decision.usage = watchdog.usage();
+        decision.budget = Some(budget);
+        decision.policy_trace = Some(trace);
+        verdict
+    }
+
+    /// The capability `proposal` needs: its tool's if there is a registry, once its
+    /// arguments validate, else the one it names.
+    fn required_capability(&self, proposal: &ProposedAction) -> Result<CapabilityId, DenyReason> {
+        match &self.tools {
+            Some(tools) => tools.validate(proposal).map(|spec| spec.capability.clone()).map_err(|violation| DenyReason::InvalidArgs { violation }),
+            None => proposal.capability().map_err(|_| DenyReason::MalformedCapability { capability: proposal.capability_required.clone() }),
+        }
+    }
+
+    fn evaluate(
+        &mut self,
+        principal: &str,
+        proposal: &ProposedAction,
+        context: &Context,
+        decision: &mut Decision,
+    ) -> LedgerResult<Option<DenyReason>> {
+        if let Some(frozen) = self.frozen_out(&proposal.tool_name) {
+            return Ok(Some(frozen));
+        }
+        let required = match self.required_capability(proposal) {
+            Ok(required) => required,
+            Err(denied) => return Ok(Some(denied)),
+        };
+        if let Some(denied) = self.subsystem_actuation(principal, &proposal.tool_name) {
+            return Ok(Some(denied));
+        }
+        let read_only = self.tools.as_ref().and_then(|tools| tools.tools.get(&proposal.tool_name)).is_some_and(|spec| spec.read_only);
+        let cache = self.cache.as_mut().filter(|_| read_only);
+        if let Some(cached) = cache.and_then(|cache| cache.get(&self.policy, principal, context, decision)) {
+            CapabilityEvent::Checked { check: cached.capability.clone() }.append_to(&mut self.ledger)?;
+            decision.usage = cached.usage;
+            decision.budget = Some(self.policy.proof.execution_budget());
+            decision.policy_trace = cached.policy_trace;
+            decision.capability = Some(cached.capability);
+            if let Some(shadow) = &mut self.shadow {
+                shadow.observe(principal, &proposal.tool_name, context.bytes(), decision, Outcome::Verdict(Verdict::Allow));
+            }
+            return Ok(self.take_rate_limit(&proposal.tool_name, &required, decision.tick));
+        }
+
+        let verdict = self.run_policy(context, decision);
+        if let Some(shadow) = &mut self.shadow {
+            let active = match &verdict {
+                Ok(verdict) => Outcome::Verdict(*verdict),
+                Err(violation) => Outcome::Budget(violation.limit),
+            };
+            shadow.observe(principal, &proposal.tool_name, context.bytes(), decision, active);
+        }
+        let escalated = match verdict.map(|verdict| self.fall_back(&required, verdict, decision)) {
+            Ok(Verdict::Allow) => None,
+            Ok(Verdict::Deny { reason }) => return Ok(Some(DenyReason::Policy { code: reason })),
+            Ok(Verdict::Escalate { reason }) if self.approvals.is_none() => return Ok(Some(DenyReason::Policy { code: reason })),
+            Ok(Verdict::Escalate { reason }) => Some(reason),
+            Err(violation) => {
+                violation.append_to(&mut self.ledger)?;
+                return Ok(Some(DenyReason::Budget { limit: violation.limit }));
+            }
+        };
+
+        if let Some(denied) = self.check_capability(principal, &required, decision)? {
+            return Ok(Some(denied));
+        }
+        if let Some(reason) = escalated {
+            return Ok(Some(self.park(principal, &decision.context_hash, reason)));
+        }
+        // A fallback decision is recorded as one, so is not served from the cache.
+        if let Some(cache) = self.cache.as_mut().filter(|_| read_only && decision.fallback.is_none()) {
+            cache.insert(&self.policy, principal, context, decision);
+        }
+        Ok(self.take_rate_limit(&proposal.tool_name, &required, decision.tick))
+    }
+
+    /// Where an escalation with `reason` parks: under its approval id, unless an
+    /// identical proposal by `principal` already waits there.
+    fn park(&self, principal: &str, context_hash: &str, reason: u16) -> DenyReason {
+        let approval_id = approval::approval_id(principal, context_hash);
+        if self.pending.contains_key(&approval_id) {
+            return DenyReason::AlreadyPending { approval_id };
+        }
+        DenyReason::PendingApproval { approval_id, code: reason }
+    }
+
+    /// `verdict`, or what its namespace falls back to if the policy reached no
+    /// decision; see `fallback`.
+    fn fall_back(&self, required: &CapabilityId, verdict: Verdict, decision: &mut Decision) -> Verdict {
+        if verdict != (Verdict::Deny { reason: REASON_NO_DECISION }) {
+            return verdict;
+        }
+        let fallback = self.namespaces.fallback(required);
+        decision.fallback = Some(fallback);
+        match fallback {
+            Fallback::DefaultDeny => verdict,
+            Fallback::DefaultAllow => Verdict::Allow,
+        }
+    }
+
+    /// Checks and records `principal`'s capability at the decision's tick.
+    fn check_capability(&mut self, principal: &str, required: &CapabilityId, decision: &mut Decision) -> LedgerResult<Option<DenyReason>> {
+        let check = self.capabilities.check(&mut self.ledger, principal, required, decision.tick)?;
+        let outcome = check.outcome.clone();
+        let granted = check.is_granted();
+        decision.capability = Some(check);
+        Ok((!granted).then_some(DenyReason::Capability { outcome }))
+    }
+
+    fn take_rate_limit(&mut self, tool: &str, required: &CapabilityId, tick: u64) -> Option<DenyReason> {
+        let limiter = self.rate_limiter.as_mut()?;
+        limiter.acquire(tool, required, tick).err().map(|limited| DenyReason::RateLimited { key: limited.key })
+    }
+
+    /// `evaluate` without effects.
+    fn dry_run(&self, principal: &str, proposal: &ProposedAction, context: &Context, simulation: &mut Simulation) -> Option<DenyReason> {
+        if let Some(replayed) = self.replayed(principal, proposal) {
+            return Some(replayed);
+        }
+        if let Some(frozen) = self.frozen_out(&proposal.tool_name) {
+            return Some(frozen);
+        }
+        let required = match self.required_capability(proposal) {
+            Ok(required) => required,
+            Err(denied) => return Some(denied),
+        };
+        if let Some(denied) = self.subsystem_actuation(principal, &proposal.tool_name) {
+            return Some(denied);
+        }
+
+        match self.run_policy(context, &mut simulation.decision) {
+            Ok(verdict) => simulation.policy_verdict = Some(verdict),
+            Err(violation) => return Some(DenyReason::Budget { limit: violation.limit }),
+        }
+        let escalated = match simulation.policy_verdict.map(|verdict| self.fall_back(&required, verdict, &mut simulation.decision)) {
+            Some(Verdict::Deny { reason }) => return Some(DenyReason::Policy { code: reason }),
+            Some(Verdict::Escalate { reason }) if self.approvals.is_none() => return Some(DenyReason::Policy { code: reason }),
+            Some(Verdict::Escalate { reason }) => Some(reason),
+            _ => None,
+        };
+
+        let check = self.capabilities.answer(principal, &required, simulation.decision.tick);
+        let outcome = check.outcome.clone();
+        let granted = check.is_granted();
+        simulation.decision.capability = Some(check);
+        if !granted {
+            return Some(DenyReason::Capability { outcome });
+        }
+        if let Some(reason) = escalated {
+            return Some(self.park(principal, &simulation.decision.context_hash, reason));
+        }
+
+        let limited = self.rate_limiter.as_ref().and_then(|limiter| limiter.peek(&proposal.tool_name, &required, simulation.decision.tick).err());
+        limited.map(|limited| DenyReason::RateLimited { key: limited.key })
+    }
+
+    /// Records an anomaly `source` observed and counts it toward the risk score; see
+    /// `anomaly`.
+    pub fn record_anomaly(&mut self, anomaly_id: &str, source: &str, description: &str) -> LedgerResult<Anomaly> {
+        let tick = self.tick();
+        let anomaly = Anomaly { anomaly_id: anomaly_id.to_string(), source: source.to_string(), description: description.to_string(), tick };
+        anomaly.append_to(&mut self.ledger)?;
+        self.risk.record_anomaly(tick);
+        Ok(anomaly)
+    }
+
+    /// Records `state`, `source`'s model in its own canonical encoding; see `anomaly`.
+    pub fn record_model_checkpoint(&mut self, source: &str, state: &[u8]) -> LedgerResult<ModelCheckpoint> {
+        let checkpoint = ModelCheckpoint::new(source, self.tick(), state);
+        checkpoint.append_to(&mut self.ledger)?;
+        Ok(checkpoint)
+    }
+
+    /// Records the result of a sandboxed run; see `sandbox::SandboxExecutor`.
+    pub fn record_execution(&mut self, result: &ExecutionResult) -> LedgerResult<()> {
+        result.append_to(&mut self.ledger)
+    }
+
+    /// Reports that the actuation `decision` allowed has succeeded. Spends the grant
+    /// it was allowed under if that is single-use, and counts it against its quotas;
+    /// returns whether either happened.
+    pub fn record_actuation(&mut self, decision: &Decision) -> LedgerResult<bool> {
+        let Some(grant_id) = decision.grant_id() else { return Ok(false) };
+        let tick = self.tick();
+        self.invalidate_cache();
+        self.capabilities.record_actuation(&mut self.ledger, grant_id, tick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    use crate::capability::store::CheckOutcome;
+    use crate::gate::anomaly;
+    use crate::gate::context::{RecentAnomalies, VAR_ANOMALIES, VAR_ARGS, VAR_TICK};
+    use crate::gate::metering::StepMeter;
+    use crate::gate::reason::{DecisionOutcome, ReasonCode};
+    use crate::gate::sandbox::ExitOutcome;
+    use crate::gate::session::{self, AuthMethod, SessionToken};
+    use crate::gate::risk::RiskHint;
+    use crate::gate::tools::{ArgSchema, ArgViolation, ToolSpec, SaferAlternative};
+    use crate::gate::wire::WireDecision;
+    use crate::ledger::entry::{self, EntryKind};
+    use crate::ledger::local_witness::LocalWitness;
+    use crate::ledger::tick::LogicalTicks;
+    use crate::ledger::notarize::{NotaryBackend, Receipt};
+    use crate::vm::bytecode::*;
+
+    fn proposal(capability: &str, args: &[(&str, &str)]) -> ProposedAction {
+        ProposedAction {
+            tool_name: "sys_diagnostic".into(),
+            capability_required: capability.into(),
+            risk_hint: "high".into(),
+            args: args.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect::<HashMap<_, _>>(),
+            tenant: None,
+            nonce: None,
+            anomaly: None,
+        }
+    }
+
+    /// Allows proposals without arguments and denies the rest with reason 7.
+    const NO_ARGS: &[(u8, u32)] = &[(LOAD32, VAR_ARGS), (JZ, 3), (DENY, 7), (ALLOW, 0)];
+
+    /// A Gate running `policy` over a fresh ledger in `dir`, where `planner` holds a
+    /// single-use `sys:read` grant.
+    fn open_gate(dir: &std::path::Path, policy: &[(u8, u32)]) -> Gate {
+        let _ = std::fs::remove_dir_all(dir);
+        let payload = policy.iter().flat_map(|&(op, arg)| encode(op, arg)).collect();
+        let policy = LoadedPolicy::load("test", payload).unwrap();
+        let mut gate = Gate::open(DeterministicStore::new(dir).unwrap(), policy).unwrap();
+        gate.issue(Grant::new("g1", "planner", "sys:read".parse().unwrap(), "operator", 0).single_use()).unwrap();
+        gate
+    }
+
+    #[test]
+    fn admits_through_policy_and_capabilities() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-admit-{}", std::process::id()));
+        let mut gate = open_gate(&dir, NO_ARGS);
+
+        let denied = gate.admit("planner", &proposal("sys:read", &[("verbose", "1")])).unwrap();
+        assert_eq!(denied.denied, Some(DenyReason::Policy { code: 7 }));
+        assert_eq!(denied.explain(), "denied: policy denied with code 7 at pc 2, after conditions pc 1 held");
+        let malformed = gate.admit("planner", &proposal("sys", &[])).unwrap();
+        assert!(malformed.denied.as_ref().is_some_and(|r| matches!(r, DenyReason::MalformedCapability { .. })));
+
+        let allowed = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        assert_eq!(allowed.grant_id(), Some("g1"));
+        assert_eq!(allowed.proposal_hash, proposal("sys:read", &[]).hash());
+        assert!(gate.record_actuation(&allowed).unwrap());
+        let spent = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        assert_eq!(spent.denied, Some(DenyReason::Capability { outcome: CheckOutcome::Consumed { grant_id: "g1".into() } }));
+
+        // Every decision, denials included, is in the ledger exactly as it was returned.
+        let mut recorded = Vec::new();
+        gate.ledger()
+            .for_each_entry(|_, payload| {
+                if let Some((EntryKind::Decision, body)) = entry::decode(payload) {
+                    recorded.push(serde_json::from_slice::<Decision>(body).unwrap());
+                }
+            })
+            .unwrap();
+        assert_eq!(recorded, vec![denied, malformed, allowed, spent]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn decisions_meter_steps_against_their_budget() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-metering-{}", std::process::id()));
+        let mut gate = open_gate(&dir, NO_ARGS);
+        let decisions = [
+            gate.admit("planner", &proposal("sys:read", &[("verbose", "1")])).unwrap(),
+            gate.admit("planner", &proposal("sys", &[])).unwrap(),
+            gate.admit("planner", &proposal("sys:read", &[])).unwrap(),
+        ];
+        let budget = gate.policy().proof.execution_budget();
+        assert_eq!(decisions.iter().map(|d| d.budget).collect::<Vec<_>>(), [Some(budget), None, Some(budget)]);
+
+        let mut node = StepMeter::default();
+        decisions.iter().for_each(|d| node.observe(d));
+        let used = &node.policies[gate.policy().hash()];
+        assert_eq!((used.evaluations, used.max_steps, used.budget_steps, used.aborted), (2, 3, budget.max_steps, 0));
+        let mut fleet = node.clone();
+        fleet.merge(&node);
+        assert_eq!(fleet.policies[gate.policy().hash()].evaluations, 4);
+        assert_eq!(fleet.near_budget(used.peak_pct()).len(), 1);
+        assert!(fleet.near_budget(used.peak_pct() + 1.0).is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn decisions_lead_back_to_their_anomaly() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-anomaly-{}", std::process::id()));
+        let mut gate = open_gate(&dir, NO_ARGS);
+        let id = anomaly::anomaly_id(subsystem::PREDICTIVE_LOOP, 0);
+        gate.record_model_checkpoint(subsystem::PREDICTIVE_LOOP, b"older model").unwrap();
+        let model = gate.record_model_checkpoint(subsystem::PREDICTIVE_LOOP, b"model").unwrap();
+        let recorded = gate.record_anomaly(&id, subsystem::PREDICTIVE_LOOP, "joint 3 jammed").unwrap();
+        assert_eq!(gate.risk_mut().recent_anomalies(recorded.tick), 1);
+
+        let caused = ProposedAction { anomaly: Some(id.clone()), ..proposal("sys:read", &[]) };
+        assert_ne!(caused.hash(), proposal("sys:read", &[]).hash());
+        assert_eq!(ProposedAction::from_canonical(&caused.canonical()).unwrap(), caused);
+        let allowed = gate.admit("planner", &caused).unwrap();
+        assert_eq!((allowed.is_allowed(), allowed.anomaly.as_deref()), (true, Some(id.as_str())));
+        gate.admit("planner", &proposal("sys:read", &[("verbose", "1")])).unwrap();
+        let run = ExecutionResult {
+            decision: allowed.context_hash.clone(),
+            proposal_hash: allowed.proposal_hash.clone(),
+            tool: allowed.tool.clone(),
+            outcome: ExitOutcome::Exited { code: 0 },
+            network: false,
+            stdout_hash: String::new(),
+            stderr_hash: String::new(),
+            stdout_len: 0,
+            stderr_len: 0,
+            duration_ms: 1,
+        };
+        gate.record_execution(&run).unwrap();
+
+        gate.record_model_checkpoint(subsystem::PREDICTIVE_LOOP, b"newer model").unwrap();
+
+        let trail = anomaly::trail(gate.ledger(), &id).unwrap();
+        assert_eq!(trail.checkpoint.as_ref().and_then(ModelCheckpoint::state).as_deref(), Some(b"model".as_slice()));
+        assert_eq!((trail.checkpoint, trail.anomaly, trail.decisions, trail.executions), (Some(model), Some(recorded), vec![allowed], vec![run]));
+        let latest = ModelCheckpoint::latest(gate.ledger(), subsystem::PREDICTIVE_LOOP).unwrap().unwrap();
+        assert_eq!(latest.state().as_deref(), Some(b"newer model".as_slice()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn providers_feed_the_context() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-providers-{}", std::process::id()));
+        let mut gate = open_gate(&dir, &[(LOAD32, VAR_ANOMALIES), (JZ, 3), (DENY, 9), (ALLOW, 0)]).with_provider(RecentAnomalies);
+        assert!(gate.admit("planner", &proposal("sys:read", &[])).unwrap().is_allowed());
+        let tick = gate.tick();
+        gate.risk_mut().record_anomaly(tick);
+        assert_eq!(gate.admit("planner", &proposal("sys:read", &[])).unwrap().denied, Some(DenyReason::Policy { code: 9 }));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn policies_reading_node_local_context_are_refused() {
+        #[derive(Debug)]
+        struct HostClock;
+        impl ContextProvider for HostClock {
+            fn var(&self) -> u32 {
+                28
+            }
+            fn value(&self, _: &ProviderInput<'_>) -> u32 {
+                0
+            }
+            fn nondeterminism(&self) -> Option<Nondeterminism> {
+                Some(Nondeterminism::WallClock)
+            }
+        }
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-lint-{}", std::process::id()));
+        let mut gate = open_gate(&dir, NO_ARGS).with_provider(HostClock).with_provider(RecentAnomalies);
+        let load = |policy: &[(u8, u32)]| LoadedPolicy::load("clocked", policy.iter().flat_map(|&(op, arg)| encode(op, arg)).collect()).unwrap();
+        let clocked = load(&[(LOAD32, VAR_ANOMALIES), (LOAD32, 28), (OR, 0), (DECIDE, 4)]);
+
+        let Err(PackageError::Nondeterministic { reads, .. }) = gate.load_policy(clocked.clone()) else { panic!("expected a refusal") };
+        assert_eq!(reads, [NondeterministicRead { module: None, pc: Some(1), bytes: 28..32, source: Nondeterminism::WallClock }]);
+        assert!(gate.load_policy(load(&[(LOAD32, VAR_ANOMALIES), (DECIDE, 4)])).is_ok());
+        let mut gate = gate.allow_nondeterministic(28);
+        assert!(gate.nondeterministic_reads(&clocked).is_empty());
+        assert!(gate.load_policy(clocked).is_ok());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn freezes_until_an_authorized_unfreeze() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-freeze-{}", std::process::id()));
+        let peer_dir = std::env::temp_dir().join(format!("rfsn-gate-freeze-peer-{}", std::process::id()));
+        let key = SigningKey::from_bytes(&[9; 32]);
+        let authority = FreezeAuthority::new().principal("oncall", key.verifying_key());
+        let mut gate = open_gate(&dir, NO_ARGS).with_freeze_authority(authority.clone());
+        let mut peer = open_gate(&peer_dir, NO_ARGS).with_freeze_authority(authority);
+
+        let event = gate.freeze("arm overheating", "operator").unwrap();
+        let FreezeEvent::Frozen { freeze } = &event else { panic!("expected a freeze") };
+        let frozen = Some(DenyReason::Frozen { freeze_id: freeze.freeze_id.clone() });
+        assert_eq!(gate.admit("planner", &proposal("sys:read", &[])).unwrap().denied, frozen);
+        assert!(peer.apply_freeze(&event).unwrap());
+        assert!(!peer.apply_freeze(&event).unwrap());
+        assert_eq!(peer.simulate("planner", &proposal("sys:read", &[])).decision.denied, frozen);
+
+        let forged = UnfreezeToken::sign(&freeze.freeze_id, "oncall", &SigningKey::from_bytes(&[1; 32]));
+        assert!(matches!(gate.unfreeze(&forged), Err(FreezeError::BadSignature { .. })));
+        let lifted = gate.unfreeze(&UnfreezeToken::sign(&freeze.freeze_id, "oncall", &key)).unwrap();
+        assert!(gate.admit("planner", &proposal("sys:read", &[])).unwrap().is_allowed());
+        assert!(peer.apply_freeze(&lifted).unwrap());
+        assert!(peer.frozen().is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+        let _ = std::fs::remove_dir_all(&peer_dir);
+    }
+
+    /// A witness that never answers.
+    struct Offline;
+
+    impl NotaryBackend for Offline {
+        fn name(&self) -> &str {
+            "offline"
+        }
+
+        fn submit(&self, _: &AnchorRequest) -> Result<Receipt, Box<dyn std::error::Error>> {
+            Err("offline".into())
+        }
+
+        fn fetch_receipt(&self, _: &str) -> Result<Receipt, Box<dyn std::error::Error>> {
+            Err("offline".into())
+        }
+
+        fn verify_receipt(&self, _: &AnchorRequest, _: &Receipt) -> Result<(), Box<dyn std::error::Error>> {
+            Err("offline".into())
+        }
+    }
+
+    #[test]
+    fn overrides_wait_for_notarization_or_approvals() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-override-{}", std::process::id()));
+        let oncall = SigningKey::from_bytes(&[5; 32]);
+        let lead = SigningKey::from_bytes(&[6; 32]);
+        let mut gate = open_gate(&dir, NO_ARGS).with_break_glass(BreakGlassPolicy::new().principal("oncall", oncall.verifying_key()));
+        let denied = gate.admit("planner", &proposal("sys:read", &[("verbose", "1")])).unwrap();
+        let token = OverrideToken::sign(&denied.context_hash, "oncall", "diagnostics during an outage", &oncall);
+        let offline = NotaryClient::quorum_of(vec![Box::new(Offline)], 1).unwrap();
+        assert!(matches!(gate.break_glass(&token, &offline), Err(BreakGlassError::NotNotarized { receipts: 0, threshold: 1 })));
+        let unknown = OverrideToken::sign(&"0".repeat(64), "oncall", "no such denial", &oncall);
+        assert!(matches!(gate.break_glass(&unknown, &offline), Err(BreakGlassError::UnknownDecision { .. })));
+
+        let witness = LocalWitness::from_seed("w1", [7; 32]);
+        let notary = NotaryClient::quorum_of(vec![Box::new(witness.clone())], 1).unwrap();
+        let OverrideOutcome::Allowed(allowed) = gate.break_glass(&token, &notary).unwrap() else { panic!("expected notarization") };
+        assert_eq!((allowed.is_allowed(), allowed.overridden_by.as_deref()), (true, Some("oncall")));
+        assert_eq!(allowed.proposal_hash, denied.proposal_hash);
+        assert_eq!(witness.issued(), 1);
+        assert!(matches!(gate.break_glass(&token, &notary), Err(BreakGlassError::AlreadyUsed { .. })));
+
+        let mut gate = gate.with_approvals(ApprovalPolicy::new(2).approver("lead", lead.verifying_key()).approver("oncall", oncall.verifying_key()));
+        let denied = gate.admit("planner", &proposal("sys:read", &[("verbose", "2")])).unwrap();
+        let token = OverrideToken::sign(&denied.context_hash, "oncall", "diagnostics during an outage", &oncall);
+        let queued = gate.break_glass(&token, &offline).unwrap();
+        assert_eq!(queued, OverrideOutcome::Queued { override_id: denied.context_hash.clone() });
+        assert!(gate.approve_override(&ApprovalToken::sign(&denied.context_hash, "lead", &lead)).unwrap().is_none());
+
+        // Queued overrides, with their approvals, and spent tokens survive a restart.
+        let policy = gate.policy().clone();
+        drop(gate);
+        let mut reopened = Gate::open(DeterministicStore::new(&dir).unwrap(), policy)
+            .unwrap()
+            .with_break_glass(BreakGlassPolicy::new().principal("oncall", oncall.verifying_key()))
+            .with_approvals(ApprovalPolicy::new(2).approver("lead", lead.verifying_key()).approver("oncall", oncall.verifying_key()));
+        let pending = &reopened.queued_overrides()[&denied.context_hash];
+        assert_eq!((&pending.decision, pending.approvers.len()), (&denied, 1));
+        assert!(matches!(reopened.break_glass(&token, &notary), Err(BreakGlassError::AlreadyUsed { .. })));
+        let released = reopened.approve_override(&ApprovalToken::sign(&denied.context_hash, "oncall", &oncall)).unwrap().unwrap();
+        assert_eq!((released.is_allowed(), released.approvers.clone()), (true, vec!["lead".to_string(), "oncall".to_string()]));
+        assert!(reopened.queued_overrides().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn simulation_has_no_effects() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-simulate-{}", std::process::id()));
+        let gate = open_gate(&dir, NO_ARGS);
+        let entries = gate.ledger().entry_count();
+
+        let simulation = gate.simulate("planner", &proposal("sys:read", &[]));
+        assert!(simulation.decision.is_allowed());
+        assert_eq!(simulation.policy_verdict, Some(Verdict::Allow));
+        assert!(simulation.decision.usage.steps > 0);
+        // The policy allows, the missing grant decides.
+        let unknown = gate.simulate("intruder", &proposal("sys:read", &[]));
+        assert_eq!(unknown.policy_verdict, Some(Verdict::Allow));
+        assert_eq!(unknown.decision.denied, Some(DenyReason::Capability { outcome: CheckOutcome::NoGrant }));
+
+        assert_eq!(gate.ledger().entry_count(), entries);
+        assert!(gate.simulate("planner", &proposal("sys:read", &[])).decision.is_allowed());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn parked_proposals_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-parked-{}", std::process::id()));
+        let keys: Vec<SigningKey> = (1..=2).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+        let approvals = (0..2).fold(ApprovalPolicy::new(2), |policy, i| policy.approver(&format!("approver{}", i), keys[i].verifying_key()));
+        let mut gate = open_gate(&dir, &[(CTXLEN, 0), (POP, 0), (ESCALATE, 9)]).with_approvals(approvals.clone());
+        let parked = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        let Some(DenyReason::PendingApproval { approval_id, .. }) = parked.denied.clone() else { panic!("not parked") };
+        assert!(gate.approve(&ApprovalToken::sign(&approval_id, "approver0", &keys[0])).unwrap().is_none());
+
+        let policy = gate.policy().clone();
+        drop(gate);
+        let mut reopened = Gate::open(DeterministicStore::new(&dir).unwrap(), policy).unwrap().with_approvals(approvals);
+        let pending = &reopened.pending()[&approval_id];
+        assert_eq!((&pending.decision, pending.approvers.len()), (&parked, 1));
+        let released = reopened.approve(&ApprovalToken::sign(&approval_id, "approver1", &keys[1])).unwrap().unwrap();
+        assert!(released.is_allowed());
+        assert!(reopened.pending().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn restored_approvals_are_verified_against_the_current_policy() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-parked-policy-{}", std::process::id()));
+        let keys: Vec<SigningKey> = (1..=3).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+        let approvals = (0..3).fold(ApprovalPolicy::new(3), |policy, i| policy.approver(&format!("approver{}", i), keys[i].verifying_key()));
+        let mut gate = open_gate(&dir, &[(ESCALATE, 9)]).with_approvals(approvals);
+        let parked = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        let Some(DenyReason::PendingApproval { approval_id, .. }) = parked.denied.clone() else { panic!("not parked") };
+        let token = |i: usize| ApprovalToken::sign(&approval_id, &format!("approver{}", i), &keys[i]);
+        assert!(gate.approve(&token(0)).unwrap().is_none());
+        assert!(gate.approve(&token(1)).unwrap().is_none());
+
+        // approver0 was removed before the restart, and approver1's key was rotated.
+        let policy = gate.policy().clone();
+        drop(gate);
+        let rotated = SigningKey::from_bytes(&[9; 32]);
+        let reduced = ApprovalPolicy::new(2).approver("approver1", rotated.verifying_key()).approver("approver2", keys[2].verifying_key());
+        let mut reopened = Gate::open(DeterministicStore::new(&dir).unwrap(), policy).unwrap().with_approvals(reduced);
+        assert!(reopened.pending()[&approval_id].approvers.is_empty());
+        assert!(reopened.approve(&token(2)).unwrap().is_none());
+        let released = reopened.approve(&ApprovalToken::sign(&approval_id, "approver1", &rotated)).unwrap().expect("two current approvers signed");
+        assert_eq!(released.approvers, ["approver1", "approver2"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn escalations_wait_for_m_of_n_approvals() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-approve-{}", std::process::id()));
+        let keys: Vec<SigningKey> = (1..=3).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+        let approvals = (0..3).fold(ApprovalPolicy::new(2), |policy, i| policy.approver(&format!("approver{}", i), keys[i].verifying_key()));
+        let mut gate = open_gate(&dir, &[(ESCALATE, 9)]).with_approvals(approvals);
+
+        let parked = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        let Some(DenyReason::PendingApproval { approval_id, code: 9 }) = parked.denied else { panic!("not parked: {:?}", parked.denied) };
+        let token = |i: usize| ApprovalToken::sign(&approval_id, &format!("approver{}", i), &keys[i]);
+        let forged = ApprovalToken::sign(&approval_id, "approver0", &keys[1]);
+        assert!(matches!(gate.approve(&forged), Err(ApprovalError::BadSignature { .. })));
+        assert!(gate.approve(&token(0)).unwrap().is_none());
+        assert!(matches!(gate.approve(&token(0)), Err(ApprovalError::AlreadyApproved { .. })));
+
+        let released = gate.approve(&token(2)).unwrap().expect("two of three approved");
+        assert!(released.is_allowed());
+        assert_eq!(released.approvers, ["approver0", "approver2"]);
+        assert!(gate.pending().is_empty());
+        assert!(matches!(gate.approve(&token(1)), Err(ApprovalError::UnknownApproval { .. })));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn approval_ids_name_the_proposer_and_are_parked_once() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-approval-id-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut ledger = DeterministicStore::new(&dir).unwrap();
+        ledger.set_tick_source(Arc::new(LogicalTicks::starting_at(1)));
+        let policy = LoadedPolicy::load("test", [(ESCALATE, 9)].iter().flat_map(|&(op, arg)| encode(op, arg)).collect()).unwrap();
+        let approvals = ApprovalPolicy::new(1).approver("approver0", SigningKey::from_bytes(&[1; 32]).verifying_key());
+        let mut gate = Gate::open(ledger, policy).unwrap().with_approvals(approvals);
+        for principal in ["planner", "scheduler"] {
+            gate.issue(Grant::new(&format!("g-{}", principal), principal, "sys:read".parse().unwrap(), "operator", 0)).unwrap();
+        }
+
+        let parked = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        let Some(DenyReason::PendingApproval { approval_id, .. }) = parked.denied.clone() else { panic!("not parked: {:?}", parked.denied) };
+        assert_eq!(approval_id, approval::approval_id("planner", &parked.context_hash));
+        let other = gate.admit("scheduler", &proposal("sys:read", &[])).unwrap();
+        assert_eq!(other.context_hash, parked.context_hash);
+        assert!(matches!(other.denied, Some(DenyReason::PendingApproval { approval_id: ref id, .. }) if *id != approval_id));
+
+        let again = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        assert_eq!(again.denied, Some(DenyReason::AlreadyPending { approval_id: approval_id.clone() }));
+        assert_eq!(gate.pending().len(), 2);
+        assert_eq!(gate.pending()[&approval_id].decision, parked);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reloaded_policies_are_versioned() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-reload-{}", std::process::id()));
+        let mut gate = open_gate(&dir, NO_ARGS);
+        let before = gate.admit("planner", &proposal("sys:read", &[("verbose", "1")])).unwrap();
+
+        let deny_all = LoadedPolicy::load("deny-all", encode(DENY, 3).to_vec()).unwrap().signed_by("operator");
+        let version = gate.load_policy(deny_all).unwrap();
+        let after = gate.admit("planner", &proposal("sys:read", &[("verbose", "1")])).unwrap();
+        assert_eq!(after.denied, Some(DenyReason::Policy { code: 3 }));
+        assert_eq!(after.policy_hash, version.policy_hash);
+        assert_ne!(after.policy_hash, before.policy_hash);
+
+        let history = PolicyVersion::history(gate.ledger()).unwrap();
+        assert_eq!(history.iter().map(|v| v.name.as_str()).collect::<Vec<_>>(), ["test", "deny-all"]);
+        assert_eq!((history[1].payload.as_slice(), history[1].signer.as_deref()), (&encode(DENY, 3)[..], Some("operator")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn policy_denials_offer_a_safer_counter_proposal() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-counter-{}", std::process::id()));
+        let mut gate = open_gate(&dir, NO_ARGS).with_counter_proposals();
+        let diagnostic = ToolSpec::new("sys:read".parse().unwrap(), RiskHint::Low).arg("verbose", ArgSchema::boolean()).safer(SaferAlternative::tool("sys_status"));
+        let status = ToolSpec::new("sys:read".parse().unwrap(), RiskHint::Low).read_only();
+        gate.load_tools(ToolRegistry::default().tool("sys_diagnostic", diagnostic).tool("sys_status", status)).unwrap();
+
+        let verbose = ProposedAction { nonce: Some(4), ..proposal("sys:read", &[("verbose", "true")]) };
+        let denied = gate.admit("planner", &verbose).unwrap();
+        assert_eq!(denied.denied, Some(DenyReason::Policy { code: 7 }));
+        let counter = denied.counter_proposal().unwrap();
+        assert_eq!((counter.tool_name.as_str(), counter.args.len(), counter.nonce), ("sys_status", 0, Some(5)));
+        assert_eq!(WireDecision::of(&denied).counter.as_ref(), Some(&counter));
+        assert!(gate.admit("planner", &counter).unwrap().is_allowed());
+        // Only policy denials get one.
+        assert_eq!(gate.admit("planner", &proposal("sys:read", &[("verbose", "1")])).unwrap().counter, None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn caches_allows_of_read_only_tools() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-cache-{}", std::process::id()));
+        let cached_gate = |dir: &std::path::Path, program: &[(u8, u32)], expiry: u64| {
+            let _ = std::fs::remove_dir_all(dir);
+            let policy = LoadedPolicy::load("test", program.iter().flat_map(|&(op, arg)| encode(op, arg)).collect()).unwrap();
+            let mut gate = Gate::open(DeterministicStore::new(dir).unwrap(), policy).unwrap().with_decision_cache(16);
+            let diagnostic = ToolSpec::new("sys:read".parse().unwrap(), RiskHint::Low).read_only();
+            gate.load_tools(ToolRegistry::default().tool("sys_diagnostic", diagnostic)).unwrap();
+            gate.issue(Grant::new("g1", "planner", "sys:read".parse().unwrap(), "operator", 0).expires_at(expiry)).unwrap();
+            gate
+        };
+        // On the default tick source every recorded decision advances the tick.
+        let mut gate = cached_gate(&dir, NO_ARGS, 12);
+        let first = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        assert!(first.is_allowed());
+        // The cached decision is the one a fresh evaluation reaches at the later tick.
+        let fresh = gate.simulate("planner", &proposal("sys:read", &[])).decision;
+        let cached = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        assert!(cached.tick > first.tick);
+        assert_eq!(cached, fresh);
+        let cache = gate.decision_cache().unwrap();
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 1, 1));
+
+        // Once the grant expires the entry is not served.
+        while gate.admit("planner", &proposal("sys:read", &[])).unwrap().is_allowed() {}
+        assert!(gate.decision_cache().unwrap().is_empty());
+        gate.issue(Grant::new("g2", "planner", "sys:read".parse().unwrap(), "operator", 0)).unwrap();
+        assert!(gate.admit("planner", &proposal("sys:read", &[])).unwrap().is_allowed());
+        gate.revoke("g2", "rotated").unwrap();
+        assert!(gate.decision_cache().unwrap().is_empty());
+        assert!(!gate.admit("planner", &proposal("sys:read", &[])).unwrap().is_allowed());
+
+        // A policy that reads the tick is keyed on it.
+        let mut gate = cached_gate(&dir, &[(LOAD32, VAR_TICK), (POP, 0), (ALLOW, 0)], 1_000);
+        assert!(gate.admit("planner", &proposal("sys:read", &[])).unwrap().is_allowed());
+        assert!(gate.admit("planner", &proposal("sys:read", &[])).unwrap().is_allowed());
+        assert_eq!((gate.decision_cache().unwrap().hits(), gate.decision_cache().unwrap().misses()), (0, 2));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn hooks_see_recorded_events() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-hooks-{}", std::process::id()));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let hook = move |event: &GateEvent| log.lock().unwrap().push(event.kind());
+        let mut gate = open_gate(&dir, NO_ARGS).with_hook(&[EventKind::Deny, EventKind::Freeze], hook);
+
+        let denied = gate.admit("planner", &proposal("sys:read", &[("verbose", "1")])).unwrap();
+        assert_eq!(GateEvent::of(&denied), GateEvent::Deny { decision: Box::new(denied.clone()) });
+        assert!(gate.admit("planner", &proposal("sys:read", &[])).unwrap().is_allowed());
+        gate.freeze("drill", "operator").unwrap();
+        // The allow was not subscribed to.
+        assert_eq!(*seen.lock().unwrap(), [EventKind::Deny, EventKind::Freeze]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn replayed_nonces_are_denied_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-replay-{}", std::process::id()));
+        let mut gate = open_gate(&dir, NO_ARGS).with_required_nonces();
+        let with_nonce = |nonce| ProposedAction { nonce: Some(nonce), ..proposal("sys:read", &[]) };
+        assert_eq!(gate.admit("planner", &proposal("sys:read", &[])).unwrap().denied, Some(DenyReason::MissingNonce));
+        assert!(gate.admit("planner", &with_nonce(5)).unwrap().is_allowed());
+        assert_eq!(gate.admit("planner", &with_nonce(5)).unwrap().denied, Some(DenyReason::Replayed { nonce: 5, last: 5 }));
+        // Nonces are per proposer.
+        assert!(matches!(gate.admit("auditor", &with_nonce(1)).unwrap().denied, Some(DenyReason::Capability { .. })));
+
+        let policy = gate.policy().clone();
+        drop(gate);
+        let mut reopened = Gate::open(DeterministicStore::new(&dir).unwrap(), policy).unwrap();
+        assert_eq!(reopened.admit("planner", &with_nonce(4)).unwrap().denied, Some(DenyReason::Replayed { nonce: 4, last: 5 }));
+        assert!(reopened.admit("planner", &with_nonce(6)).unwrap().is_allowed());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn undecided_proposals_fall_back_by_namespace() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-fallback-{}", std::process::id()));
+        // Abstains on proposals with arguments.
+        let mut gate = open_gate(&dir, &[(LOAD32, VAR_ARGS), (JZ, 3), (DENY, REASON_NO_DECISION as u32), (ALLOW, 0)]);
+        let verbose = proposal("sys:read", &[("verbose", "1")]);
+
+        let denied = gate.admit("planner", &verbose).unwrap();
+        assert_eq!((denied.denied, denied.fallback), (Some(DenyReason::Policy { code: REASON_NO_DECISION }), Some(Fallback::DefaultDeny)));
+
+        let config = NamespaceConfig::default().namespace("sys", Fallback::DefaultAllow);
+        let version = gate.load_namespaces(config.clone()).unwrap();
+        let entries = gate.ledger().entry_count();
+        assert_eq!(gate.load_namespaces(config).unwrap(), version);
+        assert_eq!(gate.ledger().entry_count(), entries);
+        assert_eq!(NamespaceVersion::latest(gate.ledger()).unwrap(), Some(version));
+
+        let allowed = gate.admit("planner", &verbose).unwrap();
+        assert!(allowed.is_allowed());
+        assert_eq!(allowed.fallback, Some(Fallback::DefaultAllow));
+        // Other namespaces still deny, and a policy that decides is not overruled.
+        let actuate = gate.admit("planner", &proposal("actuator/arm:move", &[("verbose", "1")])).unwrap();
+        assert_eq!(actuate.fallback, Some(Fallback::DefaultDeny));
+        let quiet = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        assert_eq!(quiet.fallback, None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn proposals_need_an_authenticated_session() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-session-{}", std::process::id()));
+        let issuer = SigningKey::from_bytes(&[4; 32]);
+        let authenticator = Authenticator::default().issuer("idp", issuer.verifying_key()).certificate(b"planner-cert", "planner");
+        let mut gate = open_gate(&dir, NO_ARGS).with_sessions(authenticator);
+        gate.issue(Grant::new("g2", "planner", "sys:read".parse().unwrap(), "operator", 0)).unwrap();
+
+        let outside = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        assert_eq!((outside.principal.as_str(), outside.denied), ("", Some(DenyReason::Unauthenticated)));
+        let forged = SessionToken::sign("operator", "s1", "idp", 100, &SigningKey::from_bytes(&[5; 32]));
+        assert!(matches!(gate.open_session(&Credential::Token(forged)), Err(SessionError::BadSignature { .. })));
+        assert!(matches!(gate.open_session(&Credential::Certificate(b"rogue-cert".to_vec())), Err(SessionError::UnknownCertificate { .. })));
+
+        let token = SessionToken::sign("planner", "s1", "idp", gate.tick(), &issuer);
+        let session = gate.open_session(&Credential::Token(token)).unwrap();
+        let decision = gate.admit_session(&session.session_id, &proposal("sys:read", &[])).unwrap();
+        assert!(decision.is_allowed());
+        assert_eq!((decision.principal.as_str(), decision.session.as_deref()), ("planner", Some("s1")));
+        // The decision took the tick past the token's expiry.
+        assert_eq!(gate.admit_session("s1", &proposal("sys:read", &[])).unwrap().denied, Some(DenyReason::Unauthenticated));
+
+        let session = gate.open_session(&Credential::Certificate(b"planner-cert".to_vec())).unwrap();
+        assert_eq!(session.method, AuthMethod::Certificate { fingerprint: session::fingerprint(b"planner-cert") });
+        assert!(gate.admit_session(&session.session_id, &proposal("sys:read", &[])).unwrap().is_allowed());
+        assert!(gate.close_session(&session.session_id));
+        assert_eq!(gate.admit_session(&session.session_id, &proposal("sys:read", &[])).unwrap().denied, Some(DenyReason::Unauthenticated));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn shadow_policies_are_compared_but_not_enforced() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-shadow-{}", std::process::id()));
+        let mut gate = open_gate(&dir, NO_ARGS);
+        // Agrees with NO_ARGS on proposals with arguments, denies the rest with reason 9.
+        let candidate = [(LOAD32, VAR_ARGS), (JZ, 3), (DENY, 7), (DENY, 9)];
+        let payload = candidate.iter().flat_map(|&(op, arg)| encode(op, arg)).collect();
+        assert_eq!(gate.load_shadow_policy(LoadedPolicy::load("candidate", payload).unwrap(), 8).unwrap(), None);
+
+        let allowed = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        assert!(allowed.is_allowed());
+        assert_eq!(allowed.policy_hash, gate.policy().hash());
+        assert!(gate.admit("planner", &proposal("sys:read", &[("verbose", "1")])).unwrap().denied.is_some());
+
+        let report = gate.end_shadow().unwrap();
+        assert_eq!((report.candidate.as_str(), report.evaluated, report.agreed, report.diverged()), ("candidate", 2, 1, 1));
+        let divergence = &report.divergences[0];
+        assert_eq!(divergence.context_hash, allowed.context_hash);
+        assert_eq!((&divergence.active, &divergence.candidate), (&Outcome::Verdict(Verdict::Allow), &Outcome::Verdict(Verdict::Deny { reason: 9 })));
+        assert!(gate.shadow_report().is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn subsystems_hold_read_only_attenuations() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-subsystem-{}", std::process::id()));
+        let mut gate = open_gate(&dir, NO_ARGS);
+        let diagnostic = ToolSpec::new("sys:read".parse().unwrap(), RiskHint::Low).read_only();
+        let arm = ToolSpec::new("actuator/arm:move".parse().unwrap(), RiskHint::Critical);
+        gate.load_tools(ToolRegistry::default().tool("sys_diagnostic", diagnostic).tool("arm_move", arm)).unwrap();
+        gate.issue(Grant::new("root", "operator", "*:*".parse().unwrap(), "operator", 0)).unwrap();
+
+        let attenuated = |id: &str, principal: &str, capability: &str| Grant::new(id, principal, capability.parse().unwrap(), "operator", 0);
+        let actuating = gate.delegate_to_subsystem("root", "operator", attenuated("s1", subsystem::PREDICTIVE_LOOP, "actuator/*:*"));
+        assert!(matches!(actuating, Err(SubsystemError::Actuating { tools, .. }) if tools == ["arm_move"]));
+        let external = gate.delegate_to_subsystem("root", "operator", attenuated("s1", "planner", "sys:read"));
+        assert!(matches!(external, Err(SubsystemError::NotASubsystem { .. })));
+        let delegated = gate.delegate_to_subsystem("root", "operator", attenuated("s1", subsystem::PREDICTIVE_LOOP, "sys:read")).unwrap();
+        assert_eq!(delegated.delegated_from.as_deref(), Some("root"));
+        assert!(gate.admit(subsystem::PREDICTIVE_LOOP, &proposal("sys:read", &[])).unwrap().is_allowed());
+
+        // A grant that covers actuation, however it was issued, still does not let a subsystem use it.
+        gate.issue(attenuated("s2", subsystem::MAINTENANCE, "actuator/arm:move")).unwrap();
+        let arm_move = ProposedAction { tool_name: "arm_move".into(), ..proposal("actuator/arm:move", &[]) };
+        let denied = gate.admit(subsystem::MAINTENANCE, &arm_move).unwrap();
+        assert_eq!(denied.denied, Some(DenyReason::SubsystemActuation { tool: "arm_move".into() }));
+        assert_eq!(denied.outcome(), DecisionOutcome::Deny { reason: ReasonCode::SubsystemActuation });
+        assert!(gate.admit("operator", &arm_move).unwrap().is_allowed());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn registered_tools_decide_the_required_capability() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-tools-{}", std::process::id()));
+        let mut gate = open_gate(&dir, NO_ARGS);
+        let arm = ToolSpec::new("actuator/arm:move".parse().unwrap(), RiskHint::Critical).arg("verbose", ArgSchema::boolean());
+        gate.load_tools(ToolRegistry::default().tool("sys_diagnostic", arm)).unwrap();
+
+        // The proposal claims `sys:read`, which planner holds; the registry says otherwise.
+        let decision = gate.admit("planner", &proposal("sys:read", &[])).unwrap();
+        assert_eq!(decision.denied, Some(DenyReason::Capability { outcome: CheckOutcome::NoGrant }));
+        assert_eq!(decision.risk, RiskHint::High.weight() + RiskHint::Critical.weight());
+        let malformed = gate.admit("planner", &proposal("sys:read", &[("verbose", "loud")])).unwrap();
+        assert_eq!(malformed.denied, Some(DenyReason::InvalidArgs { violation: ArgViolation::NotABoolean { arg: "verbose".into() } }));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn trusted_gates_only_load_signed_packages() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-package-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (release, rogue) = (SigningKey::from_bytes(&[1; 32]), SigningKey::from_bytes(&[2; 32]));
+        let root = TrustRoot::default().signer("release", release.verifying_key());
+        let package = |name: &str, op: u8, key: &SigningKey| PolicyPackage::sign(name, encode(op, 3).to_vec(), "release", key);
+
+        let unsigned = LoadedPolicy::load("local", encode(ALLOW, 0).to_vec()).unwrap();
+        assert!(matches!(Gate::open_trusted(DeterministicStore::new(&dir).unwrap(), unsigned, root.clone()), Err(PackageError::Unsigned { .. })));
+        let initial = root.open(&package("allow-all", ALLOW, &release)).unwrap();
+        let mut gate = Gate::open_trusted(DeterministicStore::new(&dir).unwrap(), initial, root).unwrap();
+
+        assert!(matches!(gate.load_package(&package("deny-all", DENY, &rogue)), Err(PackageError::BadSignature { .. })));
+        let swapped = LoadedPolicy::load("deny-all", encode(DENY, 3).to_vec()).unwrap().signed_by("release");
+        assert!(matches!(gate.load_policy(swapped), Err(PackageError::Unsigned { .. })));
+        assert_eq!(gate.policy().name, "allow-all");
+
+        let version = gate.load_package(&package("deny-all", DENY, &release)).unwrap();
+        assert_eq!(version.signer.as_deref(), Some("release"));
+        assert!(version.signature.is_some());
+        assert_eq!(gate.policy().name, "deny-all");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn namespaced_policies_wait_for_a_second_signer() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-cosign-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (release, reviewer) = (SigningKey::from_bytes(&[1; 32]), SigningKey::from_bytes(&[3; 32]));
+        let root = TrustRoot::default().signer("release", release.verifying_key()).signer("reviewer", reviewer.verifying_key()).require_signers("safety", 2);
+        let initial = root.open(&PolicyPackage::sign("allow-all", encode(ALLOW, 0).to_vec(), "release", &release)).unwrap();
+        let mut gate = Gate::open_trusted(DeterministicStore::new(&dir).unwrap(), initial, root).unwrap();
+
+        let package = PolicyPackage::sign("safety/deny-all", encode(DENY, 3).to_vec(), "release", &release);
+        let Err(PackageError::AwaitingSignatures { policy_hash, signers, required, .. }) = gate.load_package(&package) else { panic!("expected to be parked") };
+        assert_eq!((signers.as_slice(), required), (&["release".to_string()][..], 2));
+        assert_eq!(gate.policy().name, "allow-all");
+
+        // Signing again as the first signer does not make two.
+        let again = Cosignature::sign(&package.name, &package.payload, "release", &release);
+        assert_eq!(gate.cosign_policy(&policy_hash, again).unwrap(), None);
+        let forged = Cosignature::sign(&package.name, &package.payload, "reviewer", &release);
+        assert!(matches!(gate.cosign_policy(&policy_hash, forged), Err(PackageError::BadSignature { .. })));
+        assert!(gate.pending_policies().contains_key(&policy_hash));
+
+        let version = gate.cosign_policy(&policy_hash, Cosignature::sign(&package.name, &package.payload, "reviewer", &reviewer)).unwrap().unwrap();
+        assert_eq!(version.cosignatures.iter().map(|c| c.signer.as_str()).collect::<Vec<_>>(), ["release", "reviewer"]);
+        assert_eq!(gate.policy().name, "safety/deny-all");
+        assert!(gate.pending_policies().is_empty());
+        assert!(matches!(gate.cosign_policy(&policy_hash, Cosignature::sign(&package.name, &package.payload, "reviewer", &reviewer)), Err(PackageError::NotPending { .. })));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
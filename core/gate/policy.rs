@@ -0,0 +1,192 @@
+//! Policies as the Gate holds them.
+//!
+//! A policy is only loaded once the verifier has proven it bounded; the proof sizes
+//! the watchdog budget every evaluation runs under. A policy that reads credentials
+//! is loaded with `LoadedPolicy::load_constant_time` instead, naming the context
+//! fields that hold them, and is refused if any branch depends on them. Policies
+//! for another `PolicyRuntime`, such as WASM, are loaded with `LoadedPolicy::load_with`,
+//! and policies composed from several modules with `compose::compose`. Cedar policy
+//! sets are translated to bytecode by `cedar::import`.
+//!
+//! The active policy can be swapped at runtime with `Gate::load_policy`. Every load
+//! appends a `PolicyVersion` entry holding the payload itself, its signer and the
+//! tick it takes effect, so every decision's `policy_hash` resolves, through the
+//! ledger alone, to the exact bytes that produced it.
+
+use std::io;
+use std::ops::Range;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::decision::Decision;
+use super::package::Cosignature;
+use crate::ledger::entry::{self, EntryKind};
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+use crate::vm::coverage::Coverage;
+use crate::vm::runtime::{NativeVm, PolicyRuntime, NATIVE};
+use crate::vm::verify::{verify_constant_time, BoundednessProof, VerifyError};
+
+#[derive(Debug, Clone)]
+pub struct LoadedPolicy {
+    pub name: String,
+    pub payload: Vec<u8>,
+    pub proof: BoundednessProof,
+    /// Evaluates the payload; the native VM unless loaded with `load_with`.
+    pub runtime: Arc<dyn PolicyRuntime>,
+    /// Who released the policy, e.g. an operator identity.
+    pub signer: Option<String>,
+    /// Signature of the package it was loaded from; see `package::TrustRoot`.
+    pub signature: Option<Vec<u8>>,
+    /// Further signers' signatures over the same package.
+    pub cosignatures: Vec<Cosignature>,
+    /// The modules a composed policy evaluates; empty otherwise.
+    pub modules: Vec<PolicyModule>,
+}
+
+impl LoadedPolicy {
+    pub fn load(name: &str, payload: Vec<u8>) -> Result<Self, VerifyError> {
+        Self::load_with(Arc::new(NativeVm), name, payload)
+    }
+
+    pub fn load_with(runtime: Arc<dyn PolicyRuntime>, name: &str, payload: Vec<u8>) -> Result<Self, VerifyError> {
+        let proof = runtime.verify(&payload)?;
+        Ok(Self { name: name.to_string(), payload, proof, runtime, signer: None, signature: None, cosignatures: Vec::new(), modules: Vec::new() })
+    }
+
+    /// Loads a policy that must evaluate in constant time over the context bytes in
+    /// `secrets`, e.g. `context::var_bytes(var)` of a credential variable.
+    pub fn load_constant_time(name: &str, payload: Vec<u8>, secrets: &[Range<u32>]) -> Result<Self, VerifyError> {
+        let proof = verify_constant_time(&payload, secrets)?;
+        Ok(Self { name: name.to_string(), payload, proof, runtime: Arc::new(NativeVm), signer: None, signature: None, cosignatures: Vec::new(), modules: Vec::new() })
+    }
+
+    pub fn signed_by(mut self, signer: &str) -> Self {
+        self.signer = Some(signer.to_string());
+        self
+    }
+
+    /// Hex blake3 of the payload.
+    pub fn hash(&self) -> &str {
+        &self.proof.policy_hash
+    }
+}
+
+/// One module of a composed policy, as recorded with its version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyModule {
+    pub name: String,
+    pub policy_hash: String,
+    /// As for `PolicyVersion::runtime`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<String>,
+    #[serde(with = "hex::serde")]
+    pub payload: Vec<u8>,
+}
+
+impl PolicyModule {
+    pub fn of(policy: &LoadedPolicy) -> Self {
+        Self {
+            name: policy.name.clone(),
+            policy_hash: policy.hash().to_string(),
+            runtime: Some(policy.runtime.name()).filter(|&name| name != NATIVE).map(str::to_string),
+            payload: policy.payload.clone(),
+        }
+    }
+}
+
+/// Body of an `EntryKind::PolicyVersion` entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyVersion {
+    pub name: String,
+    pub policy_hash: String,
+    #[serde(default)]
+    pub signer: Option<String>,
+    /// Hex package signature, if the policy was loaded from a signed package.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Further signers' signatures, for namespaces that require more than one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cosignatures: Vec<Cosignature>,
+    /// First tick decided under this policy.
+    pub effective_tick: u64,
+    pub max_steps: u64,
+    /// Runtime the payload is for, if not the native VM; see `vm::runtime`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<String>,
+    /// For a composed policy, whose payload is its `compose::Composition`, the
+    /// modules it references.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modules: Vec<PolicyModule>,
+    #[serde(with = "hex::serde")]
+    pub payload: Vec<u8>,
+}
+
+impl PolicyVersion {
+    /// Records the load as an `EntryKind::PolicyVersion` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::PolicyVersion, self)
+    }
+
+    pub fn of(policy: &LoadedPolicy, effective_tick: u64) -> Self {
+        Self {
+            name: policy.name.clone(),
+            policy_hash: policy.hash().to_string(),
+            signer: policy.signer.clone(),
+            signature: policy.signature.as_ref().map(hex::encode),
+            cosignatures: policy.cosignatures.clone(),
+            effective_tick,
+            max_steps: policy.proof.max_steps,
+            runtime: Some(policy.runtime.name()).filter(|&name| name != NATIVE).map(str::to_string),
+            modules: policy.modules.clone(),
+            payload: policy.payload.clone(),
+        }
+    }
+
+    /// Every policy version recorded in `ledger`, oldest first.
+    pub fn history(ledger: &DeterministicStore) -> LedgerResult<Vec<Self>> {
+        let mut versions = Vec::new();
+        let mut unreadable = None;
+        ledger.for_each_entry(|index, payload| {
+            let Some((EntryKind::PolicyVersion, body)) = entry::decode(payload) else { return };
+            match serde_json::from_slice::<PolicyVersion>(body) {
+                Ok(version) => versions.push(version),
+                Err(_) => unreadable = unreadable.or(Some(index)),
+            }
+        })?;
+        if let Some(index) = unreadable {
+            return Err(LedgerError::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unreadable policy version entry at index {}", index),
+            )));
+        }
+        Ok(versions)
+    }
+
+    /// What the decisions in `ledger` made under this version have exercised of it;
+    /// `None` if its runtime does not trace. Decisions recorded again on release or
+    /// override repeat an evaluation already counted and are skipped.
+    pub fn coverage(&self, ledger: &DeterministicStore) -> LedgerResult<Option<Coverage>> {
+        if self.runtime.is_some() {
+            return Ok(None);
+        }
+        let mut coverage = Coverage::new(&self.payload);
+        let mut unreadable = None;
+        ledger.for_each_entry(|index, payload| {
+            let Some((EntryKind::Decision, body)) = entry::decode(payload) else { return };
+            match serde_json::from_slice::<Decision>(body) {
+                Ok(decision) if decision.policy_hash == self.policy_hash && decision.approvers.is_empty() && decision.overridden_by.is_none() => {
+                    if let Some(trace) = &decision.policy_trace {
+                        coverage.record(trace);
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => unreadable = unreadable.or(Some(index)),
+            }
+        })?;
+        match unreadable {
+            Some(index) => Err(LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("unreadable decision entry at index {}", index)))),
+            None => Ok(Some(coverage)),
+        }
+    }
+}
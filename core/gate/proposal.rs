@@ -0,0 +1,88 @@
+//! Proposals submitted to the Gate.
+//!
+//! A `ProposedAction` is the only way anything outside the Gate asks for an
+//! actuation. Its canonical form is deterministic CBOR, so the same proposal hashes
+//! to the same value on every node regardless of argument order. The proposer's
+//! nonce is part of it, so a replayed proposal cannot be given a fresh one without
+//! changing its hash (see `replay`).
+
+use std::collections::HashMap;
+
+use crate::capability::id::{CapabilityError, CapabilityId};
+use crate::ledger::cbor::{self, CborError, Value};
+
+/// Mapped representation of the TypeScript `RfsnActionProposal`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProposedAction {
+    pub tool_name: String,
+    pub capability_required: String,
+    /// The proposer's assessment, one input to `risk::RiskScorer`.
+    pub risk_hint: String,
+    pub args: HashMap<String, String>,
+    /// Routes the proposal to its tenant's Gate; see `tenant`.
+    pub tenant: Option<String>,
+    /// Increases with every proposal its proposer submits; see `replay`.
+    pub nonce: Option<u64>,
+    /// The anomaly the proposal was made because of; see `anomaly`.
+    pub anomaly: Option<String>,
+}
+
+impl ProposedAction {
+    /// `capability_required` as a structured identifier, for the Gate's capability check.
+    pub fn capability(&self) -> Result<CapabilityId, CapabilityError> {
+        self.capability_required.parse()
+    }
+
+    /// Deterministic CBOR map of the proposal; `args` is a nested map of text. The
+    /// tenant, nonce and anomaly are only included if there are any, so proposals
+    /// without them hash as before.
+    pub fn canonical(&self) -> Vec<u8> {
+        let text = |s: &str| Value::Text(s.to_string());
+        let args = self.args.iter().map(|(k, v)| (text(k), text(v))).collect();
+        let mut fields = vec![
+            (text("tool"), text(&self.tool_name)),
+            (text("capability"), text(&self.capability_required)),
+            (text("risk"), text(&self.risk_hint)),
+            (text("args"), Value::Map(args)),
+        ];
+        if let Some(tenant) = &self.tenant {
+            fields.push((text("tenant"), text(tenant)));
+        }
+        if let Some(nonce) = self.nonce {
+            fields.push((text("nonce"), Value::Uint(nonce)));
+        }
+        if let Some(anomaly) = &self.anomaly {
+            fields.push((text("anomaly"), text(anomaly)));
+        }
+        cbor::encode(&Value::Map(fields))
+    }
+
+    /// The proposal `canonical` encoded as `value`.
+    pub fn from_value(value: &Value) -> Result<Self, CborError> {
+        let Value::Map(fields) = value else { return Err(CborError("expected a map")) };
+        let field = |name: &str| fields.iter().find(|(k, _)| matches!(k, Value::Text(key) if key == name)).map(|(_, v)| v);
+        let require = |name: &str| field(name).ok_or(CborError("missing map key"));
+        let args = match require("args")? {
+            Value::Map(entries) => entries.iter().map(|(k, v)| Ok((k.as_text()?.to_string(), v.as_text()?.to_string()))).collect::<Result<_, CborError>>()?,
+            _ => return Err(CborError("expected a map")),
+        };
+        Ok(Self {
+            tool_name: require("tool")?.as_text()?.to_string(),
+            capability_required: require("capability")?.as_text()?.to_string(),
+            risk_hint: require("risk")?.as_text()?.to_string(),
+            args,
+            tenant: field("tenant").map(Value::as_text).transpose()?.map(str::to_string),
+            nonce: field("nonce").map(Value::as_uint).transpose()?,
+            anomaly: field("anomaly").map(Value::as_text).transpose()?.map(str::to_string),
+        })
+    }
+
+    pub fn from_canonical(bytes: &[u8]) -> Result<Self, CborError> {
+        Self::from_value(&cbor::decode(bytes)?)
+    }
+
+    /// Hex blake3 of the canonical form.
+    pub fn hash(&self) -> String {
+        blake3::hash(&self.canonical()).to_hex().to_string()
+    }
+}
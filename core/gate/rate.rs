@@ -0,0 +1,181 @@
+//! Rate limits consulted by the Gate.
+//!
+//! A limiter is asked once per proposal that has otherwise been allowed, so denied
+//! proposals never use up a limit.
+//!
+//! `TokenBuckets` keeps one bucket per configured tool and per configured capability
+//! pattern. Buckets refill on ticks, not wall time, and only allowed decisions take a
+//! token, so the limiter's state is a function of the `EntryKind::Decision` entries
+//! in the ledger: `resume_from_ledger` replays them after a restart, and every node
+//! replaying the same ledger makes the same rate decisions.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+use super::decision::Decision;
+use crate::capability::id::CapabilityId;
+use crate::ledger::entry::{self, EntryKind};
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+
+/// The limit a proposal ran into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited {
+    /// Which limit, e.g. `tool:sys_diagnostic`.
+    pub key: String,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rate limit {} exhausted", self.key)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+pub trait RateLimiter: Send {
+    /// Takes one actuation of `tool` under `capability` at `tick`, or reports the
+    /// exhausted limit and takes nothing.
+    fn acquire(&mut self, tool: &str, capability: &CapabilityId, tick: u64) -> Result<(), RateLimited>;
+
+    /// What `acquire` would answer, without taking anything.
+    fn peek(&self, tool: &str, capability: &CapabilityId, tick: u64) -> Result<(), RateLimited>;
+}
+
+/// A bucket of `capacity` tokens that regains one every `refill_every` ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketSpec {
+    pub capacity: u32,
+    pub refill_every: u64,
+}
+
+impl BucketSpec {
+    /// At most `count` actuations per `ticks` ticks, all of which may come at once.
+    pub fn per(count: u32, ticks: u64) -> Self {
+        Self { capacity: count, refill_every: (ticks / u64::from(count.max(1))).max(1) }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: u32,
+    refilled_at: u64,
+}
+
+impl Bucket {
+    /// The bucket as of `tick`; a bucket never used before starts full.
+    fn at(bucket: Option<Bucket>, spec: BucketSpec, tick: u64) -> Bucket {
+        let Some(mut bucket) = bucket else { return Bucket { tokens: spec.capacity, refilled_at: tick } };
+        let refills = tick.saturating_sub(bucket.refilled_at) / spec.refill_every;
+        bucket.tokens = u64::from(bucket.tokens).saturating_add(refills).min(u64::from(spec.capacity)) as u32;
+        // A full bucket does not bank refills for later.
+        bucket.refilled_at = if bucket.tokens == spec.capacity {
+            tick.max(bucket.refilled_at)
+        } else {
+            bucket.refilled_at + refills * spec.refill_every
+        };
+        bucket
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TokenBuckets {
+    tools: BTreeMap<String, BucketSpec>,
+    capabilities: Vec<(CapabilityId, BucketSpec)>,
+    buckets: BTreeMap<String, Bucket>,
+}
+
+impl TokenBuckets {
+    pub fn per_tool(mut self, tool: &str, spec: BucketSpec) -> Self {
+        self.tools.insert(tool.to_string(), spec);
+        self
+    }
+
+    /// Limits every capability `pattern` covers, together.
+    pub fn per_capability(mut self, pattern: CapabilityId, spec: BucketSpec) -> Self {
+        self.capabilities.push((pattern, spec));
+        self
+    }
+
+    /// Keys and specs of the buckets an actuation of `tool` under `capability` draws on.
+    fn limits<'a>(&'a self, tool: &str, capability: &'a CapabilityId) -> impl Iterator<Item = (String, BucketSpec)> + 'a {
+        let tool = self.tools.get(tool).map(|&spec| (format!("tool:{}", tool), spec));
+        let capabilities = self.capabilities.iter().filter(move |(pattern, _)| pattern.covers(capability));
+        tool.into_iter().chain(capabilities.map(|(pattern, spec)| (format!("capability:{}", pattern), *spec)))
+    }
+
+    /// The buckets for `tool` and `capability` as of `tick`, or the first empty one.
+    fn refilled(&self, tool: &str, capability: &CapabilityId, tick: u64) -> Result<Vec<(String, Bucket)>, RateLimited> {
+        let mut refilled = Vec::new();
+        for (key, spec) in self.limits(tool, capability) {
+            let bucket = Bucket::at(self.buckets.get(&key).copied(), spec, tick);
+            if bucket.tokens == 0 {
+                return Err(RateLimited { key });
+            }
+            refilled.push((key, bucket));
+        }
+        Ok(refilled)
+    }
+
+    /// Replays the allowed decisions in `store`, so limits carry over a restart.
+    pub fn resume_from_ledger(mut self, store: &DeterministicStore) -> LedgerResult<Self> {
+        let mut unreadable = None;
+        store.for_each_entry(|index, payload| {
+            let Some((EntryKind::Decision, body)) = entry::decode(payload) else { return };
+            match serde_json::from_slice::<Decision>(body) {
+                Ok(decision) => {
+                    if let (true, Some(check)) = (decision.is_allowed(), &decision.capability) {
+                        // Allowed decisions took their tokens, so this cannot fail.
+                        let _ = self.acquire(&decision.tool, &check.capability, decision.tick);
+                    }
+                }
+                Err(_) => unreadable = unreadable.or(Some(index)),
+            }
+        })?;
+        if let Some(index) = unreadable {
+            return Err(LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("unreadable decision entry at index {}", index))));
+        }
+        Ok(self)
+    }
+}
+
+impl RateLimiter for TokenBuckets {
+    fn acquire(&mut self, tool: &str, capability: &CapabilityId, tick: u64) -> Result<(), RateLimited> {
+        for (key, mut bucket) in self.refilled(tool, capability, tick)? {
+            bucket.tokens -= 1;
+            self.buckets.insert(key, bucket);
+        }
+        Ok(())
+    }
+
+    fn peek(&self, tool: &str, capability: &CapabilityId, tick: u64) -> Result<(), RateLimited> {
+        self.refilled(tool, capability, tick).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_refill_on_ticks_and_limit_together() {
+        let read: CapabilityId = "sys:read".parse().unwrap();
+        let mut limits = TokenBuckets::default()
+            .per_tool("sys_diagnostic", BucketSpec { capacity: 2, refill_every: 10 })
+            .per_capability("sys:*".parse().unwrap(), BucketSpec::per(3, 60));
+
+        assert_eq!(limits.acquire("sys_diagnostic", &read, 0), Ok(()));
+        assert_eq!(limits.acquire("sys_diagnostic", &read, 1), Ok(()));
+        let exhausted = Err(RateLimited { key: "tool:sys_diagnostic".into() });
+        assert_eq!(limits.peek("sys_diagnostic", &read, 5), exhausted);
+        assert_eq!(limits.acquire("sys_diagnostic", &read, 5), exhausted);
+        // The capability bucket still has a token, for any tool.
+        assert_eq!(limits.acquire("other", &read, 5), Ok(()));
+        assert_eq!(limits.acquire("other", &read, 6), Err(RateLimited { key: "capability:sys:*".into() }));
+
+        // A tool token is back at tick 10, but the shared capability bucket refills slower.
+        assert_eq!(limits.acquire("sys_diagnostic", &read, 10), Err(RateLimited { key: "capability:sys:*".into() }));
+        assert_eq!(limits.acquire("sys_diagnostic", &read, 20), Ok(()));
+        assert_eq!(limits.acquire("unlimited", &"net:send".parse().unwrap(), 20), Ok(()));
+    }
+}
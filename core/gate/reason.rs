@@ -0,0 +1,220 @@
+//! Stable, machine-readable reason codes for decisions.
+//!
+//! `DenyReason` says in detail why a stage refused, and its shape follows the Gate's
+//! internals. Automation that branches on outcomes, the agent runtime included, reads
+//! `Decision::outcome` instead: allow, deny or escalate, with a `ReasonCode` from the
+//! registry below. A code's name never changes and is never reused; codes are only
+//! added, each with the registry version that introduced it, and
+//! `REASON_CODES_VERSION` is raised whenever one is. A consumer built against an
+//! older version can treat any name it does not know as a plain denial.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::decision::{Decision, DenyReason};
+use crate::capability::store::CheckOutcome;
+use crate::vm::interp::REASON_MALFORMED;
+
+/// Version of the registry this build emits.
+pub const REASON_CODES_VERSION: u32 = 3;
+
+/// Every code's name and the registry version that introduced it.
+pub const REASON_CODES: &[(&str, u32)] = &[
+    ("capability_missing", 1),
+    ("capability_malformed", 1),
+    ("capability_expired", 1),
+    ("capability_revoked", 1),
+    ("capability_spent", 1),
+    ("quota_exhausted", 1),
+    ("budget_exceeded", 1),
+    ("rate_limited", 1),
+    ("frozen", 1),
+    ("invalid_args", 1),
+    ("replayed", 1),
+    ("nonce_missing", 1),
+    ("policy_rule", 1),
+    ("policy_fault", 1),
+    ("subsystem_actuation", 2),
+    ("unauthenticated", 3),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum ReasonCode {
+    /// No grant covers the required capability.
+    CapabilityMissing,
+    CapabilityMalformed,
+    CapabilityExpired,
+    CapabilityRevoked,
+    /// The covering grant was single-use and is spent.
+    CapabilitySpent,
+    QuotaExhausted,
+    BudgetExceeded,
+    RateLimited,
+    Frozen,
+    InvalidArgs,
+    Replayed,
+    NonceMissing,
+    /// The policy denied or escalated with reason `rule`.
+    PolicyRule { rule: u16 },
+    /// The policy faulted; `fault` is the VM's code, from `0xfff0` up.
+    PolicyFault { fault: u16 },
+    /// An internal subsystem proposed a tool that is not read-only.
+    SubsystemActuation,
+    /// Made outside an open session on a Gate that requires sessions.
+    Unauthenticated,
+}
+
+impl ReasonCode {
+    /// The code's registry name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReasonCode::CapabilityMissing => "capability_missing",
+            ReasonCode::CapabilityMalformed => "capability_malformed",
+            ReasonCode::CapabilityExpired => "capability_expired",
+            ReasonCode::CapabilityRevoked => "capability_revoked",
+            ReasonCode::CapabilitySpent => "capability_spent",
+            ReasonCode::QuotaExhausted => "quota_exhausted",
+            ReasonCode::BudgetExceeded => "budget_exceeded",
+            ReasonCode::RateLimited => "rate_limited",
+            ReasonCode::Frozen => "frozen",
+            ReasonCode::InvalidArgs => "invalid_args",
+            ReasonCode::Replayed => "replayed",
+            ReasonCode::NonceMissing => "nonce_missing",
+            ReasonCode::PolicyRule { .. } => "policy_rule",
+            ReasonCode::PolicyFault { .. } => "policy_fault",
+            ReasonCode::SubsystemActuation => "subsystem_actuation",
+            ReasonCode::Unauthenticated => "unauthenticated",
+        }
+    }
+
+    /// The code named `name`, with the rule or fault number of a policy code.
+    pub fn from_name(name: &str, number: Option<u16>) -> Option<Self> {
+        let code = match name {
+            "capability_missing" => ReasonCode::CapabilityMissing,
+            "capability_malformed" => ReasonCode::CapabilityMalformed,
+            "capability_expired" => ReasonCode::CapabilityExpired,
+            "capability_revoked" => ReasonCode::CapabilityRevoked,
+            "capability_spent" => ReasonCode::CapabilitySpent,
+            "quota_exhausted" => ReasonCode::QuotaExhausted,
+            "budget_exceeded" => ReasonCode::BudgetExceeded,
+            "rate_limited" => ReasonCode::RateLimited,
+            "frozen" => ReasonCode::Frozen,
+            "invalid_args" => ReasonCode::InvalidArgs,
+            "replayed" => ReasonCode::Replayed,
+            "nonce_missing" => ReasonCode::NonceMissing,
+            "policy_rule" => ReasonCode::PolicyRule { rule: number? },
+            "policy_fault" => ReasonCode::PolicyFault { fault: number? },
+            "subsystem_actuation" => ReasonCode::SubsystemActuation,
+            "unauthenticated" => ReasonCode::Unauthenticated,
+            _ => return None,
+        };
+        Some(code)
+    }
+
+    /// The rule or fault number of a policy code.
+    pub fn number(&self) -> Option<u16> {
+        match self {
+            ReasonCode::PolicyRule { rule } => Some(*rule),
+            ReasonCode::PolicyFault { fault } => Some(*fault),
+            _ => None,
+        }
+    }
+
+    /// The registry version that introduced the code.
+    pub fn since(&self) -> u32 {
+        REASON_CODES.iter().find(|(name, _)| *name == self.name()).map_or(REASON_CODES_VERSION, |&(_, since)| since)
+    }
+
+    /// The code for a policy reason, telling rules from VM faults.
+    pub fn policy(code: u16) -> Self {
+        if code >= REASON_MALFORMED {
+            ReasonCode::PolicyFault { fault: code }
+        } else {
+            ReasonCode::PolicyRule { rule: code }
+        }
+    }
+
+    pub fn of(reason: &DenyReason) -> Self {
+        match reason {
+            DenyReason::MalformedCapability { .. } => ReasonCode::CapabilityMalformed,
+            DenyReason::Replayed { .. } => ReasonCode::Replayed,
+            DenyReason::MissingNonce => ReasonCode::NonceMissing,
+            DenyReason::Frozen { .. } => ReasonCode::Frozen,
+            DenyReason::InvalidArgs { .. } => ReasonCode::InvalidArgs,
+            DenyReason::SubsystemActuation { .. } => ReasonCode::SubsystemActuation,
+            DenyReason::Unauthenticated => ReasonCode::Unauthenticated,
+            DenyReason::Policy { code } => ReasonCode::policy(*code),
+            DenyReason::Budget { .. } => ReasonCode::BudgetExceeded,
+            DenyReason::Capability { outcome } => match outcome {
+                CheckOutcome::Granted { .. } | CheckOutcome::NoGrant => ReasonCode::CapabilityMissing,
+                CheckOutcome::Expired { .. } => ReasonCode::CapabilityExpired,
+                CheckOutcome::Revoked { .. } => ReasonCode::CapabilityRevoked,
+                CheckOutcome::Consumed { .. } => ReasonCode::CapabilitySpent,
+                CheckOutcome::Exhausted { .. } => ReasonCode::QuotaExhausted,
+            },
+            DenyReason::RateLimited { .. } => ReasonCode::RateLimited,
+            DenyReason::PendingApproval { code, .. } => ReasonCode::policy(*code),
+            DenyReason::AlreadyPending { .. } => ReasonCode::Replayed,
+        }
+    }
+}
+
+impl fmt::Display for ReasonCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReasonCode::PolicyRule { rule } => write!(f, "policy_rule:{}", rule),
+            ReasonCode::PolicyFault { fault } => write!(f, "policy_fault:{:#06x}", fault),
+            code => f.write_str(code.name()),
+        }
+    }
+}
+
+/// A decision reduced to what automation branches on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum DecisionOutcome {
+    Allow,
+    Deny { reason: ReasonCode },
+    /// Parked until `approval_id` is approved.
+    Escalate { reason: ReasonCode, approval_id: String },
+}
+
+impl DecisionOutcome {
+    pub fn of(decision: &Decision) -> Self {
+        match &decision.denied {
+            None => DecisionOutcome::Allow,
+            Some(DenyReason::PendingApproval { approval_id, code }) => {
+                DecisionOutcome::Escalate { reason: ReasonCode::policy(*code), approval_id: approval_id.clone() }
+            }
+            Some(denied) => DecisionOutcome::Deny { reason: ReasonCode::of(denied) },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wcet::watchdog::ExceededLimit;
+
+    #[test]
+    fn codes_serialize_under_their_registry_names() {
+        let codes = [
+            ReasonCode::of(&DenyReason::Capability { outcome: CheckOutcome::NoGrant }),
+            ReasonCode::of(&DenyReason::Budget { limit: ExceededLimit::Steps }),
+            ReasonCode::of(&DenyReason::RateLimited { key: "sys_diagnostic".into() }),
+            ReasonCode::of(&DenyReason::Frozen { freeze_id: "f1".into() }),
+            ReasonCode::of(&DenyReason::Policy { code: 7 }),
+            ReasonCode::of(&DenyReason::Policy { code: REASON_MALFORMED }),
+        ];
+        for code in codes {
+            let json = serde_json::to_value(code).unwrap();
+            assert_eq!(json["code"], code.name());
+            assert!(REASON_CODES.iter().any(|&(name, since)| name == code.name() && since <= REASON_CODES_VERSION));
+        }
+        assert_eq!(serde_json::to_string(&ReasonCode::PolicyRule { rule: 7 }).unwrap(), r#"{"code":"policy_rule","rule":7}"#);
+        let outcome = DecisionOutcome::Deny { reason: ReasonCode::CapabilityMissing };
+        assert_eq!(serde_json::to_string(&outcome).unwrap(), r#"{"outcome":"deny","reason":{"code":"capability_missing"}}"#);
+    }
+}
@@ -0,0 +1,70 @@
+//! Replay protection for proposals.
+//!
+//! A proposal may carry a `nonce`, which its proposer increases with every proposal
+//! it submits. The nonce is part of the canonical proposal, so it is covered by the
+//! proposal hash and by anything that signs the proposal. The Gate keeps the highest
+//! nonce it has decided on for each principal in a `NonceIndex`, and denies any
+//! proposal whose nonce is not above it, so a captured proposal cannot be submitted
+//! again later, not even one whose first submission was denied. Each decision that
+//! used a nonce records it, and `Gate::open` rebuilds the index from those decisions,
+//! so the protection survives a restart. A Gate built `with_required_nonces` also
+//! denies proposals that carry no nonce.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use super::decision::Decision;
+use crate::ledger::entry::{self, EntryKind};
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+
+/// The nonce a decision used up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProposerNonce {
+    pub principal: String,
+    pub nonce: u64,
+}
+
+/// The last nonce used by each principal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NonceIndex {
+    last: BTreeMap<String, u64>,
+}
+
+impl NonceIndex {
+    /// Replays the nonces recorded with the decisions in `ledger`.
+    pub fn from_ledger(ledger: &DeterministicStore) -> LedgerResult<Self> {
+        let mut index = Self::default();
+        let mut unreadable = None;
+        ledger.for_each_entry(|at, payload| {
+            let Some((EntryKind::Decision, body)) = entry::decode(payload) else { return };
+            match serde_json::from_slice::<Decision>(body) {
+                Ok(decision) => {
+                    if let Some(used) = decision.nonce {
+                        index.record(&used.principal, used.nonce);
+                    }
+                }
+                Err(_) => unreadable = unreadable.or(Some(at)),
+            }
+        })?;
+        match unreadable {
+            Some(at) => Err(LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("unreadable decision entry at index {}", at)))),
+            None => Ok(index),
+        }
+    }
+
+    pub fn last(&self, principal: &str) -> Option<u64> {
+        self.last.get(principal).copied()
+    }
+
+    /// Whether `principal` has not used `nonce` or any later one.
+    pub fn is_fresh(&self, principal: &str, nonce: u64) -> bool {
+        self.last(principal).is_none_or(|last| nonce > last)
+    }
+
+    pub fn record(&mut self, principal: &str, nonce: u64) {
+        let last = self.last.entry(principal.to_string()).or_insert(nonce);
+        *last = (*last).max(nonce);
+    }
+}
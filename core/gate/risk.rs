@@ -0,0 +1,166 @@
+//! Risk scoring of proposals.
+//!
+//! The proposer's `risk_hint` is only one input, and not a trusted one. The score a
+//! policy sees combines it with the tool's base risk, the number of anomalies seen
+//! in a recent window of ticks and the state of the cluster, into a number from 0 to
+//! `MAX_RISK` in the context variable `VAR_RISK`. Every input is deterministic, so
+//! nodes that feed the scorer the same anomalies and cluster state agree on the score.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use super::proposal::ProposedAction;
+
+pub const MAX_RISK: u32 = 1000;
+
+/// Base risk of tools without an entry of their own.
+pub const DEFAULT_TOOL_RISK: u32 = 100;
+
+/// The proposer's own assessment, or a tool's declared risk class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RiskHint {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl RiskHint {
+    /// Parses a proposal's `risk_hint`; anything unrecognised counts as `High`.
+    pub fn parse(hint: &str) -> Self {
+        match hint {
+            "low" => RiskHint::Low,
+            "medium" => RiskHint::Medium,
+            "critical" => RiskHint::Critical,
+            _ => RiskHint::High,
+        }
+    }
+
+    pub fn weight(self) -> u32 {
+        match self {
+            RiskHint::Low => 0,
+            RiskHint::Medium => 100,
+            RiskHint::High => 250,
+            RiskHint::Critical => 400,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClusterState {
+    #[default]
+    Healthy,
+    /// Peers unreachable or anchoring behind; decisions are less well witnessed.
+    Degraded,
+    /// The ledger is frozen, e.g. by `ledger::freeze::AnchoringFreeze`.
+    Frozen,
+}
+
+impl ClusterState {
+    fn weight(self) -> u32 {
+        match self {
+            ClusterState::Healthy => 0,
+            ClusterState::Degraded => 150,
+            ClusterState::Frozen => 400,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RiskScorer {
+    tool_risk: BTreeMap<String, u32>,
+    anomaly_window: u64,
+    anomaly_weight: u32,
+    /// Ticks of recent anomalies, oldest first.
+    anomalies: VecDeque<u64>,
+    cluster: ClusterState,
+}
+
+impl Default for RiskScorer {
+    fn default() -> Self {
+        Self { tool_risk: BTreeMap::new(), anomaly_window: 1_000, anomaly_weight: 50, anomalies: VecDeque::new(), cluster: ClusterState::Healthy }
+    }
+}
+
+impl RiskScorer {
+    pub fn tool_risk(mut self, tool: &str, risk: u32) -> Self {
+        self.tool_risk.insert(tool.to_string(), risk);
+        self
+    }
+
+    /// Each anomaly in the last `ticks` ticks adds `weight`.
+    pub fn anomaly_window(mut self, ticks: u64, weight: u32) -> Self {
+        self.anomaly_window = ticks;
+        self.anomaly_weight = weight;
+        self
+    }
+
+    /// Records an anomaly, e.g. a prediction error of the predictive loop, at `tick`.
+    pub fn record_anomaly(&mut self, tick: u64) {
+        let horizon = tick.saturating_sub(self.anomaly_window);
+        while self.anomalies.front().is_some_and(|&at| at < horizon) {
+            self.anomalies.pop_front();
+        }
+        self.anomalies.push_back(tick);
+    }
+
+    pub fn set_cluster_state(&mut self, state: ClusterState) {
+        self.cluster = state;
+    }
+
+    pub fn cluster_state(&self) -> ClusterState {
+        self.cluster
+    }
+
+    /// Anomalies in the window ending at `tick`.
+    pub fn recent_anomalies(&self, tick: u64) -> u32 {
+        let horizon = tick.saturating_sub(self.anomaly_window);
+        self.anomalies.iter().filter(|&&at| at >= horizon && at <= tick).count() as u32
+    }
+
+    pub fn score(&self, proposal: &ProposedAction, tick: u64) -> u32 {
+        let tool = self.tool_risk.get(&proposal.tool_name).copied().unwrap_or(DEFAULT_TOOL_RISK);
+        self.score_from(tool, proposal, tick)
+    }
+
+    /// `score` with the tool's base risk given, e.g. from a `tools::ToolRegistry`.
+    pub fn score_from(&self, tool: u32, proposal: &ProposedAction, tick: u64) -> u32 {
+        let anomalies = self.recent_anomalies(tick).saturating_mul(self.anomaly_weight);
+        RiskHint::parse(&proposal.risk_hint)
+            .weight()
+            .saturating_add(tool)
+            .saturating_add(anomalies)
+            .saturating_add(self.cluster.weight())
+            .min(MAX_RISK)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combines_hint_tool_anomalies_and_cluster() {
+        let proposal = |tool: &str, hint: &str| ProposedAction {
+            tool_name: tool.into(),
+            capability_required: "sys:read".into(),
+            risk_hint: hint.into(),
+            args: Default::default(),
+            tenant: None,
+            nonce: None,
+            anomaly: None,
+        };
+        let mut scorer = RiskScorer::default().tool_risk("shell", 500).anomaly_window(100, 50);
+        assert_eq!(scorer.score(&proposal("sys_diagnostic", "low"), 0), DEFAULT_TOOL_RISK);
+        assert_eq!(scorer.score(&proposal("shell", "whatever"), 0), 750);
+
+        scorer.record_anomaly(10);
+        scorer.record_anomaly(20);
+        assert_eq!(scorer.score(&proposal("sys_diagnostic", "low"), 50), DEFAULT_TOOL_RISK + 100);
+        assert_eq!(scorer.score(&proposal("sys_diagnostic", "low"), 115), DEFAULT_TOOL_RISK + 50);
+        scorer.set_cluster_state(ClusterState::Frozen);
+        assert_eq!(scorer.score(&proposal("shell", "critical"), 50), MAX_RISK);
+    }
+}
@@ -0,0 +1,488 @@
+//! Sandboxed actuation of allowed proposals.
+//!
+//! The Gate only decides; `SandboxExecutor` is what runs a tool once a decision
+//! allows it. Each tool maps to a program, started with the proposal's arguments as
+//! `--name=value` in name order and an empty environment, and confined before it
+//! executes:
+//!
+//! * rlimits on address space, CPU time, open files, processes and file size, and no
+//!   core dumps;
+//! * `no_new_privs` and a seccomp filter that refuses the syscalls a tool never needs
+//!   (tracing, mounting, namespaces, kernel modules, BPF);
+//! * a fresh network namespace, so no network, unless the principal holds the
+//!   executor's network capability;
+//! * with `readable` paths configured, a Landlock ruleset allowing filesystem access
+//!   only beneath them.
+//!
+//! Confinement uses Linux interfaces only (x86_64 and aarch64).
+//!
+//! Any confinement step that fails makes the child exit before the tool runs: the
+//! sandbox fails closed. The tool runs under its `ToolSpec::timeout_ms` if the Gate
+//! has a tool registry, else the executor's default, and is killed when that runs out.
+//!
+//! Every run appends an `EntryKind::Execution` entry with the exit status and hashes
+//! of the output, linked to the decision that allowed it by the decision's context
+//! hash. A successful run is then reported to the Gate with `record_actuation`, so
+//! single-use grants are spent.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::decision::Decision;
+use super::pipeline::Gate;
+use super::proposal::ProposedAction;
+use crate::capability::id::CapabilityId;
+use crate::ledger::entry::EntryKind;
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+
+/// Limits applied to every tool process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandboxLimits {
+    pub max_memory_bytes: u64,
+    pub max_cpu_seconds: u64,
+    pub max_open_files: u64,
+    /// Processes of the sandbox's user, including the tool itself.
+    pub max_processes: u64,
+    pub max_file_bytes: u64,
+    /// Output kept per stream; the rest is read and dropped.
+    pub max_output_bytes: usize,
+    /// For tools without a registry timeout.
+    pub default_timeout_ms: u64,
+}
+
+impl Default for SandboxLimits {
+    fn default() -> Self {
+        Self {
+            max_memory_bytes: 256 << 20,
+            max_cpu_seconds: 10,
+            max_open_files: 64,
+            max_processes: 16,
+            max_file_bytes: 16 << 20,
+            max_output_bytes: 1 << 20,
+            default_timeout_ms: 10_000,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExecError {
+    /// The decision denied the proposal.
+    NotAllowed,
+    /// The decision was made for a different proposal.
+    ProposalMismatch,
+    /// No program is configured for the tool.
+    UnknownTool { tool: String },
+    /// The Gate was frozen after the decision; see `freeze`.
+    Frozen { freeze_id: String },
+    Spawn(io::Error),
+    Ledger(LedgerError),
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::NotAllowed => write!(f, "the decision does not allow the proposal"),
+            ExecError::ProposalMismatch => write!(f, "the decision is for a different proposal"),
+            ExecError::UnknownTool { tool } => write!(f, "no program is configured for tool {}", tool),
+            ExecError::Frozen { freeze_id } => write!(f, "the gate is frozen by {}", freeze_id),
+            ExecError::Spawn(e) => write!(f, "could not start the sandboxed tool: {}", e),
+            ExecError::Ledger(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
+
+impl From<LedgerError> for ExecError {
+    fn from(e: LedgerError) -> Self {
+        ExecError::Ledger(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum ExitOutcome {
+    Exited { code: i32 },
+    /// Killed by a signal, e.g. `SIGXCPU` at the CPU limit or `SIGSYS` from seccomp.
+    Signaled { signal: i32 },
+    /// Killed by the executor when its timeout ran out.
+    TimedOut,
+}
+
+/// Body of an `EntryKind::Execution` entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionResult {
+    /// Context hash of the decision that allowed the run.
+    pub decision: String,
+    pub proposal_hash: String,
+    pub tool: String,
+    pub outcome: ExitOutcome,
+    /// Whether the tool had network access.
+    pub network: bool,
+    /// Hex blake3 of the full output streams, including what was not kept.
+    pub stdout_hash: String,
+    pub stderr_hash: String,
+    pub stdout_len: u64,
+    pub stderr_len: u64,
+    pub duration_ms: u64,
+}
+
+impl ExecutionResult {
+    /// Records the run as an `EntryKind::Execution` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::Execution, self)
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.outcome == ExitOutcome::Exited { code: 0 }
+    }
+}
+
+/// A finished run: the recorded result and the output kept.
+#[derive(Debug, Clone)]
+pub struct Execution {
+    pub result: ExecutionResult,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+pub struct SandboxExecutor {
+    programs: BTreeMap<String, PathBuf>,
+    limits: SandboxLimits,
+    /// Capability that lets a principal's tools use the network.
+    network: CapabilityId,
+    readable: Vec<PathBuf>,
+}
+
+impl SandboxExecutor {
+    pub fn new(network: CapabilityId) -> Self {
+        Self { programs: BTreeMap::new(), limits: SandboxLimits::default(), network, readable: Vec::new() }
+    }
+
+    pub fn program(mut self, tool: &str, path: impl Into<PathBuf>) -> Self {
+        self.programs.insert(tool.to_string(), path.into());
+        self
+    }
+
+    pub fn limits(mut self, limits: SandboxLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Allows reading (and executing) beneath `path`, and turns on Landlock: tools
+    /// can then touch nothing else. Programs, and the libraries they load, must lie
+    /// beneath a readable path.
+    pub fn readable(mut self, path: impl Into<PathBuf>) -> Self {
+        self.readable.push(path.into());
+        self
+    }
+
+    /// Runs `proposal` as `decision` allowed it, records the result and, if the
+    /// tool succeeded, reports the actuation to `gate`.
+    pub fn execute(&self, gate: &mut Gate, proposal: &ProposedAction, decision: &Decision) -> Result<Execution, ExecError> {
+        let (true, Some(check)) = (decision.is_allowed(), &decision.capability) else {
+            return Err(ExecError::NotAllowed);
+        };
+        if decision.proposal_hash != proposal.hash() {
+            return Err(ExecError::ProposalMismatch);
+        }
+        if let Some(freeze) = gate.freeze_for(&proposal.tool_name) {
+            return Err(ExecError::Frozen { freeze_id: freeze.freeze_id.clone() });
+        }
+        let program = self.programs.get(&proposal.tool_name).ok_or_else(|| ExecError::UnknownTool { tool: proposal.tool_name.clone() })?;
+        let network = gate.capabilities().answer(&check.principal, &self.network, gate.tick()).is_granted();
+        let timeout_ms = gate
+            .tools()
+            .and_then(|tools| tools.tools.get(&proposal.tool_name))
+            .and_then(|spec| spec.timeout_ms)
+            .unwrap_or(self.limits.default_timeout_ms);
+
+        let mut args: Vec<(&String, &String)> = proposal.args.iter().collect();
+        args.sort();
+        let mut command = Command::new(program);
+        command.args(args.iter().map(|(name, value)| format!("--{}={}", name, value)));
+        command.env_clear().stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        let confinement = Confinement::prepare(&self.limits, network, &self.readable).map_err(ExecError::Spawn)?;
+        // SAFETY: `confine` only makes raw syscalls on data prepared before the fork;
+        // it neither allocates nor takes locks.
+        unsafe { command.pre_exec(move || confinement.confine()) };
+
+        let started = Instant::now();
+        let mut child = command.spawn().map_err(ExecError::Spawn)?;
+        let stdout = capture(child.stdout.take(), self.limits.max_output_bytes);
+        let stderr = capture(child.stderr.take(), self.limits.max_output_bytes);
+        let outcome = wait(&mut child, Duration::from_millis(timeout_ms)).map_err(ExecError::Spawn)?;
+        let duration_ms = started.elapsed().as_millis() as u64;
+        let (stdout, stdout_hash, stdout_len) = stdout.join().expect("capture thread");
+        let (stderr, stderr_hash, stderr_len) = stderr.join().expect("capture thread");
+
+        let result = ExecutionResult {
+            decision: decision.context_hash.clone(),
+            proposal_hash: decision.proposal_hash.clone(),
+            tool: proposal.tool_name.clone(),
+            outcome,
+            network,
+            stdout_hash,
+            stderr_hash,
+            stdout_len,
+            stderr_len,
+            duration_ms,
+        };
+        gate.record_execution(&result)?;
+        if result.succeeded() {
+            gate.record_actuation(decision)?;
+        }
+        Ok(Execution { result, stdout, stderr })
+    }
+}
+
+/// Reads `stream` to the end on its own thread, so a chatty tool cannot block on a
+/// full pipe, keeping the first `keep` bytes. Returns them with the hash and length
+/// of everything read.
+fn capture(stream: Option<impl Read + Send + 'static>, keep: usize) -> thread::JoinHandle<(Vec<u8>, String, u64)> {
+    thread::spawn(move || {
+        let mut kept = Vec::new();
+        let mut hasher = blake3::Hasher::new();
+        let mut len = 0;
+        let mut buf = [0u8; 8192];
+        if let Some(mut stream) = stream {
+            while let Ok(n @ 1..) = stream.read(&mut buf) {
+                hasher.update(&buf[..n]);
+                len += n as u64;
+                kept.extend_from_slice(&buf[..n.min(keep.saturating_sub(kept.len()))]);
+            }
+        }
+        (kept, hasher.finalize().to_hex().to_string(), len)
+    })
+}
+
+fn wait(child: &mut Child, timeout: Duration) -> io::Result<ExitOutcome> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(match (status.code(), status.signal()) {
+                (Some(code), _) => ExitOutcome::Exited { code },
+                (None, signal) => ExitOutcome::Signaled { signal: signal.unwrap_or(0) },
+            });
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Ok(ExitOutcome::TimedOut);
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[cfg(target_env = "gnu")]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(target_env = "gnu"))]
+type Resource = libc::c_int;
+
+/// Everything the child needs to confine itself, built before the fork so that
+/// `confine` allocates nothing.
+struct Confinement {
+    rlimits: [(Resource, u64); 6],
+    network: bool,
+    filter: Vec<libc::sock_filter>,
+    /// `O_PATH` descriptors of the readable paths; empty leaves Landlock off.
+    readable: Vec<File>,
+}
+
+impl Confinement {
+    fn prepare(limits: &SandboxLimits, network: bool, readable: &[PathBuf]) -> io::Result<Self> {
+        let rlimits = [
+            (libc::RLIMIT_AS, limits.max_memory_bytes),
+            (libc::RLIMIT_CPU, limits.max_cpu_seconds),
+            (libc::RLIMIT_NOFILE, limits.max_open_files),
+            (libc::RLIMIT_NPROC, limits.max_processes),
+            (libc::RLIMIT_FSIZE, limits.max_file_bytes),
+            (libc::RLIMIT_CORE, 0),
+        ];
+        let readable = readable.iter().map(|path| open_path(path)).collect::<io::Result<_>>()?;
+        Ok(Self { rlimits, network, filter: seccomp_filter(), readable })
+    }
+
+    /// Runs in the forked child, before `exec`.
+    fn confine(&self) -> io::Result<()> {
+        let check = |ret: libc::c_int| if ret < 0 { Err(io::Error::last_os_error()) } else { Ok(()) };
+        // SAFETY: plain syscalls on values owned by `self`, which outlives them.
+        unsafe {
+            for &(resource, limit) in &self.rlimits {
+                check(libc::setrlimit(resource, &libc::rlimit { rlim_cur: limit, rlim_max: limit }))?;
+            }
+            if !self.network {
+                check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET))?;
+            }
+            check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1 as libc::c_ulong, 0 as libc::c_ulong, 0 as libc::c_ulong, 0 as libc::c_ulong))?;
+        }
+        if !self.readable.is_empty() {
+            self.landlock()?;
+        }
+        let program = libc::sock_fprog { len: self.filter.len() as u16, filter: self.filter.as_ptr() as *mut libc::sock_filter };
+        // SAFETY: `program` points into `self.filter`, which the kernel copies.
+        check(unsafe { libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER as libc::c_ulong, &program as *const libc::sock_fprog) })
+    }
+
+    /// Restricts the filesystem to reading and executing beneath `readable`.
+    fn landlock(&self) -> io::Result<()> {
+        let check = |ret: libc::c_long| if ret < 0 { Err(io::Error::last_os_error()) } else { Ok(ret) };
+        let attr = LandlockRulesetAttr { handled_access_fs: LANDLOCK_ACCESS_FS_ALL };
+        let flags: libc::c_uint = 0;
+        // SAFETY: the attributes are `repr(C)` as the kernel defines them and outlive
+        // the calls; `ruleset` is a descriptor this function owns.
+        unsafe {
+            let size = std::mem::size_of_val(&attr);
+            let ruleset = check(libc::syscall(libc::SYS_landlock_create_ruleset, &attr as *const LandlockRulesetAttr, size, flags))? as RawFd;
+            let mut restricted = Ok(0);
+            for dir in &self.readable {
+                let rule = LandlockPathBeneathAttr { allowed_access: LANDLOCK_ACCESS_FS_READ, parent_fd: dir.as_raw_fd() };
+                restricted = restricted.and_then(|_| {
+                    check(libc::syscall(libc::SYS_landlock_add_rule, ruleset, LANDLOCK_RULE_PATH_BENEATH, &rule as *const LandlockPathBeneathAttr, flags))
+                });
+            }
+            let restricted = restricted.and_then(|_| check(libc::syscall(libc::SYS_landlock_restrict_self, ruleset, flags)));
+            libc::close(ruleset);
+            restricted.map(|_| ())
+        }
+    }
+}
+
+fn open_path(path: &Path) -> io::Result<File> {
+    OpenOptions::new().read(true).custom_flags(libc::O_PATH | libc::O_CLOEXEC).open(path)
+}
+
+// Landlock ABI v1, from <linux/landlock.h>.
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+const LANDLOCK_ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const LANDLOCK_ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const LANDLOCK_ACCESS_FS_READ_DIR: u64 = 1 << 3;
+const LANDLOCK_ACCESS_FS_READ: u64 = LANDLOCK_ACCESS_FS_EXECUTE | LANDLOCK_ACCESS_FS_READ_FILE | LANDLOCK_ACCESS_FS_READ_DIR;
+/// Every access right of ABI v1 (bits 0 to 12).
+const LANDLOCK_ACCESS_FS_ALL: u64 = (1 << 13) - 1;
+
+#[repr(C)]
+struct LandlockRulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct LandlockPathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+// Classic BPF and seccomp, from <linux/filter.h> and <linux/seccomp.h>.
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_JEQ_K: u16 = 0x15;
+const BPF_RET_K: u16 = 0x06;
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+/// Offsets of `arch` and `nr` in `struct seccomp_data`.
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// Syscalls tools are refused, with `EPERM`.
+const DENIED_SYSCALLS: [libc::c_long; 22] = [
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_setns,
+    libc::SYS_unshare,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_kexec_load,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_userfaultfd,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_reboot,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+];
+
+fn bpf(code: u16, jt: u8, jf: u8, k: u32) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
+
+/// Kills a process of a foreign architecture outright (syscall numbers would not
+/// mean what the deny list says), refuses `DENIED_SYSCALLS` and allows the rest.
+fn seccomp_filter() -> Vec<libc::sock_filter> {
+    let mut filter = vec![
+        bpf(BPF_LD_W_ABS, 0, 0, SECCOMP_DATA_ARCH),
+        bpf(BPF_JEQ_K, 1, 0, AUDIT_ARCH),
+        bpf(BPF_RET_K, 0, 0, SECCOMP_RET_KILL_PROCESS),
+        bpf(BPF_LD_W_ABS, 0, 0, SECCOMP_DATA_NR),
+    ];
+    for nr in DENIED_SYSCALLS {
+        filter.push(bpf(BPF_JEQ_K, 0, 1, nr as u32));
+        filter.push(bpf(BPF_RET_K, 0, 0, SECCOMP_RET_ERRNO | libc::EPERM as u32));
+    }
+    filter.push(bpf(BPF_RET_K, 0, 0, SECCOMP_RET_ALLOW));
+    filter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capability::store::Grant;
+    use crate::gate::policy::LoadedPolicy;
+    use crate::ledger::storage::DeterministicStore;
+    use crate::vm::bytecode::{encode, ALLOW};
+
+    #[test]
+    fn runs_allowed_tools_and_records_the_result() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-sandbox-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let policy = LoadedPolicy::load("allow-all", encode(ALLOW, 0).to_vec()).unwrap();
+        let mut gate = Gate::open(DeterministicStore::new(&dir).unwrap(), policy).unwrap();
+        gate.issue(Grant::new("g1", "planner", "sys:read".parse().unwrap(), "operator", 0).single_use()).unwrap();
+        let executor = SandboxExecutor::new("net:connect".parse().unwrap()).program("echo", "/bin/echo");
+
+        let proposal = ProposedAction {
+            tool_name: "echo".into(),
+            capability_required: "sys:read".into(),
+            risk_hint: "low".into(),
+            args: [("message".to_string(), "hello".to_string())].into_iter().collect(),
+            tenant: None,
+            nonce: None,
+            anomaly: None,
+        };
+        let decision = gate.admit("planner", &proposal).unwrap();
+        let execution = executor.execute(&mut gate, &proposal, &decision).unwrap();
+        assert!(execution.result.succeeded());
+        assert!(!execution.result.network);
+        assert_eq!(execution.stdout, b"--message=hello\n");
+        assert_eq!(execution.result.decision, decision.context_hash);
+
+        // The single-use grant was spent by the run.
+        let again = gate.admit("planner", &proposal).unwrap();
+        assert!(!again.is_allowed());
+        assert!(matches!(executor.execute(&mut gate, &proposal, &again), Err(ExecError::NotAllowed)));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,182 @@
+//! Authenticated proposer sessions.
+//!
+//! A Gate built `with_sessions` only decides proposals made through a session: a
+//! proposer first authenticates with a `Credential`, either a `SessionToken` signed by
+//! a trusted issuer or the client certificate its mTLS connection presented, and
+//! `Gate::open_session` resolves that to the principal the session acts as. Every
+//! decision made in the session records the principal and the session id, and a
+//! proposal made outside one, or in a session that has expired or been closed, is
+//! denied as `Unauthenticated`. TLS itself is terminated by the transport; the Gate
+//! only sees the certificate it verified, and maps it to a principal by pinning its
+//! fingerprint. Open sessions live in memory only; after a restart proposers
+//! authenticate again.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+/// An issuer's attestation that its bearer is `principal` until `expires_at`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionToken {
+    pub principal: String,
+    /// Chosen by the issuer; unique among its tokens.
+    pub session_id: String,
+    pub issuer: String,
+    /// Last tick the session is valid at.
+    pub expires_at: u64,
+    /// Ed25519 signature by the issuer's key over `SessionToken::signing_payload`.
+    #[serde(with = "hex::serde")]
+    pub signature: Vec<u8>,
+}
+
+impl SessionToken {
+    pub fn signing_payload(principal: &str, session_id: &str, issuer: &str, expires_at: u64) -> Vec<u8> {
+        let mut out = b"RFSN-SESSION\0".to_vec();
+        for field in [principal.as_bytes(), session_id.as_bytes(), issuer.as_bytes()] {
+            out.extend_from_slice(&(field.len() as u64).to_le_bytes());
+            out.extend_from_slice(field);
+        }
+        out.extend_from_slice(&expires_at.to_le_bytes());
+        out
+    }
+
+    pub fn sign(principal: &str, session_id: &str, issuer: &str, expires_at: u64, key: &SigningKey) -> Self {
+        let signature = key.sign(&Self::signing_payload(principal, session_id, issuer, expires_at)).to_bytes().to_vec();
+        Self { principal: principal.to_string(), session_id: session_id.to_string(), issuer: issuer.to_string(), expires_at, signature }
+    }
+}
+
+/// What a proposer authenticates with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credential {
+    Token(SessionToken),
+    /// DER of the client certificate the transport's mTLS handshake verified.
+    Certificate(Vec<u8>),
+}
+
+/// How a session was authenticated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "kebab-case")]
+pub enum AuthMethod {
+    Token { issuer: String },
+    Certificate { fingerprint: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// The token's session id, or `cert:<fingerprint>` for a certificate.
+    pub session_id: String,
+    pub principal: String,
+    pub method: AuthMethod,
+    /// Last tick the session is valid at; certificate sessions last until closed.
+    pub expires_at: Option<u64>,
+}
+
+impl Session {
+    pub fn is_valid_at(&self, tick: u64) -> bool {
+        self.expires_at.is_none_or(|expires_at| tick <= expires_at)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionError {
+    UnknownIssuer { issuer: String },
+    BadSignature { issuer: String },
+    Expired { session_id: String, expires_at: u64 },
+    /// No principal is pinned to the certificate with this fingerprint.
+    UnknownCertificate { fingerprint: String },
+    /// The Gate was not built `with_sessions`.
+    NotRequired,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::UnknownIssuer { issuer } => write!(f, "{} is not a trusted session issuer", issuer),
+            SessionError::BadSignature { issuer } => write!(f, "session token signature by {} is invalid", issuer),
+            SessionError::Expired { session_id, expires_at } => write!(f, "session {} expired at tick {}", session_id, expires_at),
+            SessionError::UnknownCertificate { fingerprint } => write!(f, "no principal is pinned to certificate {}", fingerprint),
+            SessionError::NotRequired => write!(f, "the Gate does not use sessions"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// Hex blake3 of a certificate's DER.
+pub fn fingerprint(der: &[u8]) -> String {
+    blake3::hash(der).to_hex().to_string()
+}
+
+/// The token issuers and certificates a Gate accepts.
+#[derive(Debug, Clone, Default)]
+pub struct Authenticator {
+    pub issuers: BTreeMap<String, VerifyingKey>,
+    /// Principals by certificate fingerprint.
+    pub certificates: BTreeMap<String, String>,
+}
+
+impl Authenticator {
+    pub fn issuer(mut self, name: &str, key: VerifyingKey) -> Self {
+        self.issuers.insert(name.to_string(), key);
+        self
+    }
+
+    /// Pins the certificate `der` to `principal`.
+    pub fn certificate(mut self, der: &[u8], principal: &str) -> Self {
+        self.certificates.insert(fingerprint(der), principal.to_string());
+        self
+    }
+
+    /// The session `credential` opens at `tick`.
+    pub fn authenticate(&self, credential: &Credential, tick: u64) -> Result<Session, SessionError> {
+        match credential {
+            Credential::Token(token) => {
+                let key = self.issuers.get(&token.issuer).ok_or_else(|| SessionError::UnknownIssuer { issuer: token.issuer.clone() })?;
+                let bad = || SessionError::BadSignature { issuer: token.issuer.clone() };
+                let signature = ed25519_dalek::Signature::from_slice(&token.signature).map_err(|_| bad())?;
+                let payload = SessionToken::signing_payload(&token.principal, &token.session_id, &token.issuer, token.expires_at);
+                key.verify_strict(&payload, &signature).map_err(|_| bad())?;
+                if tick > token.expires_at {
+                    return Err(SessionError::Expired { session_id: token.session_id.clone(), expires_at: token.expires_at });
+                }
+                Ok(Session {
+                    session_id: token.session_id.clone(),
+                    principal: token.principal.clone(),
+                    method: AuthMethod::Token { issuer: token.issuer.clone() },
+                    expires_at: Some(token.expires_at),
+                })
+            }
+            Credential::Certificate(der) => {
+                let fingerprint = fingerprint(der);
+                let principal = self.certificates.get(&fingerprint).ok_or_else(|| SessionError::UnknownCertificate { fingerprint: fingerprint.clone() })?;
+                Ok(Session {
+                    session_id: format!("cert:{}", fingerprint),
+                    principal: principal.clone(),
+                    method: AuthMethod::Certificate { fingerprint },
+                    expires_at: None,
+                })
+            }
+        }
+    }
+}
+
+/// An authenticator and the sessions it has opened.
+#[derive(Debug, Clone)]
+pub struct Sessions {
+    pub authenticator: Authenticator,
+    pub open: BTreeMap<String, Session>,
+}
+
+impl Sessions {
+    pub fn new(authenticator: Authenticator) -> Self {
+        Self { authenticator, open: BTreeMap::new() }
+    }
+
+    /// The session `session_id`, if it is open and valid at `tick`.
+    pub fn resolve(&self, session_id: &str, tick: u64) -> Option<&Session> {
+        self.open.get(session_id).filter(|session| session.is_valid_at(tick))
+    }
+}
@@ -0,0 +1,112 @@
+//! Shadow evaluation of candidate policies.
+//!
+//! `Gate::load_shadow_policy` puts a candidate policy beside the active one. From then
+//! on, every proposal that reaches the policy stage of `admit` is also run through the
+//! candidate, against the same context and under the candidate's own proven budget,
+//! and the two outcomes are compared. The candidate is never enforced and nothing it
+//! decides reaches the ledger: the Gate keeps a `ShadowReport` of how often the two
+//! agreed and of the proposals they disagreed on, each tied to its recorded decision
+//! by context hash, to review before cutting over with `load_policy`.
+//!
+//! Proposals refused before the policy stage (frozen, replayed, invalid arguments)
+//! are not evaluated, since no policy would see them. A decision cache hit counts as
+//! an active `allow`.
+
+use super::decision::Decision;
+use super::policy::LoadedPolicy;
+use super::suite::Outcome;
+use crate::wcet::watchdog::Watchdog;
+
+/// A proposal the candidate decided differently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub principal: String,
+    pub tool_name: String,
+    pub proposal_hash: String,
+    /// Of the recorded decision.
+    pub context_hash: String,
+    pub tick: u64,
+    pub active: Outcome,
+    pub candidate: Outcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowReport {
+    pub candidate: String,
+    pub candidate_hash: String,
+    /// Proposals run through the candidate.
+    pub evaluated: u64,
+    pub agreed: u64,
+    /// The first divergences, up to the capacity the shadow was loaded with.
+    pub divergences: Vec<Divergence>,
+    /// Divergences past the capacity, counted but not kept.
+    pub dropped: u64,
+}
+
+impl ShadowReport {
+    pub fn diverged(&self) -> u64 {
+        self.evaluated - self.agreed
+    }
+}
+
+/// A candidate policy and what it would have decided so far.
+pub struct Shadow {
+    policy: LoadedPolicy,
+    capacity: usize,
+    report: ShadowReport,
+}
+
+impl Shadow {
+    /// Keeps at most `capacity` divergences.
+    pub fn new(policy: LoadedPolicy, capacity: usize) -> Self {
+        let report = ShadowReport {
+            candidate: policy.name.clone(),
+            candidate_hash: policy.hash().to_string(),
+            evaluated: 0,
+            agreed: 0,
+            divergences: Vec::new(),
+            dropped: 0,
+        };
+        Self { policy, capacity, report }
+    }
+
+    pub fn policy(&self) -> &LoadedPolicy {
+        &self.policy
+    }
+
+    pub fn report(&self) -> &ShadowReport {
+        &self.report
+    }
+
+    pub fn into_report(self) -> ShadowReport {
+        self.report
+    }
+
+    /// Runs the candidate on `context`, the bytes `decision` was evaluated against,
+    /// and compares its outcome with the `active` one.
+    pub fn observe(&mut self, principal: &str, tool_name: &str, context: &[u8], decision: &Decision, active: Outcome) {
+        let mut watchdog = Watchdog::new(&self.policy.name, self.policy.proof.execution_budget());
+        let candidate = match self.policy.runtime.decide(&self.policy.payload, context, &mut watchdog) {
+            Ok(verdict) => Outcome::Verdict(verdict),
+            Err(violation) => Outcome::Budget(violation.limit),
+        };
+        self.report.evaluated += 1;
+        if candidate == active {
+            self.report.agreed += 1;
+            return;
+        }
+        if self.report.divergences.len() == self.capacity {
+            self.report.dropped += 1;
+            return;
+        }
+        self.report.divergences.push(Divergence {
+            principal: principal.to_string(),
+            tool_name: tool_name.to_string(),
+            proposal_hash: decision.proposal_hash.clone(),
+            context_hash: decision.context_hash.clone(),
+            tick: decision.tick,
+            active,
+            candidate,
+        });
+    }
+}
@@ -0,0 +1,59 @@
+//! Internal subsystems as principals.
+//!
+//! The predictive loop and maintenance workers propose actions like any agent, under
+//! principals named `subsystem/<name>`, and their proposals take the same path
+//! through `Gate::admit`. What they may hold is narrower: a subsystem only ever gets a
+//! grant delegated from an operator's, through `Gate::delegate_to_subsystem`, which
+//! refuses a capability that covers any tool the registry does not mark read-only.
+//! The Gate also denies a subsystem's proposal for a tool that is not read-only
+//! before its capability is checked, so neither a later registry nor a grant issued
+//! some other way lets a subsystem actuate.
+
+use std::fmt;
+
+use super::tools::ToolRegistry;
+use crate::capability::id::CapabilityId;
+use crate::capability::store::DelegationError;
+
+pub const SUBSYSTEM_PREFIX: &str = "subsystem/";
+pub const PREDICTIVE_LOOP: &str = "subsystem/predictive-loop";
+pub const MAINTENANCE: &str = "subsystem/maintenance";
+
+pub fn is_subsystem(principal: &str) -> bool {
+    principal.starts_with(SUBSYSTEM_PREFIX)
+}
+
+#[derive(Debug)]
+pub enum SubsystemError {
+    /// The delegate is not named `subsystem/<name>`.
+    NotASubsystem { principal: String },
+    /// Without a tool registry nothing says which tools are read-only.
+    NoToolRegistry,
+    /// The capability covers tools that actuate.
+    Actuating { capability: CapabilityId, tools: Vec<String> },
+    Delegation(DelegationError),
+}
+
+impl fmt::Display for SubsystemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubsystemError::NotASubsystem { principal } => write!(f, "{} is not a subsystem principal", principal),
+            SubsystemError::NoToolRegistry => write!(f, "no tool registry is loaded to tell read-only tools"),
+            SubsystemError::Actuating { capability, tools } => write!(f, "{} covers actuating tools: {}", capability, tools.join(", ")),
+            SubsystemError::Delegation(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SubsystemError {}
+
+impl From<DelegationError> for SubsystemError {
+    fn from(e: DelegationError) -> Self {
+        SubsystemError::Delegation(e)
+    }
+}
+
+/// Tools in `tools` whose capability `capability` covers and that are not read-only.
+pub fn actuating_tools(tools: &ToolRegistry, capability: &CapabilityId) -> Vec<String> {
+    tools.tools.iter().filter(|(_, spec)| !spec.read_only && capability.covers(&spec.capability)).map(|(name, _)| name.clone()).collect()
+}
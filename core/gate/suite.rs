@@ -0,0 +1,206 @@
+//! Unit tests for policies.
+//!
+//! A `PolicySuite` is a list of cases: a proposal, the tick and context variables to
+//! evaluate it with, and the verdict the author expects. `run` evaluates every case
+//! against a loaded policy exactly as the Gate would, and reports the failures along
+//! with coverage: the decisions no case reached and the conditions no case drove
+//! both ways. Coverage comes from the VM's trace, so it is only reported for native
+//! bytecode.
+
+use std::collections::BTreeSet;
+
+use super::context::Context;
+use super::policy::LoadedPolicy;
+use super::proposal::ProposedAction;
+use crate::vm::bytecode::{fetch, ALLOW, DECIDE, DENY, ESCALATE, JZ};
+use crate::vm::interp::{Condition, Trace, Verdict};
+use crate::vm::runtime::NATIVE;
+use crate::wcet::watchdog::{ExceededLimit, Watchdog};
+
+#[derive(Debug, Clone)]
+pub struct PolicyCase {
+    pub name: String,
+    pub proposal: ProposedAction,
+    pub tick: u64,
+    /// Context variables set after the Gate's own, e.g. `VAR_RISK`.
+    pub vars: Vec<(u32, u32)>,
+    pub expect: Verdict,
+}
+
+impl PolicyCase {
+    pub fn new(name: &str, proposal: ProposedAction, expect: Verdict) -> Self {
+        Self { name: name.to_string(), proposal, tick: 0, vars: Vec::new(), expect }
+    }
+
+    pub fn at(mut self, tick: u64) -> Self {
+        self.tick = tick;
+        self
+    }
+
+    pub fn var(mut self, var: u32, value: u32) -> Self {
+        self.vars.push((var, value));
+        self
+    }
+
+    fn context(&self) -> Context {
+        let mut context = Context::new(&self.proposal, self.tick);
+        for &(var, value) in &self.vars {
+            context.set(var, value);
+        }
+        context
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Verdict(Verdict),
+    /// The policy was cut off by its watchdog.
+    Budget(ExceededLimit),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseFailure {
+    pub name: String,
+    pub expected: Verdict,
+    pub actual: Outcome,
+    /// The path the policy took, to see where it diverged.
+    pub trace: Trace,
+}
+
+/// What a suite left unexercised.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// `ALLOW`, `DENY`, `ESCALATE` and `DECIDE` instructions no case reached.
+    pub uncovered_decisions: Vec<usize>,
+    /// Condition outcomes no case produced; `held` is the missing outcome.
+    pub uncovered_conditions: Vec<Condition>,
+}
+
+impl Coverage {
+    pub fn is_complete(&self) -> bool {
+        self.uncovered_decisions.is_empty() && self.uncovered_conditions.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuiteReport {
+    pub passed: usize,
+    pub failures: Vec<CaseFailure>,
+    /// `None` for runtimes that cannot trace.
+    pub coverage: Option<Coverage>,
+}
+
+impl SuiteReport {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PolicySuite {
+    cases: Vec<PolicyCase>,
+}
+
+impl PolicySuite {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn case(mut self, case: PolicyCase) -> Self {
+        self.cases.push(case);
+        self
+    }
+
+    pub fn cases(&self) -> &[PolicyCase] {
+        &self.cases
+    }
+
+    pub fn run(&self, policy: &LoadedPolicy) -> SuiteReport {
+        let mut report = SuiteReport { passed: 0, failures: Vec::new(), coverage: None };
+        let mut decided = BTreeSet::new();
+        let mut seen = BTreeSet::new();
+        for case in &self.cases {
+            let mut trace = Trace::with_capacity(policy.proof.instructions);
+            let mut watchdog = Watchdog::new(&policy.name, policy.proof.execution_budget());
+            let actual = match policy.runtime.decide_traced(&policy.payload, case.context().bytes(), &mut watchdog, &mut trace) {
+                Ok(verdict) => Outcome::Verdict(verdict),
+                Err(violation) => Outcome::Budget(violation.limit),
+            };
+            decided.extend(trace.decided_at);
+            seen.extend(trace.conditions.iter().map(|c| (c.pc, c.held)));
+            if actual == Outcome::Verdict(case.expect) {
+                report.passed += 1;
+            } else {
+                report.failures.push(CaseFailure { name: case.name.clone(), expected: case.expect, actual, trace });
+            }
+        }
+        if policy.runtime.name() == NATIVE {
+            report.coverage = Some(coverage(&policy.payload, &decided, &seen));
+        }
+        report
+    }
+}
+
+fn coverage(payload: &[u8], decided: &BTreeSet<usize>, seen: &BTreeSet<(usize, bool)>) -> Coverage {
+    let mut coverage = Coverage::default();
+    let mut pc = 0;
+    while let Some((opcode, _)) = fetch(payload, pc) {
+        match opcode {
+            ALLOW | DENY | ESCALATE | DECIDE if !decided.contains(&pc) => coverage.uncovered_decisions.push(pc),
+            JZ => {
+                let missing = [true, false].into_iter().filter(|&held| !seen.contains(&(pc, held)));
+                coverage.uncovered_conditions.extend(missing.map(|held| Condition { pc, held }));
+            }
+            _ => {}
+        }
+        pc += 1;
+    }
+    coverage
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::gate::context::VAR_RISK;
+    use crate::vm::bytecode::*;
+
+    #[test]
+    fn reports_failures_and_untested_rules() {
+        // Deny 1 above risk 50, escalate 2 above 20, else allow.
+        let program = [
+            (LOAD32, VAR_RISK), (PUSH, 50), (GT, 0), (JZ, 5), (DENY, 1),
+            (LOAD32, VAR_RISK), (PUSH, 20), (GT, 0), (JZ, 10), (ESCALATE, 2),
+            (ALLOW, 0),
+        ];
+        let payload = program.iter().flat_map(|&(op, arg)| encode(op, arg)).collect();
+        let policy = LoadedPolicy::load("risk", payload).unwrap();
+        let proposal = ProposedAction {
+            tool_name: "sys_diagnostic".into(),
+            capability_required: "sys:read".into(),
+            risk_hint: "low".into(),
+            args: HashMap::new(),
+            tenant: None,
+            nonce: None,
+            anomaly: None,
+        };
+
+        let suite = PolicySuite::new()
+            .case(PolicyCase::new("high risk denies", proposal.clone(), Verdict::Deny { reason: 1 }).var(VAR_RISK, 90))
+            .case(PolicyCase::new("low risk allows", proposal.clone(), Verdict::Allow).var(VAR_RISK, 5))
+            .case(PolicyCase::new("medium risk allows", proposal, Verdict::Allow).var(VAR_RISK, 30));
+        let report = suite.run(&policy);
+        assert_eq!(report.passed, 2);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].actual, Outcome::Verdict(Verdict::Escalate { reason: 2 }));
+        assert_eq!(report.failures[0].trace.decided_at, Some(9));
+        // Every rule was reached, so coverage is complete.
+        assert!(report.coverage.unwrap().is_complete());
+
+        let untested = PolicySuite { cases: suite.cases()[..1].to_vec() }.run(&policy).coverage.unwrap();
+        assert_eq!(untested.uncovered_decisions, [9, 10]);
+        let missing = [Condition { pc: 3, held: false }, Condition { pc: 8, held: true }, Condition { pc: 8, held: false }];
+        assert_eq!(untested.uncovered_conditions, missing);
+    }
+}
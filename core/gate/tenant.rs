@@ -0,0 +1,149 @@
+//! Per-tenant Gates.
+//!
+//! `TenantGates` lets one process serve several independent agent populations. Each
+//! tenant gets a `Gate` of its own (policy, capability store, rate limits, approvals
+//! and everything else) over its own sub-ledger in `<base>/tenants/<tenant>`, so no
+//! decision, grant or limit of one tenant can touch another's. Proposals are routed by
+//! `ProposedAction::tenant`. One without a tenant, or naming a tenant that is not
+//! registered, is refused outright rather than falling back to some other Gate.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::decision::{Decision, Simulation};
+use super::pipeline::Gate;
+use super::policy::LoadedPolicy;
+use super::proposal::ProposedAction;
+use crate::ledger::storage::{DeterministicStore, LedgerError};
+
+#[derive(Debug)]
+pub enum TenantError {
+    /// The proposal names no tenant.
+    NoTenant,
+    UnknownTenant { tenant: String },
+    /// Tenant ids name directories: ASCII letters, digits, `-` and `_` only.
+    InvalidTenant { tenant: String },
+    DuplicateTenant { tenant: String },
+    Ledger(LedgerError),
+}
+
+impl fmt::Display for TenantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TenantError::NoTenant => write!(f, "the proposal names no tenant"),
+            TenantError::UnknownTenant { tenant } => write!(f, "no tenant {}", tenant),
+            TenantError::InvalidTenant { tenant } => write!(f, "{:?} is not a valid tenant id", tenant),
+            TenantError::DuplicateTenant { tenant } => write!(f, "tenant {} already exists", tenant),
+            TenantError::Ledger(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TenantError {}
+
+impl From<LedgerError> for TenantError {
+    fn from(e: LedgerError) -> Self {
+        TenantError::Ledger(e)
+    }
+}
+
+pub struct TenantGates {
+    base_dir: PathBuf,
+    gates: BTreeMap<String, Gate>,
+}
+
+impl TenantGates {
+    pub fn new(base_dir: &Path) -> Self {
+        Self { base_dir: base_dir.to_path_buf(), gates: BTreeMap::new() }
+    }
+
+    /// Directory of `tenant`'s sub-ledger.
+    pub fn ledger_dir(&self, tenant: &str) -> PathBuf {
+        self.base_dir.join("tenants").join(tenant)
+    }
+
+    /// Opens `tenant`'s Gate over its sub-ledger, running `policy`. `configure` adds
+    /// the tenant's rate limits, approvers and the like, e.g.
+    /// `|gate| gate.with_rate_limiter(limits)`.
+    pub fn add(&mut self, tenant: &str, policy: LoadedPolicy, configure: impl FnOnce(Gate) -> Gate) -> Result<&mut Gate, TenantError> {
+        let valid = !tenant.is_empty() && tenant.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !valid {
+            return Err(TenantError::InvalidTenant { tenant: tenant.to_string() });
+        }
+        if self.gates.contains_key(tenant) {
+            return Err(TenantError::DuplicateTenant { tenant: tenant.to_string() });
+        }
+        let gate = configure(Gate::open(DeterministicStore::new(&self.ledger_dir(tenant))?, policy)?);
+        Ok(self.gates.entry(tenant.to_string()).or_insert(gate))
+    }
+
+    pub fn tenants(&self) -> impl Iterator<Item = &str> {
+        self.gates.keys().map(String::as_str)
+    }
+
+    pub fn gate(&self, tenant: &str) -> Option<&Gate> {
+        self.gates.get(tenant)
+    }
+
+    /// For tenant administration: grants, policy reloads, approvals.
+    pub fn gate_mut(&mut self, tenant: &str) -> Option<&mut Gate> {
+        self.gates.get_mut(tenant)
+    }
+
+    /// `Gate::admit` on the proposal's tenant.
+    pub fn admit(&mut self, principal: &str, proposal: &ProposedAction) -> Result<Decision, TenantError> {
+        let tenant = proposal.tenant.as_deref().ok_or(TenantError::NoTenant)?;
+        let gate = self.gates.get_mut(tenant).ok_or_else(|| TenantError::UnknownTenant { tenant: tenant.to_string() })?;
+        Ok(gate.admit(principal, proposal)?)
+    }
+
+    /// `Gate::simulate` on the proposal's tenant.
+    pub fn simulate(&self, principal: &str, proposal: &ProposedAction) -> Result<Simulation, TenantError> {
+        let tenant = proposal.tenant.as_deref().ok_or(TenantError::NoTenant)?;
+        let gate = self.gates.get(tenant).ok_or_else(|| TenantError::UnknownTenant { tenant: tenant.to_string() })?;
+        Ok(gate.simulate(principal, proposal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::capability::store::Grant;
+    use crate::vm::bytecode::*;
+
+    fn proposal(tenant: Option<&str>) -> ProposedAction {
+        ProposedAction {
+            tool_name: "sys_diagnostic".into(),
+            capability_required: "sys:read".into(),
+            risk_hint: "low".into(),
+            args: HashMap::new(),
+            tenant: tenant.map(str::to_string),
+            nonce: None,
+            anomaly: None,
+        }
+    }
+
+    #[test]
+    fn tenants_share_nothing() {
+        let dir = std::env::temp_dir().join(format!("rfsn-tenants-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let policy = |op| LoadedPolicy::load("test", encode(op, 0).to_vec()).unwrap();
+        let mut gates = TenantGates::new(&dir);
+        gates.add("acme", policy(ALLOW), |gate| gate).unwrap();
+        gates.add("globex", policy(ALLOW), |gate| gate).unwrap();
+        assert!(matches!(gates.add("../acme", policy(ALLOW), |gate| gate), Err(TenantError::InvalidTenant { .. })));
+        gates.gate_mut("acme").unwrap().issue(Grant::new("g1", "planner", "sys:read".parse().unwrap(), "operator", 0)).unwrap();
+
+        assert!(gates.admit("planner", &proposal(Some("acme"))).unwrap().is_allowed());
+        // The same principal holds nothing in the other tenant.
+        assert!(!gates.admit("planner", &proposal(Some("globex"))).unwrap().is_allowed());
+        assert!(matches!(gates.admit("planner", &proposal(None)), Err(TenantError::NoTenant)));
+        assert!(matches!(gates.simulate("planner", &proposal(Some("initech"))), Err(TenantError::UnknownTenant { .. })));
+        assert_ne!(proposal(Some("acme")).hash(), proposal(Some("globex")).hash());
+        assert!(gates.ledger_dir("acme").exists() && gates.ledger_dir("globex").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
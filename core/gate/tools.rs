@@ -0,0 +1,346 @@
+//! Declared tools, what they require and the arguments they take.
+//!
+//! A `ToolRegistry` lists every tool the Gate admits proposals for. For each it
+//! declares the capability an actuation requires, its base risk class, its WCET
+//! budget, and the arguments it accepts: their type, bounds, allowed values and
+//! maximum length. With a registry loaded, the Gate takes the required capability and
+//! base risk from it rather than from the proposal, so a proposer cannot lower either
+//! by what it writes in `capability_required`.
+//!
+//! Arguments travel as strings in `ProposedAction.args`, so a type is what the value
+//! must parse as. The Gate validates a proposal against its tool's schema before the
+//! policy runs, so policies only ever see well-formed arguments, and a malformed
+//! proposal is denied with the exact `ArgViolation`. Validation walks arguments in
+//! name order, so every node reports the same violation for the same proposal.
+//!
+//! A tool can name a `SaferAlternative`: another tool, or the same one, with some
+//! arguments pinned, e.g. a dry run. When the policy denies a proposal for the tool,
+//! a Gate built `with_counter_proposals` offers the alternative as a counter-proposal
+//! the proposer can submit instead (see `Gate::with_counter_proposals`).
+//!
+//! Registries are config files (`ToolRegistry::load`). Each one the Gate loads is
+//! appended to the ledger, with its hash, as an `EntryKind::ToolRegistry` entry.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::proposal::ProposedAction;
+use super::risk::RiskHint;
+use crate::capability::id::CapabilityId;
+use crate::ledger::entry::{self, EntryKind};
+use crate::ledger::storage::{DeterministicStore, LedgerError, LedgerResult};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ArgType {
+    Integer {
+        #[serde(default)]
+        min: Option<i64>,
+        #[serde(default)]
+        max: Option<i64>,
+    },
+    /// `true` or `false`.
+    Boolean,
+    Text,
+    OneOf { values: Vec<String> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArgSchema {
+    #[serde(flatten)]
+    pub kind: ArgType,
+    #[serde(default)]
+    pub required: bool,
+    /// Longest value in bytes.
+    #[serde(default)]
+    pub max_len: Option<usize>,
+}
+
+impl ArgSchema {
+    fn of(kind: ArgType) -> Self {
+        Self { kind, required: false, max_len: None }
+    }
+
+    pub fn integer(min: Option<i64>, max: Option<i64>) -> Self {
+        Self::of(ArgType::Integer { min, max })
+    }
+
+    pub fn boolean() -> Self {
+        Self::of(ArgType::Boolean)
+    }
+
+    pub fn text(max_len: usize) -> Self {
+        Self { max_len: Some(max_len), ..Self::of(ArgType::Text) }
+    }
+
+    pub fn one_of(values: &[&str]) -> Self {
+        Self::of(ArgType::OneOf { values: values.iter().map(|v| v.to_string()).collect() })
+    }
+
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    fn check(&self, arg: &str, value: &str) -> Result<(), ArgViolation> {
+        let arg = || arg.to_string();
+        if let Some(max_len) = self.max_len.filter(|&max| value.len() > max) {
+            return Err(ArgViolation::TooLong { arg: arg(), max_len });
+        }
+        match &self.kind {
+            ArgType::Integer { min, max } => {
+                let n: i64 = value.parse().map_err(|_| ArgViolation::NotAnInteger { arg: arg() })?;
+                if min.is_some_and(|min| n < min) || max.is_some_and(|max| n > max) {
+                    return Err(ArgViolation::OutOfRange { arg: arg(), min: *min, max: *max });
+                }
+            }
+            ArgType::Boolean if value != "true" && value != "false" => return Err(ArgViolation::NotABoolean { arg: arg() }),
+            ArgType::OneOf { values } if !values.iter().any(|v| v == value) => return Err(ArgViolation::NotOneOf { arg: arg() }),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Why a proposal's arguments were refused.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "violation", rename_all = "kebab-case")]
+pub enum ArgViolation {
+    /// The tool is not in the registry.
+    UnknownTool { tool: String },
+    /// The tool takes no argument of this name.
+    UnknownArg { arg: String },
+    MissingArg { arg: String },
+    NotAnInteger { arg: String },
+    NotABoolean { arg: String },
+    OutOfRange { arg: String, min: Option<i64>, max: Option<i64> },
+    TooLong { arg: String, max_len: usize },
+    NotOneOf { arg: String },
+}
+
+impl fmt::Display for ArgViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgViolation::UnknownTool { tool } => write!(f, "unknown tool {}", tool),
+            ArgViolation::UnknownArg { arg } => write!(f, "unknown argument {}", arg),
+            ArgViolation::MissingArg { arg } => write!(f, "missing required argument {}", arg),
+            ArgViolation::NotAnInteger { arg } => write!(f, "argument {} is not an integer", arg),
+            ArgViolation::NotABoolean { arg } => write!(f, "argument {} is not true or false", arg),
+            ArgViolation::OutOfRange { arg, min, max } => write!(f, "argument {} is outside {:?}..={:?}", arg, min, max),
+            ArgViolation::TooLong { arg, max_len } => write!(f, "argument {} is longer than {} bytes", arg, max_len),
+            ArgViolation::NotOneOf { arg } => write!(f, "argument {} is not one of its allowed values", arg),
+        }
+    }
+}
+
+impl Error for ArgViolation {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolSpec {
+    /// What an actuation of the tool requires, whatever the proposal names.
+    pub capability: CapabilityId,
+    /// Base risk the scorer starts from for this tool.
+    pub risk: RiskHint,
+    /// WCET budget of one actuation, enforced by the executor.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub args: BTreeMap<String, ArgSchema>,
+    /// The tool only observes, so it keeps running while the Gate is frozen.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub read_only: bool,
+    /// What to propose instead when the policy denies the tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safer: Option<SaferAlternative>,
+}
+
+/// A lower-risk form of a proposal.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SaferAlternative {
+    /// The tool to propose; the denied one if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    /// Arguments pinned to these values. The proposal's other arguments are kept if
+    /// the tool takes them.
+    #[serde(default)]
+    pub args: BTreeMap<String, String>,
+}
+
+impl SaferAlternative {
+    pub fn tool(name: &str) -> Self {
+        Self { tool: Some(name.to_string()), args: BTreeMap::new() }
+    }
+
+    pub fn arg(mut self, name: &str, value: &str) -> Self {
+        self.args.insert(name.to_string(), value.to_string());
+        self
+    }
+}
+
+impl ToolSpec {
+    pub fn new(capability: CapabilityId, risk: RiskHint) -> Self {
+        Self { capability, risk, timeout_ms: None, args: BTreeMap::new(), read_only: false, safer: None }
+    }
+
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    pub fn timeout_ms(mut self, timeout_ms: u64) -> Self {
+        self.timeout_ms = Some(timeout_ms);
+        self
+    }
+
+    pub fn arg(mut self, name: &str, schema: ArgSchema) -> Self {
+        self.args.insert(name.to_string(), schema);
+        self
+    }
+
+    pub fn safer(mut self, alternative: SaferAlternative) -> Self {
+        self.safer = Some(alternative);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolRegistry {
+    pub tools: BTreeMap<String, ToolSpec>,
+}
+
+impl ToolRegistry {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn tool(mut self, name: &str, spec: ToolSpec) -> Self {
+        self.tools.insert(name.to_string(), spec);
+        self
+    }
+
+    /// Hex blake3 of the registry's JSON, which is canonical: maps are ordered.
+    pub fn hash(&self) -> String {
+        blake3::hash(&serde_json::to_vec(self).expect("registries serialize")).to_hex().to_string()
+    }
+
+    /// Checks `proposal`'s arguments against its tool's schema, and returns the tool.
+    pub fn validate(&self, proposal: &ProposedAction) -> Result<&ToolSpec, ArgViolation> {
+        let spec = self.tools.get(&proposal.tool_name).ok_or_else(|| ArgViolation::UnknownTool { tool: proposal.tool_name.clone() })?;
+        let given: BTreeSet<&String> = proposal.args.keys().collect();
+        if let Some(arg) = given.iter().find(|arg| !spec.args.contains_key(arg.as_str())) {
+            return Err(ArgViolation::UnknownArg { arg: arg.to_string() });
+        }
+        for (arg, schema) in &spec.args {
+            match proposal.args.get(arg) {
+                Some(value) => schema.check(arg, value)?,
+                None if schema.required => return Err(ArgViolation::MissingArg { arg: arg.clone() }),
+                None => {}
+            }
+        }
+        Ok(spec)
+    }
+
+    /// `proposal` in the form its tool's `SaferAlternative` describes, if the tool
+    /// has one and the result validates. The nonce is left to the caller.
+    pub fn counter_proposal(&self, proposal: &ProposedAction) -> Option<ProposedAction> {
+        let safer = self.tools.get(&proposal.tool_name)?.safer.as_ref()?;
+        let tool = safer.tool.clone().unwrap_or_else(|| proposal.tool_name.clone());
+        let spec = self.tools.get(&tool)?;
+        let mut args: HashMap<String, String> = proposal.args.iter().filter(|(arg, _)| spec.args.contains_key(*arg)).map(|(k, v)| (k.clone(), v.clone())).collect();
+        args.extend(safer.args.iter().map(|(k, v)| (k.clone(), v.clone())));
+        let counter = ProposedAction {
+            tool_name: tool,
+            capability_required: spec.capability.to_string(),
+            risk_hint: proposal.risk_hint.clone(),
+            args,
+            tenant: proposal.tenant.clone(),
+            nonce: None,
+            anomaly: proposal.anomaly.clone(),
+        };
+        let differs = counter.tool_name != proposal.tool_name || counter.args != proposal.args;
+        (differs && self.validate(&counter).is_ok()).then_some(counter)
+    }
+}
+
+/// Body of an `EntryKind::ToolRegistry` entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolsVersion {
+    pub registry_hash: String,
+    /// First tick decided under this registry.
+    pub effective_tick: u64,
+    pub registry: ToolRegistry,
+}
+
+impl ToolsVersion {
+    /// Records the registry as an `EntryKind::ToolRegistry` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::ToolRegistry, self)
+    }
+
+    pub fn of(registry: &ToolRegistry, effective_tick: u64) -> Self {
+        Self { registry_hash: registry.hash(), effective_tick, registry: registry.clone() }
+    }
+
+    /// The registry last recorded in `ledger`.
+    pub fn latest(ledger: &DeterministicStore) -> LedgerResult<Option<Self>> {
+        let mut latest = None;
+        let mut unreadable = None;
+        ledger.for_each_entry(|index, payload| {
+            let Some((EntryKind::ToolRegistry, body)) = entry::decode(payload) else { return };
+            match serde_json::from_slice::<ToolsVersion>(body) {
+                Ok(version) => latest = Some(version),
+                Err(_) => unreadable = unreadable.or(Some(index)),
+            }
+        })?;
+        match unreadable {
+            Some(index) => Err(LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("unreadable tool registry entry at index {}", index)))),
+            None => Ok(latest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proposal(args: &[(&str, &str)]) -> ProposedAction {
+        ProposedAction {
+            tool_name: "sys_diagnostic".into(),
+            capability_required: "sys:read".into(),
+            risk_hint: "low".into(),
+            args: args.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect(),
+            tenant: None,
+            nonce: None,
+            anomaly: None,
+        }
+    }
+
+    #[test]
+    fn validates_arguments_against_the_schema() {
+        let spec = ToolSpec::new("sys:read".parse().unwrap(), RiskHint::Low)
+            .arg("joint", ArgSchema::integer(Some(0), Some(6)).required())
+            .arg("verbose", ArgSchema::boolean())
+            .arg("mode", ArgSchema::one_of(&["quick", "full"]))
+            .arg("note", ArgSchema::text(8));
+        let tools = ToolRegistry::default().tool("sys_diagnostic", spec);
+
+        assert!(tools.validate(&proposal(&[("joint", "2"), ("mode", "full"), ("verbose", "true")])).is_ok());
+        let violation = |args: &[(&str, &str)]| tools.validate(&proposal(args)).unwrap_err();
+        assert_eq!(violation(&[]), ArgViolation::MissingArg { arg: "joint".into() });
+        assert_eq!(violation(&[("joint", "7")]), ArgViolation::OutOfRange { arg: "joint".into(), min: Some(0), max: Some(6) });
+        assert_eq!(violation(&[("joint", "two")]), ArgViolation::NotAnInteger { arg: "joint".into() });
+        assert_eq!(violation(&[("joint", "1"), ("verbose", "yes")]), ArgViolation::NotABoolean { arg: "verbose".into() });
+        assert_eq!(violation(&[("joint", "1"), ("mode", "deep")]), ArgViolation::NotOneOf { arg: "mode".into() });
+        assert_eq!(violation(&[("joint", "1"), ("note", "far too long")]), ArgViolation::TooLong { arg: "note".into(), max_len: 8 });
+        assert_eq!(violation(&[("joint", "1"), ("force", "1"), ("extra", "1")]), ArgViolation::UnknownArg { arg: "extra".into() });
+
+        let other = ProposedAction { tool_name: "arm_move".into(), ..proposal(&[]) };
+        assert_eq!(tools.validate(&other).unwrap_err(), ArgViolation::UnknownTool { tool: "arm_move".into() });
+    }
+}
@@ -0,0 +1,442 @@
+//! The wire protocol between external agents and the Gate.
+//!
+//! An agent connects over a Unix socket or TCP and sends `Propose` frames. The Gate
+//! answers each with a `Decided` frame, or with an `Error` frame if it could not
+//! decide. Every frame has the same six-byte header, the protocol version, the frame
+//! kind and the payload length as a big-endian `u32`, and then a deterministic CBOR
+//! map (`ledger::cbor`) with text keys:
+//!
+//! - `Propose`: `id`, the agent's request id, and `proposal`, the proposal in its
+//!   canonical form (`ProposedAction::canonical`), so the agent can compute the
+//!   proposal hash the decision will carry.
+//! - `Decided`: `id`; `outcome` (`allow`, `deny` or `escalate`), with `reason`, a
+//!   name from the reason-code registry, `rule` for policy codes and `approval` for
+//!   escalations (see `reason`); `proposal_hash`, `context_hash`, `policy_hash` and
+//!   `tick`; `explanation`, as `Decision::explain`; `counter`, with a denial the
+//!   Gate offers a counter-proposal for, that proposal in canonical form; and
+//!   `record`, the decision as recorded in the ledger, in JSON.
+//! - `Error`: `id`, `code` and `message`.
+//! - `Busy`: `id`; `saturated` (`queue` or `budget-pool`), `limit`, `queued_jobs`,
+//!   `queued_cycles` and `retry_after`, from the `wcet::executor::Backpressure` of a
+//!   front end that queues proposals for the Gate and could not take this one. The
+//!   proposal was not decided; the agent resubmits it after `retry_after` ticks.
+//!
+//! The principal is not part of the protocol: `serve` decides for the principal the
+//! connection was accepted for, and `serve_session` in the session the transport
+//! opened for it, on a Gate that requires sessions. A frame the Gate cannot read is answered with an
+//! `Error` and ends the connection, since nothing after it can be trusted to be in
+//! step; so does a ledger error, as the Gate fails closed.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use super::decision::Decision;
+use super::pipeline::Gate;
+use super::proposal::ProposedAction;
+use super::reason::{DecisionOutcome, ReasonCode};
+use crate::ledger::cbor::{self, CborError, Value};
+use crate::ledger::storage::{LedgerError, LedgerResult};
+use crate::wcet::executor::{Backpressure, Load, Saturation};
+
+pub const WIRE_VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 6;
+/// Largest payload either side accepts.
+pub const MAX_PAYLOAD: usize = 1 << 20;
+
+const KIND_PROPOSE: u8 = 1;
+const KIND_DECIDED: u8 = 2;
+const KIND_ERROR: u8 = 3;
+const KIND_BUSY: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    UnsupportedVersion,
+    Malformed,
+    /// The Gate could not record the decision, so there is none.
+    Ledger,
+}
+
+impl ErrorCode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorCode::UnsupportedVersion => "unsupported-version",
+            ErrorCode::Malformed => "malformed",
+            ErrorCode::Ledger => "ledger",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [ErrorCode::UnsupportedVersion, ErrorCode::Malformed, ErrorCode::Ledger].into_iter().find(|code| code.name() == name)
+    }
+}
+
+/// A decision as sent to the agent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WireDecision {
+    pub outcome: DecisionOutcome,
+    pub proposal_hash: String,
+    pub context_hash: String,
+    pub policy_hash: String,
+    pub tick: u64,
+    pub explanation: String,
+    /// What the proposer may submit instead; see `Gate::with_counter_proposals`.
+    pub counter: Option<ProposedAction>,
+    /// The ledger's JSON of the decision.
+    pub record: Vec<u8>,
+}
+
+impl WireDecision {
+    pub fn of(decision: &Decision) -> Self {
+        Self {
+            outcome: decision.outcome(),
+            proposal_hash: decision.proposal_hash.clone(),
+            context_hash: decision.context_hash.clone(),
+            policy_hash: decision.policy_hash.clone(),
+            tick: decision.tick,
+            explanation: decision.explain(),
+            counter: decision.counter_proposal(),
+            record: serde_json::to_vec(decision).expect("decisions serialize"),
+        }
+    }
+
+    /// The full decision, parsed from `record`.
+    pub fn decision(&self) -> serde_json::Result<Decision> {
+        serde_json::from_slice(&self.record)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Propose { id: u64, proposal: ProposedAction },
+    Decided { id: u64, decision: WireDecision },
+    Error { id: u64, code: ErrorCode, message: String },
+    Busy { id: u64, backpressure: Backpressure },
+}
+
+#[derive(Debug)]
+pub enum WireError {
+    Io(io::Error),
+    UnsupportedVersion(u8),
+    UnknownKind(u8),
+    TooLarge(usize),
+    Malformed(CborError),
+    Ledger(LedgerError),
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::Io(e) => write!(f, "{}", e),
+            WireError::UnsupportedVersion(v) => write!(f, "unsupported wire version {}", v),
+            WireError::UnknownKind(k) => write!(f, "unknown frame kind {}", k),
+            WireError::TooLarge(len) => write!(f, "frame payload of {} bytes exceeds {}", len, MAX_PAYLOAD),
+            WireError::Malformed(e) => write!(f, "{}", e),
+            WireError::Ledger(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+impl From<io::Error> for WireError {
+    fn from(e: io::Error) -> Self {
+        WireError::Io(e)
+    }
+}
+
+impl From<LedgerError> for WireError {
+    fn from(e: LedgerError) -> Self {
+        WireError::Ledger(e)
+    }
+}
+
+impl From<CborError> for WireError {
+    fn from(e: CborError) -> Self {
+        WireError::Malformed(e)
+    }
+}
+
+fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+fn field<'a>(map: &'a Value, name: &str) -> Result<Option<&'a Value>, CborError> {
+    match map {
+        Value::Map(entries) => Ok(entries.iter().find(|(k, _)| matches!(k, Value::Text(key) if key == name)).map(|(_, v)| v)),
+        _ => Err(CborError("expected a map")),
+    }
+}
+
+fn require<'a>(map: &'a Value, name: &str) -> Result<&'a Value, CborError> {
+    field(map, name)?.ok_or(CborError("missing map key"))
+}
+
+fn outcome_fields(outcome: &DecisionOutcome, fields: &mut Vec<(Value, Value)>) {
+    let (name, reason) = match outcome {
+        DecisionOutcome::Allow => ("allow", None),
+        DecisionOutcome::Deny { reason } => ("deny", Some(reason)),
+        DecisionOutcome::Escalate { reason, approval_id } => {
+            fields.push((text("approval"), text(approval_id)));
+            ("escalate", Some(reason))
+        }
+    };
+    fields.push((text("outcome"), text(name)));
+    if let Some(reason) = reason {
+        fields.push((text("reason"), text(reason.name())));
+        if let Some(number) = reason.number() {
+            fields.push((text("rule"), Value::Uint(number.into())));
+        }
+    }
+}
+
+fn outcome_from(value: &Value) -> Result<DecisionOutcome, CborError> {
+    let reason = || -> Result<ReasonCode, CborError> {
+        let number = field(value, "rule")?.map(Value::as_uint).transpose()?;
+        let number = number.map(|n| u16::try_from(n).map_err(|_| CborError("rule out of range"))).transpose()?;
+        ReasonCode::from_name(require(value, "reason")?.as_text()?, number).ok_or(CborError("unknown reason code"))
+    };
+    match require(value, "outcome")?.as_text()? {
+        "allow" => Ok(DecisionOutcome::Allow),
+        "deny" => Ok(DecisionOutcome::Deny { reason: reason()? }),
+        "escalate" => Ok(DecisionOutcome::Escalate { reason: reason()?, approval_id: require(value, "approval")?.as_text()?.to_string() }),
+        _ => Err(CborError("unknown outcome")),
+    }
+}
+
+impl Message {
+    pub fn id(&self) -> u64 {
+        match self {
+            Message::Propose { id, .. } | Message::Decided { id, .. } | Message::Error { id, .. } | Message::Busy { id, .. } => *id,
+        }
+    }
+
+    /// The frame: header and payload.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut fields = vec![(text("id"), Value::Uint(self.id()))];
+        let kind = match self {
+            Message::Propose { proposal, .. } => {
+                let canonical = cbor::decode(&proposal.canonical()).expect("canonical proposals decode");
+                fields.push((text("proposal"), canonical));
+                KIND_PROPOSE
+            }
+            Message::Decided { decision, .. } => {
+                outcome_fields(&decision.outcome, &mut fields);
+                fields.push((text("proposal_hash"), text(&decision.proposal_hash)));
+                fields.push((text("context_hash"), text(&decision.context_hash)));
+                fields.push((text("policy_hash"), text(&decision.policy_hash)));
+                fields.push((text("tick"), Value::Uint(decision.tick)));
+                fields.push((text("explanation"), text(&decision.explanation)));
+                if let Some(counter) = &decision.counter {
+                    fields.push((text("counter"), cbor::decode(&counter.canonical()).expect("canonical proposals decode")));
+                }
+                fields.push((text("record"), Value::Bytes(decision.record.clone())));
+                KIND_DECIDED
+            }
+            Message::Error { code, message, .. } => {
+                fields.push((text("code"), text(code.name())));
+                fields.push((text("message"), text(message)));
+                KIND_ERROR
+            }
+            Message::Busy { backpressure, .. } => {
+                fields.push((text("saturated"), text(backpressure.saturated.name())));
+                fields.push((text("limit"), Value::Uint(backpressure.limit)));
+                fields.push((text("queued_jobs"), Value::Uint(backpressure.load.jobs)));
+                fields.push((text("queued_cycles"), Value::Uint(backpressure.load.cycles)));
+                fields.push((text("retry_after"), Value::Uint(backpressure.retry_after)));
+                KIND_BUSY
+            }
+        };
+        let payload = cbor::encode(&Value::Map(fields));
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+        frame.push(WIRE_VERSION);
+        frame.push(kind);
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&payload);
+        frame
+    }
+
+    /// Decodes the payload of a frame of `kind`.
+    pub fn decode(kind: u8, payload: &[u8]) -> Result<Self, WireError> {
+        let map = cbor::decode(payload)?;
+        let id = require(&map, "id")?.as_uint()?;
+        let text_of = |name: &str| require(&map, name).and_then(Value::as_text).map(str::to_string);
+        let message = match kind {
+            KIND_PROPOSE => Message::Propose { id, proposal: ProposedAction::from_value(require(&map, "proposal")?)? },
+            KIND_DECIDED => Message::Decided {
+                id,
+                decision: WireDecision {
+                    outcome: outcome_from(&map)?,
+                    proposal_hash: text_of("proposal_hash")?,
+                    context_hash: text_of("context_hash")?,
+                    policy_hash: text_of("policy_hash")?,
+                    tick: require(&map, "tick")?.as_uint()?,
+                    explanation: text_of("explanation")?,
+                    counter: field(&map, "counter")?.map(ProposedAction::from_value).transpose()?,
+                    record: require(&map, "record")?.as_bytes()?.to_vec(),
+                },
+            },
+            KIND_ERROR => Message::Error {
+                id,
+                code: ErrorCode::from_name(&text_of("code")?).ok_or(CborError("unknown error code"))?,
+                message: text_of("message")?,
+            },
+            KIND_BUSY => Message::Busy {
+                id,
+                backpressure: Backpressure {
+                    saturated: Saturation::from_name(&text_of("saturated")?).ok_or(CborError("unknown saturation"))?,
+                    limit: require(&map, "limit")?.as_uint()?,
+                    load: Load { jobs: require(&map, "queued_jobs")?.as_uint()?, cycles: require(&map, "queued_cycles")?.as_uint()? },
+                    retry_after: require(&map, "retry_after")?.as_uint()?,
+                },
+            },
+            other => return Err(WireError::UnknownKind(other)),
+        };
+        Ok(message)
+    }
+}
+
+/// Reads one frame; `Ok(None)` if the stream ended cleanly before it.
+pub fn read_frame(reader: &mut impl Read) -> Result<Option<Message>, WireError> {
+    let mut header = [0u8; HEADER_LEN];
+    let mut filled = 0;
+    while filled < HEADER_LEN {
+        match reader.read(&mut header[filled..])? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(WireError::Io(io::ErrorKind::UnexpectedEof.into())),
+            n => filled += n,
+        }
+    }
+    if header[0] != WIRE_VERSION {
+        return Err(WireError::UnsupportedVersion(header[0]));
+    }
+    let len = u32::from_be_bytes(header[2..].try_into().unwrap()) as usize;
+    if len > MAX_PAYLOAD {
+        return Err(WireError::TooLarge(len));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    Message::decode(header[1], &payload).map(Some)
+}
+
+pub fn write_frame(writer: &mut impl Write, message: &Message) -> io::Result<()> {
+    writer.write_all(&message.encode())?;
+    writer.flush()
+}
+
+/// Decides every proposal read from `stream` for `principal` and writes back the
+/// answers, until the agent closes the stream or a frame or the ledger fails.
+/// Returns how many proposals were decided.
+pub fn serve<S: Read + Write>(gate: &mut Gate, principal: &str, stream: &mut S) -> Result<u64, WireError> {
+    serve_with(stream, |proposal| gate.admit(principal, proposal))
+}
+
+/// `serve` for a Gate that requires sessions, deciding in the session the transport
+/// opened for the peer, e.g. from its mTLS certificate.
+pub fn serve_session<S: Read + Write>(gate: &mut Gate, session_id: &str, stream: &mut S) -> Result<u64, WireError> {
+    serve_with(stream, |proposal| gate.admit_session(session_id, proposal))
+}
+
+fn serve_with<S: Read + Write>(stream: &mut S, mut admit: impl FnMut(&ProposedAction) -> LedgerResult<Decision>) -> Result<u64, WireError> {
+    let mut decided = 0;
+    loop {
+        let (id, proposal) = match read_frame(stream) {
+            Ok(None) => return Ok(decided),
+            Ok(Some(Message::Propose { id, proposal })) => (id, proposal),
+            Ok(Some(other)) => {
+                let refusal = Message::Error { id: other.id(), code: ErrorCode::Malformed, message: "only proposals are accepted".into() };
+                write_frame(stream, &refusal)?;
+                return Err(WireError::Malformed(CborError("unexpected frame kind")));
+            }
+            Err(e) => {
+                let code = match e {
+                    WireError::UnsupportedVersion(_) => ErrorCode::UnsupportedVersion,
+                    WireError::Io(e) => return Err(WireError::Io(e)),
+                    _ => ErrorCode::Malformed,
+                };
+                write_frame(stream, &Message::Error { id: 0, code, message: e.to_string() })?;
+                return Err(e);
+            }
+        };
+        match admit(&proposal) {
+            Ok(decision) => {
+                write_frame(stream, &Message::Decided { id, decision: WireDecision::of(&decision) })?;
+                decided += 1;
+            }
+            Err(e) => {
+                write_frame(stream, &Message::Error { id, code: ErrorCode::Ledger, message: e.to_string() })?;
+                return Err(e.into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::capability::store::Grant;
+    use crate::gate::policy::LoadedPolicy;
+    use crate::ledger::storage::DeterministicStore;
+    use crate::vm::bytecode::{encode, ALLOW};
+
+    /// An agent's side of a connection: what it sent, and what the Gate wrote back.
+    struct Connection {
+        sent: io::Cursor<Vec<u8>>,
+        received: Vec<u8>,
+    }
+
+    impl Read for Connection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.sent.read(buf)
+        }
+    }
+
+    impl Write for Connection {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.received.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn serves_proposals_framed_over_a_stream() {
+        let dir = std::env::temp_dir().join(format!("rfsn-gate-wire-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let policy = LoadedPolicy::load("allow", encode(ALLOW, 0).to_vec()).unwrap();
+        let mut gate = Gate::open(DeterministicStore::new(&dir).unwrap(), policy).unwrap();
+        gate.issue(Grant::new("g1", "planner", "sys:read".parse().unwrap(), "operator", 0)).unwrap();
+
+        let proposal = |capability: &str, nonce| ProposedAction {
+            tool_name: "sys_diagnostic".into(),
+            capability_required: capability.into(),
+            risk_hint: "low".into(),
+            args: HashMap::from([("verbose".to_string(), "1".to_string())]),
+            tenant: None,
+            nonce: Some(nonce),
+            anomaly: None,
+        };
+        let mut sent = Message::Propose { id: 7, proposal: proposal("sys:read", 1) }.encode();
+        sent.extend(Message::Propose { id: 8, proposal: proposal("sys:write", 2) }.encode());
+        sent.extend([9, KIND_PROPOSE, 0, 0, 0, 0]);
+        let mut connection = Connection { sent: io::Cursor::new(sent), received: Vec::new() };
+        assert!(matches!(serve(&mut gate, "planner", &mut connection), Err(WireError::UnsupportedVersion(9))));
+
+        let mut received = io::Cursor::new(connection.received);
+        let Some(Message::Decided { id: 7, decision }) = read_frame(&mut received).unwrap() else { panic!("expected a decision") };
+        assert_eq!((decision.outcome, decision.proposal_hash), (DecisionOutcome::Allow, proposal("sys:read", 1).hash()));
+        let Some(Message::Decided { id: 8, decision }) = read_frame(&mut received).unwrap() else { panic!("expected a decision") };
+        assert_eq!(decision.outcome, DecisionOutcome::Deny { reason: ReasonCode::CapabilityMissing });
+        let Some(Message::Error { code, .. }) = read_frame(&mut received).unwrap() else { panic!("expected an error") };
+        assert_eq!(code, ErrorCode::UnsupportedVersion);
+        assert!(read_frame(&mut received).unwrap().is_none());
+
+        let backpressure = Backpressure { saturated: Saturation::Queue, limit: 64, load: Load { jobs: 64, cycles: 128_000 }, retry_after: 2_000 };
+        let busy = Message::Busy { id: 9, backpressure };
+        assert_eq!(read_frame(&mut io::Cursor::new(busy.encode())).unwrap(), Some(busy));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
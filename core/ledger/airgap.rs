@@ -0,0 +1,183 @@
+//! Air-gapped notarization.
+//!
+//! Offline deployments cannot reach a witness. Instead:
+//! 1. the offline node calls `export_anchor_request`, producing a file signed with
+//!    its node key and remembering the pending export locally;
+//! 2. the file is carried across the gap and `notarize_export` runs on a connected
+//!    machine, which checks the node signature and writes a response file holding
+//!    the export plus the witness receipts;
+//! 3. the response is carried back and `import_receipt` verifies it against the
+//!    pending export and the locally pinned witness keys before attaching the
+//!    receipts to the checkpoint exactly like an online anchor.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use super::notarize::{anchor_request_from_checkpoint, AnchorRequest, NotaryClient, QuorumNotReached, QuorumStatus, Receipt};
+
+const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// The request file carried out of the air-gapped environment.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AnchorExport {
+    pub version: u32,
+    pub node_id: String,
+    pub request: AnchorRequest,
+    /// Ed25519 signature by the node key over `AnchorExport::signing_payload`.
+    #[serde(with = "hex::serde")]
+    pub signature: Vec<u8>,
+}
+
+impl AnchorExport {
+    pub fn signing_payload(version: u32, node_id: &str, request: &AnchorRequest) -> Vec<u8> {
+        let mut out = b"RFSN-ANCHOR-EXPORT\0".to_vec();
+        out.extend_from_slice(&version.to_le_bytes());
+        for field in [node_id.as_bytes(), request.ledger_head_hash.as_bytes()] {
+            out.extend_from_slice(&(field.len() as u64).to_le_bytes());
+            out.extend_from_slice(field);
+        }
+        out.extend_from_slice(&request.index.to_le_bytes());
+        out.extend_from_slice(&request.timestamp_ticks.to_le_bytes());
+        out
+    }
+
+    pub fn verify(&self, node_key: &VerifyingKey) -> Result<(), Box<dyn Error>> {
+        if self.version != EXPORT_FORMAT_VERSION {
+            return Err(format!("unsupported anchor export version {}", self.version).into());
+        }
+        let sig = ed25519_dalek::Signature::from_slice(&self.signature)?;
+        node_key.verify_strict(&Self::signing_payload(self.version, &self.node_id, &self.request), &sig)?;
+        Ok(())
+    }
+}
+
+/// The response file carried back into the air-gapped environment.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnchorImport {
+    pub export: AnchorExport,
+    pub receipts: Vec<Receipt>,
+}
+
+fn pending_export_path(checkpoint_path: &Path, index: u64) -> PathBuf {
+    checkpoint_path.with_extension(format!("{:016x}.export", index))
+}
+
+/// Offline side: writes a signed anchor request for the current checkpoint to `path`
+/// and records it as pending next to the checkpoint.
+pub fn export_anchor_request(
+    path: &Path,
+    checkpoint_path: &Path,
+    current_index: u64,
+    node_id: &str,
+    node_key: &SigningKey,
+) -> Result<AnchorExport, Box<dyn Error>> {
+    let request = anchor_request_from_checkpoint(&fs::read(checkpoint_path)?, current_index)?;
+    let payload = AnchorExport::signing_payload(EXPORT_FORMAT_VERSION, node_id, &request);
+    let export = AnchorExport {
+        version: EXPORT_FORMAT_VERSION,
+        node_id: node_id.to_string(),
+        request,
+        signature: node_key.sign(&payload).to_bytes().to_vec(),
+    };
+    let data = serde_json::to_string_pretty(&export)?;
+    fs::write(pending_export_path(checkpoint_path, current_index), &data)?;
+    fs::write(path, data)?;
+    println!("📤 Exported anchor request for Ledger Index {} to {}.", current_index, path.display());
+    Ok(export)
+}
+
+/// Connected side: verifies the export came from the expected node, notarizes it, and
+/// writes the response file to `response_path`.
+pub fn notarize_export(
+    export_path: &Path,
+    response_path: &Path,
+    node_key: &VerifyingKey,
+    client: &NotaryClient,
+    receipts_dir: &Path,
+) -> Result<QuorumStatus, Box<dyn Error>> {
+    let export: AnchorExport = serde_json::from_str(&fs::read_to_string(export_path)?)?;
+    export.verify(node_key)?;
+
+    // Receipts are also kept on the connected side, named after the exporting node.
+    let local_checkpoint = receipts_dir.join(format!("{}.chk", export.node_id));
+    let status = client.notarize_request(&local_checkpoint, &export.request)?;
+    let response = AnchorImport { export, receipts: status.receipts.clone() };
+    fs::write(response_path, serde_json::to_string_pretty(&response)?)?;
+    Ok(status)
+}
+
+/// Offline side: validates a response against the pending export and the local witness
+/// configuration, then attaches the receipts to the checkpoint.
+pub fn import_receipt(
+    path: &Path,
+    checkpoint_path: &Path,
+    node_key: &VerifyingKey,
+    client: &NotaryClient,
+) -> Result<QuorumStatus, Box<dyn Error>> {
+    let import: AnchorImport = serde_json::from_str(&fs::read_to_string(path)?)?;
+    import.export.verify(node_key)?;
+
+    let index = import.export.request.index;
+    let pending_path = pending_export_path(checkpoint_path, index);
+    let pending: AnchorExport = serde_json::from_str(
+        &fs::read_to_string(&pending_path).map_err(|_| format!("no pending anchor export for index {}", index))?,
+    )?;
+    if pending != import.export {
+        return Err(format!("imported receipts answer a different export than the one pending for index {}", index).into());
+    }
+
+    let status = client.verify_receipts(&pending.request, import.receipts);
+    NotaryClient::persist(checkpoint_path, &status)?;
+    if !status.anchored() {
+        return Err(Box::new(QuorumNotReached(status)));
+    }
+    fs::remove_file(pending_path)?;
+    println!(
+        "📥 Imported {}/{} witness receipts for Ledger Index {}.",
+        status.receipts.len(), status.witnesses, index
+    );
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::local_witness::LocalWitness;
+    use crate::ledger::merkle::{leaf_hash, Checkpoint, MerkleFrontier};
+
+    #[test]
+    fn receipts_carried_across_the_gap_attach_to_the_pending_export_once() {
+        let dir = std::env::temp_dir().join(format!("rfsn-airgap-roundtrip-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("connected")).unwrap();
+        let checkpoint = dir.join("merkle.chk");
+        let mut tree = MerkleFrontier::new();
+        for i in 0..4u8 {
+            tree.push(leaf_hash(&[i]));
+        }
+        fs::write(&checkpoint, Checkpoint::of(&tree, 9).encode()).unwrap();
+
+        let node_key = SigningKey::from_bytes(&[8; 32]);
+        let export = export_anchor_request(&dir.join("out.json"), &checkpoint, 4, "node-a", &node_key).unwrap();
+        assert!(export.verify(&node_key.verifying_key()).is_ok());
+        let impostor = SigningKey::from_bytes(&[9; 32]).verifying_key();
+
+        let witness = LocalWitness::from_seed("w1", [1; 32]);
+        let online = NotaryClient::with_backend(Box::new(witness.clone()));
+        let response = dir.join("in.json");
+        assert!(notarize_export(&dir.join("out.json"), &response, &impostor, &online, &dir.join("connected")).is_err());
+        notarize_export(&dir.join("out.json"), &response, &node_key.verifying_key(), &online, &dir.join("connected")).unwrap();
+        assert_eq!(witness.issued(), 1);
+
+        // The offline side only holds the witness's keys; a fresh local witness with the same seed verifies the same receipts.
+        let offline = NotaryClient::with_backend(Box::new(LocalWitness::from_seed("w1", [1; 32])));
+        let status = import_receipt(&response, &checkpoint, &node_key.verifying_key(), &offline).unwrap();
+        assert_eq!((status.request, status.receipts.len()), (export.request, 1));
+        assert!(import_receipt(&response, &checkpoint, &node_key.verifying_key(), &offline).is_err_and(|e| e.to_string().contains("no pending")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
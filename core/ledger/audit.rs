@@ -0,0 +1,286 @@
+//! Receipt-chain audit.
+//!
+//! Walks every piece of anchoring evidence we hold — quorum files, batch records and
+//! `NotaryReceipt` ledger entries — and checks it against the ledger as it exists
+//! now:
+//! - the anchored head equals the Merkle root recomputed from the entries;
+//! - the receipts verify under the configured witnesses and meet the threshold;
+//! - each anchored head is consistent (RFC 6962) with the previous one, i.e. the
+//!   ledger between them was only appended to;
+//! - witness timestamps move forward with the ledger.
+//!
+//! The result is a timeline report, which is what an external auditor wants.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::batch::{BatchMembership, BatchedAnchor, BATCH_HEAD_PREFIX};
+use super::entry::{self, EntryKind, ReceiptEntry};
+use super::merkle::{consistency_proof, leaf_hash, root_of, verify_consistency, Checkpoint};
+use super::notarize::{AnchorRequest, NotaryClient, QuorumStatus, Receipt};
+use super::storage::DeterministicStore;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvidenceSource {
+    QuorumFile(PathBuf),
+    BatchFile(PathBuf),
+    /// `NotaryReceipt` entry at this ledger index.
+    LedgerEntry(u64),
+}
+
+impl fmt::Display for EvidenceSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvidenceSource::QuorumFile(path) | EvidenceSource::BatchFile(path) => write!(f, "{}", path.display()),
+            EvidenceSource::LedgerEntry(index) => write!(f, "ledger entry {}", index),
+        }
+    }
+}
+
+struct Evidence {
+    source: EvidenceSource,
+    request: AnchorRequest,
+    membership: Option<BatchMembership>,
+    receipts: Vec<Receipt>,
+}
+
+/// Audit result for one anchored ledger index.
+#[derive(Debug, Clone)]
+pub struct AuditedAnchor {
+    pub index: u64,
+    pub ledger_head_hash: String,
+    pub timestamp_ticks: u64,
+    pub sources: Vec<EvidenceSource>,
+    /// Distinct witnesses with a valid receipt, best across all sources.
+    pub valid_receipts: usize,
+    pub threshold: usize,
+    pub earliest_external_timestamp: Option<u64>,
+    pub head_matches_ledger: bool,
+    /// Consistency with the previous anchored head; `None` for the first anchor.
+    pub consistent_with_previous: Option<bool>,
+    pub problems: Vec<String>,
+}
+
+impl AuditedAnchor {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditReport {
+    pub tree_size: u64,
+    pub anchors: Vec<AuditedAnchor>,
+    /// Problems not tied to one anchor (unreadable evidence, a bad checkpoint file...).
+    pub problems: Vec<String>,
+}
+
+impl AuditReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty() && self.anchors.iter().all(AuditedAnchor::is_clean)
+    }
+}
+
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Ledger audit: {} entries, {} anchored checkpoints", self.tree_size, self.anchors.len())?;
+        for a in &self.anchors {
+            let mark = if a.is_clean() { "✅" } else { "❌" };
+            let ext = a.earliest_external_timestamp.map_or("-".to_string(), |t| t.to_string());
+            writeln!(
+                f,
+                "{} index {:>10}  ticks {:>12}  witnessed {:>12}  {}/{} receipts  head {}",
+                mark, a.index, a.timestamp_ticks, ext, a.valid_receipts, a.threshold, a.ledger_head_hash
+            )?;
+            for problem in &a.problems {
+                writeln!(f, "     - {}", problem)?;
+            }
+        }
+        for problem in &self.problems {
+            writeln!(f, "❌ {}", problem)?;
+        }
+        if self.is_clean() {
+            write!(f, "✅ Receipt chain is intact.")
+        } else {
+            write!(f, "❌ Receipt chain has problems.")
+        }
+    }
+}
+
+fn read_evidence(path: &Path, suffix: &str) -> Result<Option<Evidence>, Box<dyn Error>> {
+    if suffix.ends_with(".quorum") {
+        let status: QuorumStatus = serde_json::from_str(&fs::read_to_string(path)?)?;
+        return Ok(Some(Evidence {
+            source: EvidenceSource::QuorumFile(path.to_path_buf()),
+            request: status.request,
+            membership: None,
+            receipts: status.receipts,
+        }));
+    }
+    if suffix.ends_with(".batch") {
+        let anchor: BatchedAnchor = serde_json::from_str(&fs::read_to_string(path)?)?;
+        return Ok(Some(Evidence {
+            source: EvidenceSource::BatchFile(path.to_path_buf()),
+            request: anchor.membership.member.clone(),
+            membership: Some(anchor.membership),
+            receipts: anchor.status.receipts,
+        }));
+    }
+    Ok(None)
+}
+
+/// Quorum and batch files the notary wrote next to `checkpoint_path`.
+fn evidence_files(checkpoint_path: &Path, report: &mut AuditReport) -> Result<Vec<Evidence>, Box<dyn Error>> {
+    let dir = checkpoint_path.parent().unwrap_or(Path::new("."));
+    let stem = checkpoint_path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let mut found = Vec::new();
+    for dirent in fs::read_dir(dir)? {
+        let path = dirent?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else { continue };
+        let Some(rest) = name.strip_prefix(stem).and_then(|r| r.strip_prefix('.')) else { continue };
+        match read_evidence(&path, rest) {
+            Ok(Some(evidence)) => found.push(evidence),
+            Ok(None) => {}
+            Err(e) => report.problems.push(format!("unreadable evidence {}: {}", path.display(), e)),
+        }
+    }
+    Ok(found)
+}
+
+/// Audits every anchor recorded for `store` against `client`'s witnesses.
+pub fn audit_ledger(store: &DeterministicStore, client: &NotaryClient) -> Result<AuditReport, Box<dyn Error>> {
+    let mut report = AuditReport::default();
+    let mut leaves = Vec::new();
+    let mut evidence = Vec::new();
+    store.for_each_entry(|index, payload| {
+        leaves.push(leaf_hash(payload));
+        let Some((EntryKind::NotaryReceipt, body)) = entry::decode(payload) else { return };
+        match serde_json::from_slice::<ReceiptEntry>(body) {
+            Ok(recorded) => {
+                let Some(request) = recorded.receipts.first().map(|r| r.request.clone()) else { return };
+                // Batch commitments carry no per-checkpoint membership; they are covered by `.batch` files.
+                if !request.ledger_head_hash.starts_with(BATCH_HEAD_PREFIX) {
+                    evidence.push(Evidence {
+                        source: EvidenceSource::LedgerEntry(index),
+                        request,
+                        membership: None,
+                        receipts: recorded.receipts,
+                    });
+                }
+            }
+            Err(e) => report.problems.push(format!("unreadable receipt entry at index {}: {}", index, e)),
+        }
+    })?;
+    report.tree_size = leaves.len() as u64;
+    evidence.extend(evidence_files(&store.checkpoint_path(), &mut report)?);
+
+    match fs::read(store.checkpoint_path()) {
+        Ok(bytes) => match Checkpoint::decode(&bytes) {
+            Ok(chk) if chk.tree_size > report.tree_size => {
+                report.problems.push(format!("checkpoint covers {} entries but the ledger has {}", chk.tree_size, report.tree_size));
+            }
+            Ok(chk) if chk.root != root_of(&leaves[..chk.tree_size as usize]) => {
+                report.problems.push("checkpoint root does not match the ledger".to_string());
+            }
+            Ok(_) => {}
+            Err(e) => report.problems.push(format!("current checkpoint is invalid: {}", e)),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    let mut by_index: BTreeMap<u64, Vec<Evidence>> = BTreeMap::new();
+    for e in evidence {
+        by_index.entry(e.request.index).or_default().push(e);
+    }
+
+    let mut previous: Option<(u64, [u8; 32], Option<u64>)> = None;
+    for (index, items) in by_index {
+        let request = items[0].request.clone();
+        let mut anchor = AuditedAnchor {
+            index,
+            ledger_head_hash: request.ledger_head_hash.clone(),
+            timestamp_ticks: request.timestamp_ticks,
+            sources: Vec::new(),
+            valid_receipts: 0,
+            threshold: client.threshold(),
+            earliest_external_timestamp: None,
+            head_matches_ledger: false,
+            consistent_with_previous: None,
+            problems: Vec::new(),
+        };
+        // Missing witnesses are only reported when the quorum is not met.
+        let mut witness_failures = Vec::new();
+
+        for item in items {
+            if item.request != request {
+                anchor.problems.push(format!("{} anchors a different head for this index: {}", item.source, item.request.ledger_head_hash));
+            }
+            let commitment = match &item.membership {
+                Some(membership) => match membership.verify() {
+                    Ok(()) => membership.commitment.clone(),
+                    Err(e) => {
+                        anchor.problems.push(format!("{}: {}", item.source, e));
+                        anchor.sources.push(item.source);
+                        continue;
+                    }
+                },
+                None => item.request.clone(),
+            };
+            let status = client.verify_receipts(&commitment, item.receipts);
+            anchor.valid_receipts = anchor.valid_receipts.max(status.receipts.len());
+            let earliest = status.receipts.iter().map(|r| r.external_timestamp).min();
+            anchor.earliest_external_timestamp = match (anchor.earliest_external_timestamp, earliest) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            for (backend, why) in status.failures {
+                witness_failures.push(format!("{}: witness {}: {}", item.source, backend, why));
+            }
+            if let EvidenceSource::LedgerEntry(at) = item.source {
+                if at < index {
+                    anchor.problems.push(format!("receipt recorded at ledger index {} predates the head it covers", at));
+                }
+            }
+            anchor.sources.push(item.source);
+        }
+        if anchor.valid_receipts < anchor.threshold {
+            anchor.problems.push(format!("only {} of {} required witnesses verified", anchor.valid_receipts, anchor.threshold));
+            anchor.problems.extend(witness_failures);
+        }
+
+        let anchored_root: Option<[u8; 32]> = hex::decode(&request.ledger_head_hash).ok().and_then(|b| b.try_into().ok());
+        if index > report.tree_size {
+            anchor.problems.push(format!("anchored index is beyond the end of the ledger ({} entries): history was truncated", report.tree_size));
+        } else {
+            anchor.head_matches_ledger = anchored_root == Some(root_of(&leaves[..index as usize]));
+            if !anchor.head_matches_ledger {
+                anchor.problems.push("anchored head does not match the ledger: history was rewritten".to_string());
+            }
+        }
+
+        if let (Some((prev_index, prev_root, prev_ext)), Some(root)) = (previous, anchored_root) {
+            if index <= report.tree_size {
+                let proof = consistency_proof(&leaves[..index as usize], prev_index as usize);
+                let consistent = verify_consistency(prev_index, index, &prev_root, &root, &proof);
+                anchor.consistent_with_previous = Some(consistent);
+                if !consistent {
+                    anchor.problems.push(format!("not consistent with the head anchored at index {}", prev_index));
+                }
+            }
+            if let (Some(prev), Some(ext)) = (prev_ext, anchor.earliest_external_timestamp) {
+                if ext < prev {
+                    anchor.problems.push(format!("witnessed at {} but the earlier head at index {} was witnessed at {}", ext, prev_index, prev));
+                }
+            }
+        }
+        if let Some(root) = anchored_root {
+            previous = Some((index, root, anchor.earliest_external_timestamp));
+        }
+        report.anchors.push(anchor);
+    }
+    Ok(report)
+}
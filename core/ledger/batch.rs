@@ -0,0 +1,160 @@
+//! Batch notarization.
+//!
+//! After downtime the outbox can hold hundreds of pending checkpoints. Instead of one
+//! witness round per checkpoint, the pending requests are committed to with a Merkle
+//! tree and only the commitment is notarized. Each checkpoint then keeps a
+//! `BatchedAnchor`: its inclusion proof in the batch plus the witness receipts for
+//! the commitment, which together prove the checkpoint was witnessed.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::merkle::{inclusion_proof, leaf_hash, root_of, verify_inclusion};
+use super::notarize::{receipt_path, AnchorRequest, NotaryClient, NotaryError, QuorumNotReached, QuorumStatus};
+use super::receipt_cose::write_receipt_file;
+
+/// `ledger_head_hash` prefix of a batch commitment, so it can never be mistaken for a ledger head.
+pub const BATCH_HEAD_PREFIX: &str = "batch:";
+
+fn member_leaf(req: &AnchorRequest) -> [u8; 32] {
+    let mut buf = b"RFSN-BATCH-MEMBER\0".to_vec();
+    buf.extend_from_slice(&(req.ledger_head_hash.len() as u64).to_le_bytes());
+    buf.extend_from_slice(req.ledger_head_hash.as_bytes());
+    buf.extend_from_slice(&req.index.to_le_bytes());
+    buf.extend_from_slice(&req.timestamp_ticks.to_le_bytes());
+    leaf_hash(&buf)
+}
+
+/// A set of checkpoint requests notarized together, ordered by ledger index.
+#[derive(Debug, Clone)]
+pub struct AnchorBatch {
+    members: Vec<AnchorRequest>,
+    leaves: Vec<[u8; 32]>,
+}
+
+impl AnchorBatch {
+    pub fn new(mut members: Vec<AnchorRequest>) -> Result<Self, Box<dyn Error>> {
+        if members.is_empty() {
+            return Err("cannot notarize an empty batch".into());
+        }
+        members.sort_by_key(|r| r.index);
+        if members.windows(2).any(|w| w[0].index == w[1].index) {
+            return Err("batch contains the same ledger index twice".into());
+        }
+        let leaves = members.iter().map(member_leaf).collect();
+        Ok(Self { members, leaves })
+    }
+
+    pub fn members(&self) -> &[AnchorRequest] {
+        &self.members
+    }
+
+    /// The single request sent to the witnesses. It carries the index and tick of the
+    /// newest member.
+    pub fn commitment(&self) -> AnchorRequest {
+        let newest = self.members.last().unwrap();
+        AnchorRequest {
+            ledger_head_hash: format!("{}{}", BATCH_HEAD_PREFIX, hex::encode(root_of(&self.leaves))),
+            index: newest.index,
+            timestamp_ticks: newest.timestamp_ticks,
+        }
+    }
+
+    pub fn membership(&self, position: usize) -> BatchMembership {
+        BatchMembership {
+            member: self.members[position].clone(),
+            position: position as u64,
+            batch_size: self.members.len() as u64,
+            proof: inclusion_proof(&self.leaves, position).iter().map(hex::encode).collect(),
+            commitment: self.commitment(),
+        }
+    }
+}
+
+/// Proof that one checkpoint request is part of a batch commitment.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BatchMembership {
+    pub member: AnchorRequest,
+    pub position: u64,
+    pub batch_size: u64,
+    /// Hex-encoded audit path, leaf-most sibling first.
+    pub proof: Vec<String>,
+    pub commitment: AnchorRequest,
+}
+
+impl BatchMembership {
+    pub fn verify(&self) -> Result<(), NotaryError> {
+        let root_hex = self.commitment.ledger_head_hash
+            .strip_prefix(BATCH_HEAD_PREFIX)
+            .ok_or(NotaryError::ReceiptMismatch("commitment is not a batch head"))?;
+        let root: [u8; 32] = hex::decode(root_hex)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| NotaryError::Malformed("batch root".to_string()))?;
+        let proof = self.proof
+            .iter()
+            .map(|p| hex::decode(p).ok().and_then(|b| b.try_into().ok()))
+            .collect::<Option<Vec<[u8; 32]>>>()
+            .ok_or_else(|| NotaryError::Malformed("batch inclusion proof".to_string()))?;
+        if !verify_inclusion(&member_leaf(&self.member), self.position, self.batch_size, &proof, &root) {
+            return Err(NotaryError::ReceiptMismatch("checkpoint is not included in the batch commitment"));
+        }
+        Ok(())
+    }
+}
+
+/// Per-checkpoint record of a batched anchor, stored next to the checkpoint.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchedAnchor {
+    pub membership: BatchMembership,
+    /// Quorum over the batch commitment.
+    pub status: QuorumStatus,
+}
+
+pub(crate) fn batch_path(checkpoint_path: &Path, index: u64) -> PathBuf {
+    checkpoint_path.with_extension(format!("{:016x}.batch", index))
+}
+
+impl NotaryClient {
+    /// Notarizes several checkpoint heads with one witness round and writes a
+    /// `BatchedAnchor` for each of them next to `checkpoint_path`.
+    pub fn notarize_batch(
+        &self,
+        checkpoint_path: &Path,
+        requests: Vec<AnchorRequest>,
+    ) -> Result<Vec<BatchedAnchor>, Box<dyn Error>> {
+        let batch = AnchorBatch::new(requests)?;
+        let status = self.collect_receipts(&batch.commitment());
+        if !status.anchored() {
+            return Err(Box::new(QuorumNotReached(status)));
+        }
+        for receipt in &status.receipts {
+            write_receipt_file(&receipt_path(checkpoint_path, receipt), receipt)?;
+        }
+        let mut anchors = Vec::with_capacity(batch.members().len());
+        for position in 0..batch.members().len() {
+            let anchor = BatchedAnchor { membership: batch.membership(position), status: status.clone() };
+            fs::write(batch_path(checkpoint_path, anchor.membership.member.index), serde_json::to_string_pretty(&anchor)?)?;
+            anchors.push(anchor);
+        }
+        println!(
+            "✅ Anchored {} checkpoints (Ledger Index {}..={}) in one batch with {}/{} witnesses.",
+            anchors.len(),
+            batch.members()[0].index,
+            status.request.index,
+            status.receipts.len(),
+            status.witnesses
+        );
+        Ok(anchors)
+    }
+
+    /// Re-verifies a batched anchor: the inclusion proof, then the receipts for the
+    /// commitment against the configured witnesses.
+    pub fn verify_batched(&self, anchor: &BatchedAnchor) -> Result<QuorumStatus, Box<dyn Error>> {
+        anchor.membership.verify()?;
+        Ok(self.verify_receipts(&anchor.membership.commitment, anchor.status.receipts.clone()))
+    }
+}
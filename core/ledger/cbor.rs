@@ -0,0 +1,268 @@
+//! Minimal deterministic CBOR (RFC 8949 §4.2.1) reader/writer.
+//!
+//! Covers only what the receipt format needs: unsigned and negative integers, byte
+//! and text strings, arrays, maps and tags. Encoding is always the core deterministic
+//! form (shortest arguments, definite lengths, map keys sorted bytewise), and the
+//! decoder rejects anything else, so a decoded value re-encodes to the same bytes.
+
+use std::fmt;
+
+pub const TAG_COSE_SIGN1: u64 = 18;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CborError(pub &'static str);
+
+impl fmt::Display for CborError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CBOR decode error: {}", self.0)
+    }
+}
+
+impl std::error::Error for CborError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Uint(u64),
+    /// Negative integer `-1 - n`, stored as `n`.
+    Nint(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    /// Entries in any order; `encode` sorts them.
+    Map(Vec<(Value, Value)>),
+    Tag(u64, Box<Value>),
+}
+
+impl Value {
+    pub fn int(v: i64) -> Self {
+        if v >= 0 { Value::Uint(v as u64) } else { Value::Nint((-1 - v) as u64) }
+    }
+
+    pub fn as_uint(&self) -> Result<u64, CborError> {
+        match self {
+            Value::Uint(v) => Ok(*v),
+            _ => Err(CborError("expected an unsigned integer")),
+        }
+    }
+
+    pub fn as_bytes(&self) -> Result<&[u8], CborError> {
+        match self {
+            Value::Bytes(b) => Ok(b),
+            _ => Err(CborError("expected a byte string")),
+        }
+    }
+
+    pub fn as_text(&self) -> Result<&str, CborError> {
+        match self {
+            Value::Text(s) => Ok(s),
+            _ => Err(CborError("expected a text string")),
+        }
+    }
+
+    pub fn as_array(&self) -> Result<&[Value], CborError> {
+        match self {
+            Value::Array(items) => Ok(items),
+            _ => Err(CborError("expected an array")),
+        }
+    }
+
+    /// Looks up an integer key in a map.
+    pub fn get(&self, key: i64) -> Result<Option<&Value>, CborError> {
+        match self {
+            Value::Map(entries) => Ok(entries.iter().find(|(k, _)| *k == Value::int(key)).map(|(_, v)| v)),
+            _ => Err(CborError("expected a map")),
+        }
+    }
+
+    pub fn require(&self, key: i64) -> Result<&Value, CborError> {
+        self.get(key)?.ok_or(CborError("missing map key"))
+    }
+}
+
+fn encode_head(major: u8, arg: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    match arg {
+        0..=23 => out.push(major | arg as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, arg as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(arg as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(arg as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&arg.to_be_bytes());
+        }
+    }
+}
+
+fn encode_into(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Uint(v) => encode_head(0, *v, out),
+        Value::Nint(v) => encode_head(1, *v, out),
+        Value::Bytes(b) => {
+            encode_head(2, b.len() as u64, out);
+            out.extend_from_slice(b);
+        }
+        Value::Text(s) => {
+            encode_head(3, s.len() as u64, out);
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Array(items) => {
+            encode_head(4, items.len() as u64, out);
+            for item in items {
+                encode_into(item, out);
+            }
+        }
+        Value::Map(entries) => {
+            let mut encoded: Vec<(Vec<u8>, Vec<u8>)> = entries.iter().map(|(k, v)| (encode(k), encode(v))).collect();
+            encoded.sort();
+            encode_head(5, encoded.len() as u64, out);
+            for (k, v) in encoded {
+                out.extend_from_slice(&k);
+                out.extend_from_slice(&v);
+            }
+        }
+        Value::Tag(tag, inner) => {
+            encode_head(6, *tag, out);
+            encode_into(inner, out);
+        }
+    }
+}
+
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_into(value, &mut out);
+    out
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+const MAX_DEPTH: usize = 16;
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], CborError> {
+        let end = self.pos.checked_add(n).ok_or(CborError("length overflow"))?;
+        let bytes = self.buf.get(self.pos..end).ok_or(CborError("unexpected end of input"))?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn head(&mut self) -> Result<(u8, u64), CborError> {
+        let first = self.take(1)?[0];
+        let (major, info) = (first >> 5, first & 0x1f);
+        let arg = match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take(2)?.try_into().unwrap()) as u64,
+            26 => u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as u64,
+            27 => u64::from_be_bytes(self.take(8)?.try_into().unwrap()),
+            31 => return Err(CborError("indefinite length")),
+            _ => return Err(CborError("reserved additional information")),
+        };
+        let minimal = match info {
+            24 => arg >= 24,
+            25 => arg > 0xff,
+            26 => arg > 0xffff,
+            27 => arg > 0xffff_ffff,
+            _ => true,
+        };
+        if !minimal {
+            return Err(CborError("non-minimal argument"));
+        }
+        Ok((major, arg))
+    }
+
+    fn len(&self, arg: u64) -> Result<usize, CborError> {
+        // Every item takes at least one byte, so a count beyond the input is bogus.
+        usize::try_from(arg).ok().filter(|n| *n <= self.buf.len() - self.pos).ok_or(CborError("length exceeds input"))
+    }
+
+    fn value(&mut self) -> Result<Value, CborError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(CborError("nesting too deep"));
+        }
+        let (major, arg) = self.head()?;
+        let value = match major {
+            0 => Value::Uint(arg),
+            1 => Value::Nint(arg),
+            2 => Value::Bytes(self.take(self.len(arg)?)?.to_vec()),
+            3 => {
+                let bytes = self.take(self.len(arg)?)?;
+                Value::Text(std::str::from_utf8(bytes).map_err(|_| CborError("invalid UTF-8"))?.to_string())
+            }
+            4 => Value::Array((0..self.len(arg)?).map(|_| self.value()).collect::<Result<_, _>>()?),
+            5 => {
+                let mut entries = Vec::new();
+                let mut last_key: Option<&[u8]> = None;
+                for _ in 0..self.len(arg)? {
+                    let start = self.pos;
+                    let key = self.value()?;
+                    let buf = self.buf;
+                    let raw = &buf[start..self.pos];
+                    if last_key.is_some_and(|last| last >= raw) {
+                        return Err(CborError("map keys not in deterministic order"));
+                    }
+                    last_key = Some(raw);
+                    entries.push((key, self.value()?));
+                }
+                Value::Map(entries)
+            }
+            6 => Value::Tag(arg, Box::new(self.value()?)),
+            _ => return Err(CborError("floats and simple values are not supported")),
+        };
+        self.depth -= 1;
+        Ok(value)
+    }
+}
+
+/// Decodes exactly one deterministically encoded item.
+pub fn decode(buf: &[u8]) -> Result<Value, CborError> {
+    let mut reader = Reader { buf, pos: 0, depth: 0 };
+    let value = reader.value()?;
+    if reader.pos != buf.len() {
+        return Err(CborError("trailing bytes"));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc8949_examples() {
+        assert_eq!(encode(&Value::Uint(0)), [0x00]);
+        assert_eq!(encode(&Value::Uint(24)), [0x18, 0x18]);
+        assert_eq!(encode(&Value::Uint(1_000_000)), [0x1a, 0x00, 0x0f, 0x42, 0x40]);
+        assert_eq!(encode(&Value::int(-1000)), [0x39, 0x03, 0xe7]);
+        assert_eq!(encode(&Value::Text("IETF".into())), [0x64, 0x49, 0x45, 0x54, 0x46]);
+        let array = Value::Array(vec![Value::Uint(1), Value::Array(vec![Value::Uint(2), Value::Uint(3)])]);
+        assert_eq!(encode(&array), [0x82, 0x01, 0x82, 0x02, 0x03]);
+    }
+
+    #[test]
+    fn maps_are_sorted_and_roundtrip() {
+        let map = Value::Map(vec![(Value::int(-1), Value::Uint(1)), (Value::Uint(10), Value::Uint(2)), (Value::Uint(1), Value::Uint(3))]);
+        let enc = encode(&map);
+        assert_eq!(enc, [0xa3, 0x01, 0x03, 0x0a, 0x02, 0x20, 0x01]);
+        assert_eq!(encode(&decode(&enc).unwrap()), enc);
+    }
+
+    #[test]
+    fn rejects_non_deterministic_input() {
+        assert!(decode(&[0x18, 0x05]).is_err());
+        assert!(decode(&[0x5f, 0x41, 0x00, 0xff]).is_err());
+        assert!(decode(&[0xa2, 0x02, 0x00, 0x01, 0x00]).is_err());
+        assert!(decode(&[0x00, 0x00]).is_err());
+        assert!(decode(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+}
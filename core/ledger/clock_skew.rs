@@ -0,0 +1,156 @@
+//! Witness clock-skew detection.
+//!
+//! A receipt's `external_timestamp` is only useful as timeline evidence if it is
+//! close to when the head was actually produced. Each valid receipt is compared
+//! against a local reference time; receipts outside `max_skew_secs` are either kept
+//! with a structured warning or rejected, per policy.
+
+use serde::{Deserialize, Serialize};
+
+use super::notarize::{AnchorRequest, NotaryError, QuorumStatus, Receipt};
+use super::tick::TickSource;
+
+/// What a receipt's timestamp is compared against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkewReference {
+    /// Local wall clock when the receipt is received. Only meaningful online; receipts
+    /// verified later (air-gap import, audits) are not checked against it.
+    WallClock,
+    /// Map the request tick to Unix time: `epoch_unix + timestamp_ticks / ticks_per_second`.
+    Ticks { epoch_unix: u64, ticks_per_second: u64 },
+}
+
+impl SkewReference {
+    /// `Ticks` calibrated from the source the ledger stamps its checkpoints with, or
+    /// `WallClock` when that source does not map to wall time.
+    pub fn from_ticks(ticks: &dyn TickSource) -> Self {
+        ticks.calibration().map_or(SkewReference::WallClock, |c| c.skew_reference())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkewEnforcement {
+    /// Keep the receipt and record a `ClockSkew` in `QuorumStatus::skew_warnings`.
+    Warn,
+    /// Discard the receipt; it does not count towards the quorum.
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkewPolicy {
+    pub max_skew_secs: u64,
+    pub reference: SkewReference,
+    pub enforcement: SkewEnforcement,
+}
+
+/// A witness timestamp that disagrees with local time by more than the policy allows.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ClockSkew {
+    pub backend: String,
+    pub receipt_id: String,
+    pub expected_unix: u64,
+    pub external_timestamp: u64,
+    /// `external_timestamp - expected_unix`; positive when the witness runs ahead.
+    pub skew_secs: i64,
+}
+
+impl SkewPolicy {
+    pub fn new(max_skew_secs: u64, reference: SkewReference, enforcement: SkewEnforcement) -> Self {
+        Self { max_skew_secs, reference, enforcement }
+    }
+
+    fn expected_unix(&self, req: &AnchorRequest, now_unix: Option<u64>) -> Option<u64> {
+        match self.reference {
+            SkewReference::WallClock => now_unix,
+            SkewReference::Ticks { epoch_unix, ticks_per_second } if ticks_per_second > 0 => {
+                Some(epoch_unix + req.timestamp_ticks / ticks_per_second)
+            }
+            SkewReference::Ticks { .. } => None,
+        }
+    }
+
+    /// `Some` if the receipt is out of bounds. `now_unix` is `None` when the receipt was
+    /// not just received from the witness.
+    pub fn check(&self, req: &AnchorRequest, receipt: &Receipt, now_unix: Option<u64>) -> Option<ClockSkew> {
+        let expected_unix = self.expected_unix(req, now_unix)?;
+        let skew_secs = receipt.external_timestamp as i64 - expected_unix as i64;
+        if skew_secs.unsigned_abs() <= self.max_skew_secs {
+            return None;
+        }
+        Some(ClockSkew {
+            backend: receipt.backend.clone(),
+            receipt_id: receipt.receipt_id.clone(),
+            expected_unix,
+            external_timestamp: receipt.external_timestamp,
+            skew_secs,
+        })
+    }
+
+    /// Applies the policy to a verified receipt: records a warning on `status`, or
+    /// returns `NotaryError::ClockSkew` when the policy rejects it.
+    pub fn apply(
+        &self,
+        req: &AnchorRequest,
+        receipt: &Receipt,
+        now_unix: Option<u64>,
+        status: &mut QuorumStatus,
+    ) -> Result<(), NotaryError> {
+        let Some(skew) = self.check(req, receipt, now_unix) else {
+            return Ok(());
+        };
+        match self.enforcement {
+            SkewEnforcement::Warn => {
+                eprintln!(
+                    "⚠️  Witness {} timestamp is {}s off local time (limit {}s).",
+                    skew.backend, skew.skew_secs, self.max_skew_secs
+                );
+                status.skew_warnings.push(skew);
+                Ok(())
+            }
+            SkewEnforcement::Reject => Err(NotaryError::ClockSkew(skew)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receipt(external_timestamp: u64) -> Receipt {
+        Receipt {
+            backend: "local".to_string(),
+            receipt_id: "r1".to_string(),
+            request: request(),
+            external_timestamp,
+            evidence: Vec::new(),
+            key_id: None,
+        }
+    }
+
+    fn request() -> AnchorRequest {
+        AnchorRequest { ledger_head_hash: "00".repeat(32), index: 1, timestamp_ticks: 5_000 }
+    }
+
+    #[test]
+    fn skewed_receipts_are_warned_about_or_rejected_per_policy() {
+        // Tick 5000 at 1000 ticks/s from epoch 1_000_000 is expected at 1_000_005.
+        let ticks = SkewReference::Ticks { epoch_unix: 1_000_000, ticks_per_second: 1_000 };
+        let warn = SkewPolicy::new(30, ticks, SkewEnforcement::Warn);
+        assert_eq!(warn.check(&request(), &receipt(1_000_035), None), None);
+        let skew = warn.check(&request(), &receipt(999_970), None).unwrap();
+        assert_eq!((skew.expected_unix, skew.skew_secs), (1_000_005, -35));
+
+        let mut status = QuorumStatus::new(request(), 1, 1);
+        warn.apply(&request(), &receipt(1_000_100), None, &mut status).unwrap();
+        assert_eq!(status.skew_warnings.len(), 1);
+        let reject = SkewPolicy::new(30, ticks, SkewEnforcement::Reject);
+        assert!(matches!(reject.apply(&request(), &receipt(1_000_100), None, &mut status), Err(NotaryError::ClockSkew(_))));
+
+        // The wall clock is only a reference for receipts that were just received.
+        let wall = SkewPolicy::new(30, SkewReference::WallClock, SkewEnforcement::Reject);
+        assert_eq!(wall.check(&request(), &receipt(0), None), None);
+        assert!(wall.check(&request(), &receipt(0), Some(1_000)).is_some());
+        let uncalibrated = SkewPolicy::new(30, SkewReference::Ticks { epoch_unix: 0, ticks_per_second: 0 }, SkewEnforcement::Reject);
+        assert_eq!(uncalibrated.check(&request(), &receipt(0), Some(1_000)), None);
+    }
+}
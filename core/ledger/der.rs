@@ -0,0 +1,274 @@
+//! Minimal DER reader/writer.
+//!
+//! Covers only the subset of ASN.1 needed to build RFC 3161 `TimeStampReq`s and
+//! to walk `TimeStampResp`/CMS `SignedData` structures. Indefinite lengths (BER)
+//! are rejected; DER never uses them.
+
+use std::fmt;
+
+pub const TAG_BOOLEAN: u8 = 0x01;
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_UTF8_STRING: u8 = 0x0c;
+pub const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_SET: u8 = 0x31;
+
+/// Context-specific tag `[n]`, constructed or primitive.
+pub const fn context(n: u8, constructed: bool) -> u8 {
+    0x80 | if constructed { 0x20 } else { 0 } | (n & 0x1f)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerError(pub &'static str);
+
+impl fmt::Display for DerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DER decode error: {}", self.0)
+    }
+}
+
+impl std::error::Error for DerError {}
+
+/// One decoded tag-length-value. `raw` is the full encoding including tag and length.
+#[derive(Debug, Clone, Copy)]
+pub struct Tlv<'a> {
+    pub tag: u8,
+    pub value: &'a [u8],
+    pub raw: &'a [u8],
+}
+
+pub struct DerReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> DerReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.buf.get(self.pos).copied()
+    }
+
+    pub fn read_any(&mut self) -> Result<Tlv<'a>, DerError> {
+        let start = self.pos;
+        let tag = *self.buf.get(self.pos).ok_or(DerError("unexpected end of input"))?;
+        if tag & 0x1f == 0x1f {
+            return Err(DerError("high tag numbers are not supported"));
+        }
+        let first = *self.buf.get(self.pos + 1).ok_or(DerError("missing length"))?;
+        self.pos += 2;
+        let len = if first < 0x80 {
+            first as usize
+        } else {
+            let n = (first & 0x7f) as usize;
+            if n == 0 {
+                return Err(DerError("indefinite length"));
+            }
+            if n > 4 {
+                return Err(DerError("length too large"));
+            }
+            let bytes = self.buf.get(self.pos..self.pos + n).ok_or(DerError("truncated length"))?;
+            if bytes[0] == 0 || (n == 1 && bytes[0] < 0x80) {
+                return Err(DerError("non-minimal length"));
+            }
+            self.pos += n;
+            bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize)
+        };
+        let end = self.pos.checked_add(len).ok_or(DerError("length overflow"))?;
+        let value = self.buf.get(self.pos..end).ok_or(DerError("truncated value"))?;
+        self.pos = end;
+        Ok(Tlv { tag, value, raw: &self.buf[start..end] })
+    }
+
+    /// Reads the next element, requiring `tag`, and returns its contents.
+    pub fn read(&mut self, tag: u8) -> Result<&'a [u8], DerError> {
+        let tlv = self.read_any()?;
+        if tlv.tag != tag {
+            return Err(DerError("unexpected tag"));
+        }
+        Ok(tlv.value)
+    }
+
+    /// Reads the next element only if it carries `tag`.
+    pub fn read_optional(&mut self, tag: u8) -> Result<Option<&'a [u8]>, DerError> {
+        if self.peek_tag() == Some(tag) {
+            self.read(tag).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+fn encode_len(len: usize, out: &mut Vec<u8>) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = (len as u64).to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (8 - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+pub fn encode_tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(value.len() + 6);
+    out.push(tag);
+    encode_len(value.len(), &mut out);
+    out.extend_from_slice(value);
+    out
+}
+
+/// Concatenates already-encoded children under a constructed tag.
+pub fn encode_constructed(tag: u8, children: &[&[u8]]) -> Vec<u8> {
+    encode_tlv(tag, &children.concat())
+}
+
+/// Non-negative INTEGER with minimal encoding.
+pub fn encode_uint(v: u64) -> Vec<u8> {
+    let bytes = v.to_be_bytes();
+    let skip = bytes.iter().take_while(|b| **b == 0).count().min(7);
+    let mut value = Vec::with_capacity(9);
+    if bytes[skip] & 0x80 != 0 {
+        value.push(0);
+    }
+    value.extend_from_slice(&bytes[skip..]);
+    encode_tlv(TAG_INTEGER, &value)
+}
+
+/// Decodes a non-negative INTEGER that fits in a u64.
+pub fn decode_uint(value: &[u8]) -> Result<u64, DerError> {
+    let digits = match value {
+        [] => return Err(DerError("empty integer")),
+        [0, rest @ ..] if !rest.is_empty() => rest,
+        v if v[0] & 0x80 != 0 => return Err(DerError("negative integer")),
+        v => v,
+    };
+    if digits.len() > 8 {
+        return Err(DerError("integer does not fit in u64"));
+    }
+    Ok(digits.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+}
+
+pub fn encode_oid(arcs: &[u64]) -> Vec<u8> {
+    let mut value = Vec::new();
+    let mut push_base128 = |mut v: u64| {
+        let mut tmp = [0u8; 10];
+        let mut i = tmp.len();
+        loop {
+            i -= 1;
+            tmp[i] = (v & 0x7f) as u8 | if i == tmp.len() - 1 { 0 } else { 0x80 };
+            v >>= 7;
+            if v == 0 {
+                break;
+            }
+        }
+        value.extend_from_slice(&tmp[i..]);
+    };
+    push_base128(arcs[0] * 40 + arcs[1]);
+    for arc in &arcs[2..] {
+        push_base128(*arc);
+    }
+    encode_tlv(TAG_OID, &value)
+}
+
+pub fn decode_oid(value: &[u8]) -> Result<Vec<u64>, DerError> {
+    let mut arcs = Vec::new();
+    let mut acc: u64 = 0;
+    for (i, b) in value.iter().enumerate() {
+        if acc == 0 && *b == 0x80 {
+            return Err(DerError("non-minimal OID arc"));
+        }
+        acc = acc.checked_mul(128).ok_or(DerError("OID arc overflow"))? | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = (acc / 40).min(2);
+                arcs.push(first);
+                arcs.push(acc - first * 40);
+            } else {
+                arcs.push(acc);
+            }
+            acc = 0;
+        } else if i == value.len() - 1 {
+            return Err(DerError("truncated OID"));
+        }
+    }
+    if arcs.is_empty() {
+        return Err(DerError("empty OID"));
+    }
+    Ok(arcs)
+}
+
+/// Parses a DER GeneralizedTime (`YYYYMMDDHHMMSS[.f*]Z`) into Unix seconds.
+pub fn decode_generalized_time(value: &[u8]) -> Result<u64, DerError> {
+    let s = std::str::from_utf8(value).map_err(|_| DerError("non-ASCII time"))?;
+    let s = s.strip_suffix('Z').ok_or(DerError("time must be UTC"))?;
+    let whole = s.split('.').next().unwrap_or("");
+    if whole.len() != 14 || !whole.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(DerError("malformed GeneralizedTime"));
+    }
+    let num = |r: std::ops::Range<usize>| whole[r].parse::<i64>().unwrap();
+    let (y, m, d) = (num(0..4), num(4..6), num(6..8));
+    let (hh, mm, ss) = (num(8..10), num(10..12), num(12..14));
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) || hh > 23 || mm > 59 || ss > 60 {
+        return Err(DerError("GeneralizedTime out of range"));
+    }
+    // Days from civil (Howard Hinnant's algorithm).
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    let secs = days * 86_400 + hh * 3600 + mm * 60 + ss;
+    u64::try_from(secs).map_err(|_| DerError("time before the Unix epoch"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oid_roundtrip() {
+        let sha256 = [2, 16, 840, 1, 101, 3, 4, 2, 1];
+        let enc = encode_oid(&sha256);
+        assert_eq!(enc, [0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01]);
+        let mut r = DerReader::new(&enc);
+        assert_eq!(decode_oid(r.read(TAG_OID).unwrap()).unwrap(), sha256);
+    }
+
+    #[test]
+    fn uint_encoding_is_minimal_and_positive() {
+        assert_eq!(encode_uint(0), [0x02, 0x01, 0x00]);
+        assert_eq!(encode_uint(0x80), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(decode_uint(&[0x00, 0x80]).unwrap(), 0x80);
+        assert!(decode_uint(&[0x80]).is_err());
+    }
+
+    #[test]
+    fn long_form_lengths() {
+        let value = vec![0xab; 300];
+        let enc = encode_tlv(TAG_OCTET_STRING, &value);
+        assert_eq!(&enc[..4], &[0x04, 0x82, 0x01, 0x2c]);
+        let mut r = DerReader::new(&enc);
+        assert_eq!(r.read(TAG_OCTET_STRING).unwrap(), &value[..]);
+        assert!(r.is_empty());
+        assert!(DerReader::new(&[0x04, 0x81, 0x05, 0, 0, 0, 0, 0]).read_any().is_err());
+    }
+
+    #[test]
+    fn generalized_time() {
+        assert_eq!(decode_generalized_time(b"19700101000000Z").unwrap(), 0);
+        assert_eq!(decode_generalized_time(b"20240229123045.123Z").unwrap(), 1_709_209_845);
+        assert!(decode_generalized_time(b"20240229123045").is_err());
+    }
+}
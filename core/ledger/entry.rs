@@ -0,0 +1,212 @@
+//! Typed ledger entries.
+//!
+//! Application payloads are opaque bytes. Entries the ledger machinery writes about
+//! itself (repairs, notarization receipts, ...) carry a small header so they can be
+//! recognised when the ledger is replayed or audited:
+//!
+//! ```text
+//! "RFT1" | kind: u16 LE | body
+//! ```
+//!
+//! Replays trust these entries, so only the store writes them (`append_typed`);
+//! `append_entry` refuses application payloads that start with the magic.
+
+use serde::{Deserialize, Serialize};
+
+use super::notarize::{QuorumStatus, Receipt};
+use super::storage::{DeterministicStore, LedgerResult};
+use super::witness_keys::KeyEvent;
+
+pub const TYPED_ENTRY_MAGIC: &[u8; 4] = b"RFT1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    /// UTF-8 description of a recovery action taken by `OpenMode::Repair`.
+    Repair,
+    /// JSON `ReceiptEntry`: witness evidence for an earlier checkpoint.
+    NotaryReceipt,
+    /// JSON `KeyPinEntry`: a witness key was pinned, rotated out or revoked.
+    WitnessKey,
+    /// JSON `SplitViewReport`: witnesses hold signed heads that contradict the ledger.
+    SplitView,
+    /// JSON `BudgetExceeded`: the runtime watchdog aborted an over-budget policy execution.
+    BudgetViolation,
+    /// JSON `CapabilityEvent`: a capability was granted, or a grant was checked.
+    Capability,
+    /// JSON `gate::decision::Decision`: the Gate's answer to one proposal.
+    Decision,
+    /// JSON `gate::approval::ApprovalEvent`: a proposal was parked, approved or released.
+    Approval,
+    /// JSON `gate::policy::PolicyVersion`: the Gate loaded a policy.
+    PolicyVersion,
+    /// JSON `gate::package::PackageVerification`: a policy was checked against the trust root.
+    PolicyPackage,
+    /// JSON `gate::tools::ToolsVersion`: the Gate loaded a tool registry.
+    ToolRegistry,
+    /// JSON `gate::sandbox::ExecutionResult`: an allowed tool ran in the sandbox.
+    Execution,
+    /// JSON `gate::freeze::FreezeEvent`: the Gate was frozen or unfrozen.
+    Freeze,
+    /// JSON `gate::breakglass::OverrideEvent`: a denial was overridden, or the override progressed.
+    Override,
+    /// JSON `gate::anomaly::Anomaly`: the predictive loop reported an anomaly.
+    Anomaly,
+    /// JSON `gate::fallback::NamespaceVersion`: the Gate loaded namespace fallbacks.
+    NamespaceConfig,
+    /// JSON `gate::anomaly::ModelCheckpoint`: a source checkpointed its model.
+    ModelCheckpoint,
+}
+
+impl EntryKind {
+    pub fn to_wire(self) -> u16 {
+        match self {
+            EntryKind::Repair => 1,
+            EntryKind::NotaryReceipt => 2,
+            EntryKind::WitnessKey => 3,
+            EntryKind::SplitView => 4,
+            EntryKind::BudgetViolation => 5,
+            EntryKind::Capability => 6,
+            EntryKind::Decision => 7,
+            EntryKind::Approval => 8,
+            EntryKind::PolicyVersion => 9,
+            EntryKind::PolicyPackage => 10,
+            EntryKind::ToolRegistry => 11,
+            EntryKind::Execution => 12,
+            EntryKind::Freeze => 13,
+            EntryKind::Override => 14,
+            EntryKind::Anomaly => 15,
+            EntryKind::NamespaceConfig => 16,
+            EntryKind::ModelCheckpoint => 17,
+        }
+    }
+
+    pub fn from_wire(v: u16) -> Option<Self> {
+        match v {
+            1 => Some(EntryKind::Repair),
+            2 => Some(EntryKind::NotaryReceipt),
+            3 => Some(EntryKind::WitnessKey),
+            4 => Some(EntryKind::SplitView),
+            5 => Some(EntryKind::BudgetViolation),
+            6 => Some(EntryKind::Capability),
+            7 => Some(EntryKind::Decision),
+            8 => Some(EntryKind::Approval),
+            9 => Some(EntryKind::PolicyVersion),
+            10 => Some(EntryKind::PolicyPackage),
+            11 => Some(EntryKind::ToolRegistry),
+            12 => Some(EntryKind::Execution),
+            13 => Some(EntryKind::Freeze),
+            14 => Some(EntryKind::Override),
+            15 => Some(EntryKind::Anomaly),
+            16 => Some(EntryKind::NamespaceConfig),
+            17 => Some(EntryKind::ModelCheckpoint),
+            _ => None,
+        }
+    }
+}
+
+pub fn encode(kind: EntryKind, body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(6 + body.len());
+    out.extend_from_slice(TYPED_ENTRY_MAGIC);
+    out.extend_from_slice(&kind.to_wire().to_le_bytes());
+    out.extend_from_slice(body);
+    out
+}
+
+/// Splits a stored payload into kind and body. `None` for application entries
+/// and for kinds this build does not know.
+pub fn decode(payload: &[u8]) -> Option<(EntryKind, &[u8])> {
+    let rest = payload.strip_prefix(TYPED_ENTRY_MAGIC.as_slice())?;
+    if rest.len() < 2 {
+        return None;
+    }
+    let kind = EntryKind::from_wire(u16::from_le_bytes([rest[0], rest[1]]))?;
+    Some((kind, &rest[2..]))
+}
+
+/// Body of an `EntryKind::NotaryReceipt` entry. Appending it puts the evidence chain
+/// (head → receipt → later head) under all subsequent Merkle roots, instead of it
+/// living only as loose `.receipt` files.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReceiptEntry {
+    pub index: u64,
+    pub ledger_head_hash: String,
+    pub threshold: usize,
+    pub receipts: Vec<Receipt>,
+}
+
+impl ReceiptEntry {
+    /// Appends the evidence as an `EntryKind::NotaryReceipt` entry, putting it under every later
+    /// Merkle root.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::NotaryReceipt, self)
+    }
+
+    pub fn from_status(status: &QuorumStatus) -> Self {
+        Self {
+            index: status.request.index,
+            ledger_head_hash: status.request.ledger_head_hash.clone(),
+            threshold: status.threshold,
+            receipts: status.receipts.clone(),
+        }
+    }
+}
+
+/// Body of an `EntryKind::WitnessKey` entry.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeyPinEntry {
+    /// Operator-chosen witness identifier (e.g. its URL); several witnesses can share
+    /// a backend name.
+    pub witness: String,
+    pub event: KeyEvent,
+}
+
+impl KeyPinEntry {
+    /// Appends the key event; see `WitnessKeyring::from_ledger`.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::WitnessKey, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::local_witness::LocalWitness;
+    use crate::ledger::notarize::AnchorRequest;
+
+    #[test]
+    fn every_kind_survives_the_wire_and_application_payloads_stay_untyped() {
+        for wire in 1..=17 {
+            assert_eq!(EntryKind::from_wire(wire).map(EntryKind::to_wire), Some(wire));
+        }
+        assert_eq!(EntryKind::from_wire(0), None);
+        assert_eq!(decode(&encode(EntryKind::Repair, b"truncated")), Some((EntryKind::Repair, b"truncated".as_slice())));
+        assert_eq!(decode(b"application bytes"), None);
+        assert_eq!(decode(b"RFT1\x63\x00unknown kind"), None);
+        assert_eq!(decode(b"RFT1\x01"), None);
+    }
+
+    #[test]
+    fn anchors_replay_as_receipt_entries() {
+        let dir = std::env::temp_dir().join(format!("rfsn-entry-receipts-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut store = DeterministicStore::new(&dir).unwrap();
+        store.append_entry(b"application").unwrap();
+        let request = AnchorRequest { ledger_head_hash: "ef".repeat(32), index: 1, timestamp_ticks: 3 };
+        let mut status = QuorumStatus::new(request, 1, 1);
+        status.receipts.push(LocalWitness::from_seed("w1", [1; 32]).notarize(&status.request));
+        let recorded = ReceiptEntry::from_status(&status);
+        recorded.append_to(&mut store).unwrap();
+
+        let mut replayed = Vec::new();
+        store
+            .for_each_entry(|index, payload| {
+                if let Some((EntryKind::NotaryReceipt, body)) = decode(payload) {
+                    replayed.push((index, serde_json::from_slice::<ReceiptEntry>(body).unwrap()));
+                }
+            })
+            .unwrap();
+        assert_eq!(replayed, vec![(1, recorded)]);
+        drop(store);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
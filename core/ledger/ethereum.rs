@@ -0,0 +1,302 @@
+//! Ethereum smart-contract anchoring backend.
+//!
+//! The ledger head is published to a minimal anchoring contract exposing
+//!
+//! ```solidity
+//! function anchor(bytes32 head, uint64 index, uint64 ticks) external;
+//! ```
+//!
+//! where `head` is SHA-256 of the decoded `ledger_head_hash` root (the same imprint the TSA backend
+//! uses). Transactions are signed by the JSON-RPC node for `from_address` (an
+//! unlocked account or an external signer such as Clef); no key material lives here.
+//! The receipt is the transaction hash plus block number and hash, and
+//! `verify_receipt` re-checks all of it against the chain.
+
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use sha3::{Digest, Keccak256};
+
+use super::notarize::{AnchorRequest, NotaryBackend, NotaryError, Receipt, TsaBackend};
+use super::notary_http::HttpClientConfig;
+
+const ANCHOR_SIGNATURE: &str = "anchor(bytes32,uint64,uint64)";
+
+#[derive(Debug, Clone)]
+pub struct EthereumConfig {
+    pub rpc_url: String,
+    /// `0x`-prefixed address of the anchoring contract.
+    pub contract_address: String,
+    /// `0x`-prefixed account the node signs the anchoring transaction with.
+    pub from_address: String,
+    /// Blocks on top of the anchoring block (inclusive) before a receipt is issued.
+    pub confirmations: u64,
+    pub poll_interval: Duration,
+    /// How long `submit` waits for inclusion plus confirmations. Under `AsyncNotaryClient`
+    /// the client's `request_timeout` must be longer than this.
+    pub confirmation_timeout: Duration,
+    pub http: HttpClientConfig,
+}
+
+impl EthereumConfig {
+    pub fn new(rpc_url: &str, contract_address: &str, from_address: &str) -> Self {
+        Self {
+            rpc_url: rpc_url.to_string(),
+            contract_address: contract_address.to_string(),
+            from_address: from_address.to_string(),
+            confirmations: 12,
+            poll_interval: Duration::from_secs(4),
+            confirmation_timeout: Duration::from_secs(10 * 60),
+            http: HttpClientConfig::default(),
+        }
+    }
+
+    pub fn confirmations(mut self, blocks: u64) -> Self {
+        self.confirmations = blocks;
+        self
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    pub fn confirmation_timeout(mut self, timeout: Duration) -> Self {
+        self.confirmation_timeout = timeout;
+        self
+    }
+
+    pub fn http(mut self, http: HttpClientConfig) -> Self {
+        self.http = http;
+        self
+    }
+}
+
+pub struct EthereumBackend {
+    config: EthereumConfig,
+    client: Client,
+    next_id: AtomicU64,
+}
+
+/// Where an anchoring transaction landed; stored as the receipt evidence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainPosition {
+    pub block_number: u64,
+    pub block_hash: [u8; 32],
+}
+
+impl ChainPosition {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = self.block_number.to_le_bytes().to_vec();
+        out.extend_from_slice(&self.block_hash);
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, NotaryError> {
+        if bytes.len() != 40 {
+            return Err(NotaryError::Malformed("ethereum receipt evidence".to_string()));
+        }
+        let mut block_hash = [0u8; 32];
+        block_hash.copy_from_slice(&bytes[8..]);
+        Ok(Self { block_number: u64::from_le_bytes(bytes[..8].try_into().unwrap()), block_hash })
+    }
+}
+
+fn quantity(v: &Value) -> Result<u64, Box<dyn Error>> {
+    let s = v.as_str().ok_or("expected a hex quantity")?;
+    Ok(u64::from_str_radix(s.trim_start_matches("0x"), 16)?)
+}
+
+fn hash32(v: &Value) -> Result<[u8; 32], Box<dyn Error>> {
+    let s = v.as_str().ok_or("expected a 32-byte hex value")?;
+    let bytes = hex::decode(s.trim_start_matches("0x"))?;
+    Ok(bytes.try_into().map_err(|_| "expected a 32-byte hex value")?)
+}
+
+fn abi_u64(v: u64) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&v.to_be_bytes());
+    word
+}
+
+impl EthereumBackend {
+    pub fn new(config: EthereumConfig) -> Result<Self, Box<dyn Error>> {
+        let client = config.http.build_blocking()?;
+        Ok(Self { config, client, next_id: AtomicU64::new(1) })
+    }
+
+    /// ABI-encoded `anchor(head, index, ticks)` call for `req`.
+    pub fn calldata(req: &AnchorRequest) -> Result<Vec<u8>, NotaryError> {
+        let mut data = Keccak256::digest(ANCHOR_SIGNATURE.as_bytes())[..4].to_vec();
+        data.extend_from_slice(&TsaBackend::imprint(req)?);
+        data.extend_from_slice(&abi_u64(req.index));
+        data.extend_from_slice(&abi_u64(req.timestamp_ticks));
+        Ok(data)
+    }
+
+    fn rpc(&self, method: &str, params: Value) -> Result<Value, Box<dyn Error>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let body = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let res = self.client.post(&self.config.rpc_url).json(&body).send()?;
+        if !res.status().is_success() {
+            return Err(format!("{} failed with HTTP {}", method, res.status()).into());
+        }
+        let mut resp: Value = res.json()?;
+        if let Some(err) = resp.get("error") {
+            return Err(format!("{} failed: {}", method, err).into());
+        }
+        Ok(resp["result"].take())
+    }
+
+    fn block_number(&self) -> Result<u64, Box<dyn Error>> {
+        quantity(&self.rpc("eth_blockNumber", json!([]))?)
+    }
+
+    fn block_timestamp(&self, block_number: u64) -> Result<u64, Box<dyn Error>> {
+        let block = self.rpc("eth_getBlockByNumber", json!([format!("0x{:x}", block_number), false]))?;
+        quantity(&block["timestamp"])
+    }
+
+    /// Successful, mined transaction receipt, or `None` while pending.
+    fn mined_position(&self, tx_hash: &str) -> Result<Option<ChainPosition>, Box<dyn Error>> {
+        let receipt = self.rpc("eth_getTransactionReceipt", json!([tx_hash]))?;
+        if receipt.is_null() || receipt["blockNumber"].is_null() {
+            return Ok(None);
+        }
+        if quantity(&receipt["status"])? != 1 {
+            return Err(format!("anchoring transaction {} reverted", tx_hash).into());
+        }
+        Ok(Some(ChainPosition { block_number: quantity(&receipt["blockNumber"])?, block_hash: hash32(&receipt["blockHash"])? }))
+    }
+
+    fn confirmed(&self, position: &ChainPosition) -> Result<bool, Box<dyn Error>> {
+        Ok(self.block_number()? + 1 >= position.block_number + self.config.confirmations)
+    }
+}
+
+impl NotaryBackend for EthereumBackend {
+    fn name(&self) -> &str {
+        "ethereum"
+    }
+
+    fn submit(&self, req: &AnchorRequest) -> Result<Receipt, Box<dyn Error>> {
+        let tx = json!({
+            "from": self.config.from_address,
+            "to": self.config.contract_address,
+            "data": format!("0x{}", hex::encode(Self::calldata(req)?)),
+        });
+        let tx_value = self.rpc("eth_sendTransaction", json!([tx]))?;
+        let tx_hash = tx_value.as_str().ok_or("eth_sendTransaction returned no hash")?.to_string();
+
+        let deadline = Instant::now() + self.config.confirmation_timeout;
+        let position = loop {
+            if let Some(position) = self.mined_position(&tx_hash)? {
+                if self.confirmed(&position)? {
+                    break position;
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(format!("anchoring transaction {} not confirmed within {:?}", tx_hash, self.config.confirmation_timeout).into());
+            }
+            thread::sleep(self.config.poll_interval);
+        };
+
+        Ok(Receipt {
+            backend: self.name().to_string(),
+            receipt_id: tx_hash,
+            request: req.clone(),
+            external_timestamp: self.block_timestamp(position.block_number)?,
+            evidence: position.encode(),
+            key_id: None,
+        })
+    }
+
+    fn fetch_receipt(&self, _receipt_id: &str) -> Result<Receipt, Box<dyn Error>> {
+        Err("the chain only holds the head imprint; keep the stored receipt and re-check it with verify_receipt".into())
+    }
+
+    /// Re-checks the receipt against the chain: the transaction called the configured
+    /// contract with this request, succeeded, is still in the recorded block, and that
+    /// block is sufficiently confirmed.
+    fn verify_receipt(&self, req: &AnchorRequest, receipt: &Receipt) -> Result<(), Box<dyn Error>> {
+        if receipt.backend != self.name() || &receipt.request != req {
+            return Err(Box::new(NotaryError::ReceiptMismatch("receipt does not cover this anchor request")));
+        }
+        let recorded = ChainPosition::decode(&receipt.evidence)?;
+
+        let tx = self.rpc("eth_getTransactionByHash", json!([receipt.receipt_id]))?;
+        if tx.is_null() {
+            return Err(Box::new(NotaryError::ReceiptMismatch("anchoring transaction is not on chain")));
+        }
+        let to_contract = tx["to"].as_str().is_some_and(|to| to.eq_ignore_ascii_case(&self.config.contract_address));
+        let input = tx["input"].as_str().map(|s| s.trim_start_matches("0x").to_ascii_lowercase());
+        if !to_contract || input.as_deref() != Some(hex::encode(Self::calldata(req)?).as_str()) {
+            return Err(Box::new(NotaryError::ReceiptMismatch("transaction does not anchor this request")));
+        }
+
+        match self.mined_position(&receipt.receipt_id)? {
+            Some(position) if position == recorded => {}
+            // Reorged out or re-included elsewhere: the recorded evidence no longer holds.
+            _ => return Err(Box::new(NotaryError::ReceiptMismatch("transaction is no longer in the recorded block"))),
+        }
+        if !self.confirmed(&recorded)? {
+            return Err(Box::new(NotaryError::ReceiptMismatch("anchoring block is not sufficiently confirmed")));
+        }
+        if self.block_timestamp(recorded.block_number)? != receipt.external_timestamp {
+            return Err(Box::new(NotaryError::ReceiptMismatch("block timestamp differs from the receipt")));
+        }
+        Ok(())
+    }
+
+    fn health_check(&self) -> Result<(), Box<dyn Error>> {
+        self.block_number().map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> AnchorRequest {
+        AnchorRequest { ledger_head_hash: "34".repeat(32), index: 0x0102, timestamp_ticks: 0x0304 }
+    }
+
+    #[test]
+    fn calldata_abi_encodes_the_anchor_call() {
+        let data = EthereumBackend::calldata(&request()).unwrap();
+        assert_eq!(data.len(), 4 + 3 * 32);
+        assert_eq!(data[..4], Keccak256::digest(b"anchor(bytes32,uint64,uint64)")[..4]);
+        assert_eq!(data[4..36], TsaBackend::imprint(&request()).unwrap());
+        assert_eq!((&data[36..66], &data[66..68]), (&[0u8; 30][..], &[0x01, 0x02][..]));
+        assert_eq!((&data[68..98], &data[98..100]), (&[0u8; 30][..], &[0x03, 0x04][..]));
+        assert_eq!(quantity(&json!("0x1b4")).unwrap(), 436);
+        assert!(hash32(&json!("0x1234")).is_err());
+    }
+
+    #[test]
+    fn receipts_for_another_request_or_with_bad_evidence_are_refused_offline() {
+        let position = ChainPosition { block_number: 7, block_hash: [9; 32] };
+        assert_eq!(ChainPosition::decode(&position.encode()).unwrap(), position);
+        assert!(matches!(ChainPosition::decode(&[0; 39]), Err(NotaryError::Malformed(_))));
+
+        // Nothing listens on the discard port; both checks must fail before any RPC.
+        let backend = EthereumBackend::new(EthereumConfig::new("http://127.0.0.1:9", "0xc0ffee", "0xbeef")).unwrap();
+        let mut receipt = Receipt {
+            backend: backend.name().to_string(),
+            receipt_id: "0xabc".to_string(),
+            request: request(),
+            external_timestamp: 1,
+            evidence: vec![0; 12],
+            key_id: None,
+        };
+        let other = AnchorRequest { index: 1, ..request() };
+        assert!(backend.verify_receipt(&other, &receipt).unwrap_err().to_string().contains("does not cover"));
+        assert!(backend.verify_receipt(&request(), &receipt).unwrap_err().to_string().contains("ethereum receipt evidence"));
+        receipt.backend = "tsa".to_string();
+        assert!(backend.verify_receipt(&request(), &receipt).is_err());
+    }
+}
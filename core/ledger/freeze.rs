@@ -0,0 +1,192 @@
+//! Optional freeze after prolonged anchoring failure.
+//!
+//! Some deployments must not act without fresh external evidence that their ledger
+//! is intact. With a `FreezePolicy`, the store is considered frozen once N checkpoints
+//! have been written or T has elapsed without a successful notarization; gates ask
+//! `AnchoringFreeze::admit` before admitting a high-risk action and reject it while
+//! frozen. Low-risk actions and ledger appends continue, so the backlog can still
+//! be anchored. The freeze lifts with the next successful anchor.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+use super::entry::{self, EntryKind, ReceiptEntry};
+use super::notarize::QuorumStatus;
+use super::outbox::unix_now;
+use super::storage::{DeterministicStore, LedgerResult};
+
+/// When to freeze. With both limits set, whichever is hit first freezes.
+#[derive(Debug, Clone, Default)]
+pub struct FreezePolicy {
+    pub max_unanchored_checkpoints: Option<u64>,
+    pub max_unanchored_for: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FreezeReason {
+    Checkpoints { unanchored: u64, limit: u64 },
+    Elapsed { unanchored_for_secs: u64, limit_secs: u64 },
+}
+
+/// Returned by `AnchoringFreeze::admit` while frozen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnchoringFrozen {
+    pub reason: FreezeReason,
+    pub last_anchor_unix: u64,
+}
+
+impl fmt::Display for AnchoringFrozen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reason {
+            FreezeReason::Checkpoints { unanchored, limit } => {
+                write!(f, "ledger frozen: {} checkpoints without a notarization (limit {})", unanchored, limit)
+            }
+            FreezeReason::Elapsed { unanchored_for_secs, limit_secs } => {
+                write!(f, "ledger frozen: no notarization for {}s (limit {}s)", unanchored_for_secs, limit_secs)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnchoringFrozen {}
+
+/// Shared between the notarization scheduler, which feeds it, and the gate (wrap in an `Arc`).
+pub struct AnchoringFreeze {
+    policy: FreezePolicy,
+    last_anchor_unix: AtomicU64,
+    checkpoints_since_anchor: AtomicU64,
+    // Only used to log transitions once.
+    frozen: AtomicBool,
+}
+
+impl AnchoringFreeze {
+    /// Starts the unanchored window now.
+    pub fn new(policy: FreezePolicy) -> Self {
+        Self::starting_at(policy, unix_now())
+    }
+
+    fn starting_at(policy: FreezePolicy, last_anchor_unix: u64) -> Self {
+        Self {
+            policy,
+            last_anchor_unix: AtomicU64::new(last_anchor_unix),
+            checkpoints_since_anchor: AtomicU64::new(0),
+            frozen: AtomicBool::new(false),
+        }
+    }
+
+    /// Starts the unanchored window at the newest witness timestamp recorded in the
+    /// ledger, so a restart does not reset the clock. Checkpoints are not recorded
+    /// as entries; the checkpoint count starts from zero.
+    pub fn resume_from_ledger(policy: FreezePolicy, store: &DeterministicStore) -> LedgerResult<Self> {
+        let mut newest = None;
+        store.for_each_entry(|_, payload| {
+            let Some((EntryKind::NotaryReceipt, body)) = entry::decode(payload) else { return };
+            if let Ok(recorded) = serde_json::from_slice::<ReceiptEntry>(body) {
+                let at = recorded.receipts.iter().map(|r| r.external_timestamp).max();
+                newest = newest.max(at);
+            }
+        })?;
+        Ok(Self::starting_at(policy, newest.unwrap_or_else(unix_now)))
+    }
+
+    pub fn record_checkpoint(&self) {
+        self.checkpoints_since_anchor.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_anchor(&self, status: &QuorumStatus) {
+        if !status.anchored() {
+            return;
+        }
+        self.last_anchor_unix.fetch_max(unix_now(), Ordering::Relaxed);
+        self.checkpoints_since_anchor.store(0, Ordering::Relaxed);
+        if self.frozen.swap(false, Ordering::Relaxed) {
+            println!("✅ Anchoring resumed at index {}; ledger unfrozen.", status.request.index);
+        }
+    }
+
+    /// `Err` while frozen.
+    pub fn check(&self, now_unix: u64) -> Result<(), AnchoringFrozen> {
+        let last_anchor_unix = self.last_anchor_unix.load(Ordering::Relaxed);
+        let unanchored = self.checkpoints_since_anchor.load(Ordering::Relaxed);
+        let unanchored_for_secs = now_unix.saturating_sub(last_anchor_unix);
+        let reason = match (self.policy.max_unanchored_checkpoints, self.policy.max_unanchored_for) {
+            (Some(limit), _) if unanchored >= limit => FreezeReason::Checkpoints { unanchored, limit },
+            (_, Some(limit)) if unanchored_for_secs >= limit.as_secs() => {
+                FreezeReason::Elapsed { unanchored_for_secs, limit_secs: limit.as_secs() }
+            }
+            _ => return Ok(()),
+        };
+        let frozen = AnchoringFrozen { reason, last_anchor_unix };
+        if !self.frozen.swap(true, Ordering::Relaxed) {
+            eprintln!("❌ {}. High-risk actions are rejected until anchoring resumes.", frozen);
+        }
+        Err(frozen)
+    }
+
+    /// Admission check for a proposed action. Anything not explicitly `"low"` risk is
+    /// treated as high risk and rejected while frozen.
+    pub fn admit(&self, risk_hint: &str, now_unix: u64) -> Result<(), AnchoringFrozen> {
+        if risk_hint == "low" {
+            return Ok(());
+        }
+        self.check(now_unix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::notarize::{AnchorRequest, Receipt};
+
+    fn anchored(index: u64, external_timestamp: u64) -> QuorumStatus {
+        let request = AnchorRequest { ledger_head_hash: "56".repeat(32), index, timestamp_ticks: index };
+        let mut status = QuorumStatus::new(request.clone(), 1, 1);
+        status.receipts.push(Receipt {
+            backend: "local".to_string(),
+            receipt_id: format!("r{}", index),
+            request,
+            external_timestamp,
+            evidence: Vec::new(),
+            key_id: None,
+        });
+        status
+    }
+
+    #[test]
+    fn unanchored_checkpoints_freeze_high_risk_actions_until_the_next_anchor() {
+        let freeze = AnchoringFreeze::starting_at(FreezePolicy { max_unanchored_checkpoints: Some(2), max_unanchored_for: None }, 1_000);
+        freeze.record_checkpoint();
+        assert!(freeze.admit("high", 1_000).is_ok());
+        freeze.record_checkpoint();
+        let frozen = freeze.admit("high", 1_000).unwrap_err();
+        assert_eq!(frozen.reason, FreezeReason::Checkpoints { unanchored: 2, limit: 2 });
+        assert!(freeze.admit("", 1_000).is_err());
+        assert!(freeze.admit("low", 1_000).is_ok());
+
+        // A round short of quorum does not lift the freeze.
+        let mut short = anchored(2, 1_000);
+        short.receipts.clear();
+        freeze.record_anchor(&short);
+        assert!(freeze.check(1_000).is_err());
+        freeze.record_anchor(&anchored(2, 1_000));
+        assert!(freeze.check(1_000).is_ok());
+    }
+
+    #[test]
+    fn the_unanchored_window_resumes_from_the_newest_recorded_receipt() {
+        let dir = std::env::temp_dir().join(format!("rfsn-freeze-resume-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut store = DeterministicStore::new(&dir).unwrap();
+        ReceiptEntry::from_status(&anchored(1, 5_000)).append_to(&mut store).unwrap();
+        ReceiptEntry::from_status(&anchored(2, 4_000)).append_to(&mut store).unwrap();
+
+        let policy = FreezePolicy { max_unanchored_checkpoints: None, max_unanchored_for: Some(Duration::from_secs(60)) };
+        let freeze = AnchoringFreeze::resume_from_ledger(policy, &store).unwrap();
+        assert!(freeze.check(5_059).is_ok());
+        let frozen = freeze.check(5_060).unwrap_err();
+        assert_eq!((frozen.reason, frozen.last_anchor_unix), (FreezeReason::Elapsed { unanchored_for_secs: 60, limit_secs: 60 }, 5_000));
+        drop(store);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,155 @@
+//! Local self-witness for development and testing.
+//!
+//! `LocalWitness` signs receipts with a local Ed25519 key and keeps them in an
+//! in-process receipt store, speaking the same receipt format as the JSON witness.
+//! CI and air-gapped development can run the full anchoring pipeline (quorum,
+//! persistence, outbox, batching, audit) against it. It proves nothing to a third
+//! party and must not be configured in production.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use ed25519_dalek::{Signer, SigningKey};
+
+use super::notarize::{verify_json_receipt, AnchorRequest, NotaryBackend, Receipt};
+use super::notarize_async::{AsyncNotaryBackend, AsyncNotaryError};
+use super::outbox::unix_now;
+use super::witness_keys::{receipt_signing_payload, SignatureAlgorithm, WitnessKey, WitnessKeyring};
+
+// Both notary traits define `name()`, which makes `self.name()` ambiguous here.
+const NAME: &str = "local";
+
+/// Cheap to clone: clones share the key and the receipt store, so a test can keep a
+/// handle to inspect what the client submitted.
+#[derive(Clone)]
+pub struct LocalWitness {
+    key_id: String,
+    signing_key: Arc<SigningKey>,
+    keys: WitnessKeyring,
+    receipts: Arc<Mutex<HashMap<String, Receipt>>>,
+}
+
+impl LocalWitness {
+    pub fn new(key_id: &str, signing_key: SigningKey) -> Self {
+        let public_key = signing_key.verifying_key().to_bytes().to_vec();
+        let keys = WitnessKeyring::new(vec![WitnessKey::new(key_id, SignatureAlgorithm::Ed25519, public_key)]);
+        Self {
+            key_id: key_id.to_string(),
+            signing_key: Arc::new(signing_key),
+            keys,
+            receipts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Deterministic key derived from `seed`, so test fixtures are reproducible.
+    pub fn from_seed(key_id: &str, seed: [u8; 32]) -> Self {
+        Self::new(key_id, SigningKey::from_bytes(&seed))
+    }
+
+    /// The keyring a verifier should pin for this witness.
+    pub fn keyring(&self) -> &WitnessKeyring {
+        &self.keys
+    }
+
+    /// In-process equivalent of `POST {url}`.
+    pub fn notarize(&self, req: &AnchorRequest) -> Receipt {
+        let mut receipts = self.receipts.lock().unwrap();
+        let receipt_id = format!("local-{:016x}", receipts.len());
+        let external_timestamp = unix_now();
+        let payload = receipt_signing_payload(&receipt_id, req, external_timestamp);
+        let receipt = Receipt {
+            backend: NAME.to_string(),
+            receipt_id: receipt_id.clone(),
+            request: req.clone(),
+            external_timestamp,
+            evidence: self.signing_key.sign(&payload).to_bytes().to_vec(),
+            key_id: Some(self.key_id.clone()),
+        };
+        receipts.insert(receipt_id, receipt.clone());
+        receipt
+    }
+
+    /// In-process equivalent of `GET {url}/receipts/{id}`.
+    pub fn receipt(&self, receipt_id: &str) -> Option<Receipt> {
+        self.receipts.lock().unwrap().get(receipt_id).cloned()
+    }
+
+    /// Every receipt issued so far.
+    pub fn issued(&self) -> usize {
+        self.receipts.lock().unwrap().len()
+    }
+
+    /// The most recently issued receipt; ids are zero-padded so they sort by issue order.
+    pub fn latest(&self) -> Option<Receipt> {
+        let receipts = self.receipts.lock().unwrap();
+        receipts.keys().max().and_then(|id| receipts.get(id)).cloned()
+    }
+}
+
+impl NotaryBackend for LocalWitness {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn submit(&self, req: &AnchorRequest) -> Result<Receipt, Box<dyn Error>> {
+        Ok(self.notarize(req))
+    }
+
+    fn fetch_receipt(&self, receipt_id: &str) -> Result<Receipt, Box<dyn Error>> {
+        self.receipt(receipt_id).ok_or_else(|| format!("local witness has no receipt {}", receipt_id).into())
+    }
+
+    fn verify_receipt(&self, req: &AnchorRequest, receipt: &Receipt) -> Result<(), Box<dyn Error>> {
+        Ok(verify_json_receipt(NAME, &self.keys, req, receipt)?)
+    }
+
+    fn latest_receipt(&self) -> Result<Option<Receipt>, Box<dyn Error>> {
+        Ok(self.latest())
+    }
+}
+
+#[async_trait]
+impl AsyncNotaryBackend for LocalWitness {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn submit(&self, req: &AnchorRequest) -> Result<Receipt, AsyncNotaryError> {
+        Ok(self.notarize(req))
+    }
+
+    async fn fetch_receipt(&self, receipt_id: &str) -> Result<Receipt, AsyncNotaryError> {
+        self.receipt(receipt_id).ok_or_else(|| format!("local witness has no receipt {}", receipt_id).into())
+    }
+
+    fn verify_receipt(&self, req: &AnchorRequest, receipt: &Receipt) -> Result<(), AsyncNotaryError> {
+        Ok(verify_json_receipt(NAME, &self.keys, req, receipt)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(index: u64) -> AnchorRequest {
+        AnchorRequest { ledger_head_hash: "12".repeat(32), index, timestamp_ticks: index }
+    }
+
+    #[test]
+    fn receipts_verify_under_its_keyring_and_can_be_fetched_back() {
+        let witness = LocalWitness::from_seed("dev", [6; 32]);
+        let first = NotaryBackend::submit(&witness, &request(1)).unwrap();
+        let second = NotaryBackend::submit(&witness.clone(), &request(2)).unwrap();
+        assert_eq!((witness.issued(), witness.latest()), (2, Some(second.clone())));
+        assert_eq!(NotaryBackend::fetch_receipt(&witness, &first.receipt_id).unwrap(), first);
+        assert!(NotaryBackend::fetch_receipt(&witness, "local-ffffffffffffffff").is_err());
+
+        assert!(NotaryBackend::verify_receipt(&witness, &request(1), &first).is_ok());
+        assert!(NotaryBackend::verify_receipt(&witness, &request(2), &first).is_err());
+        let other = LocalWitness::from_seed("dev", [7; 32]);
+        assert!(NotaryBackend::verify_receipt(&other, &request(1), &first).is_err());
+        assert_eq!(witness.keyring().keys[0].key_id, "dev");
+    }
+}
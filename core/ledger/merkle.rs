@@ -0,0 +1,336 @@
+//! Ledger Merkle tree and the checkpoint file format.
+//!
+//! The tree follows RFC 6962 (leaf and node hashes are domain-separated) with BLAKE3
+//! as the hash. The store only keeps the compact frontier: the roots of the perfect
+//! subtrees that make up the current tree size, i.e. `O(log n)` hashes.
+//!
+//! A checkpoint (`index/merkle.chk`) is a fixed binary record:
+//!
+//! ```text
+//! "RFSNCHK1" | version: u32 | tree_size: u64 | tick: u64 | root: [u8; 32] | blake3(all previous): [u8; 32]
+//! ```
+
+use std::error::Error;
+use std::fmt;
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"RFSNCHK1";
+const CHECKPOINT_FORMAT_VERSION: u32 = 1;
+pub const CHECKPOINT_LEN: usize = 8 + 4 + 8 + 8 + 32 + 32;
+
+pub fn leaf_hash(payload: &[u8]) -> [u8; 32] {
+    let mut h = blake3::Hasher::new();
+    h.update(&[0x00]);
+    h.update(payload);
+    *h.finalize().as_bytes()
+}
+
+pub fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut h = blake3::Hasher::new();
+    h.update(&[0x01]);
+    h.update(left);
+    h.update(right);
+    *h.finalize().as_bytes()
+}
+
+/// Compact Merkle frontier of an append-only tree.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MerkleFrontier {
+    size: u64,
+    // (height, root) of each perfect subtree, left to right, strictly decreasing height.
+    peaks: Vec<(u32, [u8; 32])>,
+}
+
+impl MerkleFrontier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn push(&mut self, leaf: [u8; 32]) {
+        let mut node = (0u32, leaf);
+        while let Some(&(height, left)) = self.peaks.last() {
+            if height != node.0 {
+                break;
+            }
+            self.peaks.pop();
+            node = (height + 1, node_hash(&left, &node.1));
+        }
+        self.peaks.push(node);
+        self.size += 1;
+    }
+
+    /// RFC 6962 tree head for the current size. The empty tree hashes the empty string.
+    pub fn root(&self) -> [u8; 32] {
+        let mut peaks = self.peaks.iter().rev();
+        let Some(&(_, mut acc)) = peaks.next() else {
+            return *blake3::hash(&[]).as_bytes();
+        };
+        for (_, left) in peaks {
+            acc = node_hash(left, &acc);
+        }
+        acc
+    }
+}
+
+/// RFC 6962 tree head over an explicit list of leaf hashes.
+pub fn root_of(leaves: &[[u8; 32]]) -> [u8; 32] {
+    match leaves.len() {
+        0 => *blake3::hash(&[]).as_bytes(),
+        1 => leaves[0],
+        n => {
+            let k = split_point(n);
+            node_hash(&root_of(&leaves[..k]), &root_of(&leaves[k..]))
+        }
+    }
+}
+
+// Largest power of two strictly less than `n` (n >= 2).
+fn split_point(n: usize) -> usize {
+    1usize << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+/// RFC 6962 audit path for `leaves[index]`, leaf-most sibling first.
+pub fn inclusion_proof(leaves: &[[u8; 32]], index: usize) -> Vec<[u8; 32]> {
+    if leaves.len() <= 1 {
+        return Vec::new();
+    }
+    let k = split_point(leaves.len());
+    if index < k {
+        let mut path = inclusion_proof(&leaves[..k], index);
+        path.push(root_of(&leaves[k..]));
+        path
+    } else {
+        let mut path = inclusion_proof(&leaves[k..], index - k);
+        path.push(root_of(&leaves[..k]));
+        path
+    }
+}
+
+/// Checks an audit path produced by `inclusion_proof` (RFC 9162, section 2.1.3.2).
+pub fn verify_inclusion(leaf: &[u8; 32], index: u64, size: u64, proof: &[[u8; 32]], root: &[u8; 32]) -> bool {
+    if index >= size {
+        return false;
+    }
+    let (mut fn_, mut sn) = (index, size - 1);
+    let mut r = *leaf;
+    for p in proof {
+        if sn == 0 {
+            return false;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            r = node_hash(p, &r);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            r = node_hash(&r, p);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    sn == 0 && &r == root
+}
+
+/// RFC 6962 consistency proof that the tree over `leaves[..m]` is a prefix of the tree
+/// over `leaves`.
+pub fn consistency_proof(leaves: &[[u8; 32]], m: usize) -> Vec<[u8; 32]> {
+    fn subproof(m: usize, leaves: &[[u8; 32]], complete: bool) -> Vec<[u8; 32]> {
+        let n = leaves.len();
+        if m == n {
+            return if complete { Vec::new() } else { vec![root_of(leaves)] };
+        }
+        let k = split_point(n);
+        if m <= k {
+            let mut path = subproof(m, &leaves[..k], complete);
+            path.push(root_of(&leaves[k..]));
+            path
+        } else {
+            let mut path = subproof(m - k, &leaves[k..], false);
+            path.push(root_of(&leaves[..k]));
+            path
+        }
+    }
+    if m == 0 || m >= leaves.len() {
+        return Vec::new();
+    }
+    subproof(m, leaves, true)
+}
+
+/// Checks a consistency proof between tree heads of size `m` and `n` (RFC 9162, section 2.1.4.2).
+pub fn verify_consistency(m: u64, n: u64, root_m: &[u8; 32], root_n: &[u8; 32], proof: &[[u8; 32]]) -> bool {
+    if m > n {
+        return false;
+    }
+    if m == n {
+        return proof.is_empty() && root_m == root_n;
+    }
+    if m == 0 {
+        return proof.is_empty();
+    }
+    let mut path = proof.to_vec();
+    if m.is_power_of_two() {
+        path.insert(0, *root_m);
+    }
+    let Some((first, rest)) = path.split_first() else {
+        return false;
+    };
+    let (mut fn_, mut sn) = (m - 1, n - 1);
+    while fn_ & 1 == 1 {
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    let (mut fr, mut sr) = (*first, *first);
+    for c in rest {
+        if sn == 0 {
+            return false;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            fr = node_hash(c, &fr);
+            sr = node_hash(c, &sr);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            sr = node_hash(&sr, c);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    &fr == root_m && &sr == root_n && sn == 0
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckpointError {
+    Malformed(&'static str),
+    ChecksumMismatch,
+    UnsupportedVersion(u32),
+    /// The checkpoint does not cover the ledger index it was expected to.
+    SizeMismatch { expected: u64, found: u64 },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Malformed(why) => write!(f, "malformed checkpoint: {}", why),
+            CheckpointError::ChecksumMismatch => write!(f, "checkpoint checksum mismatch"),
+            CheckpointError::UnsupportedVersion(v) => write!(f, "unsupported checkpoint version {}", v),
+            CheckpointError::SizeMismatch { expected, found } => {
+                write!(f, "checkpoint covers {} entries, expected {}", found, expected)
+            }
+        }
+    }
+}
+
+impl Error for CheckpointError {}
+
+/// A signed-off tree head: what gets notarized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub tree_size: u64,
+    pub tick: u64,
+    pub root: [u8; 32],
+}
+
+impl Checkpoint {
+    pub fn of(tree: &MerkleFrontier, tick: u64) -> Self {
+        Self { tree_size: tree.size(), tick, root: tree.root() }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(CHECKPOINT_LEN);
+        buf.extend_from_slice(CHECKPOINT_MAGIC);
+        buf.extend_from_slice(&CHECKPOINT_FORMAT_VERSION.to_le_bytes());
+        buf.extend_from_slice(&self.tree_size.to_le_bytes());
+        buf.extend_from_slice(&self.tick.to_le_bytes());
+        buf.extend_from_slice(&self.root);
+        let checksum = blake3::hash(&buf);
+        buf.extend_from_slice(checksum.as_bytes());
+        buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, CheckpointError> {
+        if bytes.len() != CHECKPOINT_LEN {
+            return Err(CheckpointError::Malformed("wrong length"));
+        }
+        if &bytes[..8] != CHECKPOINT_MAGIC {
+            return Err(CheckpointError::Malformed("bad magic"));
+        }
+        let (body, checksum) = bytes.split_at(CHECKPOINT_LEN - 32);
+        if blake3::hash(body).as_bytes() != checksum {
+            return Err(CheckpointError::ChecksumMismatch);
+        }
+        let version = u32::from_le_bytes(body[8..12].try_into().unwrap());
+        if version != CHECKPOINT_FORMAT_VERSION {
+            return Err(CheckpointError::UnsupportedVersion(version));
+        }
+        let mut root = [0u8; 32];
+        root.copy_from_slice(&body[28..60]);
+        Ok(Self {
+            tree_size: u64::from_le_bytes(body[12..20].try_into().unwrap()),
+            tick: u64::from_le_bytes(body[20..28].try_into().unwrap()),
+            root,
+        })
+    }
+
+    pub fn root_hex(&self) -> String {
+        hex::encode(self.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frontier_matches_reference_tree() {
+        let mut tree = MerkleFrontier::new();
+        let mut leaves = Vec::new();
+        for i in 0u32..70 {
+            assert_eq!(tree.root(), root_of(&leaves), "size {}", i);
+            let leaf = leaf_hash(&i.to_le_bytes());
+            leaves.push(leaf);
+            tree.push(leaf);
+        }
+    }
+
+    #[test]
+    fn inclusion_proofs_verify() {
+        let leaves: Vec<_> = (0u32..13).map(|i| leaf_hash(&i.to_le_bytes())).collect();
+        let root = root_of(&leaves);
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = inclusion_proof(&leaves, i);
+            assert!(verify_inclusion(leaf, i as u64, 13, &proof, &root), "leaf {}", i);
+            assert!(!verify_inclusion(leaf, (i as u64 + 1) % 13, 13, &proof, &root));
+        }
+    }
+
+    #[test]
+    fn consistency_proofs_verify() {
+        let leaves: Vec<_> = (0u32..21).map(|i| leaf_hash(&i.to_le_bytes())).collect();
+        let root_n = root_of(&leaves);
+        for m in 1..=leaves.len() {
+            let root_m = root_of(&leaves[..m]);
+            let proof = consistency_proof(&leaves, m);
+            assert!(verify_consistency(m as u64, 21, &root_m, &root_n, &proof), "m = {}", m);
+        }
+        let forked = leaf_hash(b"rewritten");
+        let mut rewritten = leaves.clone();
+        rewritten[3] = forked;
+        let proof = consistency_proof(&rewritten, 8);
+        assert!(!verify_consistency(8, 21, &root_of(&leaves[..8]), &root_of(&rewritten), &proof));
+    }
+
+    #[test]
+    fn checkpoint_roundtrip_and_tamper() {
+        let chk = Checkpoint { tree_size: 1024, tick: 77, root: [9; 32] };
+        let mut bytes = chk.encode();
+        assert_eq!(Checkpoint::decode(&bytes), Ok(chk));
+        bytes[14] ^= 1;
+        assert_eq!(Checkpoint::decode(&bytes), Err(CheckpointError::ChecksumMismatch));
+    }
+}
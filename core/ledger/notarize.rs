@@ -1,71 +1,1025 @@
 use std::error::Error;
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
 use reqwest::blocking::Client; // Requires `reqwest` for external HTTP calls
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-#[derive(Serialize)]
-struct NotarizeRequest {
+use super::batch::BATCH_HEAD_PREFIX;
+use super::clock_skew::{ClockSkew, SkewPolicy};
+use super::der;
+use super::ethereum::{EthereumBackend, EthereumConfig};
+use super::local_witness::LocalWitness;
+use super::merkle::{Checkpoint, CheckpointError};
+use super::notary_http::HttpClientConfig;
+use super::notary_metrics::NotaryMetrics;
+use super::outbox::unix_now;
+use super::receipt_cose::write_receipt_file;
+use super::witness_directory::{Failover, Skip, WitnessHealth};
+use super::witness_keys::{self, receipt_signing_payload, SignatureAlgorithm, WitnessKeyring};
+
+/// Typed notarization failures. Backends return these boxed; callers that need to
+/// distinguish a forged or mis-signed receipt from a network error can downcast.
+#[derive(Debug)]
+pub enum NotaryError {
+    /// The receipt names a signing key that is not pinned for this witness (or none are pinned).
+    UnknownWitnessKey { backend: String, key_id: Option<String> },
+    /// The receipt names pinned keys, but none is valid at the witness timestamp
+    /// (not yet active, rotated out or revoked).
+    KeyNotValid { backend: String, key_id: Option<String>, witnessed_at: u64 },
+    /// No pinned key of this witness verifies the receipt signature.
+    InvalidSignature { backend: String, key_id: Option<String> },
+    /// The signature bytes did not verify under the given key.
+    BadSignature,
+    /// The witness used a signature algorithm we do not implement.
+    UnsupportedAlgorithm(String),
+    /// The receipt is well-formed but does not describe the anchored request.
+    ReceiptMismatch(&'static str),
+    /// A key, signature or token could not be decoded.
+    Malformed(String),
+    /// The witness timestamp is outside the configured `SkewPolicy` bound.
+    ClockSkew(ClockSkew),
+}
+
+impl std::fmt::Display for NotaryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotaryError::UnknownWitnessKey { backend, key_id } => {
+                write!(f, "witness {} signed with an unpinned key {:?}", backend, key_id)
+            }
+            NotaryError::KeyNotValid { backend, key_id, witnessed_at } => {
+                write!(f, "no key {:?} of witness {} was valid at {}", key_id, backend, witnessed_at)
+            }
+            NotaryError::InvalidSignature { backend, key_id } => {
+                write!(f, "receipt signature from witness {} (key {:?}) does not verify", backend, key_id)
+            }
+            NotaryError::BadSignature => write!(f, "signature does not verify"),
+            NotaryError::UnsupportedAlgorithm(alg) => write!(f, "unsupported signature algorithm {}", alg),
+            NotaryError::ReceiptMismatch(reason) => write!(f, "receipt mismatch: {}", reason),
+            NotaryError::Malformed(what) => write!(f, "malformed {}", what),
+            NotaryError::ClockSkew(skew) => write!(
+                f,
+                "witness {} timestamp {} is {}s off the expected {}",
+                skew.backend, skew.external_timestamp, skew.skew_secs, skew.expected_unix
+            ),
+        }
+    }
+}
+
+impl Error for NotaryError {}
+
+/// What gets anchored: a ledger head at a given index and tick, taken from a parsed
+/// `Checkpoint` (see `anchor_request_from_checkpoint`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AnchorRequest {
+    /// Hex-encoded Merkle root of the first `index` entries.
     pub ledger_head_hash: String,
+    /// Tree size the root covers.
     pub index: u64,
+    /// Tick recorded in the checkpoint, from the store's `TickSource`.
     pub timestamp_ticks: u64,
 }
 
-#[derive(Deserialize)]
-struct NotarizeResponse {
+impl AnchorRequest {
+    /// The anchored Merkle root as raw bytes, with any batch-head prefix removed.
+    pub fn head_root(&self) -> Result<[u8; 32], NotaryError> {
+        let root_hex = self.ledger_head_hash.strip_prefix(BATCH_HEAD_PREFIX).unwrap_or(&self.ledger_head_hash);
+        hex::decode(root_hex)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| NotaryError::Malformed("ledger head hash".to_string()))
+    }
+}
+
+/// Backend-neutral evidence that a witness saw an `AnchorRequest`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Receipt {
+    /// `NotaryBackend::name()` of the backend that produced this receipt.
+    pub backend: String,
     pub receipt_id: String,
+    pub request: AnchorRequest,
     pub external_timestamp: u64,
-    pub signature: String, // Witness signature of the payload
+    /// Backend-specific proof: a witness signature, a DER timestamp token, a transaction hash...
+    #[serde(with = "hex::serde")]
+    pub evidence: Vec<u8>,
+    /// Pinned witness key the evidence was signed with, when the witness names one.
+    #[serde(default)]
+    pub key_id: Option<String>,
+}
+
+/// A witness that can seal ledger heads. Implementations are selected through
+/// `NotaryConfig`, so call sites only ever see `NotaryClient`.
+pub trait NotaryBackend: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Publishes the request to the witness and returns its receipt.
+    fn submit(&self, req: &AnchorRequest) -> Result<Receipt, Box<dyn Error>>;
+
+    /// Re-fetches a previously issued receipt from the witness, where the witness supports it.
+    fn fetch_receipt(&self, receipt_id: &str) -> Result<Receipt, Box<dyn Error>>;
+
+    /// Checks that `receipt` is valid witness evidence for `req`.
+    fn verify_receipt(&self, req: &AnchorRequest, receipt: &Receipt) -> Result<(), Box<dyn Error>>;
+
+    /// The newest receipt the witness has issued to us, for split-view detection.
+    /// `None` if the witness cannot report it.
+    fn latest_receipt(&self) -> Result<Option<Receipt>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    /// Cheap liveness probe used to bring a down witness back into a `WitnessDirectory`
+    /// rotation. Backends without one are simply retried.
+    fn health_check(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Which backend a `NotaryClient` talks to.
+pub enum NotaryConfig {
+    /// JSON over HTTP to a generic witness endpoint; receipts must verify under `keys`.
+    HttpJson { url: String, keys: WitnessKeyring, http: HttpClientConfig },
+    /// RFC 3161 timestamping authority.
+    Tsa(TsaConfig),
+    /// Anchoring contract on an Ethereum chain, via JSON-RPC.
+    Ethereum(EthereumConfig),
+    /// In-process self-witness; development and CI only.
+    Local(LocalWitness),
+}
+
+impl NotaryConfig {
+    pub fn into_backend(self) -> Result<Box<dyn NotaryBackend>, Box<dyn Error>> {
+        Ok(match self {
+            NotaryConfig::HttpJson { url, keys, http } => Box::new(HttpJsonBackend::new(&url, keys, &http)?),
+            NotaryConfig::Tsa(config) => Box::new(TsaBackend::new(config)?),
+            NotaryConfig::Ethereum(config) => Box::new(EthereumBackend::new(config)?),
+            NotaryConfig::Local(witness) => Box::new(witness),
+        })
+    }
+}
+
+/// Serializable description of a witness, for configuration files and tooling.
+/// `kind` matches the backend's `NotaryBackend::name()`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum WitnessSpec {
+    HttpJson {
+        url: String,
+        keys: WitnessKeyring,
+    },
+    Rfc3161 {
+        url: String,
+        #[serde(with = "hex::serde")]
+        tsa_public_key_der: Vec<u8>,
+        #[serde(default)]
+        policy_oid: Option<Vec<u64>>,
+    },
+    Ethereum {
+        rpc_url: String,
+        contract_address: String,
+        from_address: String,
+        confirmations: u64,
+    },
+    /// Recreates a `LocalWitness` from its seed; development and CI only.
+    Local {
+        key_id: String,
+        #[serde(with = "hex::serde")]
+        seed: [u8; 32],
+    },
+}
+
+impl WitnessSpec {
+    pub fn into_config(self, http: &HttpClientConfig) -> NotaryConfig {
+        match self {
+            WitnessSpec::HttpJson { url, keys } => NotaryConfig::HttpJson { url, keys, http: http.clone() },
+            WitnessSpec::Rfc3161 { url, tsa_public_key_der, policy_oid } => {
+                NotaryConfig::Tsa(TsaConfig { url, tsa_public_key_der, policy_oid, http: http.clone() })
+            }
+            WitnessSpec::Ethereum { rpc_url, contract_address, from_address, confirmations } => NotaryConfig::Ethereum(
+                EthereumConfig::new(&rpc_url, &contract_address, &from_address)
+                    .confirmations(confirmations)
+                    .http(http.clone()),
+            ),
+            WitnessSpec::Local { key_id, seed } => NotaryConfig::Local(LocalWitness::from_seed(&key_id, seed)),
+        }
+    }
+}
+
+/// A witnesses file: `{ "threshold": 2, "witnesses": [{ "kind": "http-json", ... }, ...] }`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WitnessSet {
+    pub threshold: usize,
+    pub witnesses: Vec<WitnessSpec>,
+}
+
+impl WitnessSet {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn into_client(self, http: &HttpClientConfig) -> Result<NotaryClient, Box<dyn Error>> {
+        let configs = self.witnesses.into_iter().map(|w| w.into_config(http)).collect();
+        NotaryClient::quorum(configs, self.threshold)
+    }
+}
+
+/// Outcome of one notarization round across the configured witnesses.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuorumStatus {
+    pub request: AnchorRequest,
+    /// Number of valid receipts required before the checkpoint counts as anchored.
+    pub threshold: usize,
+    pub witnesses: usize,
+    pub receipts: Vec<Receipt>,
+    /// `(backend name, error)` for every witness that did not yield a valid receipt.
+    pub failures: Vec<(String, String)>,
+    /// Accepted receipts whose timestamp exceeded the skew bound under `SkewEnforcement::Warn`.
+    #[serde(default)]
+    pub skew_warnings: Vec<ClockSkew>,
+}
+
+impl QuorumStatus {
+    pub fn new(request: AnchorRequest, threshold: usize, witnesses: usize) -> Self {
+        Self { request, threshold, witnesses, receipts: Vec::new(), failures: Vec::new(), skew_warnings: Vec::new() }
+    }
+
+    pub fn anchored(&self) -> bool {
+        self.receipts.len() >= self.threshold
+    }
+}
+
+/// Returned when fewer than `threshold` witnesses produced a valid receipt.
+/// The partial status (and the receipts that were collected) is still persisted.
+#[derive(Debug)]
+pub struct QuorumNotReached(pub QuorumStatus);
+
+impl std::fmt::Display for QuorumNotReached {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "notarization quorum not reached: {}/{} receipts (need {})",
+            self.0.receipts.len(),
+            self.0.witnesses,
+            self.0.threshold
+        )
+    }
+}
+
+impl Error for QuorumNotReached {}
+
+/// Builds the request for a checkpoint file's contents.
+/// Parses a binary checkpoint and builds the canonical request for it. A checkpoint
+/// that fails local validation, or does not cover exactly `index` entries, is never anchored.
+pub(crate) fn anchor_request_from_checkpoint(contents: &[u8], index: u64) -> Result<AnchorRequest, Box<dyn Error>> {
+    let checkpoint = Checkpoint::decode(contents)?;
+    if checkpoint.tree_size != index {
+        return Err(Box::new(CheckpointError::SizeMismatch { expected: index, found: checkpoint.tree_size }));
+    }
+    Ok(AnchorRequest {
+        ledger_head_hash: checkpoint.root_hex(),
+        index: checkpoint.tree_size,
+        timestamp_ticks: checkpoint.tick,
+    })
+}
+
+/// COSE receipt file for `receipt`; see `receipt_cose`.
+pub(crate) fn receipt_path(checkpoint_path: &Path, receipt: &Receipt) -> std::path::PathBuf {
+    checkpoint_path.with_extension(format!("{}.receipt", receipt.receipt_id))
+}
+
+pub(crate) fn quorum_path(checkpoint_path: &Path, index: u64) -> std::path::PathBuf {
+    checkpoint_path.with_extension(format!("{:016x}.quorum", index))
 }
 
 /// External anchoring (notarization) serves as a tamper-evident seal.
 /// It periodically takes the `merkle.chk` or `ledger.head` and publishes 
 /// it to an external, untrusted but immutable witness (e.g., a timestamping authority, 
 /// a distributed ledger, or a transparency log).
+///
+/// A single witness is a single point of collusion/failure, so several independent
+/// witnesses can be configured; a checkpoint is only anchored once `threshold` of
+/// them have returned valid receipts.
 pub struct NotaryClient {
-    endpoint_url: String,
-    client: Client,
+    backends: Vec<Box<dyn NotaryBackend>>,
+    threshold: usize,
+    skew: Option<SkewPolicy>,
+    metrics: Option<Arc<NotaryMetrics>>,
+    failover: Option<Failover>,
 }
 
 impl NotaryClient {
-    pub fn new(url: &str) -> Self {
-        Self {
-            endpoint_url: url.to_string(),
-            client: Client::new(),
+    /// JSON-over-HTTP witness at `url` whose receipts must verify under one of `keys`.
+    /// `http` carries the mTLS identity, CA bundle, proxy and timeouts.
+    pub fn new(url: &str, keys: WitnessKeyring, http: HttpClientConfig) -> Result<Self, Box<dyn Error>> {
+        Self::from_config(NotaryConfig::HttpJson { url: url.to_string(), keys, http })
+    }
+
+    pub fn from_config(config: NotaryConfig) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::with_backend(config.into_backend()?))
+    }
+
+    pub fn with_backend(backend: Box<dyn NotaryBackend>) -> Self {
+        Self { backends: vec![backend], threshold: 1, skew: None, metrics: None, failover: None }
+    }
+
+    /// `threshold`-of-`configs.len()` quorum over independent witnesses.
+    pub fn quorum(configs: Vec<NotaryConfig>, threshold: usize) -> Result<Self, Box<dyn Error>> {
+        let backends = configs.into_iter().map(NotaryConfig::into_backend).collect::<Result<_, _>>()?;
+        Self::quorum_of(backends, threshold)
+    }
+
+    pub fn quorum_of(backends: Vec<Box<dyn NotaryBackend>>, threshold: usize) -> Result<Self, Box<dyn Error>> {
+        if threshold == 0 || threshold > backends.len() {
+            return Err(format!("invalid quorum {}-of-{}", threshold, backends.len()).into());
+        }
+        Ok(Self { backends, threshold, skew: None, metrics: None, failover: None })
+    }
+
+    /// Checks every receipt's `external_timestamp` against `policy`.
+    pub fn clock_skew(mut self, policy: SkewPolicy) -> Self {
+        self.skew = Some(policy);
+        self
+    }
+
+    /// Records every notarization round in `metrics`.
+    pub fn observe(mut self, metrics: Arc<NotaryMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn metrics(&self) -> Option<&Arc<NotaryMetrics>> {
+        self.metrics.as_ref()
+    }
+
+    /// Switches to priority failover; see `WitnessDirectory`.
+    pub(crate) fn failover(mut self, failover: Failover) -> Self {
+        self.failover = Some(failover);
+        self
+    }
+
+    /// Health of each witness, for clients built from a `WitnessDirectory`.
+    pub fn witness_health(&self) -> Option<Vec<WitnessHealth>> {
+        Some(self.failover.as_ref()?.health(self.backends()))
+    }
+
+    /// Health-checks down witnesses whose retry period has passed and brings the
+    /// healthy ones back. A no-op for clients without a directory.
+    pub fn check_health(&self) {
+        let Some(failover) = &self.failover else { return };
+        let now = Instant::now();
+        for i in failover.due_for_probe(now) {
+            let backend = &self.backends[i];
+            match backend.health_check() {
+                Ok(()) => {
+                    println!("✅ Witness {} is healthy again.", backend.name());
+                    failover.record(i, true, now);
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Witness {} is still down: {}", backend.name(), e);
+                    failover.still_down(i, now);
+                }
+            }
         }
     }
 
-    /// Read the latest Merkle checkpoint or Ledger head from disk and notarize it.
-    pub fn notarize_checkpoint(&self, checkpoint_path: &Path, current_index: u64, ticks: u64) -> Result<(), Box<dyn Error>> {
-        // In a real system, you parse the Merkle root from the checkpoint file.
-        // For simplicity, we read the raw hex representation here.
-        let raw_hash = fs::read_to_string(checkpoint_path)?;
-        let trimmed_hash = raw_hash.trim().to_string();
+    pub fn backends(&self) -> impl Iterator<Item = &dyn NotaryBackend> {
+        self.backends.iter().map(|b| b.as_ref())
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Reads back the persisted quorum status for the checkpoint anchored at `index`.
+    pub fn quorum_status(checkpoint_path: &Path, index: u64) -> Result<QuorumStatus, Box<dyn Error>> {
+        Ok(serde_json::from_str(&fs::read_to_string(quorum_path(checkpoint_path, index))?)?)
+    }
+
+    /// Read the latest Merkle checkpoint or Ledger head from disk and notarize it
+    /// with every configured witness.
+    pub fn notarize_checkpoint(&self, checkpoint_path: &Path, current_index: u64) -> Result<QuorumStatus, Box<dyn Error>> {
+        let req = anchor_request_from_checkpoint(&fs::read(checkpoint_path)?, current_index)?;
+        self.notarize_request(checkpoint_path, &req)
+    }
+
+    /// Notarizes an already captured request. Receipts are stored next to `checkpoint_path`;
+    /// the checkpoint file itself is not read, so this also works for heads that were
+    /// queued before the checkpoint was overwritten.
+    pub fn notarize_request(&self, checkpoint_path: &Path, req: &AnchorRequest) -> Result<QuorumStatus, Box<dyn Error>> {
+        let status = self.collect_receipts(req);
+        Self::persist(checkpoint_path, &status)?;
+
+        if !status.anchored() {
+            return Err(Box::new(QuorumNotReached(status)));
+        }
+        println!(
+            "✅ Anchored Ledger Index {} (Hash: {}) with {}/{} witnesses.",
+            req.index, req.ledger_head_hash, status.receipts.len(), status.witnesses
+        );
+        Ok(status)
+    }
 
-        let req = NotarizeRequest {
-            ledger_head_hash: trimmed_hash.clone(),
-            index: current_index,
-            timestamp_ticks: ticks,
+    /// Submits `req` to every witness (with a `WitnessDirectory`, in priority order until
+    /// the threshold is met) and verifies what comes back, without persisting anything.
+    pub fn collect_receipts(&self, req: &AnchorRequest) -> QuorumStatus {
+        let started = Instant::now();
+        if let Some(metrics) = &self.metrics {
+            metrics.record_attempt();
+        }
+        let mut status = QuorumStatus::new(req.clone(), self.threshold, self.backends.len());
+        let Some(failover) = &self.failover else {
+            for i in 0..self.backends.len() {
+                self.submit_to(i, req, &mut status);
+            }
+            return self.finish_round(started, status);
         };
 
+        // Priority order: stop as soon as the threshold is met.
+        let now = Instant::now();
+        let mut down = Vec::new();
+        for i in 0..self.backends.len() {
+            if status.anchored() {
+                break;
+            }
+            match failover.acquire(i, now) {
+                Ok(()) => self.submit_to(i, req, &mut status),
+                Err(Skip::Down) => down.push(i),
+                Err(Skip::RateLimited) => status.failures.push((self.backends[i].name().to_string(), "rate limited".to_string())),
+            }
+        }
+        // Down witnesses are a last resort, not excluded.
+        for i in down {
+            if status.anchored() {
+                break;
+            }
+            if failover.acquire_down(i) {
+                self.submit_to(i, req, &mut status);
+            } else {
+                status.failures.push((self.backends[i].name().to_string(), "down and rate limited".to_string()));
+            }
+        }
+        self.finish_round(started, status)
+    }
+
+    fn submit_to(&self, i: usize, req: &AnchorRequest, status: &mut QuorumStatus) {
+        let backend = &self.backends[i];
+        let outcome = backend.submit(req).and_then(|receipt| {
+            backend.verify_receipt(req, &receipt)?;
+            if let Some(skew) = &self.skew {
+                skew.apply(req, &receipt, Some(unix_now()), status)?;
+            }
+            Ok(receipt)
+        });
+        if let Some(failover) = &self.failover {
+            failover.record(i, outcome.is_ok(), Instant::now());
+        }
+        match outcome {
+            Ok(receipt) => status.receipts.push(receipt),
+            Err(e) => status.failures.push((backend.name().to_string(), e.to_string())),
+        }
+    }
+
+    fn finish_round(&self, started: Instant, status: QuorumStatus) -> QuorumStatus {
+        if let Some(metrics) = &self.metrics {
+            if status.anchored() {
+                metrics.record_success(started.elapsed());
+            } else {
+                metrics.record_failure(&QuorumNotReached(status.clone()).to_string());
+            }
+        }
+        status
+    }
+
+    /// Checks receipts obtained out of band (e.g. carried across an air gap) against the
+    /// configured witnesses. Each witness can account for at most one receipt, so the
+    /// quorum counts distinct witnesses, not receipts.
+    pub fn verify_receipts(&self, req: &AnchorRequest, receipts: Vec<Receipt>) -> QuorumStatus {
+        let mut remaining = receipts;
+        let mut status = QuorumStatus::new(req.clone(), self.threshold, self.backends.len());
+        for backend in &self.backends {
+            let Some(i) = remaining.iter().position(|r| backend.verify_receipt(req, r).is_ok()) else {
+                status.failures.push((backend.name().to_string(), "no valid receipt supplied".to_string()));
+                continue;
+            };
+            let receipt = remaining.swap_remove(i);
+            // Receipts carried in from elsewhere can only be checked against the tick mapping.
+            let skew = self.skew.map_or(Ok(()), |skew| skew.apply(req, &receipt, None, &mut status));
+            match skew {
+                Ok(()) => status.receipts.push(receipt),
+                Err(e) => status.failures.push((backend.name().to_string(), e.to_string())),
+            }
+        }
+        status
+    }
+
+    /// Saves every receipt and the quorum status next to the checkpoint. The combination of
+    /// local state + external receipt proves this ledger head existed at `external_timestamp`
+    /// and hasn't been rewritten.
+    pub fn persist(checkpoint_path: &Path, status: &QuorumStatus) -> Result<(), Box<dyn Error>> {
+        for receipt in &status.receipts {
+            write_receipt_file(&receipt_path(checkpoint_path, receipt), receipt)?;
+        }
+        fs::write(quorum_path(checkpoint_path, status.request.index), serde_json::to_string_pretty(status)?)?;
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// HTTP JSON witness backend
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize)]
+pub(crate) struct NotarizeResponse {
+    pub receipt_id: String,
+    pub external_timestamp: u64,
+    pub signature: String, // Witness signature of the payload
+    #[serde(default)]
+    pub key_id: Option<String>,
+    /// Echo of the anchored request; present on `fetch_receipt` responses.
+    #[serde(default)]
+    pub request: Option<AnchorRequest>,
+}
+
+/// The generic JSON witness: `POST {url}` with an `AnchorRequest`,
+/// `GET {url}/receipts/{id}` to re-fetch, `GET {url}/receipts/latest` for the
+/// newest receipt issued to this client and `GET {url}/health`. The witness signs
+/// `receipt_signing_payload(receipt_id, request, external_timestamp)`.
+pub struct HttpJsonBackend {
+    endpoint_url: String,
+    keys: WitnessKeyring,
+    client: Client,
+}
+
+impl HttpJsonBackend {
+    pub fn new(url: &str, keys: WitnessKeyring, http: &HttpClientConfig) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            endpoint_url: url.to_string(),
+            keys,
+            client: http.build_blocking()?,
+        })
+    }
+}
+
+pub(crate) fn json_receipt(backend: &str, resp: NotarizeResponse, request: AnchorRequest) -> Result<Receipt, Box<dyn Error>> {
+    Ok(Receipt {
+        backend: backend.to_string(),
+        receipt_id: resp.receipt_id,
+        request,
+        external_timestamp: resp.external_timestamp,
+        evidence: hex::decode(resp.signature.trim()).map_err(|_| NotaryError::Malformed("receipt signature hex".into()))?,
+        key_id: resp.key_id,
+    })
+}
+
+pub(crate) fn verify_json_receipt(backend: &str, keys: &WitnessKeyring, req: &AnchorRequest, receipt: &Receipt) -> Result<(), NotaryError> {
+    if receipt.backend != backend || &receipt.request != req {
+        return Err(NotaryError::ReceiptMismatch("receipt does not cover this anchor request"));
+    }
+    let payload = receipt_signing_payload(&receipt.receipt_id, &receipt.request, receipt.external_timestamp);
+    keys.verify(backend, receipt.key_id.as_deref(), receipt.external_timestamp, &payload, &receipt.evidence)?;
+    Ok(())
+}
+
+impl NotaryBackend for HttpJsonBackend {
+    fn name(&self) -> &str {
+        "http-json"
+    }
+
+    fn submit(&self, req: &AnchorRequest) -> Result<Receipt, Box<dyn Error>> {
         // Publish the hash signature to the external witness
         let res = self.client.post(&self.endpoint_url)
-            .json(&req)
+            .json(req)
             .send()?;
 
         if !res.status().is_success() {
             return Err(format!("Notarization failed with HTTP {}", res.status()).into());
         }
+        let resp: NotarizeResponse = res.json()?;
+        json_receipt(self.name(), resp, req.clone())
+    }
+
+    fn fetch_receipt(&self, receipt_id: &str) -> Result<Receipt, Box<dyn Error>> {
+        let url = format!("{}/receipts/{}", self.endpoint_url.trim_end_matches('/'), receipt_id);
+        let res = self.client.get(&url).send()?;
+        if !res.status().is_success() {
+            return Err(format!("Receipt fetch failed with HTTP {}", res.status()).into());
+        }
+        let mut resp: NotarizeResponse = res.json()?;
+        let request = resp.request.take().ok_or("witness did not echo the anchored request")?;
+        json_receipt(self.name(), resp, request)
+    }
+
+    fn verify_receipt(&self, req: &AnchorRequest, receipt: &Receipt) -> Result<(), Box<dyn Error>> {
+        Ok(verify_json_receipt(self.name(), &self.keys, req, receipt)?)
+    }
 
-        let receipt: NotarizeResponse = res.json()?;
-        
-        // Save the receipt locally. The combination of local state + external receipt
-        // proves this ledger head existed at `external_timestamp` and hasn't been rewritten.
-        let receipt_path = checkpoint_path.with_extension(format!("{}.receipt", receipt.receipt_id));
-        let receipt_data = serde_json::to_string_pretty(&receipt)?;
-        fs::write(receipt_path, receipt_data)?;
+    fn latest_receipt(&self) -> Result<Option<Receipt>, Box<dyn Error>> {
+        let url = format!("{}/receipts/latest", self.endpoint_url.trim_end_matches('/'));
+        let res = self.client.get(&url).send()?;
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !res.status().is_success() {
+            return Err(format!("Latest receipt fetch failed with HTTP {}", res.status()).into());
+        }
+        let mut resp: NotarizeResponse = res.json()?;
+        let request = resp.request.take().ok_or("witness did not echo the anchored request")?;
+        Ok(Some(json_receipt(self.name(), resp, request)?))
+    }
 
-        println!("✅ Anchored Ledger Index {} (Hash: {}) to Witness Authority.", current_index, trimmed_hash);
+    fn health_check(&self) -> Result<(), Box<dyn Error>> {
+        let url = format!("{}/health", self.endpoint_url.trim_end_matches('/'));
+        let res = self.client.get(&url).send()?;
+        if !res.status().is_success() {
+            return Err(format!("Health check failed with HTTP {}", res.status()).into());
+        }
         Ok(())
     }
 }
+
+// ---------------------------------------------------------------------------
+// RFC 3161 Time-Stamp Authority backend
+// ---------------------------------------------------------------------------
+
+const OID_SHA256: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 2, 1];
+const OID_SIGNED_DATA: &[u64] = &[1, 2, 840, 113549, 1, 7, 2];
+const OID_CT_TST_INFO: &[u64] = &[1, 2, 840, 113549, 1, 9, 16, 1, 4];
+const OID_ATTR_CONTENT_TYPE: &[u64] = &[1, 2, 840, 113549, 1, 9, 3];
+const OID_ATTR_MESSAGE_DIGEST: &[u64] = &[1, 2, 840, 113549, 1, 9, 4];
+const OID_ECDSA_WITH_SHA256: &[u64] = &[1, 2, 840, 10045, 4, 3, 2];
+const OID_RSA_ENCRYPTION: &[u64] = &[1, 2, 840, 113549, 1, 1, 1];
+const OID_SHA256_WITH_RSA: &[u64] = &[1, 2, 840, 113549, 1, 1, 11];
+
+/// Configuration for an RFC 3161 timestamping authority.
+#[derive(Debug, Clone)]
+pub struct TsaConfig {
+    pub url: String,
+    /// DER `SubjectPublicKeyInfo` of the TSA signing key (RSA or P-256) the tokens must verify against.
+    pub tsa_public_key_der: Vec<u8>,
+    /// Required TSA policy OID, if the deployment mandates one.
+    pub policy_oid: Option<Vec<u64>>,
+    pub http: HttpClientConfig,
+}
+
+/// A verified RFC 3161 timestamp token over a checkpoint root.
+#[derive(Debug, Clone)]
+pub struct TimestampReceipt {
+    pub serial_number: Vec<u8>,
+    pub gen_time: u64,
+    pub policy: Vec<u64>,
+    /// The DER `TimeStampToken` (CMS `ContentInfo`), stored verbatim as the receipt.
+    pub token_der: Vec<u8>,
+}
+
+/// Anchors checkpoints with an RFC 3161 TSA. Many compliance regimes only accept
+/// RFC 3161 tokens as proof of time. The receipt evidence is the DER `TimeStampToken`.
+pub struct TsaBackend {
+    config: TsaConfig,
+    client: Client,
+}
+
+impl TsaBackend {
+    pub fn new(config: TsaConfig) -> Result<Self, Box<dyn Error>> {
+        let client = config.http.build_blocking()?;
+        Ok(Self { config, client })
+    }
+
+    /// DER `TimeStampReq` over a SHA-256 message imprint, requesting the TSA certificate.
+    pub fn build_request(imprint: &[u8; 32], nonce: u64, policy: Option<&[u64]>) -> Vec<u8> {
+        let hash_alg = der::encode_constructed(der::TAG_SEQUENCE, &[&der::encode_oid(OID_SHA256), &der::encode_tlv(der::TAG_NULL, &[])]);
+        let message_imprint = der::encode_constructed(der::TAG_SEQUENCE, &[&hash_alg, &der::encode_tlv(der::TAG_OCTET_STRING, imprint)]);
+        let mut fields = vec![der::encode_uint(1), message_imprint];
+        if let Some(policy) = policy {
+            fields.push(der::encode_oid(policy));
+        }
+        fields.push(der::encode_uint(nonce));
+        fields.push(der::encode_tlv(der::TAG_BOOLEAN, &[0xff])); // certReq
+        let refs: Vec<&[u8]> = fields.iter().map(|f| f.as_slice()).collect();
+        der::encode_constructed(der::TAG_SEQUENCE, &refs)
+    }
+
+    /// SHA-256 of the decoded head root, so the token covers the root itself rather
+    /// than its hex spelling.
+    pub(crate) fn imprint(req: &AnchorRequest) -> Result<[u8; 32], NotaryError> {
+        Ok(Sha256::digest(req.head_root()?).into())
+    }
+
+    /// Deterministic nonce bound to what is being anchored.
+    pub(crate) fn nonce(req: &AnchorRequest) -> u64 {
+        let mut nonce_input = blake3::Hasher::new();
+        nonce_input.update(req.ledger_head_hash.as_bytes());
+        nonce_input.update(&req.index.to_le_bytes());
+        nonce_input.update(&req.timestamp_ticks.to_le_bytes());
+        u64::from_le_bytes(nonce_input.finalize().as_bytes()[..8].try_into().unwrap()) >> 1
+    }
+
+    pub(crate) fn timestamp_query(config: &TsaConfig, req: &AnchorRequest) -> Result<Vec<u8>, NotaryError> {
+        Ok(Self::build_request(&Self::imprint(req)?, Self::nonce(req), config.policy_oid.as_deref()))
+    }
+}
+
+pub(crate) fn check_tsa_token(config: &TsaConfig, req: &AnchorRequest, token_der: &[u8]) -> Result<TimestampReceipt, Box<dyn Error>> {
+    let token = verify_timestamp_token(token_der, &TsaBackend::imprint(req)?, Some(TsaBackend::nonce(req)), &config.tsa_public_key_der)?;
+    if let Some(policy) = &config.policy_oid {
+        if &token.policy != policy {
+            return Err("TSA answered under an unexpected policy".into());
+        }
+    }
+    Ok(token)
+}
+
+pub(crate) fn tsa_receipt(backend: &str, req: &AnchorRequest, token: TimestampReceipt) -> Receipt {
+    Receipt {
+        backend: backend.to_string(),
+        receipt_id: hex::encode(&token.serial_number),
+        request: req.clone(),
+        external_timestamp: token.gen_time,
+        evidence: token.token_der,
+        key_id: None,
+    }
+}
+
+pub(crate) fn verify_tsa_receipt(config: &TsaConfig, backend: &str, req: &AnchorRequest, receipt: &Receipt) -> Result<(), Box<dyn Error>> {
+    if receipt.backend != backend || &receipt.request != req {
+        return Err(Box::new(NotaryError::ReceiptMismatch("receipt does not cover this anchor request")));
+    }
+    let token = check_tsa_token(config, req, &receipt.evidence)?;
+    if hex::encode(&token.serial_number) != receipt.receipt_id || token.gen_time != receipt.external_timestamp {
+        return Err(Box::new(NotaryError::ReceiptMismatch("receipt fields disagree with the timestamp token")));
+    }
+    Ok(())
+}
+
+impl NotaryBackend for TsaBackend {
+    fn name(&self) -> &str {
+        "rfc3161"
+    }
+
+    fn submit(&self, req: &AnchorRequest) -> Result<Receipt, Box<dyn Error>> {
+        let tsq = Self::timestamp_query(&self.config, req)?;
+        let res = self.client.post(&self.config.url)
+            .header("Content-Type", "application/timestamp-query")
+            .body(tsq)
+            .send()?;
+        if !res.status().is_success() {
+            return Err(format!("TSA request failed with HTTP {}", res.status()).into());
+        }
+        let body = res.bytes()?;
+        let token = check_tsa_token(&self.config, req, parse_timestamp_response(&body)?)?;
+        Ok(tsa_receipt(self.name(), req, token))
+    }
+
+    fn fetch_receipt(&self, _receipt_id: &str) -> Result<Receipt, Box<dyn Error>> {
+        Err("RFC 3161 authorities do not serve previously issued tokens".into())
+    }
+
+    fn verify_receipt(&self, req: &AnchorRequest, receipt: &Receipt) -> Result<(), Box<dyn Error>> {
+        verify_tsa_receipt(&self.config, self.name(), req, receipt)
+    }
+}
+
+/// Extracts the `TimeStampToken` from a `TimeStampResp`, failing unless the status is granted.
+pub fn parse_timestamp_response(resp_der: &[u8]) -> Result<&[u8], Box<dyn Error>> {
+    let mut outer = der::DerReader::new(resp_der);
+    let mut resp = der::DerReader::new(outer.read(der::TAG_SEQUENCE)?);
+    let mut status_info = der::DerReader::new(resp.read(der::TAG_SEQUENCE)?);
+    let status = der::decode_uint(status_info.read(der::TAG_INTEGER)?)?;
+    // 0 = granted, 1 = grantedWithMods
+    if status > 1 {
+        return Err(format!("TSA rejected the request with PKIStatus {}", status).into());
+    }
+    let token = resp.read_any()?;
+    if token.tag != der::TAG_SEQUENCE {
+        return Err("TimeStampResp carries no token".into());
+    }
+    Ok(token.raw)
+}
+
+/// Verifies a `TimeStampToken`: CMS structure, signed attributes, signature under the
+/// pinned TSA key, and that the `TSTInfo` covers `expected_imprint` (and nonce, if given).
+pub fn verify_timestamp_token(
+    token_der: &[u8],
+    expected_imprint: &[u8; 32],
+    expected_nonce: Option<u64>,
+    tsa_public_key_der: &[u8],
+) -> Result<TimestampReceipt, Box<dyn Error>> {
+    // ContentInfo ::= SEQUENCE { contentType, [0] EXPLICIT SignedData }
+    let mut content_info = der::DerReader::new(der::DerReader::new(token_der).read(der::TAG_SEQUENCE)?);
+    if der::decode_oid(content_info.read(der::TAG_OID)?)? != OID_SIGNED_DATA {
+        return Err("timestamp token is not CMS SignedData".into());
+    }
+    let explicit = content_info.read(der::context(0, true))?;
+    let mut signed_data = der::DerReader::new(der::DerReader::new(explicit).read(der::TAG_SEQUENCE)?);
+    signed_data.read(der::TAG_INTEGER)?; // version
+    signed_data.read(der::TAG_SET)?; // digestAlgorithms
+
+    // EncapsulatedContentInfo ::= SEQUENCE { eContentType, [0] EXPLICIT OCTET STRING }
+    let mut encap = der::DerReader::new(signed_data.read(der::TAG_SEQUENCE)?);
+    if der::decode_oid(encap.read(der::TAG_OID)?)? != OID_CT_TST_INFO {
+        return Err("timestamp token does not encapsulate TSTInfo".into());
+    }
+    let tst_info_der = der::DerReader::new(encap.read(der::context(0, true))?).read(der::TAG_OCTET_STRING)?;
+
+    signed_data.read_optional(der::context(0, true))?; // certificates
+    signed_data.read_optional(der::context(1, true))?; // crls
+    let mut signer_infos = der::DerReader::new(signed_data.read(der::TAG_SET)?);
+    let signer_info = signer_infos.read(der::TAG_SEQUENCE)?;
+    if !signer_infos.is_empty() {
+        return Err("timestamp token must carry exactly one SignerInfo".into());
+    }
+
+    let mut si = der::DerReader::new(signer_info);
+    si.read(der::TAG_INTEGER)?; // version
+    si.read_any()?; // sid
+    let mut digest_alg = der::DerReader::new(si.read(der::TAG_SEQUENCE)?);
+    if der::decode_oid(digest_alg.read(der::TAG_OID)?)? != OID_SHA256 {
+        return Err("unsupported SignerInfo digest algorithm".into());
+    }
+    let signed_attrs = si.read_any()?;
+    if signed_attrs.tag != der::context(0, true) {
+        return Err("SignerInfo has no signed attributes".into());
+    }
+    let mut sig_alg = der::DerReader::new(si.read(der::TAG_SEQUENCE)?);
+    let sig_alg_oid = der::decode_oid(sig_alg.read(der::TAG_OID)?)?;
+    let signature = si.read(der::TAG_OCTET_STRING)?;
+
+    // The content-type and message-digest attributes must bind the signature to this TSTInfo.
+    let mut saw_content_type = false;
+    let mut saw_digest = false;
+    let mut attrs = der::DerReader::new(signed_attrs.value);
+    while !attrs.is_empty() {
+        let mut attr = der::DerReader::new(attrs.read(der::TAG_SEQUENCE)?);
+        let oid = der::decode_oid(attr.read(der::TAG_OID)?)?;
+        let mut values = der::DerReader::new(attr.read(der::TAG_SET)?);
+        if oid == OID_ATTR_CONTENT_TYPE {
+            saw_content_type = der::decode_oid(values.read(der::TAG_OID)?)? == OID_CT_TST_INFO;
+        } else if oid == OID_ATTR_MESSAGE_DIGEST {
+            saw_digest = values.read(der::TAG_OCTET_STRING)? == &Sha256::digest(tst_info_der)[..];
+        }
+    }
+    if !saw_content_type || !saw_digest {
+        return Err("signed attributes do not bind the TSTInfo".into());
+    }
+
+    // Signed attributes are signed as an explicit SET OF, not with their [0] IMPLICIT tag.
+    let mut signed_bytes = signed_attrs.raw.to_vec();
+    signed_bytes[0] = der::TAG_SET;
+    verify_tsa_signature(&sig_alg_oid, tsa_public_key_der, &signed_bytes, signature)?;
+
+    // TSTInfo ::= SEQUENCE { version, policy, messageImprint, serialNumber, genTime,
+    //                        accuracy OPTIONAL, ordering DEFAULT FALSE, nonce OPTIONAL, ... }
+    let mut tst = der::DerReader::new(der::DerReader::new(tst_info_der).read(der::TAG_SEQUENCE)?);
+    tst.read(der::TAG_INTEGER)?;
+    let policy = der::decode_oid(tst.read(der::TAG_OID)?)?;
+    let mut imprint = der::DerReader::new(tst.read(der::TAG_SEQUENCE)?);
+    let mut imprint_alg = der::DerReader::new(imprint.read(der::TAG_SEQUENCE)?);
+    if der::decode_oid(imprint_alg.read(der::TAG_OID)?)? != OID_SHA256
+        || imprint.read(der::TAG_OCTET_STRING)? != expected_imprint
+    {
+        return Err("TSTInfo message imprint does not match the checkpoint".into());
+    }
+    let serial_number = tst.read(der::TAG_INTEGER)?.to_vec();
+    let gen_time = der::decode_generalized_time(tst.read(der::TAG_GENERALIZED_TIME)?)?;
+    tst.read_optional(der::TAG_SEQUENCE)?; // accuracy
+    tst.read_optional(der::TAG_BOOLEAN)?; // ordering
+    let nonce = tst.read_optional(der::TAG_INTEGER)?.map(der::decode_uint).transpose()?;
+    if expected_nonce.is_some() && nonce != expected_nonce {
+        return Err("TSTInfo nonce does not match the request".into());
+    }
+
+    Ok(TimestampReceipt { serial_number, gen_time, policy, token_der: token_der.to_vec() })
+}
+
+fn verify_tsa_signature(sig_alg: &[u64], spki_der: &[u8], message: &[u8], signature: &[u8]) -> Result<(), NotaryError> {
+    let algorithm = if sig_alg == OID_ECDSA_WITH_SHA256 {
+        SignatureAlgorithm::EcdsaP256Sha256
+    } else if sig_alg == OID_SHA256_WITH_RSA || sig_alg == OID_RSA_ENCRYPTION {
+        SignatureAlgorithm::RsaPkcs1Sha256
+    } else {
+        return Err(NotaryError::UnsupportedAlgorithm(format!("{:?}", sig_alg)));
+    };
+    witness_keys::verify_signature(algorithm, spki_der, message, signature).map_err(|e| match e {
+        NotaryError::BadSignature => NotaryError::InvalidSignature { backend: "rfc3161".to_string(), key_id: None },
+        other => other,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seals a request by echoing its head hash as evidence.
+    struct Echo;
+
+    impl NotaryBackend for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn submit(&self, req: &AnchorRequest) -> Result<Receipt, Box<dyn Error>> {
+            Ok(Receipt {
+                backend: "echo".to_string(),
+                receipt_id: format!("echo-{}", req.index),
+                request: req.clone(),
+                external_timestamp: 1,
+                evidence: req.ledger_head_hash.as_bytes().to_vec(),
+                key_id: None,
+            })
+        }
+
+        fn fetch_receipt(&self, _: &str) -> Result<Receipt, Box<dyn Error>> {
+            Err("echo keeps no receipts".into())
+        }
+
+        fn verify_receipt(&self, req: &AnchorRequest, receipt: &Receipt) -> Result<(), Box<dyn Error>> {
+            if receipt.request != *req || receipt.evidence != req.ledger_head_hash.as_bytes() {
+                return Err("echo evidence does not match".into());
+            }
+            Ok(())
+        }
+    }
+
+    fn request(index: u64) -> AnchorRequest {
+        AnchorRequest { ledger_head_hash: "ab".repeat(32), index, timestamp_ticks: index * 10 }
+    }
+
+    #[test]
+    fn a_plugged_in_backend_anchors_and_its_status_is_persisted() {
+        let dir = std::env::temp_dir().join(format!("rfsn-notarize-backend-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let checkpoint = dir.join("merkle.chk");
+
+        let client = NotaryClient::with_backend(Box::new(Echo));
+        assert_eq!((client.threshold(), client.backends().map(|b| b.name()).collect::<Vec<_>>()), (1, vec!["echo"]));
+        let status = client.notarize_request(&checkpoint, &request(3)).unwrap();
+        assert!(status.anchored());
+        assert_eq!(status.receipts[0].receipt_id, "echo-3");
+
+        let persisted = NotaryClient::quorum_status(&checkpoint, 3).unwrap();
+        assert_eq!((persisted.request, persisted.receipts), (request(3), status.receipts.clone()));
+        assert!(receipt_path(&checkpoint, &status.receipts[0]).exists());
+
+        // The backend's own verification decides which receipts count.
+        let mut forged = status.receipts[0].clone();
+        forged.evidence = b"forged".to_vec();
+        assert!(!client.verify_receipts(&request(3), vec![forged]).anchored());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    struct Down;
+
+    impl NotaryBackend for Down {
+        fn name(&self) -> &str {
+            "down"
+        }
+
+        fn submit(&self, _: &AnchorRequest) -> Result<Receipt, Box<dyn Error>> {
+            Err("unreachable".into())
+        }
+
+        fn fetch_receipt(&self, _: &str) -> Result<Receipt, Box<dyn Error>> {
+            Err("unreachable".into())
+        }
+
+        fn verify_receipt(&self, _: &AnchorRequest, _: &Receipt) -> Result<(), Box<dyn Error>> {
+            Err("unreachable".into())
+        }
+    }
+
+    #[test]
+    fn a_quorum_needs_threshold_distinct_witnesses() {
+        assert!(NotaryClient::quorum_of(vec![Box::new(Echo)], 0).is_err());
+        assert!(NotaryClient::quorum_of(vec![Box::new(Echo)], 2).is_err());
+
+        let w1 = LocalWitness::from_seed("w1", [1; 32]);
+        let w2 = LocalWitness::from_seed("w2", [2; 32]);
+        let two_of_three = NotaryClient::quorum_of(vec![Box::new(w1.clone()), Box::new(Down), Box::new(w2.clone())], 2).unwrap();
+        let status = two_of_three.collect_receipts(&request(5));
+        assert!(status.anchored());
+        assert_eq!((status.receipts.len(), status.witnesses), (2, 3));
+        assert_eq!(status.failures, vec![("down".to_string(), "unreachable".to_string())]);
+
+        let three_of_three = NotaryClient::quorum_of(vec![Box::new(w1.clone()), Box::new(Down), Box::new(w2)], 3).unwrap();
+        assert!(!three_of_three.collect_receipts(&request(5)).anchored());
+
+        // One witness's receipt supplied twice still only counts once.
+        let receipt = w1.notarize(&request(5));
+        assert!(!two_of_three.verify_receipts(&request(5), vec![receipt.clone(), receipt]).anchored());
+    }
+
+    #[test]
+    fn the_timestamp_imprint_covers_the_decoded_root() {
+        assert_eq!(TsaBackend::imprint(&request(1)).unwrap(), <[u8; 32]>::from(Sha256::digest([0xab; 32])));
+        let batch = AnchorRequest { ledger_head_hash: format!("{}{}", BATCH_HEAD_PREFIX, "ab".repeat(32)), ..request(1) };
+        assert_eq!(TsaBackend::imprint(&batch).unwrap(), TsaBackend::imprint(&request(1)).unwrap());
+
+        let config = TsaConfig { url: String::new(), tsa_public_key_der: Vec::new(), policy_oid: None, http: HttpClientConfig::default() };
+        let query = TsaBackend::timestamp_query(&config, &request(1)).unwrap();
+        assert!(query.windows(32).any(|w| w == TsaBackend::imprint(&request(1)).unwrap()));
+
+        let malformed = AnchorRequest { ledger_head_hash: "ab".repeat(31), ..request(1) };
+        assert!(matches!(TsaBackend::imprint(&malformed), Err(NotaryError::Malformed(_))));
+        assert!(check_tsa_token(&config, &malformed, &[]).is_err());
+    }
+}
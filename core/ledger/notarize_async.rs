@@ -0,0 +1,388 @@
+//! Non-blocking notarization.
+//!
+//! `NotaryClient` uses `reqwest::blocking`, which stalls whichever thread calls it.
+//! Everything here runs on tokio: witnesses are contacted concurrently, every
+//! request is bounded by a timeout, and a round can be cancelled, so notarization
+//! never sits on the append or ordering paths.
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use super::clock_skew::SkewPolicy;
+use super::ethereum::EthereumBackend;
+use super::notarize::{
+    anchor_request_from_checkpoint, check_tsa_token, json_receipt, parse_timestamp_response, quorum_path,
+    receipt_path, tsa_receipt, verify_json_receipt, verify_tsa_receipt, AnchorRequest, NotarizeResponse,
+    NotaryBackend, NotaryConfig, QuorumStatus, Receipt, TsaBackend, TsaConfig,
+};
+use super::notary_http::HttpClientConfig;
+use super::notary_metrics::NotaryMetrics;
+use super::outbox::unix_now;
+use super::receipt_cose::encode_receipt;
+use super::witness_keys::WitnessKeyring;
+
+pub type AsyncNotaryError = Box<dyn Error + Send + Sync>;
+
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Async counterpart of `NotaryBackend`.
+#[async_trait]
+pub trait AsyncNotaryBackend: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn submit(&self, req: &AnchorRequest) -> Result<Receipt, AsyncNotaryError>;
+
+    async fn fetch_receipt(&self, receipt_id: &str) -> Result<Receipt, AsyncNotaryError>;
+
+    /// Receipt verification is local computation and stays synchronous.
+    fn verify_receipt(&self, req: &AnchorRequest, receipt: &Receipt) -> Result<(), AsyncNotaryError>;
+}
+
+/// Async JSON-over-HTTP witness (same wire protocol as `HttpJsonBackend`).
+pub struct AsyncHttpJsonBackend {
+    endpoint_url: String,
+    keys: WitnessKeyring,
+    client: reqwest::Client,
+}
+
+impl AsyncHttpJsonBackend {
+    pub fn new(url: &str, keys: WitnessKeyring, http: &HttpClientConfig) -> Result<Self, AsyncNotaryError> {
+        let client = http.build_async().map_err(|e| e.to_string())?;
+        Ok(Self { endpoint_url: url.to_string(), keys, client })
+    }
+}
+
+#[async_trait]
+impl AsyncNotaryBackend for AsyncHttpJsonBackend {
+    fn name(&self) -> &str {
+        "http-json"
+    }
+
+    async fn submit(&self, req: &AnchorRequest) -> Result<Receipt, AsyncNotaryError> {
+        let res = self.client.post(&self.endpoint_url).json(req).send().await?;
+        if !res.status().is_success() {
+            return Err(format!("Notarization failed with HTTP {}", res.status()).into());
+        }
+        let resp: NotarizeResponse = res.json().await?;
+        json_receipt(self.name(), resp, req.clone()).map_err(|e| e.to_string().into())
+    }
+
+    async fn fetch_receipt(&self, receipt_id: &str) -> Result<Receipt, AsyncNotaryError> {
+        let url = format!("{}/receipts/{}", self.endpoint_url.trim_end_matches('/'), receipt_id);
+        let res = self.client.get(&url).send().await?;
+        if !res.status().is_success() {
+            return Err(format!("Receipt fetch failed with HTTP {}", res.status()).into());
+        }
+        let mut resp: NotarizeResponse = res.json().await?;
+        let request = resp.request.take().ok_or("witness did not echo the anchored request")?;
+        json_receipt(self.name(), resp, request).map_err(|e| e.to_string().into())
+    }
+
+    fn verify_receipt(&self, req: &AnchorRequest, receipt: &Receipt) -> Result<(), AsyncNotaryError> {
+        Ok(verify_json_receipt(self.name(), &self.keys, req, receipt)?)
+    }
+}
+
+/// Async RFC 3161 timestamping authority (same semantics as `TsaBackend`).
+pub struct AsyncTsaBackend {
+    config: TsaConfig,
+    client: reqwest::Client,
+}
+
+impl AsyncTsaBackend {
+    pub fn new(config: TsaConfig) -> Result<Self, AsyncNotaryError> {
+        let client = config.http.build_async().map_err(|e| e.to_string())?;
+        Ok(Self { config, client })
+    }
+}
+
+#[async_trait]
+impl AsyncNotaryBackend for AsyncTsaBackend {
+    fn name(&self) -> &str {
+        "rfc3161"
+    }
+
+    async fn submit(&self, req: &AnchorRequest) -> Result<Receipt, AsyncNotaryError> {
+        let tsq = TsaBackend::timestamp_query(&self.config, req)?;
+        let res = self.client.post(&self.config.url)
+            .header("Content-Type", "application/timestamp-query")
+            .body(tsq)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            return Err(format!("TSA request failed with HTTP {}", res.status()).into());
+        }
+        let body = res.bytes().await?;
+        let token = parse_timestamp_response(&body)
+            .and_then(|token| check_tsa_token(&self.config, req, token))
+            .map_err(|e| -> AsyncNotaryError { e.to_string().into() })?;
+        Ok(tsa_receipt(self.name(), req, token))
+    }
+
+    async fn fetch_receipt(&self, _receipt_id: &str) -> Result<Receipt, AsyncNotaryError> {
+        Err("RFC 3161 authorities do not serve previously issued tokens".into())
+    }
+
+    fn verify_receipt(&self, req: &AnchorRequest, receipt: &Receipt) -> Result<(), AsyncNotaryError> {
+        verify_tsa_receipt(&self.config, self.name(), req, receipt).map_err(|e| e.to_string().into())
+    }
+}
+
+/// Runs a blocking `NotaryBackend` on tokio's blocking pool, for backends that have no
+/// native async implementation.
+pub struct BlockingAdapter {
+    inner: Arc<dyn NotaryBackend>,
+    name: String,
+}
+
+impl BlockingAdapter {
+    pub fn new(inner: Box<dyn NotaryBackend>) -> Self {
+        let name = inner.name().to_string();
+        Self { inner: Arc::from(inner), name }
+    }
+}
+
+#[async_trait]
+impl AsyncNotaryBackend for BlockingAdapter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn submit(&self, req: &AnchorRequest) -> Result<Receipt, AsyncNotaryError> {
+        let inner = Arc::clone(&self.inner);
+        let req = req.clone();
+        tokio::task::spawn_blocking(move || inner.submit(&req).map_err(|e| e.to_string()))
+            .await?
+            .map_err(Into::into)
+    }
+
+    async fn fetch_receipt(&self, receipt_id: &str) -> Result<Receipt, AsyncNotaryError> {
+        let inner = Arc::clone(&self.inner);
+        let receipt_id = receipt_id.to_string();
+        tokio::task::spawn_blocking(move || inner.fetch_receipt(&receipt_id).map_err(|e| e.to_string()))
+            .await?
+            .map_err(Into::into)
+    }
+
+    fn verify_receipt(&self, req: &AnchorRequest, receipt: &Receipt) -> Result<(), AsyncNotaryError> {
+        self.inner.verify_receipt(req, receipt).map_err(|e| e.to_string().into())
+    }
+}
+
+impl NotaryConfig {
+    pub fn into_async_backend(self) -> Result<Arc<dyn AsyncNotaryBackend>, AsyncNotaryError> {
+        Ok(match self {
+            NotaryConfig::HttpJson { url, keys, http } => Arc::new(AsyncHttpJsonBackend::new(&url, keys, &http)?),
+            NotaryConfig::Tsa(config) => Arc::new(AsyncTsaBackend::new(config)?),
+            // Confirmation polling is long and blocking; it runs on the blocking pool.
+            NotaryConfig::Ethereum(config) => {
+                Arc::new(BlockingAdapter::new(Box::new(EthereumBackend::new(config).map_err(|e| e.to_string())?)))
+            }
+            NotaryConfig::Local(witness) => Arc::new(witness),
+        })
+    }
+}
+
+/// Async, cancellable equivalent of `NotaryClient`, with the same m-of-n quorum and
+/// the same on-disk receipt / quorum-status files.
+pub struct AsyncNotaryClient {
+    backends: Vec<Arc<dyn AsyncNotaryBackend>>,
+    threshold: usize,
+    request_timeout: Duration,
+    skew: Option<SkewPolicy>,
+    metrics: Option<Arc<NotaryMetrics>>,
+}
+
+impl AsyncNotaryClient {
+    pub fn new(url: &str, keys: WitnessKeyring, http: &HttpClientConfig) -> Result<Self, AsyncNotaryError> {
+        let mut client = Self::with_backend(Arc::new(AsyncHttpJsonBackend::new(url, keys, http)?));
+        client.request_timeout = http.request_timeout;
+        Ok(client)
+    }
+
+    pub fn with_backend(backend: Arc<dyn AsyncNotaryBackend>) -> Self {
+        Self { backends: vec![backend], threshold: 1, request_timeout: DEFAULT_REQUEST_TIMEOUT, skew: None, metrics: None }
+    }
+
+    pub fn quorum(configs: Vec<NotaryConfig>, threshold: usize) -> Result<Self, AsyncNotaryError> {
+        let backends = configs.into_iter().map(NotaryConfig::into_async_backend).collect::<Result<_, _>>()?;
+        Self::quorum_of(backends, threshold)
+    }
+
+    pub fn quorum_of(backends: Vec<Arc<dyn AsyncNotaryBackend>>, threshold: usize) -> Result<Self, AsyncNotaryError> {
+        if threshold == 0 || threshold > backends.len() {
+            return Err(format!("invalid quorum {}-of-{}", threshold, backends.len()).into());
+        }
+        Ok(Self { backends, threshold, request_timeout: DEFAULT_REQUEST_TIMEOUT, skew: None, metrics: None })
+    }
+
+    /// Upper bound on each individual witness request.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Checks every receipt's `external_timestamp` against `policy`.
+    pub fn clock_skew(mut self, policy: SkewPolicy) -> Self {
+        self.skew = Some(policy);
+        self
+    }
+
+    /// Records every notarization round in `metrics`.
+    pub fn observe(mut self, metrics: Arc<NotaryMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    async fn submit_one(&self, backend: &Arc<dyn AsyncNotaryBackend>, req: &AnchorRequest) -> Result<Receipt, AsyncNotaryError> {
+        let receipt = tokio::time::timeout(self.request_timeout, backend.submit(req))
+            .await
+            .map_err(|_| format!("witness {} timed out after {:?}", backend.name(), self.request_timeout))??;
+        backend.verify_receipt(req, &receipt)?;
+        Ok(receipt)
+    }
+
+    /// Notarizes the checkpoint with every witness concurrently. Returns an error if the
+    /// round is cancelled or fewer than `threshold` witnesses return a valid receipt.
+    pub async fn notarize_checkpoint(
+        &self,
+        checkpoint_path: &Path,
+        current_index: u64,
+        cancel: &CancellationToken,
+    ) -> Result<QuorumStatus, AsyncNotaryError> {
+        let contents = tokio::fs::read(checkpoint_path).await?;
+        let req = anchor_request_from_checkpoint(&contents, current_index).map_err(|e| e.to_string())?;
+
+        let started = Instant::now();
+        if let Some(metrics) = &self.metrics {
+            metrics.record_attempt();
+        }
+        let round = join_all(self.backends.iter().map(|b| self.submit_one(b, &req)));
+        let outcomes = tokio::select! {
+            outcomes = round => outcomes,
+            _ = cancel.cancelled() => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_failure("notarization cancelled");
+                }
+                return Err("notarization cancelled".into());
+            }
+        };
+
+        let mut status = QuorumStatus::new(req.clone(), self.threshold, self.backends.len());
+        let received_at = unix_now();
+        for (backend, outcome) in self.backends.iter().zip(outcomes) {
+            let outcome = outcome.and_then(|receipt| match &self.skew {
+                Some(skew) => skew.apply(&req, &receipt, Some(received_at), &mut status).map(|()| receipt).map_err(Into::into),
+                None => Ok(receipt),
+            });
+            match outcome {
+                Ok(receipt) => {
+                    tokio::fs::write(receipt_path(checkpoint_path, &receipt), encode_receipt(&receipt, None)).await?;
+                    status.receipts.push(receipt);
+                }
+                Err(e) => status.failures.push((backend.name().to_string(), e.to_string())),
+            }
+        }
+        tokio::fs::write(quorum_path(checkpoint_path, current_index), serde_json::to_string_pretty(&status)?).await?;
+
+        if !status.anchored() {
+            let error = format!(
+                "notarization quorum not reached: {}/{} receipts (need {})",
+                status.receipts.len(), status.witnesses, status.threshold
+            );
+            if let Some(metrics) = &self.metrics {
+                metrics.record_failure(&error);
+            }
+            return Err(error.into());
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.record_success(started.elapsed());
+        }
+        println!(
+            "✅ Anchored Ledger Index {} (Hash: {}) with {}/{} witnesses.",
+            current_index, req.ledger_head_hash, status.receipts.len(), status.witnesses
+        );
+        Ok(status)
+    }
+
+    /// Fire-and-forget notarization for callers on the append path: returns immediately,
+    /// the round runs on the runtime and can be stopped through `cancel`.
+    pub fn spawn_notarization(
+        self: &Arc<Self>,
+        checkpoint_path: PathBuf,
+        current_index: u64,
+        cancel: CancellationToken,
+    ) -> JoinHandle<Result<QuorumStatus, AsyncNotaryError>> {
+        let client = Arc::clone(self);
+        tokio::spawn(async move { client.notarize_checkpoint(&checkpoint_path, current_index, &cancel).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::local_witness::LocalWitness;
+    use crate::ledger::merkle::{leaf_hash, Checkpoint, MerkleFrontier};
+
+    /// Never answers within any reasonable timeout.
+    struct Stalled;
+
+    #[async_trait]
+    impl AsyncNotaryBackend for Stalled {
+        fn name(&self) -> &str {
+            "stalled"
+        }
+
+        async fn submit(&self, _: &AnchorRequest) -> Result<Receipt, AsyncNotaryError> {
+            tokio::time::sleep(Duration::from_secs(3600)).await;
+            Err("woke up".into())
+        }
+
+        async fn fetch_receipt(&self, _: &str) -> Result<Receipt, AsyncNotaryError> {
+            Err("stalled".into())
+        }
+
+        fn verify_receipt(&self, _: &AnchorRequest, _: &Receipt) -> Result<(), AsyncNotaryError> {
+            Err("stalled".into())
+        }
+    }
+
+    fn write_checkpoint(path: &Path, entries: u64) {
+        let mut tree = MerkleFrontier::new();
+        for i in 0..entries {
+            tree.push(leaf_hash(&i.to_le_bytes()));
+        }
+        std::fs::write(path, Checkpoint::of(&tree, 42).encode()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_stalled_witness_times_out_and_a_cancelled_round_returns() {
+        let dir = std::env::temp_dir().join(format!("rfsn-notarize-async-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let checkpoint = dir.join("merkle.chk");
+        write_checkpoint(&checkpoint, 3);
+
+        let witness = LocalWitness::from_seed("w1", [3; 32]);
+        let backends: Vec<Arc<dyn AsyncNotaryBackend>> = vec![Arc::new(witness.clone()), Arc::new(Stalled)];
+        let client = Arc::new(AsyncNotaryClient::quorum_of(backends, 1).unwrap().request_timeout(Duration::from_millis(50)));
+        let status = client.notarize_checkpoint(&checkpoint, 3, &CancellationToken::new()).await.unwrap();
+        assert_eq!((status.receipts.len(), status.request.timestamp_ticks), (1, 42));
+        assert_eq!(status.failures[0].0, "stalled");
+        assert!(status.failures[0].1.contains("timed out"));
+
+        let stalled = Arc::new(AsyncNotaryClient::with_backend(Arc::new(Stalled)));
+        let cancel = CancellationToken::new();
+        let round = stalled.spawn_notarization(checkpoint.clone(), 3, cancel.clone());
+        cancel.cancel();
+        let error = round.await.unwrap().unwrap_err();
+        assert_eq!(error.to_string(), "notarization cancelled");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
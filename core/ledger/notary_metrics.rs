@@ -0,0 +1,208 @@
+//! Notarization metrics and alert hooks.
+//!
+//! Counters and gauges are plain atomics so any exporter (Prometheus, statsd, logs)
+//! can read a `MetricsSnapshot`. Alert hooks fire once per outage when anchoring has
+//! been failing for longer than the allowed unanchored window, so operators can page.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::outbox::unix_now;
+
+/// Raised when no notarization has succeeded within `AlertPolicy::max_unanchored`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AnchoringAlert {
+    pub consecutive_failures: u64,
+    pub unanchored_for_secs: u64,
+    /// Unix seconds of the last successful anchor; 0 if there has never been one.
+    pub last_success_unix: u64,
+    pub last_error: Option<String>,
+}
+
+pub type AlertHook = Box<dyn Fn(&AnchoringAlert) + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub struct AlertPolicy {
+    /// Longest tolerated time without a successful anchor.
+    pub max_unanchored: Duration,
+}
+
+impl Default for AlertPolicy {
+    fn default() -> Self {
+        Self { max_unanchored: Duration::from_secs(60 * 60) }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MetricsSnapshot {
+    pub anchors_attempted: u64,
+    pub anchors_succeeded: u64,
+    pub anchors_failed: u64,
+    pub consecutive_failures: u64,
+    pub last_success_unix: u64,
+    pub last_receipt_latency_ms: u64,
+    pub max_receipt_latency_ms: u64,
+    /// Sum over all successful rounds; divide by `anchors_succeeded` for the mean.
+    pub total_receipt_latency_ms: u64,
+    pub outbox_depth: u64,
+}
+
+/// Shared between the notary client, the outbox and the scheduler (wrap in an `Arc`).
+pub struct NotaryMetrics {
+    policy: AlertPolicy,
+    started_unix: u64,
+    anchors_attempted: AtomicU64,
+    anchors_succeeded: AtomicU64,
+    anchors_failed: AtomicU64,
+    consecutive_failures: AtomicU64,
+    last_success_unix: AtomicU64,
+    last_receipt_latency_ms: AtomicU64,
+    max_receipt_latency_ms: AtomicU64,
+    total_receipt_latency_ms: AtomicU64,
+    outbox_depth: AtomicU64,
+    // Set once an alert fired for the current outage; cleared by the next success.
+    alerted: AtomicBool,
+    last_error: Mutex<Option<String>>,
+    hooks: Mutex<Vec<AlertHook>>,
+}
+
+impl NotaryMetrics {
+    pub fn new(policy: AlertPolicy) -> Self {
+        Self {
+            policy,
+            started_unix: unix_now(),
+            anchors_attempted: AtomicU64::new(0),
+            anchors_succeeded: AtomicU64::new(0),
+            anchors_failed: AtomicU64::new(0),
+            consecutive_failures: AtomicU64::new(0),
+            last_success_unix: AtomicU64::new(0),
+            last_receipt_latency_ms: AtomicU64::new(0),
+            max_receipt_latency_ms: AtomicU64::new(0),
+            total_receipt_latency_ms: AtomicU64::new(0),
+            outbox_depth: AtomicU64::new(0),
+            alerted: AtomicBool::new(false),
+            last_error: Mutex::new(None),
+            hooks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Registers a hook called when the unanchored window is exceeded. Hooks run on the
+    /// notarizing thread and should hand off anything slow.
+    pub fn on_alert(&self, hook: impl Fn(&AnchoringAlert) + Send + Sync + 'static) {
+        self.hooks.lock().unwrap().push(Box::new(hook));
+    }
+
+    pub fn record_attempt(&self) {
+        self.anchors_attempted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_success(&self, latency: Duration) {
+        let ms = latency.as_millis() as u64;
+        self.anchors_succeeded.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        self.last_success_unix.store(unix_now(), Ordering::Relaxed);
+        self.last_receipt_latency_ms.store(ms, Ordering::Relaxed);
+        self.max_receipt_latency_ms.fetch_max(ms, Ordering::Relaxed);
+        self.total_receipt_latency_ms.fetch_add(ms, Ordering::Relaxed);
+        self.alerted.store(false, Ordering::Relaxed);
+        *self.last_error.lock().unwrap() = None;
+    }
+
+    pub fn record_failure(&self, error: &str) {
+        self.anchors_failed.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.lock().unwrap() = Some(error.to_string());
+        self.check_overdue(unix_now());
+    }
+
+    pub fn set_outbox_depth(&self, depth: usize) {
+        self.outbox_depth.store(depth as u64, Ordering::Relaxed);
+    }
+
+    /// Fires the alert hooks if the unanchored window has been exceeded and they have
+    /// not fired yet for this outage. Call periodically: an outage where nothing is even
+    /// attempted must still page. Returns the alert if one was raised.
+    pub fn check_overdue(&self, now_unix: u64) -> Option<AnchoringAlert> {
+        let last_success = self.last_success_unix.load(Ordering::Relaxed);
+        let since = if last_success == 0 { self.started_unix } else { last_success };
+        let unanchored_for_secs = now_unix.saturating_sub(since);
+        if unanchored_for_secs < self.policy.max_unanchored.as_secs() || self.alerted.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        let alert = AnchoringAlert {
+            consecutive_failures: self.consecutive_failures.load(Ordering::Relaxed),
+            unanchored_for_secs,
+            last_success_unix: last_success,
+            last_error: self.last_error.lock().unwrap().clone(),
+        };
+        eprintln!("❌ No successful notarization for {}s ({} consecutive failures).", unanchored_for_secs, alert.consecutive_failures);
+        for hook in self.hooks.lock().unwrap().iter() {
+            hook(&alert);
+        }
+        Some(alert)
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            anchors_attempted: self.anchors_attempted.load(Ordering::Relaxed),
+            anchors_succeeded: self.anchors_succeeded.load(Ordering::Relaxed),
+            anchors_failed: self.anchors_failed.load(Ordering::Relaxed),
+            consecutive_failures: self.consecutive_failures.load(Ordering::Relaxed),
+            last_success_unix: self.last_success_unix.load(Ordering::Relaxed),
+            last_receipt_latency_ms: self.last_receipt_latency_ms.load(Ordering::Relaxed),
+            max_receipt_latency_ms: self.max_receipt_latency_ms.load(Ordering::Relaxed),
+            total_receipt_latency_ms: self.total_receipt_latency_ms.load(Ordering::Relaxed),
+            outbox_depth: self.outbox_depth.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for NotaryMetrics {
+    fn default() -> Self {
+        Self::new(AlertPolicy::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn counters_track_rounds_and_alerts_fire_once_per_outage() {
+        let metrics = NotaryMetrics::new(AlertPolicy { max_unanchored: Duration::from_secs(60) });
+        let fired = Arc::new(AtomicU64::new(0));
+        let hook_fired = Arc::clone(&fired);
+        metrics.on_alert(move |_| {
+            hook_fired.fetch_add(1, Ordering::Relaxed);
+        });
+
+        metrics.record_attempt();
+        metrics.record_success(Duration::from_millis(40));
+        metrics.record_attempt();
+        metrics.record_success(Duration::from_millis(10));
+        metrics.record_attempt();
+        metrics.record_failure("witness unreachable");
+        metrics.set_outbox_depth(1);
+        let snapshot = metrics.snapshot();
+        assert_eq!((snapshot.anchors_attempted, snapshot.anchors_succeeded, snapshot.anchors_failed), (3, 2, 1));
+        assert_eq!((snapshot.last_receipt_latency_ms, snapshot.max_receipt_latency_ms, snapshot.total_receipt_latency_ms), (10, 40, 50));
+        assert_eq!((snapshot.consecutive_failures, snapshot.outbox_depth), (1, 1));
+
+        let last_success = snapshot.last_success_unix;
+        assert_eq!(metrics.check_overdue(last_success + 59), None);
+        let alert = metrics.check_overdue(last_success + 60).unwrap();
+        assert_eq!((alert.consecutive_failures, alert.last_error.as_deref()), (1, Some("witness unreachable")));
+        assert_eq!(metrics.check_overdue(last_success + 120), None);
+        assert_eq!(fired.load(Ordering::Relaxed), 1);
+
+        // The next success ends the outage; a later one alerts again.
+        metrics.record_success(Duration::from_millis(5));
+        assert_eq!(metrics.snapshot().consecutive_failures, 0);
+        assert!(metrics.check_overdue(unix_now() + 60).is_some());
+        assert_eq!(fired.load(Ordering::Relaxed), 2);
+    }
+}
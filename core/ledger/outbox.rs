@@ -0,0 +1,310 @@
+//! Persistent notarization outbox.
+//!
+//! When the witness is unreachable the (head, index, ticks) tuple is written to
+//! `outbox/` and retried with exponential backoff, including after a restart.
+//! Without this a transient 502 silently loses the anchoring opportunity for
+//! that checkpoint, because `merkle.chk` is overwritten by the next one.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::batch::BatchedAnchor;
+use super::notarize::{AnchorRequest, NotaryClient, QuorumStatus};
+use super::notary_metrics::NotaryMetrics;
+
+/// Exponential backoff: `initial * 2^attempts`, capped at `max`.
+#[derive(Debug, Clone)]
+pub struct BackoffPolicy {
+    pub initial: Duration,
+    pub max: Duration,
+    /// Give up (and keep the entry under `outbox/dead/`) after this many attempts.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self { initial: Duration::from_secs(5), max: Duration::from_secs(15 * 60), max_attempts: None }
+    }
+}
+
+impl BackoffPolicy {
+    pub fn delay_after(&self, attempts: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempts.min(31)).unwrap_or(u32::MAX);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+/// One queued anchoring opportunity.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OutboxEntry {
+    pub request: AnchorRequest,
+    /// Checkpoint the receipts are stored next to.
+    pub checkpoint_path: PathBuf,
+    pub attempts: u32,
+    /// Unix seconds before which the entry is not retried.
+    pub next_attempt_unix: u64,
+    pub last_error: Option<String>,
+}
+
+/// What a `drain` pass did.
+#[derive(Debug, Default)]
+pub struct DrainReport {
+    pub anchored: Vec<QuorumStatus>,
+    /// Checkpoints anchored as part of a batch (`drain_batched`).
+    pub batched: Vec<BatchedAnchor>,
+    pub rescheduled: usize,
+    pub abandoned: usize,
+    pub not_due: usize,
+}
+
+pub struct NotaryOutbox {
+    dir: PathBuf,
+    policy: BackoffPolicy,
+    metrics: Option<Arc<NotaryMetrics>>,
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl NotaryOutbox {
+    /// Opens (or creates) the outbox under `base_dir/outbox`.
+    pub fn open(base_dir: &Path, policy: BackoffPolicy) -> Result<Self, Box<dyn Error>> {
+        let dir = base_dir.join("outbox");
+        fs::create_dir_all(dir.join("dead"))?;
+        Ok(Self { dir, policy, metrics: None })
+    }
+
+    /// Keeps the `outbox_depth` gauge of `metrics` up to date.
+    pub fn observe(mut self, metrics: Arc<NotaryMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    fn update_depth(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(metrics) = &self.metrics {
+            metrics.set_outbox_depth(self.depth()?);
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, index: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.pending", index))
+    }
+
+    /// Atomically writes (or rewrites) the entry for its index.
+    fn store(&self, entry: &OutboxEntry) -> Result<(), Box<dyn Error>> {
+        let path = self.entry_path(entry.request.index);
+        let tmp = path.with_extension("pending.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(entry)?)?;
+        fs::File::open(&tmp)?.sync_all()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Queues a request for a later attempt. Re-enqueueing the same index keeps its attempt count.
+    pub fn enqueue(&self, checkpoint_path: &Path, request: AnchorRequest, error: &str, now_unix: u64) -> Result<(), Box<dyn Error>> {
+        let attempts = self.pending()?
+            .into_iter()
+            .find(|e| e.request.index == request.index)
+            .map(|e| e.attempts)
+            .unwrap_or(0)
+            + 1;
+        self.store(&OutboxEntry {
+            request,
+            checkpoint_path: checkpoint_path.to_path_buf(),
+            attempts,
+            next_attempt_unix: now_unix + self.policy.delay_after(attempts - 1).as_secs(),
+            last_error: Some(error.to_string()),
+        })?;
+        self.update_depth()
+    }
+
+    /// All pending entries, oldest ledger index first.
+    pub fn pending(&self) -> Result<Vec<OutboxEntry>, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for dirent in fs::read_dir(&self.dir)? {
+            let path = dirent?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("pending") {
+                entries.push(serde_json::from_slice::<OutboxEntry>(&fs::read(&path)?)?);
+            }
+        }
+        entries.sort_by_key(|e| e.request.index);
+        Ok(entries)
+    }
+
+    pub fn depth(&self) -> Result<usize, Box<dyn Error>> {
+        Ok(self.pending()?.len())
+    }
+
+    /// Notarizes now; on failure the request lands in the outbox instead of being lost.
+    pub fn notarize_or_enqueue(
+        &self,
+        client: &NotaryClient,
+        checkpoint_path: &Path,
+        request: AnchorRequest,
+        now_unix: u64,
+    ) -> Result<Option<QuorumStatus>, Box<dyn Error>> {
+        match client.notarize_request(checkpoint_path, &request) {
+            Ok(status) => Ok(Some(status)),
+            Err(e) => {
+                eprintln!("⚠️  Notarization of index {} failed ({}); queued for retry.", request.index, e);
+                self.enqueue(checkpoint_path, request, &e.to_string(), now_unix)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Retries every entry that is due. Call on startup and periodically thereafter.
+    pub fn drain(&self, client: &NotaryClient, now_unix: u64) -> Result<DrainReport, Box<dyn Error>> {
+        let mut report = DrainReport::default();
+        for mut entry in self.pending()? {
+            if entry.next_attempt_unix > now_unix {
+                report.not_due += 1;
+                continue;
+            }
+            match client.notarize_request(&entry.checkpoint_path, &entry.request) {
+                Ok(status) => {
+                    fs::remove_file(self.entry_path(entry.request.index))?;
+                    report.anchored.push(status);
+                }
+                Err(e) => self.record_failure(&mut entry, &e.to_string(), now_unix, &mut report)?,
+            }
+        }
+        self.update_depth()?;
+        Ok(report)
+    }
+
+    /// Like `drain`, but due entries that share a checkpoint path are notarized together,
+    /// up to `max_batch` per witness round. Use this when catching up after downtime.
+    pub fn drain_batched(&self, client: &NotaryClient, now_unix: u64, max_batch: usize) -> Result<DrainReport, Box<dyn Error>> {
+        let mut report = DrainReport::default();
+        let mut due: Vec<OutboxEntry> = Vec::new();
+        for entry in self.pending()? {
+            if entry.next_attempt_unix > now_unix {
+                report.not_due += 1;
+            } else {
+                due.push(entry);
+            }
+        }
+        due.sort_by(|a, b| a.checkpoint_path.cmp(&b.checkpoint_path).then(a.request.index.cmp(&b.request.index)));
+
+        for group in due.chunk_by(|a, b| a.checkpoint_path == b.checkpoint_path) {
+            for chunk in group.chunks(max_batch.max(1)) {
+                let requests = chunk.iter().map(|e| e.request.clone()).collect();
+                match client.notarize_batch(&chunk[0].checkpoint_path, requests) {
+                    Ok(anchors) => {
+                        for entry in chunk {
+                            fs::remove_file(self.entry_path(entry.request.index))?;
+                        }
+                        report.batched.extend(anchors);
+                    }
+                    Err(e) => {
+                        for entry in chunk {
+                            self.record_failure(&mut entry.clone(), &e.to_string(), now_unix, &mut report)?;
+                        }
+                    }
+                }
+            }
+        }
+        self.update_depth()?;
+        Ok(report)
+    }
+
+    /// Reschedules a failed entry, or moves it to `dead/` once it is out of attempts.
+    fn record_failure(&self, entry: &mut OutboxEntry, error: &str, now_unix: u64, report: &mut DrainReport) -> Result<(), Box<dyn Error>> {
+        entry.attempts += 1;
+        entry.last_error = Some(error.to_string());
+        if self.policy.max_attempts.is_some_and(|max| entry.attempts >= max) {
+            let dead = self.dir.join("dead").join(format!("{:016x}.pending", entry.request.index));
+            fs::write(&dead, serde_json::to_vec_pretty(&entry)?)?;
+            fs::remove_file(self.entry_path(entry.request.index))?;
+            eprintln!("❌ Giving up on notarizing index {} after {} attempts.", entry.request.index, entry.attempts);
+            report.abandoned += 1;
+        } else {
+            entry.next_attempt_unix = now_unix + self.policy.delay_after(entry.attempts - 1).as_secs();
+            self.store(entry)?;
+            report.rescheduled += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::local_witness::LocalWitness;
+    use crate::ledger::notarize::{NotaryBackend, Receipt};
+
+    struct Unreachable;
+
+    impl NotaryBackend for Unreachable {
+        fn name(&self) -> &str {
+            "unreachable"
+        }
+
+        fn submit(&self, _: &AnchorRequest) -> Result<Receipt, Box<dyn Error>> {
+            Err("502 bad gateway".into())
+        }
+
+        fn fetch_receipt(&self, _: &str) -> Result<Receipt, Box<dyn Error>> {
+            Err("502 bad gateway".into())
+        }
+
+        fn verify_receipt(&self, _: &AnchorRequest, _: &Receipt) -> Result<(), Box<dyn Error>> {
+            Err("502 bad gateway".into())
+        }
+    }
+
+    fn request(index: u64) -> AnchorRequest {
+        AnchorRequest { ledger_head_hash: "cd".repeat(32), index, timestamp_ticks: index }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_its_cap() {
+        let policy = BackoffPolicy { initial: Duration::from_secs(5), max: Duration::from_secs(60), max_attempts: None };
+        let delays: Vec<u64> = (0..6).map(|attempts| policy.delay_after(attempts).as_secs()).collect();
+        assert_eq!(delays, vec![5, 10, 20, 40, 60, 60]);
+        assert_eq!(policy.delay_after(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn failed_anchors_survive_a_restart_and_are_retried_when_due() {
+        let dir = std::env::temp_dir().join(format!("rfsn-outbox-retry-{}", std::process::id()));
+        let checkpoint = dir.join("merkle.chk");
+        let policy = BackoffPolicy { initial: Duration::from_secs(10), max: Duration::from_secs(100), max_attempts: Some(3) };
+        let down = NotaryClient::with_backend(Box::new(Unreachable));
+
+        let outbox = NotaryOutbox::open(&dir, policy.clone()).unwrap();
+        assert!(outbox.notarize_or_enqueue(&down, &checkpoint, request(1), 1_000).unwrap().is_none());
+        outbox.enqueue(&checkpoint, request(2), "502 bad gateway", 1_000).unwrap();
+        drop(outbox);
+
+        let outbox = NotaryOutbox::open(&dir, policy).unwrap();
+        let pending = outbox.pending().unwrap();
+        assert_eq!(pending.iter().map(|e| (e.request.index, e.attempts, e.next_attempt_unix)).collect::<Vec<_>>(), vec![(1, 1, 1_010), (2, 1, 1_010)]);
+        assert_eq!(outbox.drain(&down, 1_005).unwrap().not_due, 2);
+
+        let report = outbox.drain(&down, 1_010).unwrap();
+        assert_eq!(report.rescheduled, 2);
+        assert_eq!(outbox.pending().unwrap()[0].next_attempt_unix, 1_030);
+
+        let up = NotaryClient::with_backend(Box::new(LocalWitness::from_seed("w1", [4; 32])));
+        let report = outbox.drain(&up, 1_030).unwrap();
+        assert_eq!(report.anchored.iter().map(|s| s.request.index).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(outbox.depth().unwrap(), 0);
+
+        // Out of attempts: the entry is kept under dead/, not dropped.
+        outbox.enqueue(&checkpoint, request(3), "502 bad gateway", 2_000).unwrap();
+        outbox.drain(&down, 2_010).unwrap();
+        assert_eq!(outbox.drain(&down, 2_030).unwrap().abandoned, 1);
+        assert_eq!(outbox.depth().unwrap(), 0);
+        assert!(dir.join("outbox/dead").join(format!("{:016x}.pending", 3)).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+//! Versioned COSE_Sign1 receipt files.
+//!
+//! A `.receipt` file is a tagged COSE_Sign1 (RFC 9052) whose payload is the receipt
+//! in deterministic CBOR:
+//!
+//! ```text
+//! { 0: version, 1: backend, 2: receipt_id,
+//!   3: { 1: ledger_head_hash, 2: index, 3: timestamp_ticks },
+//!   4: external_timestamp, 5: evidence, ? 6: key_id }
+//! ```
+//!
+//! The witness evidence inside the payload is what proves the anchor; the COSE
+//! signature is an optional Ed25519 (`alg` -8) seal by the node that collected the
+//! receipt. Unsigned files carry an empty signature and no `alg`. Files written
+//! before this format are JSON; `read_receipt` accepts both.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+
+use super::cbor::{self, CborError, Value, TAG_COSE_SIGN1};
+use super::notarize::{AnchorRequest, Receipt};
+
+pub const RECEIPT_FORMAT_VERSION: u64 = 1;
+pub const RECEIPT_CONTENT_TYPE: &str = "application/rfsn-receipt+cbor";
+
+const HEADER_ALG: i64 = 1;
+const HEADER_CONTENT_TYPE: i64 = 3;
+const HEADER_KID: i64 = 4;
+const ALG_EDDSA: i64 = -8;
+
+pub fn receipt_to_cbor(receipt: &Receipt) -> Value {
+    let request = &receipt.request;
+    let mut map = vec![
+        (Value::Uint(0), Value::Uint(RECEIPT_FORMAT_VERSION)),
+        (Value::Uint(1), Value::Text(receipt.backend.clone())),
+        (Value::Uint(2), Value::Text(receipt.receipt_id.clone())),
+        (
+            Value::Uint(3),
+            Value::Map(vec![
+                (Value::Uint(1), Value::Text(request.ledger_head_hash.clone())),
+                (Value::Uint(2), Value::Uint(request.index)),
+                (Value::Uint(3), Value::Uint(request.timestamp_ticks)),
+            ]),
+        ),
+        (Value::Uint(4), Value::Uint(receipt.external_timestamp)),
+        (Value::Uint(5), Value::Bytes(receipt.evidence.clone())),
+    ];
+    if let Some(key_id) = &receipt.key_id {
+        map.push((Value::Uint(6), Value::Text(key_id.clone())));
+    }
+    Value::Map(map)
+}
+
+pub fn receipt_from_cbor(value: &Value) -> Result<Receipt, Box<dyn Error>> {
+    let version = value.require(0)?.as_uint()?;
+    if version != RECEIPT_FORMAT_VERSION {
+        return Err(format!("unsupported receipt format version {}", version).into());
+    }
+    let request = value.require(3)?;
+    Ok(Receipt {
+        backend: value.require(1)?.as_text()?.to_string(),
+        receipt_id: value.require(2)?.as_text()?.to_string(),
+        request: AnchorRequest {
+            ledger_head_hash: request.require(1)?.as_text()?.to_string(),
+            index: request.require(2)?.as_uint()?,
+            timestamp_ticks: request.require(3)?.as_uint()?,
+        },
+        external_timestamp: value.require(4)?.as_uint()?,
+        evidence: value.require(5)?.as_bytes()?.to_vec(),
+        key_id: value.get(6)?.map(|v| v.as_text().map(str::to_string)).transpose()?,
+    })
+}
+
+/// A decoded receipt file.
+#[derive(Debug, Clone)]
+pub struct CoseReceipt {
+    pub receipt: Receipt,
+    /// `kid` of the node key that sealed the file, if it is signed.
+    pub signer: Option<String>,
+    protected: Vec<u8>,
+    payload: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+fn sig_structure(protected: &[u8], payload: &[u8]) -> Vec<u8> {
+    cbor::encode(&Value::Array(vec![
+        Value::Text("Signature1".to_string()),
+        Value::Bytes(protected.to_vec()),
+        Value::Bytes(Vec::new()),
+        Value::Bytes(payload.to_vec()),
+    ]))
+}
+
+impl CoseReceipt {
+    pub fn is_signed(&self) -> bool {
+        !self.signature.is_empty()
+    }
+
+    /// Checks the node seal. Fails for unsigned files.
+    pub fn verify_seal(&self, node_key: &VerifyingKey) -> Result<(), Box<dyn Error>> {
+        if !self.is_signed() {
+            return Err("receipt file is not sealed".into());
+        }
+        let sig = ed25519_dalek::Signature::from_slice(&self.signature)?;
+        node_key.verify_strict(&sig_structure(&self.protected, &self.payload), &sig)?;
+        Ok(())
+    }
+}
+
+/// Encodes `receipt` as a COSE_Sign1, sealed by `signer` (`kid`, key) when given.
+pub fn encode_receipt(receipt: &Receipt, signer: Option<(&str, &SigningKey)>) -> Vec<u8> {
+    let mut header = vec![(Value::int(HEADER_CONTENT_TYPE), Value::Text(RECEIPT_CONTENT_TYPE.to_string()))];
+    if let Some((kid, _)) = signer {
+        header.push((Value::int(HEADER_ALG), Value::int(ALG_EDDSA)));
+        header.push((Value::int(HEADER_KID), Value::Bytes(kid.as_bytes().to_vec())));
+    }
+    let protected = cbor::encode(&Value::Map(header));
+    let payload = cbor::encode(&receipt_to_cbor(receipt));
+    let signature = match signer {
+        Some((_, key)) => key.sign(&sig_structure(&protected, &payload)).to_bytes().to_vec(),
+        None => Vec::new(),
+    };
+    cbor::encode(&Value::Tag(
+        TAG_COSE_SIGN1,
+        Box::new(Value::Array(vec![
+            Value::Bytes(protected),
+            Value::Map(Vec::new()),
+            Value::Bytes(payload),
+            Value::Bytes(signature),
+        ])),
+    ))
+}
+
+pub fn decode_receipt(bytes: &[u8]) -> Result<CoseReceipt, Box<dyn Error>> {
+    let Value::Tag(TAG_COSE_SIGN1, inner) = cbor::decode(bytes)? else {
+        return Err(Box::new(CborError("expected a tagged COSE_Sign1")));
+    };
+    let [protected, _unprotected, payload, signature] = inner.as_array()? else {
+        return Err(Box::new(CborError("COSE_Sign1 must have four elements")));
+    };
+    let (protected, payload, signature) = (protected.as_bytes()?, payload.as_bytes()?, signature.as_bytes()?);
+    let header = cbor::decode(protected)?;
+    if header.get(HEADER_CONTENT_TYPE)?.map(Value::as_text).transpose()? != Some(RECEIPT_CONTENT_TYPE) {
+        return Err("COSE_Sign1 does not carry an RFSN receipt".into());
+    }
+    let signer = match header.get(HEADER_ALG)? {
+        None => None,
+        Some(alg) if *alg == Value::int(ALG_EDDSA) => {
+            let kid = header.get(HEADER_KID)?.map(Value::as_bytes).transpose()?.unwrap_or_default();
+            Some(String::from_utf8_lossy(kid).into_owned())
+        }
+        Some(_) => return Err("unsupported COSE algorithm for receipt seal".into()),
+    };
+    if signer.is_none() != signature.is_empty() {
+        return Err("COSE receipt signature does not match its header".into());
+    }
+    Ok(CoseReceipt {
+        receipt: receipt_from_cbor(&cbor::decode(payload)?)?,
+        signer,
+        protected: protected.to_vec(),
+        payload: payload.to_vec(),
+        signature: signature.to_vec(),
+    })
+}
+
+/// Reads a receipt in either the COSE format or the legacy JSON format.
+pub fn read_receipt(bytes: &[u8]) -> Result<Receipt, Box<dyn Error>> {
+    match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'{') => Ok(serde_json::from_slice(bytes)?),
+        _ => Ok(decode_receipt(bytes)?.receipt),
+    }
+}
+
+pub fn read_receipt_file(path: &Path) -> Result<Receipt, Box<dyn Error>> {
+    read_receipt(&fs::read(path)?)
+}
+
+/// Writes an unsealed COSE receipt file.
+pub fn write_receipt_file(path: &Path, receipt: &Receipt) -> Result<(), Box<dyn Error>> {
+    fs::write(path, encode_receipt(receipt, None))?;
+    Ok(())
+}
@@ -0,0 +1,226 @@
+//! Built-in notarization scheduler.
+//!
+//! Missed anchors are the most common operational failure in anchoring systems,
+//! so instead of relying on the application to call `notarize_checkpoint`, the
+//! scheduler subscribes to store checkpoint events and anchors automatically
+//! every N entries and/or every T seconds.
+
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::entry::ReceiptEntry;
+use super::freeze::AnchoringFreeze;
+use super::notarize::{anchor_request_from_checkpoint, NotaryClient, QuorumStatus};
+use super::outbox::{unix_now, NotaryOutbox};
+use super::storage::{DeterministicStore, StoreEvent};
+
+/// When to anchor. At least one trigger must be set; with both, whichever fires first wins.
+#[derive(Debug, Clone, Default)]
+pub struct SchedulePolicy {
+    /// Anchor once at least this many entries have been appended since the last anchor.
+    pub every_entries: Option<u64>,
+    /// Anchor the latest checkpoint once this much time has passed since the last anchor.
+    pub every_interval: Option<Duration>,
+}
+
+struct Checkpoint {
+    path: PathBuf,
+    entry_count: u64,
+}
+
+pub struct NotarizationScheduler {
+    policy: SchedulePolicy,
+    client: NotaryClient,
+    outbox: Option<NotaryOutbox>,
+    events: Receiver<StoreEvent>,
+    anchored: Sender<QuorumStatus>,
+    freeze: Option<Arc<AnchoringFreeze>>,
+    latest: Option<Checkpoint>,
+    last_anchored_entries: u64,
+    last_anchor_at: Instant,
+}
+
+impl NotarizationScheduler {
+    /// Subscribes to `store` and returns a scheduler that anchors through `client`.
+    /// Failed anchors go to `outbox` when one is given.
+    ///
+    /// The returned receiver yields every successful anchor; the store owner should pass
+    /// each one to `record_anchors`, which appends it as a `ReceiptEntry`.
+    pub fn attach(
+        store: &mut DeterministicStore,
+        policy: SchedulePolicy,
+        client: NotaryClient,
+        outbox: Option<NotaryOutbox>,
+    ) -> Result<(Self, Receiver<QuorumStatus>), Box<dyn Error>> {
+        if policy.every_entries.is_none() && policy.every_interval.is_none() {
+            return Err("notarization schedule needs an entry count or an interval".into());
+        }
+        let (tx, rx) = mpsc::channel();
+        let (anchored_tx, anchored_rx) = mpsc::channel();
+        store.on_event(move |event| {
+            if matches!(event, StoreEvent::Checkpointed { .. }) {
+                // The scheduler may have been dropped; appends must not fail because of it.
+                let _ = tx.send(event.clone());
+            }
+        });
+        let scheduler = Self {
+            policy,
+            client,
+            outbox,
+            events: rx,
+            anchored: anchored_tx,
+            freeze: None,
+            latest: None,
+            last_anchored_entries: store.entry_count(),
+            last_anchor_at: Instant::now(),
+        };
+        Ok((scheduler, anchored_rx))
+    }
+
+    /// Feeds `freeze` with checkpoints and successful anchors.
+    pub fn freeze_on_outage(mut self, freeze: Arc<AnchoringFreeze>) -> Self {
+        self.freeze = Some(freeze);
+        self
+    }
+
+    fn absorb(&mut self, event: StoreEvent) {
+        if let StoreEvent::Checkpointed { path, entry_count } = event {
+            if let Some(freeze) = &self.freeze {
+                freeze.record_checkpoint();
+            }
+            self.latest = Some(Checkpoint { path, entry_count });
+        }
+    }
+
+    fn report(&self, status: QuorumStatus) {
+        if let Some(freeze) = &self.freeze {
+            freeze.record_anchor(&status);
+        }
+        let _ = self.anchored.send(status);
+    }
+
+    fn is_due(&self, now: Instant) -> bool {
+        let Some(latest) = &self.latest else { return false };
+        if latest.entry_count <= self.last_anchored_entries {
+            return false;
+        }
+        let by_count = self.policy.every_entries.is_some_and(|n| latest.entry_count - self.last_anchored_entries >= n);
+        let by_time = self.policy.every_interval.is_some_and(|t| now.duration_since(self.last_anchor_at) >= t);
+        by_count || by_time
+    }
+
+    /// Processes queued store events and anchors if a trigger fired.
+    /// Returns the ledger index that was anchored (or queued), if any.
+    pub fn poll(&mut self) -> Result<Option<u64>, Box<dyn Error>> {
+        while let Ok(event) = self.events.try_recv() {
+            self.absorb(event);
+        }
+        self.anchor_if_due(Instant::now())
+    }
+
+    fn anchor_if_due(&mut self, now: Instant) -> Result<Option<u64>, Box<dyn Error>> {
+        if !self.is_due(now) {
+            return Ok(None);
+        }
+        let latest = self.latest.as_ref().unwrap();
+        let index = latest.entry_count;
+        // Capture the head now: the checkpoint file is overwritten by the next checkpoint.
+        let req = anchor_request_from_checkpoint(&std::fs::read(&latest.path)?, index)?;
+        let result = match &self.outbox {
+            Some(outbox) => outbox.notarize_or_enqueue(&self.client, &latest.path, req, unix_now()),
+            None => self.client.notarize_request(&latest.path, &req).map(Some),
+        };
+        // Either anchored or durably queued; in both cases the schedule moves on.
+        self.last_anchored_entries = index;
+        self.last_anchor_at = now;
+        if let Some(status) = result? {
+            self.report(status);
+        }
+        Ok(Some(index))
+    }
+
+    /// Runs the scheduler on its own thread until the store (and so the event sender) is dropped.
+    /// Errors are logged and do not stop the loop.
+    pub fn spawn(mut self) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let wake = self.policy.every_interval.unwrap_or(Duration::from_secs(1)).min(Duration::from_secs(1));
+            loop {
+                match self.events.recv_timeout(wake) {
+                    Ok(event) => self.absorb(event),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if let Some(outbox) = &self.outbox {
+                    match outbox.drain(&self.client, unix_now()) {
+                        Ok(report) => {
+                            for status in report.anchored {
+                                self.report(status);
+                            }
+                        }
+                        Err(e) => eprintln!("⚠️  Notarization outbox drain failed: {}", e),
+                    }
+                }
+                self.client.check_health();
+                if let Err(e) = self.anchor_if_due(Instant::now()) {
+                    eprintln!("⚠️  Scheduled notarization failed: {}", e);
+                }
+                // Pages even when nothing was attempted in this pass.
+                if let Some(metrics) = self.client.metrics() {
+                    metrics.check_overdue(unix_now());
+                }
+                // Logs the transition into the freeze even when no action is proposed.
+                if let Some(freeze) = &self.freeze {
+                    let _ = freeze.check(unix_now());
+                }
+            }
+        })
+    }
+}
+
+/// Appends every anchor reported by the scheduler so far into the ledger.
+pub fn record_anchors(store: &mut DeterministicStore, anchored: &Receiver<QuorumStatus>) -> Result<usize, Box<dyn Error>> {
+    let mut recorded = 0;
+    while let Ok(status) = anchored.try_recv() {
+        ReceiptEntry::from_status(&status).append_to(store)?;
+        recorded += 1;
+    }
+    Ok(recorded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::local_witness::LocalWitness;
+    use crate::ledger::storage::StoreConfig;
+
+    #[test]
+    fn checkpoints_are_anchored_once_enough_entries_follow_the_last_anchor() {
+        let dir = std::env::temp_dir().join(format!("rfsn-scheduler-entries-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut store = DeterministicStore::open(StoreConfig::new(&dir).checkpoint_interval(2)).unwrap();
+        let witness = LocalWitness::from_seed("w1", [5; 32]);
+        let client = NotaryClient::with_backend(Box::new(witness.clone()));
+        let idle = NotaryClient::with_backend(Box::new(witness.clone()));
+        assert!(NotarizationScheduler::attach(&mut store, SchedulePolicy::default(), idle, None).is_err());
+        let policy = SchedulePolicy { every_entries: Some(4), every_interval: None };
+        let (mut scheduler, anchored) = NotarizationScheduler::attach(&mut store, policy, client, None).unwrap();
+
+        for i in 0..3u8 {
+            store.append_entry(&[i; 8]).unwrap();
+        }
+        assert_eq!(scheduler.poll().unwrap(), None);
+        store.append_entry(&[3; 8]).unwrap();
+        assert_eq!(scheduler.poll().unwrap(), Some(4));
+        assert_eq!(scheduler.poll().unwrap(), None);
+        assert_eq!(witness.issued(), 1);
+
+        assert_eq!(record_anchors(&mut store, &anchored).unwrap(), 1);
+        assert_eq!(store.entry_count(), 5);
+        drop(store);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,244 @@
+//! Cross-witness split-view detection.
+//!
+//! A compromised node (or an attacker holding its notary credentials) can show a
+//! different ledger history to each witness. Every witness sees a consistent story
+//! on its own; the fork only shows when their views are compared. The check asks each
+//! witness for the newest receipt it issued to us and compares the signed head
+//! against our own ledger: a head that is not the Merkle root of our entries at that
+//! index, or that lies beyond the end of our ledger, was anchored for a history we do
+//! not have. Findings are appended to the ledger as `EntryKind::SplitView` entries,
+//! signed receipts included, so the evidence is covered by later anchors.
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::batch::BATCH_HEAD_PREFIX;
+use super::entry::{self, EntryKind, ReceiptEntry};
+use super::merkle::{leaf_hash, MerkleFrontier};
+use super::notarize::{AnchorRequest, NotaryBackend, NotaryClient, Receipt};
+use super::outbox::unix_now;
+use super::storage::{DeterministicStore, LedgerResult};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ViewFinding {
+    /// The witness's newest head is our latest recorded anchor.
+    Agrees,
+    /// The witness's newest head matches our ledger at an earlier index: it missed an
+    /// anchor, which is not a split on its own.
+    Behind,
+    /// The head matches our ledger at a later index than our latest recorded anchor:
+    /// a round that did not reach quorum, or one not yet recorded.
+    Pending,
+    /// The witness signed a head that is not our Merkle root at that index.
+    Equivocation,
+    /// The witness signed a head beyond the end of our ledger: someone anchored on our
+    /// behalf, or our ledger was rolled back.
+    Ahead,
+    /// A batch commitment we hold no record of; it cannot be checked against the entries.
+    Unrecognized,
+    /// The witness cannot report its view, or the view did not verify.
+    Unavailable,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WitnessView {
+    /// Position of the witness in the client's backend list.
+    pub position: usize,
+    pub backend: String,
+    pub finding: ViewFinding,
+    /// The verified receipt the witness reported, if any.
+    pub receipt: Option<Receipt>,
+    pub error: Option<String>,
+}
+
+/// Body of an `EntryKind::SplitView` entry.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SplitViewReport {
+    /// Our latest recorded anchor; `None` if nothing was recorded yet.
+    pub ours: Option<AnchorRequest>,
+    pub tree_size: u64,
+    pub checked_at_unix: u64,
+    pub views: Vec<WitnessView>,
+}
+
+impl SplitViewReport {
+    /// Appends the evidence as an `EntryKind::SplitView` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::SplitView, self)
+    }
+
+    /// Whether any witness holds a signed head that contradicts our ledger.
+    pub fn is_split(&self) -> bool {
+        self.views.iter().any(|v| matches!(v.finding, ViewFinding::Equivocation | ViewFinding::Ahead))
+    }
+}
+
+fn fetch_view(position: usize, backend: &dyn NotaryBackend) -> WitnessView {
+    let mut view = WitnessView {
+        position,
+        backend: backend.name().to_string(),
+        finding: ViewFinding::Unavailable,
+        receipt: None,
+        error: None,
+    };
+    match backend.latest_receipt() {
+        // An unsigned claim is no evidence of equivocation: only verified receipts count.
+        Ok(Some(receipt)) => match backend.verify_receipt(&receipt.request, &receipt) {
+            Ok(()) => view.receipt = Some(receipt),
+            Err(e) => view.error = Some(format!("reported receipt does not verify: {}", e)),
+        },
+        Ok(None) => view.error = Some("witness cannot report its latest receipt".to_string()),
+        Err(e) => view.error = Some(e.to_string()),
+    }
+    view
+}
+
+/// Asks every witness of `client` for its newest receipt and checks the signed heads
+/// against `store`. Reads the whole ledger once.
+pub fn check_split_view(store: &DeterministicStore, client: &NotaryClient) -> Result<SplitViewReport, Box<dyn Error>> {
+    let mut views: Vec<WitnessView> = client.backends().enumerate().map(|(i, backend)| fetch_view(i, backend)).collect();
+
+    // Roots are only needed at the indices the witnesses reported.
+    let mut roots: BTreeMap<u64, Option<String>> = views
+        .iter()
+        .filter_map(|v| v.receipt.as_ref())
+        .filter(|r| !r.request.ledger_head_hash.starts_with(BATCH_HEAD_PREFIX))
+        .map(|r| (r.request.index, None))
+        .collect();
+    if roots.contains_key(&0) {
+        roots.insert(0, Some(hex::encode(MerkleFrontier::new().root())));
+    }
+    let mut tree = MerkleFrontier::new();
+    let mut recorded: HashSet<AnchorRequest> = HashSet::new();
+    let mut ours: Option<AnchorRequest> = None;
+    store.for_each_entry(|_, payload| {
+        tree.push(leaf_hash(payload));
+        if let Some(root) = roots.get_mut(&tree.size()) {
+            *root = Some(hex::encode(tree.root()));
+        }
+        let Some((EntryKind::NotaryReceipt, body)) = entry::decode(payload) else { return };
+        if let Ok(entry) = serde_json::from_slice::<ReceiptEntry>(body) {
+            if let Some(request) = entry.receipts.first().map(|r| r.request.clone()) {
+                if !request.ledger_head_hash.starts_with(BATCH_HEAD_PREFIX) && ours.as_ref().is_none_or(|o| request.index >= o.index) {
+                    ours = Some(request.clone());
+                }
+                recorded.insert(request);
+            }
+        }
+    })?;
+
+    let latest_index = ours.as_ref().map_or(0, |o| o.index);
+    for view in &mut views {
+        let Some(theirs) = view.receipt.as_ref().map(|r| &r.request) else { continue };
+        view.finding = if ours.as_ref() == Some(theirs) {
+            ViewFinding::Agrees
+        } else if theirs.ledger_head_hash.starts_with(BATCH_HEAD_PREFIX) {
+            if recorded.contains(theirs) { ViewFinding::Behind } else { ViewFinding::Unrecognized }
+        } else {
+            match roots.get(&theirs.index) {
+                Some(Some(root)) if *root == theirs.ledger_head_hash => {
+                    if theirs.index < latest_index { ViewFinding::Behind } else { ViewFinding::Pending }
+                }
+                Some(Some(_)) => ViewFinding::Equivocation,
+                _ => ViewFinding::Ahead,
+            }
+        };
+    }
+
+    let report = SplitViewReport { ours, tree_size: tree.size(), checked_at_unix: unix_now(), views };
+    if report.is_split() {
+        let forked: Vec<&str> = report
+            .views
+            .iter()
+            .filter(|v| matches!(v.finding, ViewFinding::Equivocation | ViewFinding::Ahead))
+            .map(|v| v.backend.as_str())
+            .collect();
+        eprintln!("❌ Split view: witnesses {:?} hold heads that contradict the ledger.", forked);
+    }
+    Ok(report)
+}
+
+/// Runs `check_split_view` at most once per `every` and records splits in the ledger.
+/// Call `poll` from the thread that owns the store, next to `record_anchors`.
+pub struct SplitViewMonitor {
+    every: Duration,
+    last_check: Option<Instant>,
+}
+
+impl SplitViewMonitor {
+    pub fn new(every: Duration) -> Self {
+        Self { every, last_check: None }
+    }
+
+    /// Checks if due. A split is appended as an `EntryKind::SplitView` entry and returned.
+    pub fn poll(&mut self, store: &mut DeterministicStore, client: &NotaryClient) -> Result<Option<SplitViewReport>, Box<dyn Error>> {
+        let now = Instant::now();
+        if self.last_check.is_some_and(|last| now.duration_since(last) < self.every) {
+            return Ok(None);
+        }
+        self.last_check = Some(now);
+        let report = check_split_view(store, client)?;
+        if !report.is_split() {
+            return Ok(None);
+        }
+        report.append_to(store)?;
+        Ok(Some(report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::local_witness::LocalWitness;
+    use crate::ledger::notarize::QuorumStatus;
+
+    fn head(index: u64, ledger_head_hash: String) -> AnchorRequest {
+        AnchorRequest { ledger_head_hash, index, timestamp_ticks: index }
+    }
+
+    #[test]
+    fn witnesses_holding_heads_we_never_had_are_reported_as_a_split() {
+        let dir = std::env::temp_dir().join(format!("rfsn-split-view-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut store = DeterministicStore::new(&dir).unwrap();
+        let mut tree = MerkleFrontier::new();
+        let mut roots = Vec::new();
+        for i in 0..4u8 {
+            store.append_entry(&[i; 8]).unwrap();
+            tree.push(leaf_hash(&[i; 8]));
+            roots.push(hex::encode(tree.root()));
+        }
+
+        let [agrees, behind, forked, ahead] = [1, 2, 3, 4].map(|seed| LocalWitness::from_seed("w", [seed; 32]));
+        let ours = head(4, roots[3].clone());
+        let mut status = QuorumStatus::new(ours.clone(), 1, 1);
+        status.receipts.push(agrees.notarize(&ours));
+        ReceiptEntry::from_status(&status).append_to(&mut store).unwrap();
+        behind.notarize(&head(2, roots[1].clone()));
+        forked.notarize(&head(4, "00".repeat(32)));
+        ahead.notarize(&head(9, "11".repeat(32)));
+
+        let witnesses = [agrees, behind, forked, ahead, LocalWitness::from_seed("w", [5; 32])];
+        let client = NotaryClient::quorum_of(witnesses.into_iter().map(|w| Box::new(w) as Box<dyn NotaryBackend>).collect(), 1).unwrap();
+        let report = check_split_view(&store, &client).unwrap();
+        assert_eq!((report.ours.as_ref(), report.tree_size), (Some(&ours), 5));
+        let findings: Vec<ViewFinding> = report.views.iter().map(|v| v.finding).collect();
+        assert_eq!(
+            findings,
+            vec![ViewFinding::Agrees, ViewFinding::Behind, ViewFinding::Equivocation, ViewFinding::Ahead, ViewFinding::Unavailable]
+        );
+        assert!(report.is_split());
+
+        let mut monitor = SplitViewMonitor::new(Duration::from_secs(3600));
+        let recorded = monitor.poll(&mut store, &client).unwrap().unwrap();
+        assert_eq!(recorded.views, report.views);
+        assert_eq!(store.entry_count(), 6);
+        assert!(monitor.poll(&mut store, &client).unwrap().is_none());
+        drop(store);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,102 +1,1069 @@
+use std::fmt;
 use std::fs::{File, OpenOptions};
 use std::io::{self, Write, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
 use blake3::Hasher;
+use serde::Serialize;
 
-const SEGMENT_SIZE: u64 = 64 * 1024 * 1024; // 64 MB per segment
-const MERKLE_COMPACTION_INTERVAL: u64 = 1024; // Compact Merkle tree every 1024 entries
+use super::entry::{self, EntryKind};
+use super::merkle::{leaf_hash, Checkpoint, MerkleFrontier};
+use super::tick::SharedTicks;
+
+pub const DEFAULT_SEGMENT_SIZE: u64 = 64 * 1024 * 1024; // 64 MB per segment
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 1024; // Compact Merkle tree every 1024 entries
+
+const SEGMENT_MAGIC: &[u8; 8] = b"RFSNSEG1";
+const SEGMENT_FORMAT_VERSION: u32 = 1;
+const SEGMENT_HEADER_LEN: u64 = 8 + 4 + 8 + 8 + 4;
+
+/// Errors surfaced by the ledger. Callers such as the Gate and the sequencer are
+/// expected to treat `Corruption`/`ChainMismatch` as fatal (freeze and resync)
+/// and `Io` as potentially transient.
+#[derive(Debug)]
+pub enum LedgerError {
+    /// On-disk bytes do not decode or do not match their manifest.
+    Corruption { segment: u64, offset: u64, reason: String },
+    /// A segment does not continue the entry sequence of its predecessor.
+    ChainMismatch { segment: u64, expected_first_entry: u64, found_first_entry: u64 },
+    /// A write would exceed a configured limit.
+    QuotaExceeded { limit: u64, requested: u64 },
+    /// The store is held by another process, or was opened read-only.
+    Locked(String),
+    /// A segment header or manifest was written by an unknown format version.
+    UnsupportedVersion { segment: u64, version: u32 },
+    /// The supplied `StoreConfig` cannot be used.
+    InvalidConfig(&'static str),
+    /// An application payload starts with `entry::TYPED_ENTRY_MAGIC`, which is reserved
+    /// for the entries the ledger machinery writes through `append_typed`.
+    ReservedPrefix,
+    Io(io::Error),
+}
+
+impl LedgerError {
+    /// True for failures that indicate the on-disk history cannot be trusted.
+    pub fn is_integrity_failure(&self) -> bool {
+        matches!(self, LedgerError::Corruption { .. } | LedgerError::ChainMismatch { .. })
+    }
+
+    fn corruption(segment: u64, offset: u64, reason: impl Into<String>) -> Self {
+        LedgerError::Corruption { segment, offset, reason: reason.into() }
+    }
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::Corruption { segment, offset, reason } => {
+                write!(f, "ledger corruption in segment {:08x} at offset {}: {}", segment, offset, reason)
+            }
+            LedgerError::ChainMismatch { segment, expected_first_entry, found_first_entry } => write!(
+                f,
+                "segment {:08x} starts at entry {}, expected {}",
+                segment, found_first_entry, expected_first_entry
+            ),
+            LedgerError::QuotaExceeded { limit, requested } => {
+                write!(f, "ledger quota exceeded: requested {} bytes, limit {}", requested, limit)
+            }
+            LedgerError::Locked(reason) => write!(f, "ledger is locked: {}", reason),
+            LedgerError::UnsupportedVersion { segment, version } => {
+                write!(f, "segment {:08x} uses unsupported format version {}", segment, version)
+            }
+            LedgerError::InvalidConfig(reason) => write!(f, "invalid store configuration: {}", reason),
+            LedgerError::ReservedPrefix => write!(f, "application entries may not start with the typed-entry header"),
+            LedgerError::Io(e) => write!(f, "ledger I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LedgerError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LedgerError {
+    fn from(e: io::Error) -> Self {
+        LedgerError::Io(e)
+    }
+}
+
+pub type LedgerResult<T> = Result<T, LedgerError>;
+
+/// When appended entries are forced to stable storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// fsync only on an explicit `commit()` (and on segment roll).
+    OnCommit,
+    /// fsync after every appended entry.
+    EveryEntry,
+    /// fsync only when a segment is sealed; `commit()` is a no-op.
+    OnSegmentRoll,
+}
+
+impl SyncPolicy {
+    fn to_wire(self) -> u32 {
+        match self {
+            SyncPolicy::OnCommit => 0,
+            SyncPolicy::EveryEntry => 1,
+            SyncPolicy::OnSegmentRoll => 2,
+        }
+    }
+
+    fn from_wire(v: u32) -> Option<Self> {
+        match v {
+            0 => Some(SyncPolicy::OnCommit),
+            1 => Some(SyncPolicy::EveryEntry),
+            2 => Some(SyncPolicy::OnSegmentRoll),
+            _ => None,
+        }
+    }
+}
+
+/// Where segments and the checkpoint index live under the base directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirLayout {
+    /// Segments, manifests and `merkle.chk` all sit directly in the base directory.
+    Flat,
+    /// Segments and manifests under `segments/`, checkpoints under `index/`.
+    Nested,
+}
+
+/// Store configuration. Built with chained setters:
+///
+/// ```ignore
+/// let config = StoreConfig::new(dir)
+///     .segment_size(16 * 1024 * 1024)
+///     .checkpoint_interval(256)
+///     .sync_policy(SyncPolicy::EveryEntry);
+/// let store = DeterministicStore::open(config)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct StoreConfig {
+    pub base_dir: PathBuf,
+    pub segment_size: u64,
+    pub checkpoint_interval: u64,
+    pub sync_policy: SyncPolicy,
+    pub layout: DirLayout,
+}
+
+impl StoreConfig {
+    pub fn new(base_dir: &Path) -> Self {
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            sync_policy: SyncPolicy::OnCommit,
+            layout: DirLayout::Flat,
+        }
+    }
+
+    pub fn segment_size(mut self, bytes: u64) -> Self {
+        self.segment_size = bytes;
+        self
+    }
+
+    pub fn checkpoint_interval(mut self, entries: u64) -> Self {
+        self.checkpoint_interval = entries;
+        self
+    }
+
+    pub fn sync_policy(mut self, policy: SyncPolicy) -> Self {
+        self.sync_policy = policy;
+        self
+    }
+
+    pub fn layout(mut self, layout: DirLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    fn validate(&self) -> LedgerResult<()> {
+        if self.segment_size <= SEGMENT_HEADER_LEN + 8 {
+            return Err(LedgerError::InvalidConfig("segment_size too small to hold an entry"));
+        }
+        if self.checkpoint_interval == 0 {
+            return Err(LedgerError::InvalidConfig("checkpoint_interval must be non-zero"));
+        }
+        Ok(())
+    }
+
+    fn segments_dir(&self) -> PathBuf {
+        match self.layout {
+            DirLayout::Flat => self.base_dir.clone(),
+            DirLayout::Nested => self.base_dir.join("segments"),
+        }
+    }
+
+    fn index_dir(&self) -> PathBuf {
+        match self.layout {
+            DirLayout::Flat => self.base_dir.clone(),
+            DirLayout::Nested => self.base_dir.join("index"),
+        }
+    }
+}
+
+/// Header written at the start of every segment, recording the configuration the
+/// segment was produced under so stores with mixed histories remain verifiable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentHeader {
+    pub version: u32,
+    pub segment_size: u64,
+    pub checkpoint_interval: u64,
+    pub sync_policy: SyncPolicy,
+}
+
+impl SegmentHeader {
+    fn for_config(config: &StoreConfig) -> Self {
+        Self {
+            version: SEGMENT_FORMAT_VERSION,
+            segment_size: config.segment_size,
+            checkpoint_interval: config.checkpoint_interval,
+            sync_policy: config.sync_policy,
+        }
+    }
+
+    fn encode(&self) -> [u8; SEGMENT_HEADER_LEN as usize] {
+        let mut buf = [0u8; SEGMENT_HEADER_LEN as usize];
+        buf[..8].copy_from_slice(SEGMENT_MAGIC);
+        buf[8..12].copy_from_slice(&self.version.to_le_bytes());
+        buf[12..20].copy_from_slice(&self.segment_size.to_le_bytes());
+        buf[20..28].copy_from_slice(&self.checkpoint_interval.to_le_bytes());
+        buf[28..32].copy_from_slice(&self.sync_policy.to_wire().to_le_bytes());
+        buf
+    }
+
+    fn decode(segment: u64, buf: &[u8; SEGMENT_HEADER_LEN as usize]) -> LedgerResult<Self> {
+        if &buf[..8] != SEGMENT_MAGIC {
+            return Err(LedgerError::corruption(segment, 0, "bad segment magic"));
+        }
+        let version = u32::from_le_bytes(buf[8..12].try_into().unwrap());
+        if version != SEGMENT_FORMAT_VERSION {
+            return Err(LedgerError::UnsupportedVersion { segment, version });
+        }
+        let sync_policy = SyncPolicy::from_wire(u32::from_le_bytes(buf[28..32].try_into().unwrap()))
+            .ok_or_else(|| LedgerError::corruption(segment, 28, "unknown sync policy in segment header"))?;
+        Ok(Self {
+            version,
+            segment_size: u64::from_le_bytes(buf[12..20].try_into().unwrap()),
+            checkpoint_interval: u64::from_le_bytes(buf[20..28].try_into().unwrap()),
+            sync_policy,
+        })
+    }
+}
+
+const MANIFEST_MAGIC: &[u8; 8] = b"RFSNMAN1";
+const MANIFEST_LEN: usize = 8 + 8 + 8 + 8 + 8 + 4 + 32 + 32; // fixed-size, see SegmentManifest::encode
+
+/// Segment payloads are stored compressed.
+pub const MANIFEST_FLAG_COMPRESSED: u32 = 1 << 0;
+/// Segment payloads are stored encrypted.
+pub const MANIFEST_FLAG_ENCRYPTED: u32 = 1 << 1;
+
+/// Sidecar description of a sealed segment.
+/// Written next to `log_XXXXXXXX.dat` when the segment is rolled, so a single
+/// segment (e.g. from a partial restore or archive download) can be verified
+/// without replaying the rest of the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentManifest {
+    pub segment_id: u64,
+    pub first_entry: u64,
+    pub entry_count: u64,
+    pub byte_length: u64,
+    pub flags: u32,
+    pub segment_hash: [u8; 32],
+}
+
+impl SegmentManifest {
+    /// Fixed little-endian layout followed by a BLAKE3 checksum of everything before it.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(MANIFEST_LEN);
+        buf.extend_from_slice(MANIFEST_MAGIC);
+        buf.extend_from_slice(&self.segment_id.to_le_bytes());
+        buf.extend_from_slice(&self.first_entry.to_le_bytes());
+        buf.extend_from_slice(&self.entry_count.to_le_bytes());
+        buf.extend_from_slice(&self.byte_length.to_le_bytes());
+        buf.extend_from_slice(&self.flags.to_le_bytes());
+        buf.extend_from_slice(&self.segment_hash);
+        let checksum = blake3::hash(&buf);
+        buf.extend_from_slice(checksum.as_bytes());
+        buf
+    }
+
+    /// Decodes the manifest expected to describe `segment`.
+    pub fn decode(segment: u64, bytes: &[u8]) -> LedgerResult<Self> {
+        if bytes.len() != MANIFEST_LEN || &bytes[..8] != MANIFEST_MAGIC {
+            return Err(LedgerError::corruption(segment, 0, "malformed segment manifest"));
+        }
+        let (body, checksum) = bytes.split_at(MANIFEST_LEN - 32);
+        if blake3::hash(body).as_bytes() != checksum {
+            return Err(LedgerError::corruption(segment, 0, "segment manifest checksum mismatch"));
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(body[at..at + 8].try_into().unwrap());
+        let mut segment_hash = [0u8; 32];
+        segment_hash.copy_from_slice(&body[44..76]);
+        Ok(Self {
+            segment_id: u64_at(8),
+            first_entry: u64_at(16),
+            entry_count: u64_at(24),
+            byte_length: u64_at(32),
+            flags: u32::from_le_bytes(body[40..44].try_into().unwrap()),
+            segment_hash,
+        })
+    }
+}
+
+/// How `DeterministicStore::open_with` treats what it finds on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Validate everything, never create, truncate or append. Appends fail.
+    ReadOnly,
+    /// Refuse to open anything that fails validation (the default).
+    Strict,
+    /// Perform documented recovery instead of failing:
+    /// - a torn tail (partial final entry) is truncated back to the last complete entry;
+    /// - a missing or undecodable manifest of a sealed segment is rebuilt from the
+    ///   segment contents.
+    ///
+    /// A manifest that decodes but disagrees with its segment, or with the entry sequence,
+    /// fails the open as in `Strict`: rebuilding it would bless whatever changed the segment.
+    /// Nor is a segment whose header itself is unreadable repaired. Every repair action is
+    /// appended to the ledger as an `EntryKind::Repair` entry.
+    Repair,
+}
+
+/// Notifications delivered to callbacks registered with `DeterministicStore::on_event`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreEvent {
+    /// An entry was appended; `index` is its zero-based position in the ledger.
+    Appended { index: u64 },
+    /// A Merkle checkpoint covering the first `entry_count` entries was written to `path`.
+    Checkpointed { path: PathBuf, entry_count: u64 },
+    /// A segment was sealed and its manifest written.
+    SegmentSealed { segment_id: u64 },
+}
+
+/// Store callbacks run synchronously on the append path and must not block;
+/// hand the event off (e.g. over a channel) for anything expensive.
+pub type StoreCallback = Box<dyn FnMut(&StoreEvent) + Send>;
+
+/// Receives each complete entry payload of a segment scan.
+type EntryVisitor<'a> = &'a mut dyn FnMut(&[u8]);
+
+/// Result of walking a segment file entry by entry.
+struct SegmentScan {
+    header: Option<SegmentHeader>,
+    entry_count: u64,
+    valid_length: u64,
+    hash: Hasher,
+}
 
 /// Represents a strictly append-only, log-structured deterministic storage engine.
 pub struct DeterministicStore {
-    base_dir: PathBuf,
+    config: StoreConfig,
+    mode: OpenMode,
+    // Exclusive advisory lock on `LOCK`, held for the lifetime of a writable store.
+    _lock: Option<File>,
     current_segment_id: u64,
     current_file: Option<File>,
     current_offset: u64,
     entry_count: u64,
+    segment_first_entry: u64,
+    segment_hasher: Hasher,
+    // Merkle frontier over every entry in the store, rebuilt from the segments on open.
+    tree: MerkleFrontier,
+    tick_source: Option<SharedTicks>,
+    callbacks: Vec<StoreCallback>,
 }
 
 impl DeterministicStore {
-    pub fn new(base_dir: &Path) -> io::Result<Self> {
-        std::fs::create_dir_all(base_dir)?;
+    /// Opens (or creates) a store with the default configuration.
+    pub fn new(base_dir: &Path) -> LedgerResult<Self> {
+        Self::open(StoreConfig::new(base_dir))
+    }
+
+    /// Opens (or creates) a store in `OpenMode::Strict`.
+    pub fn open(config: StoreConfig) -> LedgerResult<Self> {
+        Self::open_with(config, OpenMode::Strict)
+    }
+
+    /// Opens a store. Every sealed segment must have a manifest that matches its
+    /// contents; the highest-numbered segment is resumed for appends. What happens
+    /// when validation fails depends on `mode`.
+    pub fn open_with(config: StoreConfig, mode: OpenMode) -> LedgerResult<Self> {
+        config.validate()?;
+        let lock = if mode != OpenMode::ReadOnly {
+            std::fs::create_dir_all(config.segments_dir())?;
+            std::fs::create_dir_all(config.index_dir())?;
+            let lock = OpenOptions::new().create(true).truncate(false).write(true).open(config.base_dir.join("LOCK"))?;
+            if lock.try_lock().is_err() {
+                return Err(LedgerError::Locked(format!("{} is in use by another process", config.base_dir.display())));
+            }
+            Some(lock)
+        } else {
+            None
+        };
         let mut store = Self {
-            base_dir: base_dir.to_path_buf(),
+            config,
+            mode,
+            _lock: lock,
             current_segment_id: 0,
             current_file: None,
             current_offset: 0,
             entry_count: 0,
+            segment_first_entry: 0,
+            segment_hasher: Hasher::new(),
+            tree: MerkleFrontier::new(),
+            tick_source: None,
+            callbacks: Vec::new(),
         };
-        store.open_segment(0)?;
+        let mut repairs = Vec::new();
+
+        let last_segment = store.last_segment_id()?;
+        for id in 0..last_segment {
+            let manifest = match store.read_manifest(id) {
+                Ok(manifest) => manifest,
+                Err(e) if mode == OpenMode::Repair && (is_not_found(&e) || e.is_integrity_failure()) => {
+                    store.entry_count += store.repair_sealed_segment(id, &mut repairs)?.entry_count;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            // Leaves are only kept if the segment is accepted as-is.
+            let mut tree = store.tree.clone();
+            store.check_segment(id, &manifest, Some(&mut tree))?;
+            if manifest.first_entry != store.entry_count {
+                return Err(LedgerError::ChainMismatch {
+                    segment: id,
+                    expected_first_entry: store.entry_count,
+                    found_first_entry: manifest.first_entry,
+                });
+            }
+            store.tree = tree;
+            store.entry_count += manifest.entry_count;
+        }
+        store.open_segment_with_repair(last_segment, &mut repairs)?;
+
+        if mode == OpenMode::ReadOnly {
+            return Ok(store);
+        }
+
+        // A resumed segment written under a different configuration is sealed as-is,
+        // so every segment is internally consistent with its own header. One that holds
+        // nothing but its header has no entries to keep consistent and is rewritten instead.
+        let wanted = SegmentHeader::for_config(&store.config);
+        if store.current_header()? != wanted {
+            if store.current_offset > SEGMENT_HEADER_LEN {
+                store.roll_segment()?;
+            } else {
+                store.current_file = None;
+                Self::truncate_segment(&store.segment_path(store.current_segment_id), 0)?;
+                store.open_segment(store.current_segment_id)?;
+            }
+        }
+
+        for action in repairs {
+            store.append_typed(EntryKind::Repair, action.as_bytes())?;
+        }
+        if mode == OpenMode::Repair {
+            // Repair notes are durable regardless of the configured sync policy.
+            if let Some(file) = &store.current_file {
+                file.sync_data()?;
+            }
+        }
         Ok(store)
     }
 
+    /// Registers a callback for appends, checkpoints and segment seals.
+    pub fn on_event(&mut self, callback: impl FnMut(&StoreEvent) + Send + 'static) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    /// Sets the tick source whose value is recorded in each checkpoint, and so in
+    /// every anchor request built from one. Pass the node's shared source so ledger
+    /// ticks agree with the sequencer and the scheduler. Without one, the checkpoint
+    /// tick is the logical time, i.e. the entry count.
+    pub fn set_tick_source(&mut self, ticks: SharedTicks) {
+        self.tick_source = Some(ticks);
+    }
+
+    pub fn tick_source(&self) -> Option<&SharedTicks> {
+        self.tick_source.as_ref()
+    }
+
+    fn emit(&mut self, event: StoreEvent) {
+        for callback in &mut self.callbacks {
+            callback(&event);
+        }
+    }
+
+    pub fn mode(&self) -> OpenMode {
+        self.mode
+    }
+
+    /// Total number of entries in the store.
+    pub fn entry_count(&self) -> u64 {
+        self.entry_count
+    }
+
+    pub fn config(&self) -> &StoreConfig {
+        &self.config
+    }
+
+    /// Merkle tree over every entry; `tree().root()` is the current ledger head.
+    pub fn tree(&self) -> &MerkleFrontier {
+        &self.tree
+    }
+
     fn segment_path(&self, id: u64) -> PathBuf {
-        self.base_dir.join(format!("log_{:08x}.dat", id))
+        self.config.segments_dir().join(format!("log_{:08x}.dat", id))
+    }
+
+    fn manifest_path(&self, id: u64) -> PathBuf {
+        self.config.segments_dir().join(format!("log_{:08x}.manifest", id))
+    }
+
+    pub fn checkpoint_path(&self) -> PathBuf {
+        self.config.index_dir().join("merkle.chk")
+    }
+
+    fn last_segment_id(&self) -> LedgerResult<u64> {
+        let mut last = 0;
+        for dirent in std::fs::read_dir(self.config.segments_dir())? {
+            let name = dirent?.file_name();
+            let name = name.to_string_lossy();
+            if let Some(hex) = name.strip_prefix("log_").and_then(|n| n.strip_suffix(".dat")) {
+                if let Ok(id) = u64::from_str_radix(hex, 16) {
+                    last = last.max(id);
+                }
+            }
+        }
+        Ok(last)
+    }
+
+    fn current_header(&self) -> LedgerResult<SegmentHeader> {
+        let id = self.current_segment_id;
+        Self::scan_segment(id, &self.segment_path(id), None)?
+            .header
+            .ok_or_else(|| LedgerError::corruption(id, 0, "segment is missing its header"))
+    }
+
+    /// Walks the header and length-prefixed entries of a segment, hashing the bytes
+    /// of the header and every complete entry. Complete payloads are also passed to `visit`.
+    fn scan_segment(id: u64, path: &Path, mut visit: Option<EntryVisitor<'_>>) -> LedgerResult<SegmentScan> {
+        let mut file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut scan = SegmentScan { header: None, entry_count: 0, valid_length: 0, hash: Hasher::new() };
+        if file_len < SEGMENT_HEADER_LEN {
+            return Ok(scan);
+        }
+
+        let mut header_buf = [0u8; SEGMENT_HEADER_LEN as usize];
+        file.read_exact(&mut header_buf)?;
+        scan.header = Some(SegmentHeader::decode(id, &header_buf)?);
+        scan.hash.update(&header_buf);
+        scan.valid_length = SEGMENT_HEADER_LEN;
+
+        let mut len_buf = [0u8; 8];
+        let mut payload = Vec::new();
+        while scan.valid_length + 8 <= file_len {
+            file.read_exact(&mut len_buf)?;
+            let payload_len = u64::from_le_bytes(len_buf);
+            // A length running past the end of the file, or past any file, is a torn tail.
+            let Some(end) = (scan.valid_length + 8).checked_add(payload_len).filter(|&end| end <= file_len) else {
+                break;
+            };
+            payload.resize(payload_len as usize, 0);
+            file.read_exact(&mut payload)?;
+            scan.hash.update(&len_buf);
+            scan.hash.update(&payload);
+            if let Some(visit) = visit.as_deref_mut() {
+                visit(&payload);
+            }
+            scan.valid_length = end;
+            scan.entry_count += 1;
+        }
+        Ok(scan)
+    }
+
+    /// Calls `f(index, payload)` for every entry in ledger order. Works on a read-only store,
+    /// e.g. for audits.
+    pub fn for_each_entry(&self, mut f: impl FnMut(u64, &[u8])) -> LedgerResult<()> {
+        let mut index = 0;
+        for id in 0..=self.current_segment_id {
+            let path = self.segment_path(id);
+            if !path.exists() {
+                continue;
+            }
+            Self::scan_segment(id, &path, Some(&mut |p: &[u8]| {
+                f(index, p);
+                index += 1;
+            }))?;
+        }
+        Ok(())
+    }
+
+    /// Checks a sealed segment against its manifest.
+    pub fn validate_manifest(&self, id: u64) -> LedgerResult<SegmentManifest> {
+        let manifest = self.read_manifest(id)?;
+        self.check_segment(id, &manifest, None)?;
+        Ok(manifest)
     }
 
-    fn open_segment(&mut self, id: u64) -> io::Result<()> {
+    fn read_manifest(&self, id: u64) -> LedgerResult<SegmentManifest> {
+        SegmentManifest::decode(id, &std::fs::read(self.manifest_path(id))?)
+    }
+
+    /// Checks segment `id` against a manifest that decoded, pushing its leaves onto `tree`.
+    fn check_segment(&self, id: u64, manifest: &SegmentManifest, tree: Option<&mut MerkleFrontier>) -> LedgerResult<()> {
+        let scan = match tree {
+            Some(tree) => Self::scan_segment(id, &self.segment_path(id), Some(&mut |p: &[u8]| tree.push(leaf_hash(p))))?,
+            None => Self::scan_segment(id, &self.segment_path(id), None)?,
+        };
+        let actual_len = std::fs::metadata(self.segment_path(id))?.len();
+
+        if scan.header.is_none()
+            || manifest.segment_id != id
+            || manifest.byte_length != actual_len
+            || manifest.byte_length != scan.valid_length
+            || manifest.entry_count != scan.entry_count
+            || &manifest.segment_hash != scan.hash.finalize().as_bytes()
+        {
+            return Err(LedgerError::corruption(id, scan.valid_length, "segment does not match its manifest"));
+        }
+        Ok(())
+    }
+
+    /// `OpenMode::Repair` for a sealed segment: truncate a torn tail and rebuild the manifest.
+    fn repair_sealed_segment(&mut self, id: u64, repairs: &mut Vec<String>) -> LedgerResult<SegmentManifest> {
+        let path = self.segment_path(id);
+        let scan = Self::scan_segment(id, &path, Some(&mut |p: &[u8]| self.tree.push(leaf_hash(p))))?;
+        if scan.header.is_none() {
+            return Err(LedgerError::corruption(id, 0, "segment has no readable header; refusing to repair"));
+        }
+        let file_len = std::fs::metadata(&path)?.len();
+        if scan.valid_length != file_len {
+            Self::truncate_segment(&path, scan.valid_length)?;
+            repairs.push(format!("truncated sealed segment {:08x} from {} to {} bytes", id, file_len, scan.valid_length));
+        }
+        let manifest = SegmentManifest {
+            segment_id: id,
+            first_entry: self.entry_count,
+            entry_count: scan.entry_count,
+            byte_length: scan.valid_length,
+            flags: 0,
+            segment_hash: *scan.hash.finalize().as_bytes(),
+        };
+        self.write_manifest(&manifest)?;
+        repairs.push(format!("rebuilt manifest for segment {:08x} (entries {}..{})", id, manifest.first_entry, manifest.first_entry + manifest.entry_count));
+        Ok(manifest)
+    }
+
+    fn truncate_segment(path: &Path, len: u64) -> LedgerResult<()> {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(len)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Opens the active segment, truncating a torn tail first when in `OpenMode::Repair`.
+    fn open_segment_with_repair(&mut self, id: u64, repairs: &mut Vec<String>) -> LedgerResult<()> {
+        let path = self.segment_path(id);
+        if self.mode == OpenMode::Repair && path.exists() {
+            let scan = Self::scan_segment(id, &path, None)?;
+            let file_len = std::fs::metadata(&path)?.len();
+            if scan.header.is_some() && scan.valid_length != file_len {
+                Self::truncate_segment(&path, scan.valid_length)?;
+                repairs.push(format!("truncated torn tail of segment {:08x} from {} to {} bytes", id, file_len, scan.valid_length));
+            }
+        }
+        self.open_segment(id)
+    }
+
+    fn open_segment(&mut self, id: u64) -> LedgerResult<()> {
         let path = self.segment_path(id);
+        if self.mode == OpenMode::ReadOnly {
+            // Nothing is ever created or written; an empty store simply has no active segment.
+            let scan = match Self::scan_segment(id, &path, Some(&mut |p: &[u8]| self.tree.push(leaf_hash(p)))) {
+                Ok(scan) => scan,
+                Err(e) if is_not_found(&e) && id == 0 => {
+                    self.current_segment_id = id;
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            };
+            if scan.header.is_none() || scan.valid_length != std::fs::metadata(&path)?.len() {
+                return Err(LedgerError::corruption(id, scan.valid_length, "torn tail"));
+            }
+            self.current_segment_id = id;
+            self.current_offset = scan.valid_length;
+            self.segment_first_entry = self.entry_count;
+            self.entry_count += scan.entry_count;
+            self.segment_hasher = scan.hash;
+            return Ok(());
+        }
+
         let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
-        let metadata = file.metadata()?;
+
+        // Resuming an existing segment: rebuild the running hash and entry range.
+        let mut scan = Self::scan_segment(id, &path, Some(&mut |p: &[u8]| self.tree.push(leaf_hash(p))))?;
+        let file_len = file.metadata()?.len();
+        if file_len == 0 {
+            let header = SegmentHeader::for_config(&self.config).encode();
+            file.write_all(&header)?;
+            scan.hash.update(&header);
+            scan.valid_length = SEGMENT_HEADER_LEN;
+        } else if scan.header.is_none() || scan.valid_length != file_len {
+            return Err(LedgerError::corruption(id, scan.valid_length, "torn tail"));
+        }
+        file.seek(SeekFrom::End(0))?;
+
         self.current_segment_id = id;
         self.current_file = Some(file);
-        self.current_offset = metadata.len();
+        self.current_offset = scan.valid_length;
+        self.segment_first_entry = self.entry_count;
+        self.entry_count += scan.entry_count;
+        self.segment_hasher = scan.hash;
         Ok(())
     }
 
-    fn roll_segment(&mut self) -> io::Result<()> {
+    fn roll_segment(&mut self) -> LedgerResult<()> {
         if let Some(file) = &mut self.current_file {
             file.sync_all()?;
         }
+        let manifest = SegmentManifest {
+            segment_id: self.current_segment_id,
+            first_entry: self.segment_first_entry,
+            entry_count: self.entry_count - self.segment_first_entry,
+            byte_length: self.current_offset,
+            flags: 0, // segments are currently written uncompressed and unencrypted
+            segment_hash: *self.segment_hasher.finalize().as_bytes(),
+        };
+        self.write_manifest(&manifest)?;
+        self.emit(StoreEvent::SegmentSealed { segment_id: manifest.segment_id });
         self.open_segment(self.current_segment_id + 1)?;
         Ok(())
     }
 
+    /// Seals a segment by writing its manifest (rename-replace, like the checkpoint).
+    fn write_manifest(&self, manifest: &SegmentManifest) -> LedgerResult<()> {
+        let final_path = self.manifest_path(manifest.segment_id);
+        let tmp_path = final_path.with_extension("manifest.tmp");
+        let mut f = File::create(&tmp_path)?;
+        f.write_all(&manifest.encode())?;
+        f.sync_all()?;
+        std::fs::rename(tmp_path, final_path)?;
+        Ok(())
+    }
+
     /// Appends a new Ledger entry deterministically.
     /// The input must already contain the hash of the payload linked to the previous entry log.
-    pub fn append_entry(&mut self, payload: &[u8]) -> io::Result<()> {
+    /// Payloads starting with `entry::TYPED_ENTRY_MAGIC` are refused, so application
+    /// code cannot forge the typed entries that replays trust.
+    pub fn append_entry(&mut self, payload: &[u8]) -> LedgerResult<()> {
+        if payload.starts_with(entry::TYPED_ENTRY_MAGIC) {
+            return Err(LedgerError::ReservedPrefix);
+        }
+        self.append_payload(payload)
+    }
+
+    fn append_payload(&mut self, payload: &[u8]) -> LedgerResult<()> {
+        if self.mode == OpenMode::ReadOnly {
+            return Err(LedgerError::Locked("store was opened read-only".to_string()));
+        }
         let payload_len = payload.len() as u64;
         let entry_size = 8 + payload_len; // 8 bytes for length prefix
 
-        if self.current_offset + entry_size > SEGMENT_SIZE {
+        if SEGMENT_HEADER_LEN + entry_size > self.config.segment_size {
+            return Err(LedgerError::QuotaExceeded {
+                limit: self.config.segment_size - SEGMENT_HEADER_LEN,
+                requested: entry_size,
+            });
+        }
+        if self.current_offset + entry_size > self.config.segment_size {
             self.roll_segment()?;
         }
 
-        let mut file = self.current_file.as_ref().unwrap();
+        let file = self.current_file.as_ref().unwrap();
         // Deterministic write sequence: length prefix followed by payload.
         let mut wfile = file.try_clone()?;
-        wfile.write_all(&(payload_len as u32).to_le_bytes())?;
+        let len_prefix = payload_len.to_le_bytes();
+        wfile.write_all(&len_prefix)?;
         wfile.write_all(payload)?;
-        
+        self.segment_hasher.update(&len_prefix);
+        self.segment_hasher.update(payload);
+        self.tree.push(leaf_hash(payload));
+
         self.current_offset += entry_size;
         self.entry_count += 1;
+        self.emit(StoreEvent::Appended { index: self.entry_count - 1 });
 
-        // Note: fsync is deferred until an explicit flush/commit point 
-        // to batch I/O, maintaining the determinism of write ordering.
+        // Note: unless the sync policy says otherwise, fsync is deferred until an
+        // explicit flush/commit point to batch I/O, maintaining the determinism of write ordering.
+        if self.config.sync_policy == SyncPolicy::EveryEntry {
+            wfile.sync_data()?;
+        }
 
-        if self.entry_count % MERKLE_COMPACTION_INTERVAL == 0 {
+        if self.entry_count.is_multiple_of(self.config.checkpoint_interval) {
             self.compact_merkle_checkpoint()?;
+            self.emit(StoreEvent::Checkpointed { path: self.checkpoint_path(), entry_count: self.entry_count });
         }
         Ok(())
     }
 
+    /// Appends an entry the ledger machinery writes about itself; see `entry`.
+    pub fn append_typed(&mut self, kind: EntryKind, body: &[u8]) -> LedgerResult<()> {
+        self.append_payload(&entry::encode(kind, body))
+    }
+
+    /// Appends `body`, serialized as JSON, as a `kind` entry. The types written this way
+    /// append themselves through it, e.g. `Decision::append_to`.
+    pub fn append_json<T: Serialize>(&mut self, kind: EntryKind, body: &T) -> LedgerResult<()> {
+        let body = serde_json::to_vec(body).map_err(|e| LedgerError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        self.append_typed(kind, &body)
+    }
+
     /// Ensures the deterministic ordering is physically realized on disk.
-    pub fn commit(&mut self) -> io::Result<()> {
+    pub fn commit(&mut self) -> LedgerResult<()> {
+        if self.config.sync_policy == SyncPolicy::OnSegmentRoll {
+            return Ok(());
+        }
         if let Some(file) = &mut self.current_file {
             file.sync_data()?;
         }
         Ok(())
     }
 
-    fn compact_merkle_checkpoint(&self) -> io::Result<()> {
-        // In a real implementation:
-        // 1. Traverse the last 1024 entry hashes.
-        // 2. Compute a deterministic Merkle root.
-        // 3. Write securely to index/merkle.chk using a rename-replace pattern to ensure atomicity.
-        let chk_path = self.base_dir.join("merkle.chk.tmp");
+    /// Writes the current tree head to index/merkle.chk (rename-replace, so readers
+    /// never observe a partial checkpoint).
+    fn compact_merkle_checkpoint(&self) -> LedgerResult<()> {
+        let tick = self.tick_source.as_ref().map_or(self.entry_count, |ticks| ticks.now());
+        let checkpoint = Checkpoint::of(&self.tree, tick);
+        let final_path = self.checkpoint_path();
+        let chk_path = final_path.with_extension("chk.tmp");
         let mut f = File::create(&chk_path)?;
-        f.write_all(b"MERKLE_ROOT_PLACEHOLDER")?;
+        f.write_all(&checkpoint.encode())?;
         f.sync_all()?;
-        std::fs::rename(chk_path, self.base_dir.join("merkle.chk"))?;
+        std::fs::rename(chk_path, final_path)?;
         Ok(())
     }
 }
+
+fn is_not_found(e: &LedgerError) -> bool {
+    matches!(e, LedgerError::Io(io) if io.kind() == io::ErrorKind::NotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory for one test, named after it.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rfsn-storage-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Room for two 16-byte entries per segment, so a third rolls.
+    fn small_config(dir: &Path) -> StoreConfig {
+        StoreConfig::new(dir).segment_size(SEGMENT_HEADER_LEN + 2 * 24)
+    }
+
+    fn fill(config: &StoreConfig, entries: u8) {
+        let mut store = DeterministicStore::open(config.clone()).unwrap();
+        for i in 0..entries {
+            store.append_entry(&[i; 16]).unwrap();
+        }
+        store.commit().unwrap();
+    }
+
+    #[test]
+    fn manifests_seal_segments_and_detect_a_flipped_byte() {
+        let dir = scratch("manifest");
+        let config = small_config(&dir);
+        fill(&config, 5);
+        let store = DeterministicStore::open_with(config.clone(), OpenMode::ReadOnly).unwrap();
+        assert_eq!(store.entry_count(), 5);
+        let manifest = store.validate_manifest(1).unwrap();
+        assert_eq!((manifest.first_entry, manifest.entry_count, manifest.byte_length), (2, 2, SEGMENT_HEADER_LEN + 48));
+        assert_eq!(SegmentManifest::decode(1, &manifest.encode()).unwrap(), manifest);
+        drop(store);
+
+        let path = config.segments_dir().join("log_00000001.manifest");
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[20] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(SegmentManifest::decode(1, &bytes), Err(LedgerError::Corruption { reason, .. }) if reason.contains("checksum")));
+        assert!(DeterministicStore::open(config).is_err_and(|e| e.is_integrity_failure()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_length_prefix_past_any_file_is_a_torn_tail() {
+        let dir = scratch("overlong");
+        let config = StoreConfig::new(&dir);
+        fill(&config, 3);
+        let mut segment = OpenOptions::new().append(true).open(config.segments_dir().join("log_00000000.dat")).unwrap();
+        segment.write_all(&u64::MAX.to_le_bytes()).unwrap();
+        drop(segment);
+
+        let scan = DeterministicStore::scan_segment(0, &config.segments_dir().join("log_00000000.dat"), None).unwrap();
+        assert_eq!((scan.entry_count, scan.valid_length), (3, SEGMENT_HEADER_LEN + 3 * 24));
+        assert!(matches!(DeterministicStore::open(config), Err(LedgerError::Corruption { reason, .. }) if reason == "torn tail"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_changed_config_seals_the_resumed_segment_under_its_own_header() {
+        let dir = scratch("config");
+        fill(&StoreConfig::new(&dir), 2);
+        let config = StoreConfig::new(&dir).checkpoint_interval(4).sync_policy(SyncPolicy::EveryEntry);
+        let mut store = DeterministicStore::open(config.clone()).unwrap();
+        store.append_entry(&[9; 16]).unwrap();
+        assert_eq!(store.entry_count(), 3);
+        assert_eq!(store.validate_manifest(0).unwrap().entry_count, 2);
+        assert_eq!(store.current_header().unwrap(), SegmentHeader::for_config(&config));
+        drop(store);
+
+        let old = DeterministicStore::scan_segment(0, &config.segments_dir().join("log_00000000.dat"), None).unwrap();
+        assert_eq!(old.header, Some(SegmentHeader::for_config(&StoreConfig::new(&dir))));
+        assert!(matches!(StoreConfig::new(&dir).segment_size(8).validate(), Err(LedgerError::InvalidConfig(_))));
+        assert!(matches!(StoreConfig::new(&dir).checkpoint_interval(0).validate(), Err(LedgerError::InvalidConfig(_))));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_changed_config_rewrites_the_header_of_an_empty_segment() {
+        let dir = scratch("config-empty");
+        fill(&StoreConfig::new(&dir), 0);
+        let config = StoreConfig::new(&dir).checkpoint_interval(4);
+        let mut store = DeterministicStore::open(config.clone()).unwrap();
+        assert_eq!(store.current_segment_id, 0);
+        assert_eq!(store.current_header().unwrap(), SegmentHeader::for_config(&config));
+        store.append_entry(&[7; 16]).unwrap();
+        store.commit().unwrap();
+        drop(store);
+
+        let segment = config.segments_dir().join("log_00000000.dat");
+        assert_eq!(std::fs::read(&segment).unwrap()[..SEGMENT_HEADER_LEN as usize], SegmentHeader::for_config(&config).encode());
+        assert!(!config.segments_dir().join("log_00000001.dat").exists());
+        let store = DeterministicStore::open_with(config, OpenMode::ReadOnly).unwrap();
+        assert_eq!(store.entry_count(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn repair_notes(store: &DeterministicStore) -> Vec<String> {
+        let mut notes = Vec::new();
+        store
+            .for_each_entry(|_, payload| {
+                if let Some((EntryKind::Repair, body)) = entry::decode(payload) {
+                    notes.push(String::from_utf8_lossy(body).into_owned());
+                }
+            })
+            .unwrap();
+        notes
+    }
+
+    #[test]
+    fn read_only_opens_write_nothing() {
+        let dir = scratch("read-only");
+        let config = small_config(&dir);
+        fill(&config, 3);
+        let listing = || {
+            let mut names: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|d| d.unwrap().file_name()).collect();
+            names.sort();
+            names
+        };
+        let before = listing();
+        let mut store = DeterministicStore::open_with(config, OpenMode::ReadOnly).unwrap();
+        assert_eq!(store.entry_count(), 3);
+        assert!(matches!(store.append_entry(b"x"), Err(LedgerError::Locked(_))));
+        drop(store);
+        assert_eq!(listing(), before);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn repair_truncates_a_torn_tail_that_strict_refuses() {
+        let dir = scratch("torn-tail");
+        let config = StoreConfig::new(&dir);
+        fill(&config, 2);
+        let path = config.segments_dir().join("log_00000000.dat");
+        let mut segment = OpenOptions::new().append(true).open(&path).unwrap();
+        segment.write_all(&[16, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3]).unwrap();
+        drop(segment);
+
+        assert!(matches!(DeterministicStore::open(config.clone()), Err(LedgerError::Corruption { reason, .. }) if reason == "torn tail"));
+        assert!(DeterministicStore::open_with(config.clone(), OpenMode::ReadOnly).is_err());
+        let store = DeterministicStore::open_with(config, OpenMode::Repair).unwrap();
+        assert_eq!(store.entry_count(), 3);
+        assert_eq!(repair_notes(&store), ["truncated torn tail of segment 00000000 from 91 to 80 bytes"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn repair_rebuilds_only_a_manifest_that_is_missing_or_undecodable() {
+        let dir = scratch("repair");
+        let config = small_config(&dir);
+        fill(&config, 5);
+        std::fs::remove_file(config.segments_dir().join("log_00000000.manifest")).unwrap();
+        std::fs::write(config.segments_dir().join("log_00000001.manifest"), b"garbage").unwrap();
+        assert!(DeterministicStore::open(config.clone()).is_err());
+
+        // Repair notes do not fit the small segments.
+        let config = config.segment_size(DEFAULT_SEGMENT_SIZE);
+        let store = DeterministicStore::open_with(config.clone(), OpenMode::Repair).unwrap();
+        assert_eq!(store.entry_count(), 5 + 2);
+        assert_eq!(repair_notes(&store), ["rebuilt manifest for segment 00000000 (entries 0..2)", "rebuilt manifest for segment 00000001 (entries 2..4)"]);
+        assert_eq!(store.validate_manifest(1).unwrap().first_entry, 2);
+        drop(store);
+        assert_eq!(DeterministicStore::open(config).unwrap().entry_count(), 7);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn repair_refuses_a_segment_its_valid_manifest_disagrees_with() {
+        let dir = scratch("tampered");
+        let config = small_config(&dir);
+        fill(&config, 5);
+        let path = config.segments_dir().join("log_00000000.dat");
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[SEGMENT_HEADER_LEN as usize + 8] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        let manifest = std::fs::read(config.segments_dir().join("log_00000000.manifest")).unwrap();
+
+        assert!(matches!(DeterministicStore::open_with(config.clone(), OpenMode::Repair), Err(LedgerError::Corruption { segment: 0, .. })));
+        assert_eq!(std::fs::read(config.segments_dir().join("log_00000000.manifest")).unwrap(), manifest);
+
+        // A manifest renumbered to another place in the sequence is refused the same way.
+        bytes[SEGMENT_HEADER_LEN as usize + 8] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        let renumbered_path = config.segments_dir().join("log_00000001.manifest");
+        let mut renumbered = SegmentManifest::decode(1, &std::fs::read(&renumbered_path).unwrap()).unwrap();
+        renumbered.first_entry = 7;
+        std::fs::write(&renumbered_path, renumbered.encode()).unwrap();
+        for mode in [OpenMode::Strict, OpenMode::Repair] {
+            assert!(matches!(
+                DeterministicStore::open_with(config.clone(), mode),
+                Err(LedgerError::ChainMismatch { segment: 1, expected_first_entry: 2, found_first_entry: 7 })
+            ));
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_second_writer_is_refused_the_lock() {
+        let dir = scratch("lock");
+        let first = DeterministicStore::new(&dir).unwrap();
+        match DeterministicStore::new(&dir) {
+            Err(e @ LedgerError::Locked(_)) => assert!(!e.is_integrity_failure()),
+            other => panic!("second writer opened: {:?}", other.err()),
+        }
+        assert!(DeterministicStore::open_with(StoreConfig::new(&dir), OpenMode::ReadOnly).is_ok());
+        drop(first);
+        assert!(DeterministicStore::new(&dir).is_ok());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
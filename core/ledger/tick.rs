@@ -0,0 +1,150 @@
+//! Shared tick source.
+//!
+//! Checkpoints, anchor requests, sequencer orders and scheduling deadlines all carry
+//! a tick. They must agree on what a tick is, and replaying the same inputs must
+//! produce the same ticks, so the value comes from a monotonic `TickSource` that the
+//! node owns and hands to every subsystem, never from the wall clock. A source that
+//! ticks at a known rate from a known Unix time can also be calibrated, which is
+//! what `SkewReference::Ticks` needs to check witness timestamps.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::clock_skew::SkewReference;
+use super::outbox::unix_now;
+
+/// A monotonic counter shared by the node's subsystems.
+pub trait TickSource: Send + Sync {
+    /// Current tick; never decreases.
+    fn now(&self) -> u64;
+
+    /// How ticks map to wall time, when they do.
+    fn calibration(&self) -> Option<TickCalibration> {
+        None
+    }
+}
+
+pub type SharedTicks = Arc<dyn TickSource>;
+
+impl<F: Fn() -> u64 + Send + Sync> TickSource for F {
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+/// Tick 0 is `epoch_unix`; ticks advance `ticks_per_second` per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickCalibration {
+    pub epoch_unix: u64,
+    pub ticks_per_second: u64,
+}
+
+impl TickCalibration {
+    pub fn skew_reference(&self) -> SkewReference {
+        SkewReference::Ticks { epoch_unix: self.epoch_unix, ticks_per_second: self.ticks_per_second }
+    }
+}
+
+/// Logical time, advanced explicitly by its owner (e.g. once per control cycle).
+/// Fully deterministic: replays see the same ticks.
+#[derive(Debug, Default)]
+pub struct LogicalTicks {
+    tick: AtomicU64,
+}
+
+impl LogicalTicks {
+    pub fn starting_at(tick: u64) -> Self {
+        Self { tick: AtomicU64::new(tick) }
+    }
+
+    /// Advances by one tick and returns the new value.
+    pub fn advance(&self) -> u64 {
+        self.tick.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Moves to `tick` if that is ahead; used to follow a replayed log.
+    pub fn advance_to(&self, tick: u64) {
+        self.tick.fetch_max(tick, Ordering::SeqCst);
+    }
+}
+
+impl TickSource for LogicalTicks {
+    fn now(&self) -> u64 {
+        self.tick.load(Ordering::SeqCst)
+    }
+}
+
+/// Monotonic clock time since construction at `ticks_per_second`, calibrated to the
+/// Unix time it was created at. Immune to wall-clock steps, but not replay-stable.
+#[derive(Debug, Clone)]
+pub struct MonotonicTicks {
+    origin: Instant,
+    epoch_unix: u64,
+    ticks_per_second: u64,
+}
+
+impl MonotonicTicks {
+    pub fn new(ticks_per_second: u64) -> Self {
+        Self { origin: Instant::now(), epoch_unix: unix_now(), ticks_per_second: ticks_per_second.max(1) }
+    }
+
+    pub fn millis() -> Self {
+        Self::new(1_000)
+    }
+}
+
+impl TickSource for MonotonicTicks {
+    fn now(&self) -> u64 {
+        (self.origin.elapsed().as_nanos() * self.ticks_per_second as u128 / 1_000_000_000) as u64
+    }
+
+    fn calibration(&self) -> Option<TickCalibration> {
+        Some(TickCalibration { epoch_unix: self.epoch_unix, ticks_per_second: self.ticks_per_second })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::merkle::Checkpoint;
+    use crate::ledger::storage::{DeterministicStore, StoreConfig};
+
+    #[test]
+    fn logical_ticks_only_move_forward_and_stamp_the_ledger_checkpoints() {
+        let ticks = Arc::new(LogicalTicks::starting_at(40));
+        assert_eq!((ticks.advance(), ticks.now()), (41, 41));
+        ticks.advance_to(39);
+        assert_eq!(ticks.now(), 41);
+        ticks.advance_to(42);
+
+        let dir = std::env::temp_dir().join(format!("rfsn-tick-checkpoint-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut store = DeterministicStore::open(StoreConfig::new(&dir).checkpoint_interval(2)).unwrap();
+        store.set_tick_source(ticks.clone());
+        store.append_entry(b"one").unwrap();
+        store.append_entry(b"two").unwrap();
+        let checkpoint = Checkpoint::decode(&std::fs::read(store.checkpoint_path()).unwrap()).unwrap();
+        assert_eq!((checkpoint.tree_size, checkpoint.tick), (2, 42));
+        drop(store);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn only_calibrated_sources_give_a_tick_based_skew_reference() {
+        assert_eq!(SkewReference::from_ticks(&LogicalTicks::default()), SkewReference::WallClock);
+        assert_eq!(SkewReference::from_ticks(&|| 7u64), SkewReference::WallClock);
+
+        let monotonic = MonotonicTicks::millis();
+        let calibration = monotonic.calibration().unwrap();
+        assert_eq!(calibration.ticks_per_second, 1_000);
+        assert!(calibration.epoch_unix.abs_diff(unix_now()) <= 1);
+        assert_eq!(
+            SkewReference::from_ticks(&monotonic),
+            SkewReference::Ticks { epoch_unix: calibration.epoch_unix, ticks_per_second: 1_000 }
+        );
+        let first = monotonic.now();
+        assert!(monotonic.now() >= first);
+    }
+}
@@ -0,0 +1,289 @@
+//! Witness directory: ordered witnesses with health checks, failover and rate limits.
+//!
+//! A plain `WitnessSet` submits every anchor to every witness. A directory instead
+//! tries witnesses in priority order and stops once the threshold is met, so the
+//! secondaries only see traffic when a primary fails. A witness that fails
+//! `failures_before_down` times in a row is marked down and skipped until
+//! `retry_after` has passed and a health check succeeds; down witnesses are still
+//! tried as a last resort before a round is given up. Per-witness rate limits keep
+//! us within what each timestamping authority allows.
+//!
+//! ```json
+//! { "threshold": 1,
+//!   "witnesses": [
+//!     { "kind": "rfc3161", "priority": 0, "url": "...", "tsa_public_key_der": "...",
+//!       "rate_limit": { "max_requests": 60, "per_secs": 3600 } },
+//!     { "kind": "http-json", "priority": 1, "url": "...", "keys": { "keys": [] } } ] }
+//! ```
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::notarize::{NotaryBackend, NotaryClient, WitnessSpec};
+use super::notary_http::HttpClientConfig;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub max_requests: u32,
+    pub per_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct HealthPolicy {
+    /// Consecutive failures after which a witness is marked down.
+    pub failures_before_down: u32,
+    /// How long a down witness is skipped before it is health-checked again.
+    pub retry_after_secs: u64,
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        Self { failures_before_down: 3, retry_after_secs: 60 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DirectoryEntry {
+    /// Lower is tried first; ties keep file order.
+    #[serde(default)]
+    pub priority: u32,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    #[serde(flatten)]
+    pub spec: WitnessSpec,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WitnessDirectory {
+    pub threshold: usize,
+    pub witnesses: Vec<DirectoryEntry>,
+    #[serde(default)]
+    pub health: HealthPolicy,
+}
+
+impl WitnessDirectory {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn into_client(mut self, http: &HttpClientConfig) -> Result<NotaryClient, Box<dyn Error>> {
+        self.witnesses.sort_by_key(|w| w.priority);
+        let slots = self.witnesses.iter().map(|w| Slot::new(w.rate_limit)).collect();
+        let configs = self.witnesses.into_iter().map(|w| w.spec.into_config(http)).collect();
+        Ok(NotaryClient::quorum(configs, self.threshold)?.failover(Failover { slots, health: self.health }))
+    }
+}
+
+/// Operational view of one directory witness.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WitnessHealth {
+    pub backend: String,
+    pub up: bool,
+    pub consecutive_failures: u32,
+    /// Requests left in the current rate-limit window; `None` without a limit.
+    pub remaining_requests: Option<u32>,
+}
+
+#[derive(Debug)]
+struct SlotState {
+    consecutive_failures: u32,
+    down_since: Option<Instant>,
+    window_start: Instant,
+    window_requests: u32,
+}
+
+#[derive(Debug)]
+pub(crate) struct Slot {
+    rate_limit: Option<RateLimit>,
+    state: Mutex<SlotState>,
+}
+
+impl Slot {
+    fn new(rate_limit: Option<RateLimit>) -> Self {
+        let state = SlotState { consecutive_failures: 0, down_since: None, window_start: Instant::now(), window_requests: 0 };
+        Self { rate_limit, state: Mutex::new(state) }
+    }
+}
+
+/// Why a witness was not asked in a round.
+pub(crate) enum Skip {
+    Down,
+    RateLimited,
+}
+
+/// Per-backend state of a directory-built `NotaryClient`, parallel to its backends.
+#[derive(Debug)]
+pub(crate) struct Failover {
+    slots: Vec<Slot>,
+    health: HealthPolicy,
+}
+
+impl Failover {
+    /// Reserves a request slot for backend `i`, or says why it should be skipped.
+    pub(crate) fn acquire(&self, i: usize, now: Instant) -> Result<(), Skip> {
+        let slot = &self.slots[i];
+        let mut state = slot.state.lock().unwrap();
+        if let Some(limit) = slot.rate_limit {
+            if now.duration_since(state.window_start) >= Duration::from_secs(limit.per_secs) {
+                state.window_start = now;
+                state.window_requests = 0;
+            }
+            if state.window_requests >= limit.max_requests {
+                return Err(Skip::RateLimited);
+            }
+        }
+        if state.down_since.is_some() {
+            return Err(Skip::Down);
+        }
+        state.window_requests += 1;
+        Ok(())
+    }
+
+    /// Last-resort attempt at a down witness; still subject to its rate limit.
+    pub(crate) fn acquire_down(&self, i: usize) -> bool {
+        let slot = &self.slots[i];
+        let mut state = slot.state.lock().unwrap();
+        if slot.rate_limit.is_some_and(|limit| state.window_requests >= limit.max_requests) {
+            return false;
+        }
+        state.window_requests += 1;
+        true
+    }
+
+    pub(crate) fn record(&self, i: usize, ok: bool, now: Instant) {
+        let mut state = self.slots[i].state.lock().unwrap();
+        if ok {
+            state.consecutive_failures = 0;
+            state.down_since = None;
+            return;
+        }
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.health.failures_before_down && state.down_since.is_none() {
+            state.down_since = Some(now);
+        }
+    }
+
+    /// Backends that are down and due for a health check.
+    pub(crate) fn due_for_probe(&self, now: Instant) -> Vec<usize> {
+        let retry = Duration::from_secs(self.health.retry_after_secs);
+        (0..self.slots.len())
+            .filter(|i| self.slots[*i].state.lock().unwrap().down_since.is_some_and(|since| now.duration_since(since) >= retry))
+            .collect()
+    }
+
+    /// Restarts the down period of a witness whose health check failed.
+    pub(crate) fn still_down(&self, i: usize, now: Instant) {
+        self.slots[i].state.lock().unwrap().down_since = Some(now);
+    }
+
+    pub(crate) fn health<'a>(&self, backends: impl Iterator<Item = &'a dyn NotaryBackend>) -> Vec<WitnessHealth> {
+        let now = Instant::now();
+        backends
+            .zip(&self.slots)
+            .map(|(backend, slot)| {
+                let state = slot.state.lock().unwrap();
+                let remaining_requests = slot.rate_limit.map(|limit| {
+                    if now.duration_since(state.window_start) >= Duration::from_secs(limit.per_secs) {
+                        limit.max_requests
+                    } else {
+                        limit.max_requests.saturating_sub(state.window_requests)
+                    }
+                });
+                WitnessHealth {
+                    backend: backend.name().to_string(),
+                    up: state.down_since.is_none(),
+                    consecutive_failures: state.consecutive_failures,
+                    remaining_requests,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use crate::ledger::local_witness::LocalWitness;
+    use crate::ledger::notarize::{AnchorRequest, Receipt};
+
+    /// A local witness that can be taken offline.
+    #[derive(Clone)]
+    struct Switchable {
+        witness: LocalWitness,
+        up: Arc<AtomicBool>,
+        calls: Arc<AtomicU32>,
+    }
+
+    impl NotaryBackend for Switchable {
+        fn name(&self) -> &str {
+            "switchable"
+        }
+
+        fn submit(&self, req: &AnchorRequest) -> Result<Receipt, Box<dyn Error>> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            self.health_check()?;
+            NotaryBackend::submit(&self.witness, req)
+        }
+
+        fn fetch_receipt(&self, receipt_id: &str) -> Result<Receipt, Box<dyn Error>> {
+            NotaryBackend::fetch_receipt(&self.witness, receipt_id)
+        }
+
+        fn verify_receipt(&self, req: &AnchorRequest, receipt: &Receipt) -> Result<(), Box<dyn Error>> {
+            NotaryBackend::verify_receipt(&self.witness, req, receipt)
+        }
+
+        fn health_check(&self) -> Result<(), Box<dyn Error>> {
+            if self.up.load(Ordering::Relaxed) { Ok(()) } else { Err("offline".into()) }
+        }
+    }
+
+    #[test]
+    fn secondaries_take_over_while_the_primary_is_down_within_their_rate_limit() {
+        let primary = Switchable {
+            witness: LocalWitness::from_seed("primary", [1; 32]),
+            up: Arc::new(AtomicBool::new(true)),
+            calls: Arc::new(AtomicU32::new(0)),
+        };
+        let secondary = LocalWitness::from_seed("secondary", [2; 32]);
+        let slots = vec![Slot::new(None), Slot::new(Some(RateLimit { max_requests: 2, per_secs: 3600 }))];
+        let health = HealthPolicy { failures_before_down: 1, retry_after_secs: 0 };
+        let client = NotaryClient::quorum_of(vec![Box::new(primary.clone()), Box::new(secondary.clone())], 1)
+            .unwrap()
+            .failover(Failover { slots, health });
+        let req = AnchorRequest { ledger_head_hash: "78".repeat(32), index: 1, timestamp_ticks: 1 };
+        let calls = || primary.calls.load(Ordering::Relaxed);
+
+        assert!(client.collect_receipts(&req).anchored());
+        assert_eq!((calls(), secondary.issued()), (1, 0));
+
+        primary.up.store(false, Ordering::Relaxed);
+        assert!(client.collect_receipts(&req).anchored());
+        assert!(client.collect_receipts(&req).anchored());
+        assert_eq!((calls(), secondary.issued()), (2, 2));
+        let status = client.witness_health().unwrap();
+        assert_eq!((status[0].up, status[0].consecutive_failures, status[1].remaining_requests), (false, 1, Some(0)));
+
+        // Secondary exhausted: the down primary is the last resort, and is still down.
+        let status = client.collect_receipts(&req);
+        assert!(!status.anchored());
+        assert_eq!(calls(), 3);
+        assert!(status.failures.iter().any(|(_, e)| e == "rate limited"));
+
+        client.check_health();
+        assert!(!client.witness_health().unwrap()[0].up);
+        primary.up.store(true, Ordering::Relaxed);
+        client.check_health();
+        assert!(client.witness_health().unwrap()[0].up);
+        assert!(client.collect_receipts(&req).anchored());
+        assert_eq!(primary.witness.issued(), 2);
+    }
+}
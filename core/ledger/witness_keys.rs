@@ -0,0 +1,306 @@
+//! Pinned witness public keys.
+//!
+//! Receipts are only accepted if their signature verifies under a key that was
+//! configured ahead of time. Several algorithms are supported so a witness can
+//! move to a different scheme without a code change on our side.
+//!
+//! Each key carries a validity window checked against the receipt's witness
+//! timestamp. A rotation closes the old key's window at the end of an overlap period
+//! and opens the new one, so receipts signed during the changeover verify under
+//! either key and historical receipts keep verifying under the key that signed them.
+//! Pins, rotations and revocations are recorded as `EntryKind::WitnessKey` ledger
+//! entries; `WitnessKeyring::from_ledger` rebuilds the keyring from them, so a key
+//! cannot be swapped without leaving a trace under every later Merkle root.
+
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use super::entry::{self, EntryKind, KeyPinEntry};
+use super::notarize::{AnchorRequest, NotaryError};
+use super::storage::{DeterministicStore, LedgerError, LedgerResult};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureAlgorithm {
+    /// `public_key` is the raw 32-byte key; signature is 64 bytes.
+    Ed25519,
+    /// `public_key` is a DER SubjectPublicKeyInfo; signature is DER-encoded.
+    EcdsaP256Sha256,
+    /// `public_key` is a DER SubjectPublicKeyInfo; PKCS#1 v1.5 signature.
+    RsaPkcs1Sha256,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WitnessKey {
+    pub key_id: String,
+    pub algorithm: SignatureAlgorithm,
+    #[serde(with = "hex::serde")]
+    pub public_key: Vec<u8>,
+    /// First witness timestamp (Unix seconds) accepted under this key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_from: Option<u64>,
+    /// Witness timestamps at or after this are rejected; set when the key is rotated out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<u64>,
+    /// Set when the key is known or suspected to be compromised. Receipts timestamped
+    /// at or after this are rejected. A compromised key can backdate receipts, so put
+    /// this at the earliest possible compromise, not at the time it was noticed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<u64>,
+}
+
+impl WitnessKey {
+    /// A key with no validity bounds.
+    pub fn new(key_id: &str, algorithm: SignatureAlgorithm, public_key: Vec<u8>) -> Self {
+        Self { key_id: key_id.to_string(), algorithm, public_key, valid_from: None, valid_until: None, revoked_at: None }
+    }
+
+    pub fn valid_from(mut self, unix: u64) -> Self {
+        self.valid_from = Some(unix);
+        self
+    }
+
+    pub fn valid_until(mut self, unix: u64) -> Self {
+        self.valid_until = Some(unix);
+        self
+    }
+
+    /// Whether a receipt witnessed at `unix` may be signed by this key.
+    pub fn valid_at(&self, unix: u64) -> bool {
+        self.valid_from.is_none_or(|from| unix >= from)
+            && self.valid_until.is_none_or(|until| unix < until)
+            && self.revoked_at.is_none_or(|revoked| unix < revoked)
+    }
+}
+
+/// A change to a witness keyring, as recorded in the ledger.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum KeyEvent {
+    /// A key is pinned (or re-pinned with a new window).
+    Pinned { key: WitnessKey },
+    /// A key is rotated out: it stays valid for receipts before `valid_until`.
+    Retired { key_id: String, valid_until: u64 },
+    /// A key is compromised: receipts from `revoked_at` on are rejected.
+    Revoked { key_id: String, revoked_at: u64 },
+}
+
+/// The set of keys a given witness may sign with.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct WitnessKeyring {
+    pub keys: Vec<WitnessKey>,
+}
+
+impl WitnessKeyring {
+    pub fn new(keys: Vec<WitnessKey>) -> Self {
+        Self { keys }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Verifies `signature` over `message` for a receipt witnessed at `witnessed_at`.
+    /// With a `key_id` only that key is tried; without one, any pinned key may match.
+    /// Keys whose validity window does not contain `witnessed_at` are never tried.
+    pub fn verify(&self, backend: &str, key_id: Option<&str>, witnessed_at: u64, message: &[u8], signature: &[u8]) -> Result<&WitnessKey, NotaryError> {
+        let mut pinned = self.keys.iter().filter(|k| key_id.is_none_or(|id| k.key_id == id)).peekable();
+        if pinned.peek().is_none() {
+            return Err(NotaryError::UnknownWitnessKey {
+                backend: backend.to_string(),
+                key_id: key_id.map(str::to_string),
+            });
+        }
+        let mut candidates = pinned.filter(|k| k.valid_at(witnessed_at)).peekable();
+        if candidates.peek().is_none() {
+            return Err(NotaryError::KeyNotValid {
+                backend: backend.to_string(),
+                key_id: key_id.map(str::to_string),
+                witnessed_at,
+            });
+        }
+        candidates
+            .find(|k| verify_signature(k.algorithm, &k.public_key, message, signature).is_ok())
+            .ok_or_else(|| NotaryError::InvalidSignature {
+                backend: backend.to_string(),
+                key_id: key_id.map(str::to_string),
+            })
+    }
+
+    /// Applies a recorded change. Retiring or revoking a key that is not pinned is a
+    /// no-op. Re-pinning a key replaces its window but never lifts a revocation.
+    pub fn apply(&mut self, event: &KeyEvent) {
+        match event {
+            KeyEvent::Pinned { key } => {
+                let mut key = key.clone();
+                if let Some(old) = self.keys.iter().position(|k| k.key_id == key.key_id) {
+                    let old = self.keys.remove(old);
+                    key.revoked_at = match (old.revoked_at, key.revoked_at) {
+                        (Some(a), Some(b)) => Some(a.min(b)),
+                        (a, b) => a.or(b),
+                    };
+                }
+                self.keys.push(key);
+            }
+            KeyEvent::Retired { key_id, valid_until } => {
+                for k in self.keys.iter_mut().filter(|k| &k.key_id == key_id) {
+                    k.valid_until = Some(k.valid_until.map_or(*valid_until, |u| u.min(*valid_until)));
+                }
+            }
+            KeyEvent::Revoked { key_id, revoked_at } => {
+                for k in self.keys.iter_mut().filter(|k| &k.key_id == key_id) {
+                    k.revoked_at = Some(k.revoked_at.map_or(*revoked_at, |r| r.min(*revoked_at)));
+                }
+            }
+        }
+    }
+
+    /// Planned rotation to `new_key`: every currently open-ended key is retired at
+    /// `overlap_until`, so both old and new keys verify receipts until then. Returns the
+    /// events to record with `KeyPinEntry::append_to`.
+    pub fn rotate(&mut self, new_key: WitnessKey, overlap_until: u64) -> Vec<KeyEvent> {
+        let mut events: Vec<KeyEvent> = self
+            .keys
+            .iter()
+            .filter(|k| k.key_id != new_key.key_id && k.valid_until.is_none() && k.revoked_at.is_none())
+            .map(|k| KeyEvent::Retired { key_id: k.key_id.clone(), valid_until: overlap_until })
+            .collect();
+        events.insert(0, KeyEvent::Pinned { key: new_key });
+        for event in &events {
+            self.apply(event);
+        }
+        events
+    }
+
+    /// Rebuilds the keyring of `witness` by replaying its `WitnessKey` ledger entries
+    /// in order.
+    pub fn from_ledger(store: &DeterministicStore, witness: &str) -> LedgerResult<Self> {
+        let mut keyring = Self::default();
+        let mut unreadable = None;
+        store.for_each_entry(|index, payload| {
+            let Some((EntryKind::WitnessKey, body)) = entry::decode(payload) else { return };
+            match serde_json::from_slice::<KeyPinEntry>(body) {
+                Ok(pin) if pin.witness == witness => keyring.apply(&pin.event),
+                Ok(_) => {}
+                Err(_) => unreadable = unreadable.or(Some(index)),
+            }
+        })?;
+        if let Some(index) = unreadable {
+            // Skipping a revocation would widen what we accept, so fail closed.
+            return Err(LedgerError::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unreadable witness key entry at index {}", index),
+            )));
+        }
+        Ok(keyring)
+    }
+}
+
+/// Canonical bytes a JSON witness signs for a receipt.
+pub fn receipt_signing_payload(receipt_id: &str, request: &AnchorRequest, external_timestamp: u64) -> Vec<u8> {
+    let mut out = b"RFSN-RECEIPT-V1\0".to_vec();
+    for field in [receipt_id.as_bytes(), request.ledger_head_hash.as_bytes()] {
+        out.extend_from_slice(&(field.len() as u64).to_le_bytes());
+        out.extend_from_slice(field);
+    }
+    out.extend_from_slice(&request.index.to_le_bytes());
+    out.extend_from_slice(&request.timestamp_ticks.to_le_bytes());
+    out.extend_from_slice(&external_timestamp.to_le_bytes());
+    out
+}
+
+pub fn verify_signature(algorithm: SignatureAlgorithm, public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), NotaryError> {
+    use p256::pkcs8::DecodePublicKey;
+    use signature::Verifier;
+
+    let malformed = |what: &str| NotaryError::Malformed(format!("{:?} {}", algorithm, what));
+    match algorithm {
+        SignatureAlgorithm::Ed25519 => {
+            let key: [u8; 32] = public_key.try_into().map_err(|_| malformed("public key"))?;
+            let key = ed25519_dalek::VerifyingKey::from_bytes(&key).map_err(|_| malformed("public key"))?;
+            let sig = ed25519_dalek::Signature::from_slice(signature).map_err(|_| malformed("signature"))?;
+            key.verify_strict(message, &sig).map_err(|_| NotaryError::BadSignature)
+        }
+        SignatureAlgorithm::EcdsaP256Sha256 => {
+            let key = p256::ecdsa::VerifyingKey::from_public_key_der(public_key).map_err(|_| malformed("public key"))?;
+            let sig = p256::ecdsa::Signature::from_der(signature).map_err(|_| malformed("signature"))?;
+            key.verify(message, &sig).map_err(|_| NotaryError::BadSignature)
+        }
+        SignatureAlgorithm::RsaPkcs1Sha256 => {
+            let key = rsa::RsaPublicKey::from_public_key_der(public_key).map_err(|_| malformed("public key"))?;
+            let key = rsa::pkcs1v15::VerifyingKey::<sha2::Sha256>::new(key);
+            let sig = rsa::pkcs1v15::Signature::try_from(signature).map_err(|_| malformed("signature"))?;
+            key.verify(message, &sig).map_err(|_| NotaryError::BadSignature)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn ed25519_key(key_id: &str, signer: &SigningKey) -> WitnessKey {
+        WitnessKey::new(key_id, SignatureAlgorithm::Ed25519, signer.verifying_key().to_bytes().to_vec())
+    }
+
+    #[test]
+    fn only_pinned_keys_verify_receipts() {
+        let pinned = SigningKey::from_bytes(&[1; 32]);
+        let stranger = SigningKey::from_bytes(&[2; 32]);
+        let keyring = WitnessKeyring::new(vec![ed25519_key("k1", &pinned)]);
+        let message = b"head";
+
+        let signature = pinned.sign(message).to_bytes();
+        assert_eq!(keyring.verify("json", Some("k1"), 0, message, &signature).unwrap().key_id, "k1");
+        assert_eq!(keyring.verify("json", None, 0, message, &signature).unwrap().key_id, "k1");
+        assert!(matches!(keyring.verify("json", Some("k1"), 0, b"other head", &signature), Err(NotaryError::InvalidSignature { .. })));
+
+        let forged = stranger.sign(message).to_bytes();
+        assert!(matches!(keyring.verify("json", None, 0, message, &forged), Err(NotaryError::InvalidSignature { .. })));
+        assert!(matches!(keyring.verify("json", Some("k2"), 0, message, &forged), Err(NotaryError::UnknownWitnessKey { .. })));
+        assert!(matches!(
+            verify_signature(SignatureAlgorithm::Ed25519, &[0; 31], message, &signature),
+            Err(NotaryError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn rotations_overlap_and_revocations_replay_from_the_ledger() {
+        let old = SigningKey::from_bytes(&[1; 32]);
+        let new = SigningKey::from_bytes(&[2; 32]);
+        let mut keyring = WitnessKeyring::new(vec![ed25519_key("old", &old)]);
+        let events = keyring.rotate(ed25519_key("new", &new).valid_from(100), 200);
+        assert_eq!(events.len(), 2);
+
+        let message = b"head";
+        let by_old = old.sign(message).to_bytes();
+        let by_new = new.sign(message).to_bytes();
+        assert!(keyring.verify("json", Some("old"), 150, message, &by_old).is_ok());
+        assert!(keyring.verify("json", Some("new"), 150, message, &by_new).is_ok());
+        assert!(matches!(keyring.verify("json", Some("old"), 200, message, &by_old), Err(NotaryError::KeyNotValid { witnessed_at: 200, .. })));
+        assert!(matches!(keyring.verify("json", Some("new"), 99, message, &by_new), Err(NotaryError::KeyNotValid { .. })));
+
+        let dir = std::env::temp_dir().join(format!("rfsn-witness-keys-replay-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut store = DeterministicStore::new(&dir).unwrap();
+        let pin = |event: KeyEvent| KeyPinEntry { witness: "https://witness.example".to_string(), event };
+        pin(KeyEvent::Pinned { key: ed25519_key("old", &old) }).append_to(&mut store).unwrap();
+        for event in events {
+            pin(event).append_to(&mut store).unwrap();
+        }
+        pin(KeyEvent::Revoked { key_id: "new".to_string(), revoked_at: 300 }).append_to(&mut store).unwrap();
+        // Re-pinning a revoked key does not lift the revocation.
+        pin(KeyEvent::Pinned { key: ed25519_key("new", &new) }).append_to(&mut store).unwrap();
+        KeyPinEntry { witness: "elsewhere".to_string(), event: KeyEvent::Revoked { key_id: "old".to_string(), revoked_at: 0 } }
+            .append_to(&mut store)
+            .unwrap();
+
+        let replayed = WitnessKeyring::from_ledger(&store, "https://witness.example").unwrap();
+        assert!(replayed.verify("json", Some("old"), 150, message, &by_old).is_ok());
+        assert!(replayed.verify("json", Some("new"), 299, message, &by_new).is_ok());
+        assert!(matches!(replayed.verify("json", Some("new"), 300, message, &by_new), Err(NotaryError::KeyNotValid { .. })));
+        drop(store);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,133 @@
+//! Policy bytecode encoding.
+//!
+//! Instructions are fixed-width: one opcode byte followed by a 24-bit little-endian
+//! operand, so instruction `pc` starts at byte `pc * INSTR_LEN` and the interpreter
+//! decodes in place without building a program in memory. Jump operands are
+//! instruction indices and must point forward; a program therefore runs at most once
+//! through each instruction.
+
+use crate::wcet::static_bound::{AnalysisError, Decoder, Flow, Instr};
+
+pub const INSTR_LEN: usize = 4;
+
+/// Largest operand value.
+pub const MAX_OPERAND: u32 = (1 << 24) - 1;
+
+/// Pushes the operand.
+pub const PUSH: u8 = 0x01;
+pub const POP: u8 = 0x02;
+pub const DUP: u8 = 0x03;
+pub const SWAP: u8 = 0x04;
+
+/// Binary operators pop `b` then `a` and push `a op b`. Arithmetic wraps.
+pub const ADD: u8 = 0x10;
+pub const SUB: u8 = 0x11;
+pub const MUL: u8 = 0x12;
+pub const AND: u8 = 0x13;
+pub const OR: u8 = 0x14;
+pub const XOR: u8 = 0x15;
+/// Logical not: 1 if the top is 0, else 0.
+pub const NOT: u8 = 0x16;
+
+pub const EQ: u8 = 0x20;
+pub const LT: u8 = 0x21;
+pub const GT: u8 = 0x22;
+
+/// Pushes the context byte at the operand offset.
+pub const LOAD8: u8 = 0x30;
+/// Pushes the little-endian u32 of the context at the operand offset.
+pub const LOAD32: u8 = 0x31;
+pub const CTXLEN: u8 = 0x32;
+
+/// Jumps to the operand instruction.
+pub const JMP: u8 = 0x40;
+/// Pops; jumps to the operand instruction if the value is 0.
+pub const JZ: u8 = 0x41;
+
+pub const ALLOW: u8 = 0x50;
+/// Denies with the operand as reason code.
+pub const DENY: u8 = 0x51;
+/// Leaves the decision to human approvers, with the operand as reason code.
+pub const ESCALATE: u8 = 0x52;
+/// Pops; allows if the value is nonzero, else denies with the operand as reason code.
+/// Lets a policy decide on a secret without branching on it.
+pub const DECIDE: u8 = 0x53;
+
+/// Every opcode the VM executes; anything else is malformed.
+pub const OPCODES: [u8; 23] = [
+    PUSH, POP, DUP, SWAP, ADD, SUB, MUL, AND, OR, XOR, NOT, EQ, LT, GT, LOAD8, LOAD32, CTXLEN, JMP, JZ, ALLOW, DENY, ESCALATE, DECIDE,
+];
+
+/// Assembler name of `opcode`, if the VM executes it.
+pub fn mnemonic(opcode: u8) -> Option<&'static str> {
+    let name = match opcode {
+        PUSH => "PUSH",
+        POP => "POP",
+        DUP => "DUP",
+        SWAP => "SWAP",
+        ADD => "ADD",
+        SUB => "SUB",
+        MUL => "MUL",
+        AND => "AND",
+        OR => "OR",
+        XOR => "XOR",
+        NOT => "NOT",
+        EQ => "EQ",
+        LT => "LT",
+        GT => "GT",
+        LOAD8 => "LOAD8",
+        LOAD32 => "LOAD32",
+        CTXLEN => "CTXLEN",
+        JMP => "JMP",
+        JZ => "JZ",
+        ALLOW => "ALLOW",
+        DENY => "DENY",
+        ESCALATE => "ESCALATE",
+        DECIDE => "DECIDE",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Instruction `pc` of `program` as `(opcode, operand)`, or `None` past the end.
+pub fn fetch(program: &[u8], pc: usize) -> Option<(u8, u32)> {
+    let bytes = program.get(pc * INSTR_LEN..pc * INSTR_LEN + INSTR_LEN)?;
+    Some((bytes[0], u32::from_le_bytes([bytes[1], bytes[2], bytes[3], 0])))
+}
+
+/// Encodes one instruction; the operand is truncated to 24 bits.
+pub fn encode(opcode: u8, operand: u32) -> [u8; INSTR_LEN] {
+    let arg = (operand & MAX_OPERAND).to_le_bytes();
+    [opcode, arg[0], arg[1], arg[2]]
+}
+
+/// Control flow of an opcode, in the analyzer's terms.
+pub fn flow(opcode: u8, operand: u32) -> Flow {
+    match opcode {
+        JMP => Flow::Jump(operand as usize),
+        JZ => Flow::Branch(operand as usize),
+        ALLOW | DENY | ESCALATE | DECIDE => Flow::Halt,
+        _ => Flow::Next,
+    }
+}
+
+/// The VM's `Decoder` for static WCET analysis.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PolicyDecoder;
+
+impl Decoder for PolicyDecoder {
+    fn decode(&self, bytecode: &[u8]) -> Result<Vec<Instr>, AnalysisError> {
+        if !bytecode.len().is_multiple_of(INSTR_LEN) {
+            return Err(AnalysisError::Decode(format!("length {} is not a multiple of {}", bytecode.len(), INSTR_LEN)));
+        }
+        (0..bytecode.len() / INSTR_LEN)
+            .map(|pc| {
+                let (opcode, operand) = fetch(bytecode, pc).expect("in range");
+                if !OPCODES.contains(&opcode) {
+                    return Err(AnalysisError::Decode(format!("unknown opcode {:#04x} at pc {}", opcode, pc)));
+                }
+                Ok(Instr { opcode, flow: flow(opcode, operand) })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,182 @@
+//! Which parts of a policy its evaluations have exercised.
+//!
+//! Every evaluation the Gate records carries its `Trace`: the outcome of each `JZ` it
+//! evaluated and the instruction that decided. Folding those traces over a policy's
+//! bytecode shows its rules (each `ALLOW`, `DENY`, `ESCALATE` and `DECIDE`) that have
+//! never decided anything and the branch outcomes never taken. A rule that never
+//! fires in production is either dead or guards a case nothing has tested yet, and a
+//! deny or escalate rule in that state is a guard whose behaviour is unknown. A
+//! `DECIDE` counts as fired whichever way it decided.
+
+use std::fmt;
+
+use super::bytecode::{fetch, mnemonic, ALLOW, DECIDE, DENY, ESCALATE, JZ};
+use super::interp::Trace;
+
+/// What a rule decides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleKind {
+    Allow,
+    Deny { reason: u16 },
+    Escalate { reason: u16 },
+    /// `DECIDE`: allows, or denies with `reason`, on a computed value.
+    Decide { reason: u16 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleCoverage {
+    pub pc: usize,
+    pub kind: RuleKind,
+    /// Evaluations this rule decided.
+    pub fired: u64,
+}
+
+impl RuleCoverage {
+    /// Whether the rule can refuse a proposal.
+    pub fn refuses(&self) -> bool {
+        !matches!(self.kind, RuleKind::Allow)
+    }
+}
+
+/// How often a `JZ` fell through (its value held) and how often it jumped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchCoverage {
+    pub pc: usize,
+    pub held: u64,
+    pub jumped: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    pub evaluations: u64,
+    /// Evaluations that faulted, at an instruction other than a rule.
+    pub faults: u64,
+    /// Evaluations cut off by their watchdog or that ran off the end.
+    pub undecided: u64,
+    pub rules: Vec<RuleCoverage>,
+    pub branches: Vec<BranchCoverage>,
+}
+
+impl Coverage {
+    /// Nothing covered yet of `policy`'s rules and branches.
+    pub fn new(policy: &[u8]) -> Self {
+        let mut rules = Vec::new();
+        let mut branches = Vec::new();
+        for pc in 0.. {
+            let Some((opcode, operand)) = fetch(policy, pc) else { break };
+            let reason = operand as u16;
+            let kind = match opcode {
+                JZ => {
+                    branches.push(BranchCoverage { pc, held: 0, jumped: 0 });
+                    continue;
+                }
+                ALLOW => RuleKind::Allow,
+                DENY => RuleKind::Deny { reason },
+                ESCALATE => RuleKind::Escalate { reason },
+                DECIDE => RuleKind::Decide { reason },
+                _ => continue,
+            };
+            rules.push(RuleCoverage { pc, kind, fired: 0 });
+        }
+        Self { evaluations: 0, faults: 0, undecided: 0, rules, branches }
+    }
+
+    /// Counts one evaluation of the policy.
+    pub fn record(&mut self, trace: &Trace) {
+        self.evaluations += 1;
+        for condition in &trace.conditions {
+            if let Some(branch) = self.branches.iter_mut().find(|b| b.pc == condition.pc) {
+                match condition.held {
+                    true => branch.held += 1,
+                    false => branch.jumped += 1,
+                }
+            }
+        }
+        match trace.decided_at {
+            None => self.undecided += 1,
+            Some(pc) => match self.rules.iter_mut().find(|r| r.pc == pc) {
+                Some(rule) => rule.fired += 1,
+                None => self.faults += 1,
+            },
+        }
+    }
+
+    /// Rules that have never decided an evaluation.
+    pub fn dead_rules(&self) -> impl Iterator<Item = &RuleCoverage> {
+        self.rules.iter().filter(|r| r.fired == 0)
+    }
+
+    /// Deny, escalate and `DECIDE` rules that have never decided an evaluation.
+    pub fn untested_refusals(&self) -> impl Iterator<Item = &RuleCoverage> {
+        self.dead_rules().filter(|r| r.refuses())
+    }
+
+    /// Branches with an outcome never taken.
+    pub fn partial_branches(&self) -> impl Iterator<Item = &BranchCoverage> {
+        self.branches.iter().filter(|b| b.held == 0 || b.jumped == 0)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.dead_rules().next().is_none() && self.partial_branches().next().is_none()
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fired = self.rules.len() - self.dead_rules().count();
+        let taken: usize = self.branches.iter().map(|b| (b.held > 0) as usize + (b.jumped > 0) as usize).sum();
+        writeln!(f, "{} evaluations ({} faulted, {} undecided)", self.evaluations, self.faults, self.undecided)?;
+        writeln!(f, "rules fired: {} of {}", fired, self.rules.len())?;
+        writeln!(f, "branch outcomes taken: {} of {}", taken, self.branches.len() * 2)?;
+        for rule in self.dead_rules() {
+            let (opcode, operand) = match rule.kind {
+                RuleKind::Allow => (ALLOW, None),
+                RuleKind::Deny { reason } => (DENY, Some(reason)),
+                RuleKind::Escalate { reason } => (ESCALATE, Some(reason)),
+                RuleKind::Decide { reason } => (DECIDE, Some(reason)),
+            };
+            let name = mnemonic(opcode).expect("a rule opcode");
+            let marker = if rule.refuses() { "untested refusal" } else { "dead rule" };
+            match operand {
+                Some(reason) => writeln!(f, "  {}: pc {} {} {}", marker, rule.pc, name, reason)?,
+                None => writeln!(f, "  {}: pc {} {}", marker, rule.pc, name)?,
+            }
+        }
+        for branch in self.partial_branches() {
+            let never = match (branch.held, branch.jumped) {
+                (0, 0) => "never evaluated",
+                (0, _) => "never held",
+                _ => "never jumped",
+            };
+            writeln!(f, "  branch: pc {} JZ {}", branch.pc, never)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::bytecode::*;
+    use crate::vm::interp::decide_traced;
+    use crate::wcet::watchdog::{ExecutionBudget, Watchdog};
+
+    #[test]
+    fn reports_rules_and_branches_never_exercised() {
+        // Deny 7 if context[0] is nonzero, escalate 9 if context[1] is, else allow.
+        let policy: Vec<u8> = [(LOAD8, 0), (JZ, 3), (DENY, 7), (LOAD8, 1), (JZ, 6), (ESCALATE, 9), (ALLOW, 0)]
+            .iter()
+            .flat_map(|&(op, arg)| encode(op, arg))
+            .collect();
+        let mut coverage = Coverage::new(&policy);
+        for context in [[0u8, 0], [1, 0], [0, 0]] {
+            let mut trace = Trace::default();
+            decide_traced(&policy, &context, &mut Watchdog::new("test", ExecutionBudget::steps(100)), &mut trace).unwrap();
+            coverage.record(&trace);
+        }
+        assert_eq!(coverage.rules.iter().map(|r| r.fired).collect::<Vec<_>>(), [1, 0, 2]);
+        assert_eq!(coverage.untested_refusals().map(|r| r.kind).collect::<Vec<_>>(), [RuleKind::Escalate { reason: 9 }]);
+        assert_eq!(coverage.partial_branches().map(|b| b.pc).collect::<Vec<_>>(), [4]);
+        assert!(coverage.to_string().contains("untested refusal: pc 5 ESCALATE 9\n  branch: pc 4 JZ never held"));
+    }
+}
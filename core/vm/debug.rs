@@ -0,0 +1,144 @@
+//! A single-stepping debugger for policy bytecode.
+//!
+//! `Debugger` runs a compiled policy against a context the way `interp::decide` does,
+//! on the same instruction semantics and under a `Watchdog`, but one instruction per
+//! `step`, and after each one reports the program counter, the instruction, the
+//! operand stack and the budget consumed so far. It is for policy authors working out
+//! why bytecode decides as it does; the Gate never uses it.
+//!
+//! The module only exists with the `vm-debug` feature, and a release build with the
+//! feature enabled does not compile: a debugger that can run policies outside the
+//! Gate's pipeline has no place in a production binary.
+#![cfg(feature = "vm-debug")]
+
+#[cfg(not(debug_assertions))]
+compile_error!("the `vm-debug` feature is for policy development and must not be enabled in release builds");
+
+use std::fmt;
+
+use super::bytecode::{fetch, mnemonic, INSTR_LEN};
+use super::interp::{execute, Stack, Step, Verdict, FRAME_BYTES, REASON_MALFORMED, REASON_NO_DECISION};
+use crate::wcet::watchdog::{BudgetExceeded, ExecutionBudget, ExecutionUsage, Watchdog};
+
+/// The machine right after one instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepState {
+    pub pc: usize,
+    pub opcode: u8,
+    pub operand: u32,
+    /// Bottom first.
+    pub stack: Vec<i64>,
+    pub usage: ExecutionUsage,
+    /// Where execution continues, or `None` if the instruction ended the run.
+    pub next_pc: Option<usize>,
+}
+
+impl fmt::Display for StepState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = mnemonic(self.opcode).unwrap_or("??");
+        write!(f, "pc {:>4}  {:<8} {:<8}  stack {:?}  steps {}", self.pc, name, self.operand, self.stack, self.usage.steps)
+    }
+}
+
+pub struct Debugger<'a> {
+    policy: &'a [u8],
+    context: &'a [u8],
+    watchdog: Watchdog<'a>,
+    stack: Stack,
+    pc: usize,
+    outcome: Option<Result<Verdict, BudgetExceeded>>,
+}
+
+impl<'a> Debugger<'a> {
+    /// Stops before the first instruction of `policy`, named `name` in budget reports.
+    pub fn new(name: &'a str, policy: &'a [u8], context: &'a [u8], budget: ExecutionBudget) -> Self {
+        let mut watchdog = Watchdog::new(name, budget);
+        let outcome = match watchdog.enter_frame(FRAME_BYTES) {
+            Err(exceeded) => Some(Err(exceeded)),
+            Ok(()) if !policy.len().is_multiple_of(INSTR_LEN) => Some(Ok(Verdict::Deny { reason: REASON_MALFORMED })),
+            Ok(()) => None,
+        };
+        Self { policy, context, watchdog, stack: Stack::new(), pc: 0, outcome }
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn stack(&self) -> &[i64] {
+        self.stack.as_slice()
+    }
+
+    pub fn usage(&self) -> ExecutionUsage {
+        self.watchdog.usage()
+    }
+
+    /// The run's result, once it has ended: what `decide` would have returned.
+    pub fn outcome(&self) -> Option<&Result<Verdict, BudgetExceeded>> {
+        self.outcome.as_ref()
+    }
+
+    /// Executes one instruction; `None` once the run has ended.
+    pub fn step(&mut self) -> Option<StepState> {
+        if self.outcome.is_some() {
+            return None;
+        }
+        let Some((opcode, operand)) = fetch(self.policy, self.pc) else {
+            self.finish(Ok(Verdict::Deny { reason: REASON_NO_DECISION }));
+            return None;
+        };
+        let pc = self.pc;
+        if let Err(exceeded) = self.watchdog.step() {
+            self.finish(Err(exceeded));
+            return None;
+        }
+        let next_pc = match execute(opcode, operand, pc, self.context, &mut self.stack) {
+            Ok(Step::Next) => Some(pc + 1),
+            Ok(Step::Jump(target)) => Some(target),
+            Ok(Step::Decide(verdict)) => {
+                self.finish(Ok(verdict));
+                None
+            }
+            Err(reason) => {
+                self.finish(Ok(Verdict::Deny { reason }));
+                None
+            }
+        };
+        self.pc = next_pc.unwrap_or(pc);
+        Some(StepState { pc, opcode, operand, stack: self.stack().to_vec(), usage: self.usage(), next_pc })
+    }
+
+    /// Steps to the end of the run, returning every state on the way.
+    pub fn run(&mut self) -> Vec<StepState> {
+        std::iter::from_fn(|| self.step()).collect()
+    }
+
+    fn finish(&mut self, outcome: Result<Verdict, BudgetExceeded>) {
+        self.watchdog.exit_frame(FRAME_BYTES);
+        self.outcome = Some(outcome);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::bytecode::*;
+    use crate::vm::interp::decide;
+
+    #[test]
+    fn steps_as_decide_runs() {
+        // Allow if context[0] == 7, else deny with reason 42.
+        let policy: Vec<u8> = [(LOAD8, 0), (PUSH, 7), (EQ, 0), (JZ, 5), (ALLOW, 0), (DENY, 42)].iter().flat_map(|&(op, arg)| encode(op, arg)).collect();
+        let mut debugger = Debugger::new("test", &policy, &[8], ExecutionBudget::steps(100));
+        let states = debugger.run();
+
+        let stacks: Vec<&[i64]> = states.iter().map(|s| s.stack.as_slice()).collect();
+        assert_eq!(stacks, [&[8][..], &[8, 7], &[0], &[], &[]]);
+        assert_eq!(states.iter().map(|s| s.pc).collect::<Vec<_>>(), [0, 1, 2, 3, 5]);
+        assert_eq!((states[3].next_pc, states[4].next_pc, states[4].usage.steps), (Some(5), None, 5));
+        assert_eq!(states[3].to_string(), "pc    3  JZ       5         stack []  steps 4");
+        let expected = decide(&policy, &[8], &mut Watchdog::new("test", ExecutionBudget::steps(100))).unwrap();
+        assert_eq!(debugger.outcome().unwrap().as_ref().unwrap(), &expected);
+        assert!(debugger.step().is_none());
+    }
+}
@@ -0,0 +1,241 @@
+//! Differential testing of the native VM against the WASM runtime.
+//!
+//! `to_wat` compiles native bytecode to a WASM module with the same semantics: the
+//! same verdicts, the same reason codes, and the same fault codes for stack misuse,
+//! context reads out of range, backward jumps, unknown opcodes and running off the
+//! end. `differential` runs a corpus of policies, each against every context of a
+//! corpus, through `NativeVm` directly and through `WasmRuntime` as compiled, and
+//! returns every case where the two disagree. The two runtimes share nothing but the
+//! policy ABI, so any divergence is semantic drift in one of them.
+//!
+//! `generated_corpus` produces deterministic pseudo-random programs that exercise
+//! every opcode, faults included, for the corpus.
+//!
+//! Budgets are not compared: a WASM step is a unit of fuel, not an instruction, so
+//! each side runs on a budget its policies cannot exhaust.
+
+use std::fmt::Write as _;
+
+use super::bytecode::*;
+use super::interp::{Verdict, REASON_CONTEXT_RANGE, REASON_MALFORMED, REASON_NO_DECISION, REASON_STACK_OVERFLOW, REASON_STACK_UNDERFLOW, STACK_DEPTH};
+use super::runtime::{NativeVm, PolicyRuntime};
+use super::verify::VerifyError;
+use super::wasm::WasmRuntime;
+use crate::wcet::watchdog::{ExceededLimit, ExecutionBudget, Watchdog};
+
+/// Native steps allowed per evaluation; forward-only jumps bound a run by its length.
+const NATIVE_STEPS: u64 = 1 << 20;
+
+/// A case the runtimes decided differently, by index into the corpora.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub policy: usize,
+    pub context: usize,
+    pub native: Result<Verdict, ExceededLimit>,
+    pub wasm: Result<Verdict, ExceededLimit>,
+}
+
+/// `decide`'s packed result for `verdict`, as a WASM constant.
+fn packed(verdict: Verdict) -> i64 {
+    match verdict {
+        Verdict::Allow => 0,
+        Verdict::Deny { reason } => (1 << 16) | reason as i64,
+        Verdict::Escalate { reason } => (2 << 16) | reason as i64,
+    }
+}
+
+fn ret(verdict: Verdict) -> String {
+    format!("(return (i64.const {}))", packed(verdict))
+}
+
+fn fault(reason: u16) -> String {
+    ret(Verdict::Deny { reason })
+}
+
+fn push(value: &str) -> String {
+    format!(
+        "(if (i32.ge_u (local.get $sp) (i32.const {})) (then {})) \
+         (i64.store (i32.add (local.get $base) (i32.shl (local.get $sp) (i32.const 3))) {}) \
+         (local.set $sp (i32.add (local.get $sp) (i32.const 1)))",
+        STACK_DEPTH,
+        fault(REASON_STACK_OVERFLOW),
+        value
+    )
+}
+
+fn pop(into: &str) -> String {
+    format!(
+        "(if (i32.eqz (local.get $sp)) (then {})) \
+         (local.set $sp (i32.sub (local.get $sp) (i32.const 1))) \
+         (local.set ${} (i64.load (i32.add (local.get $base) (i32.shl (local.get $sp) (i32.const 3)))))",
+        fault(REASON_STACK_UNDERFLOW),
+        into
+    )
+}
+
+fn binary(op: &str) -> String {
+    format!("{} {} {}", pop("b"), pop("a"), push(&format!("({} (local.get $a) (local.get $b))", op)))
+}
+
+fn load(offset: u32, width: u32, op: &str) -> String {
+    let end = offset as u64 + width as u64;
+    format!("(if (i64.gt_u (i64.const {}) (i64.extend_i32_u (local.get $len))) (then {})) {}", end, fault(REASON_CONTEXT_RANGE), push(&format!("({} (i32.const {}))", op, offset)))
+}
+
+fn reason(operand: u32) -> u16 {
+    operand.min(u16::MAX as u32) as u16
+}
+
+/// Instruction `pc` as WASM; falling off its end continues at `pc + 1`.
+fn instruction(pc: usize, opcode: u8, operand: u32) -> String {
+    let jump = |target: usize| format!("(local.set $pc (i32.const {})) (br $dispatch)", target);
+    if matches!(opcode, JMP | JZ) && operand as usize <= pc {
+        return fault(REASON_MALFORMED);
+    }
+    match opcode {
+        PUSH => push(&format!("(i64.const {})", operand)),
+        POP => pop("a"),
+        DUP => format!("{} {} {}", pop("a"), push("(local.get $a)"), push("(local.get $a)")),
+        SWAP => format!("{} {} {} {}", pop("b"), pop("a"), push("(local.get $b)"), push("(local.get $a)")),
+        ADD => binary("i64.add"),
+        SUB => binary("i64.sub"),
+        MUL => binary("i64.mul"),
+        AND => binary("i64.and"),
+        OR => binary("i64.or"),
+        XOR => binary("i64.xor"),
+        NOT => format!("{} {}", pop("a"), push("(i64.extend_i32_u (i64.eqz (local.get $a)))")),
+        EQ => format!("{} {} {}", pop("b"), pop("a"), push("(i64.extend_i32_u (i64.eq (local.get $a) (local.get $b)))")),
+        LT => format!("{} {} {}", pop("b"), pop("a"), push("(i64.extend_i32_u (i64.lt_s (local.get $a) (local.get $b)))")),
+        GT => format!("{} {} {}", pop("b"), pop("a"), push("(i64.extend_i32_u (i64.gt_s (local.get $a) (local.get $b)))")),
+        LOAD8 => load(operand, 1, "i64.load8_u"),
+        LOAD32 => load(operand, 4, "i64.load32_u"),
+        CTXLEN => push("(i64.extend_i32_u (local.get $len))"),
+        JMP => jump(operand as usize),
+        JZ => format!("{} (if (i64.eqz (local.get $a)) (then {}))", pop("a"), jump(operand as usize)),
+        ALLOW => ret(Verdict::Allow),
+        DENY => ret(Verdict::Deny { reason: reason(operand) }),
+        ESCALATE => ret(Verdict::Escalate { reason: reason(operand) }),
+        DECIDE => format!("{} (if (i64.ne (local.get $a) (i64.const 0)) (then {})) {}", pop("a"), ret(Verdict::Allow), ret(Verdict::Deny { reason: reason(operand) })),
+        _ => fault(REASON_MALFORMED),
+    }
+}
+
+/// `bytecode` as a WASM module, in text form, that decides as the native VM does.
+///
+/// Instruction `pc` is the code after the end of block `$i<pc>`; `$dispatch` jumps to
+/// `$pc` through a `br_table`, and the default, past the last instruction, denies as
+/// running off the end does. The operand stack lives in memory right after the
+/// context.
+pub fn to_wat(bytecode: &[u8]) -> String {
+    let mut body = String::new();
+    if !bytecode.len().is_multiple_of(INSTR_LEN) {
+        body.push_str(&fault(REASON_MALFORMED));
+    } else {
+        let count = bytecode.len() / INSTR_LEN;
+        // 8-aligned stack base after the context, with a page more if it does not fit.
+        let _ = write!(
+            body,
+            "(local.set $base (i32.and (i32.add (local.get $len) (i32.const 7)) (i32.const -8))) \
+             (if (i32.gt_u (i32.add (local.get $base) (i32.const {})) (i32.mul (memory.size) (i32.const 65536))) \
+                 (then (if (i32.eq (memory.grow (i32.const 1)) (i32.const -1)) (then {})))) \
+             (loop $dispatch ",
+            STACK_DEPTH * 8,
+            fault(REASON_MALFORMED)
+        );
+        for pc in (0..=count).rev() {
+            let _ = write!(body, "(block $i{} ", pc);
+        }
+        let labels: Vec<String> = (0..=count).map(|pc| format!("$i{}", pc)).collect();
+        let _ = write!(body, "(br_table {} (local.get $pc))", labels.join(" "));
+        for pc in 0..count {
+            let (opcode, operand) = fetch(bytecode, pc).expect("in range");
+            let _ = write!(body, ")\n{}\n", instruction(pc, opcode, operand));
+        }
+        let _ = write!(body, ") {})", fault(REASON_NO_DECISION));
+    }
+    format!(
+        "(module (memory (export \"memory\") 1) \
+         (func (export \"decide\") (param $len i32) (result i64) \
+         (local $pc i32) (local $sp i32) (local $base i32) (local $a i64) (local $b i64)\n{}\n(unreachable)))",
+        body
+    )
+}
+
+/// Runs every policy against every context through both runtimes.
+pub fn differential(wasm: &WasmRuntime, policies: &[Vec<u8>], contexts: &[Vec<u8>]) -> Result<Vec<Divergence>, VerifyError> {
+    let mut divergences = Vec::new();
+    for (p, policy) in policies.iter().enumerate() {
+        let module = to_wat(policy);
+        let proof = wasm.verify(module.as_bytes())?;
+        for (c, context) in contexts.iter().enumerate() {
+            let native = NativeVm.decide(policy, context, &mut Watchdog::new("native", ExecutionBudget::steps(NATIVE_STEPS))).map_err(|e| e.limit);
+            let compiled = wasm.decide(module.as_bytes(), context, &mut Watchdog::new("wasm", proof.execution_budget())).map_err(|e| e.limit);
+            if native != compiled {
+                divergences.push(Divergence { policy: p, context: c, native, wasm: compiled });
+            }
+        }
+    }
+    Ok(divergences)
+}
+
+/// `count` programs of up to `max_len` instructions, the same for the same `seed`.
+/// Operands are drawn near the values that matter: small constants and context
+/// offsets, jump targets around the program, and reasons past `u16::MAX`; one opcode
+/// in 24 is not one the VM executes.
+pub fn generated_corpus(seed: u64, count: usize, max_len: usize) -> Vec<Vec<u8>> {
+    let mut state = seed | 1;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    (0..count)
+        .map(|_| {
+            let len = 1 + (next() % max_len.max(1) as u64) as usize;
+            (0..len)
+                .flat_map(|pc| {
+                    let pick = (next() % (OPCODES.len() as u64 + 1)) as usize;
+                    let opcode = OPCODES.get(pick).copied().unwrap_or(0xff);
+                    let operand = match opcode {
+                        JMP | JZ => (pc as u64 + next() % (len as u64 + 2)).saturating_sub(1) as u32,
+                        LOAD8 | LOAD32 => (next() % 12) as u32,
+                        DENY | ESCALATE | DECIDE => (next() % 0x1_0004) as u32,
+                        _ if next() % 8 == 0 => (next() as u32) & MAX_OPERAND,
+                        _ => (next() % 10) as u32,
+                    };
+                    encode(opcode, operand)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::wasm::DEFAULT_MAX_MEMORY_BYTES;
+
+    fn program(instrs: &[(u8, u32)]) -> Vec<u8> {
+        instrs.iter().flat_map(|&(op, arg)| encode(op, arg)).collect()
+    }
+
+    #[test]
+    fn native_and_wasm_decide_alike() {
+        let mut policies = vec![
+            program(&[(LOAD8, 0), (PUSH, 7), (EQ, 0), (JZ, 5), (ALLOW, 0), (DENY, 42)]),
+            program(&[(LOAD32, 0), (PUSH, 5), (GT, 0), (DECIDE, 3)]),
+            program(&[(CTXLEN, 0), (NOT, 0), (JZ, 3), (ESCALATE, 9), (DENY, 0x1_0000)]),
+            program(&[(PUSH, 1); STACK_DEPTH + 1]),
+            program(&[(PUSH, 1), (JMP, 0)]),
+            program(&[(PUSH, 1)]),
+            vec![PUSH],
+        ];
+        policies.extend(generated_corpus(0x5eed, 400, 12));
+        let contexts = [vec![], vec![7], vec![8, 0, 0, 0], (0u8..12).collect(), vec![0xff; 12]];
+
+        let wasm = WasmRuntime::new(100_000, DEFAULT_MAX_MEMORY_BYTES).unwrap();
+        let divergences = differential(&wasm, &policies, &contexts).unwrap();
+        assert!(divergences.is_empty(), "{} divergences, first {:?}", divergences.len(), divergences.first());
+    }
+}
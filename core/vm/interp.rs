@@ -0,0 +1,326 @@
+//! The policy VM: a deterministic, step-bounded stack machine.
+//!
+//! `decide` evaluates a policy against a context and returns `Allow`, or `Deny` or
+//! `Escalate` with a reason code. Execution reads the bytecode in place and keeps its operand stack in a
+//! fixed array, so it allocates nothing; every instruction is one `Watchdog` step, and
+//! the only error that escapes is the watchdog's `BudgetExceeded`. Everything else a
+//! policy can do wrong (malformed bytecode, stack misuse, reading past the context)
+//! fails closed as a denial with one of the reserved `REASON_*` codes.
+//!
+//! `decide_traced` also records the path a run took: every `JZ` condition it
+//! evaluated, with its outcome, and the instruction that decided. That is what lets a
+//! denial be explained without reading the bytecode. The trace is pushed into a
+//! vector the caller sizes beforehand, so a traced run allocates nothing either.
+//!
+//! Built with the `vm-debug` feature, `debug::Debugger` steps the same machine one
+//! instruction at a time for policy authors.
+//!
+//! Comparisons and `NOT` are computed without data-dependent branches, so a policy
+//! verified with `verify::verify_constant_time` takes the same path, and the same
+//! time, whatever the secrets in its context hold.
+
+use serde::{Deserialize, Serialize};
+
+use super::bytecode::*;
+use crate::wcet::watchdog::{BudgetExceeded, Watchdog};
+
+/// Operand stack slots.
+pub const STACK_DEPTH: usize = 64;
+
+/// Bytes of VM stack charged to the watchdog for the single frame a policy runs in.
+pub const FRAME_BYTES: u64 = (STACK_DEPTH * std::mem::size_of::<i64>()) as u64;
+
+/// Reason codes from `0xfff0` up are reserved for VM faults.
+pub const REASON_MALFORMED: u16 = 0xfff0;
+pub const REASON_STACK_OVERFLOW: u16 = 0xfff1;
+pub const REASON_STACK_UNDERFLOW: u16 = 0xfff2;
+pub const REASON_CONTEXT_RANGE: u16 = 0xfff3;
+/// Execution ran past the last instruction without deciding.
+pub const REASON_NO_DECISION: u16 = 0xfff4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verdict {
+    Allow,
+    Deny { reason: u16 },
+    /// Neither allowed nor denied by the policy: the Gate parks the proposal until
+    /// enough approvers sign off on it.
+    Escalate { reason: u16 },
+}
+
+impl Verdict {
+    pub fn is_allow(&self) -> bool {
+        matches!(self, Verdict::Allow)
+    }
+
+    /// Whether the denial comes from a VM fault rather than the policy's own `DENY`.
+    pub fn is_fault(&self) -> bool {
+        matches!(self, Verdict::Deny { reason } if *reason >= REASON_MALFORMED)
+    }
+}
+
+/// A `JZ` a run evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Condition {
+    pub pc: usize,
+    /// Whether the tested value was nonzero, so execution fell through.
+    pub held: bool,
+}
+
+/// The path one run took through a policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trace {
+    /// In execution order.
+    pub conditions: Vec<Condition>,
+    /// Instruction that decided or faulted; `None` if the run was cut off or ran off
+    /// the end.
+    pub decided_at: Option<usize>,
+}
+
+impl Trace {
+    /// A trace with room for every condition of a policy of `instructions`.
+    pub fn with_capacity(instructions: usize) -> Self {
+        Self { conditions: Vec::with_capacity(instructions), decided_at: None }
+    }
+}
+
+pub(super) struct Stack {
+    slots: [i64; STACK_DEPTH],
+    len: usize,
+}
+
+impl Stack {
+    pub(super) fn new() -> Self {
+        Self { slots: [0; STACK_DEPTH], len: 0 }
+    }
+
+    /// The live slots, bottom first.
+    pub(super) fn as_slice(&self) -> &[i64] {
+        &self.slots[..self.len]
+    }
+
+    /// A stack holding `values`, bottom first; `None` if they do not fit.
+    pub(super) fn from_slice(values: &[i64]) -> Option<Self> {
+        let mut stack = Self::new();
+        stack.slots.get_mut(..values.len())?.copy_from_slice(values);
+        stack.len = values.len();
+        Some(stack)
+    }
+
+    fn push(&mut self, value: i64) -> Result<(), u16> {
+        let slot = self.slots.get_mut(self.len).ok_or(REASON_STACK_OVERFLOW)?;
+        *slot = value;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<i64, u16> {
+        self.len = self.len.checked_sub(1).ok_or(REASON_STACK_UNDERFLOW)?;
+        Ok(self.slots[self.len])
+    }
+
+    fn top(&self) -> Option<i64> {
+        self.len.checked_sub(1).map(|top| self.slots[top])
+    }
+}
+
+/// Evaluates `policy` against `context` under `watchdog`.
+// `BudgetExceeded` is returned unboxed, as by `Watchdog::step`.
+#[allow(clippy::result_large_err)]
+pub fn decide(policy: &[u8], context: &[u8], watchdog: &mut Watchdog<'_>) -> Result<Verdict, BudgetExceeded> {
+    watchdog.enter_frame(FRAME_BYTES)?;
+    let verdict = run(policy, context, watchdog, None);
+    watchdog.exit_frame(FRAME_BYTES);
+    verdict
+}
+
+/// `decide`, recording the run into `trace`.
+#[allow(clippy::result_large_err)]
+pub fn decide_traced(policy: &[u8], context: &[u8], watchdog: &mut Watchdog<'_>, trace: &mut Trace) -> Result<Verdict, BudgetExceeded> {
+    watchdog.enter_frame(FRAME_BYTES)?;
+    let verdict = run(policy, context, watchdog, Some(trace));
+    watchdog.exit_frame(FRAME_BYTES);
+    verdict
+}
+
+#[allow(clippy::result_large_err)]
+fn run(policy: &[u8], context: &[u8], watchdog: &mut Watchdog<'_>, mut trace: Option<&mut Trace>) -> Result<Verdict, BudgetExceeded> {
+    if !policy.len().is_multiple_of(INSTR_LEN) {
+        return Ok(Verdict::Deny { reason: REASON_MALFORMED });
+    }
+    let mut stack = Stack::new();
+    let mut pc = 0;
+    while let Some((opcode, operand)) = fetch(policy, pc) {
+        watchdog.step()?;
+        let tested = if opcode == JZ { stack.top() } else { None };
+        let step = execute(opcode, operand, pc, context, &mut stack);
+        if let Some(trace) = trace.as_deref_mut() {
+            match (&step, tested) {
+                (Ok(_), Some(value)) => trace.conditions.push(Condition { pc, held: value != 0 }),
+                (Ok(Step::Decide(_)) | Err(_), _) => trace.decided_at = Some(pc),
+                _ => {}
+            }
+        }
+        match step {
+            Ok(Step::Next) => pc += 1,
+            Ok(Step::Jump(target)) => pc = target,
+            Ok(Step::Decide(verdict)) => return Ok(verdict),
+            Err(reason) => return Ok(Verdict::Deny { reason }),
+        }
+    }
+    Ok(Verdict::Deny { reason: REASON_NO_DECISION })
+}
+
+/// `(a == b) as i64` without a data-dependent branch.
+fn ct_eq(a: i64, b: i64) -> i64 {
+    let x = (a ^ b) as u64;
+    (((x | x.wrapping_neg()) >> 63) ^ 1) as i64
+}
+
+/// `(a < b) as i64` without a data-dependent branch: the sign of `a - b`, corrected
+/// for overflow.
+fn ct_lt(a: i64, b: i64) -> i64 {
+    let d = a.wrapping_sub(b);
+    ((d ^ ((a ^ b) & (d ^ a))) as u64 >> 63) as i64
+}
+
+pub(super) enum Step {
+    Next,
+    Jump(usize),
+    Decide(Verdict),
+}
+
+fn context_bytes<const N: usize>(context: &[u8], offset: u32) -> Result<[u8; N], u16> {
+    let start = offset as usize;
+    context.get(start..start + N).and_then(|b| b.try_into().ok()).ok_or(REASON_CONTEXT_RANGE)
+}
+
+pub(super) fn execute(opcode: u8, operand: u32, pc: usize, context: &[u8], stack: &mut Stack) -> Result<Step, u16> {
+    let binary = |stack: &mut Stack, op: fn(i64, i64) -> i64| -> Result<Step, u16> {
+        let b = stack.pop()?;
+        let a = stack.pop()?;
+        stack.push(op(a, b))?;
+        Ok(Step::Next)
+    };
+    match opcode {
+        PUSH => stack.push(operand as i64)?,
+        POP => {
+            stack.pop()?;
+        }
+        DUP => {
+            let top = stack.pop()?;
+            stack.push(top)?;
+            stack.push(top)?;
+        }
+        SWAP => {
+            let b = stack.pop()?;
+            let a = stack.pop()?;
+            stack.push(b)?;
+            stack.push(a)?;
+        }
+        ADD => return binary(stack, i64::wrapping_add),
+        SUB => return binary(stack, i64::wrapping_sub),
+        MUL => return binary(stack, i64::wrapping_mul),
+        AND => return binary(stack, |a, b| a & b),
+        OR => return binary(stack, |a, b| a | b),
+        XOR => return binary(stack, |a, b| a ^ b),
+        NOT => {
+            let top = stack.pop()?;
+            stack.push(ct_eq(top, 0))?;
+        }
+        EQ => return binary(stack, ct_eq),
+        LT => return binary(stack, ct_lt),
+        GT => return binary(stack, |a, b| ct_lt(b, a)),
+        LOAD8 => stack.push(context_bytes::<1>(context, operand)?[0] as i64)?,
+        LOAD32 => stack.push(u32::from_le_bytes(context_bytes::<4>(context, operand)?) as i64)?,
+        CTXLEN => stack.push(context.len() as i64)?,
+        JMP | JZ => {
+            let target = operand as usize;
+            // Forward-only jumps bound execution to one pass over the program.
+            if target <= pc {
+                return Err(REASON_MALFORMED);
+            }
+            if opcode == JMP || stack.pop()? == 0 {
+                return Ok(Step::Jump(target));
+            }
+        }
+        ALLOW => return Ok(Step::Decide(Verdict::Allow)),
+        DENY => return Ok(Step::Decide(Verdict::Deny { reason: operand.min(u16::MAX as u32) as u16 })),
+        ESCALATE => return Ok(Step::Decide(Verdict::Escalate { reason: operand.min(u16::MAX as u32) as u16 })),
+        DECIDE => {
+            let allow = stack.pop()? != 0;
+            return Ok(Step::Decide(if allow { Verdict::Allow } else { Verdict::Deny { reason: operand.min(u16::MAX as u32) as u16 } }));
+        }
+        _ => return Err(REASON_MALFORMED),
+    }
+    Ok(Step::Next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wcet::watchdog::{ExceededLimit, ExecutionBudget};
+
+    fn program(instrs: &[(u8, u32)]) -> Vec<u8> {
+        instrs.iter().flat_map(|&(op, arg)| encode(op, arg)).collect()
+    }
+
+    fn eval(policy: &[u8], context: &[u8]) -> Verdict {
+        decide(policy, context, &mut Watchdog::new("test", ExecutionBudget::steps(100))).expect("within budget")
+    }
+
+    #[test]
+    fn allows_or_denies_on_context() {
+        // Allow if context[0] == 7, else deny with reason 42.
+        let policy = program(&[(LOAD8, 0), (PUSH, 7), (EQ, 0), (JZ, 5), (ALLOW, 0), (DENY, 42)]);
+        assert_eq!(eval(&policy, &[7]), Verdict::Allow);
+        assert_eq!(eval(&policy, &[8]), Verdict::Deny { reason: 42 });
+        assert_eq!(eval(&policy, &[]), Verdict::Deny { reason: REASON_CONTEXT_RANGE });
+    }
+
+    #[test]
+    fn comparisons_are_exact_at_the_extremes() {
+        let values = [i64::MIN, i64::MIN + 1, -1, 0, 1, u32::MAX as i64, i64::MAX - 1, i64::MAX];
+        for a in values {
+            for b in values {
+                assert_eq!((ct_eq(a, b), ct_lt(a, b)), ((a == b) as i64, (a < b) as i64), "{} vs {}", a, b);
+            }
+        }
+        let policy = program(&[(LOAD8, 0), (PUSH, 7), (EQ, 0), (DECIDE, 42)]);
+        assert_eq!((eval(&policy, &[7]), eval(&policy, &[8])), (Verdict::Allow, Verdict::Deny { reason: 42 }));
+    }
+
+    #[test]
+    fn traces_the_conditions_that_decided() {
+        // Deny 1 unless ctx[0] != 0; then deny 2 unless ctx[1] == 7; else allow.
+        let policy = program(&[(LOAD8, 0), (JZ, 7), (LOAD8, 1), (PUSH, 7), (EQ, 0), (JZ, 8), (ALLOW, 0), (DENY, 1), (DENY, 2)]);
+        let traced = |context: &[u8]| {
+            let mut trace = Trace::with_capacity(9);
+            let verdict = decide_traced(&policy, context, &mut Watchdog::new("test", ExecutionBudget::steps(100)), &mut trace).unwrap();
+            (verdict, trace)
+        };
+        let (verdict, trace) = traced(&[1, 8]);
+        assert_eq!(verdict, Verdict::Deny { reason: 2 });
+        assert_eq!(trace.conditions, [Condition { pc: 1, held: true }, Condition { pc: 5, held: false }]);
+        assert_eq!(trace.decided_at, Some(8));
+        assert_eq!(traced(&[0]).1.conditions, [Condition { pc: 1, held: false }]);
+        assert_eq!(traced(&[1]).1.decided_at, Some(2));
+    }
+
+    #[test]
+    fn faults_fail_closed() {
+        assert_eq!(eval(&program(&[(ADD, 0)]), &[]), Verdict::Deny { reason: REASON_STACK_UNDERFLOW });
+        assert_eq!(eval(&program(&[(PUSH, 1), (JMP, 0)]), &[]), Verdict::Deny { reason: REASON_MALFORMED });
+        assert_eq!(eval(&program(&[(PUSH, 1)]), &[]), Verdict::Deny { reason: REASON_NO_DECISION });
+        assert_eq!(eval(&[PUSH], &[]), Verdict::Deny { reason: REASON_MALFORMED });
+        let overflow = program(&[(PUSH, 1); STACK_DEPTH + 1]);
+        assert!(decide(&overflow, &[], &mut Watchdog::new("test", ExecutionBudget::steps(1_000))).unwrap().is_fault());
+    }
+
+    #[test]
+    fn stops_at_the_step_budget() {
+        let long = program(&[(PUSH, 1), (POP, 0), (PUSH, 1), (POP, 0), (ALLOW, 0)]);
+        let err = decide(&long, &[], &mut Watchdog::new("test", ExecutionBudget::steps(3))).unwrap_err();
+        assert_eq!((err.limit, err.steps), (ExceededLimit::Steps, 4));
+    }
+}
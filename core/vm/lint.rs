@@ -0,0 +1,100 @@
+//! Linting policy bytecode for non-deterministic reads.
+//!
+//! Every node in a cluster must reach the bit-identical decision for a proposal, so a
+//! policy may only depend on context bytes every node fills alike. Context providers
+//! that draw on something node-local (the host clock, an RNG, the process
+//! environment) declare it, and `lint` flags each instruction that loads any byte of
+//! such a range. Bytecode addresses the context only through the static operands of
+//! `LOAD8` and `LOAD32`, so the lint is exact up to reachability: a load on a path no
+//! context takes is flagged all the same.
+//!
+//! A policy for a runtime whose loads are computed, such as WASM, cannot be linted this
+//! way; the Gate treats it as reading every non-deterministic range.
+
+use std::fmt;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use super::bytecode::{fetch, LOAD32, LOAD8};
+
+/// Where a non-deterministic context value comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Nondeterminism {
+    WallClock,
+    Random,
+    Environment,
+}
+
+impl fmt::Display for Nondeterminism {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Nondeterminism::WallClock => write!(f, "the wall clock"),
+            Nondeterminism::Random => write!(f, "a random source"),
+            Nondeterminism::Environment => write!(f, "the host environment"),
+        }
+    }
+}
+
+/// A read of a non-deterministic context range.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NondeterministicRead {
+    /// The composition module the read is in, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    /// The loading instruction, or `None` if the policy could not be linted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pc: Option<usize>,
+    /// The non-deterministic range read.
+    pub bytes: Range<u32>,
+    pub source: Nondeterminism,
+}
+
+impl fmt::Display for NondeterministicRead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(module) = &self.module {
+            write!(f, "module {}: ", module)?;
+        }
+        match self.pc {
+            Some(pc) => write!(f, "pc {} reads context bytes {:?} from {}", pc, self.bytes, self.source),
+            None => write!(f, "may read context bytes {:?} from {}", self.bytes, self.source),
+        }
+    }
+}
+
+/// Every load in `policy` of a byte in one of the `nondeterministic` ranges.
+pub fn lint(policy: &[u8], nondeterministic: &[(Range<u32>, Nondeterminism)]) -> Vec<NondeterministicRead> {
+    let mut reads = Vec::new();
+    for pc in 0.. {
+        let Some((opcode, operand)) = fetch(policy, pc) else { break };
+        let width = match opcode {
+            LOAD8 => 1,
+            LOAD32 => 4,
+            _ => continue,
+        };
+        let end = operand.saturating_add(width);
+        for (bytes, source) in nondeterministic {
+            if bytes.start < end && operand < bytes.end {
+                reads.push(NondeterministicRead { module: None, pc: Some(pc), bytes: bytes.clone(), source: *source });
+            }
+        }
+    }
+    reads
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::bytecode::*;
+
+    #[test]
+    fn flags_loads_overlapping_nondeterministic_ranges() {
+        let policy: Vec<u8> = [(LOAD32, 0), (LOAD8, 30), (GT, 0), (LOAD32, 26), (AND, 0), (DECIDE, 1)].iter().flat_map(|&(op, arg)| encode(op, arg)).collect();
+        let ranges = [(28..32, Nondeterminism::WallClock), (40..44, Nondeterminism::Random)];
+        let reads = lint(&policy, &ranges);
+        assert_eq!(reads.iter().map(|r| (r.pc, r.source)).collect::<Vec<_>>(), [(Some(1), Nondeterminism::WallClock), (Some(3), Nondeterminism::WallClock)]);
+        assert_eq!(reads[0].to_string(), "pc 1 reads context bytes 28..32 from the wall clock");
+        assert!(lint(&policy, &ranges[1..]).is_empty());
+    }
+}
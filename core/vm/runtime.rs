@@ -0,0 +1,62 @@
+//! Pluggable policy runtimes.
+//!
+//! The Gate evaluates a policy through a `PolicyRuntime`: the native bytecode VM in
+//! `interp`, or `wasm::WasmRuntime` for policies compiled from higher-level
+//! languages. Either way a payload is checked once at load time, and every evaluation
+//! runs under a `Watchdog` budget and fails closed, so a runtime only changes how a
+//! policy is written, not what the Gate can rely on.
+//!
+//! `differential` runs bytecode through both runtimes and reports any case where
+//! their decisions differ.
+
+use std::fmt;
+
+use super::interp::{decide, decide_traced, Trace, Verdict};
+use super::verify::{verify, BoundednessProof, VerifyError};
+use crate::wcet::watchdog::{BudgetExceeded, Watchdog};
+
+pub trait PolicyRuntime: fmt::Debug + Send + Sync {
+    /// Recorded with each policy version, e.g. `native`.
+    fn name(&self) -> &'static str;
+
+    /// Load-time check of `payload`. Its proof's budget bounds every evaluation.
+    fn verify(&self, payload: &[u8]) -> Result<BoundednessProof, VerifyError>;
+
+    /// Evaluates a verified `payload` against `context`. Faults deny with one of the
+    /// interpreter's reserved `REASON_*` codes; only an exhausted budget is an error.
+    // `BudgetExceeded` is returned unboxed, as by `Watchdog::step`.
+    #[allow(clippy::result_large_err)]
+    fn decide(&self, payload: &[u8], context: &[u8], watchdog: &mut Watchdog<'_>) -> Result<Verdict, BudgetExceeded>;
+
+    /// `decide`, recording the conditions the policy evaluated into `trace`. Runtimes
+    /// that cannot see inside a policy leave it empty.
+    #[allow(clippy::result_large_err)]
+    fn decide_traced(&self, payload: &[u8], context: &[u8], watchdog: &mut Watchdog<'_>, trace: &mut Trace) -> Result<Verdict, BudgetExceeded> {
+        let _ = trace;
+        self.decide(payload, context, watchdog)
+    }
+}
+
+/// The bytecode VM.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeVm;
+
+pub const NATIVE: &str = "native";
+
+impl PolicyRuntime for NativeVm {
+    fn name(&self) -> &'static str {
+        NATIVE
+    }
+
+    fn verify(&self, payload: &[u8]) -> Result<BoundednessProof, VerifyError> {
+        verify(payload)
+    }
+
+    fn decide(&self, payload: &[u8], context: &[u8], watchdog: &mut Watchdog<'_>) -> Result<Verdict, BudgetExceeded> {
+        decide(payload, context, watchdog)
+    }
+
+    fn decide_traced(&self, payload: &[u8], context: &[u8], watchdog: &mut Watchdog<'_>, trace: &mut Trace) -> Result<Verdict, BudgetExceeded> {
+        decide_traced(payload, context, watchdog, trace)
+    }
+}
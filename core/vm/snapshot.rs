@@ -0,0 +1,249 @@
+//! Suspending and resuming policy evaluations.
+//!
+//! `Machine` runs native bytecode as `interp::decide` does, on the same instruction
+//! semantics and under a `Watchdog`, but can stop after any instruction and be
+//! captured as a `Snapshot`: the program counter, the operand stack, the steps taken
+//! and, once the run has ended, its verdict, together with the blake3 hashes of the
+//! policy and context it runs against. `Snapshot::to_bytes` is canonical, one state
+//! having exactly one encoding, so a snapshot can be hashed and recorded in the
+//! ledger. `Machine::restore` continues a snapshot, given the same policy and
+//! context, exactly where it stopped: a run split across any number of snapshots and
+//! processes reaches the verdict an uninterrupted run would, in the same total
+//! number of steps.
+//!
+//! Each `run` is charged to its own watchdog, so the budget of a restored run should
+//! be what the proof allows less `Snapshot::steps`.
+//!
+//! Layout, integers little-endian: `RFSN-VM\0`, the format version byte, the policy
+//! and context hashes (32 bytes each), `pc` (u32), `steps` (u64), a state byte (0
+//! running, 1 allowed, 2 denied, 3 escalated) followed by the reason (u16) for the
+//! last two, the stack depth (u8) and the stack, bottom first (i64 each).
+
+use std::fmt;
+
+use super::bytecode::{fetch, INSTR_LEN};
+use super::interp::{execute, Stack, Step, Verdict, FRAME_BYTES, REASON_MALFORMED, REASON_NO_DECISION, STACK_DEPTH};
+use crate::wcet::watchdog::{BudgetExceeded, Watchdog};
+
+const MAGIC: &[u8; 8] = b"RFSN-VM\0";
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub policy_hash: [u8; 32],
+    pub context_hash: [u8; 32],
+    /// The next instruction, or the deciding one once the run has ended.
+    pub pc: u32,
+    pub steps: u64,
+    /// Bottom first.
+    pub stack: Vec<i64>,
+    /// The verdict, once the run has ended.
+    pub outcome: Option<Verdict>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    Malformed(&'static str),
+    UnsupportedVersion(u8),
+    /// The snapshot is of a run of other bytecode.
+    PolicyMismatch,
+    /// The snapshot is of a run against another context.
+    ContextMismatch,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Malformed(what) => write!(f, "malformed VM snapshot: {}", what),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported VM snapshot version {}", v),
+            SnapshotError::PolicyMismatch => write!(f, "the snapshot is of another policy"),
+            SnapshotError::ContextMismatch => write!(f, "the snapshot is of another context"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(SNAPSHOT_VERSION);
+        out.extend_from_slice(&self.policy_hash);
+        out.extend_from_slice(&self.context_hash);
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(&self.steps.to_le_bytes());
+        match self.outcome {
+            None => out.push(0),
+            Some(Verdict::Allow) => out.push(1),
+            Some(Verdict::Deny { reason }) => {
+                out.push(2);
+                out.extend_from_slice(&reason.to_le_bytes());
+            }
+            Some(Verdict::Escalate { reason }) => {
+                out.push(3);
+                out.extend_from_slice(&reason.to_le_bytes());
+            }
+        }
+        out.push(self.stack.len() as u8);
+        for value in &self.stack {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out
+    }
+
+    /// Decodes what `to_bytes` encodes, and only that: trailing bytes, an unknown
+    /// state or a stack deeper than the VM's are refused.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut rest = bytes.strip_prefix(MAGIC.as_slice()).ok_or(SnapshotError::Malformed("bad magic"))?;
+        let mut take = |n: usize| -> Result<&[u8], SnapshotError> {
+            let (head, tail) = rest.split_at_checked(n).ok_or(SnapshotError::Malformed("truncated"))?;
+            rest = tail;
+            Ok(head)
+        };
+        let version = take(1)?[0];
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let policy_hash = take(32)?.try_into().expect("32 bytes");
+        let context_hash = take(32)?.try_into().expect("32 bytes");
+        let pc = u32::from_le_bytes(take(4)?.try_into().expect("4 bytes"));
+        let steps = u64::from_le_bytes(take(8)?.try_into().expect("8 bytes"));
+        let outcome = match take(1)?[0] {
+            0 => None,
+            1 => Some(Verdict::Allow),
+            2 => Some(Verdict::Deny { reason: u16::from_le_bytes(take(2)?.try_into().expect("2 bytes")) }),
+            3 => Some(Verdict::Escalate { reason: u16::from_le_bytes(take(2)?.try_into().expect("2 bytes")) }),
+            _ => return Err(SnapshotError::Malformed("unknown state")),
+        };
+        let depth = take(1)?[0] as usize;
+        if depth > STACK_DEPTH {
+            return Err(SnapshotError::Malformed("stack deeper than the VM's"));
+        }
+        let stack = (0..depth).map(|_| take(8).map(|b| i64::from_le_bytes(b.try_into().expect("8 bytes")))).collect::<Result<_, _>>()?;
+        if !rest.is_empty() {
+            return Err(SnapshotError::Malformed("trailing bytes"));
+        }
+        Ok(Self { policy_hash, context_hash, pc, steps, stack, outcome })
+    }
+}
+
+/// A native policy evaluation that can be suspended and resumed.
+pub struct Machine<'a> {
+    policy: &'a [u8],
+    context: &'a [u8],
+    stack: Stack,
+    pc: usize,
+    steps: u64,
+    outcome: Option<Verdict>,
+}
+
+impl<'a> Machine<'a> {
+    /// A run of `policy` against `context`, before its first instruction.
+    pub fn new(policy: &'a [u8], context: &'a [u8]) -> Self {
+        let outcome = (!policy.len().is_multiple_of(INSTR_LEN)).then_some(Verdict::Deny { reason: REASON_MALFORMED });
+        Self { policy, context, stack: Stack::new(), pc: 0, steps: 0, outcome }
+    }
+
+    /// The run `snapshot` captured, continuing where it stopped.
+    pub fn restore(policy: &'a [u8], context: &'a [u8], snapshot: &Snapshot) -> Result<Self, SnapshotError> {
+        if *blake3::hash(policy).as_bytes() != snapshot.policy_hash {
+            return Err(SnapshotError::PolicyMismatch);
+        }
+        if *blake3::hash(context).as_bytes() != snapshot.context_hash {
+            return Err(SnapshotError::ContextMismatch);
+        }
+        let stack = Stack::from_slice(&snapshot.stack).ok_or(SnapshotError::Malformed("stack deeper than the VM's"))?;
+        Ok(Self { policy, context, stack, pc: snapshot.pc as usize, steps: snapshot.steps, outcome: snapshot.outcome })
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            policy_hash: *blake3::hash(self.policy).as_bytes(),
+            context_hash: *blake3::hash(self.context).as_bytes(),
+            pc: self.pc as u32,
+            steps: self.steps,
+            stack: self.stack.as_slice().to_vec(),
+            outcome: self.outcome,
+        }
+    }
+
+    /// Steps taken since the run began, across every restore.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn outcome(&self) -> Option<Verdict> {
+        self.outcome
+    }
+
+    /// Runs until the verdict, or until `pause_after` instructions if given, in
+    /// which case `None` is returned and the run can be snapshotted or continued.
+    // `BudgetExceeded` is returned unboxed, as by `Watchdog::step`.
+    #[allow(clippy::result_large_err)]
+    pub fn run(&mut self, watchdog: &mut Watchdog<'_>, pause_after: Option<u64>) -> Result<Option<Verdict>, BudgetExceeded> {
+        if self.outcome.is_some() {
+            return Ok(self.outcome);
+        }
+        watchdog.enter_frame(FRAME_BYTES)?;
+        let ran = self.advance(watchdog, pause_after);
+        watchdog.exit_frame(FRAME_BYTES);
+        ran?;
+        Ok(self.outcome)
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn advance(&mut self, watchdog: &mut Watchdog<'_>, pause_after: Option<u64>) -> Result<(), BudgetExceeded> {
+        let mut remaining = pause_after;
+        while remaining != Some(0) {
+            let Some((opcode, operand)) = fetch(self.policy, self.pc) else {
+                self.outcome = Some(Verdict::Deny { reason: REASON_NO_DECISION });
+                return Ok(());
+            };
+            watchdog.step()?;
+            self.steps += 1;
+            remaining = remaining.map(|n| n - 1);
+            match execute(opcode, operand, self.pc, self.context, &mut self.stack) {
+                Ok(Step::Next) => self.pc += 1,
+                Ok(Step::Jump(target)) => self.pc = target,
+                Ok(Step::Decide(verdict)) => {
+                    self.outcome = Some(verdict);
+                    return Ok(());
+                }
+                Err(reason) => {
+                    self.outcome = Some(Verdict::Deny { reason });
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::bytecode::*;
+    use crate::vm::interp::decide;
+    use crate::wcet::watchdog::ExecutionBudget;
+
+    #[test]
+    fn restored_runs_continue_identically() {
+        // Escalate if context[0] > 5, else deny with reason 3.
+        let policy: Vec<u8> = [(LOAD8, 0), (PUSH, 5), (GT, 0), (JZ, 5), (ESCALATE, 9), (DENY, 3)].iter().flat_map(|&(op, arg)| encode(op, arg)).collect();
+        let context = [8u8];
+        let budget = || Watchdog::new("test", ExecutionBudget::steps(100));
+        let expected = decide(&policy, &context, &mut budget()).unwrap();
+
+        let mut machine = Machine::new(&policy, &context);
+        assert_eq!(machine.run(&mut budget(), Some(2)).unwrap(), None);
+        let bytes = machine.snapshot().to_bytes();
+        let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+        assert_eq!((snapshot.pc, snapshot.stack.as_slice(), &snapshot.to_bytes()), (2, &[8, 5][..], &bytes));
+
+        let mut restored = Machine::restore(&policy, &context, &snapshot).unwrap();
+        assert_eq!(restored.run(&mut budget(), None).unwrap(), Some(expected));
+        assert_eq!((restored.steps(), restored.snapshot().outcome), (5, Some(Verdict::Escalate { reason: 9 })));
+        assert_eq!(Machine::restore(&policy, &[2], &snapshot).err(), Some(SnapshotError::ContextMismatch));
+        assert!(matches!(Snapshot::from_bytes(&[bytes.as_slice(), &[0]].concat()), Err(SnapshotError::Malformed(_))));
+    }
+}
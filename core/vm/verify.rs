@@ -0,0 +1,243 @@
+//! Load-time verification of policy bytecode.
+//!
+//! A policy is only loaded once it is proven to terminate within a known number of
+//! steps and to use its operand stack correctly on every path. Termination follows
+//! from the jump rules (in range, strictly forward: the control-flow graph is a DAG);
+//! the step bound is the longest path through it. Stack use is checked by tracking
+//! the depth along every edge: it must never drop below what an instruction pops or
+//! exceed `STACK_DEPTH`, and paths that merge must agree on it. A verified policy can
+//! still be denied at runtime, e.g. for reading past a short context, but never for
+//! looping or stack misuse. The resulting `BoundednessProof` is recorded in the
+//! `BudgetRegistry` and sizes the policy's watchdog budget.
+//!
+//! `verify_constant_time` additionally takes the context byte ranges that hold
+//! secrets (credentials, tokens) and tracks which stack slots derive from them. A
+//! policy passes only if it never branches on such a value: it may compare and
+//! combine secrets, and hand the result to `DECIDE`, so the instructions it executes,
+//! and with the VM's branch-free comparisons its latency, do not depend on them.
+
+use std::fmt;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use super::bytecode::*;
+use super::interp::{FRAME_BYTES, STACK_DEPTH};
+use crate::wcet::budget::BudgetRegistry;
+use crate::wcet::static_bound::{analyze, AnalysisError, CostModel, Decoder, Flow};
+use crate::wcet::watchdog::ExecutionBudget;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /// Undecodable bytecode, a jump out of range or backwards, or a path that runs
+    /// off the end.
+    Structure(AnalysisError),
+    StackUnderflow { pc: usize, depth: usize, pops: usize },
+    StackOverflow { pc: usize },
+    /// Two paths reach `pc` with different stack depths.
+    StackMismatch { pc: usize, depths: (usize, usize) },
+    /// A `JZ` at `pc` branches on a value derived from a secret context field.
+    SecretBranch { pc: usize },
+    /// A policy module the runtime cannot load; see `runtime`.
+    Module(String),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Structure(e) => write!(f, "{}", e),
+            VerifyError::StackUnderflow { pc, depth, pops } => {
+                write!(f, "instruction at pc {} pops {} values from a stack of {}", pc, pops, depth)
+            }
+            VerifyError::StackOverflow { pc } => write!(f, "stack exceeds {} slots at pc {}", STACK_DEPTH, pc),
+            VerifyError::StackMismatch { pc, depths } => {
+                write!(f, "paths reach pc {} with stack depths {} and {}", pc, depths.0, depths.1)
+            }
+            VerifyError::SecretBranch { pc } => write!(f, "instruction at pc {} branches on a secret", pc),
+            VerifyError::Module(e) => write!(f, "policy module rejected: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+impl From<AnalysisError> for VerifyError {
+    fn from(e: AnalysisError) -> Self {
+        VerifyError::Structure(e)
+    }
+}
+
+/// What verification established about one policy payload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoundednessProof {
+    /// Hex blake3 of the verified payload.
+    pub policy_hash: String,
+    pub instructions: usize,
+    /// Instructions executed on the longest path; the VM cannot take more steps.
+    pub max_steps: u64,
+    pub max_stack_depth: usize,
+    /// Context byte ranges the policy was proven never to branch on; empty unless
+    /// verified with `verify_constant_time`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secrets: Vec<Range<u32>>,
+}
+
+impl BoundednessProof {
+    /// Watchdog budget that a verified run of this policy can never exceed.
+    pub fn execution_budget(&self) -> ExecutionBudget {
+        ExecutionBudget::steps(self.max_steps).with_stack(FRAME_BYTES, 1)
+    }
+}
+
+/// `(pops, pushes)` of an opcode.
+fn stack_effect(opcode: u8) -> (usize, usize) {
+    match opcode {
+        PUSH | LOAD8 | LOAD32 | CTXLEN => (0, 1),
+        POP | JZ | DECIDE => (1, 0),
+        DUP => (1, 2),
+        SWAP => (2, 2),
+        NOT => (1, 1),
+        ADD | SUB | MUL | AND | OR | XOR | EQ | LT | GT => (2, 1),
+        _ => (0, 0),
+    }
+}
+
+/// Taint of the stack after an instruction, one bit per slot, given the taint
+/// `before` it at `depth`. Loads of a secret byte taint their slot, and operators
+/// taint their result if any operand is; everything else pushes clean values.
+fn taint_after(opcode: u8, operand: u32, depth: usize, before: u64, secrets: &[Range<u32>]) -> u64 {
+    let bit = |slot: usize| (before >> slot) & 1;
+    let below = |slots: usize| u64::MAX.checked_shl(slots as u32).map_or(before, |above| before & !above);
+    match opcode {
+        LOAD8 | LOAD32 => {
+            let end = operand.saturating_add(if opcode == LOAD8 { 1 } else { 4 });
+            let secret = secrets.iter().any(|s| s.start < end && operand < s.end);
+            below(depth) | (secret as u64) << depth
+        }
+        DUP => below(depth) | bit(depth - 1) << depth,
+        SWAP => below(depth - 2) | bit(depth - 1) << (depth - 2) | bit(depth - 2) << (depth - 1),
+        NOT => before,
+        ADD | SUB | MUL | AND | OR | XOR | EQ | LT | GT => below(depth - 2) | (bit(depth - 1) | bit(depth - 2)) << (depth - 2),
+        _ => below(depth - stack_effect(opcode).0),
+    }
+}
+
+/// Verifies `policy` and returns its proof.
+pub fn verify(policy: &[u8]) -> Result<BoundednessProof, VerifyError> {
+    verify_constant_time(policy, &[])
+}
+
+/// Verifies `policy` as `verify` does, and that no branch depends on the context
+/// bytes in `secrets`.
+pub fn verify_constant_time(policy: &[u8], secrets: &[Range<u32>]) -> Result<BoundednessProof, VerifyError> {
+    let program = PolicyDecoder.decode(policy)?;
+    // Every instruction costs one step, so the longest path in cycles is in steps.
+    let unit = OPCODES.iter().fold(CostModel::new("steps", 0), |model, &opcode| model.cost(opcode, 1));
+    let bound = analyze(&program, &unit)?;
+
+    let mut depth: Vec<Option<usize>> = vec![None; program.len()];
+    let mut taint = vec![0u64; program.len()];
+    depth[0] = Some(0);
+    let mut max_stack_depth = 0;
+    // Edges only point forward, so every predecessor of `pc` has been visited.
+    for pc in 0..program.len() {
+        let Some(before) = depth[pc] else { continue };
+        let instr = program[pc];
+        let (pops, pushes) = stack_effect(instr.opcode);
+        if before < pops {
+            return Err(VerifyError::StackUnderflow { pc, depth: before, pops });
+        }
+        let after = before - pops + pushes;
+        if after > STACK_DEPTH {
+            return Err(VerifyError::StackOverflow { pc });
+        }
+        if instr.opcode == JZ && (taint[pc] >> (before - 1)) & 1 == 1 {
+            return Err(VerifyError::SecretBranch { pc });
+        }
+        let (_, operand) = fetch(policy, pc).expect("decoded");
+        let tainted = taint_after(instr.opcode, operand, before, taint[pc], secrets);
+        max_stack_depth = max_stack_depth.max(after);
+        let successors = match instr.flow {
+            Flow::Next => [Some(pc + 1), None],
+            Flow::Jump(target) => [Some(target), None],
+            Flow::Branch(target) => [Some(pc + 1), Some(target)],
+            Flow::Halt => [None, None],
+        };
+        for next in successors.into_iter().flatten() {
+            match depth[next] {
+                Some(existing) if existing != after => {
+                    return Err(VerifyError::StackMismatch { pc: next, depths: (existing, after) });
+                }
+                _ => depth[next] = Some(after),
+            }
+            // A value is secret-derived if it is on any path.
+            taint[next] |= tainted;
+        }
+    }
+
+    Ok(BoundednessProof {
+        policy_hash: blake3::hash(policy).to_hex().to_string(),
+        instructions: program.len(),
+        max_steps: bound.cycles,
+        max_stack_depth,
+        secrets: secrets.to_vec(),
+    })
+}
+
+/// Verifies `policy_payload` and records its proof in `registry`.
+pub fn verify_and_record(registry: &mut BudgetRegistry, policy: &str, policy_payload: &[u8]) -> Result<BoundednessProof, VerifyError> {
+    let proof = verify(policy_payload)?;
+    registry.record_proof(policy, policy_payload, &proof);
+    Ok(proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(instrs: &[(u8, u32)]) -> Vec<u8> {
+        instrs.iter().flat_map(|&(op, arg)| encode(op, arg)).collect()
+    }
+
+    #[test]
+    fn proves_the_longest_path() {
+        // if ctx[0] == 7 { allow } else { push; pop; deny }
+        let policy = program(&[(LOAD8, 0), (PUSH, 7), (EQ, 0), (JZ, 5), (ALLOW, 0), (PUSH, 1), (POP, 0), (DENY, 3)]);
+        let proof = verify(&policy).unwrap();
+        assert_eq!((proof.max_steps, proof.max_stack_depth, proof.instructions), (7, 2, 8));
+        assert_eq!(proof.execution_budget().max_steps, 7);
+    }
+
+    #[test]
+    fn rejects_loops_and_stack_misuse() {
+        let looping = program(&[(PUSH, 1), (JZ, 0), (ALLOW, 0)]);
+        assert_eq!(verify(&looping), Err(VerifyError::Structure(AnalysisError::BackwardJump { pc: 1, target: 0 })));
+        let out_of_range = program(&[(JMP, 9), (ALLOW, 0)]);
+        assert_eq!(verify(&out_of_range), Err(VerifyError::Structure(AnalysisError::JumpOutOfRange { pc: 0, target: 9 })));
+        let underflow = program(&[(PUSH, 1), (ADD, 0), (ALLOW, 0)]);
+        assert_eq!(verify(&underflow), Err(VerifyError::StackUnderflow { pc: 1, depth: 1, pops: 2 }));
+        // The fall-through path pushes one more value than the jump path.
+        let unbalanced = program(&[(CTXLEN, 0), (JZ, 3), (PUSH, 1), (ALLOW, 0)]);
+        assert_eq!(verify(&unbalanced), Err(VerifyError::StackMismatch { pc: 3, depths: (0, 1) }));
+        let overflow = program(&[&[(PUSH, 1); STACK_DEPTH + 1][..], &[(ALLOW, 0)]].concat());
+        assert_eq!(verify(&overflow), Err(VerifyError::StackOverflow { pc: STACK_DEPTH }));
+    }
+
+    #[test]
+    fn constant_time_policies_never_branch_on_secrets() {
+        let secret = 16..20;
+        let token = [secret.clone()];
+        // if ctx[16..20] == 0x1234 { allow } else { deny }
+        let branching = program(&[(LOAD32, 16), (PUSH, 0x1234), (EQ, 0), (JZ, 5), (ALLOW, 0), (DENY, 1)]);
+        assert!(verify(&branching).is_ok());
+        assert_eq!(verify_constant_time(&branching, &token), Err(VerifyError::SecretBranch { pc: 3 }));
+        // Taint survives stack shuffling and arithmetic.
+        let laundered = program(&[(PUSH, 0), (LOAD8, 18), (SWAP, 0), (POP, 0), (PUSH, 1), (ADD, 0), (JZ, 8), (ALLOW, 0), (DENY, 1)]);
+        assert_eq!(verify_constant_time(&laundered, &token), Err(VerifyError::SecretBranch { pc: 6 }));
+
+        // Branching on public inputs is fine; the secret only reaches DECIDE.
+        let deciding = program(&[(LOAD32, 4), (JZ, 3), (DENY, 2), (LOAD32, 16), (PUSH, 0x1234), (EQ, 0), (DECIDE, 1)]);
+        let proof = verify_constant_time(&deciding, &token).unwrap();
+        assert_eq!((proof.secrets, proof.max_steps), (vec![secret], 6));
+    }
+}
@@ -0,0 +1,178 @@
+//! WebAssembly policies, metered with fuel.
+//!
+//! A WASM policy is a module with no imports that exports its `memory` and
+//! `decide(len: i32) -> i64`. The Gate writes the context to the start of memory and
+//! calls `decide` with its length; the result packs the verdict kind into bits 16
+//! and up (0 allow, 1 deny, 2 escalate) and the reason code into the low 16 bits.
+//!
+//! Termination cannot be proven for arbitrary WASM, so it is enforced instead: every
+//! evaluation runs on fuel equal to what remains of the watchdog's step budget, and
+//! fuel spent is charged to the watchdog as steps, so running out is the same
+//! `ExceededLimit::Steps` abort the native VM reports. The proof's `max_steps` is the
+//! configured fuel. Memory is capped per evaluation by `StoreLimits`. With no imports
+//! and NaNs canonicalized a module sees nothing but its context, so its decisions are
+//! as deterministic as the native VM's.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use wasmtime::{Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+
+use super::interp::{Verdict, FRAME_BYTES, REASON_CONTEXT_RANGE, REASON_MALFORMED};
+use super::runtime::PolicyRuntime;
+use super::verify::{BoundednessProof, VerifyError};
+use crate::wcet::watchdog::{BudgetExceeded, Watchdog};
+
+pub const WASM: &str = "wasm";
+
+/// Default fuel per evaluation.
+pub const DEFAULT_FUEL: u64 = 1_000_000;
+
+/// Default cap on a policy's linear memory: 16 pages.
+pub const DEFAULT_MAX_MEMORY_BYTES: usize = 16 * 64 * 1024;
+
+pub struct WasmRuntime {
+    engine: Engine,
+    fuel: u64,
+    max_memory_bytes: usize,
+    /// Compiled modules by payload hash, filled by `verify`.
+    modules: Mutex<HashMap<String, Module>>,
+}
+
+impl std::fmt::Debug for WasmRuntime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WasmRuntime").field("fuel", &self.fuel).field("max_memory_bytes", &self.max_memory_bytes).finish()
+    }
+}
+
+fn unloadable(e: impl std::fmt::Display) -> VerifyError {
+    VerifyError::Module(e.to_string())
+}
+
+impl WasmRuntime {
+    pub fn new(fuel: u64, max_memory_bytes: usize) -> Result<Self, VerifyError> {
+        let mut config = Config::new();
+        config.consume_fuel(true).cranelift_nan_canonicalization(true).wasm_threads(false);
+        let engine = Engine::new(&config).map_err(unloadable)?;
+        Ok(Self { engine, fuel, max_memory_bytes, modules: Mutex::new(HashMap::new()) })
+    }
+
+    fn store(&self, fuel: u64) -> Result<Store<StoreLimits>, wasmtime::Error> {
+        let limits = StoreLimitsBuilder::new().memory_size(self.max_memory_bytes).instances(1).tables(1).build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(fuel)?;
+        Ok(store)
+    }
+}
+
+/// Unpacks `decide`'s result.
+fn unpack(result: i64) -> Verdict {
+    let reason = (result & 0xffff) as u16;
+    match result >> 16 {
+        0 if reason == 0 => Verdict::Allow,
+        1 => Verdict::Deny { reason },
+        2 => Verdict::Escalate { reason },
+        _ => Verdict::Deny { reason: REASON_MALFORMED },
+    }
+}
+
+impl PolicyRuntime for WasmRuntime {
+    fn name(&self) -> &'static str {
+        WASM
+    }
+
+    /// Compiles the module and checks it imports nothing and exports the policy ABI.
+    fn verify(&self, payload: &[u8]) -> Result<BoundednessProof, VerifyError> {
+        let module = Module::new(&self.engine, payload).map_err(unloadable)?;
+        if let Some(import) = module.imports().next() {
+            return Err(VerifyError::Module(format!("policy imports {}::{}", import.module(), import.name())));
+        }
+        // Fueled, so a start function cannot hang the load.
+        let mut store = self.store(self.fuel).map_err(unloadable)?;
+        let instance = Instance::new(&mut store, &module, &[]).map_err(unloadable)?;
+        instance.get_memory(&mut store, "memory").ok_or_else(|| VerifyError::Module("policy exports no memory".into()))?;
+        instance.get_typed_func::<i32, i64>(&mut store, "decide").map_err(unloadable)?;
+
+        let policy_hash = blake3::hash(payload).to_hex().to_string();
+        self.modules.lock().unwrap_or_else(|e| e.into_inner()).insert(policy_hash.clone(), module);
+        Ok(BoundednessProof { policy_hash, instructions: 0, max_steps: self.fuel, max_stack_depth: 0, secrets: Vec::new() })
+    }
+
+    fn decide(&self, payload: &[u8], context: &[u8], watchdog: &mut Watchdog<'_>) -> Result<Verdict, BudgetExceeded> {
+        let hash = blake3::hash(payload).to_hex().to_string();
+        let Some(module) = self.modules.lock().unwrap_or_else(|e| e.into_inner()).get(&hash).cloned() else {
+            // Never verified by this runtime.
+            return Ok(Verdict::Deny { reason: REASON_MALFORMED });
+        };
+        watchdog.enter_frame(FRAME_BYTES)?;
+        let fuel = watchdog.remaining_steps();
+        let (result, spent) = match self.store(fuel) {
+            Ok(mut store) => {
+                let result = run(&mut store, &module, context);
+                (result, fuel - store.get_fuel().unwrap_or(0))
+            }
+            Err(e) => (Err(e), 0),
+        };
+        let verdict = match result {
+            Ok(verdict) => watchdog.charge(spent).map(|()| verdict),
+            Err(e) if e.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel) => watchdog.charge(fuel + 1).map(|()| Verdict::Deny { reason: REASON_MALFORMED }),
+            Err(_) => watchdog.charge(spent).map(|()| Verdict::Deny { reason: REASON_MALFORMED }),
+        };
+        watchdog.exit_frame(FRAME_BYTES);
+        verdict
+    }
+}
+
+fn run(store: &mut Store<StoreLimits>, module: &Module, context: &[u8]) -> Result<Verdict, wasmtime::Error> {
+    let instance = Instance::new(&mut *store, module, &[])?;
+    let memory = instance.get_memory(&mut *store, "memory").ok_or_else(|| wasmtime::Error::msg("policy exports no memory"))?;
+    let decide = instance.get_typed_func::<i32, i64>(&mut *store, "decide")?;
+    let pages = (context.len() as u64).div_ceil(64 * 1024);
+    let size = memory.size(&*store);
+    if size < pages && memory.grow(&mut *store, pages - size).is_err() {
+        return Ok(Verdict::Deny { reason: REASON_CONTEXT_RANGE });
+    }
+    memory.write(&mut *store, 0, context)?;
+    Ok(unpack(decide.call(&mut *store, context.len() as i32)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wcet::watchdog::{ExceededLimit, ExecutionBudget};
+
+    /// Allows if the first context byte is 7, else denies with reason 42.
+    const BYTE_IS_SEVEN: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "decide") (param $len i32) (result i64)
+            (if (result i64) (i32.eq (i32.load8_u (i32.const 0)) (i32.const 7))
+                (then (i64.const 0))
+                (else (i64.const 0x1002a)))))"#;
+
+    const SPINS: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "decide") (param $len i32) (result i64)
+            (loop $forever (br $forever))
+            (i64.const 0)))"#;
+
+    #[test]
+    fn evaluates_with_fuel_as_steps() {
+        let runtime = WasmRuntime::new(10_000, DEFAULT_MAX_MEMORY_BYTES).unwrap();
+        let proof = runtime.verify(BYTE_IS_SEVEN.as_bytes()).unwrap();
+        // `BudgetExceeded` is returned unboxed, as by `Watchdog::step`.
+        #[allow(clippy::result_large_err)]
+        let eval = |context: &[u8]| runtime.decide(BYTE_IS_SEVEN.as_bytes(), context, &mut Watchdog::new("test", proof.execution_budget()));
+        assert_eq!(eval(&[7]).unwrap(), Verdict::Allow);
+        assert_eq!(eval(&[8]).unwrap(), Verdict::Deny { reason: 42 });
+
+        let proof = runtime.verify(SPINS.as_bytes()).unwrap();
+        let err = runtime.decide(SPINS.as_bytes(), &[], &mut Watchdog::new("test", proof.execution_budget())).unwrap_err();
+        assert_eq!(err.limit, ExceededLimit::Steps);
+
+        let importing = r#"(module (import "env" "clock" (func)) (memory (export "memory") 1))"#;
+        assert!(matches!(runtime.verify(importing.as_bytes()), Err(VerifyError::Module(_))));
+        let unverified = runtime.decide(b"(module)", &[], &mut Watchdog::new("test", ExecutionBudget::steps(10)));
+        assert_eq!(unverified.unwrap(), Verdict::Deny { reason: REASON_MALFORMED });
+    }
+}
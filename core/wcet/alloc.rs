@@ -0,0 +1,196 @@
+//! Heap accounting for policy runs.
+//!
+//! A cycle bound says nothing about memory: a policy that stays within its cycles can
+//! still allocate without limit. `AccountingAllocator` wraps the global allocator and,
+//! while an `AllocScope` is open on the current thread, counts allocations and tracks
+//! the peak of live bytes. Binaries that want heap bounds install it:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOC: AccountingAllocator<System> = AccountingAllocator::new(System);
+//! ```
+//!
+//! Without it every scope reports zero and `is_installed` is false. Counting is per
+//! thread, so allocations of other threads never leak into a policy's numbers.
+
+use std::alloc::{GlobalAlloc, Layout};
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+/// Heap usage of one run, or the maximum over many.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeapUsage {
+    /// Highest number of live bytes allocated inside the scope.
+    pub peak_bytes: u64,
+    pub allocations: u64,
+}
+
+impl HeapUsage {
+    /// Element-wise maximum, for folding usages over many runs.
+    pub fn max(self, other: Self) -> Self {
+        Self { peak_bytes: self.peak_bytes.max(other.peak_bytes), allocations: self.allocations.max(other.allocations) }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Counters {
+    active: bool,
+    /// Net bytes allocated since the scope opened; negative when the scope frees
+    /// memory allocated before it.
+    live: i64,
+    peak: i64,
+    allocations: u64,
+}
+
+const IDLE: Counters = Counters { active: false, live: 0, peak: 0, allocations: 0 };
+
+thread_local! {
+    // `const` initialization: no lazy init and no destructor, so it is safe to touch
+    // from inside the allocator.
+    static COUNTERS: Cell<Counters> = const { Cell::new(IDLE) };
+}
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Whether an `AccountingAllocator` is the global allocator (has served an allocation).
+pub fn is_installed() -> bool {
+    INSTALLED.load(Ordering::Relaxed)
+}
+
+fn account(delta: i64, allocations: u64) {
+    // `try_with` fails during thread teardown; those allocations are not a policy's.
+    let _ = COUNTERS.try_with(|c| {
+        let mut counters = c.get();
+        if counters.active {
+            counters.live += delta;
+            counters.peak = counters.peak.max(counters.live);
+            counters.allocations += allocations;
+            c.set(counters);
+        }
+    });
+}
+
+/// Global allocator wrapper that feeds the open `AllocScope` of the calling thread.
+pub struct AccountingAllocator<A> {
+    inner: A,
+}
+
+impl<A> AccountingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+}
+
+// SAFETY: every call is forwarded unchanged to `inner`; the accounting only touches a
+// `const`-initialized thread-local and never allocates.
+unsafe impl<A: GlobalAlloc> GlobalAlloc for AccountingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        INSTALLED.store(true, Ordering::Relaxed);
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            account(layout.size() as i64, 1);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        INSTALLED.store(true, Ordering::Relaxed);
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            account(layout.size() as i64, 1);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        account(-(layout.size() as i64), 0);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = self.inner.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            account(new_size as i64 - layout.size() as i64, 1);
+        }
+        new
+    }
+}
+
+/// Counts the current thread's allocations until finished or dropped. Scopes nest: an
+/// inner scope's usage is folded into the outer one when it ends.
+pub struct AllocScope {
+    outer: Counters,
+    // Tied to the thread whose counters it swapped out.
+    _thread: PhantomData<*const ()>,
+}
+
+impl AllocScope {
+    pub fn start() -> Self {
+        let outer = COUNTERS.with(|c| c.replace(Counters { active: true, ..IDLE }));
+        Self { outer, _thread: PhantomData }
+    }
+
+    /// Usage so far.
+    pub fn usage(&self) -> HeapUsage {
+        let counters = COUNTERS.with(Cell::get);
+        HeapUsage { peak_bytes: counters.peak.max(0) as u64, allocations: counters.allocations }
+    }
+
+    pub fn finish(self) -> HeapUsage {
+        self.usage()
+    }
+}
+
+impl Drop for AllocScope {
+    fn drop(&mut self) {
+        COUNTERS.with(|c| {
+            let inner = c.get();
+            let mut outer = self.outer;
+            if outer.active {
+                outer.peak = outer.peak.max(outer.live + inner.peak);
+                outer.live += inner.live;
+                outer.allocations += inner.allocations;
+            }
+            c.set(outer);
+        });
+    }
+}
+
+/// Runs `f` in its own scope and returns its result with its heap usage.
+pub fn measure_heap<T, F: FnOnce() -> T>(f: F) -> (T, HeapUsage) {
+    let scope = AllocScope::start();
+    let result = f();
+    (result, scope.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::System;
+
+    // Not the test binary's global allocator; driven by hand so only these calls count.
+    static ACCOUNTING: AccountingAllocator<System> = AccountingAllocator::new(System);
+
+    #[test]
+    fn scopes_track_peak_live_bytes_and_fold_into_their_outer_scope() {
+        let small = Layout::from_size_align(64, 8).unwrap();
+        let large = Layout::from_size_align(1_024, 8).unwrap();
+        let outer = AllocScope::start();
+        // SAFETY: every block is freed once, with the layout it was allocated with.
+        unsafe {
+            let a = ACCOUNTING.alloc(small);
+            let ((), inner) = measure_heap(|| {
+                let b = ACCOUNTING.alloc_zeroed(large);
+                let b = ACCOUNTING.realloc(b, large, 2_048);
+                ACCOUNTING.dealloc(b, Layout::from_size_align(2_048, 8).unwrap());
+            });
+            assert_eq!(inner, HeapUsage { peak_bytes: 2_048, allocations: 2 });
+            ACCOUNTING.dealloc(a, small);
+        }
+        assert_eq!(outer.finish(), HeapUsage { peak_bytes: 64 + 2_048, allocations: 3 });
+        assert!(is_installed());
+    }
+}
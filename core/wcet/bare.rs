@@ -0,0 +1,176 @@
+//! `no_std` measurement core for bare-metal and RTOS targets.
+//!
+//! Everything else in `wcet` assumes a hosted OS (perf events, `/proc`, heap-backed
+//! histograms). This module uses `core` only and allocates nothing, so it builds for
+//! microcontrollers: the crate's `std` feature off gives a `#![no_std]` build that
+//! exposes just this module. The target supplies its own cycle counter as a closure,
+//! e.g. `DWT->CYCCNT` on Cortex-M or `mcycle` on RISC-V:
+//!
+//! ```ignore
+//! let counter = CycleCounter::new(|| DWT::cycle_count() as u64, 32);
+//! let profile = profile(counter, 1_000, || { policy.decide(&ctx); });
+//! assert!(profile.max_cycles <= BUDGET_CYCLES);
+//! ```
+//!
+//! Native stack usage is measured by paint-and-scan: `paint_stack` the task's stack
+//! before it starts, run the policy corpus, then read `stack_high_water`.
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// A user-provided cycle counter read plus its width; deltas are taken modulo 2^bits.
+pub struct CycleCounter<R: FnMut() -> u64> {
+    read: R,
+    mask: u64,
+}
+
+impl<R: FnMut() -> u64> CycleCounter<R> {
+    pub fn new(read: R, counter_bits: u32) -> Self {
+        let mask = if counter_bits >= 64 { u64::MAX } else { (1u64 << counter_bits) - 1 };
+        Self { read, mask }
+    }
+
+    /// Cycles spent in `f`, correct across one counter wrap. The compiler fences keep
+    /// `f` between the reads; in-order cores need nothing more, out-of-order ones
+    /// should add their own barrier to `read`.
+    pub fn measure<F: FnOnce()>(&mut self, f: F) -> u64 {
+        let start = (self.read)();
+        compiler_fence(Ordering::SeqCst);
+        f();
+        compiler_fence(Ordering::SeqCst);
+        let end = (self.read)();
+        end.wrapping_sub(start) & self.mask
+    }
+}
+
+/// Power-of-two buckets: bucket `i` holds values with bit length `i`.
+const BARE_BUCKETS: usize = 65;
+
+/// Fixed-size summary of a profiling run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BareProfile {
+    pub iterations: u64,
+    pub min_cycles: u64,
+    pub max_cycles: u64,
+    sum: u128,
+    buckets: [u32; BARE_BUCKETS],
+}
+
+impl Default for BareProfile {
+    fn default() -> Self {
+        Self { iterations: 0, min_cycles: u64::MAX, max_cycles: 0, sum: 0, buckets: [0; BARE_BUCKETS] }
+    }
+}
+
+impl BareProfile {
+    pub fn record(&mut self, cycles: u64) {
+        self.iterations += 1;
+        self.min_cycles = self.min_cycles.min(cycles);
+        self.max_cycles = self.max_cycles.max(cycles);
+        self.sum += cycles as u128;
+        let bucket = (u64::BITS - cycles.leading_zeros()) as usize;
+        self.buckets[bucket] = self.buckets[bucket].saturating_add(1);
+    }
+
+    pub fn mean_cycles(&self) -> u64 {
+        if self.iterations == 0 { 0 } else { (self.sum / self.iterations as u128) as u64 }
+    }
+
+    /// Upper bound of the value at `ppm` parts per million of the runs, e.g. `990_000`
+    /// for p99: the top of its power-of-two bucket, capped at the observed maximum.
+    /// Integer-only, since `core` has no float rounding.
+    pub fn percentile_upper_bound(&self, ppm: u32) -> u64 {
+        if self.iterations == 0 {
+            return 0;
+        }
+        let rank = ((self.iterations as u128 * ppm.min(1_000_000) as u128).div_ceil(1_000_000) as u64).max(1);
+        let mut seen = 0u64;
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            seen += count as u64;
+            if seen >= rank {
+                let upper = if bucket >= 64 { u64::MAX } else { (1u64 << bucket) - 1 };
+                return upper.min(self.max_cycles);
+            }
+        }
+        self.max_cycles
+    }
+
+    pub fn within(&self, budget_cycles: u64) -> bool {
+        self.max_cycles <= budget_cycles
+    }
+}
+
+/// Runs `execute` `iterations` times under `counter`.
+pub fn profile<R, F>(mut counter: CycleCounter<R>, iterations: u64, mut execute: F) -> BareProfile
+where
+    R: FnMut() -> u64,
+    F: FnMut(),
+{
+    let mut profile = BareProfile::default();
+    for _ in 0..iterations {
+        profile.record(counter.measure(&mut execute));
+    }
+    profile
+}
+
+/// Fill byte for stack painting.
+pub const STACK_PAINT: u8 = 0xc5;
+
+/// Paints a stack region before the task that owns it starts. `region` is the whole
+/// stack, lowest address first.
+pub fn paint_stack(region: &mut [u8]) {
+    region.fill(STACK_PAINT);
+}
+
+/// Bytes of a painted, downward-growing stack that have been written since painting:
+/// everything above the lowest overwritten byte. Reads the region while the task may
+/// still run, so call it once the task is idle.
+pub fn stack_high_water(region: &[u8]) -> usize {
+    let untouched = region.iter().take_while(|&&b| b == STACK_PAINT).count();
+    region.len() - untouched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_stay_exact_across_a_narrow_counter_wrap() {
+        // A 16-bit counter that advances 100 cycles per read, starting just below its wrap.
+        let mut now = 0xffc0u64;
+        let counter = CycleCounter::new(
+            || {
+                let read = now & 0xffff;
+                now += 100;
+                read
+            },
+            16,
+        );
+        let profile = profile(counter, 10, || {});
+        assert_eq!(profile.iterations, 10);
+        assert_eq!((profile.min_cycles, profile.max_cycles, profile.mean_cycles()), (100, 100, 100));
+        assert!(profile.within(100) && !profile.within(99));
+    }
+
+    #[test]
+    fn percentiles_are_bucket_tops_capped_at_the_maximum() {
+        let mut profile = BareProfile::default();
+        assert_eq!(profile.percentile_upper_bound(990_000), 0);
+        for _ in 0..99 {
+            profile.record(5);
+        }
+        profile.record(1_000);
+        assert_eq!(profile.percentile_upper_bound(500_000), 7);
+        assert_eq!(profile.percentile_upper_bound(990_000), 7);
+        assert_eq!(profile.percentile_upper_bound(1_000_000), 1_000);
+    }
+
+    #[test]
+    fn the_high_water_mark_counts_down_from_the_top_of_the_stack() {
+        let mut stack = [0u8; 256];
+        paint_stack(&mut stack);
+        assert_eq!(stack_high_water(&stack), 0);
+        stack[200..].fill(0);
+        stack[220] = STACK_PAINT;
+        assert_eq!(stack_high_water(&stack), 56);
+    }
+}
@@ -0,0 +1,123 @@
+//! Stored WCET baselines for regression gating.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::cycles::default_cycle_source;
+use super::profile::WcetProfile;
+
+/// Stored WCET result for one policy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineEntry {
+    /// Hex blake3 of the policy payload; a changed policy starts a new baseline.
+    pub policy_hash: String,
+    pub max_vm_cycles: u64,
+    pub capacity_margin: f64,
+    /// `CycleSource::name` the baseline was measured with; other sources are not comparable.
+    pub cycle_source: String,
+}
+
+/// Per-policy baselines, keyed by policy name, stored as pretty JSON so diffs are reviewable.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WcetBaseline {
+    pub version: u32,
+    pub policies: BTreeMap<String, BaselineEntry>,
+}
+
+const BASELINE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BaselineCheck {
+    /// No comparable baseline (new policy, changed policy or other cycle source).
+    New,
+    Within { baseline_cycles: u64, change_pct: f64 },
+    Regressed { baseline_cycles: u64, change_pct: f64 },
+}
+
+impl WcetBaseline {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match fs::read_to_string(path) {
+            Ok(json) => {
+                let baseline: Self = serde_json::from_str(&json)?;
+                if baseline.version != BASELINE_VERSION {
+                    return Err(format!("unsupported WCET baseline version {}", baseline.version).into());
+                }
+                Ok(baseline)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self { version: BASELINE_VERSION, ..Default::default() }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Compares `profile` with the stored baseline for `name`. A measurement more than
+    /// `max_regression_pct` percent slower than the baseline is a regression.
+    pub fn check(&self, name: &str, policy_payload: &[u8], profile: &WcetProfile, max_regression_pct: f64) -> BaselineCheck {
+        let hash = blake3::hash(policy_payload).to_hex().to_string();
+        let source = default_cycle_source().name();
+        let Some(entry) = self.policies.get(name).filter(|e| e.policy_hash == hash && e.cycle_source == source) else {
+            return BaselineCheck::New;
+        };
+        let change_pct = (profile.max_vm_cycles as f64 - entry.max_vm_cycles as f64) / entry.max_vm_cycles.max(1) as f64 * 100.0;
+        if change_pct > max_regression_pct {
+            BaselineCheck::Regressed { baseline_cycles: entry.max_vm_cycles, change_pct }
+        } else {
+            BaselineCheck::Within { baseline_cycles: entry.max_vm_cycles, change_pct }
+        }
+    }
+
+    pub fn record(&mut self, name: &str, policy_payload: &[u8], profile: &WcetProfile) {
+        self.version = BASELINE_VERSION;
+        self.policies.insert(
+            name.to_string(),
+            BaselineEntry {
+                policy_hash: blake3::hash(policy_payload).to_hex().to_string(),
+                max_vm_cycles: profile.max_vm_cycles,
+                capacity_margin: profile.capacity_margin,
+                cycle_source: default_cycle_source().name().to_string(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wcet::disturb::MeasurementMode;
+    use crate::wcet::profile::profile;
+
+    fn measured(max_vm_cycles: u64) -> WcetProfile {
+        let mut profile = profile(1, 10_000, MeasurementMode::Warm, || {});
+        profile.max_vm_cycles = max_vm_cycles;
+        profile
+    }
+
+    #[test]
+    fn regressions_are_gated_against_the_baseline_of_the_same_policy() {
+        let path = std::env::temp_dir().join(format!("rfsn-wcet-baseline-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut baseline = WcetBaseline::load(&path).unwrap();
+        assert_eq!(baseline.check("arm", b"policy v1", &measured(1_000), 10.0), BaselineCheck::New);
+        baseline.record("arm", b"policy v1", &measured(1_000));
+        baseline.save(&path).unwrap();
+
+        let baseline = WcetBaseline::load(&path).unwrap();
+        assert_eq!(baseline.check("arm", b"policy v1", &measured(1_100), 10.0), BaselineCheck::Within { baseline_cycles: 1_000, change_pct: 10.0 });
+        assert_eq!(baseline.check("arm", b"policy v1", &measured(1_200), 10.0), BaselineCheck::Regressed { baseline_cycles: 1_000, change_pct: 20.0 });
+        assert_eq!(baseline.check("arm", b"policy v2", &measured(5_000), 10.0), BaselineCheck::New);
+
+        fs::write(&path, r#"{ "version": 2, "policies": {} }"#).unwrap();
+        assert!(WcetBaseline::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+//! Per-policy WCET budgets, checked when a policy is loaded.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::profile::{WcetProfile, DEFAULT_BUDGET_CYCLES};
+use crate::vm::verify::BoundednessProof;
+
+/// Declared and established WCET bounds of one policy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Budget {
+    /// Hex blake3 of the policy payload the bounds were established for.
+    pub policy_hash: String,
+    pub declared_cycles: u64,
+    /// Observed maximum from the harness.
+    #[serde(default)]
+    pub measured_cycles: Option<u64>,
+    /// Upper bound from static analysis of the bytecode.
+    #[serde(default)]
+    pub estimated_cycles: Option<u64>,
+    /// Step bound proven by the bytecode verifier.
+    #[serde(default)]
+    pub proven_max_steps: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BudgetViolation {
+    Undeclared { policy: String },
+    /// The payload differs from the one the bounds were established for.
+    Unprofiled { policy: String },
+    /// Neither a measurement nor an estimate exists yet.
+    Unbounded { policy: String },
+    OverBudget { policy: String, bound_cycles: u64, declared_cycles: u64 },
+}
+
+impl std::fmt::Display for BudgetViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetViolation::Undeclared { policy } => write!(f, "policy {} declares no WCET budget", policy),
+            BudgetViolation::Unprofiled { policy } => write!(f, "policy {} changed since its WCET was established", policy),
+            BudgetViolation::Unbounded { policy } => write!(f, "policy {} has no measured or estimated WCET", policy),
+            BudgetViolation::OverBudget { policy, bound_cycles, declared_cycles } => {
+                write!(f, "policy {} WCET {} cycles exceeds its budget of {}", policy, bound_cycles, declared_cycles)
+            }
+        }
+    }
+}
+
+impl Error for BudgetViolation {}
+
+/// Cycle budgets per policy. The harness records bounds into it; the Gate consults
+/// `check_load` before loading a policy.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BudgetRegistry {
+    pub policies: BTreeMap<String, Budget>,
+}
+
+impl BudgetRegistry {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match fs::read_to_string(path) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Declares (or re-declares) the budget of `policy`. A different payload drops
+    /// bounds established for the old one.
+    pub fn declare(&mut self, policy: &str, policy_payload: &[u8], declared_cycles: u64) {
+        let policy_hash = blake3::hash(policy_payload).to_hex().to_string();
+        let entry = self.policies.entry(policy.to_string()).or_insert_with(|| Budget {
+            policy_hash: policy_hash.clone(),
+            declared_cycles,
+            measured_cycles: None,
+            estimated_cycles: None,
+            proven_max_steps: None,
+        });
+        if entry.policy_hash != policy_hash {
+            *entry = Budget { policy_hash, declared_cycles, measured_cycles: None, estimated_cycles: None, proven_max_steps: None };
+        }
+        entry.declared_cycles = declared_cycles;
+    }
+
+    fn entry_for(&mut self, policy: &str, policy_payload: &[u8]) -> Option<&mut Budget> {
+        let hash = blake3::hash(policy_payload).to_hex().to_string();
+        self.policies.get_mut(policy).filter(|e| e.policy_hash == hash)
+    }
+
+    /// Records the harness result; ignored for undeclared or changed policies.
+    pub fn record_measured(&mut self, policy: &str, policy_payload: &[u8], profile: &WcetProfile) {
+        if let Some(entry) = self.entry_for(policy, policy_payload) {
+            entry.measured_cycles = Some(profile.max_vm_cycles);
+        }
+    }
+
+    pub fn record_estimate(&mut self, policy: &str, policy_payload: &[u8], estimated_cycles: u64) {
+        if let Some(entry) = self.entry_for(policy, policy_payload) {
+            entry.estimated_cycles = Some(estimated_cycles);
+        }
+    }
+
+    /// Records the verifier's step bound; ignored for undeclared or changed policies.
+    pub fn record_proof(&mut self, policy: &str, policy_payload: &[u8], proof: &BoundednessProof) {
+        if let Some(entry) = self.entry_for(policy, policy_payload) {
+            entry.proven_max_steps = Some(proof.max_steps);
+        }
+    }
+
+    /// Proven step bound, to size the policy's watchdog budget.
+    pub fn max_steps_of(&self, policy: &str) -> Option<u64> {
+        self.policies.get(policy).and_then(|e| e.proven_max_steps)
+    }
+
+    /// Budget to profile `policy` against, falling back to the default envelope.
+    pub fn budget_of(&self, policy: &str) -> u64 {
+        self.policies.get(policy).map_or(DEFAULT_BUDGET_CYCLES, |e| e.declared_cycles)
+    }
+
+    /// Load-time admission: the larger of the measured and estimated bound must fit
+    /// the declared budget.
+    pub fn check_load(&self, policy: &str, policy_payload: &[u8]) -> Result<u64, BudgetViolation> {
+        let name = || policy.to_string();
+        let entry = self.policies.get(policy).ok_or_else(|| BudgetViolation::Undeclared { policy: name() })?;
+        if entry.policy_hash != blake3::hash(policy_payload).to_hex().as_str() {
+            return Err(BudgetViolation::Unprofiled { policy: name() });
+        }
+        let bound = entry.measured_cycles.max(entry.estimated_cycles).ok_or_else(|| BudgetViolation::Unbounded { policy: name() })?;
+        if bound > entry.declared_cycles {
+            return Err(BudgetViolation::OverBudget { policy: name(), bound_cycles: bound, declared_cycles: entry.declared_cycles });
+        }
+        Ok(bound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wcet::disturb::MeasurementMode;
+    use crate::wcet::profile::profile;
+
+    #[test]
+    fn policies_load_only_with_a_bound_inside_their_declared_budget() {
+        let name = |policy: &str| policy.to_string();
+        let mut registry = BudgetRegistry::default();
+        assert_eq!(registry.check_load("arm", b"v1"), Err(BudgetViolation::Undeclared { policy: name("arm") }));
+        assert_eq!(registry.budget_of("arm"), DEFAULT_BUDGET_CYCLES);
+
+        registry.declare("arm", b"v1", 2_000);
+        assert_eq!(registry.check_load("arm", b"v1"), Err(BudgetViolation::Unbounded { policy: name("arm") }));
+        let mut measured = profile(1, 2_000, MeasurementMode::Warm, || {});
+        measured.max_vm_cycles = 1_500;
+        registry.record_measured("arm", b"v1", &measured);
+        assert_eq!(registry.check_load("arm", b"v1"), Ok(1_500));
+        registry.record_estimate("arm", b"v1", 2_500);
+        assert_eq!(
+            registry.check_load("arm", b"v1"),
+            Err(BudgetViolation::OverBudget { policy: name("arm"), bound_cycles: 2_500, declared_cycles: 2_000 })
+        );
+        assert_eq!(registry.check_load("arm", b"v2"), Err(BudgetViolation::Unprofiled { policy: name("arm") }));
+
+        // Bounds of another payload are neither recorded nor kept.
+        registry.record_estimate("arm", b"v2", 10);
+        registry.declare("arm", b"v2", 3_000);
+        assert_eq!(registry.check_load("arm", b"v2"), Err(BudgetViolation::Unbounded { policy: name("arm") }));
+        assert_eq!(registry.budget_of("arm"), 3_000);
+    }
+}
@@ -0,0 +1,558 @@
+//! Cycle sources and per-run measurement.
+//!
+//! Cycles come from a `CycleSource`: the TSC on x86_64, the generic timer (or, once
+//! enabled, the PMU) on ARM64, falling back to a wall-clock approximation elsewhere.
+//! On Linux, `Measurer` also reads instruction, cache-miss and branch-miss counters.
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// A monotonic cycle counter.
+pub trait CycleSource: Send + Sync {
+    /// Reads the counter at the start of a measured region. Implementations must
+    /// not let earlier instructions drift past the read.
+    fn start(&self) -> u64;
+
+    /// Reads the counter at the end of a measured region. Implementations must wait
+    /// for the measured instructions to retire before reading.
+    fn stop(&self) -> u64;
+
+    /// Counter ticks per second.
+    fn frequency_hz(&self) -> u64;
+
+    /// Width of the hardware counter; deltas are taken modulo 2^bits.
+    fn counter_bits(&self) -> u32 {
+        64
+    }
+
+    /// Ticks from `start` to `end`, correct across one counter wrap.
+    fn delta(&self, start: u64, end: u64) -> u64 {
+        let bits = self.counter_bits();
+        let mask = if bits >= 64 { u64::MAX } else { (1u64 << bits) - 1 };
+        end.wrapping_sub(start) & mask
+    }
+
+    /// Frequency the counter was assumed to tick at when it is derived from wall time
+    /// rather than read from hardware.
+    fn assumed_hz(&self) -> Option<u64> {
+        None
+    }
+
+    fn name(&self) -> &'static str;
+}
+
+/// Wall-clock fallback: nanoseconds scaled to cycles at an assumed frequency.
+/// Only good enough for coarse bounds.
+pub struct InstantClock {
+    origin: Instant,
+    assumed_hz: u64,
+}
+
+impl InstantClock {
+    pub fn new(assumed_hz: u64) -> Self {
+        Self { origin: Instant::now(), assumed_hz }
+    }
+
+    fn read(&self) -> u64 {
+        let nanos = self.origin.elapsed().as_nanos();
+        (nanos * self.assumed_hz as u128 / 1_000_000_000) as u64
+    }
+}
+
+impl CycleSource for InstantClock {
+    fn start(&self) -> u64 {
+        self.read()
+    }
+
+    fn stop(&self) -> u64 {
+        self.read()
+    }
+
+    fn frequency_hz(&self) -> u64 {
+        self.assumed_hz
+    }
+
+    fn assumed_hz(&self) -> Option<u64> {
+        Some(self.assumed_hz)
+    }
+
+    fn name(&self) -> &'static str {
+        "instant"
+    }
+}
+
+/// x86_64 time-stamp counter, read as `lfence; rdtsc` at the start and
+/// `rdtscp; lfence` at the end so the measured instructions can neither be
+/// hoisted above nor sunk below the reads.
+#[cfg(target_arch = "x86_64")]
+pub struct Rdtscp {
+    frequency_hz: u64,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Rdtscp {
+    /// `None` if the CPU has no `rdtscp` or its TSC is not invariant (the rate would
+    /// follow frequency scaling and stop in deep C-states).
+    // `__cpuid` is a safe fn from Rust 1.87 on; the blocks keep older toolchains building.
+    #[allow(unused_unsafe)]
+    pub fn new() -> Option<Self> {
+        use std::arch::x86_64::__cpuid;
+        // SAFETY: cpuid is available on every x86_64 CPU.
+        let max_ext = unsafe { __cpuid(0x8000_0000) }.eax;
+        if max_ext < 0x8000_0007 {
+            return None;
+        }
+        let has_rdtscp = unsafe { __cpuid(0x8000_0001) }.edx & (1 << 27) != 0;
+        let invariant = unsafe { __cpuid(0x8000_0007) }.edx & (1 << 8) != 0;
+        if !has_rdtscp || !invariant {
+            return None;
+        }
+        let mut tsc = Self { frequency_hz: 0 };
+        tsc.frequency_hz = tsc.calibrate(Duration::from_millis(50));
+        Some(tsc)
+    }
+
+    /// Measures the TSC rate against the monotonic clock; keeps the best of a few
+    /// runs so a preemption during one of them does not skew the result.
+    fn calibrate(&self, window: Duration) -> u64 {
+        (0..3)
+            .map(|_| {
+                let (t0, c0) = (Instant::now(), self.start());
+                while t0.elapsed() < window {
+                    std::hint::spin_loop();
+                }
+                let (c1, elapsed) = (self.stop(), t0.elapsed());
+                (self.delta(c0, c1) as u128 * 1_000_000_000 / elapsed.as_nanos()) as u64
+            })
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl CycleSource for Rdtscp {
+    fn start(&self) -> u64 {
+        use std::arch::x86_64::{_mm_lfence, _rdtsc};
+        // SAFETY: `Rdtscp::new` checked that the TSC is usable.
+        unsafe {
+            _mm_lfence();
+            _rdtsc()
+        }
+    }
+
+    fn stop(&self) -> u64 {
+        use std::arch::x86_64::{__rdtscp, _mm_lfence};
+        let mut aux = 0u32;
+        // SAFETY: `Rdtscp::new` checked for rdtscp support.
+        unsafe {
+            let t = __rdtscp(&mut aux);
+            _mm_lfence();
+            t
+        }
+    }
+
+    fn frequency_hz(&self) -> u64 {
+        self.frequency_hz
+    }
+
+    fn name(&self) -> &'static str {
+        "rdtscp"
+    }
+}
+
+/// ARM64 generic timer (`CNTVCT_EL0`). Always readable from EL0 on Linux, but it
+/// ticks at the system counter rate (`CNTFRQ_EL0`, typically 1-100 MHz), not the
+/// core clock: use it for bounds of a few microseconds and up.
+#[cfg(target_arch = "aarch64")]
+pub struct ArmGenericTimer {
+    frequency_hz: u64,
+}
+
+#[cfg(target_arch = "aarch64")]
+impl ArmGenericTimer {
+    pub fn new() -> Self {
+        let frequency_hz: u64;
+        // SAFETY: CNTFRQ_EL0 is readable from EL0 whenever CNTVCT_EL0 is.
+        unsafe { std::arch::asm!("mrs {}, cntfrq_el0", out(reg) frequency_hz, options(nomem, nostack)) };
+        Self { frequency_hz }
+    }
+
+    fn read() -> u64 {
+        let v: u64;
+        // SAFETY: the kernel enables EL0 access to the virtual counter (CNTKCTL_EL1.EL0VCTEN).
+        // The `isb` keeps the read from being speculated ahead of earlier instructions.
+        unsafe { std::arch::asm!("isb", "mrs {}, cntvct_el0", out(reg) v, options(nostack)) };
+        v
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl CycleSource for ArmGenericTimer {
+    fn start(&self) -> u64 {
+        Self::read()
+    }
+
+    fn stop(&self) -> u64 {
+        Self::read()
+    }
+
+    fn frequency_hz(&self) -> u64 {
+        self.frequency_hz
+    }
+
+    /// The architecture guarantees at least 56 bits.
+    fn counter_bits(&self) -> u32 {
+        56
+    }
+
+    fn name(&self) -> &'static str {
+        "cntvct_el0"
+    }
+}
+
+/// ARM64 PMU cycle counter (`PMCCNTR_EL0`), counting core clock cycles.
+///
+/// EL0 reads trap unless the kernel has enabled user access. Setup, in order:
+/// 1. Linux 6.2+: `sysctl kernel.perf_user_access=1`, then open a cycles event with
+///    `perf_event_open` (`config1 = 0x2` requests user access) and keep it open for
+///    the measurement thread; or, on kernels without that knob, load a small module
+///    that sets `PMUSERENR_EL0.EN`/`.CR`, `PMCR_EL0.E`/`.LC` and bit 31 of
+///    `PMCNTENSET_EL0` on every core.
+/// 2. Pin the measuring thread, since the enable bits are per core.
+/// 3. Construct with `ArmPmuCycles::new`, which calibrates against the generic timer.
+#[cfg(target_arch = "aarch64")]
+pub struct ArmPmuCycles {
+    frequency_hz: u64,
+}
+
+#[cfg(target_arch = "aarch64")]
+impl ArmPmuCycles {
+    /// # Safety
+    /// User access to `PMCCNTR_EL0` must be enabled on every core this thread can run
+    /// on (see the type docs); otherwise the first read raises SIGILL.
+    pub unsafe fn new() -> Self {
+        let timer = ArmGenericTimer::new();
+        let mut pmu = Self { frequency_hz: 0 };
+        let (t0, c0) = (timer.start(), pmu.start());
+        let window = timer.frequency_hz() / 20; // 50 ms
+        while timer.delta(t0, timer.stop()) < window {
+            std::hint::spin_loop();
+        }
+        let (c1, t1) = (pmu.stop(), timer.stop());
+        pmu.frequency_hz = (pmu.delta(c0, c1) as u128 * timer.frequency_hz() as u128 / timer.delta(t0, t1).max(1) as u128) as u64;
+        pmu
+    }
+
+    fn read() -> u64 {
+        let v: u64;
+        // SAFETY: guaranteed by the contract of `ArmPmuCycles::new`.
+        unsafe { std::arch::asm!("isb", "mrs {}, pmccntr_el0", out(reg) v, options(nostack)) };
+        v
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl CycleSource for ArmPmuCycles {
+    fn start(&self) -> u64 {
+        Self::read()
+    }
+
+    fn stop(&self) -> u64 {
+        Self::read()
+    }
+
+    fn frequency_hz(&self) -> u64 {
+        self.frequency_hz
+    }
+
+    fn name(&self) -> &'static str {
+        "pmccntr_el0"
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn platform_cycle_source() -> Option<Box<dyn CycleSource>> {
+    Rdtscp::new().map(|tsc| Box::new(tsc) as Box<dyn CycleSource>)
+}
+
+/// The PMU needs privileged setup, so only the generic timer is picked automatically.
+#[cfg(target_arch = "aarch64")]
+fn platform_cycle_source() -> Option<Box<dyn CycleSource>> {
+    Some(Box::new(ArmGenericTimer::new()))
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn platform_cycle_source() -> Option<Box<dyn CycleSource>> {
+    None
+}
+
+/// The best cycle source on this machine, calibrated once.
+pub fn default_cycle_source() -> &'static dyn CycleSource {
+    static SOURCE: OnceLock<Box<dyn CycleSource>> = OnceLock::new();
+    SOURCE
+        // Without a hardware counter, assume ~3GHz for illustration.
+        .get_or_init(|| platform_cycle_source().unwrap_or_else(|| Box::new(InstantClock::new(3_000_000_000))))
+        .as_ref()
+}
+
+pub fn measure_cycles_with<F: FnOnce()>(source: &dyn CycleSource, f: F) -> u64 {
+    let start = source.start();
+    f();
+    let end = source.stop();
+    source.delta(start, end)
+}
+
+pub fn measure_cycles<F: FnOnce()>(f: F) -> u64 {
+    measure_cycles_with(default_cycle_source(), f)
+}
+
+/// One measured run. Hardware event counts are `None` when perf events are
+/// unavailable (non-Linux, `perf_event_paranoid`, containers...).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Measurement {
+    pub cycles: u64,
+    pub instructions: Option<u64>,
+    pub llc_misses: Option<u64>,
+    pub branch_misses: Option<u64>,
+}
+
+/// Counts retired instructions, last-level cache misses and branch mispredicts for
+/// the calling thread through `perf_event_open`, as one group so all three cover
+/// exactly the same window.
+#[cfg(target_os = "linux")]
+pub struct PerfCounters {
+    leader: i32,
+    members: [i32; 2],
+}
+
+#[cfg(target_os = "linux")]
+mod perf_sys {
+    pub const PERF_TYPE_HARDWARE: u32 = 0;
+    pub const PERF_COUNT_HW_INSTRUCTIONS: u64 = 1;
+    pub const PERF_COUNT_HW_CACHE_MISSES: u64 = 3;
+    pub const PERF_COUNT_HW_BRANCH_MISSES: u64 = 5;
+    pub const PERF_FORMAT_GROUP: u64 = 1 << 3;
+    pub const FLAG_DISABLED: u64 = 1 << 0;
+    pub const FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+    pub const FLAG_EXCLUDE_HV: u64 = 1 << 6;
+    pub const PERF_EVENT_IOC_ENABLE: u64 = 0x2400;
+    pub const PERF_EVENT_IOC_DISABLE: u64 = 0x2401;
+    pub const PERF_EVENT_IOC_RESET: u64 = 0x2403;
+    pub const PERF_IOC_FLAG_GROUP: u64 = 1;
+
+    /// `struct perf_event_attr`, `PERF_ATTR_SIZE_VER5` layout; bitfields folded into `flags`.
+    #[repr(C)]
+    #[derive(Default)]
+    pub struct PerfEventAttr {
+        pub type_: u32,
+        pub size: u32,
+        pub config: u64,
+        pub sample_period: u64,
+        pub sample_type: u64,
+        pub read_format: u64,
+        pub flags: u64,
+        pub wakeup_events: u32,
+        pub bp_type: u32,
+        pub config1: u64,
+        pub config2: u64,
+        pub branch_sample_type: u64,
+        pub sample_regs_user: u64,
+        pub sample_stack_user: u32,
+        pub clockid: i32,
+        pub sample_regs_intr: u64,
+        pub aux_watermark: u32,
+        pub sample_max_stack: u16,
+        pub reserved: u16,
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl PerfCounters {
+    pub fn open() -> std::io::Result<Self> {
+        use perf_sys::*;
+        let open = |config: u64, group_fd: i32| -> std::io::Result<i32> {
+            let attr = PerfEventAttr {
+                type_: PERF_TYPE_HARDWARE,
+                size: std::mem::size_of::<PerfEventAttr>() as u32,
+                config,
+                read_format: PERF_FORMAT_GROUP,
+                // Only the leader starts disabled; members follow it.
+                flags: FLAG_EXCLUDE_KERNEL | FLAG_EXCLUDE_HV | if group_fd == -1 { FLAG_DISABLED } else { 0 },
+                ..Default::default()
+            };
+            // SAFETY: `attr` is a valid perf_event_attr of the size it declares.
+            let fd = unsafe { libc::syscall(libc::SYS_perf_event_open, &attr as *const PerfEventAttr, 0, -1, group_fd, 0) };
+            if fd < 0 { Err(std::io::Error::last_os_error()) } else { Ok(fd as i32) }
+        };
+        let leader = open(PERF_COUNT_HW_INSTRUCTIONS, -1)?;
+        let counters = |config| {
+            open(config, leader).inspect_err(|_| {
+                // SAFETY: `leader` is an fd we own.
+                unsafe { libc::close(leader) };
+            })
+        };
+        let llc = counters(PERF_COUNT_HW_CACHE_MISSES)?;
+        let branch = counters(PERF_COUNT_HW_BRANCH_MISSES).inspect_err(|_| {
+            // SAFETY: `llc` is an fd we own.
+            unsafe { libc::close(llc) };
+        })?;
+        Ok(Self { leader, members: [llc, branch] })
+    }
+
+    fn ioctl(&self, request: u64) {
+        // SAFETY: `leader` is an open perf event fd.
+        unsafe { libc::ioctl(self.leader, request as _, perf_sys::PERF_IOC_FLAG_GROUP) };
+    }
+
+    /// Runs `f` between cycle reads with the counters enabled.
+    pub fn measure<F: FnOnce()>(&self, source: &dyn CycleSource, f: F) -> Measurement {
+        self.ioctl(perf_sys::PERF_EVENT_IOC_RESET);
+        self.ioctl(perf_sys::PERF_EVENT_IOC_ENABLE);
+        let cycles = measure_cycles_with(source, f);
+        self.ioctl(perf_sys::PERF_EVENT_IOC_DISABLE);
+
+        // PERF_FORMAT_GROUP: { nr, values[nr] } in open order.
+        let mut buf = [0u64; 4];
+        // SAFETY: `buf` is large enough for a three-event group read.
+        let n = unsafe { libc::read(self.leader, buf.as_mut_ptr().cast(), std::mem::size_of_val(&buf)) };
+        if n < 0 || buf[0] != 3 {
+            return Measurement { cycles, ..Default::default() };
+        }
+        Measurement { cycles, instructions: Some(buf[1]), llc_misses: Some(buf[2]), branch_misses: Some(buf[3]) }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for PerfCounters {
+    fn drop(&mut self) {
+        for fd in self.members.into_iter().chain([self.leader]) {
+            // SAFETY: every fd was opened by `PerfCounters::open` and is closed once.
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+/// A cycle source plus hardware event counters where the platform allows them.
+pub struct Measurer {
+    source: &'static dyn CycleSource,
+    #[cfg(target_os = "linux")]
+    perf: Option<PerfCounters>,
+}
+
+impl Measurer {
+    /// Perf counters are opened for the calling thread: measure on the thread that creates this.
+    pub fn new(source: &'static dyn CycleSource) -> Self {
+        Self {
+            source,
+            #[cfg(target_os = "linux")]
+            perf: PerfCounters::open().ok(),
+        }
+    }
+
+    pub fn has_event_counters(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        return self.perf.is_some();
+        #[cfg(not(target_os = "linux"))]
+        return false;
+    }
+
+    pub fn measure<F: FnOnce()>(&self, f: F) -> Measurement {
+        #[cfg(target_os = "linux")]
+        if let Some(perf) = &self.perf {
+            return perf.measure(self.source, f);
+        }
+        Measurement { cycles: measure_cycles_with(self.source, f), ..Default::default() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// Advances by a fixed step on every read, starting just below the counter wrap.
+    struct Stepping {
+        next: AtomicU64,
+        step: u64,
+        bits: u32,
+    }
+
+    impl CycleSource for Stepping {
+        fn start(&self) -> u64 {
+            self.next.fetch_add(self.step, Ordering::Relaxed)
+        }
+
+        fn stop(&self) -> u64 {
+            self.start()
+        }
+
+        fn frequency_hz(&self) -> u64 {
+            1_000
+        }
+
+        fn counter_bits(&self) -> u32 {
+            self.bits
+        }
+
+        fn name(&self) -> &'static str {
+            "stepping"
+        }
+    }
+
+    #[test]
+    fn measured_regions_span_a_counter_wrap() {
+        let source = Stepping { next: AtomicU64::new(u64::MAX - 4), step: 10, bits: 64 };
+        let mut ran = false;
+        assert_eq!(measure_cycles_with(&source, || ran = true), 10);
+        assert!(ran);
+        assert_eq!((source.assumed_hz(), InstantClock::new(1_000).assumed_hz()), (None, Some(1_000)));
+
+        let real = default_cycle_source();
+        assert!(real.frequency_hz() > 0, "{} reports no frequency", real.name());
+        let (a, b) = (real.start(), real.stop());
+        assert!(real.delta(a, b) < real.frequency_hz(), "{} went backwards", real.name());
+    }
+
+    #[test]
+    fn narrow_counters_wrap_at_their_own_width() {
+        let wrap = 1u64 << 56;
+        let source = Stepping { next: AtomicU64::new(wrap - 3), step: 7, bits: 56 };
+        assert_eq!(source.delta(wrap - 3, 4), 7);
+        assert_eq!(measure_cycles_with(&source, || {}), 7);
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn the_generic_timer_is_monotonic_at_its_reported_rate() {
+        let timer = ArmGenericTimer::new();
+        assert!(timer.frequency_hz() > 0);
+        let start = timer.start();
+        std::thread::sleep(Duration::from_millis(20));
+        let ticks = timer.delta(start, timer.stop());
+        assert!(ticks >= timer.frequency_hz() / 50, "{} ticks in 20ms at {} Hz", ticks, timer.frequency_hz());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn perf_counters_cover_the_measured_region_when_available() {
+        // PERF_ATTR_SIZE_VER5.
+        assert_eq!(std::mem::size_of::<perf_sys::PerfEventAttr>(), 112);
+
+        let measurer = Measurer::new(default_cycle_source());
+        let spin = || {
+            for i in 0..10_000u64 {
+                std::hint::black_box(i);
+            }
+        };
+        let measured = measurer.measure(spin);
+        if !measurer.has_event_counters() {
+            assert_eq!((measured.instructions, measured.llc_misses, measured.branch_misses), (None, None, None));
+            return;
+        }
+        assert!(measured.instructions.unwrap() >= 10_000);
+        let idle = measurer.measure(|| {});
+        assert!(idle.instructions.unwrap() < measured.instructions.unwrap());
+    }
+}
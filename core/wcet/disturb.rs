@@ -0,0 +1,134 @@
+//! Pessimistic measurement mode.
+//!
+//! Back-to-back iterations measure a warmed-up steady state: the policy's code and
+//! data sit in cache and the branch predictor has learned its paths. A deadline has
+//! to hold for the cold case too, so pessimistic mode disturbs all of that between
+//! iterations.
+
+use std::hint::black_box;
+
+use serde::{Deserialize, Serialize};
+
+/// Pessimistic-mode state: disturbs the microarchitecture between iterations so each run
+/// starts cold (evicted caches, mistrained branch predictor, shifted stack and heap)
+/// instead of benefiting from the previous run's warm-up.
+pub struct Disturbance {
+    eviction: Vec<u8>,
+    rng: u64,
+    /// Keeps the previous iteration's heap padding alive so the next one lands elsewhere.
+    heap_pad: Vec<u8>,
+}
+
+/// Default eviction buffer; should exceed the last-level cache.
+pub const DEFAULT_EVICTION_BYTES: usize = 64 << 20;
+const CACHE_LINE: usize = 64;
+/// Stack offsets are taken in frames of this many bytes, up to `MAX_STACK_FRAMES`.
+const STACK_FRAME_BYTES: usize = 64;
+const MAX_STACK_FRAMES: u64 = 64;
+const MAX_HEAP_PAD: u64 = 64 << 10;
+
+impl Disturbance {
+    pub fn new(eviction_bytes: usize, seed: u64) -> Self {
+        Self { eviction: vec![1; eviction_bytes], rng: seed | 1, heap_pad: Vec::new() }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    /// Evicts data caches by touching every line of a buffer larger than the LLC, and
+    /// on x86_64 also flushes the buffer itself so the measured run sees no hits from it.
+    fn flush_caches(&mut self) {
+        for line in self.eviction.chunks_mut(CACHE_LINE) {
+            line[0] = line[0].wrapping_add(1);
+        }
+        #[cfg(target_arch = "x86_64")]
+        for line in self.eviction.chunks(CACHE_LINE) {
+            // SAFETY: `line` points into a live allocation; clflush is baseline SSE2.
+            unsafe { std::arch::x86_64::_mm_clflush(line.as_ptr()) };
+        }
+        black_box(&self.eviction);
+    }
+
+    /// Trains the branch predictors on random outcomes so they hold no useful history.
+    fn pollute_branch_predictor(&mut self) {
+        let mut acc = 0u64;
+        for _ in 0..4096 {
+            let r = self.next();
+            // Several independent, unpredictable branches per round.
+            if r & 1 != 0 { acc = acc.wrapping_add(r) } else { acc ^= r }
+            if r & 2 != 0 { acc = acc.rotate_left(3) }
+            if r & 4 != 0 { acc = acc.wrapping_mul(3) }
+            match (r >> 3) & 3 {
+                0 => acc += 1,
+                1 => acc ^= 0x55,
+                2 => acc = acc.wrapping_sub(7),
+                _ => acc = !acc,
+            }
+        }
+        black_box(acc);
+    }
+
+    /// Disturbs caches and predictors, then runs `f` at a random stack depth with a
+    /// random amount of heap allocated ahead of it.
+    pub fn run<R, F: FnOnce() -> R>(&mut self, f: F) -> R {
+        self.flush_caches();
+        self.pollute_branch_predictor();
+        let heap = (self.next() % MAX_HEAP_PAD) as usize;
+        self.heap_pad = vec![0; heap];
+        black_box(&self.heap_pad);
+        let frames = self.next() % MAX_STACK_FRAMES;
+        at_stack_depth(frames, f)
+    }
+}
+
+#[inline(never)]
+fn at_stack_depth<R, F: FnOnce() -> R>(frames: u64, f: F) -> R {
+    let pad = [0u8; STACK_FRAME_BYTES];
+    black_box(&pad);
+    if frames == 0 { f() } else { at_stack_depth(frames - 1, f) }
+}
+
+/// How iterations are separated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MeasurementMode {
+    /// Back to back: measures the warmed-up steady state.
+    #[default]
+    Warm,
+    /// Disturbed between iterations (see `Disturbance`): measures cold-path worst cases.
+    Pessimistic,
+}
+
+impl MeasurementMode {
+    /// `WCET_PESSIMISTIC=1` selects pessimistic mode.
+    pub fn from_env() -> Self {
+        if std::env::var("WCET_PESSIMISTIC").is_ok_and(|v| v == "1") { Self::Pessimistic } else { Self::Warm }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn disturbed_runs_start_at_shifting_stack_depths() {
+        let mut disturbance = Disturbance::new(64 << 10, 42);
+        let mut depths = BTreeSet::new();
+        for i in 0..16 {
+            let (value, address) = disturbance.run(|| {
+                let local = 0u8;
+                (i * 2, black_box(&local) as *const u8 as usize)
+            });
+            assert_eq!(value, i * 2);
+            depths.insert(address);
+        }
+        assert!(depths.len() > 1, "every run started at the same depth");
+        assert!(disturbance.heap_pad.len() < MAX_HEAP_PAD as usize);
+        assert_eq!(MeasurementMode::default(), MeasurementMode::Warm);
+    }
+}
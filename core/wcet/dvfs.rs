@@ -0,0 +1,166 @@
+//! Frequency-scaling and thermal-throttle detection.
+//!
+//! With DVFS the same code takes a different number of nanoseconds, and a different
+//! number of TSC ticks, depending on the clock the core happened to run at; a
+//! wall-clock-derived "cycle" count is meaningless unless the clock held still.
+//! The profiler samples the measuring CPU's frequency and throttle counters before,
+//! during and after a run and classifies the result: `Stable`, `Rescaled` when only
+//! the wall-clock fallback was affected and a constant clock allows converting it to
+//! real cycles, or `Invalid`.
+
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+/// Relative frequency spread tolerated within one run.
+pub const FREQUENCY_TOLERANCE: f64 = 0.02;
+
+/// Clock state of one CPU at one point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockSample {
+    pub cur_khz: Option<u64>,
+    /// Sum of core and package thermal-throttle events since boot (x86 only).
+    pub throttle_events: Option<u64>,
+}
+
+fn read_u64(path: &str) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// CPU the calling thread runs on; pin it first for this to stay meaningful.
+pub fn current_cpu() -> Option<usize> {
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: sched_getcpu has no preconditions.
+        let cpu = unsafe { libc::sched_getcpu() };
+        if cpu >= 0 {
+            return Some(cpu as usize);
+        }
+    }
+    None
+}
+
+impl ClockSample {
+    pub fn read(cpu: usize) -> Self {
+        let base = format!("/sys/devices/system/cpu/cpu{}", cpu);
+        let core = read_u64(&format!("{}/thermal_throttle/core_throttle_count", base));
+        let package = read_u64(&format!("{}/thermal_throttle/package_throttle_count", base));
+        Self {
+            cur_khz: read_u64(&format!("{}/cpufreq/scaling_cur_freq", base)),
+            throttle_events: match (core, package) {
+                (None, None) => None,
+                (c, p) => Some(c.unwrap_or(0) + p.unwrap_or(0)),
+            },
+        }
+    }
+}
+
+/// Verdict on the clock over a profiling run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum ClockCheck {
+    /// Frequency held within tolerance and no throttling occurred.
+    Stable { khz: Option<u64> },
+    /// The wall-clock fallback assumed `assumed_hz`, but the core ran at a stable
+    /// `khz`; multiply cycle counts by `factor` to get core cycles.
+    Rescaled { khz: u64, factor: f64 },
+    Invalid { reason: String },
+    /// The platform exposes neither frequency nor throttle counters.
+    Unknown,
+}
+
+impl ClockCheck {
+    pub fn is_valid(&self) -> bool {
+        !matches!(self, ClockCheck::Invalid { .. })
+    }
+}
+
+/// Collects clock samples over a run.
+#[derive(Debug, Clone)]
+pub struct ClockMonitor {
+    cpu: Option<usize>,
+    first: ClockSample,
+    min_khz: Option<u64>,
+    max_khz: Option<u64>,
+    last: ClockSample,
+}
+
+impl ClockMonitor {
+    /// Samples the current CPU; call on the measuring thread right before the run.
+    pub fn start() -> Self {
+        let cpu = current_cpu();
+        let first = cpu.map(ClockSample::read).unwrap_or_default();
+        Self { cpu, first, min_khz: first.cur_khz, max_khz: first.cur_khz, last: first }
+    }
+
+    /// Samples again; call between iterations, outside the measured region.
+    pub fn sample(&mut self) {
+        let Some(cpu) = self.cpu else { return };
+        // The thread may have migrated; follow it.
+        let cpu = current_cpu().unwrap_or(cpu);
+        self.cpu = Some(cpu);
+        self.last = ClockSample::read(cpu);
+        if let Some(khz) = self.last.cur_khz {
+            self.min_khz = Some(self.min_khz.map_or(khz, |m| m.min(khz)));
+            self.max_khz = Some(self.max_khz.map_or(khz, |m| m.max(khz)));
+        }
+    }
+
+    /// Final sample and verdict. `assumed_hz` is set when cycles were derived from
+    /// wall time at an assumed frequency (see `cycles::InstantClock`).
+    pub fn finish(mut self, assumed_hz: Option<u64>) -> ClockCheck {
+        self.sample();
+        if let (Some(before), Some(after)) = (self.first.throttle_events, self.last.throttle_events) {
+            if after > before {
+                return ClockCheck::Invalid { reason: format!("{} thermal throttle events during the run", after - before) };
+            }
+        }
+        let (Some(min), Some(max)) = (self.min_khz, self.max_khz) else {
+            return if self.first.throttle_events.is_some() { ClockCheck::Stable { khz: None } } else { ClockCheck::Unknown };
+        };
+        if (max - min) as f64 > max as f64 * FREQUENCY_TOLERANCE {
+            return ClockCheck::Invalid { reason: format!("CPU frequency varied between {} and {} kHz", min, max) };
+        }
+        let khz = (min + max) / 2;
+        match assumed_hz {
+            Some(hz) if hz > 0 => ClockCheck::Rescaled { khz, factor: khz as f64 * 1000.0 / hz as f64 },
+            _ => ClockCheck::Stable { khz: Some(khz) },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A monitor whose samples are given rather than read; with no CPU, `finish` reads nothing more.
+    fn monitor(first: ClockSample, last: ClockSample, min_khz: Option<u64>, max_khz: Option<u64>) -> ClockMonitor {
+        ClockMonitor { cpu: None, first, min_khz, max_khz, last }
+    }
+
+    #[test]
+    fn runs_are_classified_by_how_the_clock_behaved() {
+        let at = |khz| ClockSample { cur_khz: Some(khz), throttle_events: Some(3) };
+        assert_eq!(monitor(at(2_000_000), at(2_010_000), Some(2_000_000), Some(2_010_000)).finish(None), ClockCheck::Stable { khz: Some(2_005_000) });
+
+        let varied = monitor(at(2_000_000), at(3_000_000), Some(2_000_000), Some(3_000_000)).finish(None);
+        assert_eq!(varied, ClockCheck::Invalid { reason: "CPU frequency varied between 2000000 and 3000000 kHz".into() });
+        assert!(!varied.is_valid());
+
+        let throttled = ClockSample { throttle_events: Some(5), ..at(2_000_000) };
+        let check = monitor(at(2_000_000), throttled, Some(2_000_000), Some(2_000_000)).finish(None);
+        assert_eq!(check, ClockCheck::Invalid { reason: "2 thermal throttle events during the run".into() });
+
+        let rescaled = monitor(at(3_000_000), at(3_000_000), Some(3_000_000), Some(3_000_000)).finish(Some(1_000_000_000));
+        let ClockCheck::Rescaled { khz, factor } = rescaled else {
+            panic!("expected a wall-clock run at a stable frequency to be rescaled")
+        };
+        assert_eq!(khz, 3_000_000);
+        assert!((factor - 3.0).abs() < 1e-9);
+
+        let blind = ClockSample::default();
+        assert_eq!(monitor(blind, blind, None, None).finish(None), ClockCheck::Unknown);
+        let counters_only = ClockSample { cur_khz: None, throttle_events: Some(0) };
+        assert_eq!(monitor(counters_only, counters_only, None, None).finish(None), ClockCheck::Stable { khz: None });
+    }
+}
@@ -0,0 +1,388 @@
+//! Real-time admission of proposals in front of the Gate.
+//!
+//! Proposals arrive faster than the Gate can evaluate them inside one control cycle.
+//! The executor queues them by class and deadline and, at the start of each cycle,
+//! admits only as many as fit in that cycle's budget. Every job is charged its
+//! policy's declared WCET from the `BudgetRegistry` plus Gate framing, never an
+//! observed average, so an admitted set finishes within the cycle even if every
+//! evaluation takes its worst case. `SafetyCritical` jobs go first, then `FastCtrl`,
+//! then `Background`, earliest deadline first within a class. A job whose deadline
+//! cannot be met any more is dropped and handed back instead of being run late.
+//!
+//! A class can be capped at a number of jobs per cycle with `with_class_limit`, so a
+//! burst in one class leaves room for the others. With `with_starvation_after`, a job
+//! deferred that many cycles is promoted one class and goes ahead of everything
+//! unpromoted in it. Nothing is ever promoted into `SafetyCritical`, so no amount of
+//! waiting work can get in front of a safety-critical proposal.
+//!
+//! The executor never queues without bound when given limits: with
+//! `with_backlog_limit` it holds at most that many jobs, and with `with_budget_pool`
+//! at most that many cycles of queued WCET. A submission past either is refused with a
+//! `Backpressure` saying which limit is saturated, the current load and how many ticks
+//! to wait before retrying, so the proposer can throttle itself rather than have its
+//! job dropped at its deadline. `SafetyCritical` jobs are always queued.
+//!
+//! The executor is generic over the queued action; the predictive loop's
+//! `ProposedAction` is the usual payload. Deadlines and `now` are ticks of the
+//! node's shared `TickSource`, counted in cycles (see `schedule_now`).
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::budget::BudgetRegistry;
+use super::profile::GATE_FRAMING_CYCLES;
+use crate::ledger::tick::TickSource;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PriorityClass {
+    /// Emergency stops and the like; always scheduled first.
+    SafetyCritical,
+    /// Control-loop decisions; always scheduled ahead of background work.
+    FastCtrl,
+    Background,
+}
+
+impl PriorityClass {
+    /// The class a starved job moves up to.
+    fn promoted(self) -> Self {
+        match self {
+            PriorityClass::Background => PriorityClass::FastCtrl,
+            class => class,
+        }
+    }
+}
+
+/// Which limit a refused submission ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Saturation {
+    /// The backlog holds its limit of jobs.
+    Queue,
+    /// The backlog holds its limit of WCET cycles.
+    BudgetPool,
+}
+
+impl Saturation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Saturation::Queue => "queue",
+            Saturation::BudgetPool => "budget-pool",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "queue" => Some(Saturation::Queue),
+            "budget-pool" => Some(Saturation::BudgetPool),
+            _ => None,
+        }
+    }
+}
+
+/// What is queued.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Load {
+    pub jobs: u64,
+    /// Sum of the queued jobs' costs.
+    pub cycles: u64,
+}
+
+/// A submission refused because the executor is saturated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Backpressure {
+    pub saturated: Saturation,
+    /// The saturated limit, in jobs or cycles.
+    pub limit: u64,
+    pub load: Load,
+    /// Ticks until enough of the backlog has run for the submission to fit, if every
+    /// cycle's budget went to it: the cost of the next job to run for a full queue,
+    /// the cycles over the pool otherwise.
+    pub retry_after: u64,
+}
+
+impl fmt::Display for Backpressure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} saturated at {} ({} jobs, {} cycles queued); retry after {} ticks",
+            self.saturated.name(),
+            self.limit,
+            self.load.jobs,
+            self.load.cycles,
+            self.retry_after
+        )
+    }
+}
+
+impl std::error::Error for Backpressure {}
+
+/// A queued proposal. Cycle counts are on the same counter the caller passes as `now`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job<A> {
+    pub policy: String,
+    pub class: PriorityClass,
+    /// Absolute cycle count by which the decision must be complete.
+    pub deadline: u64,
+    /// Cycles charged for the job: the policy's declared WCET plus Gate framing.
+    pub cost_cycles: u64,
+    pub action: A,
+    /// Cycles the job was passed over for lack of budget or class capacity.
+    pub deferrals: u32,
+    /// The class it was submitted in, if starvation protection promoted it.
+    pub promoted_from: Option<PriorityClass>,
+    seq: u64,
+}
+
+impl<A> Job<A> {
+    /// Scheduling key: class, promoted jobs first, then deadline, then arrival.
+    /// Smaller runs first.
+    fn key(&self) -> (PriorityClass, bool, u64, u64) {
+        (self.class, self.promoted_from.is_none(), self.deadline, self.seq)
+    }
+}
+
+impl<A: Eq> Ord for Job<A> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // `BinaryHeap` is a max-heap; reverse so the most urgent job is on top.
+        other.key().cmp(&self.key())
+    }
+}
+
+impl<A: Eq> PartialOrd for Job<A> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Outcome of scheduling one cycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleSchedule<A> {
+    /// Jobs to hand to the Gate this cycle, in order.
+    pub admitted: Vec<Job<A>>,
+    /// Jobs that can no longer finish by their deadline.
+    pub expired: Vec<Job<A>>,
+    /// Sum of the admitted jobs' costs.
+    pub committed_cycles: u64,
+}
+
+/// Deadline- and class-ordered queue of proposals.
+pub struct Executor<A> {
+    registry: BudgetRegistry,
+    queue: BinaryHeap<Job<A>>,
+    next_seq: u64,
+    /// Most jobs of a class admitted per cycle.
+    class_limits: BTreeMap<PriorityClass, usize>,
+    starvation_after: Option<u32>,
+    backlog_limit: Option<usize>,
+    budget_pool: Option<u64>,
+    queued_cycles: u64,
+}
+
+impl<A: Eq> Executor<A> {
+    pub fn new(registry: BudgetRegistry) -> Self {
+        Self {
+            registry,
+            queue: BinaryHeap::new(),
+            next_seq: 0,
+            class_limits: BTreeMap::new(),
+            starvation_after: None,
+            backlog_limit: None,
+            budget_pool: None,
+            queued_cycles: 0,
+        }
+    }
+
+    /// Admits at most `jobs` of `class` per cycle.
+    pub fn with_class_limit(mut self, class: PriorityClass, jobs: usize) -> Self {
+        self.class_limits.insert(class, jobs);
+        self
+    }
+
+    /// Promotes a job one class once it has been deferred `cycles` times.
+    pub fn with_starvation_after(mut self, cycles: u32) -> Self {
+        self.starvation_after = Some(cycles);
+        self
+    }
+
+    /// Refuses submissions while `jobs` are queued.
+    pub fn with_backlog_limit(mut self, jobs: usize) -> Self {
+        self.backlog_limit = Some(jobs);
+        self
+    }
+
+    /// Refuses submissions that would queue more than `cycles` of WCET.
+    pub fn with_budget_pool(mut self, cycles: u64) -> Self {
+        self.budget_pool = Some(cycles);
+        self
+    }
+
+    pub fn load(&self) -> Load {
+        Load { jobs: self.queue.len() as u64, cycles: self.queued_cycles }
+    }
+
+    pub fn registry(&self) -> &BudgetRegistry {
+        &self.registry
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Queues `action` for evaluation under `policy`; returns the cycles it will be
+    /// charged, or the backpressure that refused it.
+    pub fn submit(&mut self, policy: &str, class: PriorityClass, deadline: u64, action: A) -> Result<u64, Backpressure> {
+        let cost_cycles = self.registry.budget_of(policy) + GATE_FRAMING_CYCLES;
+        if class != PriorityClass::SafetyCritical {
+            self.check_backlog(cost_cycles)?;
+        }
+        self.queued_cycles += cost_cycles;
+        self.queue.push(Job { policy: policy.to_string(), class, deadline, cost_cycles, action, deferrals: 0, promoted_from: None, seq: self.next_seq });
+        self.next_seq += 1;
+        Ok(cost_cycles)
+    }
+
+    fn check_backlog(&self, cost_cycles: u64) -> Result<(), Backpressure> {
+        let load = self.load();
+        if let Some(limit) = self.backlog_limit.filter(|&limit| self.queue.len() >= limit) {
+            let retry_after = self.queue.peek().map_or(0, |next| next.cost_cycles);
+            return Err(Backpressure { saturated: Saturation::Queue, limit: limit as u64, load, retry_after });
+        }
+        // A job bigger than the whole pool still goes into an empty backlog.
+        if let Some(pool) = self.budget_pool.filter(|&pool| load.jobs > 0 && load.cycles + cost_cycles > pool) {
+            let retry_after = (load.cycles + cost_cycles - pool).min(load.cycles);
+            return Err(Backpressure { saturated: Saturation::BudgetPool, limit: pool, load, retry_after });
+        }
+        Ok(())
+    }
+
+    /// Picks the jobs to run in a cycle starting at `now` with `cycle_budget` cycles.
+    /// Jobs are taken in priority order; one that does not fit, or whose class is at
+    /// its limit, stays queued while jobs behind it may still fill the remaining budget.
+    pub fn schedule(&mut self, now: u64, cycle_budget: u64) -> CycleSchedule<A> {
+        let mut schedule = CycleSchedule { admitted: Vec::new(), expired: Vec::new(), committed_cycles: 0 };
+        let mut admitted_per_class = BTreeMap::new();
+        let mut deferred = Vec::new();
+        while let Some(mut job) = self.queue.pop() {
+            let finish = now + schedule.committed_cycles + job.cost_cycles;
+            let admitted = admitted_per_class.entry(job.class).or_insert(0);
+            let has_room = self.class_limits.get(&job.class).is_none_or(|&limit| *admitted < limit);
+            if now.saturating_add(job.cost_cycles) > job.deadline {
+                // Even alone at the start of this cycle it would be late.
+                self.queued_cycles -= job.cost_cycles;
+                schedule.expired.push(job);
+            } else if has_room && schedule.committed_cycles + job.cost_cycles <= cycle_budget && finish <= job.deadline {
+                *admitted += 1;
+                schedule.committed_cycles += job.cost_cycles;
+                self.queued_cycles -= job.cost_cycles;
+                schedule.admitted.push(job);
+            } else {
+                job.deferrals += 1;
+                if self.starvation_after.is_some_and(|cycles| job.deferrals >= cycles) && job.class.promoted() != job.class {
+                    job.promoted_from = job.promoted_from.or(Some(job.class));
+                    job.class = job.class.promoted();
+                }
+                deferred.push(job);
+            }
+        }
+        self.queue.extend(deferred);
+        schedule
+    }
+
+    /// `schedule` at the current tick of `ticks`.
+    pub fn schedule_now(&mut self, ticks: &dyn TickSource, cycle_budget: u64) -> CycleSchedule<A> {
+        self.schedule(ticks.now(), cycle_budget)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn executor() -> Executor<&'static str> {
+        let mut registry = BudgetRegistry::default();
+        registry.declare("cheap", b"cheap", 500);
+        registry.declare("heavy", b"heavy", 8_500);
+        Executor::new(registry)
+    }
+
+    #[test]
+    fn fast_ctrl_first_then_earliest_deadline() {
+        let mut executor = executor();
+        executor.submit("cheap", PriorityClass::Background, 7_000, "background").unwrap();
+        executor.submit("cheap", PriorityClass::FastCtrl, 9_000, "late").unwrap();
+        executor.submit("cheap", PriorityClass::FastCtrl, 4_000, "early").unwrap();
+        let schedule = executor.schedule(0, 100_000);
+        let order: Vec<_> = schedule.admitted.iter().map(|j| j.action).collect();
+        assert_eq!(order, ["early", "late", "background"]);
+        assert_eq!(schedule.committed_cycles, 3 * 2_000);
+        assert!(executor.is_empty());
+    }
+
+    #[test]
+    fn admits_only_what_fits_and_drops_what_is_late() {
+        let mut executor = executor();
+        executor.submit("heavy", PriorityClass::FastCtrl, 50_000, "heavy").unwrap();
+        executor.submit("cheap", PriorityClass::Background, 50_000, "filler").unwrap();
+        executor.submit("cheap", PriorityClass::FastCtrl, 1_000, "hopeless").unwrap();
+        let schedule = executor.schedule(0, 5_000);
+        assert_eq!(schedule.expired.iter().map(|j| j.action).collect::<Vec<_>>(), ["hopeless"]);
+        assert_eq!(schedule.admitted.iter().map(|j| j.action).collect::<Vec<_>>(), ["filler"]);
+        assert_eq!(executor.len(), 1);
+        assert_eq!(executor.schedule(10_000, 10_000).admitted[0].action, "heavy");
+    }
+
+    #[test]
+    fn limits_classes_and_promotes_starved_jobs() {
+        let actions = |schedule: CycleSchedule<&'static str>| schedule.admitted.iter().map(|j| j.action).collect::<Vec<_>>();
+        let mut executor = executor().with_class_limit(PriorityClass::FastCtrl, 2);
+        for action in ["a", "b", "c"] {
+            executor.submit("cheap", PriorityClass::FastCtrl, 90_000, action).unwrap();
+        }
+        executor.submit("cheap", PriorityClass::Background, 90_000, "report").unwrap();
+        executor.submit("cheap", PriorityClass::SafetyCritical, 90_000, "stop").unwrap();
+        // Room for four; the third control job waits for the next cycle.
+        assert_eq!(actions(executor.schedule(0, 8_000)), ["stop", "a", "b", "report"]);
+
+        let mut executor = executor_with_burst().with_starvation_after(2);
+        assert_eq!(actions(executor.schedule(0, 4_000)), ["a", "b"]);
+        assert_eq!(actions(executor.schedule(10_000, 4_000)), ["c", "d"]);
+        // Passed over twice, the report now goes ahead of the remaining control jobs.
+        let third = executor.schedule(20_000, 4_000);
+        assert_eq!(third.admitted[0].promoted_from, Some(PriorityClass::Background));
+        assert_eq!(actions(third), ["report", "e"]);
+    }
+
+    #[test]
+    fn saturated_backlogs_push_back() {
+        let mut executor = executor().with_backlog_limit(2).with_budget_pool(10_000);
+        executor.submit("cheap", PriorityClass::FastCtrl, 90_000, "a").unwrap();
+        let refused = executor.submit("heavy", PriorityClass::FastCtrl, 90_000, "b").unwrap_err();
+        assert_eq!((refused.saturated, refused.limit, refused.retry_after), (Saturation::BudgetPool, 10_000, 2_000));
+        executor.submit("cheap", PriorityClass::Background, 90_000, "c").unwrap();
+        let refused = executor.submit("cheap", PriorityClass::FastCtrl, 90_000, "d").unwrap_err();
+        assert_eq!((refused.saturated, refused.load, refused.retry_after), (Saturation::Queue, Load { jobs: 2, cycles: 4_000 }, 2_000));
+        // Safety-critical work is never pushed back.
+        executor.submit("heavy", PriorityClass::SafetyCritical, 90_000, "stop").unwrap();
+
+        executor.schedule(0, 100_000);
+        assert_eq!(executor.load(), Load::default());
+        assert!(executor.submit("heavy", PriorityClass::FastCtrl, 90_000, "b").is_ok());
+    }
+
+    fn executor_with_burst() -> Executor<&'static str> {
+        let mut executor = executor();
+        executor.submit("cheap", PriorityClass::Background, 90_000, "report").unwrap();
+        for action in ["a", "b", "c", "d", "e", "f"] {
+            executor.submit("cheap", PriorityClass::FastCtrl, 80_000, action).unwrap();
+        }
+        executor
+    }
+}
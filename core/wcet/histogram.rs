@@ -0,0 +1,210 @@
+//! Latency distribution and jitter metrics.
+//!
+//! The maximum alone cannot tell a noisy measurement environment from a policy whose
+//! execution time genuinely varies; the shape of the distribution can.
+
+use serde::{Deserialize, Serialize};
+
+/// Log-linear (HDR-style) histogram of cycle counts. Values below `2^HIST_SUB_BUCKET_BITS`
+/// are exact; larger ones keep that many significant bits, so any recorded value is
+/// reported within 1/64 (1.6%) of its true value whatever its magnitude.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+    min: u64,
+    max: u64,
+}
+
+const HIST_SUB_BUCKET_BITS: u32 = 7;
+const HIST_SUB_BUCKETS: u64 = 1 << HIST_SUB_BUCKET_BITS;
+const HIST_HALF: u64 = HIST_SUB_BUCKETS / 2;
+
+impl LatencyHistogram {
+    fn index_of(value: u64) -> usize {
+        if value < HIST_SUB_BUCKETS {
+            return value as usize;
+        }
+        let shift = (63 - value.leading_zeros()) - (HIST_SUB_BUCKET_BITS - 1);
+        let mantissa = value >> shift;
+        (HIST_SUB_BUCKETS + (shift as u64 - 1) * HIST_HALF + (mantissa - HIST_HALF)) as usize
+    }
+
+    /// Largest value that lands in bucket `index`.
+    fn highest_equivalent(index: usize) -> u64 {
+        let index = index as u64;
+        if index < HIST_SUB_BUCKETS {
+            return index;
+        }
+        let k = index - HIST_SUB_BUCKETS;
+        let shift = k / HIST_HALF + 1;
+        let lower = (HIST_HALF + k % HIST_HALF) << shift;
+        lower.saturating_add((1u64 << shift) - 1)
+    }
+
+    pub fn record(&mut self, cycles: u64) {
+        let index = Self::index_of(cycles);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.min = if self.total == 0 { cycles } else { self.min.min(cycles) };
+        self.max = self.max.max(cycles);
+        self.total += 1;
+    }
+
+    pub fn len(&self) -> u64 {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    pub fn min(&self) -> u64 {
+        self.min
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Smallest value at or above `percentile` percent of the runs, e.g. `99.99`. Rounds
+    /// up to the bucket's upper edge (never above the observed maximum) so a reported
+    /// tail is never optimistic.
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let rank = ((percentile / 100.0 * self.total as f64).ceil() as u64).clamp(1, self.total);
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::highest_equivalent(index).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Non-empty buckets as `(upper edge, count)`, for plotting.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.counts.iter().enumerate().filter(|(_, &c)| c > 0).map(|(i, &c)| (Self::highest_equivalent(i), c))
+    }
+}
+
+/// Where run-to-run variation comes from, judged from the shape of the distribution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JitterSource {
+    /// Body and tail both tight.
+    Stable,
+    /// Tight body with a few isolated spikes: interrupts, migrations, frequency
+    /// changes. Re-measure on an isolated core before trusting the maximum.
+    Environment,
+    /// The body itself is wide: the policy takes data- or state-dependent paths.
+    Execution,
+}
+
+/// Percentiles and jitter metrics of a profiling run, in cycles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub p50_cycles: u64,
+    pub p99_cycles: u64,
+    pub p9999_cycles: u64,
+    pub mean_cycles: f64,
+    pub stddev_cycles: f64,
+    /// Mean absolute difference between consecutive runs. Unlike the standard
+    /// deviation it ignores slow drift (thermal, frequency ramp-up).
+    pub mean_successive_delta: f64,
+}
+
+/// Spread (relative to the median) below which a part of the distribution counts as tight.
+const TIGHT_SPREAD: f64 = 0.10;
+
+impl LatencySummary {
+    /// `samples` must be in measurement order for the successive delta to mean anything.
+    pub fn from_samples(samples: &[u64], histogram: &LatencyHistogram) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let n = samples.len() as f64;
+        let mean = samples.iter().map(|&s| s as f64).sum::<f64>() / n;
+        let var = samples.iter().map(|&s| (s as f64 - mean).powi(2)).sum::<f64>() / n;
+        let deltas: Vec<f64> = samples.windows(2).map(|w| w[0].abs_diff(w[1]) as f64).collect();
+        Self {
+            p50_cycles: histogram.percentile(50.0),
+            p99_cycles: histogram.percentile(99.0),
+            p9999_cycles: histogram.percentile(99.99),
+            mean_cycles: mean,
+            stddev_cycles: var.sqrt(),
+            mean_successive_delta: if deltas.is_empty() { 0.0 } else { deltas.iter().sum::<f64>() / deltas.len() as f64 },
+        }
+    }
+
+    /// Standard deviation over mean.
+    pub fn coefficient_of_variation(&self) -> f64 {
+        if self.mean_cycles > 0.0 { self.stddev_cycles / self.mean_cycles } else { 0.0 }
+    }
+
+    pub fn jitter_source(&self) -> JitterSource {
+        let p50 = self.p50_cycles.max(1) as f64;
+        let body = (self.p99_cycles - self.p50_cycles.min(self.p99_cycles)) as f64 / p50;
+        let tail = (self.p9999_cycles - self.p99_cycles.min(self.p9999_cycles)) as f64 / p50;
+        if body > TIGHT_SPREAD {
+            JitterSource::Execution
+        } else if tail > TIGHT_SPREAD {
+            JitterSource::Environment
+        } else {
+            JitterSource::Stable
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram_of(samples: &[u64]) -> LatencyHistogram {
+        let mut histogram = LatencyHistogram::default();
+        for &s in samples {
+            histogram.record(s);
+        }
+        histogram
+    }
+
+    #[test]
+    fn buckets_are_exact_for_small_values_and_within_two_percent_above() {
+        let edges = (7..63).flat_map(|bits| [1u64 << bits, (1u64 << bits) + 12_345 % (1 << bits), u64::MAX >> (63 - bits)]);
+        for value in (0..HIST_SUB_BUCKETS).chain(edges) {
+            let upper = LatencyHistogram::highest_equivalent(LatencyHistogram::index_of(value));
+            assert!(upper >= value, "{} reported as {}", value, upper);
+            assert!(upper - value <= value / 64, "{} reported as {}", value, upper);
+        }
+        assert_eq!(LatencyHistogram::highest_equivalent(LatencyHistogram::index_of(100)), 100);
+
+        let histogram = histogram_of(&(1..=1_000).collect::<Vec<_>>());
+        assert_eq!((histogram.len(), histogram.min(), histogram.max()), (1_000, 1, 1_000));
+        assert_eq!(histogram.percentile(100.0), 1_000);
+        assert!((500..=508).contains(&histogram.percentile(50.0)));
+        assert_eq!(histogram.buckets().map(|(_, count)| count).sum::<u64>(), 1_000);
+        assert_eq!(LatencyHistogram::default().percentile(99.0), 0);
+    }
+
+    #[test]
+    fn jitter_is_attributed_from_the_shape_of_the_distribution() {
+        let summarize = |samples: &[u64]| LatencySummary::from_samples(samples, &histogram_of(samples));
+        let steady = vec![1_000; 20_000];
+        assert_eq!(summarize(&steady).jitter_source(), JitterSource::Stable);
+        assert_eq!(summarize(&steady).coefficient_of_variation(), 0.0);
+
+        // Ten isolated spikes: above the 99th percentile, inside the 99.99th.
+        let spiky: Vec<u64> = (0..20_000).map(|i| if i % 2_000 == 1_999 { 5_000 } else { 1_000 }).collect();
+        assert_eq!(summarize(&spiky).jitter_source(), JitterSource::Environment);
+
+        let alternating: Vec<u64> = (0..20_000).map(|i| if i % 2 == 0 { 1_000 } else { 2_000 }).collect();
+        let summary = summarize(&alternating);
+        assert_eq!(summary.jitter_source(), JitterSource::Execution);
+        assert_eq!((summary.mean_cycles, summary.mean_successive_delta), (1_500.0, 1_000.0));
+    }
+}
@@ -0,0 +1,244 @@
+//! Measurement-thread isolation.
+//!
+//! A maximum taken on a CPU that also runs other tasks, timer ticks and device
+//! interrupts bounds the noise, not the policy. `prepare` pins the calling thread to
+//! one CPU, raises it to `SCHED_FIFO` and checks the host for the usual isolation
+//! setup (`isolcpus`, `nohz_full`, interrupts steered away, `performance` governor).
+//! What is missing is reported as `NoiseFinding`s; with `NoisePolicy::Abort` any
+//! finding fails the setup instead, for CI runners that must produce clean numbers.
+
+use std::fmt;
+use std::fs;
+
+/// What to do when the environment is noisy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoisePolicy {
+    Warn,
+    Abort,
+}
+
+#[derive(Debug, Clone)]
+pub struct IsolationConfig {
+    /// CPU to pin to. `None` picks the highest isolated CPU, or the highest CPU if none
+    /// is isolated.
+    pub cpu: Option<usize>,
+    /// `SCHED_FIFO` priority (1-99); `None` leaves the scheduling class alone.
+    pub fifo_priority: Option<i32>,
+    pub policy: NoisePolicy,
+}
+
+impl Default for IsolationConfig {
+    fn default() -> Self {
+        Self { cpu: None, fifo_priority: Some(80), policy: NoisePolicy::Warn }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NoiseFinding {
+    PinFailed { cpu: usize, error: String },
+    FifoFailed { error: String },
+    /// The CPU is not in `isolcpus`, so the scheduler still places other tasks on it.
+    NotIsolated { cpu: usize },
+    /// The CPU still takes the periodic scheduler tick.
+    TickNotStopped { cpu: usize },
+    /// These interrupts may be delivered to the CPU.
+    IrqsRouted { cpu: usize, irqs: Vec<u32> },
+    FrequencyScaling { cpu: usize, governor: String },
+    /// Isolation cannot be set up or checked on this platform.
+    Unsupported,
+}
+
+impl fmt::Display for NoiseFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoiseFinding::PinFailed { cpu, error } => write!(f, "could not pin to CPU {}: {}", cpu, error),
+            NoiseFinding::FifoFailed { error } => write!(f, "could not switch to SCHED_FIFO: {}", error),
+            NoiseFinding::NotIsolated { cpu } => write!(f, "CPU {} is not in isolcpus", cpu),
+            NoiseFinding::TickNotStopped { cpu } => write!(f, "CPU {} is not in nohz_full", cpu),
+            NoiseFinding::IrqsRouted { cpu, irqs } => write!(f, "{} interrupts can be delivered to CPU {}", irqs.len(), cpu),
+            NoiseFinding::FrequencyScaling { cpu, governor } => write!(f, "CPU {} uses the {} governor", cpu, governor),
+            NoiseFinding::Unsupported => write!(f, "measurement isolation is not supported on this platform"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IsolationReport {
+    pub cpu: usize,
+    pub pinned: bool,
+    pub fifo: bool,
+    pub findings: Vec<NoiseFinding>,
+}
+
+impl IsolationReport {
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// Returned by `prepare` under `NoisePolicy::Abort`.
+#[derive(Debug)]
+pub struct NoisyEnvironment(pub IsolationReport);
+
+impl fmt::Display for NoisyEnvironment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "noisy measurement environment on CPU {}: ", self.0.cpu)?;
+        let findings: Vec<String> = self.0.findings.iter().map(ToString::to_string).collect();
+        f.write_str(&findings.join("; "))
+    }
+}
+
+impl std::error::Error for NoisyEnvironment {}
+
+/// Parses a kernel CPU list such as `1,4-7`.
+pub fn parse_cpu_list(list: &str) -> Vec<usize> {
+    list.trim()
+        .split(',')
+        .filter(|part| !part.is_empty())
+        .flat_map(|part| match part.split_once('-') {
+            Some((lo, hi)) => match (lo.parse::<usize>(), hi.parse::<usize>()) {
+                (Ok(lo), Ok(hi)) => (lo..=hi).collect(),
+                _ => Vec::new(),
+            },
+            None => part.parse().into_iter().collect(),
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn sysfs_cpu_list(path: &str) -> Vec<usize> {
+    fs::read_to_string(path).map(|s| parse_cpu_list(&s)).unwrap_or_default()
+}
+
+/// Interrupts whose affinity includes `cpu`. Uses the effective affinity where the
+/// kernel exposes it, since the requested mask is often all CPUs.
+#[cfg(target_os = "linux")]
+fn irqs_routed_to(cpu: usize) -> Vec<u32> {
+    let Ok(entries) = fs::read_dir("/proc/irq") else { return Vec::new() };
+    let mut irqs: Vec<u32> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let irq: u32 = e.file_name().to_str()?.parse().ok()?;
+            let dir = e.path();
+            let list = fs::read_to_string(dir.join("effective_affinity_list"))
+                .or_else(|_| fs::read_to_string(dir.join("smp_affinity_list")))
+                .ok()?;
+            parse_cpu_list(&list).contains(&cpu).then_some(irq)
+        })
+        .collect();
+    irqs.sort_unstable();
+    irqs
+}
+
+#[cfg(target_os = "linux")]
+fn pin_to(cpu: usize) -> std::io::Result<()> {
+    // SAFETY: `set` is a zeroed cpu_set_t that outlives the call.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_fifo(priority: i32) -> std::io::Result<()> {
+    // SAFETY: sched_param is plain data (zeroed is valid, and musl has extra fields);
+    // pid 0 is the calling thread.
+    let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+    param.sched_priority = priority;
+    if unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Isolates the calling thread for measurement; call it on the thread that will
+/// measure, before creating its `Measurer`.
+#[cfg(target_os = "linux")]
+pub fn prepare(config: &IsolationConfig) -> Result<IsolationReport, NoisyEnvironment> {
+    let isolated = sysfs_cpu_list("/sys/devices/system/cpu/isolated");
+    let nohz_full = sysfs_cpu_list("/sys/devices/system/cpu/nohz_full");
+    let last_cpu = std::thread::available_parallelism().map_or(0, |n| n.get() - 1);
+    let cpu = config.cpu.or_else(|| isolated.iter().copied().max()).unwrap_or(last_cpu);
+
+    let mut findings = Vec::new();
+    let pinned = match pin_to(cpu) {
+        Ok(()) => true,
+        Err(e) => {
+            findings.push(NoiseFinding::PinFailed { cpu, error: e.to_string() });
+            false
+        }
+    };
+    let fifo = match config.fifo_priority.map(set_fifo) {
+        Some(Ok(())) => true,
+        Some(Err(e)) => {
+            findings.push(NoiseFinding::FifoFailed { error: e.to_string() });
+            false
+        }
+        None => false,
+    };
+    if !isolated.contains(&cpu) {
+        findings.push(NoiseFinding::NotIsolated { cpu });
+    }
+    if !nohz_full.contains(&cpu) {
+        findings.push(NoiseFinding::TickNotStopped { cpu });
+    }
+    let irqs = irqs_routed_to(cpu);
+    if !irqs.is_empty() {
+        findings.push(NoiseFinding::IrqsRouted { cpu, irqs });
+    }
+    if let Ok(governor) = fs::read_to_string(format!("/sys/devices/system/cpu/cpu{}/cpufreq/scaling_governor", cpu)) {
+        let governor = governor.trim();
+        if governor != "performance" {
+            findings.push(NoiseFinding::FrequencyScaling { cpu, governor: governor.to_string() });
+        }
+    }
+
+    finish(IsolationReport { cpu, pinned, fifo, findings }, config.policy)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn prepare(config: &IsolationConfig) -> Result<IsolationReport, NoisyEnvironment> {
+    let report = IsolationReport { cpu: config.cpu.unwrap_or(0), pinned: false, fifo: false, findings: vec![NoiseFinding::Unsupported] };
+    finish(report, config.policy)
+}
+
+fn finish(report: IsolationReport, policy: NoisePolicy) -> Result<IsolationReport, NoisyEnvironment> {
+    if policy == NoisePolicy::Abort && !report.is_clean() {
+        return Err(NoisyEnvironment(report));
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_cpu_lists_parse_ranges_and_skip_garbage() {
+        assert_eq!(parse_cpu_list("1,4-7\n"), vec![1, 4, 5, 6, 7]);
+        assert_eq!(parse_cpu_list("3"), vec![3]);
+        assert_eq!(parse_cpu_list("\n"), Vec::<usize>::new());
+        assert_eq!(parse_cpu_list("0,x-2,5"), vec![0, 5]);
+    }
+
+    #[test]
+    fn a_noisy_host_only_fails_setup_under_the_abort_policy() {
+        let noisy = IsolationReport { cpu: 2, pinned: true, fifo: false, findings: vec![NoiseFinding::NotIsolated { cpu: 2 }] };
+        assert!(finish(noisy.clone(), NoisePolicy::Warn).is_ok());
+        let error = finish(noisy, NoisePolicy::Abort).unwrap_err();
+        assert_eq!(error.to_string(), "noisy measurement environment on CPU 2: CPU 2 is not in isolcpus");
+        let clean = IsolationReport { cpu: 0, pinned: true, fifo: true, findings: Vec::new() };
+        assert!(finish(clean, NoisePolicy::Abort).is_ok());
+
+        // On a thread of its own, so the pinning does not outlive the test.
+        let config = IsolationConfig { cpu: Some(0), fifo_priority: None, policy: NoisePolicy::Warn };
+        let report = std::thread::spawn(move || prepare(&config)).join().unwrap().expect("warnings never fail setup");
+        assert_eq!(report.cpu, 0);
+        assert!(!report.fifo);
+        assert_eq!(report.pinned, !report.findings.iter().any(|f| matches!(f, NoiseFinding::PinFailed { .. })));
+    }
+}
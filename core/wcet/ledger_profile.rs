@@ -0,0 +1,146 @@
+//! WCET of the ledger side of a decision.
+//!
+//! The Gate's deadline covers more than the VM: every decision is appended to the
+//! ledger, and some appends also seal a segment or write a Merkle checkpoint, both of
+//! which fsync. Averaged together those spikes disappear in the noise, so each append
+//! is classified by what it triggered (through the store's event callbacks) and the
+//! paths are profiled separately, along with `commit`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::cycles::{default_cycle_source, Measurer};
+use super::histogram::{LatencyHistogram, LatencySummary};
+use super::profile::WcetProfile;
+use crate::ledger::storage::{DeterministicStore, LedgerResult, StoreConfig, StoreEvent};
+use crate::ledger::tick::LogicalTicks;
+
+/// Distribution of one ledger path.
+#[derive(Debug, Clone, Default)]
+pub struct PathProfile {
+    pub max_cycles: u64,
+    pub latency: LatencyHistogram,
+    pub summary: LatencySummary,
+}
+
+#[derive(Default)]
+struct PathRecorder {
+    samples: Vec<u64>,
+    latency: LatencyHistogram,
+}
+
+impl PathRecorder {
+    fn record(&mut self, cycles: u64) {
+        self.samples.push(cycles);
+        self.latency.record(cycles);
+    }
+
+    fn finish(self) -> PathProfile {
+        PathProfile {
+            max_cycles: self.latency.max(),
+            summary: LatencySummary::from_samples(&self.samples, &self.latency),
+            latency: self.latency,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LedgerWcetProfile {
+    /// Appends that neither sealed a segment nor wrote a checkpoint.
+    pub append: PathProfile,
+    /// Appends that sealed the current segment and opened the next one.
+    pub rollover: PathProfile,
+    /// Appends that wrote a Merkle checkpoint (and did not roll over).
+    pub checkpoint: PathProfile,
+    pub commit: PathProfile,
+}
+
+impl LedgerWcetProfile {
+    /// Worst append over all paths.
+    pub fn max_append_cycles(&self) -> u64 {
+        self.append.max_cycles.max(self.rollover.max_cycles).max(self.checkpoint.max_cycles)
+    }
+
+    /// Bound on one decision from Gate entry to a committed ledger entry.
+    pub fn max_gate_to_ledger_cycles(&self, vm: &WcetProfile) -> u64 {
+        vm.max_gate_cycles + self.max_append_cycles() + self.commit.max_cycles
+    }
+}
+
+/// Opens a fresh store from `config` and appends `payload` `iterations` times, each
+/// followed by a `commit`. Choose `segment_size` and `checkpoint_interval` so that
+/// rollovers and checkpoints occur several times within `iterations`; a path that
+/// never occurs is reported empty.
+pub fn profile_ledger(config: StoreConfig, payload: &[u8], iterations: usize) -> LedgerResult<LedgerWcetProfile> {
+    let mut store = DeterministicStore::open(config)?;
+    // One logical tick per decision, as the control loop would advance it.
+    let ticks = Arc::new(LogicalTicks::default());
+    store.set_tick_source(ticks.clone());
+    let rolled = Arc::new(AtomicBool::new(false));
+    let checkpointed = Arc::new(AtomicBool::new(false));
+    {
+        let (rolled, checkpointed) = (rolled.clone(), checkpointed.clone());
+        store.on_event(move |event| match event {
+            StoreEvent::SegmentSealed { .. } => rolled.store(true, Ordering::Relaxed),
+            StoreEvent::Checkpointed { .. } => checkpointed.store(true, Ordering::Relaxed),
+            StoreEvent::Appended { .. } => {}
+        });
+    }
+
+    let measurer = Measurer::new(default_cycle_source());
+    let (mut append, mut rollover, mut checkpoint, mut commit) =
+        (PathRecorder::default(), PathRecorder::default(), PathRecorder::default(), PathRecorder::default());
+    for _ in 0..iterations {
+        ticks.advance();
+        rolled.store(false, Ordering::Relaxed);
+        checkpointed.store(false, Ordering::Relaxed);
+        let mut result = Ok(());
+        let run = measurer.measure(|| result = store.append_entry(payload));
+        result?;
+        if rolled.load(Ordering::Relaxed) {
+            rollover.record(run.cycles);
+        } else if checkpointed.load(Ordering::Relaxed) {
+            checkpoint.record(run.cycles);
+        } else {
+            append.record(run.cycles);
+        }
+
+        let mut result = Ok(());
+        let run = measurer.measure(|| result = store.commit());
+        result?;
+        commit.record(run.cycles);
+    }
+
+    Ok(LedgerWcetProfile {
+        append: append.finish(),
+        rollover: rollover.finish(),
+        checkpoint: checkpoint.finish(),
+        commit: commit.finish(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wcet::disturb::MeasurementMode;
+    use crate::wcet::profile::profile;
+
+    #[test]
+    fn every_append_is_classified_by_the_path_it_took() {
+        let dir = std::env::temp_dir().join(format!("rfsn-wcet-ledger-paths-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = StoreConfig::new(&dir).segment_size(2 * 1024).checkpoint_interval(5);
+        let ledger = profile_ledger(config, &[0x5a; 256], 60).unwrap();
+
+        let appends = ledger.append.latency.len() + ledger.rollover.latency.len() + ledger.checkpoint.latency.len();
+        assert_eq!(appends, 60);
+        assert_eq!(ledger.commit.latency.len(), 60);
+        assert!(!ledger.rollover.latency.is_empty() && !ledger.checkpoint.latency.is_empty() && !ledger.append.latency.is_empty());
+        assert!(ledger.max_append_cycles() >= ledger.rollover.max_cycles.max(ledger.checkpoint.max_cycles));
+
+        let vm = profile(4, 1_000_000, MeasurementMode::Warm, || {});
+        assert_eq!(ledger.max_gate_to_ledger_cycles(&vm), vm.max_gate_cycles + ledger.max_append_cycles() + ledger.commit.max_cycles);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
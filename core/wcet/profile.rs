@@ -0,0 +1,251 @@
+//! Profiling a policy execution over many iterations.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use super::alloc::{measure_heap, HeapUsage};
+use super::cycles::{default_cycle_source, Measurement, Measurer};
+use super::disturb::{Disturbance, MeasurementMode, DEFAULT_EVICTION_BYTES};
+use super::dvfs::{ClockCheck, ClockMonitor};
+use super::evt::{estimate_pwcet, EvtEstimate};
+use super::histogram::{LatencyHistogram, LatencySummary};
+
+pub struct WcetProfile {
+    pub max_gate_cycles: u64,
+    pub max_vm_cycles: u64,
+    /// Budget the profile was taken against.
+    pub budget_cycles: u64,
+    pub mode: MeasurementMode,
+    /// Fraction of the budget left over; negative when the budget was exceeded.
+    pub capacity_margin: f64,
+    /// Distribution of all runs, not just the slowest.
+    pub latency: LatencyHistogram,
+    pub summary: LatencySummary,
+    /// Event counts of the slowest run, to tell cache or branch effects from real work.
+    pub worst_run: Measurement,
+    /// Probabilistic bound; `None` with too few samples for a fit.
+    pub evt: Option<EvtEstimate>,
+    /// Whether the CPU clock held still during the run.
+    pub clock: ClockCheck,
+    /// Largest heap peak and allocation count of any run; zero unless
+    /// `alloc::AccountingAllocator` is the global allocator.
+    pub heap: HeapUsage,
+}
+
+impl WcetProfile {
+    pub fn within_budget(&self) -> bool {
+        self.max_vm_cycles <= self.budget_cycles
+    }
+
+    /// False when the clock changed mid-run; such numbers must not be used as bounds.
+    pub fn is_valid(&self) -> bool {
+        self.clock.is_valid()
+    }
+
+    pub fn heap_within(&self, max_heap_bytes: u64) -> bool {
+        self.heap.peak_bytes <= max_heap_bytes
+    }
+}
+
+/// Safety envelope for FastCtrl deadlines, used when a policy declares no budget.
+pub const DEFAULT_BUDGET_CYCLES: u64 = 50_000;
+
+/// Heap ceiling for a policy run, used when a policy declares none.
+pub const DEFAULT_HEAP_BYTES: u64 = 64 * 1024;
+
+/// Iterations between clock samples.
+const CLOCK_SAMPLE_INTERVAL: usize = 256;
+
+/// Gate framing overhead added on top of the VM time.
+pub const GATE_FRAMING_CYCLES: u64 = 1500;
+
+/// Runs `execute` (one policy evaluation) `iterations` times on the default cycle
+/// source and summarizes the result against `budget_cycles`. Exceeding the budget is
+/// reported through `WcetProfile::within_budget`, not a panic; callers decide.
+pub fn profile<F: FnMut()>(iterations: usize, budget_cycles: u64, mode: MeasurementMode, mut execute: F) -> WcetProfile {
+    profile_indexed(iterations, budget_cycles, mode, |_| execute()).0
+}
+
+/// `profile`, passing the iteration number to `execute`; also returns the cycles of
+/// every iteration.
+fn profile_indexed<F: FnMut(usize)>(iterations: usize, budget_cycles: u64, mode: MeasurementMode, mut execute: F) -> (WcetProfile, Vec<u64>) {
+    let mut max_vm = 0;
+    let mut worst_run = Measurement::default();
+    let mut samples = Vec::with_capacity(iterations);
+    let mut latency = LatencyHistogram::default();
+    let mut heap = HeapUsage::default();
+    let measurer = Measurer::new(default_cycle_source());
+    let mut disturbance = (mode == MeasurementMode::Pessimistic).then(|| Disturbance::new(DEFAULT_EVICTION_BYTES, 0x2545_f491_4f6c_dd1d));
+    let mut clock = ClockMonitor::start();
+
+    for iteration in 0..iterations {
+        if iteration % CLOCK_SAMPLE_INTERVAL == CLOCK_SAMPLE_INTERVAL - 1 {
+            clock.sample();
+        }
+        let mut run_once = || execute(iteration);
+        // The heap scope sits inside the disturbance, which allocates padding of its own.
+        let (run, run_heap) = match disturbance.as_mut() {
+            Some(disturbance) => disturbance.run(|| measure_heap(|| measurer.measure(&mut run_once))),
+            None => measure_heap(|| measurer.measure(&mut run_once)),
+        };
+        heap = heap.max(run_heap);
+
+        samples.push(run.cycles);
+        latency.record(run.cycles);
+        if run.cycles > max_vm {
+            max_vm = run.cycles;
+            worst_run = run;
+        }
+    }
+
+    let profile = WcetProfile {
+        max_gate_cycles: max_vm + GATE_FRAMING_CYCLES,
+        max_vm_cycles: max_vm,
+        budget_cycles,
+        mode,
+        capacity_margin: (budget_cycles as f64 - max_vm as f64) / budget_cycles as f64,
+        summary: LatencySummary::from_samples(&samples, &latency),
+        latency,
+        worst_run,
+        evt: estimate_pwcet(&samples, 1e-9, 0.95),
+        clock: clock.finish(default_cycle_source().assumed_hz()),
+        heap,
+    };
+    (profile, samples)
+}
+
+/// One recorded decision input: a compiled policy and the context it was evaluated on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorpusInput {
+    /// File name or other label, reported for the worst input.
+    pub name: String,
+    pub policy: Vec<u8>,
+    pub context: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Corpus {
+    pub inputs: Vec<CorpusInput>,
+}
+
+impl Corpus {
+    pub fn push(&mut self, name: &str, policy: &[u8], context: &[u8]) {
+        self.inputs.push(CorpusInput { name: name.to_string(), policy: policy.to_vec(), context: context.to_vec() });
+    }
+
+    /// Every regular file in `dir` as a context for `policy`, in name order. This is the
+    /// layout fuzzers write their corpora in, so a fuzzing run's corpus (including the
+    /// slow inputs it found) can be profiled directly.
+    pub fn from_dir(dir: &Path, policy: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut files: Vec<_> = fs::read_dir(dir)?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+            .map(|e| e.path())
+            .collect();
+        files.sort();
+        let mut corpus = Self::default();
+        for path in files {
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            corpus.push(&name, policy, &fs::read(&path)?);
+        }
+        Ok(corpus)
+    }
+}
+
+/// Per-input maxima and the input behind the overall maximum.
+pub struct CorpusProfile {
+    /// All runs over all inputs.
+    pub profile: WcetProfile,
+    /// Index into the corpus of the input that produced `profile.max_vm_cycles`.
+    pub worst_input: usize,
+    pub max_cycles_per_input: Vec<u64>,
+}
+
+impl CorpusProfile {
+    pub fn worst<'c>(&self, corpus: &'c Corpus) -> &'c CorpusInput {
+        &corpus.inputs[self.worst_input]
+    }
+}
+
+/// Profiles `execute` over every input of `corpus`, `rounds` times each. Inputs are
+/// interleaved rather than run in blocks, so warm-up and drift do not favour the
+/// inputs that happen to come first.
+pub fn profile_corpus<F>(corpus: &Corpus, rounds: usize, budget_cycles: u64, mode: MeasurementMode, mut execute: F) -> Option<CorpusProfile>
+where
+    F: FnMut(&CorpusInput),
+{
+    let n = corpus.inputs.len();
+    if n == 0 {
+        return None;
+    }
+    let (profile, samples) = profile_indexed(n * rounds, budget_cycles, mode, |i| execute(&corpus.inputs[i % n]));
+    let mut max_cycles_per_input = vec![0u64; n];
+    for (i, &cycles) in samples.iter().enumerate() {
+        max_cycles_per_input[i % n] = max_cycles_per_input[i % n].max(cycles);
+    }
+    let worst_input = (0..n).max_by_key(|&i| max_cycles_per_input[i]).unwrap_or(0);
+    Some(CorpusProfile { profile, worst_input, max_cycles_per_input })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hint::black_box;
+
+    use crate::wcet::evt::EVT_BLOCK_SIZE;
+
+    fn work(n: u64) {
+        let mut acc = 0u64;
+        for i in 0..n {
+            acc = black_box(acc.wrapping_mul(31).wrapping_add(i));
+        }
+        black_box(acc);
+    }
+
+    #[test]
+    fn a_profile_summarizes_every_run_against_the_budget() {
+        // Enough blocks for the EVT fit.
+        let iterations = EVT_BLOCK_SIZE * 30;
+        let mut runs = 0;
+        let profile = profile(iterations, u64::MAX / 2, MeasurementMode::Warm, || {
+            runs += 1;
+            work(100);
+        });
+        assert_eq!((runs, profile.latency.len()), (iterations, iterations as u64));
+        assert_eq!(profile.max_gate_cycles, profile.max_vm_cycles + GATE_FRAMING_CYCLES);
+        assert_eq!((profile.latency.max(), profile.worst_run.cycles), (profile.max_vm_cycles, profile.max_vm_cycles));
+        assert!(profile.within_budget() && profile.capacity_margin > 0.99);
+        assert!(profile.summary.p50_cycles <= profile.max_vm_cycles);
+        assert!(profile.evt.as_ref().is_some_and(|evt| evt.pwcet_cycles >= profile.max_vm_cycles));
+
+        let tight = super::profile(10, 1, MeasurementMode::Warm, || work(1_000));
+        assert!(!tight.within_budget() && tight.capacity_margin < 0.0);
+        assert!(tight.evt.is_none());
+    }
+
+    #[test]
+    fn a_corpus_from_a_fuzzer_directory_reports_its_slowest_input() {
+        let dir = std::env::temp_dir().join(format!("rfsn-wcet-corpus-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("crashes")).unwrap();
+        fs::write(dir.join("b-slow"), [0xff; 64]).unwrap();
+        fs::write(dir.join("a-fast"), [0x01]).unwrap();
+        let corpus = Corpus::from_dir(&dir, b"policy").unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        let names: Vec<&str> = corpus.inputs.iter().map(|input| input.name.as_str()).collect();
+        assert_eq!(names, ["a-fast", "b-slow"]);
+        assert!(corpus.inputs.iter().all(|input| input.policy == b"policy"));
+
+        let mut runs = [0usize; 2];
+        let profiled = profile_corpus(&corpus, 20, u64::MAX / 2, MeasurementMode::Warm, |input| {
+            runs[input.context.len() / 64] += 1;
+            work((input.context.len() as u64 - 1) * 50_000);
+        })
+        .unwrap();
+        assert_eq!(runs, [20, 20]);
+        assert_eq!(profiled.worst(&corpus).name, "b-slow");
+        assert_eq!(profiled.max_cycles_per_input[1], profiled.profile.max_vm_cycles);
+        assert!(profile_corpus(&Corpus::default(), 20, 1, MeasurementMode::Warm, |_| {}).is_none());
+    }
+}
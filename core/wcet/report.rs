@@ -0,0 +1,236 @@
+//! Machine-readable WCET reports.
+//!
+//! A report is a `WcetProfile` plus the conditions it was measured under, so it can
+//! be archived as certification evidence and compared across runs. Numbers measured
+//! on a machine with frequency scaling enabled or without isolated cores are not
+//! comparable with ones taken on a tuned host; the environment block makes that
+//! visible instead of leaving it to whoever reads the numbers later.
+
+use std::fmt::Write as _;
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use super::alloc::HeapUsage;
+use super::cycles::{default_cycle_source, Measurement};
+use super::disturb::MeasurementMode;
+use super::dvfs::ClockCheck;
+use super::evt::EvtEstimate;
+use super::histogram::{JitterSource, LatencySummary};
+use super::profile::WcetProfile;
+use crate::ledger::outbox::unix_now;
+
+pub const REPORT_VERSION: u32 = 1;
+
+/// Host conditions at measurement time. Fields are `None` where the platform does not
+/// expose them (non-Linux, containers without `/sys`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Environment {
+    pub cpu_model: Option<String>,
+    pub logical_cpus: Option<usize>,
+    /// cpufreq governor of CPU 0, e.g. `performance`.
+    pub frequency_governor: Option<String>,
+    pub cur_frequency_khz: Option<u64>,
+    pub kernel: Option<String>,
+    /// Isolated CPU list, e.g. `2-3`; empty when none are isolated.
+    pub isolcpus: Option<String>,
+    pub arch: String,
+    pub os: String,
+}
+
+fn read_trimmed(path: &str) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+impl Environment {
+    pub fn capture() -> Self {
+        let cpu_model = fs::read_to_string("/proc/cpuinfo").ok().and_then(|info| {
+            info.lines()
+                .find(|l| l.starts_with("model name") || l.starts_with("Model") || l.starts_with("CPU part"))
+                .and_then(|l| l.split_once(':'))
+                .map(|(_, v)| v.trim().to_string())
+        });
+        // Prefer the kernel's view; fall back to the boot parameter.
+        let isolcpus = read_trimmed("/sys/devices/system/cpu/isolated").or_else(|| {
+            read_trimmed("/proc/cmdline")?
+                .split_whitespace()
+                .find_map(|arg| arg.strip_prefix("isolcpus=").map(str::to_string))
+        });
+        Self {
+            cpu_model,
+            logical_cpus: std::thread::available_parallelism().ok().map(|n| n.get()),
+            frequency_governor: read_trimmed("/sys/devices/system/cpu/cpu0/cpufreq/scaling_governor"),
+            cur_frequency_khz: read_trimmed("/sys/devices/system/cpu/cpu0/cpufreq/scaling_cur_freq").and_then(|v| v.parse().ok()),
+            kernel: read_trimmed("/proc/sys/kernel/osrelease"),
+            isolcpus,
+            arch: std::env::consts::ARCH.to_string(),
+            os: std::env::consts::OS.to_string(),
+        }
+    }
+}
+
+/// Serialized form of a profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WcetReport {
+    pub version: u32,
+    pub generated_at: u64,
+    pub environment: Environment,
+    pub cycle_source: String,
+    pub cycle_frequency_hz: u64,
+    pub mode: MeasurementMode,
+    pub iterations: u64,
+    pub budget_cycles: u64,
+    pub max_vm_cycles: u64,
+    pub max_gate_cycles: u64,
+    pub capacity_margin: f64,
+    pub min_cycles: u64,
+    pub summary: LatencySummary,
+    pub jitter_source: JitterSource,
+    pub worst_run: Measurement,
+    #[serde(default)]
+    pub evt: Option<EvtEstimate>,
+    pub clock: ClockCheck,
+    #[serde(default)]
+    pub heap: HeapUsage,
+    /// Non-empty histogram buckets as `(upper edge, count)`.
+    pub histogram: Vec<(u64, u64)>,
+}
+
+const CSV_HEADER: &str = "generated_at,cycle_source,cycle_frequency_hz,mode,iterations,budget_cycles,max_vm_cycles,max_gate_cycles,capacity_margin,min_cycles,p50_cycles,p99_cycles,p9999_cycles,mean_cycles,stddev_cycles,mean_successive_delta,jitter_source,pwcet_cycles,pwcet_upper_cycles,clock_valid,heap_peak_bytes,allocations,cpu_model,frequency_governor,cur_frequency_khz,kernel,isolcpus,arch";
+
+/// Quotes a CSV field if needed (RFC 4180).
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl WcetReport {
+    pub fn new(profile: &WcetProfile, environment: Environment) -> Self {
+        let source = default_cycle_source();
+        Self {
+            version: REPORT_VERSION,
+            generated_at: unix_now(),
+            environment,
+            cycle_source: source.name().to_string(),
+            cycle_frequency_hz: source.frequency_hz(),
+            mode: profile.mode,
+            iterations: profile.latency.len(),
+            budget_cycles: profile.budget_cycles,
+            max_vm_cycles: profile.max_vm_cycles,
+            max_gate_cycles: profile.max_gate_cycles,
+            capacity_margin: profile.capacity_margin,
+            min_cycles: profile.latency.min(),
+            summary: profile.summary,
+            jitter_source: profile.summary.jitter_source(),
+            worst_run: profile.worst_run,
+            evt: profile.evt.clone(),
+            clock: profile.clock.clone(),
+            heap: profile.heap,
+            histogram: profile.latency.buckets().collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// One header line and one data row; the histogram is left out (see
+    /// `histogram_csv`). Rows from several reports can be concatenated under one header.
+    pub fn to_csv(&self) -> String {
+        let opt = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_default();
+        let env = &self.environment;
+        let fields = [
+            self.generated_at.to_string(),
+            self.cycle_source.clone(),
+            self.cycle_frequency_hz.to_string(),
+            format!("{:?}", self.mode).to_lowercase(),
+            self.iterations.to_string(),
+            self.budget_cycles.to_string(),
+            self.max_vm_cycles.to_string(),
+            self.max_gate_cycles.to_string(),
+            format!("{:.6}", self.capacity_margin),
+            self.min_cycles.to_string(),
+            self.summary.p50_cycles.to_string(),
+            self.summary.p99_cycles.to_string(),
+            self.summary.p9999_cycles.to_string(),
+            format!("{:.1}", self.summary.mean_cycles),
+            format!("{:.1}", self.summary.stddev_cycles),
+            format!("{:.1}", self.summary.mean_successive_delta),
+            format!("{:?}", self.jitter_source).to_lowercase(),
+            opt(self.evt.as_ref().map(|e| e.pwcet_cycles)),
+            opt(self.evt.as_ref().map(|e| e.pwcet_upper_cycles)),
+            self.clock.is_valid().to_string(),
+            self.heap.peak_bytes.to_string(),
+            self.heap.allocations.to_string(),
+            env.cpu_model.clone().unwrap_or_default(),
+            env.frequency_governor.clone().unwrap_or_default(),
+            opt(env.cur_frequency_khz),
+            env.kernel.clone().unwrap_or_default(),
+            env.isolcpus.clone().unwrap_or_default(),
+            env.arch.clone(),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        format!("{}\n{}\n", CSV_HEADER, row.join(","))
+    }
+
+    pub fn histogram_csv(&self) -> String {
+        let mut out = String::from("upper_cycles,count\n");
+        for (upper, count) in &self.histogram {
+            let _ = writeln!(out, "{},{}", upper, count);
+        }
+        out
+    }
+}
+
+impl WcetProfile {
+    /// JSON report with the current host's environment.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        WcetReport::new(self, Environment::capture()).to_json()
+    }
+
+    /// CSV report with the current host's environment; see `WcetReport::to_csv`.
+    pub fn to_csv(&self) -> String {
+        WcetReport::new(self, Environment::capture()).to_csv()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wcet::profile::profile;
+
+    #[test]
+    fn reports_roundtrip_as_json_and_flatten_to_one_csv_row() {
+        let measured = profile(64, 1_000_000, MeasurementMode::Warm, || {});
+        let environment = Environment {
+            cpu_model: Some("Vendor CPU, rev \"B\"".to_string()),
+            arch: "aarch64".to_string(),
+            os: "linux".to_string(),
+            ..Default::default()
+        };
+        let report = WcetReport::new(&measured, environment);
+        assert_eq!((report.version, report.iterations, report.max_vm_cycles), (REPORT_VERSION, 64, measured.max_vm_cycles));
+        let parsed = serde_json::from_str::<WcetReport>(&report.to_json().unwrap()).unwrap();
+        // serde_json may parse a float back one ulp off; everything else roundtrips exactly.
+        let close = |a: f64, b: f64| (a - b).abs() <= b.abs() * 1e-12;
+        assert!(close(parsed.summary.mean_cycles, report.summary.mean_cycles));
+        assert!(close(parsed.summary.stddev_cycles, report.summary.stddev_cycles));
+        assert!(close(parsed.summary.mean_successive_delta, report.summary.mean_successive_delta));
+        assert_eq!(WcetReport { summary: report.summary, ..parsed }, report);
+
+        let csv = report.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].contains(",\"Vendor CPU, rev \"\"B\"\"\","));
+        // The quoted model holds one comma of its own.
+        assert_eq!(lines[1].matches(',').count(), lines[0].matches(',').count() + 1);
+
+        let histogram = report.histogram_csv();
+        let counted: u64 = histogram.lines().skip(1).map(|l| l.split_once(',').unwrap().1.parse::<u64>().unwrap()).sum();
+        assert_eq!(counted, 64);
+    }
+}
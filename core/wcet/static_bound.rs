@@ -0,0 +1,205 @@
+//! Analytical WCET bounds for policy bytecode.
+//!
+//! Measurement only covers the inputs that were tried. The analyzer here bounds every
+//! input: it walks the decoded program, charges each instruction the worst-case
+//! cycles its opcode can take under a `CostModel`, and takes the most expensive path
+//! through the control-flow graph. Programs with backward jumps are rejected, as the
+//! policy VM requires; the control-flow graph is then a DAG and the longest path is
+//! exact over it. The result is recorded as the estimate in the `BudgetRegistry`,
+//! where `check_load` admits a policy only if both the measured and the analytical
+//! bound fit its budget.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::budget::BudgetRegistry;
+use super::profile::WcetProfile;
+
+/// Control flow out of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Falls through to the next instruction.
+    Next,
+    Jump(usize),
+    /// Either falls through or jumps.
+    Branch(usize),
+    Halt,
+}
+
+/// One decoded instruction; `pc` is its index in the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instr {
+    pub opcode: u8,
+    pub flow: Flow,
+}
+
+/// Turns raw policy bytecode into instructions. Implemented by the VM, which owns the
+/// encoding.
+pub trait Decoder {
+    fn decode(&self, bytecode: &[u8]) -> Result<Vec<Instr>, AnalysisError>;
+}
+
+/// Worst-case cycles per opcode on one target, plus the interpreter's per-instruction
+/// dispatch overhead. Stored as JSON next to the target's budget registry.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CostModel {
+    pub target: String,
+    pub dispatch_cycles: u64,
+    pub opcodes: BTreeMap<u8, u64>,
+}
+
+impl CostModel {
+    pub fn new(target: &str, dispatch_cycles: u64) -> Self {
+        Self { target: target.to_string(), dispatch_cycles, opcodes: BTreeMap::new() }
+    }
+
+    pub fn cost(mut self, opcode: u8, cycles: u64) -> Self {
+        self.opcodes.insert(opcode, cycles);
+        self
+    }
+
+    fn cycles_of(&self, pc: usize, opcode: u8) -> Result<u64, AnalysisError> {
+        self.opcodes
+            .get(&opcode)
+            .map(|c| c + self.dispatch_cycles)
+            .ok_or(AnalysisError::UnknownOpcode { pc, opcode })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnalysisError {
+    Decode(String),
+    EmptyProgram,
+    /// The cost model has no entry for this opcode, so no bound can be given.
+    UnknownOpcode { pc: usize, opcode: u8 },
+    JumpOutOfRange { pc: usize, target: usize },
+    /// A jump to itself or an earlier instruction: the program may not terminate.
+    BackwardJump { pc: usize, target: usize },
+    /// The last instruction can fall through past the end of the program.
+    FallsOffEnd { pc: usize },
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalysisError::Decode(e) => write!(f, "bytecode does not decode: {}", e),
+            AnalysisError::EmptyProgram => write!(f, "empty program"),
+            AnalysisError::UnknownOpcode { pc, opcode } => write!(f, "no cost for opcode {:#04x} at pc {}", opcode, pc),
+            AnalysisError::JumpOutOfRange { pc, target } => write!(f, "jump at pc {} to {} is out of range", pc, target),
+            AnalysisError::BackwardJump { pc, target } => write!(f, "backward jump at pc {} to {}", pc, target),
+            AnalysisError::FallsOffEnd { pc } => write!(f, "execution can fall off the end at pc {}", pc),
+        }
+    }
+}
+
+impl std::error::Error for AnalysisError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticBound {
+    pub cycles: u64,
+    /// Program counters along the most expensive path.
+    pub worst_path: Vec<usize>,
+    pub target: String,
+}
+
+impl StaticBound {
+    /// An analytical bound below an observed maximum means the cost model
+    /// underestimates some opcode on this machine and the bound must not be trusted.
+    pub fn covers(&self, profile: &WcetProfile) -> bool {
+        self.cycles >= profile.max_vm_cycles
+    }
+}
+
+/// Longest path from pc 0 to any `Halt`.
+pub fn analyze(program: &[Instr], model: &CostModel) -> Result<StaticBound, AnalysisError> {
+    if program.is_empty() {
+        return Err(AnalysisError::EmptyProgram);
+    }
+    let len = program.len();
+    for (pc, instr) in program.iter().enumerate() {
+        if let Flow::Jump(target) | Flow::Branch(target) = instr.flow {
+            if target >= len {
+                return Err(AnalysisError::JumpOutOfRange { pc, target });
+            }
+            if target <= pc {
+                return Err(AnalysisError::BackwardJump { pc, target });
+            }
+        }
+        if matches!(instr.flow, Flow::Next | Flow::Branch(_)) && pc + 1 == len {
+            return Err(AnalysisError::FallsOffEnd { pc });
+        }
+    }
+
+    // All edges point forward, so a reverse sweep sees every successor first.
+    // `best[pc]` is the worst cost from `pc` to a halt, `next[pc]` the successor on that path.
+    let mut best = vec![0u64; len];
+    let mut next = vec![None; len];
+    for pc in (0..len).rev() {
+        let instr = program[pc];
+        let own = model.cycles_of(pc, instr.opcode)?;
+        let successor = match instr.flow {
+            Flow::Next => Some(pc + 1),
+            Flow::Jump(target) => Some(target),
+            Flow::Branch(target) => Some(if best[target] > best[pc + 1] { target } else { pc + 1 }),
+            Flow::Halt => None,
+        };
+        best[pc] = own.saturating_add(successor.map_or(0, |s| best[s]));
+        next[pc] = successor;
+    }
+
+    let mut worst_path = vec![0];
+    while let Some(pc) = next[*worst_path.last().unwrap()] {
+        worst_path.push(pc);
+    }
+    Ok(StaticBound { cycles: best[0], worst_path, target: model.target.clone() })
+}
+
+/// Decodes and analyzes `policy_payload`, and records the bound as the policy's
+/// estimate in `registry`.
+pub fn estimate_and_record(
+    registry: &mut BudgetRegistry,
+    policy: &str,
+    policy_payload: &[u8],
+    decoder: &dyn Decoder,
+    model: &CostModel,
+) -> Result<StaticBound, AnalysisError> {
+    let bound = analyze(&decoder.decode(policy_payload)?, model)?;
+    registry.record_estimate(policy, policy_payload, bound.cycles);
+    Ok(bound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> CostModel {
+        CostModel::new("test", 1).cost(0, 10).cost(1, 100).cost(2, 1)
+    }
+
+    #[test]
+    fn takes_the_most_expensive_branch() {
+        // 0: branch -> 3 | 1: cheap | 2: jump -> 4 | 3: expensive | 4: halt
+        let program = [
+            Instr { opcode: 0, flow: Flow::Branch(3) },
+            Instr { opcode: 2, flow: Flow::Next },
+            Instr { opcode: 2, flow: Flow::Jump(4) },
+            Instr { opcode: 1, flow: Flow::Next },
+            Instr { opcode: 0, flow: Flow::Halt },
+        ];
+        let bound = analyze(&program, &model()).unwrap();
+        assert_eq!(bound.cycles, 11 + 101 + 11);
+        assert_eq!(bound.worst_path, vec![0, 3, 4]);
+    }
+
+    #[test]
+    fn rejects_unbounded_programs() {
+        let looping = [Instr { opcode: 0, flow: Flow::Next }, Instr { opcode: 0, flow: Flow::Branch(0) }, Instr { opcode: 0, flow: Flow::Halt }];
+        assert_eq!(analyze(&looping, &model()), Err(AnalysisError::BackwardJump { pc: 1, target: 0 }));
+        let open_ended = [Instr { opcode: 0, flow: Flow::Next }];
+        assert_eq!(analyze(&open_ended, &model()), Err(AnalysisError::FallsOffEnd { pc: 0 }));
+        let unknown = [Instr { opcode: 9, flow: Flow::Halt }];
+        assert_eq!(analyze(&unknown, &model()), Err(AnalysisError::UnknownOpcode { pc: 0, opcode: 9 }));
+    }
+}
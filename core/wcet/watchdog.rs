@@ -0,0 +1,387 @@
+//! Runtime enforcement of execution budgets.
+//!
+//! Offline profiling only shows that the inputs we tried fit the budget. At runtime
+//! the VM threads a `Watchdog` through its dispatch loop and calls `step` once per
+//! instruction; once the budget is spent the execution is aborted and the caller gets
+//! `Decision::Deny(BudgetExceeded)`. The step limit is deterministic: the same policy
+//! on the same input is cut off at the same instruction on every node. The optional
+//! cycle limit is a backstop for slow host calls and is not replay-stable.
+//! The interpreter also reports its own stack accounting through `enter_frame` and
+//! `exit_frame`, so the worst-case VM stack depth is known (for sizing stacks on
+//! embedded targets) and unexpected recursion is cut off like any other overrun.
+//! Heap use is counted by `alloc::AccountingAllocator` for as long as the watchdog
+//! lives and checked against the optional heap ceiling on every step.
+//! Every abort is appended to the ledger as an `EntryKind::BudgetViolation` entry,
+//! stamped with the ledger tick of its decision rather than wall time, so nodes
+//! replaying the same proposal write the same bytes.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::alloc::AllocScope;
+use super::cycles::{default_cycle_source, CycleSource};
+use crate::ledger::entry::EntryKind;
+use crate::ledger::storage::{DeterministicStore, LedgerResult};
+
+/// Cycles are read every this many steps, to keep the counter off the hot path.
+const CYCLE_CHECK_INTERVAL: u64 = 64;
+
+/// Limits for one policy execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionBudget {
+    pub max_steps: u64,
+    #[serde(default)]
+    pub max_cycles: Option<u64>,
+    /// Bytes of VM stack (frames as the interpreter sizes them).
+    #[serde(default)]
+    pub max_stack_bytes: Option<u64>,
+    #[serde(default)]
+    pub max_call_depth: Option<u32>,
+    /// Peak live heap bytes; needs `alloc::AccountingAllocator` installed.
+    #[serde(default)]
+    pub max_heap_bytes: Option<u64>,
+}
+
+impl ExecutionBudget {
+    pub fn steps(max_steps: u64) -> Self {
+        Self { max_steps, max_cycles: None, max_stack_bytes: None, max_call_depth: None, max_heap_bytes: None }
+    }
+
+    pub fn with_cycles(mut self, max_cycles: u64) -> Self {
+        self.max_cycles = Some(max_cycles);
+        self
+    }
+
+    pub fn with_stack(mut self, max_stack_bytes: u64, max_call_depth: u32) -> Self {
+        self.max_stack_bytes = Some(max_stack_bytes);
+        self.max_call_depth = Some(max_call_depth);
+        self
+    }
+
+    pub fn with_heap(mut self, max_heap_bytes: u64) -> Self {
+        self.max_heap_bytes = Some(max_heap_bytes);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExceededLimit {
+    Steps,
+    Cycles,
+    Stack,
+    CallDepth,
+    Heap,
+}
+
+/// Body of an `EntryKind::BudgetViolation` entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BudgetExceeded {
+    pub policy: String,
+    pub limit: ExceededLimit,
+    pub budget: ExecutionBudget,
+    /// Steps executed when the execution was aborted.
+    pub steps: u64,
+    /// Cycles elapsed when the execution was aborted, if cycles were being checked.
+    #[serde(default)]
+    pub cycles: Option<u64>,
+    /// Stack in use when the execution was aborted.
+    #[serde(default)]
+    pub stack_bytes: u64,
+    #[serde(default)]
+    pub call_depth: u32,
+    /// Peak heap bytes when the execution was aborted.
+    #[serde(default)]
+    pub heap_bytes: u64,
+    /// Ledger tick of the decision whose execution was aborted.
+    pub tick: u64,
+}
+
+impl BudgetExceeded {
+    /// Records the aborted execution as an `EntryKind::BudgetViolation` entry.
+    pub fn append_to(&self, ledger: &mut DeterministicStore) -> LedgerResult<()> {
+        ledger.append_json(EntryKind::BudgetViolation, self)
+    }
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.limit {
+            ExceededLimit::Steps => write!(f, "policy {} exceeded its budget of {} steps", self.policy, self.budget.max_steps),
+            ExceededLimit::Cycles => write!(
+                f,
+                "policy {} exceeded its budget of {} cycles after {} steps",
+                self.policy,
+                self.budget.max_cycles.unwrap_or(0),
+                self.steps
+            ),
+            ExceededLimit::Stack => write!(
+                f,
+                "policy {} exceeded its stack budget of {} bytes",
+                self.policy,
+                self.budget.max_stack_bytes.unwrap_or(0)
+            ),
+            ExceededLimit::CallDepth => write!(
+                f,
+                "policy {} exceeded its call depth of {}",
+                self.policy,
+                self.budget.max_call_depth.unwrap_or(0)
+            ),
+            ExceededLimit::Heap => write!(
+                f,
+                "policy {} exceeded its heap budget of {} bytes",
+                self.policy,
+                self.budget.max_heap_bytes.unwrap_or(0)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// Outcome of a guarded execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision<T> {
+    /// The execution finished within budget with the VM's verdict.
+    Allow(T),
+    Deny(BudgetExceeded),
+}
+
+/// High-water marks of one execution.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionUsage {
+    pub steps: u64,
+    pub max_stack_bytes: u64,
+    pub max_call_depth: u32,
+    #[serde(default)]
+    pub max_heap_bytes: u64,
+    #[serde(default)]
+    pub allocations: u64,
+}
+
+impl ExecutionUsage {
+    /// Element-wise maximum, for folding usages over a corpus.
+    pub fn max(self, other: Self) -> Self {
+        Self {
+            steps: self.steps.max(other.steps),
+            max_stack_bytes: self.max_stack_bytes.max(other.max_stack_bytes),
+            max_call_depth: self.max_call_depth.max(other.max_call_depth),
+            max_heap_bytes: self.max_heap_bytes.max(other.max_heap_bytes),
+            allocations: self.allocations.max(other.allocations),
+        }
+    }
+}
+
+/// Per-execution step, cycle and stack counter.
+pub struct Watchdog<'a> {
+    policy: &'a str,
+    budget: ExecutionBudget,
+    source: &'static dyn CycleSource,
+    steps: u64,
+    started: u64,
+    stack_bytes: u64,
+    call_depth: u32,
+    usage: ExecutionUsage,
+    heap: AllocScope,
+    tick: u64,
+}
+
+impl<'a> Watchdog<'a> {
+    pub fn new(policy: &'a str, budget: ExecutionBudget) -> Self {
+        Self::with_source(policy, budget, default_cycle_source())
+    }
+
+    pub fn with_source(policy: &'a str, budget: ExecutionBudget, source: &'static dyn CycleSource) -> Self {
+        Self {
+            policy,
+            budget,
+            source,
+            steps: 0,
+            started: source.start(),
+            stack_bytes: 0,
+            call_depth: 0,
+            usage: ExecutionUsage::default(),
+            heap: AllocScope::start(),
+            tick: 0,
+        }
+    }
+
+    /// Sets the ledger tick recorded in a violation; needed whenever the violation
+    /// is appended to the ledger.
+    pub fn at_tick(mut self, tick: u64) -> Self {
+        self.tick = tick;
+        self
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Steps left before the budget is spent.
+    pub fn remaining_steps(&self) -> u64 {
+        self.budget.max_steps.saturating_sub(self.steps)
+    }
+
+    pub fn usage(&self) -> ExecutionUsage {
+        let heap = self.heap.usage();
+        ExecutionUsage { steps: self.steps, max_heap_bytes: heap.peak_bytes, allocations: heap.allocations, ..self.usage }
+    }
+
+    /// Accounts for a new VM frame of `frame_bytes` (a call, or the entry frame).
+    // The error is the ledger entry body; boxing it would put an allocation on the
+    // abort path of a run whose heap is being bounded.
+    #[allow(clippy::result_large_err)]
+    pub fn enter_frame(&mut self, frame_bytes: u64) -> Result<(), BudgetExceeded> {
+        self.stack_bytes += frame_bytes;
+        self.call_depth += 1;
+        self.usage.max_stack_bytes = self.usage.max_stack_bytes.max(self.stack_bytes);
+        self.usage.max_call_depth = self.usage.max_call_depth.max(self.call_depth);
+        if self.budget.max_stack_bytes.is_some_and(|max| self.stack_bytes > max) {
+            return Err(self.exceeded(ExceededLimit::Stack, None));
+        }
+        if self.budget.max_call_depth.is_some_and(|max| self.call_depth > max) {
+            return Err(self.exceeded(ExceededLimit::CallDepth, None));
+        }
+        Ok(())
+    }
+
+    /// Pops the frame pushed by the matching `enter_frame`.
+    pub fn exit_frame(&mut self, frame_bytes: u64) {
+        self.stack_bytes = self.stack_bytes.saturating_sub(frame_bytes);
+        self.call_depth = self.call_depth.saturating_sub(1);
+    }
+
+    /// Accounts for one VM step. Propagate the error with `?` to abort.
+    #[allow(clippy::result_large_err)]
+    pub fn step(&mut self) -> Result<(), BudgetExceeded> {
+        self.steps += 1;
+        if self.steps > self.budget.max_steps {
+            return Err(self.exceeded(ExceededLimit::Steps, None));
+        }
+        if self.budget.max_heap_bytes.is_some_and(|max| self.heap.usage().peak_bytes > max) {
+            return Err(self.exceeded(ExceededLimit::Heap, None));
+        }
+        if let Some(max_cycles) = self.budget.max_cycles {
+            if self.steps.is_multiple_of(CYCLE_CHECK_INTERVAL) {
+                let cycles = self.source.delta(self.started, self.source.stop());
+                if cycles > max_cycles {
+                    return Err(self.exceeded(ExceededLimit::Cycles, Some(cycles)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Accounts for `steps` steps at once, for runtimes that meter in bulk, such as
+    /// WASM fuel. Checks the same limits as `step`.
+    #[allow(clippy::result_large_err)]
+    pub fn charge(&mut self, steps: u64) -> Result<(), BudgetExceeded> {
+        if steps == 0 {
+            return Ok(());
+        }
+        self.steps += steps - 1;
+        self.step()
+    }
+
+    fn exceeded(&self, limit: ExceededLimit, cycles: Option<u64>) -> BudgetExceeded {
+        BudgetExceeded {
+            policy: self.policy.to_string(),
+            limit,
+            budget: self.budget,
+            steps: self.steps,
+            cycles,
+            stack_bytes: self.stack_bytes,
+            call_depth: self.call_depth,
+            heap_bytes: self.heap.usage().peak_bytes,
+            tick: self.tick,
+        }
+    }
+}
+
+/// Runs `execute` under a fresh watchdog at ledger tick `tick`. If it aborts, the
+/// violation is appended to `store` before the denial is returned; a ledger error is
+/// returned instead of the decision so the violation is never silently lost.
+pub fn guard<T, F>(store: &mut DeterministicStore, tick: u64, policy: &str, budget: ExecutionBudget, execute: F) -> LedgerResult<Decision<T>>
+where
+    F: FnOnce(&mut Watchdog<'_>) -> Result<T, BudgetExceeded>,
+{
+    let mut watchdog = Watchdog::new(policy, budget).at_tick(tick);
+    match execute(&mut watchdog) {
+        Ok(verdict) => Ok(Decision::Allow(verdict)),
+        Err(violation) => {
+            violation.append_to(store)?;
+            Ok(Decision::Deny(violation))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::entry;
+
+    #[allow(clippy::result_large_err)]
+    fn run(watchdog: &mut Watchdog<'_>, steps: u64) -> Result<u64, BudgetExceeded> {
+        for _ in 0..steps {
+            watchdog.step()?;
+        }
+        Ok(watchdog.steps())
+    }
+
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn overruns_are_cut_off_at_the_same_step_and_recorded() {
+        let mut watchdog = Watchdog::new("loop", ExecutionBudget::steps(100));
+        let violation = run(&mut watchdog, 1_000).unwrap_err();
+        assert_eq!((violation.limit, violation.steps), (ExceededLimit::Steps, 101));
+        let mut bulk = Watchdog::new("loop", ExecutionBudget::steps(100));
+        assert!(bulk.charge(100).is_ok());
+        assert_eq!((bulk.remaining_steps(), bulk.charge(1).unwrap_err().steps), (0, 101));
+
+        let dir = std::env::temp_dir().join(format!("rfsn-watchdog-guard-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut store = DeterministicStore::new(&dir).unwrap();
+        let allowed = guard(&mut store, 7, "loop", ExecutionBudget::steps(100), |w| run(w, 100)).unwrap();
+        assert_eq!((allowed, store.entry_count()), (Decision::Allow(100), 0));
+
+        let Decision::Deny(denied) = guard(&mut store, 7, "loop", ExecutionBudget::steps(100), |w| run(w, 1_000)).unwrap() else {
+            panic!("expected the overrun to be denied")
+        };
+        let mut recorded = Vec::new();
+        store
+            .for_each_entry(|_, payload| {
+                if let Some((EntryKind::BudgetViolation, body)) = entry::decode(payload) {
+                    recorded.push(serde_json::from_slice::<BudgetExceeded>(body).unwrap());
+                }
+            })
+            .unwrap();
+        assert_eq!(recorded, vec![denied.clone()]);
+        // Stamped with the tick, so replaying the overrun records the same entry.
+        let replayed = run(&mut Watchdog::new("loop", ExecutionBudget::steps(100)).at_tick(7), 1_000).unwrap_err();
+        assert_eq!((denied.tick, replayed), (7, denied));
+        drop(store);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stack_depth_is_tracked_and_recursion_is_cut_off() {
+        let mut watchdog = Watchdog::new("calls", ExecutionBudget::steps(100).with_stack(256, 3));
+        watchdog.enter_frame(64).unwrap();
+        watchdog.enter_frame(128).unwrap();
+        watchdog.exit_frame(128);
+        watchdog.enter_frame(32).unwrap();
+        watchdog.enter_frame(32).unwrap();
+        let usage = watchdog.usage();
+        assert_eq!((usage.max_stack_bytes, usage.max_call_depth), (192, 3));
+        let violation = watchdog.enter_frame(16).unwrap_err();
+        assert_eq!((violation.limit, violation.stack_bytes, violation.call_depth), (ExceededLimit::CallDepth, 144, 4));
+
+        let mut deep = Watchdog::new("calls", ExecutionBudget::steps(100).with_stack(256, 3));
+        deep.enter_frame(200).unwrap();
+        assert_eq!(deep.enter_frame(100).unwrap_err().limit, ExceededLimit::Stack);
+
+        let folded = usage.max(ExecutionUsage { steps: 7, max_stack_bytes: 8, max_call_depth: 5, ..Default::default() });
+        assert_eq!((folded.steps, folded.max_stack_bytes, folded.max_call_depth), (7, 192, 5));
+    }
+}
@@ -1,8 +1,12 @@
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use rfsn_core::gate::freeze::FreezeEvent;
+use rfsn_core::ledger::tick::{MonotonicTicks, SharedTicks};
 use serde::{Deserialize, Serialize};
 
+use super::receipt_gossip::{EvidenceBook, GossipMsg};
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PrecommitMsg {
     pub node_id: u64,
@@ -14,6 +18,15 @@ pub struct PrecommitMsg {
 pub struct OrderMsg {
     pub order_id: u64,
     pub target_hash: String,
+    /// Sequencer tick at assignment, on the cluster's shared `TickSource`.
+    #[serde(default)]
+    pub tick: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FreezeMsg {
+    pub node_id: u64,
+    pub event: FreezeEvent,
 }
 
 /// Represents the deterministic central Sequencer in the distributed RFSN cluster.
@@ -23,13 +36,25 @@ pub struct OrderMsg {
 pub struct Sequencer {
     order_id_counter: AtomicU64,
     last_known_head: Arc<Mutex<String>>,
+    evidence: Arc<Mutex<EvidenceBook>>,
+    /// Latest freeze event any node relayed.
+    freeze: Arc<Mutex<Option<FreezeEvent>>>,
+    ticks: SharedTicks,
 }
 
 impl Sequencer {
     pub fn new() -> Self {
+        Self::with_ticks(Arc::new(MonotonicTicks::millis()))
+    }
+
+    /// A sequencer stamping orders with `ticks`; pass the source the nodes' ledgers use.
+    pub fn with_ticks(ticks: SharedTicks) -> Self {
         Self {
             order_id_counter: AtomicU64::new(1),
             last_known_head: Arc::new(Mutex::new(String::new())),
+            evidence: Arc::new(Mutex::new(EvidenceBook::new())),
+            freeze: Arc::new(Mutex::new(None)),
+            ticks,
         }
     }
 
@@ -57,6 +82,39 @@ impl Sequencer {
         Ok(OrderMsg {
             order_id: assigned_id,
             target_hash: req.local_hash,
+            tick: self.ticks.now(),
         })
     }
+
+    /// Handles a receipt gossip message from a Node and returns the anchoring evidence
+    /// held for every other node, which the Node merges into its own `EvidenceBook`.
+    /// A Node whose ledger shrank below what it had anchored, or which reports a
+    /// different head for an anchored index, is rejected (triggering a freeze/sync).
+    pub async fn handle_gossip(&self, msg: GossipMsg) -> Result<Vec<GossipMsg>, String> {
+        let mut evidence = self.evidence.lock().await;
+        evidence.merge(&msg).map_err(|violation| violation.to_string())?;
+        Ok(evidence.peers_of(msg.node_id))
+    }
+
+    /// Handles a freeze or unfreeze a Node's Gate recorded, and returns the cluster's
+    /// latest freeze event, which every Node applies with `Gate::apply_freeze`.
+    /// Nodes verify unfreeze tokens themselves; the Sequencer only refuses one that
+    /// does not name the freeze in force, so a stray token cannot mask a freeze from
+    /// Nodes that have not applied it yet.
+    pub async fn handle_freeze(&self, msg: FreezeMsg) -> Result<Option<FreezeEvent>, String> {
+        let mut freeze = self.freeze.lock().await;
+        if let FreezeEvent::Unfrozen { token, .. } = &msg.event {
+            match &*freeze {
+                Some(FreezeEvent::Frozen { freeze }) if freeze.freeze_id == token.freeze_id => {}
+                _ => return Err(format!("Node {} lifted freeze {}, which is not in force", msg.node_id, token.freeze_id)),
+            }
+        }
+        *freeze = Some(msg.event);
+        Ok(freeze.clone())
+    }
+
+    /// The cluster's latest freeze event, for Nodes polling between relays.
+    pub async fn cluster_freeze(&self) -> Option<FreezeEvent> {
+        self.freeze.lock().await.clone()
+    }
 }
@@ -0,0 +1,190 @@
+//! Inter-node gossip of anchoring evidence over the sequencer transport.
+//!
+//! Each node periodically sends the sequencer its ledger size and its recorded
+//! anchors, and gets back everything the cluster holds for the other nodes. Every
+//! node therefore keeps every peer's receipts. A node whose ledger was rolled back
+//! (or whose receipts were deleted to hide that) is caught: its peers still hold
+//! signed anchors beyond its current ledger size, and those do not go away.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use rfsn_core::ledger::entry::ReceiptEntry;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GossipMsg {
+    pub node_id: u64,
+    /// Entries in the sender's ledger when the message was built.
+    pub ledger_size: u64,
+    /// Anchors the node holds evidence for. May be a suffix; evidence is only ever added.
+    pub anchors: Vec<ReceiptEntry>,
+}
+
+/// Why a node's gossip contradicts what the cluster already holds for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GossipViolation {
+    /// The node reports fewer entries than it had already anchored or announced.
+    RolledBack { node_id: u64, ledger_size: u64, known_size: u64 },
+    /// The node reports a different head for an index it had already anchored.
+    Equivocated { node_id: u64, index: u64, known_head: String, reported_head: String },
+}
+
+impl fmt::Display for GossipViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GossipViolation::RolledBack { node_id, ledger_size, known_size } => write!(
+                f,
+                "LEDGER ROLLBACK DETECTED. Node {} reports {} entries but peers hold evidence for {}",
+                node_id, ledger_size, known_size
+            ),
+            GossipViolation::Equivocated { node_id, index, known_head, reported_head } => write!(
+                f,
+                "ANCHOR EQUIVOCATION DETECTED. Node {} index {}: peers hold {} | node reports {}",
+                node_id, index, known_head, reported_head
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GossipViolation {}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+struct NodeEvidence {
+    /// Largest ledger size the node has ever announced or anchored.
+    known_size: u64,
+    anchors: BTreeMap<u64, ReceiptEntry>,
+}
+
+/// Anchoring evidence held for every node of the cluster. The sequencer keeps one to
+/// check incoming gossip; each node keeps one with its peers' evidence.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct EvidenceBook {
+    nodes: HashMap<u64, NodeEvidence>,
+}
+
+impl EvidenceBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks `msg` against the evidence held for its sender, then merges it. A
+    /// violating message is not merged, so the evidence against the node is kept.
+    pub fn merge(&mut self, msg: &GossipMsg) -> Result<(), GossipViolation> {
+        self.check(msg)?;
+        let known = self.nodes.entry(msg.node_id).or_default();
+        known.known_size = known.known_size.max(msg.ledger_size);
+        for anchor in &msg.anchors {
+            known.known_size = known.known_size.max(anchor.index);
+            known.anchors.entry(anchor.index).or_insert_with(|| anchor.clone());
+        }
+        Ok(())
+    }
+
+    pub fn check(&self, msg: &GossipMsg) -> Result<(), GossipViolation> {
+        let Some(known) = self.nodes.get(&msg.node_id) else { return Ok(()) };
+        if msg.ledger_size < known.known_size {
+            return Err(GossipViolation::RolledBack {
+                node_id: msg.node_id,
+                ledger_size: msg.ledger_size,
+                known_size: known.known_size,
+            });
+        }
+        for anchor in &msg.anchors {
+            if let Some(held) = known.anchors.get(&anchor.index) {
+                if held.ledger_head_hash != anchor.ledger_head_hash {
+                    return Err(GossipViolation::Equivocated {
+                        node_id: msg.node_id,
+                        index: anchor.index,
+                        known_head: held.ledger_head_hash.clone(),
+                        reported_head: anchor.ledger_head_hash.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Everything held for nodes other than `node_id`, one message per node.
+    pub fn peers_of(&self, node_id: u64) -> Vec<GossipMsg> {
+        let mut peers: Vec<GossipMsg> = self
+            .nodes
+            .iter()
+            .filter(|(id, _)| **id != node_id)
+            .map(|(id, known)| GossipMsg {
+                node_id: *id,
+                ledger_size: known.known_size,
+                anchors: known.anchors.values().cloned().collect(),
+            })
+            .collect();
+        peers.sort_by_key(|m| m.node_id);
+        peers
+    }
+
+    /// Latest anchor held for `node_id`.
+    pub fn latest_anchor(&self, node_id: u64) -> Option<&ReceiptEntry> {
+        self.nodes.get(&node_id)?.anchors.values().next_back()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        match fs::read_to_string(path) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the book atomically (tmp + rename), so a crash never loses peer evidence.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anchor(index: u64, head: &str) -> ReceiptEntry {
+        ReceiptEntry { index, ledger_head_hash: head.to_string(), threshold: 1, receipts: Vec::new() }
+    }
+
+    fn gossip(node_id: u64, ledger_size: u64, anchors: Vec<ReceiptEntry>) -> GossipMsg {
+        GossipMsg { node_id, ledger_size, anchors }
+    }
+
+    #[test]
+    fn peers_keep_evidence_that_catches_rollbacks_and_equivocation() {
+        let mut book = EvidenceBook::new();
+        book.merge(&gossip(1, 10, vec![anchor(8, "aa")])).unwrap();
+        book.merge(&gossip(2, 3, Vec::new())).unwrap();
+        // Evidence is only added: a suffix without the old anchor keeps it.
+        book.merge(&gossip(1, 12, vec![anchor(12, "bb")])).unwrap();
+        assert_eq!(book.latest_anchor(1).map(|a| a.index), Some(12));
+
+        let peers = book.peers_of(2);
+        assert_eq!(peers.len(), 1);
+        assert_eq!((peers[0].node_id, peers[0].ledger_size, peers[0].anchors.len()), (1, 12, 2));
+
+        assert_eq!(
+            book.merge(&gossip(1, 9, Vec::new())),
+            Err(GossipViolation::RolledBack { node_id: 1, ledger_size: 9, known_size: 12 })
+        );
+        assert!(matches!(
+            book.merge(&gossip(1, 12, vec![anchor(8, "cc")])),
+            Err(GossipViolation::Equivocated { index: 8, .. })
+        ));
+        assert_eq!(book.latest_anchor(1).unwrap().ledger_head_hash, "bb");
+
+        let path = std::env::temp_dir().join(format!("rfsn-gossip-book-{}.json", std::process::id()));
+        book.save(&path).unwrap();
+        let reloaded = EvidenceBook::load(&path).unwrap();
+        assert!(reloaded.check(&gossip(1, 11, Vec::new())).is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(EvidenceBook::load(&path).unwrap().peers_of(0).is_empty());
+    }
+}
@@ -1,67 +1,758 @@
 //! Predictive Learning Loop Architecture
 //! 
-//! This module represents the L0-L4 Hierarchy where predictive coding anomalies 
-//! generate ActionProposals for the Gate. 
+//! This module implements the L0-L4 Hierarchy where predictive coding anomalies 
+//! generate ActionProposals for the Gate. L0 is the observation; L1 to L4 each
+//! predict the level below (`HierarchicalModel`), with their own dimension and
+//! learning rate, and pass their residual errors upward. Each channel of the
+//! observation has its own alarm (`Threshold`), which adapts to the residuals the
+//! channel normally shows.
+//!
+//! `PredictiveLearningLoop::to_bytes` encodes the loop's whole inference state
+//! canonically, one state having exactly one encoding, and `restore` decodes it into
+//! a loop that steps exactly as the original would. The loop offers a checkpoint
+//! every `with_checkpoints` steps, and one of its state before the step whenever it
+//! raises an anomaly, so replaying the observation on that checkpoint raises it
+//! again; the host records each in the ledger (`Gate::record_model_checkpoint`)
+//! before the anomaly, and restores from the latest after a restart.
+//!
+//! Layout, integers and f64 bits little-endian: `RFSN-PL\0`, the format version
+//! byte, steps and anomalies (u64), the channel count (u32) and each channel's name
+//! length (u32), UTF-8 name and width (u32); each channel's threshold: alpha, raise,
+//! clear (f64), debounce (u32), floor (f64), warmup (u64), mean, deviation (f64),
+//! steps (u64), steps above (u32) and raised (u8); then the level count (u32) and
+//! each level, L1 first: its dimension and the dimension below (u32), learning rate
+//! (f64), state, weights row by row, and residual (f64 each).
 //! CRITICAL: This module **cannot** execute tools or actuate the system; 
 //! it can only submit a formal RfsnActionProposal for VM & Policy evaluating.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 
-// Placeholder mathematical model (State vector -> State prediction)
+// Proposals are defined alongside the Gate that admits them.
+pub use rfsn_core::gate::proposal::ProposedAction;
+// The loop proposes as this principal, holding only read-only attenuations.
+pub use rfsn_core::gate::subsystem::PREDICTIVE_LOOP as PRINCIPAL;
+use rfsn_core::gate::anomaly::anomaly_id;
+
+const MAGIC: &[u8; 8] = b"RFSN-PL\0";
+pub const CHECKPOINT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckpointError {
+    Malformed(&'static str),
+    UnsupportedVersion(u8),
+    /// The channels and levels decoded do not make a model.
+    Hierarchy(ConfigError),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Malformed(what) => write!(f, "malformed model checkpoint: {}", what),
+            CheckpointError::UnsupportedVersion(v) => write!(f, "unsupported model checkpoint version {}", v),
+            CheckpointError::Hierarchy(e) => write!(f, "model checkpoint: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+/// Why a `HierarchyConfig` cannot be built into a model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    /// Only L0: nothing would predict the observation.
+    NoLevels,
+    NoChannels,
+    /// A channel or level of no width.
+    ZeroWidth,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NoLevels => write!(f, "the hierarchy has no levels above L0"),
+            ConfigError::NoChannels => write!(f, "the hierarchy has no channels"),
+            ConfigError::ZeroWidth => write!(f, "a channel or level of the hierarchy has width 0"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Reads a checkpoint front to back.
+struct Reader<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], CheckpointError> {
+        let (head, tail) = self.rest.split_at_checked(n).ok_or(CheckpointError::Malformed("truncated"))?;
+        self.rest = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, CheckpointError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, CheckpointError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("4 bytes")))
+    }
+
+    fn u64(&mut self) -> Result<u64, CheckpointError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().expect("8 bytes")))
+    }
+
+    fn f64(&mut self) -> Result<f64, CheckpointError> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    /// `n` f64s, refusing a count the rest could not hold before allocating.
+    fn f64s(&mut self, n: usize) -> Result<Vec<f64>, CheckpointError> {
+        let bytes = self.take(n.checked_mul(8).ok_or(CheckpointError::Malformed("truncated"))?)?;
+        Ok(bytes.chunks_exact(8).map(|b| f64::from_bits(u64::from_le_bytes(b.try_into().expect("8 bytes")))).collect())
+    }
+}
+
+fn put_f64s(out: &mut Vec<u8>, values: &[f64]) {
+    values.iter().for_each(|v| out.extend_from_slice(&v.to_bits().to_le_bytes()));
+}
+
+/// The channel of a scalar observation; see `Observation::scalar`.
+pub const VALUE: &str = "value";
+
+/// One step's readings: a vector per named channel, e.g. a joint's angle, velocity
+/// and torque under `joint2`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Observation {
+    pub channels: BTreeMap<String, Vec<f64>>,
+}
+
+impl Observation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn scalar(value: f64) -> Self {
+        Self::new().channel(VALUE, &[value])
+    }
+
+    pub fn channel(mut self, name: &str, values: &[f64]) -> Self {
+        self.channels.insert(name.to_string(), values.to_vec());
+        self
+    }
+}
+
+/// What the model predicted of one channel, and how far off it was.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelError {
+    pub channel: String,
+    pub prediction: Vec<f64>,
+    /// The reading minus the prediction.
+    pub residual: Vec<f64>,
+}
+
+impl ChannelError {
+    pub fn norm(&self) -> f64 {
+        self.residual.iter().map(|e| e * e).sum::<f64>().sqrt()
+    }
+}
+
+/// How a channel's alarm adapts to the residuals it normally sees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdConfig {
+    /// Weight of each new residual in the channel's running mean and deviation.
+    pub alpha: f64,
+    /// Deviations above the mean at which the channel raises...
+    pub raise: f64,
+    /// ...and below which a raised channel clears.
+    pub clear: f64,
+    /// Consecutive steps above the raise threshold it takes to raise.
+    pub debounce: u32,
+    /// The least residual that raises, however quiet the channel.
+    pub floor: f64,
+    /// Steps the channel only learns its statistics, while the model settles.
+    pub warmup: u64,
+}
+
+impl Default for ThresholdConfig {
+    fn default() -> Self {
+        Self { alpha: 0.05, raise: 4.0, clear: 2.0, debounce: 2, floor: 1.0, warmup: 20 }
+    }
+}
+
+/// A channel's alarm: an EWMA of its residual norm and of their absolute deviation
+/// from it, with hysteresis between raising and clearing. The statistics stand still
+/// while the residual is above the raise threshold or the alarm is raised, so an
+/// anomaly does not become the new normal, but slow drift moves them along.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    pub config: ThresholdConfig,
+    pub mean: f64,
+    pub deviation: f64,
+    pub steps: u64,
+    /// Consecutive steps above the raise threshold.
+    pub above: u32,
+    pub raised: bool,
+}
+
+impl Threshold {
+    pub fn new(config: ThresholdConfig) -> Self {
+        Self { config, mean: 0.0, deviation: 0.0, steps: 0, above: 0, raised: false }
+    }
+
+    pub fn raise_at(&self) -> f64 {
+        (self.mean + self.config.raise * self.deviation).max(self.config.floor)
+    }
+
+    pub fn clear_at(&self) -> f64 {
+        (self.mean + self.config.clear * self.deviation).min(self.raise_at())
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        let config = &self.config;
+        put_f64s(out, &[config.alpha, config.raise, config.clear]);
+        out.extend_from_slice(&config.debounce.to_le_bytes());
+        put_f64s(out, &[config.floor]);
+        out.extend_from_slice(&config.warmup.to_le_bytes());
+        put_f64s(out, &[self.mean, self.deviation]);
+        out.extend_from_slice(&self.steps.to_le_bytes());
+        out.extend_from_slice(&self.above.to_le_bytes());
+        out.push(self.raised as u8);
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, CheckpointError> {
+        let (alpha, raise, clear) = (reader.f64()?, reader.f64()?, reader.f64()?);
+        let debounce = reader.u32()?;
+        let floor = reader.f64()?;
+        let warmup = reader.u64()?;
+        let config = ThresholdConfig { alpha, raise, clear, debounce, floor, warmup };
+        let (mean, deviation, steps, above) = (reader.f64()?, reader.f64()?, reader.u64()?, reader.u32()?);
+        let raised = match reader.u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(CheckpointError::Malformed("raised is not a boolean")),
+        };
+        Ok(Self { config, mean, deviation, steps, above, raised })
+    }
+
+    /// Feeds one step's residual norm; true if the channel raised at this step.
+    pub fn observe(&mut self, norm: f64) -> bool {
+        self.steps += 1;
+        if self.raised {
+            if norm < self.clear_at() {
+                self.raised = false;
+                self.above = 0;
+            }
+            return false;
+        }
+        let over = self.steps > self.config.warmup && norm > self.raise_at();
+        self.above = if over { self.above + 1 } else { 0 };
+        if self.above >= self.config.debounce.max(1) {
+            self.raised = true;
+            return true;
+        }
+        if !over {
+            let alpha = if self.steps == 1 { 1.0 } else { self.config.alpha };
+            self.deviation += alpha * ((norm - self.mean).abs() - self.deviation);
+            self.mean += alpha * (norm - self.mean);
+        }
+        false
+    }
+}
+
+/// Dimension and learning rate of one level above L0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelConfig {
+    pub dim: usize,
+    pub learning_rate: f64,
+}
+
+/// Shape of the hierarchy: L0 is the observation itself, its channels laid end to
+/// end in order, and each further level, L1 first, predicts the one below it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HierarchyConfig {
+    /// Name and width of each channel.
+    pub channels: Vec<(String, usize)>,
+    pub levels: Vec<LevelConfig>,
+}
+
+impl HierarchyConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn channel(mut self, name: &str, width: usize) -> Self {
+        self.channels.push((name.to_string(), width));
+        self
+    }
+
+    pub fn input_dim(&self) -> usize {
+        self.channels.iter().map(|(_, width)| width).sum()
+    }
+
+    /// Stacks a level on top.
+    pub fn level(mut self, dim: usize, learning_rate: f64) -> Self {
+        self.levels.push(LevelConfig { dim, learning_rate });
+        self
+    }
+
+    /// Checks that the config describes a model: at least one channel and one level,
+    /// none of them empty.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.channels.is_empty() {
+            return Err(ConfigError::NoChannels);
+        }
+        if self.levels.is_empty() {
+            return Err(ConfigError::NoLevels);
+        }
+        if self.channels.iter().any(|&(_, width)| width == 0) || self.levels.iter().any(|level| level.dim == 0) {
+            return Err(ConfigError::ZeroWidth);
+        }
+        Ok(())
+    }
+}
+
+impl HierarchyConfig {
+    /// L0 to L4 over `channels`, narrower and slower toward the top.
+    pub fn standard(channels: &[(&str, usize)]) -> Self {
+        let config = channels.iter().fold(Self::new(), |config, &(name, width)| config.channel(name, width));
+        config.level(64, 0.05).level(32, 0.02).level(16, 0.01).level(8, 0.005)
+    }
+}
+
+/// One level of the hierarchy above L0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub state: Vec<f64>,
+    /// Maps `state` to a prediction of the level below: a row per dimension below.
+    pub weights: Vec<Vec<f64>>,
+    pub learning_rate: f64,
+    /// The level below minus this level's prediction of it, at the last step.
+    pub residual: Vec<f64>,
+}
+
+impl Level {
+    /// A level at rest over one of `below` dimensions. Weights start with orthonormal
+    /// columns, each spreading one state dimension over every `dim`th row below, so
+    /// a fresh model is deterministic and its residuals already reach every state.
+    fn new(below: usize, config: LevelConfig) -> Self {
+        let dim = config.dim;
+        let scale = (dim as f64 / below.max(dim) as f64).sqrt();
+        let weights = (0..below).map(|row| (0..dim).map(|col| if row % dim == col { scale } else { 0.0 }).collect()).collect();
+        Self { state: vec![0.0; dim], weights, learning_rate: config.learning_rate, residual: vec![0.0; below] }
+    }
+
+    /// This level's prediction of the level below.
+    pub fn predict(&self) -> Vec<f64> {
+        self.weights.iter().map(|row| row.iter().zip(&self.state).map(|(w, s)| w * s).sum()).collect()
+    }
+
+    pub fn residual_norm(&self) -> f64 {
+        self.residual.iter().map(|e| e * e).sum::<f64>().sqrt()
+    }
+}
+
+/// Stacked predictive-coding levels, after Rao and Ballard. Every step each level
+/// predicts the one below, the residuals travel up, and each level moves its state
+/// to explain the residual below it while staying close to what the level above
+/// predicted of it, then learns its weights from its own residual.
+#[derive(Debug, Clone, PartialEq)]
 pub struct HierarchicalModel {
-    pub internal_state: Vec<f64>,
+    /// L1 first.
+    pub levels: Vec<Level>,
 }
 
 impl HierarchicalModel {
-    pub fn new(dim: usize) -> Self {
-        Self { internal_state: vec![0.0; dim] }
+    pub fn new(config: &HierarchyConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+        let mut below = config.input_dim();
+        let levels = config
+            .levels
+            .iter()
+            .map(|&level| {
+                let level = Level::new(below, level);
+                below = level.state.len();
+                level
+            })
+            .collect();
+        Ok(Self { levels })
+    }
+
+    /// The model's prediction of the next observation.
+    pub fn predict(&self) -> Vec<f64> {
+        self.levels[0].predict()
     }
-    
-    // Simulate updating world weights based on anomaly
-    pub fn adapt(&mut self, error: f64) {
-        for w in &mut self.internal_state {
-            *w += error * 0.01;
+
+    /// Learns from `observation` and returns its residual: the observation minus what
+    /// the model predicted of it.
+    pub fn step(&mut self, observation: &[f64]) -> Vec<f64> {
+        let mut residuals: Vec<Vec<f64>> = Vec::with_capacity(self.levels.len());
+        for (i, level) in self.levels.iter().enumerate() {
+            let below = if i == 0 { observation } else { &self.levels[i - 1].state };
+            residuals.push(below.iter().zip(level.predict()).map(|(x, p)| x - p).collect());
+        }
+        for (i, level) in self.levels.iter_mut().enumerate() {
+            let residual = &residuals[i];
+            let mut drive: Vec<f64> = (0..level.state.len()).map(|col| level.weights.iter().zip(residual).map(|(row, e)| row[col] * e).sum()).collect();
+            // The level above's residual is this state minus its prediction of it.
+            if let Some(above) = residuals.get(i + 1) {
+                drive.iter_mut().zip(above).for_each(|(d, e)| *d -= e);
+            }
+            // Weights learn at a rate normalized by the state's energy, as in NLMS, so
+            // large readings cannot run the state and weights away together.
+            let rate = level.learning_rate;
+            let weight_rate = rate / (1.0 + level.state.iter().map(|s| s * s).sum::<f64>());
+            for (row, e) in level.weights.iter_mut().zip(residual) {
+                row.iter_mut().zip(&level.state).for_each(|(w, s)| *w += weight_rate * e * s);
+            }
+            level.state.iter_mut().zip(drive).for_each(|(s, d)| *s += rate * d);
+            level.residual.clone_from(residual);
         }
+        residuals.into_iter().next().expect("validated models have a level")
+    }
+
+    /// The residual norm of each level, L1 first.
+    pub fn residual_norms(&self) -> Vec<f64> {
+        self.levels.iter().map(Level::residual_norm).collect()
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.levels.len() as u32).to_le_bytes());
+        for level in &self.levels {
+            out.extend_from_slice(&(level.state.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(level.residual.len() as u32).to_le_bytes());
+            put_f64s(out, &[level.learning_rate]);
+            put_f64s(out, &level.state);
+            level.weights.iter().for_each(|row| put_f64s(out, row));
+            put_f64s(out, &level.residual);
+        }
+    }
+
+    /// Decodes a model over `input_dim` dimensions, refusing levels that do not
+    /// stack.
+    fn decode(reader: &mut Reader, input_dim: usize) -> Result<Self, CheckpointError> {
+        let count = reader.u32()?;
+        let mut expected_below = input_dim;
+        let mut levels = Vec::new();
+        for _ in 0..count {
+            let (dim, below) = (reader.u32()? as usize, reader.u32()? as usize);
+            if below != expected_below || dim == 0 {
+                return Err(CheckpointError::Malformed("levels do not stack"));
+            }
+            let learning_rate = reader.f64()?;
+            let state = reader.f64s(dim)?;
+            let weights = (0..below).map(|_| reader.f64s(dim)).collect::<Result<_, _>>()?;
+            let residual = reader.f64s(below)?;
+            levels.push(Level { state, weights, learning_rate, residual });
+            expected_below = dim;
+        }
+        Ok(Self { levels })
     }
 }
 
 pub struct PredictiveLearningLoop {
     pub model: HierarchicalModel,
+    /// Name and width of each channel, in the model's input order.
+    pub channels: Vec<(String, usize)>,
+    /// Each channel's error at the last step.
+    pub errors: Vec<ChannelError>,
+    /// Each channel's alarm.
+    pub thresholds: Vec<Threshold>,
+    /// Anomalies emitted so far; the next one's sequence number.
+    pub anomalies: u64,
+    pub steps: u64,
+    /// Steps between periodic checkpoints.
+    pub checkpoint_every: Option<u64>,
+    /// The checkpoint the host has yet to take.
+    checkpoint: Option<Vec<u8>>,
 }
 
 impl PredictiveLearningLoop {
+    /// A loop over scalar observations.
     pub fn new() -> Self {
-        Self { model: HierarchicalModel::new(64) }
+        Self::with_hierarchy(&HierarchyConfig::standard(&[(VALUE, 1)])).expect("the standard hierarchy is valid")
+    }
+
+    pub fn with_hierarchy(config: &HierarchyConfig) -> Result<Self, ConfigError> {
+        let thresholds = vec![Threshold::new(ThresholdConfig::default()); config.channels.len()];
+        Ok(Self {
+            model: HierarchicalModel::new(config)?,
+            channels: config.channels.clone(),
+            errors: Vec::new(),
+            thresholds,
+            anomalies: 0,
+            steps: 0,
+            checkpoint_every: None,
+            checkpoint: None,
+        })
+    }
+
+    /// Offers a checkpoint every `steps` steps.
+    pub fn with_checkpoints(mut self, steps: u64) -> Self {
+        self.checkpoint_every = Some(steps.max(1));
+        self
+    }
+
+    /// The checkpoint due, if any: the state before a step that raised an anomaly, or
+    /// else a periodic one. The host records it before the anomaly.
+    pub fn take_checkpoint(&mut self) -> Option<Vec<u8>> {
+        self.checkpoint.take()
+    }
+
+    /// The loop's inference state; see the module docs for the layout. The errors of
+    /// the last step and the checkpoint settings are not part of it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(CHECKPOINT_VERSION);
+        out.extend_from_slice(&self.steps.to_le_bytes());
+        out.extend_from_slice(&self.anomalies.to_le_bytes());
+        out.extend_from_slice(&(self.channels.len() as u32).to_le_bytes());
+        for (name, width) in &self.channels {
+            out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&(*width as u32).to_le_bytes());
+        }
+        self.thresholds.iter().for_each(|threshold| threshold.encode(&mut out));
+        self.model.encode(&mut out);
+        out
+    }
+
+    /// Decodes what `to_bytes` encodes, and only that: trailing bytes, a threshold
+    /// that is not a boolean, levels that do not stack on the channels and a hierarchy
+    /// `HierarchyConfig::validate` refuses are refused.
+    pub fn restore(bytes: &[u8]) -> Result<Self, CheckpointError> {
+        let rest = bytes.strip_prefix(MAGIC.as_slice()).ok_or(CheckpointError::Malformed("bad magic"))?;
+        let mut reader = Reader { rest };
+        let version = reader.u8()?;
+        if version != CHECKPOINT_VERSION {
+            return Err(CheckpointError::UnsupportedVersion(version));
+        }
+        let (steps, anomalies) = (reader.u64()?, reader.u64()?);
+        let count = reader.u32()?;
+        let mut channels = Vec::new();
+        for _ in 0..count {
+            let len = reader.u32()? as usize;
+            let name = std::str::from_utf8(reader.take(len)?).map_err(|_| CheckpointError::Malformed("channel name is not UTF-8"))?;
+            channels.push((name.to_string(), reader.u32()? as usize));
+        }
+        let thresholds = channels.iter().map(|_| Threshold::decode(&mut reader)).collect::<Result<_, _>>()?;
+        let model = HierarchicalModel::decode(&mut reader, channels.iter().map(|(_, width)| width).sum())?;
+        if !reader.rest.is_empty() {
+            return Err(CheckpointError::Malformed("trailing bytes"));
+        }
+        let levels = model.levels.iter().map(|level| LevelConfig { dim: level.state.len(), learning_rate: level.learning_rate }).collect();
+        HierarchyConfig { channels: channels.clone(), levels }.validate().map_err(CheckpointError::Hierarchy)?;
+        Ok(Self { model, channels, errors: Vec::new(), thresholds, anomalies, steps, checkpoint_every: None, checkpoint: None })
+    }
+
+    /// Alarms every channel under `config`.
+    pub fn with_thresholds(mut self, config: ThresholdConfig) -> Self {
+        self.thresholds.iter_mut().for_each(|threshold| *threshold = Threshold::new(config));
+        self
+    }
+
+    /// Alarms `channel` under `config`.
+    pub fn with_channel_threshold(mut self, channel: &str, config: ThresholdConfig) -> Self {
+        if let Some(at) = self.channels.iter().position(|(name, _)| name == channel) {
+            self.thresholds[at] = Threshold::new(config);
+        }
+        self
+    }
+
+    /// Lays `observation` out as the model's input. A channel the observation lacks
+    /// reads as predicted, so it neither errs nor teaches the model anything, and a
+    /// channel the loop was not configured with is ignored. Readings are cut or
+    /// padded with the prediction to their channel's width.
+    fn input(&self, observation: &Observation, prediction: &[f64]) -> Vec<f64> {
+        let mut input = prediction.to_vec();
+        let mut offset = 0;
+        for (name, width) in &self.channels {
+            if let Some(values) = observation.channels.get(name) {
+                input[offset..offset + width].iter_mut().zip(values).for_each(|(x, v)| *x = *v);
+            }
+            offset += width;
+        }
+        input
     }
 
     /// Primary Cognitive Loop: Predict -> Observe -> Error -> Propose
-    pub fn step(&mut self, observation: f64) -> Option<ProposedAction> {
-        let prediction = self.model.internal_state[0]; // Simplified prediction access
-        let error = observation - prediction;
-        
-        self.model.adapt(error);
+    pub fn step(&mut self, observation: &Observation) -> Option<ProposedAction> {
+        let prediction = self.model.predict();
+        let input = self.input(observation, &prediction);
+        // The residual the model learns from is its prediction's error, known before it
+        // learns, and with it whether a channel raises. The state before the step is
+        // only encoded when one does.
+        let mut offset = 0;
+        let errors: Vec<ChannelError> = self
+            .channels
+            .iter()
+            .map(|(name, width)| {
+                let span = offset..offset + width;
+                offset += width;
+                let residual = input[span.clone()].iter().zip(&prediction[span.clone()]).map(|(x, p)| x - p).collect();
+                ChannelError { channel: name.clone(), prediction: prediction[span].to_vec(), residual }
+            })
+            .collect();
 
         // Substantial deviation -> Auto-Propose an Investigation Action
         // e.g., if a robotics joint unexpectedly jams, or network traffic spikes
-        if error.abs() > 5.0 {
-            println!("[Predictive Loop] High epsilon anomaly ({:.2}). Emitting proposal.", error);
-            
-            return Some(ProposedAction {
-                tool_name: "sys_diagnostic".to_string(),
-                capability_required: "sys:read".to_string(),
-                risk_hint: "high".to_string(), // Informs VM to apply tighter bounds
-                args: HashMap::new(),
-            });
-        }
-        
-        None
-    }
-}
-
-/// Mapped representation of the TypeScript RfsnActionProposal.
-pub struct ProposedAction {
-    pub tool_name: String,
-    pub capability_required: String,
-    pub risk_hint: String,
-    pub args: HashMap<String, String>,
+        // Once per raise: a channel proposes again only after it has cleared.
+        let mut thresholds = self.thresholds.clone();
+        let mut worst: Option<(usize, f64)> = None;
+        for (at, (error, threshold)) in errors.iter().zip(&mut thresholds).enumerate() {
+            let raise_at = threshold.raise_at();
+            if threshold.observe(error.norm()) {
+                let excess = error.norm() / raise_at;
+                if worst.is_none_or(|(_, most)| excess > most) {
+                    worst = Some((at, excess));
+                }
+            }
+        }
+        if worst.is_some() {
+            self.checkpoint = Some(self.to_bytes());
+        }
+
+        self.steps += 1;
+        self.model.step(&input);
+        self.thresholds = thresholds;
+        self.errors = errors;
+        let Some((worst, _)) = worst else {
+            if self.checkpoint_every.is_some_and(|every| self.steps.is_multiple_of(every)) {
+                self.checkpoint = Some(self.to_bytes());
+            }
+            return None;
+        };
+
+        let worst = &self.errors[worst];
+        // The host records the anomaly under this id (`Gate::record_anomaly`) before submitting.
+        let anomaly = anomaly_id(PRINCIPAL, self.anomalies);
+        self.anomalies += 1;
+        println!("[Predictive Loop] High epsilon anomaly {} on {} ({:.2}). Emitting proposal.", anomaly, worst.channel, worst.norm());
+
+        Some(ProposedAction {
+            tool_name: "sys_diagnostic".to_string(),
+            capability_required: "sys:read".to_string(),
+            risk_hint: "high".to_string(), // Informs VM to apply tighter bounds
+            // The channel furthest past its threshold, for the diagnostic to start from.
+            args: HashMap::from([("channel".to_string(), worst.channel.clone())]),
+            tenant: None,
+            nonce: None,
+            anomaly: Some(anomaly),
+        })
+    }
+}
+
+impl Default for PredictiveLearningLoop {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_learn_a_steady_signal_and_flag_a_change() {
+        let mut model = HierarchicalModel::new(&HierarchyConfig::standard(&[(VALUE, 1)])).unwrap();
+        assert_eq!(model.levels.iter().map(|level| level.state.len()).collect::<Vec<_>>(), [64, 32, 16, 8]);
+        let first = model.step(&[10.0])[0];
+        for _ in 0..200 {
+            model.step(&[10.0]);
+        }
+        assert!(model.step(&[10.0])[0].abs() < first.abs() * 0.1);
+        // The residuals reached the top level.
+        assert!(model.levels[3].state.iter().any(|&s| s != 0.0));
+        assert!(model.step(&[30.0])[0] > 15.0);
+    }
+
+    #[test]
+    fn hierarchies_without_levels_or_width_are_refused() {
+        let channel = HierarchyConfig::new().channel(VALUE, 1);
+        assert_eq!(HierarchicalModel::new(&channel).err(), Some(ConfigError::NoLevels));
+        assert_eq!(HierarchicalModel::new(&HierarchyConfig::new().level(8, 0.01)).err(), Some(ConfigError::NoChannels));
+        assert_eq!(HierarchicalModel::new(&channel.clone().level(0, 0.01)).err(), Some(ConfigError::ZeroWidth));
+        assert_eq!(HierarchicalModel::new(&HierarchyConfig::new().channel(VALUE, 0).level(8, 0.01)).err(), Some(ConfigError::ZeroWidth));
+        assert!(PredictiveLearningLoop::with_hierarchy(&channel.level(8, 0.01)).is_ok());
+    }
+
+    #[test]
+    fn proposals_name_the_channel_that_deviated() {
+        let mut predictive = PredictiveLearningLoop::with_hierarchy(&HierarchyConfig::standard(&[("joint2", 3), ("network", 1)])).unwrap();
+        let steady = Observation::new().channel("joint2", &[1.0, 0.0, 2.0]).channel("network", &[4.0]);
+        for _ in 0..200 {
+            predictive.step(&steady);
+        }
+        assert!(predictive.step(&steady).is_none());
+        assert_eq!(predictive.errors.iter().map(|e| e.channel.as_str()).collect::<Vec<_>>(), ["joint2", "network"]);
+        assert!((predictive.errors[0].prediction[2] - 2.0).abs() < 0.5);
+
+        let jammed = Observation::new().channel("joint2", &[1.0, 0.0, 20.0]).channel("network", &[4.0]);
+        assert!(predictive.step(&jammed).is_none());
+        let proposal = predictive.step(&jammed).unwrap();
+        assert_eq!(proposal.args.get("channel").map(String::as_str), Some("joint2"));
+        assert!(predictive.errors[0].norm() > predictive.errors[1].norm());
+        // A missing channel reads as predicted.
+        predictive.step(&Observation::new().channel("joint2", &[1.0, 0.0, 2.0]));
+        assert_eq!(predictive.errors[1].norm(), 0.0);
+    }
+
+    #[test]
+    fn checkpoints_restore_the_loop_exactly() {
+        let mut predictive = PredictiveLearningLoop::with_hierarchy(&HierarchyConfig::standard(&[("joint2", 3)])).unwrap().with_checkpoints(50);
+        let steady = Observation::new().channel("joint2", &[1.0, 0.0, 2.0]);
+        let mut periodic = Vec::new();
+        for _ in 0..120 {
+            predictive.step(&steady);
+            periodic.extend(predictive.take_checkpoint());
+        }
+        assert_eq!(periodic.len(), 2);
+        let mut restored = PredictiveLearningLoop::restore(&periodic[1]).unwrap();
+        assert_eq!((restored.steps, restored.to_bytes()), (100, periodic[1].clone()));
+        for _ in 100..120 {
+            restored.step(&steady);
+        }
+        assert_eq!(restored.to_bytes(), predictive.to_bytes());
+
+        // The checkpoint of a raise is the model that raised it.
+        let jammed = Observation::new().channel("joint2", &[1.0, 0.0, 20.0]);
+        assert!(predictive.step(&jammed).is_none());
+        let proposal = predictive.step(&jammed).unwrap();
+        let raising = predictive.take_checkpoint().unwrap();
+        assert_eq!(PredictiveLearningLoop::restore(&raising).unwrap().step(&jammed), Some(proposal));
+        assert_eq!(PredictiveLearningLoop::restore(&raising[..raising.len() - 1]).err(), Some(CheckpointError::Malformed("truncated")));
+        assert_eq!(PredictiveLearningLoop::restore(&[raising.as_slice(), &[0]].concat()).err(), Some(CheckpointError::Malformed("trailing bytes")));
+
+        // A checkpoint of a model without levels, or without channels, steps nowhere.
+        let mut levelless = PredictiveLearningLoop::restore(&raising).unwrap();
+        levelless.model.levels.clear();
+        assert_eq!(PredictiveLearningLoop::restore(&levelless.to_bytes()).err(), Some(CheckpointError::Hierarchy(ConfigError::NoLevels)));
+        let empty = PredictiveLearningLoop { channels: Vec::new(), thresholds: Vec::new(), ..PredictiveLearningLoop::restore(&raising).unwrap() };
+        assert!(PredictiveLearningLoop::restore(&empty.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn thresholds_adapt_to_noise_and_hold_through_an_anomaly() {
+        let mut predictive = PredictiveLearningLoop::with_hierarchy(&HierarchyConfig::standard(&[("quiet", 1), ("noisy", 1)])).unwrap();
+        let mut proposals = Vec::new();
+        // Noise of a few units on one channel and slow drift on the other.
+        for step in 0..400 {
+            let noise = [3.0, -3.0, 2.0, -2.0][step % 4];
+            let observation = Observation::new().channel("quiet", &[10.0 + step as f64 * 0.005]).channel("noisy", &[50.0 + noise]);
+            proposals.extend(predictive.step(&observation).map(|proposal| (step, proposal)));
+        }
+        assert!(proposals.is_empty());
+        assert!(predictive.thresholds[1].raise_at() > predictive.thresholds[0].raise_at());
+
+        // A step of 4 on the quiet channel, under the noisy one's residuals, raises once
+        // after the debounce and not again while it lasts.
+        for step in 400..440 {
+            let noise = [3.0, -3.0, 2.0, -2.0][step % 4];
+            let observation = Observation::new().channel("quiet", &[14.0 + step as f64 * 0.005]).channel("noisy", &[50.0 + noise]);
+            proposals.extend(predictive.step(&observation).map(|proposal| (step, proposal)));
+        }
+        assert_eq!(proposals.len(), 1);
+        assert_eq!((proposals[0].0, proposals[0].1.args["channel"].as_str()), (401, "quiet"));
+    }
 }
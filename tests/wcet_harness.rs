@@ -1,68 +1,230 @@
-use std::time::Instant;
-use criterion::{black_box, Criterion};
-
-// This harness simulates measuring Worst-Case Execution Time (WCET)
-// for the Gate and Policy VM. In a real environment (especially bare-metal),
-// you would read the `rdtsc` register or a dedicated cycle counter.
-
-pub fn measure_cycles<F: FnOnce()>(f: F) -> u64 {
-    // Note: for production WCET on x86, use core::arch::x86_64::_rdtsc()
-    let start = Instant::now();
-    f();
-    let elapsed = start.elapsed();
-    // Approximate nanos to cycles (assuming ~3GHz for illustration)
-    (elapsed.as_nanos() * 3) as u64
+use std::alloc::System;
+use std::path::PathBuf;
+use criterion::black_box;
+use rfsn_core::wcet::alloc::{is_installed, AccountingAllocator};
+use rfsn_core::wcet::baseline::{BaselineCheck, WcetBaseline};
+use rfsn_core::ledger::storage::StoreConfig;
+use rfsn_core::vm::bytecode::*;
+use rfsn_core::vm::interp::decide;
+use rfsn_core::vm::verify::verify;
+use rfsn_core::wcet::disturb::MeasurementMode;
+use rfsn_core::wcet::dvfs::ClockCheck;
+use rfsn_core::wcet::isolation::{prepare, IsolationConfig, NoisePolicy};
+use rfsn_core::wcet::ledger_profile::profile_ledger;
+use rfsn_core::wcet::profile::{profile, profile_corpus, Corpus, CorpusProfile, WcetProfile, DEFAULT_BUDGET_CYCLES, DEFAULT_HEAP_BYTES};
+use rfsn_core::wcet::report::{Environment, WcetReport};
+use rfsn_core::wcet::watchdog::{ExecutionBudget, Watchdog};
+
+// This harness measures Worst-Case Execution Time (WCET) for the Gate and
+// Policy VM with the measurement machinery in `rfsn_core::wcet`, and gates CI
+// on the result.
+
+#[global_allocator]
+static ALLOC: AccountingAllocator<System> = AccountingAllocator::new(System);
+
+/// Step limit for harness runs; generous, since the cycle budget is what is measured.
+const MAX_STEPS: u64 = 10_000;
+
+/// Denies contexts over 4 KiB (reason 1) or starting with a byte >= 0x80 (reason 2);
+/// allows the rest, including the empty context.
+fn sample_policy() -> Vec<u8> {
+    [
+        (CTXLEN, 0),
+        (PUSH, 4096),
+        (GT, 0),
+        (JZ, 5),
+        (DENY, 1),
+        (CTXLEN, 0),
+        (JZ, 11),
+        (LOAD8, 0),
+        (PUSH, 0x80),
+        (LT, 0),
+        (JZ, 12),
+        (ALLOW, 0),
+        (DENY, 2),
+    ]
+    .iter()
+    .flat_map(|&(opcode, operand)| encode(opcode, operand))
+    .collect()
 }
 
-pub struct WcetProfile {
-    pub max_gate_cycles: u64,
-    pub max_vm_cycles: u64,
-    pub capacity_margin: f64,
+fn run_vm(policy_payload: &[u8], context: &[u8]) {
+    let mut watchdog = Watchdog::new("policy", ExecutionBudget::steps(MAX_STEPS));
+    black_box(decide(black_box(policy_payload), black_box(context), &mut watchdog).expect("within step budget"));
 }
 
-pub fn profile_policy_bound(policy_payload: &[u8], iterations: usize) -> WcetProfile {
-    let mut max_vm = 0;
-    
-    // Simulate finding the WCET across N executions of a policy to detect jitter
-    for _ in 0..iterations {
-        let cycles = measure_cycles(|| {
-            // Simulated VM Execution
-            // e.g., rfsn_core::vm::decide(black_box(policy_payload));
-            let mut steps = 0;
-            // Fake loop representing maximum bytecode operations
-            while steps < 256 {
-                black_box(steps);
-                steps += 1;
-            }
-        });
-        
-        if cycles > max_vm {
-            max_vm = cycles;
-        }
+/// Profiles every input of `corpus` `rounds` times and panics if the slowest one
+/// exceeds the default budget or the heaviest one the default heap ceiling.
+pub fn profile_policy_bound(corpus: &Corpus, rounds: usize) -> CorpusProfile {
+    let result = profile_corpus(corpus, rounds, DEFAULT_BUDGET_CYCLES, MeasurementMode::from_env(), |input| {
+        run_vm(&input.policy, &input.context)
+    })
+    .expect("non-empty WCET corpus");
+    if !result.profile.within_budget() {
+        panic!(
+            "WCET VIOLATION: input {} exceeded the constant-time safety envelope! Expected < {} cycles, got {}",
+            result.worst(corpus).name, DEFAULT_BUDGET_CYCLES, result.profile.max_vm_cycles
+        );
     }
-    
-    // Hard check: If the WCET exceeds our safety envelope (e.g., 50,000 cycles for FastCtrl deadlines)
-    if max_vm > 50_000 {
-        panic!("WCET VIOLATION: Policy execution exceeded the constant-time safety envelope! Expected < 50000 cycles, got {}", max_vm);
+    if !result.profile.heap_within(DEFAULT_HEAP_BYTES) {
+        panic!(
+            "WCET VIOLATION: policy heap peaked at {} bytes, over the ceiling of {}",
+            result.profile.heap.peak_bytes, DEFAULT_HEAP_BYTES
+        );
+    }
+    result
+}
+
+/// Profiles the policy and panics if the observed maximum exceeds `budget_cycles`.
+pub fn profile_policy_within(policy_payload: &[u8], iterations: usize, budget_cycles: u64) -> WcetProfile {
+    profile_policy_with(policy_payload, iterations, budget_cycles, MeasurementMode::Warm)
+}
+
+/// Like `profile_policy_within`, in the given measurement mode.
+pub fn profile_policy_with(policy_payload: &[u8], iterations: usize, budget_cycles: u64, mode: MeasurementMode) -> WcetProfile {
+    let profile = profile(iterations, budget_cycles, mode, || run_vm(policy_payload, &[]));
+
+    // Hard check: If the WCET exceeds the policy's safety envelope
+    if !profile.within_budget() {
+        panic!("WCET VIOLATION: Policy execution exceeded the constant-time safety envelope! Expected < {} cycles, got {}", budget_cycles, profile.max_vm_cycles);
+    }
+    profile
+}
+
+/// Regression gate for CI. With `WCET_BASELINE=<path>` set, the profile is compared
+/// with the stored baseline and a regression beyond `WCET_MAX_REGRESSION_PCT` (default
+/// 10) panics; `WCET_UPDATE_BASELINE=1` rewrites the entry instead. Unset, it is a no-op.
+pub fn gate_on_baseline(name: &str, policy_payload: &[u8], profile: &WcetProfile) {
+    let Ok(path) = std::env::var("WCET_BASELINE") else { return };
+    let path = PathBuf::from(path);
+    let max_regression_pct = std::env::var("WCET_MAX_REGRESSION_PCT").ok().and_then(|v| v.parse().ok()).unwrap_or(10.0);
+    let mut baseline = WcetBaseline::load(&path).expect("readable WCET baseline");
+    if std::env::var("WCET_UPDATE_BASELINE").is_ok_and(|v| v == "1") {
+        baseline.record(name, policy_payload, profile);
+        baseline.save(&path).expect("writable WCET baseline");
+        println!("📥 WCET baseline for {} updated: {} cycles", name, profile.max_vm_cycles);
+        return;
+    }
+    match baseline.check(name, policy_payload, profile, max_regression_pct) {
+        BaselineCheck::New => println!("⚠️  No comparable WCET baseline for {}; run with WCET_UPDATE_BASELINE=1", name),
+        BaselineCheck::Within { baseline_cycles, change_pct } => {
+            println!("✅ WCET baseline for {}: {} cycles ({:+.1}% vs {})", name, profile.max_vm_cycles, change_pct, baseline_cycles)
+        }
+        BaselineCheck::Regressed { baseline_cycles, change_pct } => panic!(
+            "WCET REGRESSION: {} took {} cycles, {:+.1}% vs the baseline {} (limit +{}%)",
+            name, profile.max_vm_cycles, change_pct, baseline_cycles, max_regression_pct
+        ),
     }
+}
+
+/// With `WCET_REPORT=<path>` set, writes the JSON report to `<path>` and the CSV row and
+/// histogram next to it (`.csv`, `.histogram.csv`) for archiving.
+pub fn export_report(profile: &WcetProfile) {
+    let Ok(path) = std::env::var("WCET_REPORT") else { return };
+    let path = PathBuf::from(path);
+    let report = WcetReport::new(profile, Environment::capture());
+    std::fs::write(&path, report.to_json().expect("serializable WCET report")).expect("writable WCET report");
+    std::fs::write(path.with_extension("csv"), report.to_csv()).expect("writable WCET report");
+    std::fs::write(path.with_extension("histogram.csv"), report.histogram_csv()).expect("writable WCET report");
+    println!("📄 WCET report written to {}", path.display());
+}
 
-    WcetProfile {
-        max_gate_cycles: max_vm + 1500, // Adding Gate framing overhead
-        max_vm_cycles: max_vm,
-        capacity_margin: (50_000.0 - max_vm as f64) / 50_000.0,
+/// `WCET_ISOLATE=warn|abort` pins the harness thread (to `WCET_CPU` if set) and checks
+/// the host for isolation; `abort` fails the run on a noisy host.
+pub fn isolate_from_env() {
+    let policy = match std::env::var("WCET_ISOLATE").as_deref() {
+        Ok("warn") => NoisePolicy::Warn,
+        Ok("abort") => NoisePolicy::Abort,
+        _ => return,
+    };
+    let cpu = std::env::var("WCET_CPU").ok().and_then(|v| v.parse().ok());
+    match prepare(&IsolationConfig { cpu, policy, ..Default::default() }) {
+        Ok(report) => {
+            println!("📌 Measuring on CPU {} (pinned: {}, SCHED_FIFO: {})", report.cpu, report.pinned, report.fifo);
+            for finding in &report.findings {
+                println!("⚠️  {}", finding);
+            }
+        }
+        Err(noisy) => panic!("WCET ENVIRONMENT: {}", noisy),
     }
 }
 
 pub fn assert_wcet() {
     println!("Running Formal WCET (Worst-Case Execution Time) Profiling Harness...");
+    isolate_from_env();
     
-    // Test Policy 1: Simple Context Evaluation
-    let payload = b"policy_stub";
-    let profile = profile_policy_bound(payload, 10_000);
+    // Test Policy 1: Simple Context Evaluation, over `WCET_CORPUS=<dir>` (e.g. a fuzzer
+    // corpus of contexts) when set, otherwise one empty context.
+    let payload = &sample_policy();
+    let proof = verify(payload).expect("verifiable sample policy");
+    println!("✅ Policy verified: at most {} steps, stack depth {}", proof.max_steps, proof.max_stack_depth);
+    let corpus = match std::env::var("WCET_CORPUS") {
+        Ok(dir) => Corpus::from_dir(&PathBuf::from(dir), payload).expect("readable WCET corpus"),
+        Err(_) => {
+            let mut corpus = Corpus::default();
+            corpus.push("empty-context", payload, &[]);
+            corpus
+        }
+    };
+    let rounds = (10_000 / corpus.inputs.len().max(1)).max(1);
+    let result = profile_policy_bound(&corpus, rounds);
+    let profile = result.profile;
+    println!("   {} corpus inputs; slowest: {}", corpus.inputs.len(), corpus.inputs[result.worst_input].name);
     
+    match &profile.clock {
+        ClockCheck::Invalid { reason } => println!("⚠️  WCET profile invalid: {}", reason),
+        ClockCheck::Rescaled { khz, factor } => println!("⚠️  Wall-clock cycles; core ran at {} kHz (x{:.3} to rescale)", khz, factor),
+        ClockCheck::Stable { .. } | ClockCheck::Unknown => {}
+    }
     println!("✅ WCET PASS: Maximum Policy VM Cycles: {}", profile.max_vm_cycles);
     println!("✅ WCET PASS: Maximum total Gate latency: {}", profile.max_gate_cycles);
     println!("✅ Safety Margin: {:.2}% below deadline", profile.capacity_margin * 100.0);
+    if is_installed() {
+        println!(
+            "✅ Heap: peak {} bytes in {} allocations (ceiling {})",
+            profile.heap.peak_bytes, profile.heap.allocations, DEFAULT_HEAP_BYTES
+        );
+    }
+    let summary = &profile.summary;
+    println!(
+        "   p50 {} / p99 {} / p99.99 {} cycles; stddev {:.0} (CV {:.1}%), successive delta {:.0}: {:?} jitter",
+        summary.p50_cycles,
+        summary.p99_cycles,
+        summary.p9999_cycles,
+        summary.stddev_cycles,
+        summary.coefficient_of_variation() * 100.0,
+        summary.mean_successive_delta,
+        summary.jitter_source()
+    );
+    if let Some(evt) = &profile.evt {
+        println!(
+            "   pWCET @ {:e}/run: {} cycles ({:.0}% upper bound {})",
+            evt.exceedance_probability, evt.pwcet_cycles, evt.confidence * 100.0, evt.pwcet_upper_cycles
+        );
+    }
+    gate_on_baseline("sample_policy", payload, &profile);
+    export_report(&profile);
+
+    // Ledger side: small segments and a short checkpoint interval so both spikes occur.
+    let dir = std::env::temp_dir().join(format!("rfsn-wcet-ledger-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("creatable ledger directory");
+    let config = StoreConfig::new(&dir).segment_size(64 * 1024).checkpoint_interval(64);
+    let ledger = profile_ledger(config, &[0x5a; 256], 2_000).expect("ledger profiling");
+    let _ = std::fs::remove_dir_all(&dir);
+    println!(
+        "   Ledger: append {} / rollover {} / checkpoint {} / commit {} cycles (max)",
+        ledger.append.max_cycles, ledger.rollover.max_cycles, ledger.checkpoint.max_cycles, ledger.commit.max_cycles
+    );
+    println!("✅ Gate-to-ledger bound: {} cycles", ledger.max_gate_to_ledger_cycles(&profile));
+    if let Some(instructions) = profile.worst_run.instructions {
+        println!(
+            "   Slowest run: {} instructions, {} LLC misses, {} branch mispredicts",
+            instructions,
+            profile.worst_run.llc_misses.unwrap_or(0),
+            profile.worst_run.branch_misses.unwrap_or(0)
+        );
+    }
 }
 
 // In a real build, we'd hook this into the Rust unit test framework: